@@ -1,9 +1,43 @@
+use arc_swap::ArcSwap;
 use axum::http::HeaderMap;
+use once_cell::sync::Lazy;
 use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 use tracing::debug;
 
 use crate::{config, metrics};
 
+/// 全局访问名单快照：HTTP/WS/stream 监听共用，配置变化时整体替换，不需要重启监听
+#[derive(Debug, Clone, Default)]
+pub struct AccessLists {
+    pub allow_all_lan: bool,
+    pub allow_all_ip: bool,
+    pub whitelist: Arc<[config::WhitelistEntry]>,
+}
+
+impl AccessLists {
+    pub fn from_config(cfg: &config::Config) -> Self {
+        Self {
+            allow_all_lan: cfg.allow_all_lan,
+            allow_all_ip: cfg.allow_all_ip,
+            whitelist: Arc::from(cfg.whitelist.clone()),
+        }
+    }
+}
+
+static ACCESS_LISTS: Lazy<Arc<ArcSwap<AccessLists>>> =
+    Lazy::new(|| Arc::new(ArcSwap::from_pointee(AccessLists::default())));
+
+/// 监听持有的名单槽位，与 ListenRule 的 rule_slot 一样按请求读取最新快照
+pub fn access_lists_slot() -> Arc<ArcSwap<AccessLists>> {
+    ACCESS_LISTS.clone()
+}
+
+/// 配置加载或替换时调用
+pub fn store_access_lists(cfg: &config::Config) {
+    ACCESS_LISTS.store(Arc::new(AccessLists::from_config(cfg)));
+}
+
 fn parse_ip(s: &str) -> Option<IpAddr> {
     s.trim().parse::<IpAddr>().ok()
 }
@@ -140,6 +174,27 @@ mod tests {
     use axum::http::HeaderMap;
     use std::net::{Ipv4Addr, Ipv6Addr};
 
+    #[test]
+    fn stored_access_lists_reach_existing_slots() {
+        // 监听启动时拿到的槽位，之后的名单替换对其立即可见
+        let slot = access_lists_slot();
+        let mut cfg = config::get_config();
+        cfg.allow_all_ip = false;
+        cfg.whitelist = vec![config::WhitelistEntry {
+            ip: "203.0.113.7".to_string(),
+        }];
+        store_access_lists(&cfg);
+
+        let lists = slot.load();
+        let remote: SocketAddr = "203.0.113.7:40000".parse().unwrap();
+        assert!(is_allowed_remote_ip(
+            &remote,
+            lists.allow_all_lan,
+            lists.allow_all_ip,
+            &lists.whitelist
+        ));
+    }
+
     #[test]
     fn ipv4_mapped_conversion() {
         let ipv6_mapped = "::ffff:192.168.1.128".parse::<IpAddr>().unwrap();
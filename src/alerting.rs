@@ -1,12 +1,16 @@
-use crate::config::{self, AlertWebhookConfig};
+use crate::app_events::AppEvents;
+use crate::config::{self, AlertMetric, AlertRule, AlertWebhookConfig, AlertsConfig};
+use crate::metrics::RealtimeWindowStats;
 use crate::system_metrics::{NetworkInterfaceStats, SystemMetricsPoint};
 use chrono::Datelike;
 use once_cell::sync::Lazy;
 use parking_lot::RwLock;
+use serde::Serialize;
 use serde_json::json;
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 use std::time::Duration;
-use tauri::AppHandle;
+use tauri_plugin_notification::NotificationExt;
 use tracing::{info, warn};
 
 static SYSTEM_REPORT_PUSHER_RUNNING: AtomicBool = AtomicBool::new(false);
@@ -14,6 +18,14 @@ static SYSTEM_REPORT_LAST_SLOT: AtomicI64 = AtomicI64::new(-1);
 static SYSTEM_REPORT_PUSHER_HANDLE: Lazy<RwLock<Option<tauri::async_runtime::JoinHandle<()>>>> =
     Lazy::new(|| RwLock::new(None));
 
+const METRIC_ALERT_EVAL_INTERVAL: Duration = Duration::from_secs(30);
+static METRIC_ALERT_RUNNING: AtomicBool = AtomicBool::new(false);
+static METRIC_ALERT_HANDLE: Lazy<RwLock<Option<tauri::async_runtime::JoinHandle<()>>>> =
+    Lazy::new(|| RwLock::new(None));
+// 按规则名记录告警状态
+static METRIC_ALERT_STATES: Lazy<RwLock<HashMap<String, MetricAlertState>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
 fn normalize_provider(provider: &str) -> String {
     provider.trim().to_lowercase()
 }
@@ -309,7 +321,7 @@ async fn try_send_system_report() -> Result<(), String> {
     send_payload(&webhook, payload).await
 }
 
-pub fn start_system_report_pusher(_app: AppHandle) {
+pub fn start_system_report_pusher(_app: AppEvents) {
     if SYSTEM_REPORT_PUSHER_RUNNING.swap(true, Ordering::SeqCst) {
         return;
     }
@@ -373,7 +385,7 @@ pub async fn send_test_alert(alerting: config::AlertingConfig) -> Result<(), Str
     send_payload(webhook, payload).await
 }
 
-pub fn notify_server_start_error(_app: &AppHandle, listen_addr: &str, err: &str) {
+pub fn notify_server_start_error(_app: &AppEvents, listen_addr: &str, err: &str) {
     let cfg = config::get_config();
     let Some(alerting) = cfg.alerting else {
         return;
@@ -410,3 +422,361 @@ pub fn notify_server_start_error(_app: &AppHandle, listen_addr: &str, err: &str)
         }
     });
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+/// `proxy-alert` 事件内容
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertEvent {
+    pub rule: String,
+    pub listen_addr: String,
+    pub metric: AlertMetric,
+    pub state: AlertState,
+    pub threshold: f64,
+    pub value: f64,
+    pub at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveAlert {
+    pub rule: String,
+    pub listen_addr: String,
+    pub metric: AlertMetric,
+    pub threshold: f64,
+    pub value: f64,
+    pub since: i64,
+}
+
+#[derive(Debug, Clone)]
+struct MetricAlertState {
+    rule: AlertRule,
+    value: f64,
+    firing_since: Option<i64>,
+    /// 触发后指标首次回落的时间，持续 cooldown 后才解除
+    clear_since: Option<i64>,
+}
+
+impl MetricAlertState {
+    fn new(rule: AlertRule) -> Self {
+        Self {
+            rule,
+            value: 0.0,
+            firing_since: None,
+            clear_since: None,
+        }
+    }
+
+    fn step(
+        &mut self,
+        breached: bool,
+        value: f64,
+        now: i64,
+        cooldown_secs: i64,
+    ) -> Option<AlertState> {
+        self.value = value;
+        match (self.firing_since, breached) {
+            (None, true) => {
+                self.firing_since = Some(now);
+                self.clear_since = None;
+                Some(AlertState::Firing)
+            }
+            (None, false) => None,
+            (Some(_), true) => {
+                self.clear_since = None;
+                None
+            }
+            (Some(_), false) => {
+                let since = *self.clear_since.get_or_insert(now);
+                if now - since >= cooldown_secs {
+                    self.firing_since = None;
+                    self.clear_since = None;
+                    Some(AlertState::Resolved)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+fn metric_value(metric: AlertMetric, stats: &RealtimeWindowStats) -> f64 {
+    match metric {
+        AlertMetric::ErrorRate5xx => stats.error_rate_5xx(),
+        AlertMetric::LatencyP95 => stats.p95_latency_ms,
+        AlertMetric::LatencyP99 => stats.p99_latency_ms,
+    }
+}
+
+fn metric_label(metric: AlertMetric) -> &'static str {
+    match metric {
+        AlertMetric::ErrorRate5xx => "5xx 占比",
+        AlertMetric::LatencyP95 => "p95 耗时",
+        AlertMetric::LatencyP99 => "p99 耗时",
+    }
+}
+
+fn format_metric_value(metric: AlertMetric, value: f64) -> String {
+    match metric {
+        AlertMetric::ErrorRate5xx => format!("{value:.1}%"),
+        AlertMetric::LatencyP95 | AlertMetric::LatencyP99 => format!("{value:.0}ms"),
+    }
+}
+
+/// 按规则评估一轮，返回状态发生变化的告警；已删除或停用的规则直接丢弃状态
+fn evaluate_metric_alerts(
+    states: &mut HashMap<String, MetricAlertState>,
+    alerts: &AlertsConfig,
+    now: i64,
+    window_stats: impl Fn(&str, i64) -> RealtimeWindowStats,
+) -> Vec<AlertEvent> {
+    let rules: Vec<&AlertRule> = alerts.rules.iter().filter(|r| r.enabled).collect();
+    states.retain(|name, _| rules.iter().any(|r| r.name.trim() == name));
+
+    let mut events = Vec::new();
+    for rule in rules {
+        let stats = window_stats(&rule.listen_addr, i64::from(rule.window_secs));
+        let value = metric_value(rule.metric, &stats);
+        let breached = stats.requests >= i64::from(rule.min_requests) && value > rule.threshold;
+
+        let state = states
+            .entry(rule.name.trim().to_string())
+            .or_insert_with(|| MetricAlertState::new(rule.clone()));
+        state.rule = rule.clone();
+        if let Some(transition) = state.step(breached, value, now, i64::from(alerts.cooldown_secs))
+        {
+            events.push(AlertEvent {
+                rule: rule.name.trim().to_string(),
+                listen_addr: rule.listen_addr.clone(),
+                metric: rule.metric,
+                state: transition,
+                threshold: rule.threshold,
+                value,
+                at: now,
+            });
+        }
+    }
+    events
+}
+
+fn build_metric_alert_text(event: &AlertEvent) -> (String, String) {
+    let title = match event.state {
+        AlertState::Firing => "SSLProxyManager 告警 / Alert",
+        AlertState::Resolved => "SSLProxyManager 告警恢复 / Resolved",
+    };
+    let listen = if event.listen_addr.trim().is_empty() {
+        "全局"
+    } else {
+        event.listen_addr.trim()
+    };
+    let body = format!(
+        "规则: {}\n监听地址: {}\n{}: {}（阈值 {}）\n时间: {}",
+        event.rule,
+        listen,
+        metric_label(event.metric),
+        format_metric_value(event.metric, event.value),
+        format_metric_value(event.metric, event.threshold),
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+    );
+    (title.to_string(), body)
+}
+
+fn dispatch_metric_alert(
+    app: &AppEvents,
+    cfg: &config::Config,
+    alerts: &AlertsConfig,
+    event: &AlertEvent,
+) {
+    let _ = app.emit("proxy-alert", event);
+
+    let (title, body) = build_metric_alert_text(event);
+    crate::proxy::logging::push_log_line(format!(
+        "[ALERT] {} {}",
+        title,
+        body.replace('\n', " | ")
+    ));
+
+    if let Some(handle) = app.handle().filter(|_| alerts.desktop_notification) {
+        if let Err(e) = handle
+            .notification()
+            .builder()
+            .title(&title)
+            .body(&body)
+            .show()
+        {
+            warn!("alert desktop notification failed: {e}");
+        }
+    }
+
+    if !alerts.webhook {
+        return;
+    }
+    let Some(webhook) = cfg
+        .alerting
+        .as_ref()
+        .filter(|a| a.enabled)
+        .and_then(|a| a.webhook.clone())
+    else {
+        return;
+    };
+    if !webhook.enabled
+        || webhook.url.trim().is_empty()
+        || is_in_quiet_hours(&webhook, chrono::Local::now())
+    {
+        return;
+    }
+
+    let payload = build_plain_text(&webhook.provider, &title, &body);
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = send_payload(&webhook, payload).await {
+            warn!(
+                "metric alert webhook failed: provider={}, err={}",
+                webhook.provider, e
+            );
+        }
+    });
+}
+
+pub fn start_metric_alert_evaluator(app: AppEvents) {
+    if METRIC_ALERT_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let handle = tauri::async_runtime::spawn(async move {
+        let mut ticker = tokio::time::interval(METRIC_ALERT_EVAL_INTERVAL);
+        loop {
+            ticker.tick().await;
+
+            if !METRIC_ALERT_RUNNING.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let cfg = config::get_config();
+            let Some(alerts) = cfg.alerts.clone().filter(|a| a.enabled) else {
+                METRIC_ALERT_STATES.write().clear();
+                continue;
+            };
+
+            let now = chrono::Utc::now().timestamp();
+            let events = evaluate_metric_alerts(
+                &mut METRIC_ALERT_STATES.write(),
+                &alerts,
+                now,
+                |listen, window| crate::metrics::realtime_window_stats(listen, window, now),
+            );
+            for event in events.iter() {
+                dispatch_metric_alert(&app, &cfg, &alerts, event);
+            }
+        }
+
+        METRIC_ALERT_RUNNING.store(false, Ordering::SeqCst);
+    });
+
+    *METRIC_ALERT_HANDLE.write() = Some(handle);
+}
+
+pub fn stop_metric_alert_evaluator() {
+    METRIC_ALERT_RUNNING.store(false, Ordering::SeqCst);
+    METRIC_ALERT_STATES.write().clear();
+
+    if let Some(handle) = METRIC_ALERT_HANDLE.write().take() {
+        handle.abort();
+    }
+}
+
+/// 当前处于触发状态的告警，按触发时间排序
+pub fn get_active_alerts() -> Vec<ActiveAlert> {
+    let mut active: Vec<ActiveAlert> = METRIC_ALERT_STATES
+        .read()
+        .iter()
+        .filter_map(|(name, s)| {
+            Some(ActiveAlert {
+                rule: name.clone(),
+                listen_addr: s.rule.listen_addr.clone(),
+                metric: s.rule.metric,
+                threshold: s.rule.threshold,
+                value: s.value,
+                since: s.firing_since?,
+            })
+        })
+        .collect();
+    active.sort_by(|a, b| a.since.cmp(&b.since).then_with(|| a.rule.cmp(&b.rule)));
+    active
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_alerts() -> AlertsConfig {
+        AlertsConfig {
+            enabled: true,
+            rules: vec![AlertRule {
+                name: "5xx".into(),
+                enabled: true,
+                listen_addr: ":443".into(),
+                metric: AlertMetric::ErrorRate5xx,
+                threshold: 10.0,
+                window_secs: 300,
+                min_requests: 20,
+            }],
+            cooldown_secs: 60,
+            desktop_notification: false,
+            webhook: false,
+        }
+    }
+
+    fn stats(requests: i64, s5xx: i64) -> RealtimeWindowStats {
+        RealtimeWindowStats {
+            requests,
+            s5xx,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn metric_alert_fires_once_and_resolves_after_cooldown() {
+        let alerts = sample_alerts();
+        let mut states = HashMap::new();
+
+        // 样本不足时即使 5xx 占比很高也不触发
+        assert!(evaluate_metric_alerts(&mut states, &alerts, 0, |_, _| stats(10, 10)).is_empty());
+
+        let ev = evaluate_metric_alerts(&mut states, &alerts, 30, |_, _| stats(100, 50));
+        assert_eq!(ev.len(), 1);
+        assert_eq!(ev[0].state, AlertState::Firing);
+        assert!((ev[0].value - 50.0).abs() < 1e-9);
+        assert!(evaluate_metric_alerts(&mut states, &alerts, 60, |_, _| stats(100, 50)).is_empty());
+
+        // 回落未满 cooldown 又超限，不解除也不重复触发
+        assert!(evaluate_metric_alerts(&mut states, &alerts, 90, |_, _| stats(100, 1)).is_empty());
+        assert!(
+            evaluate_metric_alerts(&mut states, &alerts, 120, |_, _| stats(100, 50)).is_empty()
+        );
+        assert!(evaluate_metric_alerts(&mut states, &alerts, 150, |_, _| stats(100, 1)).is_empty());
+        assert_eq!(states["5xx"].firing_since, Some(30));
+
+        let ev = evaluate_metric_alerts(&mut states, &alerts, 210, |_, _| stats(100, 1));
+        assert_eq!(ev.len(), 1);
+        assert_eq!(ev[0].state, AlertState::Resolved);
+        assert!(states["5xx"].firing_since.is_none());
+    }
+
+    #[test]
+    fn metric_alert_state_dropped_when_rule_removed() {
+        let mut alerts = sample_alerts();
+        let mut states = HashMap::new();
+        evaluate_metric_alerts(&mut states, &alerts, 0, |_, _| stats(100, 50));
+        assert_eq!(states.len(), 1);
+
+        alerts.rules[0].enabled = false;
+        assert!(evaluate_metric_alerts(&mut states, &alerts, 30, |_, _| stats(100, 50)).is_empty());
+        assert!(states.is_empty());
+    }
+}
@@ -1,10 +1,17 @@
 use anyhow::Result;
 use parking_lot::RwLock;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tauri::{AppHandle, Emitter, Manager};
 
+use crate::app_events::AppEvents;
+
 static METRICS_PUSHER_RUNNING: AtomicBool = AtomicBool::new(false);
+// 主窗口重新显示/获得焦点时唤醒推送任务，立即补发一次全量
+static METRICS_PUSHER_WAKE: once_cell::sync::Lazy<tokio::sync::Notify> =
+    once_cell::sync::Lazy::new(tokio::sync::Notify::new);
+// 增量模式下每隔这么久发送一次全量，纠正前端可能累积的偏差
+const METRICS_FULL_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
 static METRICS_PUSHER_HANDLE: once_cell::sync::Lazy<
     RwLock<Option<tauri::async_runtime::JoinHandle<()>>>,
 > = once_cell::sync::Lazy::new(|| RwLock::new(None));
@@ -16,22 +23,52 @@ fn start_metrics_pusher(app: AppHandle) {
     }
 
     let handle = tauri::async_runtime::spawn(async move {
-        let mut ticker = tokio::time::interval(Duration::from_secs(2));
+        let mut last_push_ts: Option<i64> = None;
+        let mut last_full_push: Option<Instant> = None;
         loop {
-            ticker.tick().await;
+            let (interval, incremental) = crate::config::metrics_push_settings();
+            let woken = tokio::select! {
+                _ = tokio::time::sleep(interval) => false,
+                _ = METRICS_PUSHER_WAKE.notified() => true,
+            };
 
             // 若被停止则退出
             if !METRICS_PUSHER_RUNNING.load(Ordering::Relaxed) {
                 break;
             }
 
+            // 窗口隐藏到托盘或最小化时不序列化也不推送，恢复显示后先发一次全量
+            let Some(window) = app.get_webview_window("main") else {
+                continue;
+            };
+            let visible =
+                window.is_visible().unwrap_or(true) && !window.is_minimized().unwrap_or(false);
+            if !visible {
+                last_push_ts = None;
+                continue;
+            }
+
             // 获取 metrics（内部有 500ms 缓存）
             let payload = crate::metrics::get_metrics();
-
-            // 推送到前端：给 main 窗口 emit（前端订阅 EventsOn('metrics')）
-            if let Some(window) = app.get_webview_window("main") {
-                let _ = window.emit("metrics", payload);
+            let now = chrono::Utc::now().timestamp();
+            let full_due = !incremental
+                || woken
+                || last_full_push.is_none_or(|t| t.elapsed() >= METRICS_FULL_REFRESH_INTERVAL);
+
+            // 推送到前端：全量走 'metrics'，增量走 'metrics-delta'
+            match last_push_ts {
+                Some(since) if !full_due => {
+                    let _ = window.emit(
+                        "metrics-delta",
+                        crate::metrics::metrics_delta_since(payload, since),
+                    );
+                }
+                _ => {
+                    let _ = window.emit("metrics", payload);
+                    last_full_push = Some(Instant::now());
+                }
             }
+            last_push_ts = Some(now);
         }
 
         METRICS_PUSHER_RUNNING.store(false, Ordering::SeqCst);
@@ -40,6 +77,11 @@ fn start_metrics_pusher(app: AppHandle) {
     *METRICS_PUSHER_HANDLE.write() = Some(handle);
 }
 
+/// 主窗口重新可见时调用，让推送任务立即发送一次全量
+pub fn wake_metrics_pusher() {
+    METRICS_PUSHER_WAKE.notify_one();
+}
+
 fn stop_metrics_pusher() {
     METRICS_PUSHER_RUNNING.store(false, Ordering::SeqCst);
 
@@ -48,31 +90,80 @@ fn stop_metrics_pusher() {
     }
 }
 
-pub fn init(app: &AppHandle) -> Result<()> {
+/// 界面与无界面模式共用的初始化：配置、GeoIP、访问日志、syslog、Prometheus
+pub fn init_core() -> Result<()> {
     // rustls 0.23 需要显式选择 CryptoProvider（避免运行时 panic）
     let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
 
     // 初始化配置
     crate::config::load_config()?;
 
-    // 初始化数据库（异步，避免在 runtime 内 block_on 导致崩溃）
-    // 以及：启动请求日志异步写入 worker
-    if let Some(metrics_storage) = crate::config::get_config().metrics_storage.as_ref() {
-        if metrics_storage.enabled {
-            let db_path = metrics_storage.db_path.clone();
-            tauri::async_runtime::spawn(async move {
-                if let Err(e) = crate::metrics::init_db(db_path).await {
-                    eprintln!("初始化数据库失败: {e}");
-                }
-                crate::metrics::init_request_log_writer().await;
-            });
-        }
+    if let Err(e) = crate::geoip::apply_geoip_config(crate::config::get_config().geoip.as_ref()) {
+        eprintln!("加载 GeoIP 数据库失败: {e:#}");
+    }
+    if let Err(e) = crate::proxy::access_log::apply_access_log_config(&crate::config::get_config())
+    {
+        eprintln!("访问日志配置无效: {e:#}");
+    }
+    if let Err(e) = crate::proxy::syslog::apply_syslog_config(crate::config::get_config().syslog) {
+        eprintln!("启动 syslog 输出失败: {e:#}");
+    }
+
+    if let Some(prometheus) = crate::config::get_config().prometheus {
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = crate::metrics::apply_prometheus_config(Some(prometheus)).await {
+                eprintln!("启动 Prometheus 导出失败: {e:#}");
+            }
+        });
+    }
+
+    crate::metrics::start_in_flight_sampler();
+    Ok(())
+}
+
+/// 初始化数据库并启动请求日志异步写入 worker
+pub async fn init_metrics_storage() {
+    let Some(metrics_storage) = crate::config::get_config().metrics_storage else {
+        return;
+    };
+    if !metrics_storage.enabled {
+        return;
+    }
+    if let Err(e) = crate::metrics::init_storage(&metrics_storage).await {
+        eprintln!("初始化数据库失败: {e}");
+        crate::metrics::schedule_db_recovery(&e.to_string());
     }
+    crate::metrics::init_request_log_writer().await;
+}
+
+/// 界面与无界面模式共用的后台任务：配置文件监听、系统采样、告警、上游健康检查
+pub fn start_background_services(events: AppEvents) {
+    if let Err(e) = crate::config_watcher::start(events.clone()) {
+        eprintln!("启动配置文件监听失败: {e:#}");
+    }
+    crate::system_metrics::start_system_sampler(events.clone());
+    crate::alerting::start_system_report_pusher(events.clone());
+    crate::alerting::start_metric_alert_evaluator(events.clone());
+    crate::proxy::upstream_health::start_health_watcher(events);
+}
+
+pub fn stop_background_services() {
+    crate::config_watcher::stop();
+    crate::system_metrics::stop_system_sampler();
+    crate::alerting::stop_system_report_pusher();
+    crate::alerting::stop_metric_alert_evaluator();
+}
+
+pub fn init(app: &AppHandle) -> Result<()> {
+    init_core()?;
+
+    // 初始化数据库（异步，避免在 runtime 内 block_on 导致崩溃）
+    tauri::async_runtime::spawn(init_metrics_storage());
+
+    start_background_services(app.into());
 
     // 启动 metrics 定时推送（应用级别，和 proxy running/stopped 无关）
     start_metrics_pusher(app.clone());
-    crate::system_metrics::start_system_sampler(app.clone());
-    crate::alerting::start_system_report_pusher(app.clone());
 
     // 启动后自动检查更新
     let app_handle = app.clone();
@@ -98,7 +189,8 @@ pub fn init(app: &AppHandle) -> Result<()> {
 }
 
 pub fn cleanup() {
+    stop_background_services();
     stop_metrics_pusher();
-    crate::system_metrics::stop_system_sampler();
-    crate::alerting::stop_system_report_pusher();
+    crate::metrics::stop_prometheus_exporter();
+    crate::metrics::shutdown_blocking(crate::metrics::SHUTDOWN_FLUSH_TIMEOUT);
 }
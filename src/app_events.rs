@@ -0,0 +1,36 @@
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+/// 向前端推送事件的句柄；无界面模式下没有 AppHandle，事件直接丢弃
+#[derive(Clone)]
+pub struct AppEvents(Option<AppHandle>);
+
+impl AppEvents {
+    pub fn headless() -> Self {
+        Self(None)
+    }
+
+    pub fn emit<S: Serialize + Clone>(&self, event: &str, payload: S) -> tauri::Result<()> {
+        match &self.0 {
+            Some(app) => app.emit(event, payload),
+            None => Ok(()),
+        }
+    }
+
+    /// 需要窗口、通知等界面能力时使用；无界面模式下为 None
+    pub fn handle(&self) -> Option<&AppHandle> {
+        self.0.as_ref()
+    }
+}
+
+impl From<AppHandle> for AppEvents {
+    fn from(app: AppHandle) -> Self {
+        Self(Some(app))
+    }
+}
+
+impl From<&AppHandle> for AppEvents {
+    fn from(app: &AppHandle) -> Self {
+        Self(Some(app.clone()))
+    }
+}
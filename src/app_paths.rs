@@ -0,0 +1,64 @@
+use anyhow::{anyhow, Context, Result};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::{config, config_profiles, metrics};
+
+const PORTABLE_FLAG_FILE: &str = "portable.flag";
+const PORTABLE_ARG: &str = "--portable";
+
+/// 便携模式的触发方式，进程内只判断一次；None 表示未启用
+static PORTABLE_TRIGGER: Lazy<Option<&'static str>> = Lazy::new(detect_portable);
+
+pub fn exe_dir() -> Result<PathBuf> {
+    let exe = std::env::current_exe().context("无法获取当前可执行文件路径")?;
+    exe.parent()
+        .map(Path::to_path_buf)
+        .ok_or_else(|| anyhow!("无法获取可执行文件所在目录"))
+}
+
+fn detect_portable() -> Option<&'static str> {
+    if std::env::args_os().skip(1).any(|arg| arg == PORTABLE_ARG) {
+        return Some("argument");
+    }
+    let flag = exe_dir().ok()?.join(PORTABLE_FLAG_FILE);
+    flag.is_file().then_some("flag_file")
+}
+
+/// 便携模式：配置、备份、方案与默认数据库都放在可执行文件所在目录
+pub fn is_portable() -> bool {
+    PORTABLE_TRIGGER.is_some()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AppPaths {
+    pub portable: bool,
+    /// 便携模式的触发方式：flag_file / argument
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub portable_trigger: Option<String>,
+    pub exe_dir: String,
+    pub config_path: String,
+    pub config_snapshots_dir: String,
+    pub profiles_dir: String,
+    /// 按当前配置解析出的 SQLite 数据库路径（db_path 为空时为默认位置）
+    pub metrics_db_path: String,
+}
+
+pub fn app_paths() -> Result<AppPaths> {
+    let config_path = config::get_config_path()?;
+    let db_path = config::get_config()
+        .metrics_storage
+        .map(|m| m.db_path)
+        .unwrap_or_default();
+
+    Ok(AppPaths {
+        portable: is_portable(),
+        portable_trigger: PORTABLE_TRIGGER.map(str::to_string),
+        exe_dir: exe_dir()?.display().to_string(),
+        config_snapshots_dir: config::snapshots_dir(&config_path)?.display().to_string(),
+        profiles_dir: config_profiles::profiles_dir()?.display().to_string(),
+        metrics_db_path: metrics::resolve_db_path(db_path)?.display().to_string(),
+        config_path: config_path.display().to_string(),
+    })
+}
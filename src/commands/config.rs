@@ -1,9 +1,48 @@
+use crate::app_events::AppEvents;
 use crate::config;
+use crate::config_check::ConfigIssue;
+use crate::config_example;
+use crate::config_profiles::{self, ProfileInfo};
+use crate::nginx_import::{self, NginxImportReport};
+use crate::proxy;
 use crate::proxy::stream_proxy;
+use crate::secrets;
 use crate::system_metrics;
 use anyhow::Result;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use tauri_plugin_dialog::DialogExt;
+
+/// 导入前与当前配置的对比：按监听/上游等分组列出新增与移除项
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ConfigSectionDiff {
+    pub section: String,
+    pub current: usize,
+    pub imported: usize,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ConfigImportPreview {
+    pub path: String,
+    pub sections: Vec<ConfigSectionDiff>,
+    /// 取值有变化的顶层配置项
+    pub changed_keys: Vec<String>,
+    /// 未启用规则引用的证书文件不存在等不阻止导入的问题
+    pub warnings: Vec<String>,
+}
+
+pub async fn validate_config_for_save(cfg: &config::Config) -> Result<(), String> {
+    if let Some((path, _, name)) = secrets::missing_secrets(cfg)
+        .into_iter()
+        .find(|(_, enabled, _)| *enabled)
+    {
+        return Err(format!(
+            "Keychain entry '{name}' referenced by {path} not found"
+        ));
+    }
 
-pub async fn validate_config(cfg: &config::Config) -> Result<(), String> {
     for rule in &cfg.rules {
         if !rule.enabled || !rule.ssl_enable {
             continue;
@@ -29,7 +68,24 @@ pub async fn validate_config(cfg: &config::Config) -> Result<(), String> {
 
     if let Some(ws_rules) = &cfg.ws_proxy {
         for rule in ws_rules {
-            if !rule.enabled || !rule.ssl_enable {
+            if !rule.enabled {
+                continue;
+            }
+            for route in &rule.routes {
+                if route.upstreams.is_empty() {
+                    return Err(format!(
+                        "WS rule ({}) route ({}) has no upstream configured",
+                        rule.listen_addr, route.path
+                    ));
+                }
+                stream_proxy::parse_duration(&route.fail_timeout).map_err(|e| {
+                    format!(
+                        "WS rule ({}) route ({}) has invalid fail_timeout: {e}",
+                        rule.listen_addr, route.path
+                    )
+                })?;
+            }
+            if !rule.ssl_enable {
                 continue;
             }
             if rule.cert_file.trim().is_empty() || rule.key_file.trim().is_empty() {
@@ -54,13 +110,38 @@ pub async fn validate_config(cfg: &config::Config) -> Result<(), String> {
 
     if cfg.stream.enabled {
         stream_proxy::validate_stream_config(&cfg.stream).map_err(|e| e.to_string())?;
+        stream_proxy::resolve_stream_upstreams(&cfg.stream)
+            .await
+            .map_err(|e| e.to_string())?;
     }
 
     config::validate_alerting_config(&cfg.alerting)?;
+    config::validate_alerts_config(&cfg.alerts)?;
+    config::validate_geoip_config(&cfg.geoip)?;
+    config::validate_access_log_format(&cfg.access_log_format)?;
+    config::validate_log_sampling_config(&cfg.metrics_storage)?;
+    config::validate_prometheus_config(&cfg.prometheus)?;
+    config::validate_syslog_config(&cfg.syslog)?;
 
     Ok(())
 }
 
+/// 只做检查不应用，返回带字段路径的全部问题，供界面逐项标记
+#[tauri::command]
+pub async fn validate_config(cfg: config::Config) -> Result<Vec<ConfigIssue>, String> {
+    Ok(crate::config_check::check_config(&cfg))
+}
+
+/// 试绑定监听地址；被占用时尽量给出占用的进程
+#[tauri::command]
+pub async fn check_port_available(
+    listen_addr: String,
+) -> Result<proxy::listen::PortAvailability, String> {
+    proxy::listen::check_port_available(&listen_addr)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub fn get_config() -> Result<config::Config, String> {
     Ok(config::get_config())
@@ -76,13 +157,38 @@ pub async fn restore_config_snapshot(
     app: tauri::AppHandle,
     snapshot_name: String,
 ) -> Result<config::Config, String> {
-    let cfg = config::load_config_snapshot(&snapshot_name).map_err(|e| e.to_string())?;
-    validate_config(&cfg).await?;
+    restore_config_backup(app, snapshot_name).await
+}
+
+/// 每次保存前自动生成的配置备份（最新在前），与快照共用 config_snapshots 目录
+#[tauri::command]
+pub fn list_config_backups() -> Result<Vec<config::ConfigSnapshotInfo>, String> {
+    config::list_config_snapshots().map_err(|e| e.to_string())
+}
+
+/// 校验并恢复指定备份；恢复本身也经 save_config，当前配置会先被备份
+#[tauri::command]
+pub async fn restore_config_backup(
+    app: tauri::AppHandle,
+    name: String,
+) -> Result<config::Config, String> {
+    let cfg = config::load_config_snapshot(&name).map_err(|e| format!("{e:#}"))?;
+    validate_config_for_save(&cfg).await?;
+    apply_metrics_storage(&cfg).await?;
 
-    let saved_cfg = crate::hot_reload::graceful_reload(app, cfg)
+    let saved_cfg = crate::hot_reload::graceful_reload((&app).into(), cfg)
         .await
-        .map_err(|e| e.to_string())?;
-    system_metrics::refresh_sample_interval_from_config();
+        .map_err(|e| e.to_string())?
+        .config;
+    apply_runtime_settings(&saved_cfg).await;
+
+    tracing::info!("config restored from backup {name}");
+    proxy::send_log_with_app(
+        &AppEvents::from(&app),
+        proxy::LogLevel::Info,
+        None,
+        format!("Config restored from backup {name}"),
+    );
     Ok(saved_cfg)
 }
 
@@ -97,38 +203,411 @@ pub async fn send_test_alert(cfg: config::AlertingConfig) -> Result<(), String>
     .await
 }
 
+#[tauri::command]
+pub fn get_active_alerts() -> Result<Vec<crate::alerting::ActiveAlert>, String> {
+    Ok(crate::alerting::get_active_alerts())
+}
+
+/// 保存并按差异应用配置；返回的配置附带 restarted_listeners，列出本次实际重启的监听
 #[tauri::command]
 pub async fn save_config(
     app: tauri::AppHandle,
     mut cfg: config::Config,
-) -> Result<config::Config, String> {
+) -> Result<crate::hot_reload::ReloadOutcome, String> {
     config::ensure_config_ids_for_save(&mut cfg);
     config::normalize_alerting_config(&mut cfg.alerting);
-    validate_config(&cfg).await?;
+    validate_config_for_save(&cfg).await?;
+    apply_metrics_storage(&cfg).await?;
 
+    let outcome = crate::hot_reload::graceful_reload(app.into(), cfg)
+        .await
+        .map_err(|e| e.to_string())?;
+    apply_runtime_settings(&outcome.config).await;
+    Ok(outcome)
+}
+
+/// 选择 TOML 文件并校验，返回与当前配置的差异供确认；校验失败不影响现有配置
+#[tauri::command]
+pub async fn import_config_toml(
+    app: tauri::AppHandle,
+) -> Result<Option<ConfigImportPreview>, String> {
+    crate::headless::require_gui()?;
+    let file = app
+        .dialog()
+        .file()
+        .set_title("Import Configuration")
+        .add_filter("TOML", &["toml"])
+        .add_filter("All Files", &["*"])
+        .blocking_pick_file();
+
+    let Some(file) = file else {
+        return Ok(None);
+    };
+    let path = file
+        .into_path()
+        .map_err(|e| format!("Failed to get file path: {e}"))?;
+
+    let (cfg, warnings) = load_import_candidate(&path).await?;
+    let (sections, changed_keys) = diff_configs(&config::get_config(), &cfg);
+    Ok(Some(ConfigImportPreview {
+        path: path.to_string_lossy().to_string(),
+        sections,
+        changed_keys,
+        warnings,
+    }))
+}
+
+/// 确认导入：重新读取并校验文件，停止服务后替换配置，原本在运行则重新启动
+#[tauri::command]
+pub async fn apply_config_import(
+    app: tauri::AppHandle,
+    path: String,
+) -> Result<config::Config, String> {
+    let (cfg, _) = load_import_candidate(Path::new(&path)).await?;
+    replace_active_config(app, cfg).await
+}
+
+/// 整体替换当前配置：停止服务、保存新配置并刷新全局设置，之前在运行则重新启动
+async fn replace_active_config(
+    app: tauri::AppHandle,
+    mut cfg: config::Config,
+) -> Result<config::Config, String> {
+    apply_metrics_storage(&cfg).await?;
+
+    let was_running = proxy::is_effectively_running();
+    if was_running {
+        proxy::stop_for_restart(&AppEvents::from(&app), true).await;
+    }
+
+    config::ensure_config_ids_for_save(&mut cfg);
+    config::set_config(cfg.clone());
+    config::save_config().map_err(|e| e.to_string())?;
+    apply_runtime_settings(&cfg).await;
+
+    if was_running {
+        proxy::start_server_after_restart(app.into()).map_err(|e| e.to_string())?;
+    }
+    Ok(cfg)
+}
+
+#[tauri::command]
+pub fn list_profiles() -> Result<Vec<ProfileInfo>, String> {
+    config_profiles::list_profiles().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_active_profile() -> Result<Option<String>, String> {
+    Ok(config_profiles::active_profile())
+}
+
+/// 将当前配置保存为命名方案，同名方案会被覆盖
+#[tauri::command]
+pub fn save_profile(name: String) -> Result<(), String> {
+    config_profiles::save_profile(&name).map_err(|e| format!("{e:#}"))
+}
+
+#[tauri::command]
+pub fn delete_profile(name: String) -> Result<(), String> {
+    config_profiles::delete_profile(&name).map_err(|e| format!("{e:#}"))
+}
+
+/// 校验并切换到指定方案；切换前的配置照常写入备份
+#[tauri::command]
+pub async fn activate_profile(
+    app: tauri::AppHandle,
+    name: String,
+) -> Result<config::Config, String> {
+    let cfg = config_profiles::load_profile(&name).map_err(|e| format!("{e:#}"))?;
+    validate_config_for_save(&cfg).await?;
+
+    let cfg = replace_active_config(app.clone(), cfg).await?;
+    config_profiles::set_active_profile(&name).map_err(|e| format!("{e:#}"))?;
+    crate::tray::set_tray_active_profile(Some(&name));
+
+    proxy::send_log_with_app(
+        &AppEvents::from(&app),
+        proxy::LogLevel::Info,
+        None,
+        format!("Switched to config profile {name}"),
+    );
+    Ok(cfg)
+}
+
+/// 导入 nginx 配置中的 server/location，追加到当前配置（不替换已有规则）
+#[tauri::command]
+pub async fn import_nginx_config(
+    app: tauri::AppHandle,
+    path: String,
+) -> Result<NginxImportReport, String> {
+    let mut cfg = config::get_config();
+    let report = nginx_import::import_nginx_file(Path::new(&path), &mut cfg)
+        .map_err(|e| format!("{e:#}"))?;
+    if report.routes_added == 0 {
+        return Ok(report);
+    }
+
+    config::ensure_config_ids_for_save(&mut cfg);
+    validate_config_for_save(&cfg).await?;
+    let saved_cfg = crate::hot_reload::graceful_reload(app.into(), cfg)
+        .await
+        .map_err(|e| e.to_string())?
+        .config;
+    apply_runtime_settings(&saved_cfg).await;
+    Ok(report)
+}
+
+/// 生成带注释的示例配置并返回写入路径；未指定路径时写入当前配置文件（仅当其中还没有任何规则）并立即应用
+#[tauri::command]
+pub async fn generate_example_config(
+    app: tauri::AppHandle,
+    path: Option<String>,
+) -> Result<String, String> {
+    let active = config::get_config_path().map_err(|e| e.to_string())?;
+    let target = path
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| active.clone());
+
+    if target != active {
+        config_example::write_example(&target).map_err(|e| format!("{e:#}"))?;
+        return Ok(target.display().to_string());
+    }
+
+    if config_example::has_entries(&config::get_config()) {
+        return Err(
+            "Current config already has rules; choose another path for the example config"
+                .to_string(),
+        );
+    }
+    let base_dir = active.parent().unwrap_or(Path::new("."));
+    let cfg = config::parse_config_toml(config_example::EXAMPLE_CONFIG, base_dir)
+        .map_err(|e| format!("{e:#}"))?;
+    validate_config_for_save(&cfg).await?;
+    config::write_config_text(config_example::EXAMPLE_CONFIG).map_err(|e| format!("{e:#}"))?;
+    apply_external_config(AppEvents::from(&app), cfg).await?;
+
+    proxy::send_log_with_app(
+        &AppEvents::from(&app),
+        proxy::LogLevel::Info,
+        None,
+        format!("Example config written to {}", active.display()),
+    );
+    Ok(active.display().to_string())
+}
+
+async fn load_import_candidate(path: &Path) -> Result<(config::Config, Vec<String>), String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read file ({}): {e}", path.display()))?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let cfg = config::parse_config_toml(&content, base_dir).map_err(|e| format!("{e:#}"))?;
+
+    let warnings = check_cert_files(&cfg)?;
+    // 未启用的 stream 配置 validate_config_for_save 不检查，导入时同样要求合法
+    if !cfg.stream.enabled && !cfg.stream.servers.is_empty() {
+        stream_proxy::validate_stream_config(&cfg.stream).map_err(|e| e.to_string())?;
+    }
+    validate_config_for_save(&cfg).await?;
+    Ok((cfg, warnings))
+}
+
+/// 启用的规则引用不存在的证书/私钥时报错，未启用的规则只给出警告
+fn check_cert_files(cfg: &config::Config) -> Result<Vec<String>, String> {
+    let http = cfg.rules.iter().map(|r| {
+        (
+            "Listen rule",
+            r.enabled,
+            r.ssl_enable,
+            &r.listen_addr,
+            [&r.cert_file, &r.key_file],
+        )
+    });
+    let ws = cfg.ws_proxy.iter().flatten().map(|r| {
+        (
+            "WS rule",
+            r.enabled,
+            r.ssl_enable,
+            &r.listen_addr,
+            [&r.cert_file, &r.key_file],
+        )
+    });
+
+    let mut warnings = Vec::new();
+    for (kind, enabled, ssl_enable, listen_addr, files) in http.chain(ws) {
+        if !ssl_enable {
+            continue;
+        }
+        for file in files {
+            let file = file.trim();
+            if file.is_empty() || Path::new(file).exists() {
+                continue;
+            }
+            let msg = format!("{kind} ({listen_addr}) references missing file: {file}");
+            if enabled {
+                return Err(msg);
+            }
+            warnings.push(msg);
+        }
+    }
+    Ok(warnings)
+}
+
+fn diff_configs(
+    current: &config::Config,
+    imported: &config::Config,
+) -> (Vec<ConfigSectionDiff>, Vec<String>) {
+    fn rule_keys(cfg: &config::Config) -> Vec<String> {
+        cfg.rules
+            .iter()
+            .map(|r| {
+                if r.listen_addrs.is_empty() {
+                    r.listen_addr.clone()
+                } else {
+                    r.listen_addrs.join(",")
+                }
+            })
+            .collect()
+    }
+    fn ws_keys(cfg: &config::Config) -> Vec<String> {
+        cfg.ws_proxy
+            .iter()
+            .flatten()
+            .map(|r| r.listen_addr.clone())
+            .collect()
+    }
+    fn stream_server_keys(cfg: &config::Config) -> Vec<String> {
+        cfg.stream
+            .servers
+            .iter()
+            .map(|s| {
+                let addr = s
+                    .listen_addr
+                    .clone()
+                    .or_else(|| s.listen_port.map(|p| p.to_string()))
+                    .unwrap_or_default();
+                if s.udp {
+                    format!("{addr}/udp")
+                } else {
+                    addr
+                }
+            })
+            .collect()
+    }
+    fn stream_upstream_keys(cfg: &config::Config) -> Vec<String> {
+        cfg.stream
+            .upstreams
+            .iter()
+            .map(|u| u.name.clone())
+            .collect()
+    }
+    fn whitelist_keys(cfg: &config::Config) -> Vec<String> {
+        cfg.whitelist.iter().map(|w| w.ip.clone()).collect()
+    }
+
+    type SectionKeys = fn(&config::Config) -> Vec<String>;
+    let sections: [(&str, SectionKeys); 5] = [
+        ("rules", rule_keys),
+        ("ws_proxy", ws_keys),
+        ("stream.servers", stream_server_keys),
+        ("stream.upstreams", stream_upstream_keys),
+        ("whitelist", whitelist_keys),
+    ];
+    let sections = sections
+        .into_iter()
+        .map(|(section, keys)| {
+            let (cur, imp) = (keys(current), keys(imported));
+            ConfigSectionDiff {
+                section: section.to_string(),
+                current: cur.len(),
+                imported: imp.len(),
+                added: imp.iter().filter(|k| !cur.contains(k)).cloned().collect(),
+                removed: cur.iter().filter(|k| !imp.contains(k)).cloned().collect(),
+            }
+        })
+        .collect();
+
+    let to_map = |cfg: &config::Config| match serde_json::to_value(cfg) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    };
+    let (cur, imp) = (to_map(current), to_map(imported));
+    let changed_keys = cur
+        .keys()
+        .chain(imp.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|k| cur.get(*k) != imp.get(*k))
+        .cloned()
+        .collect();
+
+    (sections, changed_keys)
+}
+
+async fn apply_metrics_storage(cfg: &config::Config) -> Result<(), String> {
     if let Some(metrics_storage) = cfg.metrics_storage.as_ref() {
         if metrics_storage.enabled {
-            crate::metrics::init_db(metrics_storage.db_path.clone())
+            crate::metrics::init_storage(metrics_storage)
                 .await
                 .map_err(|e| e.to_string())?;
             crate::metrics::init_request_log_writer().await;
-        } else {
-            crate::metrics::deinit_db();
+            return Ok(());
         }
-    } else {
-        crate::metrics::deinit_db();
     }
+    crate::metrics::deinit_db();
+    Ok(())
+}
 
-    let saved_cfg = crate::hot_reload::graceful_reload(app, cfg)
+/// 应用外部修改的配置文件（文件监听触发）：校验通过后按差异重载，不回写文件
+pub(crate) async fn apply_external_config(
+    app: AppEvents,
+    cfg: config::Config,
+) -> Result<config::Config, String> {
+    validate_config_for_save(&cfg).await?;
+    apply_metrics_storage(&cfg).await?;
+    let applied = crate::hot_reload::reload_without_save(app, cfg)
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| e.to_string())?
+        .config;
+    apply_runtime_settings(&applied).await;
+    Ok(applied)
+}
+
+/// 将密码等写入系统凭据存储，配置中以 "keyring:<name>" 引用
+#[tauri::command]
+pub fn set_secret(name: String, value: String) -> Result<(), String> {
+    secrets::set_secret(&name, &value).map_err(|e| format!("{e:#}"))
+}
+
+#[tauri::command]
+pub fn delete_secret(name: String) -> Result<(), String> {
+    secrets::delete_secret(&name).map_err(|e| format!("{e:#}"))
+}
+
+#[tauri::command]
+pub fn list_secret_names() -> Result<Vec<String>, String> {
+    secrets::list_secret_names().map_err(|e| format!("{e:#}"))
+}
+
+/// 配置落盘后刷新不随监听重启生效的全局设置
+async fn apply_runtime_settings(saved_cfg: &config::Config) {
     system_metrics::refresh_sample_interval_from_config();
-    Ok(saved_cfg)
+    if let Err(e) = crate::geoip::apply_geoip_config(saved_cfg.geoip.as_ref()) {
+        proxy::logging::push_log_line(format!("[GEOIP] {e:#}"));
+    }
+    if let Err(e) = proxy::access_log::apply_access_log_config(saved_cfg) {
+        proxy::logging::push_log_line(format!("[ACCESS_LOG] {e:#}"));
+    }
+    if let Err(e) = crate::metrics::apply_prometheus_config(saved_cfg.prometheus.clone()).await {
+        proxy::logging::push_log_line(format!("[PROMETHEUS] {e:#}"));
+    }
+    if let Err(e) = proxy::syslog::apply_syslog_config(saved_cfg.syslog.clone()) {
+        proxy::logging::push_log_line(format!("[SYSLOG] {e:#}"));
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::validate_config;
+    use super::{diff_configs, validate_config_for_save};
     use crate::config::{
         AlertRulesConfig, AlertWebhookConfig, AlertingConfig, Config, ListenRule, Route,
         StreamProxyConfig, Upstream, WhitelistEntry,
@@ -169,6 +648,8 @@ mod tests {
 
     fn sample_config() -> Config {
         Config {
+            config_version: crate::config_migration::CURRENT_CONFIG_VERSION,
+            include: vec![],
             rules: vec![ListenRule {
                 id: Some("rule".into()),
                 enabled: true,
@@ -187,6 +668,8 @@ mod tests {
                 rate_limit_burst_size: None,
                 rate_limit_window_seconds: None,
                 rate_limit_ban_seconds: None,
+                bandwidth_limit_bytes_per_sec: None,
+                client_overrides: None,
             }],
             ws_proxy_enabled: true,
             ws_proxy: None,
@@ -202,9 +685,16 @@ mod tests {
             auto_start: false,
             show_realtime_logs: true,
             realtime_logs_only_errors: false,
+            log_buffer_size: 3000,
+            log_queue_capacity: 10_000,
+            config_backup_keep: 10,
+            stream_log_connections: false,
             stream_proxy: true,
             max_body_size: 1024,
             max_response_body_size: 1024,
+            slow_request_threshold_ms: 0,
+            drain_timeout_secs: 10,
+            partial_start_allowed: false,
             upstream_connect_timeout_ms: 3000,
             upstream_read_timeout_ms: 30000,
             upstream_pool_max_idle: 10,
@@ -218,15 +708,25 @@ mod tests {
             compression_brotli_level: 6,
             system_metrics_sample_interval_secs: 10,
             system_metrics_persistence_enabled: true,
+            metrics_push_interval_ms: 2000,
+            metrics_push_incremental: false,
+            access_log_format: None,
+            access_log_file: None,
+            access_log_json: false,
+            access_log_json_events: false,
             metrics_storage: None,
             update: None,
             alerting: None,
+            alerts: None,
+            geoip: None,
+            prometheus: None,
+            syslog: None,
         }
     }
 
     #[tokio::test]
     async fn validate_config_accepts_minimal_valid_config() {
-        validate_config(&sample_config()).await.unwrap();
+        validate_config_for_save(&sample_config()).await.unwrap();
     }
 
     #[tokio::test]
@@ -234,7 +734,7 @@ mod tests {
         let mut cfg = sample_config();
         cfg.rules[0].ssl_enable = true;
 
-        let err = validate_config(&cfg).await.unwrap_err();
+        let err = validate_config_for_save(&cfg).await.unwrap_err();
         assert!(err.contains("certificate or private key path is empty"));
     }
 
@@ -248,12 +748,31 @@ mod tests {
             cert_file: String::new(),
             key_file: String::new(),
             routes: vec![WsRoute {
+                host: None,
                 path: "/ws".into(),
-                upstream_url: "ws://backend".into(),
+                upstreams: vec![Upstream {
+                    url: "ws://backend".into(),
+                    weight: 1,
+                }],
+                lb_strategy: Default::default(),
+                proxy_pass_path: None,
+                forward_headers: vec![],
+                tls: None,
+                retry_count: 0,
+                max_fails: 1,
+                fail_timeout: "30s".into(),
             }],
+            ping_interval_secs: 0,
+            idle_timeout_secs: 0,
+            max_missed_pings: 3,
+            max_message_size: None,
+            max_frame_size: None,
+            max_connections_per_ip: None,
+            max_total_connections: None,
+            permessage_deflate: false,
         }]);
 
-        let err = validate_config(&cfg).await.unwrap_err();
+        let err = validate_config_for_save(&cfg).await.unwrap_err();
         assert!(err.contains("WS rule"));
         assert!(err.contains("certificate or private key path is empty"));
     }
@@ -268,11 +787,18 @@ mod tests {
             proxy_pass: "missing".into(),
             proxy_connect_timeout: "3s".into(),
             proxy_timeout: "30s".into(),
+            max_session_duration: None,
             udp: false,
             listen_addr: Some("127.0.0.1:7000".into()),
+            sni_routing: vec![],
+            proxy_protocol: false,
+            connect_retries: 2,
+            limit_rate: None,
+            limit_rate_total: None,
+            limit_pps: None,
         }];
 
-        let err = validate_config(&cfg).await.unwrap_err();
+        let err = validate_config_for_save(&cfg).await.unwrap_err();
         assert!(err.contains("references missing upstream"));
     }
 
@@ -298,7 +824,27 @@ mod tests {
             },
         });
 
-        let err = validate_config(&cfg).await.unwrap_err();
+        let err = validate_config_for_save(&cfg).await.unwrap_err();
         assert!(err.contains("Webhook URL is empty"));
     }
+
+    #[test]
+    fn diff_configs_lists_added_removed_and_changed_keys() {
+        let current = sample_config();
+        let mut imported = sample_config();
+        imported.rules[0].listen_addr = "0.0.0.0:443".into();
+        imported.whitelist.push(WhitelistEntry {
+            ip: "10.0.0.0/8".into(),
+        });
+        imported.max_body_size = 4096;
+
+        let (sections, changed) = diff_configs(&current, &imported);
+        let rules = sections.iter().find(|s| s.section == "rules").unwrap();
+        assert_eq!(rules.added, vec!["0.0.0.0:443".to_string()]);
+        assert_eq!(rules.removed, vec!["127.0.0.1:8080".to_string()]);
+        let whitelist = sections.iter().find(|s| s.section == "whitelist").unwrap();
+        assert_eq!((whitelist.current, whitelist.imported), (1, 2));
+        assert!(whitelist.removed.is_empty());
+        assert_eq!(changed, vec!["max_body_size", "rules", "whitelist"]);
+    }
 }
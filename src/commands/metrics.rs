@@ -1,6 +1,10 @@
 use crate::cache_optimizer;
 use crate::metrics;
+use crate::rate_limit;
 use crate::system_metrics;
+use std::path::PathBuf;
+use tauri::Manager;
+use tauri_plugin_dialog::DialogExt;
 
 #[tauri::command]
 pub fn get_metrics() -> Result<metrics::MetricsPayload, String> {
@@ -20,6 +24,24 @@ pub fn set_system_metrics_subscription(active: bool) -> Result<(), String> {
     Ok(())
 }
 
+/// 实时推送匹配的请求日志（`request-log-live` 事件），返回订阅 id
+#[tauri::command]
+pub fn subscribe_request_logs(
+    webview: tauri::Webview,
+    filter: Option<metrics::LiveTailFilter>,
+) -> Result<u64, String> {
+    Ok(metrics::subscribe_request_logs(
+        webview.app_handle().clone(),
+        webview.label(),
+        filter.unwrap_or_default(),
+    ))
+}
+
+#[tauri::command]
+pub fn unsubscribe_request_logs(id: u64) -> Result<bool, String> {
+    Ok(metrics::unsubscribe_request_logs(id))
+}
+
 #[tauri::command]
 pub async fn query_historical_system_metrics(
     req: system_metrics::QuerySystemMetricsRequest,
@@ -54,6 +76,33 @@ pub async fn get_dashboard_stats(
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn get_status_code_breakdown(
+    req: metrics::StatusCodeBreakdownRequest,
+) -> Result<metrics::StatusCodeBreakdownResponse, String> {
+    metrics::get_status_code_breakdown(req)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_upstream_stats(
+    req: metrics::UpstreamStatsRequest,
+) -> Result<Vec<metrics::UpstreamStatsItem>, String> {
+    metrics::get_upstream_stats(req)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_route_stats(
+    req: metrics::RouteStatsRequest,
+) -> Result<Vec<metrics::RouteStatsItem>, String> {
+    metrics::get_route_stats(req)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn query_request_logs(
     req: metrics::QueryRequestLogsRequest,
@@ -63,6 +112,76 @@ pub async fn query_request_logs(
         .map_err(|e| e.to_string())
 }
 
+/// 命中行数超过上限且未传 force 时不弹出保存框，返回 over_limit 由前端确认
+#[tauri::command]
+pub async fn export_request_logs(
+    app: tauri::AppHandle,
+    req: metrics::QueryRequestLogsRequest,
+    format: String,
+    force: Option<bool>,
+) -> Result<metrics::ExportRequestLogsResult, String> {
+    crate::headless::require_gui()?;
+    let format = metrics::ExportFormat::parse(&format).map_err(|e| e.to_string())?;
+    let max_rows = metrics::export_max_rows();
+    let total = metrics::count_request_logs_for_export(&req)
+        .await
+        .map_err(|e| e.to_string())?;
+    if total.max(0) as u64 > max_rows && !force.unwrap_or(false) {
+        return Ok(metrics::ExportRequestLogsResult {
+            rows: total,
+            path: None,
+            max_rows,
+            over_limit: true,
+        });
+    }
+
+    let ts = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let default_name = format!("request-logs-{}.{}", ts, format.extension());
+    let filter_name = match format {
+        metrics::ExportFormat::Csv => "CSV",
+        metrics::ExportFormat::Jsonl => "JSON Lines",
+    };
+    let file = app
+        .dialog()
+        .file()
+        .set_title("Export Request Logs")
+        .set_file_name(&default_name)
+        .add_filter(filter_name, &[format.extension()])
+        .add_filter("All Files", &["*"])
+        .blocking_save_file();
+
+    let Some(file) = file else {
+        return Ok(metrics::ExportRequestLogsResult {
+            rows: 0,
+            path: None,
+            max_rows,
+            over_limit: false,
+        });
+    };
+    let path: PathBuf = file
+        .into_path()
+        .map_err(|e| format!("Failed to get save path: {e}"))?;
+
+    let rows = metrics::export_request_logs_to_file(req, format, &path)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(metrics::ExportRequestLogsResult {
+        rows,
+        path: Some(path.to_string_lossy().to_string()),
+        max_rows,
+        over_limit: false,
+    })
+}
+
+#[tauri::command]
+pub async fn query_stream_logs(
+    req: metrics::QueryStreamLogsRequest,
+) -> Result<metrics::QueryStreamLogsResponse, String> {
+    metrics::query_stream_logs(req)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn add_blacklist_entry(
     ip: String,
@@ -95,6 +214,15 @@ pub async fn refresh_blacklist_cache() -> Result<(), String> {
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn clear_request_logs(
+    req: metrics::ClearRequestLogsRequest,
+) -> Result<metrics::ClearRequestLogsResult, String> {
+    metrics::clear_request_logs(req)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub fn get_metrics_db_status() -> Result<metrics::MetricsDBStatus, String> {
     Ok(metrics::get_metrics_db_status())
@@ -108,8 +236,26 @@ pub async fn get_metrics_db_status_detail() -> Result<metrics::MetricsDBStatus,
 }
 
 #[tauri::command]
-pub async fn test_metrics_db_connection(db_path: String) -> Result<(bool, String), String> {
-    metrics::test_metrics_db_connection(db_path)
+pub async fn get_metrics_db_stats() -> Result<metrics::MetricsDBStats, String> {
+    metrics::get_metrics_db_stats()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn vacuum_metrics_db() -> Result<metrics::VacuumResult, String> {
+    metrics::vacuum_metrics_db()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn test_metrics_db_connection(
+    db_path: String,
+    backend: Option<String>,
+    url: Option<String>,
+) -> Result<(bool, String), String> {
+    metrics::test_metrics_db_connection(db_path, backend, url)
         .await
         .map_err(|e| e.to_string())
 }
@@ -143,6 +289,40 @@ pub fn get_cache_stats() -> Result<serde_json::Value, String> {
     }))
 }
 
+#[tauri::command]
+pub fn get_rate_limit_stats() -> Result<serde_json::Value, String> {
+    let request_limiters: Vec<serde_json::Value> = rate_limit::RATE_LIMITERS
+        .iter()
+        .map(|entry| {
+            serde_json::json!({
+                "listen_addr": entry.key(),
+                "tracked_ips": entry.value().read().tracked_ips(),
+            })
+        })
+        .collect();
+
+    let bandwidth_limiters: Vec<serde_json::Value> = rate_limit::BANDWIDTH_LIMITERS
+        .iter()
+        .map(|entry| {
+            serde_json::json!({
+                "listen_addr": entry.key(),
+                "limit_bytes_per_sec": entry.value().bytes_per_sec(),
+                "ips": entry.value().stats(),
+            })
+        })
+        .collect();
+
+    Ok(serde_json::json!({
+        "request": request_limiters,
+        "bandwidth": bandwidth_limiters,
+    }))
+}
+
+#[tauri::command]
+pub fn get_ws_connections() -> Result<Vec<crate::proxy::ws_proxy::WsListenConnections>, String> {
+    Ok(crate::proxy::ws_proxy::get_ws_connections())
+}
+
 #[tauri::command]
 pub fn clear_all_caches() -> Result<(), String> {
     let manager = cache_optimizer::global_cache_manager();
@@ -1,3 +1,4 @@
+use crate::app_paths::{self, AppPaths};
 use crate::config;
 use crate::proxy;
 use crate::tray;
@@ -37,70 +38,125 @@ pub async fn check_update() -> Result<update::CheckResult, String> {
     }
 }
 
+/// 当前是否处于便携模式及解析后的配置/数据路径
+#[tauri::command]
+pub fn get_app_paths() -> Result<AppPaths, String> {
+    app_paths::app_paths().map_err(|e| format!("{e:#}"))
+}
+
 #[tauri::command]
 pub fn start_server(app: tauri::AppHandle) -> Result<(), String> {
-    proxy::start_server(app).map_err(|e| e.to_string())
+    proxy::start_server(app.into()).map_err(|e| e.to_string())
 }
 
+/// 停止接收新连接，等待进行中的连接在 drain_timeout_secs 内结束，返回正常结束与被强制断开的连接数
 #[tauri::command]
-pub fn stop_server(app: tauri::AppHandle) -> Result<(), String> {
-    proxy::stop_server(app).map_err(|e| e.to_string())
+pub async fn stop_server(app: tauri::AppHandle) -> Result<proxy::DrainReport, String> {
+    Ok(proxy::stop_server_gracefully(app.into()).await)
 }
 
+/// 停止并等待监听释放后再启动，期间 status 事件为 "restarting"
 #[tauri::command]
-pub fn get_status() -> Result<String, String> {
-    Ok(if proxy::is_running() {
-        "running".to_string()
+pub async fn restart_server(app: tauri::AppHandle) -> Result<(), String> {
+    proxy::restart_server(app.into())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(untagged)]
+pub enum StatusReply {
+    Summary(String),
+    Detailed {
+        /// running / starting / stopped / failed
+        status: String,
+        rules: Vec<proxy::ListenRuleStatus>,
+        /// HTTP、WS、stream 各监听的状态
+        listeners: Vec<proxy::ListenerStatus>,
+    },
+}
+
+/// 默认只返回 "running" / "stopped"，与现有界面兼容；detailed 为 true 时返回整体阶段及各规则、各监听的状态
+#[tauri::command]
+pub fn get_status(detailed: Option<bool>) -> Result<StatusReply, String> {
+    if detailed.unwrap_or(false) {
+        return Ok(StatusReply::Detailed {
+            status: proxy::phase_name().to_string(),
+            rules: proxy::listen_rule_statuses(),
+            listeners: proxy::listener_statuses(),
+        });
+    }
+    let status = if proxy::is_running() {
+        "running"
     } else {
-        "stopped".to_string()
-    })
+        "stopped"
+    };
+    Ok(StatusReply::Summary(status.to_string()))
 }
 
+/// 进行中的 HTTP 请求、WS 会话与 stream 会话，按监听地址分组
 #[tauri::command]
-pub fn set_tray_proxy_state(_app: tauri::AppHandle, running: bool) -> Result<(), String> {
-    tray::set_tray_proxy_state(running);
-    Ok(())
+pub fn get_active_connections() -> Result<Vec<proxy::connections::ListenerConnections>, String> {
+    Ok(proxy::connections::active_connections())
 }
 
+/// 全部上游的健康状态，按 HTTP/WS 路由与 stream 上游组分组
 #[tauri::command]
-pub async fn set_route_enabled(
-    app: tauri::AppHandle,
-    args: SetRouteEnabledArgs,
-) -> Result<config::Config, String> {
-    let was_running = proxy::is_effectively_running();
+pub fn get_upstream_health() -> Result<Vec<proxy::upstream_health::UpstreamHealthGroup>, String> {
+    Ok(proxy::upstream_health::upstream_health(
+        &config::get_config(),
+    ))
+}
 
-    if was_running {
-        proxy::stop_server(app.clone()).map_err(|e| e.to_string())?;
-        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
-    }
+/// 启用并单独启动一条监听规则，不影响其他监听
+#[tauri::command]
+pub fn start_listen_rule(app: tauri::AppHandle, listen_rule_id: String) -> Result<(), String> {
+    proxy::start_listen_rule(app.into(), &listen_rule_id).map_err(|e| e.to_string())
+}
 
-    let mut cfg = config::get_config();
+/// 停用并单独停止一条监听规则，不影响其他监听
+#[tauri::command]
+pub async fn stop_listen_rule(app: tauri::AppHandle, listen_rule_id: String) -> Result<(), String> {
+    proxy::stop_listen_rule(app.into(), &listen_rule_id)
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    let mut found = false;
-    for lr in &mut cfg.rules {
-        if lr.id.as_deref().unwrap_or("") == args.listen_rule_id {
-            for rt in &mut lr.routes {
-                if rt.id.as_deref().unwrap_or("") == args.route_id {
-                    rt.enabled = args.enabled;
-                    found = true;
-                    break;
-                }
-            }
-        }
-        if found {
-            break;
-        }
-    }
+#[tauri::command]
+pub fn set_tray_proxy_state(_app: tauri::AppHandle, running: bool) -> Result<(), String> {
+    tray::set_tray_proxy_state(running);
+    Ok(())
+}
 
-    if !found {
-        return Err("Target listen rule or route not found".to_string());
-    }
+/// 切换路由启用状态：保存配置后把新规则交给正在运行的监听，不重启监听也不重启应用
+#[tauri::command]
+pub fn set_route_enabled(args: SetRouteEnabledArgs) -> Result<config::Config, String> {
+    let mut cfg = config::get_config();
+    let rule = apply_route_enabled(&mut cfg, &args)?;
 
-    config::ensure_config_ids_for_save(&mut cfg);
     config::set_config(cfg.clone());
     config::save_config().map_err(|e| e.to_string())?;
+    proxy::update_listen_rule(&rule).map_err(|e| e.to_string())?;
+    Ok(cfg)
+}
 
-    app.restart();
+/// 修改配置中目标路由的 enabled，返回所在的监听规则
+fn apply_route_enabled(
+    cfg: &mut config::Config,
+    args: &SetRouteEnabledArgs,
+) -> Result<config::ListenRule, String> {
+    let rule = cfg
+        .rules
+        .iter_mut()
+        .find(|lr| lr.id.as_deref() == Some(args.listen_rule_id.as_str()))
+        .ok_or_else(|| "Target listen rule or route not found".to_string())?;
+    let route = rule
+        .routes
+        .iter_mut()
+        .find(|rt| rt.id.as_deref() == Some(args.route_id.as_str()))
+        .ok_or_else(|| "Target listen rule or route not found".to_string())?;
+    route.enabled = args.enabled;
+    Ok(rule.clone())
 }
 
 #[tauri::command]
@@ -108,31 +164,81 @@ pub async fn set_listen_rule_enabled(
     app: tauri::AppHandle,
     args: SetListenRuleEnabledArgs,
 ) -> Result<config::Config, String> {
-    let was_running = proxy::is_effectively_running();
-
-    if was_running {
-        proxy::stop_server(app.clone()).map_err(|e| e.to_string())?;
-        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
-    }
-
-    let mut cfg = config::get_config();
-
-    let mut found = false;
-    for lr in &mut cfg.rules {
-        if lr.id.as_deref().unwrap_or("") == args.listen_rule_id {
-            lr.enabled = args.enabled;
-            found = true;
-            break;
-        }
+    if args.enabled {
+        proxy::start_listen_rule(app.into(), &args.listen_rule_id)
+    } else {
+        proxy::stop_listen_rule(app.into(), &args.listen_rule_id).await
     }
+    .map_err(|e| e.to_string())?;
+    Ok(config::get_config())
+}
 
-    if !found {
-        return Err("Target listen rule not found".to_string());
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_route_enabled_changes_only_the_target_route() {
+        let mut cfg: config::Config = toml::from_str(
+            r#"
+allow_all_lan = true
+whitelist = []
+
+[[rules]]
+id = "site-a"
+listen_addr = "127.0.0.1:18080"
+ssl_enable = false
+cert_file = ""
+key_file = ""
+basic_auth_enable = false
+basic_auth_username = ""
+basic_auth_password = ""
+basic_auth_forward_header = false
+
+[[rules.routes]]
+id = "a-api"
+path = "/api"
+upstreams = []
+
+[[rules.routes]]
+id = "a-root"
+path = "/"
+upstreams = []
+
+[[rules]]
+id = "site-b"
+listen_addr = "127.0.0.1:18081"
+ssl_enable = false
+cert_file = ""
+key_file = ""
+basic_auth_enable = false
+basic_auth_username = ""
+basic_auth_password = ""
+basic_auth_forward_header = false
+
+[[rules.routes]]
+id = "b-root"
+path = "/"
+upstreams = []
+"#,
+        )
+        .unwrap();
+        let other = cfg.rules[1].clone();
+
+        let args = |rule: &str, route: &str| SetRouteEnabledArgs {
+            listen_rule_id: rule.into(),
+            route_id: route.into(),
+            enabled: false,
+        };
+        let rule = apply_route_enabled(&mut cfg, &args("site-a", "a-api")).unwrap();
+
+        assert_eq!(rule.id.as_deref(), Some("site-a"));
+        assert!(!rule.routes[0].enabled);
+        assert!(rule.routes[1].enabled);
+        assert_eq!(cfg.rules[0], rule);
+        assert_eq!(cfg.rules[1], other);
+
+        assert!(apply_route_enabled(&mut cfg, &args("site-a", "b-root")).is_err());
+        assert!(apply_route_enabled(&mut cfg, &args("missing", "a-api")).is_err());
     }
-
-    config::ensure_config_ids_for_save(&mut cfg);
-    config::set_config(cfg.clone());
-    config::save_config().map_err(|e| e.to_string())?;
-
-    app.restart();
 }
@@ -1,3 +1,4 @@
+use crate::proxy::replay;
 use crate::test_tools;
 
 #[tauri::command]
@@ -57,6 +58,17 @@ pub async fn get_ssl_cert_info(
         .map_err(|e| e.to_string())
 }
 
+/// 按代理转发时的客户端设置测试上游，返回 DNS、TCP、TLS、请求各阶段的耗时或出错阶段
+#[tauri::command]
+pub async fn test_upstream(
+    url: String,
+    options: Option<test_tools::UpstreamTestOptions>,
+) -> Result<test_tools::UpstreamTestResult, String> {
+    test_tools::test_upstream(&url, options.unwrap_or_default())
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub fn generate_self_signed_cert(
     req: test_tools::SelfSignedCertRequest,
@@ -77,3 +89,21 @@ pub fn encode_decode(
 ) -> Result<test_tools::EncodeDecodeResult, String> {
     test_tools::encode_decode(req).map_err(|e| e.to_string())
 }
+
+#[tauri::command]
+pub async fn replay_request(
+    log_id: i64,
+    target: String,
+    confirm: Option<bool>,
+    record: Option<bool>,
+) -> Result<replay::ReplayResult, String> {
+    let target = replay::ReplayTarget::parse(&target).map_err(|e| e.to_string())?;
+    replay::replay_logged_request(
+        log_id,
+        target,
+        confirm.unwrap_or(false),
+        record.unwrap_or(false),
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
@@ -13,9 +13,20 @@ pub fn get_version() -> Result<String, String> {
     Ok(env!("CARGO_PKG_VERSION").to_string())
 }
 
+/// 旧版纯文本日志，可选按级别、节点与关键字过滤
 #[tauri::command]
-pub fn get_logs() -> Result<Vec<String>, String> {
-    Ok(proxy::get_logs())
+pub fn get_logs(filter: Option<proxy::LogFilter>) -> Result<Vec<String>, String> {
+    Ok(proxy::get_logs(&filter.unwrap_or_default()))
+}
+
+#[tauri::command]
+pub fn get_log_entries(filter: Option<proxy::LogFilter>) -> Result<Vec<proxy::LogEntry>, String> {
+    Ok(proxy::get_log_entries(&filter.unwrap_or_default()))
+}
+
+#[tauri::command]
+pub fn get_log_stats() -> Result<proxy::LogStats, String> {
+    Ok(proxy::get_log_stats())
 }
 
 #[tauri::command]
@@ -34,6 +45,7 @@ pub fn hide_to_tray(app: tauri::AppHandle) -> Result<(), String> {
 
 #[tauri::command]
 pub async fn open_cert_file_dialog(app: tauri::AppHandle) -> Result<Option<String>, String> {
+    crate::headless::require_gui()?;
     let file = app
         .dialog()
         .file()
@@ -49,6 +61,7 @@ pub async fn open_cert_file_dialog(app: tauri::AppHandle) -> Result<Option<Strin
 
 #[tauri::command]
 pub async fn open_key_file_dialog(app: tauri::AppHandle) -> Result<Option<String>, String> {
+    crate::headless::require_gui()?;
     let file = app
         .dialog()
         .file()
@@ -64,6 +77,7 @@ pub async fn open_key_file_dialog(app: tauri::AppHandle) -> Result<Option<String
 
 #[tauri::command]
 pub async fn open_directory_dialog(app: tauri::AppHandle) -> Result<Option<String>, String> {
+    crate::headless::require_gui()?;
     let dir = app
         .dialog()
         .file()
@@ -77,6 +91,7 @@ pub async fn open_directory_dialog(app: tauri::AppHandle) -> Result<Option<Strin
 
 #[tauri::command]
 pub async fn open_db_file_dialog(app: tauri::AppHandle) -> Result<Option<String>, String> {
+    crate::headless::require_gui()?;
     let file = app
         .dialog()
         .file()
@@ -93,6 +108,7 @@ pub async fn open_db_file_dialog(app: tauri::AppHandle) -> Result<Option<String>
 
 #[tauri::command]
 pub async fn open_existing_db_file_dialog(app: tauri::AppHandle) -> Result<Option<String>, String> {
+    crate::headless::require_gui()?;
     let file = app
         .dialog()
         .file()
@@ -175,7 +191,8 @@ pub async fn open_chart_preview_window(
 
 #[tauri::command]
 pub fn quit_app(app: tauri::AppHandle) -> Result<(), String> {
-    proxy::stop_server(app.clone()).ok();
+    proxy::stop_server((&app).into()).ok();
+    crate::metrics::shutdown_blocking(crate::metrics::SHUTDOWN_FLUSH_TIMEOUT);
     app.exit(0);
     Ok(())
 }
@@ -185,6 +202,7 @@ pub async fn save_config_toml_as(
     app: tauri::AppHandle,
     content: String,
 ) -> Result<Option<String>, String> {
+    crate::headless::require_gui()?;
     let ts = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
     let default_name = format!("config-{}.toml", ts);
 
@@ -216,6 +234,7 @@ pub async fn save_chart_png_with_dialog(
     default_file_name: String,
     png_data_url: String,
 ) -> Result<Option<String>, String> {
+    crate::headless::require_gui()?;
     let file = app
         .dialog()
         .file()
@@ -250,14 +269,20 @@ pub async fn save_chart_png_with_dialog(
 
 #[tauri::command]
 pub async fn export_current_config_toml(app: tauri::AppHandle) -> Result<Option<String>, String> {
+    crate::headless::require_gui()?;
     let cfg_path = crate::config::get_config_path().map_err(|e| e.to_string())?;
 
-    let content = std::fs::read_to_string(&cfg_path).map_err(|e| {
-        format!(
-            "Failed to read current config file ({}): {e}",
-            cfg_path.display()
-        )
-    })?;
+    // 使用 include 拆分的配置合并为单文件导出，否则原样导出配置文件
+    let content = if crate::config::get_config().include.is_empty() {
+        std::fs::read_to_string(&cfg_path).map_err(|e| {
+            format!(
+                "Failed to read current config file ({}): {e}",
+                cfg_path.display()
+            )
+        })?
+    } else {
+        crate::config::flattened_config_toml().map_err(|e| format!("{e:#}"))?
+    };
 
     let ts = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
     let default_name = format!("config-{}.toml", ts);
@@ -284,6 +309,34 @@ pub async fn export_current_config_toml(app: tauri::AppHandle) -> Result<Option<
     Ok(Some(path.to_string_lossy().to_string()))
 }
 
+/// 将当前配置翻译为 nginx.conf 并通过保存对话框导出
+#[tauri::command]
+pub async fn export_nginx_config(app: tauri::AppHandle) -> Result<Option<String>, String> {
+    crate::headless::require_gui()?;
+    let content = crate::nginx_export::render_nginx_config(&crate::config::get_config());
+
+    let file = app
+        .dialog()
+        .file()
+        .set_title("Export nginx Configuration")
+        .set_file_name("nginx.conf")
+        .add_filter("nginx config", &["conf"])
+        .add_filter("All Files", &["*"])
+        .blocking_save_file();
+
+    let Some(file) = file else {
+        return Ok(None);
+    };
+
+    let path: PathBuf = file
+        .into_path()
+        .map_err(|e| format!("Failed to get save path: {e}"))?;
+
+    std::fs::write(&path, content).map_err(|e| format!("Failed to write file: {e}"))?;
+
+    Ok(Some(path.to_string_lossy().to_string()))
+}
+
 #[tauri::command]
 pub fn set_locale(locale: String) -> Result<(), String> {
     i18n::set_locale(locale);
@@ -0,0 +1,556 @@
+use serde::Serialize;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use crate::config::{BodyReplaceRule, Config, ListenRule};
+use crate::proxy::{parse_listen_addr, stream_proxy};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IssueSeverity {
+    Error,
+    Warning,
+}
+
+/// 单项配置问题；path 指向具体字段，如 rules[1].routes[0].url_rewrite_rules[2].pattern
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigIssue {
+    pub path: String,
+    pub severity: IssueSeverity,
+    pub message: String,
+}
+
+#[derive(Default)]
+struct Issues(Vec<ConfigIssue>);
+
+impl Issues {
+    /// 未启用的规则不影响启动，只记为警告
+    fn push(&mut self, enabled: bool, path: String, message: impl Into<String>) {
+        let severity = if enabled {
+            IssueSeverity::Error
+        } else {
+            IssueSeverity::Warning
+        };
+        self.0.push(ConfigIssue {
+            path,
+            severity,
+            message: message.into(),
+        });
+    }
+}
+
+/// 一个会实际占用端口的监听
+struct Listener {
+    path: String,
+    addr: SocketAddr,
+    udp: bool,
+}
+
+/// 两个配置条目会绑定到重叠的地址；path 为字段路径，如 rules[0].listen_addrs[1]
+#[derive(Debug, Clone, Serialize)]
+pub struct PortConflict {
+    pub path: String,
+    pub addr: SocketAddr,
+    pub conflicts_with: String,
+    pub conflicts_with_addr: SocketAddr,
+    pub udp: bool,
+}
+
+impl fmt::Display for PortConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} port {} conflict: {} ({}) overlaps {} ({})",
+            if self.udp { "UDP" } else { "TCP" },
+            self.addr.port(),
+            self.path,
+            self.addr,
+            self.conflicts_with,
+            self.conflicts_with_addr
+        )
+    }
+}
+
+impl std::error::Error for PortConflict {}
+
+/// 规则实际监听的地址及其字段路径；listen_addrs 为空时回退到 listen_addr
+fn rule_addr_paths<'a>(base: &str, rule: &'a ListenRule) -> Vec<(String, &'a String)> {
+    if rule.listen_addrs.is_empty() {
+        vec![(format!("{base}.listen_addr"), &rule.listen_addr)]
+    } else {
+        rule.listen_addrs
+            .iter()
+            .enumerate()
+            .map(|(j, a)| (format!("{base}.listen_addrs[{j}]"), a))
+            .collect()
+    }
+}
+
+/// 启动时会实际绑定的全部监听；无法解析的地址由 check_config 另行报告
+fn active_listeners(cfg: &Config) -> Vec<Listener> {
+    let mut listeners = Vec::new();
+
+    for (i, rule) in cfg.rules.iter().enumerate().filter(|(_, r)| r.enabled) {
+        for (path, addr) in rule_addr_paths(&format!("rules[{i}]"), rule) {
+            if let Ok((addr, _)) = parse_listen_addr(addr) {
+                listeners.push(Listener {
+                    path,
+                    addr,
+                    udp: false,
+                });
+            }
+        }
+    }
+
+    if cfg.ws_proxy_enabled {
+        for (i, rule) in cfg.ws_proxy.iter().flatten().enumerate() {
+            if !rule.enabled {
+                continue;
+            }
+            if let Ok((addr, _)) = parse_listen_addr(&rule.listen_addr) {
+                listeners.push(Listener {
+                    path: format!("ws_proxy[{i}].listen_addr"),
+                    addr,
+                    udp: false,
+                });
+            }
+        }
+    }
+
+    if cfg.stream.enabled {
+        for (i, server) in cfg.stream.servers.iter().enumerate() {
+            if !server.enabled {
+                continue;
+            }
+            if let Ok((addr, _)) =
+                stream_proxy::resolve_listen_addr(server).and_then(|a| parse_listen_addr(&a))
+            {
+                listeners.push(Listener {
+                    path: format!("stream.servers[{i}].listen_addr"),
+                    addr,
+                    udp: server.udp,
+                });
+            }
+        }
+    }
+
+    listeners
+}
+
+/// 检查 HTTP、WS 与 Stream 监听之间的端口冲突，每个冲突条目只报告与它最先冲突的一项
+pub fn port_conflicts(cfg: &Config) -> Vec<PortConflict> {
+    let listeners = active_listeners(cfg);
+    let mut conflicts = Vec::new();
+    for (i, a) in listeners.iter().enumerate() {
+        let Some(b) = listeners[..i]
+            .iter()
+            .find(|b| a.udp == b.udp && addrs_overlap(a.addr, b.addr))
+        else {
+            continue;
+        };
+        conflicts.push(PortConflict {
+            path: a.path.clone(),
+            addr: a.addr,
+            conflicts_with: b.path.clone(),
+            conflicts_with_addr: b.addr,
+            udp: a.udp,
+        });
+    }
+    conflicts
+}
+
+/// 同端口时，地址相同或一方为通配地址即重叠；[::] 为双栈监听，同时覆盖 IPv4 地址
+fn addrs_overlap(a: SocketAddr, b: SocketAddr) -> bool {
+    fn covers(wildcard: IpAddr, other: IpAddr) -> bool {
+        wildcard.is_unspecified() && (wildcard.is_ipv6() || other.is_ipv4())
+    }
+    a.port() == b.port() && (a.ip() == b.ip() || covers(a.ip(), b.ip()) || covers(b.ip(), a.ip()))
+}
+
+/// 不应用配置，收集全部可检测的问题（而不是遇到第一个错误就返回）
+pub fn check_config(cfg: &Config) -> Vec<ConfigIssue> {
+    let mut issues = Issues::default();
+
+    for (i, rule) in cfg.rules.iter().enumerate() {
+        let base = format!("rules[{i}]");
+        for (path, addr) in rule_addr_paths(&base, rule) {
+            if let Err(e) = parse_listen_addr(addr) {
+                issues.push(rule.enabled, path, format!("{e:#}"));
+            }
+        }
+        if rule.ssl_enable {
+            check_cert_pair(
+                &mut issues,
+                rule.enabled,
+                &base,
+                &rule.cert_file,
+                &rule.key_file,
+            );
+        }
+
+        for (r, route) in rule.routes.iter().enumerate() {
+            let base = format!("{base}.routes[{r}]");
+            let enabled = rule.enabled && route.enabled;
+            if route.upstreams.is_empty() && route.static_dir.is_none() {
+                issues.push(
+                    enabled,
+                    format!("{base}.upstreams"),
+                    "Route has no upstream configured",
+                );
+            }
+            for (u, upstream) in route.upstreams.iter().enumerate() {
+                check_url(
+                    &mut issues,
+                    enabled,
+                    format!("{base}.upstreams[{u}].url"),
+                    &upstream.url,
+                    &["http", "https"],
+                );
+            }
+            for (k, rw) in route.url_rewrite_rules.iter().flatten().enumerate() {
+                if let Err(e) = regex::Regex::new(&rw.pattern) {
+                    issues.push(
+                        enabled && rw.enabled,
+                        format!("{base}.url_rewrite_rules[{k}].pattern"),
+                        format!("Invalid regex: {e}"),
+                    );
+                }
+            }
+            check_body_replace(
+                &mut issues,
+                enabled,
+                &format!("{base}.request_body_replace"),
+                route.request_body_replace.as_deref(),
+            );
+            check_body_replace(
+                &mut issues,
+                enabled,
+                &format!("{base}.response_body_replace"),
+                route.response_body_replace.as_deref(),
+            );
+        }
+    }
+
+    let ws_enabled = cfg.ws_proxy_enabled;
+    for (i, rule) in cfg.ws_proxy.iter().flatten().enumerate() {
+        let base = format!("ws_proxy[{i}]");
+        let enabled = ws_enabled && rule.enabled;
+        if let Err(e) = parse_listen_addr(&rule.listen_addr) {
+            issues.push(enabled, format!("{base}.listen_addr"), format!("{e:#}"));
+        }
+        if rule.ssl_enable {
+            check_cert_pair(&mut issues, enabled, &base, &rule.cert_file, &rule.key_file);
+        }
+        for (r, route) in rule.routes.iter().enumerate() {
+            let base = format!("{base}.routes[{r}]");
+            if route.upstreams.is_empty() {
+                issues.push(
+                    enabled,
+                    format!("{base}.upstreams"),
+                    "WS route has no upstream configured",
+                );
+            }
+            for (u, upstream) in route.upstreams.iter().enumerate() {
+                check_url(
+                    &mut issues,
+                    enabled,
+                    format!("{base}.upstreams[{u}].url"),
+                    &upstream.url,
+                    &["ws", "wss", "http", "https"],
+                );
+            }
+            if let Err(e) = stream_proxy::parse_duration(&route.fail_timeout) {
+                issues.push(
+                    enabled,
+                    format!("{base}.fail_timeout"),
+                    format!("Invalid duration: {e}"),
+                );
+            }
+        }
+    }
+
+    let stream = &cfg.stream;
+    for (i, server) in stream.servers.iter().enumerate() {
+        let enabled = stream.enabled && server.enabled;
+        if let Err(e) =
+            stream_proxy::resolve_listen_addr(server).and_then(|a| parse_listen_addr(&a))
+        {
+            issues.push(
+                enabled,
+                format!("stream.servers[{i}].listen_addr"),
+                format!("{e:#}"),
+            );
+        }
+    }
+    if !stream.servers.is_empty() || !stream.upstreams.is_empty() {
+        if let Err(e) = stream_proxy::validate_stream_config(stream) {
+            issues.push(stream.enabled, "stream".to_string(), format!("{e:#}"));
+        }
+    }
+
+    for conflict in port_conflicts(cfg) {
+        issues.push(true, conflict.path.clone(), conflict.to_string());
+    }
+
+    for (i, entry) in cfg.whitelist.iter().enumerate() {
+        if entry.ip.trim().parse::<std::net::IpAddr>().is_err() {
+            issues.push(
+                true,
+                format!("whitelist[{i}].ip"),
+                format!("Not a valid IP address: {}", entry.ip),
+            );
+        }
+    }
+
+    for (path, enabled, name) in crate::secrets::missing_secrets(cfg) {
+        issues.push(enabled, path, format!("Keychain entry '{name}' not found"));
+    }
+
+    let sections: [(&str, Result<(), String>); 7] = [
+        (
+            "alerting",
+            crate::config::validate_alerting_config(&cfg.alerting),
+        ),
+        ("alerts", crate::config::validate_alerts_config(&cfg.alerts)),
+        ("geoip", crate::config::validate_geoip_config(&cfg.geoip)),
+        (
+            "access_log_format",
+            crate::config::validate_access_log_format(&cfg.access_log_format),
+        ),
+        (
+            "metrics_storage",
+            crate::config::validate_log_sampling_config(&cfg.metrics_storage),
+        ),
+        (
+            "prometheus",
+            crate::config::validate_prometheus_config(&cfg.prometheus),
+        ),
+        ("syslog", crate::config::validate_syslog_config(&cfg.syslog)),
+    ];
+    for (path, result) in sections {
+        if let Err(e) = result {
+            issues.push(true, path.to_string(), e);
+        }
+    }
+
+    issues.0
+}
+
+fn check_url(issues: &mut Issues, enabled: bool, path: String, raw: &str, schemes: &[&str]) {
+    match url::Url::parse(raw.trim()) {
+        Ok(u) if schemes.contains(&u.scheme()) => {}
+        Ok(u) => issues.push(
+            enabled,
+            path,
+            format!("Unsupported upstream scheme: {}", u.scheme()),
+        ),
+        Err(e) => issues.push(enabled, path, format!("Invalid upstream URL: {e}")),
+    }
+}
+
+fn check_body_replace(
+    issues: &mut Issues,
+    enabled: bool,
+    base: &str,
+    rules: Option<&[BodyReplaceRule]>,
+) {
+    for (k, rule) in rules.unwrap_or_default().iter().enumerate() {
+        if !rule.use_regex {
+            continue;
+        }
+        if let Err(e) = regex::Regex::new(&rule.find) {
+            issues.push(
+                enabled && rule.enabled,
+                format!("{base}[{k}].find"),
+                format!("Invalid regex: {e}"),
+            );
+        }
+    }
+}
+
+/// 证书与私钥可读、可解析，且私钥与证书公钥匹配
+fn check_cert_pair(
+    issues: &mut Issues,
+    enabled: bool,
+    base: &str,
+    cert_file: &str,
+    key_file: &str,
+) {
+    if let Err((field, message)) = load_cert_pair(cert_file.trim(), key_file.trim()) {
+        issues.push(enabled, format!("{base}.{field}"), message);
+    }
+}
+
+fn load_cert_pair(cert_file: &str, key_file: &str) -> Result<(), (&'static str, String)> {
+    if cert_file.is_empty() {
+        return Err(("cert_file", "Certificate path is empty".to_string()));
+    }
+    if key_file.is_empty() {
+        return Err(("key_file", "Private key path is empty".to_string()));
+    }
+    let cert_pem = std::fs::read(cert_file)
+        .map_err(|e| ("cert_file", format!("Failed to read certificate: {e}")))?;
+    let key_pem = std::fs::read(key_file)
+        .map_err(|e| ("key_file", format!("Failed to read private key: {e}")))?;
+
+    let certs = rustls_pemfile::certs(&mut cert_pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| ("cert_file", format!("Invalid certificate PEM: {e}")))?;
+    if certs.is_empty() {
+        return Err(("cert_file", "No certificate found in file".to_string()));
+    }
+    let key = rustls_pemfile::private_key(&mut key_pem.as_slice())
+        .map_err(|e| ("key_file", format!("Invalid private key PEM: {e}")))?
+        .ok_or_else(|| ("key_file", "No private key found in file".to_string()))?;
+
+    let provider = rustls::crypto::CryptoProvider::get_default()
+        .cloned()
+        .unwrap_or_else(|| Arc::new(rustls::crypto::aws_lc_rs::default_provider()));
+    let signing_key = provider
+        .key_provider
+        .load_private_key(key)
+        .map_err(|e| ("key_file", format!("Unsupported private key: {e}")))?;
+    match rustls::sign::CertifiedKey::new(certs, signing_key).keys_match() {
+        // 无法从私钥导出公钥时不判定为不匹配
+        Ok(()) | Err(rustls::Error::InconsistentKeys(rustls::InconsistentKeys::Unknown)) => Ok(()),
+        Err(e) => Err((
+            "key_file",
+            format!("Private key does not match certificate: {e}"),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_issues_with_field_paths() {
+        let cfg: Config = toml::from_str(
+            r#"
+            allow_all_lan = true
+            whitelist = [{ ip = "127.0.0.1" }, { ip = "10.0.0.0/8" }]
+
+            [[rules]]
+            listen_addr = "0.0.0.0:8080"
+            ssl_enable = false
+            cert_file = ""
+            key_file = ""
+            basic_auth_enable = false
+            basic_auth_username = ""
+            basic_auth_password = ""
+            basic_auth_forward_header = false
+
+            [[rules.routes]]
+            path = "/"
+            upstreams = [{ url = "http://127.0.0.1:3000" }, { url = "not a url" }]
+            url_rewrite_rules = [{ pattern = "^/api/(.*", replacement = "/$1" }]
+
+            [[rules]]
+            listen_addr = "127.0.0.1:8080"
+            ssl_enable = true
+            cert_file = "/nonexistent/cert.pem"
+            key_file = "/nonexistent/key.pem"
+            basic_auth_enable = false
+            basic_auth_username = ""
+            basic_auth_password = ""
+            basic_auth_forward_header = false
+            routes = []
+            "#,
+        )
+        .unwrap();
+
+        let issues = check_config(&cfg);
+        let paths: Vec<&str> = issues.iter().map(|i| i.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "rules[0].routes[0].upstreams[1].url",
+                "rules[0].routes[0].url_rewrite_rules[0].pattern",
+                "rules[1].cert_file",
+                "rules[1].listen_addr",
+                "whitelist[1].ip",
+            ]
+        );
+        assert!(issues.iter().all(|i| i.severity == IssueSeverity::Error));
+        assert!(issues[3].message.contains("rules[0].listen_addr"));
+    }
+
+    #[test]
+    fn port_conflicts_span_http_ws_and_stream_listeners() {
+        let cfg: Config = toml::from_str(
+            r#"
+            allow_all_lan = true
+            whitelist = []
+            ws_proxy_enabled = true
+
+            [[rules]]
+            listen_addr = ":8080"
+            listen_addrs = [":8080", "127.0.0.1:9000"]
+            ssl_enable = false
+            cert_file = ""
+            key_file = ""
+            basic_auth_enable = false
+            basic_auth_username = ""
+            basic_auth_password = ""
+            basic_auth_forward_header = false
+            routes = []
+
+            [[ws_proxy]]
+            enabled = true
+            listen_addr = "192.168.1.10:8080"
+            ssl_enable = false
+            cert_file = ""
+            key_file = ""
+            routes = []
+
+            [[ws_proxy]]
+            enabled = true
+            listen_addr = "[::1]:9000"
+            ssl_enable = false
+            cert_file = ""
+            key_file = ""
+            routes = []
+
+            [stream]
+            enabled = true
+
+            [[stream.servers]]
+            enabled = true
+            listen_addr = "127.0.0.1:9000"
+            proxy_pass = "backend"
+            udp = true
+
+            [[stream.servers]]
+            enabled = true
+            listen_addr = "0.0.0.0:9000"
+            proxy_pass = "backend"
+            "#,
+        )
+        .unwrap();
+
+        let found = port_conflicts(&cfg);
+        let conflicts: Vec<(&str, &str)> = found
+            .iter()
+            .map(|c| (c.path.as_str(), c.conflicts_with.as_str()))
+            .collect();
+        // [::1] 与 127.0.0.1 互不影响，UDP 与 TCP 互不影响；双栈 [::] 覆盖 IPv4 地址
+        assert_eq!(
+            conflicts,
+            vec![
+                ("ws_proxy[0].listen_addr", "rules[0].listen_addrs[0]"),
+                ("stream.servers[1].listen_addr", "rules[0].listen_addrs[1]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn ipv4_wildcard_does_not_cover_ipv6_addresses() {
+        let v4_any: SocketAddr = "0.0.0.0:80".parse().unwrap();
+        assert!(!addrs_overlap(v4_any, "[::1]:80".parse().unwrap()));
+        assert!(addrs_overlap(v4_any, "10.0.0.1:80".parse().unwrap()));
+        assert!(addrs_overlap(v4_any, "[::]:80".parse().unwrap()));
+        assert!(!addrs_overlap(v4_any, "0.0.0.0:81".parse().unwrap()));
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use std::{fs, path::Path};
+
+use crate::config::{self, Config};
+
+/// 带注释的示例配置，即仓库根目录的 config.toml.example
+pub const EXAMPLE_CONFIG: &str = include_str!("../config.toml.example");
+
+/// 配置中是否已有任何监听条目；为空时才允许用示例覆盖当前配置
+pub fn has_entries(cfg: &Config) -> bool {
+    !cfg.rules.is_empty()
+        || cfg.ws_proxy.as_ref().is_some_and(|rules| !rules.is_empty())
+        || !cfg.stream.servers.is_empty()
+}
+
+/// 把示例原样写入指定文件，保留注释
+pub fn write_example(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("创建目录失败: {}", parent.display()))?;
+    }
+    config::write_file_atomic(path, EXAMPLE_CONFIG)
+        .with_context(|| format!("写入示例配置失败: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_migration::CURRENT_CONFIG_VERSION;
+
+    /// 示例中的每个键都必须被 Config 识别并原样写回，否则说明示例已过时
+    fn assert_round_trips(path: &str, expected: &toml::Value, actual: &toml::Value) {
+        match (expected, actual) {
+            (toml::Value::Table(e), toml::Value::Table(a)) => {
+                for (key, value) in e {
+                    let field = format!("{path}.{key}");
+                    let got = a
+                        .get(key)
+                        .unwrap_or_else(|| panic!("{field} is not kept by Config"));
+                    assert_round_trips(&field, value, got);
+                }
+            }
+            (toml::Value::Array(e), toml::Value::Array(a)) => {
+                assert_eq!(e.len(), a.len(), "{path}");
+                for (i, (ev, av)) in e.iter().zip(a).enumerate() {
+                    assert_round_trips(&format!("{path}[{i}]"), ev, av);
+                }
+            }
+            _ => assert_eq!(expected, actual, "{path}"),
+        }
+    }
+
+    #[test]
+    fn example_config_round_trips_through_config_structs() {
+        let doc: toml::Value = toml::from_str(EXAMPLE_CONFIG).unwrap();
+        let cfg: Config = toml::from_str(EXAMPLE_CONFIG).unwrap();
+        let reserialized = toml::Value::try_from(&cfg).unwrap();
+        assert_round_trips("", &doc, &reserialized);
+
+        assert_eq!(cfg.config_version, CURRENT_CONFIG_VERSION);
+        assert!(has_entries(&cfg));
+        assert_eq!(cfg.rules.len(), 2);
+        assert!(cfg.rules.iter().any(|r| r.ssl_enable));
+        assert!(cfg.stream.servers.iter().any(|s| s.udp));
+        assert!(cfg.stream.servers.iter().any(|s| !s.udp));
+        crate::proxy::stream_proxy::validate_stream_config(&cfg.stream).unwrap();
+    }
+}
@@ -0,0 +1,433 @@
+use anyhow::{bail, Context, Result};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, ListenRule, StreamServer, StreamUpstream};
+use crate::proxy::ws_proxy::WsListenRule;
+
+/// 被 include 的文件只能包含这些数组，合并时追加到主配置之后
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Fragment {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rules: Vec<ListenRule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    ws_proxy: Vec<WsListenRule>,
+    #[serde(default, skip_serializing_if = "FragmentStream::is_empty")]
+    stream: FragmentStream,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FragmentStream {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    upstreams: Vec<StreamUpstream>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    servers: Vec<StreamServer>,
+}
+
+impl FragmentStream {
+    fn is_empty(&self) -> bool {
+        self.upstreams.is_empty() && self.servers.is_empty()
+    }
+}
+
+/// 条目标识：HTTP 规则用 id，WS 规则用监听地址，stream upstream 用名称，stream server 用监听端口+协议
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum EntryKey {
+    Rule(String),
+    WsRule(String),
+    StreamUpstream(String),
+    StreamServer(String),
+}
+
+fn rule_key(rule: &ListenRule) -> Option<EntryKey> {
+    rule.id.clone().map(EntryKey::Rule)
+}
+
+fn ws_key(rule: &WsListenRule) -> EntryKey {
+    EntryKey::WsRule(rule.listen_addr.clone())
+}
+
+fn upstream_key(upstream: &StreamUpstream) -> EntryKey {
+    EntryKey::StreamUpstream(upstream.name.clone())
+}
+
+fn server_key(server: &StreamServer) -> EntryKey {
+    let listen = match (&server.listen_addr, server.listen_port) {
+        (Some(addr), _) => addr.clone(),
+        (None, Some(port)) => port.to_string(),
+        (None, None) => String::new(),
+    };
+    let proto = if server.udp { "udp" } else { "tcp" };
+    EntryKey::StreamServer(format!("{listen}/{proto}"))
+}
+
+/// 条目来源文件；未记录的条目（包括新增条目）写回主配置文件
+type Sources = HashMap<EntryKey, PathBuf>;
+
+static SOURCES: Lazy<RwLock<Sources>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// 合并后各数组中每个元素来自哪个文件（None 表示主配置文件），与数组下标一一对应
+#[derive(Debug, Default)]
+pub(crate) struct IncludeOrigins {
+    rules: Vec<Option<PathBuf>>,
+    ws_proxy: Vec<Option<PathBuf>>,
+    upstreams: Vec<Option<PathBuf>>,
+    servers: Vec<Option<PathBuf>>,
+}
+
+fn resolve_pattern(pattern: &str, base_dir: Option<&Path>) -> PathBuf {
+    let path = Path::new(pattern);
+    match base_dir {
+        Some(base) if path.is_relative() => base.join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// include 模式所在目录，供文件监听使用
+pub(crate) fn pattern_dir(pattern: &str, base_dir: &Path) -> Option<PathBuf> {
+    resolve_pattern(pattern, Some(base_dir))
+        .parent()
+        .map(Path::to_path_buf)
+}
+
+/// 解析 include 模式，文件名部分支持一个 *（如 rules/*.toml），结果按文件名排序
+pub(crate) fn expand_glob(pattern: &str, base_dir: Option<&Path>) -> Vec<PathBuf> {
+    let path = resolve_pattern(pattern, base_dir);
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let Some((prefix, suffix)) = file_name.split_once('*') else {
+        return if path.is_file() { vec![path] } else { vec![] };
+    };
+    let Some(dir) = path.parent() else {
+        return vec![];
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .filter(|p| {
+            p.file_name().map(|n| n.to_string_lossy()).is_some_and(|n| {
+                n.len() >= prefix.len() + suffix.len()
+                    && n.starts_with(prefix)
+                    && n.ends_with(suffix)
+            })
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+fn included_files(patterns: &[String], base_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for pattern in patterns {
+        let matched = expand_glob(pattern, Some(base_dir));
+        if matched.is_empty() && !pattern.contains('*') {
+            bail!("Included config file not found: {pattern}");
+        }
+        for file in matched {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn origins_of<T>(items: &[T]) -> Vec<Option<PathBuf>> {
+    vec![None; items.len()]
+}
+
+/// 把 include 文件中的数组按文件名顺序追加到 cfg，返回各元素的来源
+pub(crate) fn merge_includes(cfg: &mut Config, base_dir: &Path) -> Result<IncludeOrigins> {
+    let mut origins = IncludeOrigins {
+        rules: origins_of(&cfg.rules),
+        ws_proxy: origins_of(cfg.ws_proxy.as_deref().unwrap_or_default()),
+        upstreams: origins_of(&cfg.stream.upstreams),
+        servers: origins_of(&cfg.stream.servers),
+    };
+
+    for file in included_files(&cfg.include, base_dir)? {
+        let content = fs::read_to_string(&file)
+            .with_context(|| format!("无法读取 include 配置文件: {}", file.display()))?;
+        let fragment: Fragment = toml::from_str(&content)
+            .with_context(|| format!("解析 include 配置文件失败: {}", file.display()))?;
+
+        let source = Some(file.clone());
+        origins
+            .rules
+            .extend(std::iter::repeat_n(source.clone(), fragment.rules.len()));
+        origins
+            .ws_proxy
+            .extend(std::iter::repeat_n(source.clone(), fragment.ws_proxy.len()));
+        origins.upstreams.extend(std::iter::repeat_n(
+            source.clone(),
+            fragment.stream.upstreams.len(),
+        ));
+        origins
+            .servers
+            .extend(std::iter::repeat_n(source, fragment.stream.servers.len()));
+
+        cfg.rules.extend(fragment.rules);
+        if !fragment.ws_proxy.is_empty() {
+            cfg.ws_proxy
+                .get_or_insert_with(Vec::new)
+                .extend(fragment.ws_proxy);
+        }
+        cfg.stream.upstreams.extend(fragment.stream.upstreams);
+        cfg.stream.servers.extend(fragment.stream.servers);
+    }
+    Ok(origins)
+}
+
+fn file_label(source: &Option<PathBuf>) -> String {
+    match source {
+        Some(path) => path.display().to_string(),
+        None => "main config".to_string(),
+    }
+}
+
+/// 在补齐 id 之后建立条目到来源文件的映射；同一 id 出现在不同文件时报错
+fn build_sources(cfg: &Config, origins: &IncludeOrigins) -> Result<Sources> {
+    let mut seen: HashMap<&str, &Option<PathBuf>> = HashMap::new();
+    for (rule, source) in cfg.rules.iter().zip(&origins.rules) {
+        let Some(id) = rule.id.as_deref() else {
+            continue;
+        };
+        if let Some(first) = seen.insert(id, source) {
+            if first != source {
+                bail!(
+                    "Duplicate rule id '{id}' in {} and {}",
+                    file_label(first),
+                    file_label(source)
+                );
+            }
+        }
+    }
+
+    let mut sources = Sources::new();
+    let mut record = |key: Option<EntryKey>, source: &Option<PathBuf>| {
+        if let (Some(key), Some(path)) = (key, source) {
+            sources.insert(key, path.clone());
+        }
+    };
+    for (rule, source) in cfg.rules.iter().zip(&origins.rules) {
+        record(rule_key(rule), source);
+    }
+    for (rule, source) in cfg.ws_proxy.iter().flatten().zip(&origins.ws_proxy) {
+        record(Some(ws_key(rule)), source);
+    }
+    for (upstream, source) in cfg.stream.upstreams.iter().zip(&origins.upstreams) {
+        record(Some(upstream_key(upstream)), source);
+    }
+    for (server, source) in cfg.stream.servers.iter().zip(&origins.servers) {
+        record(Some(server_key(server)), source);
+    }
+    Ok(sources)
+}
+
+/// 记录条目来源，供 save_config 写回对应文件；只追加不清除，
+/// 读取后未被采用的配置（如校验失败的快照）不会让当前条目丢失来源
+pub(crate) fn record_sources(cfg: &Config, origins: &IncludeOrigins) -> Result<()> {
+    let sources = build_sources(cfg, origins)?;
+    SOURCES.write().extend(sources);
+    Ok(())
+}
+
+/// 按来源拆分待保存的配置：返回主配置（不含 include 文件中的条目）与各 include 文件的新内容
+pub(crate) fn split_for_save(
+    cfg: &Config,
+    base_dir: &Path,
+) -> Result<(Config, Vec<(PathBuf, String)>)> {
+    split_with_sources(cfg, base_dir, &SOURCES.read())
+}
+
+fn split_with_sources(
+    cfg: &Config,
+    base_dir: &Path,
+    sources: &Sources,
+) -> Result<(Config, Vec<(PathBuf, String)>)> {
+    if cfg.include.is_empty() {
+        return Ok((cfg.clone(), vec![]));
+    }
+
+    let files = included_files(&cfg.include, base_dir)?;
+    let mut fragments: Vec<Fragment> = files.iter().map(|_| Fragment::default()).collect();
+    // 来源文件已不在 include 范围内的条目回到主配置
+    let target = |key: Option<EntryKey>| {
+        let path = sources.get(&key?)?;
+        files.iter().position(|f| f == path)
+    };
+
+    let mut main = cfg.clone();
+    main.rules.clear();
+    for rule in &cfg.rules {
+        match target(rule_key(rule)) {
+            Some(i) => fragments[i].rules.push(rule.clone()),
+            None => main.rules.push(rule.clone()),
+        }
+    }
+    if let Some(ws_rules) = &cfg.ws_proxy {
+        let mut kept = Vec::new();
+        for rule in ws_rules {
+            match target(Some(ws_key(rule))) {
+                Some(i) => fragments[i].ws_proxy.push(rule.clone()),
+                None => kept.push(rule.clone()),
+            }
+        }
+        main.ws_proxy = Some(kept);
+    }
+    main.stream.upstreams.clear();
+    for upstream in &cfg.stream.upstreams {
+        match target(Some(upstream_key(upstream))) {
+            Some(i) => fragments[i].stream.upstreams.push(upstream.clone()),
+            None => main.stream.upstreams.push(upstream.clone()),
+        }
+    }
+    main.stream.servers.clear();
+    for server in &cfg.stream.servers {
+        match target(Some(server_key(server))) {
+            Some(i) => fragments[i].stream.servers.push(server.clone()),
+            None => main.stream.servers.push(server.clone()),
+        }
+    }
+
+    let mut out = Vec::with_capacity(files.len());
+    for (file, fragment) in files.into_iter().zip(fragments) {
+        let content = toml::to_string_pretty(&fragment)
+            .with_context(|| format!("序列化 include 配置失败: {}", file.display()))?;
+        out.push((file, content));
+    }
+    Ok((main, out))
+}
+
+/// 合并全部 include 内容后的单文件配置，便于导出迁移
+pub(crate) fn flatten(cfg: &Config) -> Config {
+    let mut flat = cfg.clone();
+    flat.include.clear();
+    flat
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAIN: &str = r#"
+allow_all_lan = true
+whitelist = []
+include = ["rules/*.toml"]
+
+[[rules]]
+id = "main"
+listen_addr = ":80"
+ssl_enable = false
+cert_file = ""
+key_file = ""
+basic_auth_enable = false
+basic_auth_username = ""
+basic_auth_password = ""
+basic_auth_forward_header = false
+routes = []
+"#;
+
+    fn fragment_rule(id: &str, addr: &str) -> String {
+        format!(
+            r#"
+[[rules]]
+id = "{id}"
+listen_addr = "{addr}"
+ssl_enable = false
+cert_file = ""
+key_file = ""
+basic_auth_enable = false
+basic_auth_username = ""
+basic_auth_password = ""
+basic_auth_forward_header = false
+
+[[rules.routes]]
+path = "/"
+upstreams = [{{ url = "http://127.0.0.1:9000" }}]
+"#
+        )
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sslpm-include-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("rules")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn merges_included_files_and_writes_rules_back_to_their_source() {
+        let dir = temp_dir("merge");
+        fs::write(dir.join("rules/b.toml"), fragment_rule("site-b", ":8081")).unwrap();
+        fs::write(dir.join("rules/a.toml"), fragment_rule("site-a", ":8080")).unwrap();
+        fs::write(
+            dir.join("rules/stream.toml"),
+            "[[stream.upstreams]]\nname = \"db\"\nservers = []\n",
+        )
+        .unwrap();
+
+        let mut cfg: Config = toml::from_str(MAIN).unwrap();
+        let origins = merge_includes(&mut cfg, &dir).unwrap();
+        let ids: Vec<_> = cfg.rules.iter().map(|r| r.id.clone().unwrap()).collect();
+        assert_eq!(ids, ["main", "site-a", "site-b"]);
+        assert_eq!(cfg.stream.upstreams[0].name, "db");
+
+        let sources = build_sources(&cfg, &origins).unwrap();
+        cfg.rules[1].listen_addr = ":9090".into();
+        cfg.rules.push(ListenRule {
+            id: Some("new".into()),
+            ..cfg.rules[0].clone()
+        });
+
+        let (main, files) = split_with_sources(&cfg, &dir, &sources).unwrap();
+        let main_ids: Vec<_> = main.rules.iter().map(|r| r.id.clone().unwrap()).collect();
+        assert_eq!(main_ids, ["main", "new"]);
+        assert!(main.stream.upstreams.is_empty());
+        assert_eq!(main.include, ["rules/*.toml"]);
+
+        let by_name: HashMap<_, _> = files
+            .iter()
+            .map(|(p, c)| (p.file_name().unwrap().to_string_lossy().to_string(), c))
+            .collect();
+        assert!(by_name["a.toml"].contains(":9090"));
+        assert!(!by_name["a.toml"].contains("site-b"));
+        assert!(by_name["b.toml"].contains("site-b"));
+        assert!(by_name["stream.toml"].contains("name = \"db\""));
+
+        assert!(flatten(&cfg).include.is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rejects_duplicate_ids_unknown_keys_and_missing_files() {
+        let dir = temp_dir("dup");
+        fs::write(dir.join("rules/a.toml"), fragment_rule("main", ":8080")).unwrap();
+        let mut cfg: Config = toml::from_str(MAIN).unwrap();
+        let origins = merge_includes(&mut cfg, &dir).unwrap();
+        let err = build_sources(&cfg, &origins).unwrap_err().to_string();
+        assert!(err.contains("Duplicate rule id 'main'"), "{err}");
+
+        fs::write(dir.join("rules/a.toml"), "allow_all_ip = true\n").unwrap();
+        let mut cfg: Config = toml::from_str(MAIN).unwrap();
+        assert!(merge_includes(&mut cfg, &dir).is_err());
+
+        let mut cfg: Config = toml::from_str(MAIN).unwrap();
+        cfg.include = vec!["missing.toml".into()];
+        let err = merge_includes(&mut cfg, &dir).unwrap_err().to_string();
+        assert!(err.contains("not found"), "{err}");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::{anyhow, bail, Result};
+
+/// 当前程序写出的配置版本；新增迁移时递增并在 MIGRATIONS 末尾追加一步
+pub const CURRENT_CONFIG_VERSION: u32 = 1;
+
+type Migration = fn(&mut toml::Table) -> Result<()>;
+
+/// MIGRATIONS[i] 把版本 i 的文档升级到 i + 1
+const MIGRATIONS: [Migration; CURRENT_CONFIG_VERSION as usize] = [migrate_v0_to_v1];
+
+/// 读取文档中的 config_version；缺省视为引入版本号之前的 0
+fn document_version(doc: &toml::Table) -> Result<u32> {
+    match doc.get("config_version") {
+        None => Ok(0),
+        Some(toml::Value::Integer(v)) => {
+            u32::try_from(*v).map_err(|_| anyhow!("Invalid config_version: {v}"))
+        }
+        Some(other) => bail!("config_version must be an integer, got {other}"),
+    }
+}
+
+/// 在反序列化为 Config 之前逐级升级文档；返回升级前的版本，已是最新时返回 None
+pub fn migrate_document(doc: &mut toml::Table) -> Result<Option<u32>> {
+    let from = document_version(doc)?;
+    if from > CURRENT_CONFIG_VERSION {
+        bail!(
+            "config_version {from} is newer than this app supports ({CURRENT_CONFIG_VERSION}); please upgrade SSLProxyManager"
+        );
+    }
+    if from == CURRENT_CONFIG_VERSION {
+        return Ok(None);
+    }
+
+    for version in from..CURRENT_CONFIG_VERSION {
+        MIGRATIONS[version as usize](doc).map_err(|e| {
+            anyhow!(
+                "config migration v{version} -> v{} failed: {e:#}",
+                version + 1
+            )
+        })?;
+        doc.insert(
+            "config_version".to_string(),
+            toml::Value::Integer(i64::from(version + 1)),
+        );
+    }
+    Ok(Some(from))
+}
+
+/// v0 -> v1：引入版本号，此前新增的字段均由 serde 默认值兼容，文档结构不变
+fn migrate_v0_to_v1(_doc: &mut toml::Table) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn fixture(name: &str) -> toml::Table {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/config")
+            .join(name);
+        toml::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn migrates_unversioned_document_to_v1() {
+        let original = fixture("v0_unversioned.toml");
+        let mut doc = original.clone();
+
+        assert_eq!(migrate_document(&mut doc).unwrap(), Some(0));
+        assert_eq!(doc["config_version"].as_integer(), Some(1));
+        doc.remove("config_version");
+        assert_eq!(doc, original);
+
+        let mut doc = fixture("v0_unversioned.toml");
+        migrate_document(&mut doc).unwrap();
+        let cfg: Config = toml::Value::Table(doc).try_into().unwrap();
+        assert_eq!(cfg.config_version, 1);
+        assert_eq!(cfg.rules[0].routes[0].upstreams[0].weight, 2);
+
+        // 已是最新版本时不再改动
+        let mut doc = toml::Table::new();
+        doc.insert("config_version".into(), toml::Value::Integer(1));
+        assert_eq!(migrate_document(&mut doc).unwrap(), None);
+    }
+
+    #[test]
+    fn refuses_newer_or_invalid_versions() {
+        let mut doc = toml::Table::new();
+        doc.insert(
+            "config_version".into(),
+            toml::Value::Integer(i64::from(CURRENT_CONFIG_VERSION) + 1),
+        );
+        let err = migrate_document(&mut doc).unwrap_err().to_string();
+        assert!(err.contains("newer than this app supports"), "{err}");
+
+        for bad in [toml::Value::Integer(-1), toml::Value::String("2".into())] {
+            let mut doc = toml::Table::new();
+            doc.insert("config_version".into(), bad);
+            assert!(migrate_document(&mut doc).is_err());
+        }
+    }
+}
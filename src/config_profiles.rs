@@ -0,0 +1,228 @@
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::config::{self, Config};
+use crate::config_include;
+
+// 记录当前激活的方案名，位于 profiles 目录内
+const ACTIVE_STATE_FILE: &str = "active_profile";
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileInfo {
+    pub name: String,
+    pub active: bool,
+    pub modified_at_unix_ms: i64,
+    pub size_bytes: u64,
+}
+
+pub(crate) fn profiles_dir() -> Result<PathBuf> {
+    let path = config::get_config_path()?;
+    let parent = path
+        .parent()
+        .context("配置路径缺少父目录，无法定位 profiles 目录")?;
+    Ok(parent.join("profiles"))
+}
+
+/// 方案名直接用作文件名，只允许字母数字、空格、- 和 _
+fn validate_name(name: &str) -> Result<()> {
+    let valid_chars = name
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ' '));
+    if name.trim().is_empty() || name.len() > MAX_NAME_LEN || !valid_chars || name != name.trim() {
+        bail!(
+            "Invalid profile name '{name}': use up to {MAX_NAME_LEN} letters, digits, spaces, '-' or '_'"
+        );
+    }
+    Ok(())
+}
+
+fn profile_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    validate_name(name)?;
+    Ok(dir.join(format!("{name}.toml")))
+}
+
+fn active_in(dir: &Path) -> Option<String> {
+    let name = fs::read_to_string(dir.join(ACTIVE_STATE_FILE)).ok()?;
+    let name = name.trim();
+    let exists = profile_path(dir, name).is_ok_and(|p| p.is_file());
+    exists.then(|| name.to_string())
+}
+
+fn list_in(dir: &Path) -> Result<Vec<ProfileInfo>> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let active = active_in(dir);
+
+    let mut out: Vec<ProfileInfo> = fs::read_dir(dir)
+        .with_context(|| format!("读取 profiles 目录失败: {}", dir.display()))?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let path = e.path();
+            if path.extension().and_then(|v| v.to_str()) != Some("toml") {
+                return None;
+            }
+            let name = path.file_stem()?.to_string_lossy().to_string();
+            validate_name(&name).ok()?;
+            let meta = e.metadata().ok()?;
+            let ms = meta
+                .modified()
+                .ok()
+                .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_millis() as i64)
+                .unwrap_or(0);
+
+            Some(ProfileInfo {
+                active: active.as_deref() == Some(name.as_str()),
+                name,
+                modified_at_unix_ms: ms,
+                size_bytes: meta.len(),
+            })
+        })
+        .collect();
+
+    out.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(out)
+}
+
+fn save_in(dir: &Path, name: &str, cfg: &Config) -> Result<()> {
+    let path = profile_path(dir, name)?;
+    fs::create_dir_all(dir)
+        .with_context(|| format!("创建 profiles 目录失败: {}", dir.display()))?;
+    // 方案是单文件配置：include 中的条目展开写入，每条规则只保存一次
+    let content =
+        toml::to_string_pretty(&config_include::flatten(cfg)).context("序列化配置失败")?;
+    config::write_file_atomic(&path, &content)
+        .with_context(|| format!("写入配置方案失败: {}", path.display()))
+}
+
+fn delete_in(dir: &Path, name: &str) -> Result<()> {
+    let path = profile_path(dir, name)?;
+    if active_in(dir).as_deref() == Some(name) {
+        bail!("Profile '{name}' is active and cannot be deleted");
+    }
+    fs::remove_file(&path).with_context(|| format!("删除配置方案失败: {}", path.display()))
+}
+
+pub fn list_profiles() -> Result<Vec<ProfileInfo>> {
+    list_in(&profiles_dir()?)
+}
+
+pub fn active_profile() -> Option<String> {
+    active_in(&profiles_dir().ok()?)
+}
+
+/// 把当前配置保存为命名方案（同名覆盖）
+pub fn save_profile(name: &str) -> Result<()> {
+    save_in(&profiles_dir()?, name, &config::get_config())
+}
+
+pub fn delete_profile(name: &str) -> Result<()> {
+    delete_in(&profiles_dir()?, name)
+}
+
+fn load_in(dir: &Path, name: &str) -> Result<Config> {
+    let path = profile_path(dir, name)?;
+    if !path.is_file() {
+        bail!("Profile '{name}' does not exist");
+    }
+    config::read_standalone_config_file(&path)
+}
+
+/// 读取方案内容，与加载配置文件相同地执行迁移和预编译
+pub fn load_profile(name: &str) -> Result<Config> {
+    load_in(&profiles_dir()?, name)
+}
+
+pub fn set_active_profile(name: &str) -> Result<()> {
+    let dir = profiles_dir()?;
+    validate_name(name)?;
+    config::write_file_atomic(&dir.join(ACTIVE_STATE_FILE), name).context("写入当前配置方案失败")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_lists_and_protects_active_profile() {
+        let dir = std::env::temp_dir().join(format!("sslpm-profiles-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cfg: Config =
+            toml::from_str("allow_all_lan = true\nwhitelist = []\nrules = []").unwrap();
+
+        save_in(&dir, "home lab", &cfg).unwrap();
+        save_in(&dir, "office-demo", &cfg).unwrap();
+        for bad in ["", "../evil", "a/b", " padded", ".hidden"] {
+            assert!(save_in(&dir, bad, &cfg).is_err(), "{bad:?}");
+        }
+
+        fs::write(dir.join(ACTIVE_STATE_FILE), "office-demo").unwrap();
+        let names: Vec<_> = list_in(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|p| (p.name, p.active))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("home lab".to_string(), false),
+                ("office-demo".to_string(), true)
+            ]
+        );
+
+        let err = delete_in(&dir, "office-demo").unwrap_err().to_string();
+        assert!(err.contains("is active"), "{err}");
+        delete_in(&dir, "home lab").unwrap();
+        assert_eq!(list_in(&dir).unwrap().len(), 1);
+
+        // 状态文件指向已不存在的方案时视为未激活
+        fs::write(dir.join(ACTIVE_STATE_FILE), "home lab").unwrap();
+        assert_eq!(active_in(&dir), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn profile_round_trip_keeps_included_rules_once() {
+        let dir = std::env::temp_dir().join(format!("sslpm-profiles-inc-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("rules")).unwrap();
+        let rule = |id: &str, addr: &str| {
+            format!(
+                "[[rules]]\nid = \"{id}\"\nlisten_addr = \"{addr}\"\nssl_enable = false\n\
+                 cert_file = \"\"\nkey_file = \"\"\nbasic_auth_enable = false\n\
+                 basic_auth_username = \"\"\nbasic_auth_password = \"\"\n\
+                 basic_auth_forward_header = false\nroutes = []\n"
+            )
+        };
+        fs::write(dir.join("rules/extra.toml"), rule("extra", ":81")).unwrap();
+        let mut merged: Config = toml::from_str(&format!(
+            "allow_all_lan = true\nwhitelist = []\ninclude = [\"rules/*.toml\"]\n{}",
+            rule("main", ":80")
+        ))
+        .unwrap();
+        config_include::merge_includes(&mut merged, &dir).unwrap();
+        let ids = |cfg: &Config| -> Vec<String> {
+            cfg.rules.iter().filter_map(|r| r.id.clone()).collect()
+        };
+        assert_eq!(ids(&merged), vec!["main", "extra"]);
+
+        let profiles = dir.join("profiles");
+        save_in(&profiles, "with include", &merged).unwrap();
+        let loaded = load_in(&profiles, "with include").unwrap();
+        assert!(loaded.include.is_empty());
+        assert_eq!(ids(&loaded), vec!["main", "extra"]);
+
+        // 旧版本保存的方案仍带 include，加载时不再合并，规则不会重复
+        let legacy = toml::to_string_pretty(&merged).unwrap();
+        fs::write(profiles.join("legacy.toml"), legacy).unwrap();
+        let loaded = load_in(&profiles, "legacy").unwrap();
+        assert!(loaded.include.is_empty());
+        assert_eq!(ids(&loaded), vec!["main", "extra"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
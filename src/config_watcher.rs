@@ -0,0 +1,259 @@
+use anyhow::{Context, Result};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::app_events::AppEvents;
+use crate::config;
+use crate::config_include;
+use crate::proxy::{send_log_with_app, LogLevel};
+
+// 编辑器一次保存常触发多个事件，合并后再重载
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+static WATCHER: Lazy<Mutex<Option<RecommendedWatcher>>> = Lazy::new(|| Mutex::new(None));
+static TARGETS: Lazy<Mutex<WatchTargets>> = Lazy::new(|| Mutex::new(WatchTargets::default()));
+
+/// 主配置文件与 include 模式；include 列表随重载更新
+#[derive(Debug, Clone, Default)]
+struct WatchTargets {
+    main: PathBuf,
+    base_dir: PathBuf,
+    include: Vec<String>,
+    /// 已注册监听的目录
+    watched: HashSet<PathBuf>,
+}
+
+impl WatchTargets {
+    /// 需要监听的目录：主配置所在目录与各 include 模式所在目录
+    fn dirs(&self) -> HashSet<PathBuf> {
+        let mut dirs: HashSet<PathBuf> = self.main.parent().map(canonical).into_iter().collect();
+        for pattern in &self.include {
+            if let Some(dir) = config_include::pattern_dir(pattern, &self.base_dir) {
+                if dir.is_dir() {
+                    dirs.insert(canonical(&dir));
+                }
+            }
+        }
+        dirs
+    }
+
+    /// 每次按当前目录内容展开 include 模式，新建的匹配文件也会触发重载
+    fn matches(&self, path: &Path) -> bool {
+        let path = canonical(path);
+        path == self.main
+            || self.include.iter().any(|pattern| {
+                config_include::expand_glob(pattern, Some(&self.base_dir))
+                    .iter()
+                    .any(|file| canonical(file) == path)
+            })
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigReloadError {
+    path: String,
+    error: String,
+}
+
+/// 监听配置文件及 include 文件所在目录：不少编辑器以"写临时文件再 rename"方式保存，直接监听文件会在首次保存后失效
+pub fn start(app: AppEvents) -> Result<()> {
+    let config_path = config::get_config_path()?;
+    let path = canonical(&config_path);
+    let dir = path
+        .parent()
+        .context("config path has no parent directory")?
+        .to_path_buf();
+    // include 相对主配置文件所在目录解析，与加载时一致
+    let base_dir = config_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let Ok(event) = res else {
+            return;
+        };
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+            return;
+        }
+        // 先取快照再匹配，避免持锁期间与重新注册监听互相等待
+        let targets = TARGETS.lock().clone();
+        if event.paths.iter().any(|p| targets.matches(p)) {
+            let _ = tx.send(());
+        }
+    })
+    .context("failed to create config file watcher")?;
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("failed to watch {}", dir.display()))?;
+    *TARGETS.lock() = WatchTargets {
+        main: path.clone(),
+        base_dir,
+        include: Vec::new(),
+        watched: HashSet::from([dir]),
+    };
+    *WATCHER.lock() = Some(watcher);
+    refresh_include_watches(&app);
+
+    tauri::async_runtime::spawn(watch_loop(app, path, rx));
+    Ok(())
+}
+
+pub fn stop() {
+    WATCHER.lock().take();
+    *TARGETS.lock() = WatchTargets::default();
+}
+
+/// 按当前生效配置的 include 列表增减监听目录
+fn refresh_include_watches(app: &AppEvents) {
+    let (added, removed) = {
+        let mut targets = TARGETS.lock();
+        targets.include = config::get_config().include;
+        let dirs = targets.dirs();
+        let added: Vec<PathBuf> = dirs.difference(&targets.watched).cloned().collect();
+        let removed: Vec<PathBuf> = targets.watched.difference(&dirs).cloned().collect();
+        targets.watched = dirs;
+        (added, removed)
+    };
+    if added.is_empty() && removed.is_empty() {
+        return;
+    }
+
+    let mut guard = WATCHER.lock();
+    let Some(watcher) = guard.as_mut() else {
+        return;
+    };
+    for dir in &removed {
+        let _ = watcher.unwatch(dir);
+    }
+    for dir in &added {
+        if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+            TARGETS.lock().watched.remove(dir);
+            send_log_with_app(
+                app,
+                LogLevel::Warn,
+                None,
+                format!("Failed to watch include directory {}: {e}", dir.display()),
+            );
+        }
+    }
+}
+
+async fn watch_loop(app: AppEvents, path: PathBuf, mut rx: mpsc::UnboundedReceiver<()>) {
+    let mut seen_generation = config::config_write_generation();
+
+    while rx.recv().await.is_some() {
+        loop {
+            match tokio::time::timeout(DEBOUNCE, rx.recv()).await {
+                Ok(Some(())) => continue,
+                Ok(None) => return,
+                Err(_) => break,
+            }
+        }
+
+        // 期间本程序自己保存过配置，事件来自自身写入
+        let generation = config::config_write_generation();
+        if generation != seen_generation {
+            seen_generation = generation;
+        } else {
+            reload_from_disk(&app, &path).await;
+        }
+        refresh_include_watches(&app);
+    }
+}
+
+async fn reload_from_disk(app: &AppEvents, path: &Path) {
+    let result = match config::read_config_file(path) {
+        Ok((cfg, _)) if same_as_running(&cfg) => return,
+        Ok((cfg, _)) => crate::commands::apply_external_config(app.clone(), cfg).await,
+        Err(e) => Err(format!("{e:#}")),
+    };
+
+    match result {
+        Ok(cfg) => {
+            send_log_with_app(
+                app,
+                LogLevel::Info,
+                None,
+                format!("Config reloaded from {}", path.display()),
+            );
+            let _ = app.emit("config-reloaded", cfg);
+        }
+        Err(error) => {
+            send_log_with_app(
+                app,
+                LogLevel::Error,
+                None,
+                format!(
+                    "Config file change ignored, keeping the running config: {}: {error}",
+                    path.display()
+                ),
+            );
+            let _ = app.emit(
+                "config-reload-error",
+                ConfigReloadError {
+                    path: path.display().to_string(),
+                    error,
+                },
+            );
+        }
+    }
+}
+
+/// 内容与当前配置一致（如仅 touch 或格式调整）时不触发重载
+fn same_as_running(cfg: &config::Config) -> bool {
+    let running = config::get_config();
+    match (toml::to_string(cfg), toml::to_string(&running)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watch_targets_cover_included_files() {
+        let dir = std::env::temp_dir().join(format!("sslpm-watch-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("rules")).unwrap();
+        std::fs::write(dir.join("config.toml"), "").unwrap();
+        std::fs::write(dir.join("extra.toml"), "").unwrap();
+        std::fs::write(dir.join("rules/a.toml"), "").unwrap();
+        std::fs::write(dir.join("rules/a.bak"), "").unwrap();
+
+        let targets = WatchTargets {
+            main: canonical(&dir.join("config.toml")),
+            base_dir: dir.clone(),
+            include: vec!["rules/*.toml".into(), "extra.toml".into()],
+            watched: HashSet::new(),
+        };
+        assert_eq!(
+            targets.dirs(),
+            HashSet::from([canonical(&dir), canonical(&dir.join("rules"))])
+        );
+        assert!(targets.matches(&dir.join("config.toml")));
+        assert!(targets.matches(&dir.join("extra.toml")));
+        assert!(targets.matches(&dir.join("rules/a.toml")));
+        assert!(!targets.matches(&dir.join("rules/a.bak")));
+
+        // 之后新建的匹配文件同样触发重载
+        std::fs::write(dir.join("rules/b.toml"), "").unwrap();
+        assert!(targets.matches(&dir.join("rules/b.toml")));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::{Context, Result};
+use maxminddb::{geoip2, Reader};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use crate::config::GeoIpConfig;
+
+struct GeoIpReaders {
+    country: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+static GEOIP_READERS: Lazy<RwLock<Option<Arc<GeoIpReaders>>>> = Lazy::new(|| RwLock::new(None));
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GeoInfo {
+    /// ISO 3166 两位国家代码，未知时为空
+    pub country: String,
+    /// 如 "AS13335 Cloudflare, Inc."，未知时为空
+    pub asn: String,
+}
+
+/// 按配置把 MMDB 整个读入内存；未启用时清空，之后的查询直接返回空
+pub fn apply_geoip_config(cfg: Option<&GeoIpConfig>) -> Result<()> {
+    let Some(cfg) = cfg.filter(|c| c.enabled) else {
+        *GEOIP_READERS.write() = None;
+        return Ok(());
+    };
+
+    let country = open_reader(cfg.country_db.as_deref())?;
+    let asn = open_reader(cfg.asn_db.as_deref())?;
+    *GEOIP_READERS.write() = if country.is_none() && asn.is_none() {
+        None
+    } else {
+        Some(Arc::new(GeoIpReaders { country, asn }))
+    };
+    Ok(())
+}
+
+fn open_reader(path: Option<&str>) -> Result<Option<Reader<Vec<u8>>>> {
+    let Some(path) = path.map(str::trim).filter(|p| !p.is_empty()) else {
+        return Ok(None);
+    };
+    let reader = Reader::open_readfile(path)
+        .with_context(|| format!("failed to open GeoIP database: {}", path))?;
+    Ok(Some(reader))
+}
+
+/// 只查内存中的数据库，不做 I/O；未配置或查不到时返回空字段
+pub fn lookup(ip: &str) -> GeoInfo {
+    let Some(readers) = GEOIP_READERS.read().clone() else {
+        return GeoInfo::default();
+    };
+    let Ok(addr) = ip.trim().parse::<IpAddr>() else {
+        return GeoInfo::default();
+    };
+
+    let country = readers
+        .country
+        .as_ref()
+        .and_then(|r| r.lookup::<geoip2::Country>(addr).ok())
+        .and_then(|c| c.country)
+        .and_then(|c| c.iso_code)
+        .map(str::to_string)
+        .unwrap_or_default();
+    let asn = readers
+        .asn
+        .as_ref()
+        .and_then(|r| r.lookup::<geoip2::Asn>(addr).ok())
+        .map(|a| format_asn(a.autonomous_system_number, a.autonomous_system_organization))
+        .unwrap_or_default();
+
+    GeoInfo { country, asn }
+}
+
+fn format_asn(number: Option<u32>, org: Option<&str>) -> String {
+    match (number, org.map(str::trim).filter(|o| !o.is_empty())) {
+        (Some(n), Some(org)) => format!("AS{} {}", n, org),
+        (Some(n), None) => format!("AS{}", n),
+        (None, _) => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_is_empty_without_database() {
+        apply_geoip_config(None).unwrap();
+        assert_eq!(lookup("8.8.8.8"), GeoInfo::default());
+        assert_eq!(
+            format_asn(Some(13335), Some(" Cloudflare, Inc. ")),
+            "AS13335 Cloudflare, Inc."
+        );
+        assert_eq!(format_asn(Some(64512), None), "AS64512");
+        assert_eq!(format_asn(None, Some("x")), "");
+    }
+}
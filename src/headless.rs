@@ -0,0 +1,137 @@
+use anyhow::{anyhow, Context, Result};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::app_events::AppEvents;
+use crate::{app, metrics, proxy};
+
+const HEADLESS_ARG: &str = "--headless";
+const CONFIG_ARG: &str = "--config";
+
+static HEADLESS: AtomicBool = AtomicBool::new(false);
+
+/// 以 `--headless` 启动：不创建窗口、托盘，没有对话框
+pub fn is_headless() -> bool {
+    HEADLESS.load(Ordering::Relaxed)
+}
+
+/// 需要弹出对话框的命令在无界面模式下直接返回错误
+pub fn require_gui() -> Result<(), String> {
+    if is_headless() {
+        return Err("not available in headless mode".to_string());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CliArgs {
+    pub headless: bool,
+    pub config: Option<PathBuf>,
+}
+
+/// 解析命令行；未识别的参数（如 `--portable`）留给其他模块处理
+pub fn parse_args<I>(args: I) -> Result<CliArgs>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut parsed = CliArgs::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == HEADLESS_ARG {
+            parsed.headless = true;
+        } else if arg == CONFIG_ARG {
+            let path = args
+                .next()
+                .filter(|p| !p.is_empty())
+                .ok_or_else(|| anyhow!("{CONFIG_ARG} requires a path"))?;
+            parsed.config = Some(PathBuf::from(path));
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            if path.is_empty() {
+                return Err(anyhow!("{CONFIG_ARG} requires a path"));
+            }
+            parsed.config = Some(PathBuf::from(path));
+        }
+    }
+    Ok(parsed)
+}
+
+/// 在普通 tokio runtime 中运行代理，收到 SIGINT/SIGTERM 后按正常停止流程 drain 连接并落盘日志
+pub fn run() -> Result<()> {
+    HEADLESS.store(true, Ordering::Relaxed);
+
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Failed to build tokio runtime")?;
+    // 代理内部通过 tauri::async_runtime::spawn 派生任务，需指向同一个 runtime
+    tauri::async_runtime::set(rt.handle().clone());
+
+    rt.block_on(async {
+        app::init_core()?;
+        app::init_metrics_storage().await;
+
+        let events = AppEvents::headless();
+        proxy::start_server(events.clone())?;
+        app::start_background_services(events.clone());
+        println!("Proxy started in headless mode");
+
+        wait_for_shutdown_signal().await?;
+        println!("Shutdown signal received, stopping proxy");
+
+        app::stop_background_services();
+        let report = proxy::stop_server_gracefully(events).await;
+        println!(
+            "Proxy stopped: {} connections drained, {} cut",
+            report.drained, report.cut
+        );
+        metrics::stop_prometheus_exporter();
+        metrics::shutdown(metrics::SHUTDOWN_FLUSH_TIMEOUT).await;
+        Ok(())
+    })
+}
+
+#[cfg(unix)]
+async fn wait_for_shutdown_signal() -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut term = signal(SignalKind::terminate()).context("Failed to listen for SIGTERM")?;
+    tokio::select! {
+        res = tokio::signal::ctrl_c() => res.context("Failed to listen for SIGINT")?,
+        _ = term.recv() => {}
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn wait_for_shutdown_signal() -> Result<()> {
+    tokio::signal::ctrl_c()
+        .await
+        .context("Failed to listen for Ctrl+C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(v: &[&str]) -> Result<CliArgs> {
+        parse_args(v.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn parses_headless_and_config_forms() {
+        assert_eq!(args(&[]).unwrap(), CliArgs::default());
+        assert_eq!(
+            args(&["--portable", "--headless", "--config", "/etc/proxy.toml"]).unwrap(),
+            CliArgs {
+                headless: true,
+                config: Some(PathBuf::from("/etc/proxy.toml")),
+            }
+        );
+        assert_eq!(
+            args(&["--config=proxy.toml"]).unwrap().config,
+            Some(PathBuf::from("proxy.toml"))
+        );
+        assert!(args(&["--config"]).is_err());
+        assert!(args(&["--config="]).is_err());
+    }
+}
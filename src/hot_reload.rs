@@ -1,103 +1,200 @@
-use crate::config::{self, Config};
+use crate::app_events::AppEvents;
+use crate::config::{self, Config, ListenRule};
 use crate::proxy;
+use crate::proxy::ws_proxy::WsListenRule;
 use anyhow::{Context, Result};
-use std::collections::HashSet;
+use serde::Serialize;
+use std::collections::BTreeSet;
 use std::net::SocketAddr;
 use std::time::{Duration, Instant};
-use tauri::AppHandle;
-
-/// 配置变更类型
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum ConfigChange {
-    /// 仅基础配置变化（不需要重启服务）
-    BasicOnly,
-    /// HTTP 规则变化
-    HttpRules,
-    /// WebSocket 规则变化
-    WsRules,
-    /// Stream 规则变化
-    StreamRules,
-    /// 全局配置变化（需要完全重启）
-    Global,
-}
-
-/// 检测配置变更类型
-pub fn detect_config_changes(old: &Config, new: &Config) -> ConfigChange {
-    if old.enable_http2 != new.enable_http2
+
+/// 重载时被停止或重新启动的监听
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct RestartedListener {
+    /// http / ws / tcp / udp
+    pub kind: &'static str,
+    pub listen_addr: String,
+}
+
+/// 重载结果：应用后的配置，附带本次实际重启的监听（为空表示所有监听保持运行）
+#[derive(Debug, Clone, Serialize)]
+pub struct ReloadOutcome {
+    #[serde(flatten)]
+    pub config: Config,
+    pub restarted_listeners: Vec<RestartedListener>,
+}
+
+/// 按新旧配置差异得出的重载计划
+#[derive(Debug, Default)]
+struct ReloadPlan {
+    /// 需要停止的 HTTP 规则 id（已移除、停用或内容变化）
+    stop_rules: Vec<String>,
+    /// 需要启动的 HTTP 规则（新增、启用或内容变化）
+    start_rules: Vec<ListenRule>,
+    /// 需要停止的 WS 监听地址（已移除、停用或内容变化）
+    stop_ws: Vec<String>,
+    /// 需要启动的 WS 规则
+    start_ws: Vec<WsListenRule>,
+    /// stream 自行按差异重载，未变的监听保留，访问控制就地更新
+    reload_stream: bool,
+    restarted: Vec<RestartedListener>,
+}
+
+impl ReloadPlan {
+    fn is_in_place(&self) -> bool {
+        self.stop_rules.is_empty()
+            && self.start_rules.is_empty()
+            && self.stop_ws.is_empty()
+            && self.start_ws.is_empty()
+            && !self.reload_stream
+    }
+}
+
+fn serialized<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+/// HTTP 监听启动时读取的全局设置，变化后所有 HTTP 规则都要重启；
+/// 访问名单（allow_all_lan / allow_all_ip / whitelist）由 set_config 就地替换，不在此列
+fn http_listener_settings_changed(old: &Config, new: &Config) -> bool {
+    old.enable_http2 != new.enable_http2
         || old.compression_enabled != new.compression_enabled
         || old.compression_gzip != new.compression_gzip
         || old.compression_brotli != new.compression_brotli
+        || old.compression_gzip_level != new.compression_gzip_level
+        || old.compression_brotli_level != new.compression_brotli_level
         || old.max_body_size != new.max_body_size
         || old.max_response_body_size != new.max_response_body_size
         || old.upstream_connect_timeout_ms != new.upstream_connect_timeout_ms
         || old.upstream_read_timeout_ms != new.upstream_read_timeout_ms
         || old.upstream_pool_max_idle != new.upstream_pool_max_idle
         || old.upstream_pool_idle_timeout_sec != new.upstream_pool_idle_timeout_sec
-    {
-        return ConfigChange::Global;
-    }
-
-    let mut changes = HashSet::new();
+        || old.stream_proxy != new.stream_proxy
+        || old.slow_request_threshold_ms != new.slow_request_threshold_ms
+        || old.http_access_control_enabled != new.http_access_control_enabled
+}
 
-    if old.rules != new.rules {
-        changes.insert(ConfigChange::HttpRules);
-    }
+/// 已启用的 HTTP 规则及其序列化内容；没有 id 的规则无法对应，总是视为变化
+fn enabled_rules(cfg: &Config) -> Vec<(&ListenRule, String)> {
+    cfg.rules
+        .iter()
+        .filter(|r| r.enabled)
+        .map(|r| (r, serialized(r)))
+        .collect()
+}
 
-    if old.ws_proxy_enabled != new.ws_proxy_enabled || old.ws_proxy != new.ws_proxy {
-        changes.insert(ConfigChange::WsRules);
-    }
+fn rule_unchanged(rule: &ListenRule, form: &str, others: &[(&ListenRule, String)]) -> bool {
+    rule.id.as_deref().is_some_and(|id| !id.is_empty())
+        && others
+            .iter()
+            .any(|(other, other_form)| other.id == rule.id && other_form == form)
+}
 
-    if old.stream.enabled != new.stream.enabled
-        || old.stream.upstreams != new.stream.upstreams
-        || old.stream.servers != new.stream.servers
-    {
-        changes.insert(ConfigChange::StreamRules);
+/// 已启用的 WS 规则及其序列化内容；WS 规则没有 id，按监听地址对应
+fn enabled_ws_rules(cfg: &Config) -> Vec<(&WsListenRule, String)> {
+    if !cfg.ws_proxy_enabled {
+        return Vec::new();
     }
+    cfg.ws_proxy
+        .iter()
+        .flatten()
+        .filter(|r| r.enabled)
+        .map(|r| (r, serialized(r)))
+        .collect()
+}
 
-    if old.http_access_control_enabled != new.http_access_control_enabled
-        || old.ws_access_control_enabled != new.ws_access_control_enabled
-        || old.stream_access_control_enabled != new.stream_access_control_enabled
-        || old.allow_all_lan != new.allow_all_lan
-        || old.allow_all_ip != new.allow_all_ip
-        || old.whitelist != new.whitelist
-    {
-        changes.insert(ConfigChange::HttpRules);
-        changes.insert(ConfigChange::WsRules);
-        changes.insert(ConfigChange::StreamRules);
-    }
+fn ws_rule_unchanged(rule: &WsListenRule, form: &str, others: &[(&WsListenRule, String)]) -> bool {
+    others
+        .iter()
+        .any(|(other, other_form)| other.listen_addr == rule.listen_addr && other_form == form)
+}
 
-    if changes.len() > 1 {
-        return ConfigChange::Global;
+fn enabled_stream_servers(cfg: &Config) -> Vec<&config::StreamServer> {
+    if !cfg.stream.enabled {
+        return Vec::new();
     }
-
-    changes
-        .into_iter()
-        .next()
-        .unwrap_or(ConfigChange::BasicOnly)
+    cfg.stream.servers.iter().filter(|s| s.enabled).collect()
 }
 
-fn config_listen_addrs(cfg: &Config) -> Vec<SocketAddr> {
-    let mut addrs = Vec::new();
-
-    for rule in &cfg.rules {
-        if !rule.enabled {
-            continue;
+/// 比较新旧配置：内容未变的规则保留监听，变化、新增、移除的规则单独停止/启动；
+/// 不影响监听的全局设置（日志、指标推送等）不产生任何重启
+fn plan_reload(old: &Config, new: &Config) -> ReloadPlan {
+    let mut plan = ReloadPlan::default();
+    let mut restarted = BTreeSet::new();
+
+    let http_all = http_listener_settings_changed(old, new);
+    let old_rules = enabled_rules(old);
+    let new_rules = enabled_rules(new);
+
+    for (rule, form) in &old_rules {
+        if http_all || !rule_unchanged(rule, form, &new_rules) {
+            let id = rule.id.clone().unwrap_or_default();
+            if !plan.stop_rules.contains(&id) {
+                plan.stop_rules.push(id);
+            }
+            for addr in proxy::rule_listen_addrs(rule) {
+                restarted.insert(("http", addr));
+            }
+        }
+    }
+    for (rule, form) in &new_rules {
+        if http_all || !rule_unchanged(rule, form, &old_rules) {
+            plan.start_rules.push((*rule).clone());
+            for addr in proxy::rule_listen_addrs(rule) {
+                restarted.insert(("http", addr));
+            }
         }
+    }
 
-        let candidates = if !rule.listen_addrs.is_empty() {
-            rule.listen_addrs.clone()
-        } else {
-            vec![rule.listen_addr.clone()]
-        };
+    // 访问控制开关在 WS 监听启动时读取，变化后所有 WS 监听都要重启
+    let ws_all = old.ws_access_control_enabled != new.ws_access_control_enabled;
+    let old_ws = enabled_ws_rules(old);
+    let new_ws = enabled_ws_rules(new);
+    for (rule, form) in &old_ws {
+        if ws_all || !ws_rule_unchanged(rule, form, &new_ws) {
+            plan.stop_ws.push(rule.listen_addr.clone());
+            restarted.insert(("ws", rule.listen_addr.clone()));
+        }
+    }
+    for (rule, form) in &new_ws {
+        if ws_all || !ws_rule_unchanged(rule, form, &old_ws) {
+            plan.start_ws.push((*rule).clone());
+            restarted.insert(("ws", rule.listen_addr.clone()));
+        }
+    }
 
-        for candidate in candidates {
-            if let Ok((addr, _)) = proxy::parse_listen_addr(&candidate) {
-                addrs.push(addr);
-            }
+    plan.reload_stream = serialized(&old.stream) != serialized(&new.stream)
+        || old.stream_access_control_enabled != new.stream_access_control_enabled;
+    if plan.reload_stream {
+        // 与 stream_proxy 的重载一致：只有监听配置不同的 server 会被重启
+        let old_servers = enabled_stream_servers(old);
+        let new_servers = enabled_stream_servers(new);
+        let changed = old_servers
+            .iter()
+            .filter(|s| !new_servers.contains(*s))
+            .chain(new_servers.iter().filter(|s| !old_servers.contains(*s)));
+        for server in changed {
+            let kind = if server.udp { "udp" } else { "tcp" };
+            let addr = proxy::stream_proxy::resolve_listen_addr(server)
+                .unwrap_or_else(|_| server.listen_addr.clone().unwrap_or_default());
+            restarted.insert((kind, addr));
         }
     }
 
-    addrs
+    plan.restarted = restarted
+        .into_iter()
+        .map(|(kind, listen_addr)| RestartedListener { kind, listen_addr })
+        .collect();
+    plan
+}
+
+fn rule_socket_addrs(rules: &[ListenRule]) -> Vec<SocketAddr> {
+    rules
+        .iter()
+        .flat_map(proxy::rule_listen_addrs)
+        .filter_map(|candidate| proxy::parse_listen_addr(&candidate).ok())
+        .map(|(addr, _)| addr)
+        .collect()
 }
 
 async fn wait_for_ports_state(
@@ -136,77 +233,304 @@ async fn wait_for_ports_state(
 /// 优雅重载配置
 ///
 /// 相比直接停止-启动，这个函数会：
-/// 1. 检测配置变更类型
-/// 2. 只重启受影响的部分
-/// 3. 优先等待端口实际关闭/打开，而不是仅依赖固定 sleep
-/// 4. 提供更好的错误处理
-pub async fn graceful_reload(app: AppHandle, new_config: Config) -> Result<Config> {
-    let old_config = config::get_config();
-    let change_type = detect_config_changes(&old_config, &new_config);
+/// 1. 按规则比较新旧配置
+/// 2. 只停止并重新启动受影响的监听，其余监听保持运行
+/// 3. 等待旧端口释放、新端口就绪，而不是仅依赖固定 sleep
+/// 4. 在结果中返回实际重启的监听
+pub async fn graceful_reload(app: AppEvents, new_config: Config) -> Result<ReloadOutcome> {
+    reload(app, new_config, true).await
+}
 
-    tracing::info!("配置变更类型: {:?}", change_type);
+/// 应用外部编辑后的配置文件：与 graceful_reload 相同的重载流程，但不回写配置文件
+pub async fn reload_without_save(app: AppEvents, new_config: Config) -> Result<ReloadOutcome> {
+    reload(app, new_config, false).await
+}
 
-    match change_type {
-        ConfigChange::BasicOnly => {
-            config::set_config(new_config.clone());
-            config::save_config()?;
-            Ok(new_config)
+async fn reload(app: AppEvents, new_config: Config, persist: bool) -> Result<ReloadOutcome> {
+    let old_config = config::get_config();
+    // 服务未运行时只更新配置，下次启动生效
+    let plan = if proxy::is_effectively_running() {
+        plan_reload(&old_config, &new_config)
+    } else {
+        ReloadPlan::default()
+    };
+
+    if plan.is_in_place() {
+        config::set_config(new_config.clone());
+        if persist {
+            config::save_config().context("保存配置文件失败")?;
         }
-        _ => {
-            let was_running = proxy::is_effectively_running();
-            let old_addrs = config_listen_addrs(&old_config);
-            let new_addrs = config_listen_addrs(&new_config);
-
-            if was_running {
-                proxy::stop_server(app.clone()).context("停止服务失败")?;
-
-                let stopped = wait_for_ports_state(&old_addrs, false, Duration::from_secs(3)).await;
-                if !stopped {
-                    tracing::warn!("等待旧监听端口关闭超时，将继续尝试应用新配置");
-                }
-            }
+        tracing::info!("配置已就地应用，未重启任何监听");
+        return Ok(ReloadOutcome {
+            config: new_config,
+            restarted_listeners: Vec::new(),
+        });
+    }
 
-            config::set_config(new_config.clone());
-            config::save_config().context("保存配置文件失败")?;
+    tracing::info!(
+        "按差异重载: 停止 HTTP 规则 {:?}，启动 {} 条，停止 WS {:?}，启动 {} 条，重载 Stream: {}",
+        plan.stop_rules,
+        plan.start_rules.len(),
+        plan.stop_ws,
+        plan.start_ws.len(),
+        plan.reload_stream
+    );
+
+    proxy::stop_listeners_for_reload(&app, &plan.stop_rules, &plan.stop_ws).await;
+
+    config::set_config(new_config.clone());
+    if persist {
+        config::save_config().context("保存配置文件失败")?;
+    }
 
-            if was_running {
-                match proxy::start_server(app.clone()) {
-                    Ok(_) => {
-                        let started =
-                            wait_for_ports_state(&new_addrs, true, Duration::from_secs(3)).await;
-                        if !started {
-                            tracing::warn!("服务启动后等待监听端口就绪超时，但启动调用已返回成功");
-                        }
-                        tracing::info!("服务重启成功");
-                        Ok(new_config)
-                    }
-                    Err(e) => {
-                        tracing::error!("服务启动失败，尝试回滚: {}", e);
-
-                        config::set_config(old_config.clone());
-                        config::save_config().ok();
-
-                        let _ =
-                            wait_for_ports_state(&new_addrs, false, Duration::from_secs(2)).await;
-
-                        if let Err(rollback_err) = proxy::start_server(app) {
-                            tracing::error!("回滚启动也失败: {}", rollback_err);
-                            return Err(anyhow::anyhow!(
-                                "新配置启动失败: {}，回滚也失败: {}",
-                                e,
-                                rollback_err
-                            ));
-                        }
-
-                        let _ =
-                            wait_for_ports_state(&old_addrs, true, Duration::from_secs(3)).await;
-
-                        Err(anyhow::anyhow!("新配置启动失败，已回滚到旧配置: {}", e))
-                    }
-                }
-            } else {
-                Ok(new_config)
+    proxy::start_listeners_after_reload(&app, &plan.start_rules, &plan.start_ws);
+
+    if plan.reload_stream {
+        let stream_cfg = new_config.stream.clone();
+        let app2 = app.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) =
+                proxy::stream_proxy::start_stream_servers(app2.clone(), &stream_cfg).await
+            {
+                proxy::send_log_with_app(
+                    &app2,
+                    proxy::LogLevel::Error,
+                    None,
+                    format!("[STREAM] Failed to reload listeners: {e}"),
+                );
             }
+        });
+    }
+
+    let started = wait_for_ports_state(
+        &rule_socket_addrs(&plan.start_rules),
+        true,
+        Duration::from_secs(3),
+    )
+    .await;
+    if !started {
+        tracing::warn!("等待重启的监听端口就绪超时");
+    }
+
+    let message = if plan.restarted.is_empty() {
+        "Config applied without restarting listeners".to_string()
+    } else {
+        let listeners = plan
+            .restarted
+            .iter()
+            .map(|l| format!("{} {}", l.kind, l.listen_addr))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("Config applied; restarted listeners: {listeners}")
+    };
+    proxy::send_log_with_app(&app, proxy::LogLevel::Info, None, message);
+
+    Ok(ReloadOutcome {
+        config: new_config,
+        restarted_listeners: plan.restarted,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = r#"
+allow_all_lan = true
+whitelist = []
+
+[[rules]]
+id = "a"
+listen_addr = "127.0.0.1:18080"
+ssl_enable = false
+cert_file = ""
+key_file = ""
+basic_auth_enable = false
+basic_auth_username = ""
+basic_auth_password = ""
+basic_auth_forward_header = false
+
+[[rules.routes]]
+id = "a-root"
+path = "/"
+upstreams = []
+
+[[rules]]
+id = "b"
+listen_addr = "127.0.0.1:18081"
+ssl_enable = false
+cert_file = ""
+key_file = ""
+basic_auth_enable = false
+basic_auth_username = ""
+basic_auth_password = ""
+basic_auth_forward_header = false
+
+[[rules.routes]]
+id = "b-root"
+path = "/"
+upstreams = []
+"#;
+
+    fn started_ids(plan: &ReloadPlan) -> Vec<&str> {
+        plan.start_rules
+            .iter()
+            .filter_map(|r| r.id.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn plan_reload_restarts_only_changed_rules() {
+        let old: Config = toml::from_str(BASE).unwrap();
+
+        let mut new = old.clone();
+        new.show_realtime_logs = !new.show_realtime_logs;
+        new.metrics_push_interval_ms += 1000;
+        let plan = plan_reload(&old, &new);
+        assert!(plan.is_in_place());
+        assert!(plan.restarted.is_empty());
+
+        // basic_auth_enable 不在 ListenRule 的 PartialEq 中，按序列化内容比较仍能发现
+        let mut new = old.clone();
+        new.rules[1].basic_auth_enable = true;
+        let plan = plan_reload(&old, &new);
+        assert_eq!(plan.stop_rules, vec!["b".to_string()]);
+        assert_eq!(started_ids(&plan), vec!["b"]);
+        assert!(plan.stop_ws.is_empty());
+        assert!(plan.start_ws.is_empty());
+        assert!(!plan.reload_stream);
+        assert_eq!(
+            plan.restarted,
+            vec![RestartedListener {
+                kind: "http",
+                listen_addr: "127.0.0.1:18081".to_string(),
+            }]
+        );
+
+        let mut new = old.clone();
+        new.rules[0].enabled = false;
+        let plan = plan_reload(&old, &new);
+        assert_eq!(plan.stop_rules, vec!["a".to_string()]);
+        assert!(plan.start_rules.is_empty());
+
+        // 访问名单就地替换，不重启任何监听
+        let mut new = old.clone();
+        new.whitelist.push(config::WhitelistEntry {
+            ip: "203.0.113.10".to_string(),
+        });
+        new.allow_all_lan = !new.allow_all_lan;
+        new.allow_all_ip = !new.allow_all_ip;
+        let plan = plan_reload(&old, &new);
+        assert!(plan.is_in_place());
+        assert!(plan.stop_rules.is_empty());
+        assert!(plan.start_rules.is_empty());
+        assert!(plan.stop_ws.is_empty());
+        assert!(plan.start_ws.is_empty());
+        assert!(!plan.reload_stream);
+        assert!(plan.restarted.is_empty());
+    }
+
+    #[test]
+    fn plan_reload_restarts_only_changed_ws_listeners() {
+        let ws_rule = |addr: &str| -> WsListenRule {
+            toml::from_str(&format!(
+                "enabled = true\nlisten_addr = \"{addr}\"\nssl_enable = false\n\
+                 cert_file = \"\"\nkey_file = \"\"\nroutes = []"
+            ))
+            .unwrap()
+        };
+        let mut old: Config = toml::from_str(BASE).unwrap();
+        old.ws_proxy_enabled = true;
+        old.ws_proxy = Some(vec![ws_rule("127.0.0.1:19001"), ws_rule("127.0.0.1:19002")]);
+
+        let mut new = old.clone();
+        new.ws_proxy.as_mut().unwrap()[1].ping_interval_secs = 30;
+        let plan = plan_reload(&old, &new);
+        assert_eq!(plan.stop_ws, vec!["127.0.0.1:19002".to_string()]);
+        let started: Vec<&str> = plan
+            .start_ws
+            .iter()
+            .map(|r| r.listen_addr.as_str())
+            .collect();
+        assert_eq!(started, vec!["127.0.0.1:19002"]);
+        assert!(plan.stop_rules.is_empty());
+        assert_eq!(
+            plan.restarted,
+            vec![RestartedListener {
+                kind: "ws",
+                listen_addr: "127.0.0.1:19002".to_string(),
+            }]
+        );
+
+        // 访问控制开关在监听启动时读取，所有 WS 监听都要重启
+        let mut new = old.clone();
+        new.ws_access_control_enabled = !new.ws_access_control_enabled;
+        let plan = plan_reload(&old, &new);
+        assert_eq!(plan.stop_ws.len(), 2);
+        assert_eq!(plan.start_ws.len(), 2);
+
+        let mut new = old.clone();
+        new.ws_proxy_enabled = false;
+        let plan = plan_reload(&old, &new);
+        assert_eq!(plan.stop_ws.len(), 2);
+        assert!(plan.start_ws.is_empty());
+    }
+
+    #[test]
+    fn plan_reload_restarts_http_listeners_on_compression_level_change() {
+        let old: Config = toml::from_str(BASE).unwrap();
+        for change in [
+            |c: &mut Config| c.compression_gzip_level += 1,
+            |c: &mut Config| c.compression_brotli_level += 1,
+        ] {
+            let mut new = old.clone();
+            change(&mut new);
+            let plan = plan_reload(&old, &new);
+            assert_eq!(plan.stop_rules, vec!["a".to_string(), "b".to_string()]);
+            assert_eq!(started_ids(&plan), vec!["a", "b"]);
         }
     }
+
+    #[test]
+    fn plan_reload_compares_every_stream_server_field() {
+        let mut old: Config = toml::from_str(BASE).unwrap();
+        old.stream.enabled = true;
+        old.stream.servers = vec![toml::from_str(
+            "enabled = true\nproxy_pass = \"backend\"\nlisten_addr = \"127.0.0.1:5353\"",
+        )
+        .unwrap()];
+        fn restarted(old: &Config, new: &Config) -> Vec<String> {
+            let plan = plan_reload(old, new);
+            assert!(plan.stop_rules.is_empty());
+            plan.restarted
+                .into_iter()
+                .map(|l| format!("{} {}", l.kind, l.listen_addr))
+                .collect()
+        }
+
+        let mut new = old.clone();
+        new.stream.servers[0].udp = true;
+        assert_eq!(
+            restarted(&old, &new),
+            vec!["tcp 127.0.0.1:5353", "udp 127.0.0.1:5353"]
+        );
+
+        let mut new = old.clone();
+        new.stream.servers[0].proxy_timeout = "42s".into();
+        assert_eq!(restarted(&old, &new), vec!["tcp 127.0.0.1:5353"]);
+
+        let mut new = old.clone();
+        new.stream.servers[0].max_session_duration = Some("1h".into());
+        assert_eq!(restarted(&old, &new), vec!["tcp 127.0.0.1:5353"]);
+
+        // 只配置了 listen_port 的旧式 server，改端口后新旧地址都要重启
+        old.stream.servers[0].listen_addr = None;
+        old.stream.servers[0].listen_port = Some(5353);
+        let mut new = old.clone();
+        new.stream.servers[0].listen_port = Some(5354);
+        assert_eq!(
+            restarted(&old, &new),
+            vec!["tcp 127.0.0.1:5353", "tcp 127.0.0.1:5354"]
+        );
+    }
 }
@@ -18,6 +18,7 @@ pub fn get_locale() -> Locale {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TrayText {
     StatusRunning,
+    StatusPartial,
     StatusStopped,
     ToggleStart,
     ToggleStop,
@@ -26,6 +27,8 @@ pub enum TrayText {
     RestartProxy,
     Quit,
     Tooltip,
+    ProfileLabel,
+    ProfileDefault,
 }
 
 // 翻译映射
@@ -40,6 +43,12 @@ static TRANSLATIONS: Lazy<Translations> = Lazy::new(|| {
     status_running.insert("en-US".to_string(), "Status: Running");
     map.insert(TrayText::StatusRunning, status_running);
 
+    // 状态：部分运行
+    let mut status_partial = HashMap::new();
+    status_partial.insert("zh-CN".to_string(), "状态：部分运行");
+    status_partial.insert("en-US".to_string(), "Status: Partially running");
+    map.insert(TrayText::StatusPartial, status_partial);
+
     // 状态：已停止
     let mut status_stopped = HashMap::new();
     status_stopped.insert("zh-CN".to_string(), "状态：已停止");
@@ -88,6 +97,18 @@ static TRANSLATIONS: Lazy<Translations> = Lazy::new(|| {
     tooltip.insert("en-US".to_string(), "SSL Proxy Manager");
     map.insert(TrayText::Tooltip, tooltip);
 
+    // 当前配置方案（后接方案名）
+    let mut profile_label = HashMap::new();
+    profile_label.insert("zh-CN".to_string(), "配置方案：");
+    profile_label.insert("en-US".to_string(), "Profile: ");
+    map.insert(TrayText::ProfileLabel, profile_label);
+
+    // 未使用命名方案
+    let mut profile_default = HashMap::new();
+    profile_default.insert("zh-CN".to_string(), "配置方案：默认");
+    profile_default.insert("en-US".to_string(), "Profile: Default");
+    map.insert(TrayText::ProfileDefault, profile_default);
+
     map
 });
 
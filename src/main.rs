@@ -8,16 +8,30 @@ static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 mod access_control;
 mod alerting;
 mod app;
+mod app_events;
+mod app_paths;
 mod buffer_pool;
 mod cache_optimizer;
 mod commands;
 mod config;
+mod config_check;
+mod config_example;
+mod config_include;
+mod config_migration;
+mod config_profiles;
+mod config_watcher;
+mod geoip;
+mod headless;
 mod hot_reload;
 mod i18n;
 mod metrics;
 mod network_optimizer;
+mod nginx_export;
+mod nginx_import;
+mod port_owner;
 mod proxy;
 mod rate_limit;
+mod secrets;
 mod single_instance;
 mod system_metrics;
 mod test_tools;
@@ -51,6 +65,24 @@ fn main() {
         .with(fmt_layer)
         .init();
 
+    let args = match headless::parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e:#}");
+            std::process::exit(2);
+        }
+    };
+    if let Some(path) = args.config {
+        config::set_config_path_override(std::path::absolute(&path).unwrap_or(path));
+    }
+    if args.headless {
+        if let Err(e) = headless::run() {
+            eprintln!("Headless mode failed: {e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     tauri::Builder::default()
         .plugin(tauri_plugin_single_instance::init(|app, _argv, _cwd| {
             single_instance::handle_second_instance(&app);
@@ -59,6 +91,7 @@ fn main() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_autostart::init(
             tauri_plugin_autostart::MacosLauncher::LaunchAgent,
             None,
@@ -66,30 +99,65 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             commands::get_version,
             commands::get_config,
+            commands::validate_config,
+            commands::check_port_available,
             commands::list_config_snapshots,
             commands::restore_config_snapshot,
+            commands::list_config_backups,
+            commands::restore_config_backup,
+            commands::list_profiles,
+            commands::get_active_profile,
+            commands::save_profile,
+            commands::delete_profile,
+            commands::activate_profile,
+            commands::set_secret,
+            commands::delete_secret,
+            commands::list_secret_names,
+            commands::import_config_toml,
+            commands::apply_config_import,
+            commands::import_nginx_config,
+            commands::generate_example_config,
             commands::send_test_alert,
+            commands::get_active_alerts,
             commands::save_config,
             commands::check_update,
             commands::start_server,
             commands::stop_server,
             commands::get_status,
+            commands::get_active_connections,
+            commands::get_upstream_health,
+            commands::restart_server,
+            commands::start_listen_rule,
+            commands::stop_listen_rule,
+            commands::get_app_paths,
             commands::get_logs,
+            commands::get_log_entries,
+            commands::get_log_stats,
             commands::clear_logs,
             commands::get_metrics,
             commands::get_system_metrics,
             commands::set_system_metrics_subscription,
+            commands::subscribe_request_logs,
+            commands::unsubscribe_request_logs,
             commands::query_historical_system_metrics,
             commands::get_listen_addrs,
             commands::query_historical_metrics,
             commands::get_dashboard_stats,
+            commands::get_status_code_breakdown,
+            commands::get_upstream_stats,
+            commands::get_route_stats,
             commands::query_request_logs,
+            commands::export_request_logs,
+            commands::clear_request_logs,
+            commands::query_stream_logs,
             commands::add_blacklist_entry,
             commands::remove_blacklist_entry,
             commands::get_blacklist_entries,
             commands::refresh_blacklist_cache,
             commands::get_metrics_db_status,
             commands::get_metrics_db_status_detail,
+            commands::get_metrics_db_stats,
+            commands::vacuum_metrics_db,
             commands::test_metrics_db_connection,
             commands::open_cert_file_dialog,
             commands::open_key_file_dialog,
@@ -99,6 +167,7 @@ fn main() {
             commands::save_config_toml_as,
             commands::save_chart_png_with_dialog,
             commands::export_current_config_toml,
+            commands::export_nginx_config,
             commands::set_route_enabled,
             commands::set_listen_rule_enabled,
             commands::hide_to_tray,
@@ -108,17 +177,21 @@ fn main() {
             commands::get_locale,
             commands::set_tray_proxy_state,
             commands::send_http_test,
+            commands::replay_request,
             commands::test_route_match,
             commands::run_route_test_suite,
             commands::run_performance_test,
             commands::validate_config_tool,
             commands::dns_lookup,
             commands::get_ssl_cert_info,
+            commands::test_upstream,
             commands::generate_self_signed_cert,
             commands::scan_ports,
             commands::encode_decode,
             commands::get_buffer_pool_stats,
             commands::get_cache_stats,
+            commands::get_rate_limit_stats,
+            commands::get_ws_connections,
             commands::clear_all_caches,
         ])
         .setup(|app| {
@@ -126,6 +199,12 @@ fn main() {
             tray::init_tray(app.handle()).map_err(|e| anyhow::anyhow!(e.to_string()))?;
             Ok(())
         })
+        .on_page_load(|webview, payload| {
+            // 页面刷新后前端的事件监听已失效，清掉旧的实时日志订阅
+            if matches!(payload.event(), tauri::webview::PageLoadEvent::Started) {
+                metrics::unsubscribe_webview(webview.label());
+            }
+        })
         .on_window_event(|window, event| {
             if window.label() == "main" {
                 match event {
@@ -135,9 +214,10 @@ fn main() {
                             let _ = main_window.hide();
                         }
                     }
+                    tauri::WindowEvent::Focused(true) => app::wake_metrics_pusher(),
                     tauri::WindowEvent::Destroyed => {
                         app::cleanup();
-                        let _ = proxy::stop_server(window.app_handle().clone());
+                        let _ = proxy::stop_server(window.app_handle().into());
                     }
                     _ => {}
                 }
@@ -0,0 +1,103 @@
+use super::*;
+use crate::config::IpAnonymizeMode;
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+
+const SALT_FILE_NAME: &str = "ip_anonymize_salt";
+
+// 每个安装生成一次的随机盐，保存在配置目录，保证同一 IP 的哈希结果在重启后保持一致
+static IP_HASH_SALT: Lazy<String> = Lazy::new(load_or_create_salt);
+
+fn load_or_create_salt() -> String {
+    let path = crate::config::get_config_path()
+        .ok()
+        .and_then(|p| p.parent().map(|dir| dir.join(SALT_FILE_NAME)));
+    if let Some(saved) = path
+        .as_ref()
+        .and_then(|p| std::fs::read_to_string(p).ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+    {
+        return saved;
+    }
+
+    let salt = format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    );
+    if let Some(path) = path {
+        if let Err(e) = std::fs::write(&path, &salt) {
+            eprintln!("Failed to save IP anonymization salt: {}", e);
+        }
+    }
+    salt
+}
+
+/// 提前加载盐，避免首次写日志时在请求路径上读文件
+pub(super) fn preload_ip_hash_salt() {
+    Lazy::force(&IP_HASH_SALT);
+}
+
+pub(super) fn anonymize_ip(ip: &str, mode: IpAnonymizeMode) -> String {
+    anonymize_ip_with_salt(ip, mode, &IP_HASH_SALT)
+}
+
+fn anonymize_ip_with_salt(ip: &str, mode: IpAnonymizeMode, salt: &str) -> String {
+    let ip = ip.trim();
+    if ip.is_empty() {
+        return String::new();
+    }
+    match mode {
+        IpAnonymizeMode::Truncate => truncate_ip(ip),
+        IpAnonymizeMode::Hash => {
+            let digest = Sha256::new()
+                .chain_update(salt.as_bytes())
+                .chain_update(ip.as_bytes())
+                .finalize();
+            format!("anon-{}", hex::encode(&digest[..8]))
+        }
+    }
+}
+
+/// IPv4 清零最后一段，IPv6 只保留前 48 位；无法解析的值原样返回
+fn truncate_ip(ip: &str) -> String {
+    match ip.parse::<IpAddr>() {
+        Ok(IpAddr::V4(v4)) => {
+            let [a, b, c, _] = v4.octets();
+            format!("{a}.{b}.{c}.0")
+        }
+        Ok(IpAddr::V6(v6)) => {
+            let s = v6.segments();
+            std::net::Ipv6Addr::new(s[0], s[1], s[2], 0, 0, 0, 0, 0).to_string()
+        }
+        Err(_) => ip.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anonymize_truncates_and_hashes_stably() {
+        let mode = IpAnonymizeMode::Truncate;
+        assert_eq!(
+            anonymize_ip_with_salt("203.0.113.77", mode, "s"),
+            "203.0.113.0"
+        );
+        assert_eq!(
+            anonymize_ip_with_salt("2001:db8:abcd:12::1", mode, "s"),
+            "2001:db8:abcd::"
+        );
+        assert_eq!(anonymize_ip_with_salt("unknown", mode, "s"), "unknown");
+
+        let mode = IpAnonymizeMode::Hash;
+        let a = anonymize_ip_with_salt("203.0.113.77", mode, "salt-a");
+        assert!(a.starts_with("anon-") && a.len() == 21);
+        assert_eq!(a, anonymize_ip_with_salt("203.0.113.77", mode, "salt-a"));
+        assert_ne!(a, anonymize_ip_with_salt("203.0.113.78", mode, "salt-a"));
+        assert_ne!(a, anonymize_ip_with_salt("203.0.113.77", mode, "salt-b"));
+        assert_eq!(anonymize_ip_with_salt(" ", mode, "salt-a"), "");
+    }
+}
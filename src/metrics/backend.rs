@@ -0,0 +1,572 @@
+use super::db::{
+    db_stats_sqlite, delete_request_logs_batch_query, file_len, purge_expired_sqlite,
+    reclaim_after_bulk_delete, status_detail_sqlite, vacuum_sqlite, PurgeOutcome,
+};
+use super::fts::index_request_logs_after;
+use super::query::{
+    dashboard_stats_sqlite, historical_metrics_sqlite, query_request_logs_sqlite,
+    query_stream_logs_sqlite, route_stats_sqlite, status_code_counts_query,
+    status_code_series_query, system_metrics_history_query, upstream_stats_query,
+    HistoricalMetricsParts, HistoricalScope, RouteStatsParts, UpstreamStatRow,
+    DISTINCT_LISTEN_ADDRS_SQL,
+};
+use super::rollup::{
+    raw_series_points, rollup_request_logs_step, rollup_series_points, rollup_watermark,
+    RollupPoint,
+};
+use super::*;
+use crate::system_metrics::{HistoricalRow, SystemMetricsPoint};
+use async_trait::async_trait;
+
+/// metrics 持久化后端：批量写入、日志/统计查询与黑名单存取。
+/// 默认 SQLite；配置 `metrics_storage.backend = "postgres"` 时使用 Postgres。
+#[async_trait]
+pub(crate) trait MetricsBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn insert_request_logs(&self, rows: &[RequestLogInsert]) -> Result<()>;
+    async fn insert_stream_logs(&self, rows: &[StreamLogInsert]) -> Result<()>;
+    async fn insert_ws_session(&self, rec: &WsSessionInsert) -> Result<()>;
+
+    async fn query_request_logs(
+        &self,
+        req: QueryRequestLogsRequest,
+    ) -> Result<QueryRequestLogsResponse>;
+    async fn request_log_by_id(&self, id: i64) -> Result<Option<RequestLog>>;
+    async fn dashboard_stats(&self, req: DashboardStatsRequest) -> Result<DashboardStatsResponse>;
+    async fn query_stream_logs(
+        &self,
+        req: QueryStreamLogsRequest,
+    ) -> Result<QueryStreamLogsResponse>;
+    async fn distinct_listen_addrs(&self) -> Result<Vec<String>>;
+    async fn status_code_counts(
+        &self,
+        req: &StatusCodeBreakdownRequest,
+        limit: i64,
+    ) -> Result<Vec<StatusCodeCount>>;
+    /// (time_bucket, status_code, count)
+    async fn status_code_series(
+        &self,
+        req: &StatusCodeBreakdownRequest,
+        codes: &[i32],
+        gran: i64,
+    ) -> Result<Vec<(i64, i32, i64)>>;
+    async fn upstream_stat_rows(&self, req: &UpstreamStatsRequest) -> Result<Vec<UpstreamStatRow>>;
+    /// route_ids 为 None 时统计全部路由
+    async fn route_stats(
+        &self,
+        req: &RouteStatsRequest,
+        route_ids: Option<&[String]>,
+    ) -> Result<RouteStatsParts>;
+    async fn historical_metrics(
+        &self,
+        scope: HistoricalScope<'_>,
+    ) -> Result<HistoricalMetricsParts>;
+
+    /// 长期汇总水位；汇总表不可用时为 None，时间序列只读原始表
+    async fn rollup_watermark(&self) -> Option<i64>;
+    /// 汇总一段原始行并推进水位，返回推进后的水位
+    async fn rollup_step(&self, target: i64) -> Result<i64>;
+    async fn raw_series_points(
+        &self,
+        start: i64,
+        end: i64,
+        granularity: i64,
+        listen_addr: Option<&str>,
+        route_id: Option<&str>,
+    ) -> Result<Vec<RollupPoint>>;
+    /// resolution 为 60 读分钟表，3600 读小时表
+    async fn rollup_series_points(
+        &self,
+        resolution: i64,
+        start: i64,
+        end: i64,
+        granularity: i64,
+        listen_addr: Option<&str>,
+        route_id: Option<&str>,
+    ) -> Result<Vec<RollupPoint>>;
+
+    /// request_logs 的 (MIN(timestamp), MAX(timestamp))
+    async fn request_logs_time_range(&self) -> Result<(Option<i64>, Option<i64>)>;
+    /// 删除一批命中的请求日志，返回删除行数
+    async fn delete_request_logs_batch(
+        &self,
+        req: &ClearRequestLogsRequest,
+        limit: i64,
+    ) -> Result<u64>;
+    /// 手动清理后回收空间
+    async fn reclaim_after_clear(&self, deleted_rows: u64) -> Result<()>;
+
+    /// 删除 cutoff 之前的日志；max_db_size_mb 为体积上限（仅 SQLite 支持）
+    async fn purge_expired(&self, cutoff: i64, max_db_size_mb: Option<u64>)
+        -> Result<PurgeOutcome>;
+    async fn db_stats(&self, path: String) -> Result<MetricsDBStats>;
+    /// 存储占用字节数，用于 VACUUM 前后对比
+    async fn storage_size_bytes(&self) -> Result<i64>;
+    async fn vacuum(&self) -> Result<()>;
+    /// 在同步状态基础上补充实时检查结果
+    async fn status_detail(&self, base: MetricsDBStatus) -> Result<MetricsDBStatus>;
+
+    async fn insert_system_metrics(&self, points: &[SystemMetricsPoint]) -> Result<()>;
+    /// 删除 cutoff 之前的系统指标，返回删除行数
+    async fn purge_system_metrics(&self, cutoff: i64) -> Result<u64>;
+    async fn system_metrics_history(
+        &self,
+        start: i64,
+        end: i64,
+        granularity: i64,
+    ) -> Result<Vec<HistoricalRow>>;
+
+    /// 未过期的黑名单 (ip, expires_at)
+    async fn active_blacklist(&self, now: i64) -> Result<Vec<(String, i64)>>;
+    async fn upsert_blacklist(
+        &self,
+        ip: &str,
+        reason: &str,
+        expires_at: i64,
+        now: i64,
+    ) -> Result<BlacklistEntry>;
+    async fn delete_blacklist(&self, ip: &str) -> Result<()>;
+    async fn list_blacklist(&self) -> Result<Vec<BlacklistEntry>>;
+}
+
+pub(crate) fn db_backend() -> Option<Arc<dyn MetricsBackend>> {
+    DB_BACKEND.read().clone()
+}
+
+// SQLite 单条语句的绑定参数上限（libsqlite3-sys 自带的 SQLite >= 3.32 为 32766）
+const SQLITE_MAX_VARIABLES: usize = 32_766;
+const REQUEST_LOG_COLUMNS: usize = 20;
+pub(super) const REQUEST_LOG_CHUNK_SIZE: usize = SQLITE_MAX_VARIABLES / REQUEST_LOG_COLUMNS;
+const STREAM_LOG_CHUNK_SIZE: usize = 500;
+// 33 列，两种后端的绑定参数上限内都能容纳
+pub(super) const SYSTEM_METRICS_CHUNK_SIZE: usize = 300;
+
+pub(super) fn request_log_insert_builder<'a, DB>(
+    rows: impl IntoIterator<Item = &'a RequestLogInsert>,
+) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    i32: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    f64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a String: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let mut query_builder = QueryBuilder::new(
+        "INSERT INTO request_logs (timestamp, listen_addr, client_ip, remote_ip, method, request_path, request_host, status_code, upstream, latency_ms, guard_ms, prepare_ms, upstream_ms, user_agent, referer, matched_route_id, bytes_sent, country, asn, sample_rate) "
+    );
+
+    query_builder.push_values(rows, |mut b, it| {
+        b.push_bind(it.timestamp)
+            .push_bind(&it.listen_addr)
+            .push_bind(&it.client_ip)
+            .push_bind(&it.remote_ip)
+            .push_bind(&it.method)
+            .push_bind(&it.request_path)
+            .push_bind(&it.request_host)
+            .push_bind(it.status_code)
+            .push_bind(&it.upstream)
+            .push_bind(it.latency_ms)
+            .push_bind(it.guard_ms)
+            .push_bind(it.prepare_ms)
+            .push_bind(it.upstream_ms)
+            .push_bind(&it.user_agent)
+            .push_bind(&it.referer)
+            .push_bind(&it.matched_route_id)
+            .push_bind(it.bytes_sent)
+            .push_bind(&it.country)
+            .push_bind(&it.asn)
+            .push_bind(it.sample_rate);
+    });
+    query_builder
+}
+
+pub(super) fn stream_log_insert_builder<'a, DB>(
+    rows: impl IntoIterator<Item = &'a StreamLogInsert>,
+) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    i32: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    f64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a String: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let mut query_builder = QueryBuilder::new(
+        "INSERT INTO stream_logs (timestamp, listen_port, protocol, client_ip, upstream_addr, duration_ms, bytes_in, bytes_out, disconnect_reason, attempts) "
+    );
+
+    query_builder.push_values(rows, |mut b, it| {
+        b.push_bind(it.timestamp)
+            .push_bind(it.listen_port)
+            .push_bind(&it.protocol)
+            .push_bind(&it.client_ip)
+            .push_bind(&it.upstream_addr)
+            .push_bind(it.duration_ms)
+            .push_bind(it.bytes_in)
+            .push_bind(it.bytes_out)
+            .push_bind(&it.disconnect_reason)
+            .push_bind(it.attempts);
+    });
+    query_builder
+}
+
+pub(super) fn system_metrics_insert_builder<'a, DB>(
+    rows: impl IntoIterator<Item = &'a SystemMetricsPoint>,
+) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    f64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let mut qb = QueryBuilder::new(
+        "INSERT INTO system_metrics (timestamp, cpu_usage_percent, load1, load5, load15, mem_total_bytes, mem_available_bytes, mem_used_bytes, mem_used_percent, swap_total_bytes, swap_free_bytes, swap_used_bytes, swap_used_percent, net_rx_bytes, net_tx_bytes, net_rx_bps, net_tx_bps, disk_read_bytes, disk_write_bytes, disk_read_bps, disk_write_bps, tcp_established, tcp_time_wait, tcp_close_wait, process_count, fd_used, fd_max, fd_usage_percent, procs_running, procs_blocked, context_switches, processes_forked_total, uptime_seconds) "
+    );
+
+    qb.push_values(rows, |mut b, it| {
+        b.push_bind(it.timestamp)
+            .push_bind(it.cpu_usage_percent)
+            .push_bind(it.load1)
+            .push_bind(it.load5)
+            .push_bind(it.load15)
+            .push_bind(it.mem_total_bytes)
+            .push_bind(it.mem_available_bytes)
+            .push_bind(it.mem_used_bytes)
+            .push_bind(it.mem_used_percent)
+            .push_bind(it.swap_total_bytes)
+            .push_bind(it.swap_free_bytes)
+            .push_bind(it.swap_used_bytes)
+            .push_bind(it.swap_used_percent)
+            .push_bind(it.net_rx_bytes)
+            .push_bind(it.net_tx_bytes)
+            .push_bind(it.net_rx_bps)
+            .push_bind(it.net_tx_bps)
+            .push_bind(it.disk_read_bytes)
+            .push_bind(it.disk_write_bytes)
+            .push_bind(it.disk_read_bps)
+            .push_bind(it.disk_write_bps)
+            .push_bind(it.tcp_established)
+            .push_bind(it.tcp_time_wait)
+            .push_bind(it.tcp_close_wait)
+            .push_bind(it.process_count)
+            .push_bind(it.fd_used)
+            .push_bind(it.fd_max)
+            .push_bind(it.fd_usage_percent)
+            .push_bind(it.procs_running)
+            .push_bind(it.procs_blocked)
+            .push_bind(it.context_switches)
+            .push_bind(it.processes_forked_total)
+            .push_bind(it.uptime_seconds);
+    });
+    qb
+}
+
+pub(super) struct SqliteBackend {
+    pub(super) write: Arc<SqlitePool>,
+    pub(super) read: Arc<SqlitePool>,
+}
+
+#[async_trait]
+impl MetricsBackend for SqliteBackend {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    async fn insert_request_logs(&self, rows: &[RequestLogInsert]) -> Result<()> {
+        let mut tx = self.write.begin().await?;
+        // 写池只有一个连接，事务内 last_id 之后的行就是本批
+        let last_id: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM request_logs")
+            .fetch_one(&mut *tx)
+            .await?;
+        request_log_insert_builder::<sqlx::Sqlite>(rows)
+            .build()
+            .execute(&mut *tx)
+            .await?;
+        index_request_logs_after(&mut tx, last_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn insert_stream_logs(&self, rows: &[StreamLogInsert]) -> Result<()> {
+        for chunk in rows.chunks(STREAM_LOG_CHUNK_SIZE) {
+            stream_log_insert_builder::<sqlx::Sqlite>(chunk)
+                .build()
+                .execute(&*self.write)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_ws_session(&self, rec: &WsSessionInsert) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO ws_sessions(timestamp, listen_addr, client_ip, request_path, upstream, duration_ms, messages_in, messages_out, bytes_in, bytes_out) VALUES(?,?,?,?,?,?,?,?,?,?)",
+        )
+        .bind(rec.timestamp)
+        .bind(&rec.listen_addr)
+        .bind(&rec.client_ip)
+        .bind(&rec.request_path)
+        .bind(&rec.upstream)
+        .bind(rec.duration_ms)
+        .bind(rec.messages_in)
+        .bind(rec.messages_out)
+        .bind(rec.bytes_in)
+        .bind(rec.bytes_out)
+        .execute(&*self.write)
+        .await?;
+        Ok(())
+    }
+
+    async fn query_request_logs(
+        &self,
+        req: QueryRequestLogsRequest,
+    ) -> Result<QueryRequestLogsResponse> {
+        query_request_logs_sqlite(&self.read, req).await
+    }
+
+    async fn request_log_by_id(&self, id: i64) -> Result<Option<RequestLog>> {
+        let log = sqlx::query_as::<_, RequestLog>(
+            "SELECT id, timestamp, listen_addr, client_ip, remote_ip, method, request_path, request_host, status_code, upstream, latency_ms, guard_ms, prepare_ms, upstream_ms, user_agent, referer, matched_route_id, bytes_sent, country, asn, sample_rate FROM request_logs WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_optional(&*self.read)
+        .await?;
+        Ok(log)
+    }
+
+    async fn dashboard_stats(&self, req: DashboardStatsRequest) -> Result<DashboardStatsResponse> {
+        dashboard_stats_sqlite(self, req).await
+    }
+
+    async fn query_stream_logs(
+        &self,
+        req: QueryStreamLogsRequest,
+    ) -> Result<QueryStreamLogsResponse> {
+        query_stream_logs_sqlite(&self.read, req).await
+    }
+
+    async fn distinct_listen_addrs(&self) -> Result<Vec<String>> {
+        let rows = sqlx::query_scalar::<_, String>(DISTINCT_LISTEN_ADDRS_SQL)
+            .fetch_all(&*self.read)
+            .await?;
+        Ok(rows)
+    }
+
+    async fn status_code_counts(
+        &self,
+        req: &StatusCodeBreakdownRequest,
+        limit: i64,
+    ) -> Result<Vec<StatusCodeCount>> {
+        let rows = status_code_counts_query::<sqlx::Sqlite>(req, limit)
+            .build_query_as::<StatusCodeCount>()
+            .fetch_all(&*self.read)
+            .await?;
+        Ok(rows)
+    }
+
+    async fn status_code_series(
+        &self,
+        req: &StatusCodeBreakdownRequest,
+        codes: &[i32],
+        gran: i64,
+    ) -> Result<Vec<(i64, i32, i64)>> {
+        let rows = status_code_series_query::<sqlx::Sqlite>(req, codes, gran)
+            .build_query_as::<(i64, i32, i64)>()
+            .fetch_all(&*self.read)
+            .await?;
+        Ok(rows)
+    }
+
+    async fn upstream_stat_rows(&self, req: &UpstreamStatsRequest) -> Result<Vec<UpstreamStatRow>> {
+        let rows = upstream_stats_query::<sqlx::Sqlite>(req)
+            .build_query_as::<UpstreamStatRow>()
+            .fetch_all(&*self.read)
+            .await?;
+        Ok(rows)
+    }
+
+    async fn route_stats(
+        &self,
+        req: &RouteStatsRequest,
+        route_ids: Option<&[String]>,
+    ) -> Result<RouteStatsParts> {
+        route_stats_sqlite(&self.read, req, route_ids).await
+    }
+
+    async fn historical_metrics(
+        &self,
+        scope: HistoricalScope<'_>,
+    ) -> Result<HistoricalMetricsParts> {
+        historical_metrics_sqlite(&self.read, scope).await
+    }
+
+    async fn rollup_watermark(&self) -> Option<i64> {
+        rollup_watermark(&self.read).await
+    }
+
+    async fn rollup_step(&self, target: i64) -> Result<i64> {
+        rollup_request_logs_step(&self.write, target).await
+    }
+
+    async fn raw_series_points(
+        &self,
+        start: i64,
+        end: i64,
+        granularity: i64,
+        listen_addr: Option<&str>,
+        route_id: Option<&str>,
+    ) -> Result<Vec<RollupPoint>> {
+        raw_series_points(&self.read, start, end, granularity, listen_addr, route_id).await
+    }
+
+    async fn rollup_series_points(
+        &self,
+        resolution: i64,
+        start: i64,
+        end: i64,
+        granularity: i64,
+        listen_addr: Option<&str>,
+        route_id: Option<&str>,
+    ) -> Result<Vec<RollupPoint>> {
+        rollup_series_points(
+            &self.read,
+            resolution,
+            start,
+            end,
+            granularity,
+            listen_addr,
+            route_id,
+        )
+        .await
+    }
+
+    async fn request_logs_time_range(&self) -> Result<(Option<i64>, Option<i64>)> {
+        let range = sqlx::query_as("SELECT MIN(timestamp), MAX(timestamp) FROM request_logs")
+            .fetch_one(&*self.read)
+            .await?;
+        Ok(range)
+    }
+
+    async fn delete_request_logs_batch(
+        &self,
+        req: &ClearRequestLogsRequest,
+        limit: i64,
+    ) -> Result<u64> {
+        let n = delete_request_logs_batch_query::<sqlx::Sqlite>(req, limit)
+            .build()
+            .execute(&*self.write)
+            .await?
+            .rows_affected();
+        Ok(n)
+    }
+
+    async fn reclaim_after_clear(&self, deleted_rows: u64) -> Result<()> {
+        reclaim_after_bulk_delete(&self.write, deleted_rows).await;
+        Ok(())
+    }
+
+    async fn purge_expired(
+        &self,
+        cutoff: i64,
+        max_db_size_mb: Option<u64>,
+    ) -> Result<PurgeOutcome> {
+        purge_expired_sqlite(&self.write, cutoff, max_db_size_mb).await
+    }
+
+    async fn db_stats(&self, path: String) -> Result<MetricsDBStats> {
+        db_stats_sqlite(&self.read, path).await
+    }
+
+    async fn storage_size_bytes(&self) -> Result<i64> {
+        let path = DB_PATH.read().clone();
+        Ok(file_len(&path) + file_len(&format!("{}-wal", path)))
+    }
+
+    async fn vacuum(&self) -> Result<()> {
+        vacuum_sqlite(&self.write).await
+    }
+
+    async fn status_detail(&self, base: MetricsDBStatus) -> Result<MetricsDBStatus> {
+        status_detail_sqlite(&self.read, base).await
+    }
+
+    async fn insert_system_metrics(&self, points: &[SystemMetricsPoint]) -> Result<()> {
+        for chunk in points.chunks(SYSTEM_METRICS_CHUNK_SIZE) {
+            system_metrics_insert_builder::<sqlx::Sqlite>(chunk)
+                .build()
+                .execute(&*self.write)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn purge_system_metrics(&self, cutoff: i64) -> Result<u64> {
+        let deleted_rows = sqlx::query("DELETE FROM system_metrics WHERE timestamp < ?")
+            .bind(cutoff)
+            .execute(&*self.write)
+            .await?
+            .rows_affected();
+        reclaim_db_space_after_delete(&self.write, deleted_rows).await;
+        Ok(deleted_rows)
+    }
+
+    async fn system_metrics_history(
+        &self,
+        start: i64,
+        end: i64,
+        granularity: i64,
+    ) -> Result<Vec<HistoricalRow>> {
+        let rows = system_metrics_history_query::<sqlx::Sqlite>(start, end, granularity)
+            .build_query_as::<HistoricalRow>()
+            .fetch_all(&*self.read)
+            .await?;
+        Ok(rows)
+    }
+
+    async fn active_blacklist(&self, now: i64) -> Result<Vec<(String, i64)>> {
+        let rows = sqlx::query_as::<_, (String, i64)>(
+            "SELECT ip, expires_at FROM blacklist WHERE expires_at=0 OR expires_at>?",
+        )
+        .bind(now)
+        .fetch_all(&*self.read)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn upsert_blacklist(
+        &self,
+        ip: &str,
+        reason: &str,
+        expires_at: i64,
+        now: i64,
+    ) -> Result<BlacklistEntry> {
+        let rec = sqlx::query_as::<_, BlacklistEntry>(
+            "INSERT OR REPLACE INTO blacklist(ip, reason, expires_at, created_at) VALUES(?,?,?,?) RETURNING id, ip, reason, expires_at, created_at",
+        )
+        .bind(ip)
+        .bind(Some(reason))
+        .bind(expires_at)
+        .bind(now)
+        .fetch_one(&*self.write)
+        .await?;
+        Ok(rec)
+    }
+
+    async fn delete_blacklist(&self, ip: &str) -> Result<()> {
+        sqlx::query("DELETE FROM blacklist WHERE ip=?")
+            .bind(ip)
+            .execute(&*self.write)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_blacklist(&self) -> Result<Vec<BlacklistEntry>> {
+        let rows = sqlx::query_as::<_, BlacklistEntry>(
+            "SELECT id, ip, reason, expires_at, created_at FROM blacklist ORDER BY created_at DESC",
+        )
+        .fetch_all(&*self.read)
+        .await?;
+        Ok(rows)
+    }
+}
@@ -1,14 +1,17 @@
+use super::backend::SqliteBackend;
+use super::fts::backfill_request_logs_fts;
+use super::migrations::migrate_schema;
+use super::postgres::{redact_url, PostgresBackend};
+use super::recovery::{cancel_db_recovery, db_recovery_state};
+use super::rollup::{rollup_watermark, run_request_log_rollups};
 use super::*;
+use crate::system_metrics::{HistoricalRow, SystemMetricsPoint};
 
 fn default_db_path() -> Result<PathBuf> {
-    let exe = std::env::current_exe().with_context(|| "无法获取可执行文件路径")?;
-    let dir = exe
-        .parent()
-        .ok_or_else(|| anyhow!("无法获取可执行文件所在目录"))?;
-    Ok(dir.join("data").join("metrics.db"))
+    Ok(crate::app_paths::exe_dir()?.join("data").join("metrics.db"))
 }
 
-fn resolve_db_path(input: String) -> Result<PathBuf> {
+pub(crate) fn resolve_db_path(input: String) -> Result<PathBuf> {
     let s = input.trim();
     if s.is_empty() {
         return default_db_path();
@@ -17,11 +20,7 @@ fn resolve_db_path(input: String) -> Result<PathBuf> {
     let p = if raw_path.is_absolute() {
         raw_path
     } else {
-        let exe = std::env::current_exe().with_context(|| "无法获取可执行文件路径")?;
-        let dir = exe
-            .parent()
-            .ok_or_else(|| anyhow!("无法获取可执行文件所在目录"))?;
-        dir.join(raw_path)
+        crate::app_paths::exe_dir()?.join(raw_path)
     };
 
     // 兼容用户将 db_path 配置为目录：
@@ -52,15 +51,16 @@ pub fn is_ip_blacklisted(ip: &str) -> bool {
     }
 }
 
-fn pool() -> Option<Arc<SqlitePool>> {
-    DB_POOL.read().clone()
-}
-
 #[cfg_attr(not(any(target_os = "linux", target_os = "windows")), allow(dead_code))]
 pub(crate) fn db_pool() -> Option<Arc<SqlitePool>> {
     DB_POOL.read().clone()
 }
 
+/// 写入前获取；手动 VACUUM 期间会在此等待
+pub(crate) async fn db_write_gate() -> tokio::sync::RwLockReadGuard<'static, ()> {
+    DB_WRITE_GATE.read().await
+}
+
 async fn maybe_vacuum_metrics_db(pool: &SqlitePool, deleted_rows: u64) {
     if deleted_rows < DB_SPACE_RECLAIM_MIN_DELETED_ROWS {
         return;
@@ -121,8 +121,328 @@ pub(crate) async fn reclaim_db_space_after_delete(pool: &SqlitePool, deleted_row
     maybe_vacuum_metrics_db(pool, deleted_rows).await;
 }
 
+async fn delete_in_batches(pool: &SqlitePool, table: &str, cutoff: Option<i64>) -> u64 {
+    // cutoff 为空时按 id 删除最早的一批
+    let sql = match cutoff {
+        Some(_) => format!(
+            "DELETE FROM {table} WHERE id IN (SELECT id FROM {table} WHERE timestamp < ? LIMIT ?)"
+        ),
+        None => {
+            format!("DELETE FROM {table} WHERE id IN (SELECT id FROM {table} ORDER BY id LIMIT ?)")
+        }
+    };
+
+    let _gate = db_write_gate().await;
+    let mut q = sqlx::query(&sql);
+    if let Some(cutoff) = cutoff {
+        q = q.bind(cutoff);
+    }
+    match q.bind(RETENTION_PURGE_BATCH).execute(pool).await {
+        Ok(r) => r.rows_affected(),
+        Err(e) => {
+            eprintln!("Purge {} failed: {}", table, e);
+            0
+        }
+    }
+}
+
+pub(super) async fn reclaim_after_bulk_delete(pool: &SqlitePool, deleted_rows: u64) {
+    // auto_vacuum=INCREMENTAL（2）的库可以直接归还空闲页
+    let auto_vacuum: i64 = sqlx::query_scalar("PRAGMA auto_vacuum")
+        .fetch_one(pool)
+        .await
+        .unwrap_or(0);
+    if deleted_rows > 0 && auto_vacuum == 2 {
+        let _ = sqlx::query("PRAGMA incremental_vacuum").execute(pool).await;
+    }
+    reclaim_db_space_after_delete(pool, deleted_rows).await;
+}
+
+async fn db_used_bytes(pool: &SqlitePool) -> Option<i64> {
+    let page_size: i64 = sqlx::query_scalar("PRAGMA page_size")
+        .fetch_one(pool)
+        .await
+        .ok()?;
+    let page_count: i64 = sqlx::query_scalar("PRAGMA page_count")
+        .fetch_one(pool)
+        .await
+        .ok()?;
+    let freelist_count: i64 = sqlx::query_scalar("PRAGMA freelist_count")
+        .fetch_one(pool)
+        .await
+        .ok()?;
+    Some((page_count - freelist_count).max(0) * page_size)
+}
+
+/// 一次保留清理的结果
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct PurgeOutcome {
+    /// 超过保留天数删除的行数
+    pub expired_rows: u64,
+    /// 超过体积上限额外删除的最早请求日志行数
+    pub trimmed_rows: u64,
+    pub remaining_request_logs: Option<i64>,
+}
+
+/// 按保留天数与体积上限清理日志表；同一时间只允许一个清理任务。
+pub(crate) async fn purge_expired_logs(backend: &dyn MetricsBackend) {
+    if DB_PURGE_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let (retention_days, max_db_size_mb) = crate::config::metrics_retention();
+    let retention_days = match retention_days {
+        Some(d) if d > 0 => d as i64,
+        _ => REQUEST_LOG_RETENTION_DAYS,
+    };
+    let cutoff = chrono::Utc::now().timestamp() - retention_days * 24 * 60 * 60;
+
+    match backend
+        .purge_expired(cutoff, max_db_size_mb.filter(|v| *v > 0))
+        .await
+    {
+        Ok(outcome) => {
+            let deleted_rows = outcome.expired_rows + outcome.trimmed_rows;
+            *DB_LAST_PURGE.write() = Some(PurgeRecord {
+                at: chrono::Utc::now().timestamp(),
+                deleted_rows,
+                remaining_request_logs: outcome.remaining_request_logs,
+            });
+            if deleted_rows > 0 {
+                crate::proxy::logging::push_log_line(format!(
+                    "Metrics retention purged {} rows ({} older than {} days, {} over size limit)",
+                    deleted_rows, outcome.expired_rows, retention_days, outcome.trimmed_rows
+                ));
+            }
+        }
+        Err(e) => eprintln!("Metrics retention purge failed: {:#}", e),
+    }
+
+    DB_PURGE_RUNNING.store(false, Ordering::SeqCst);
+}
+
+/// SQLite：每批删除后短暂让出，批量写入可在批次之间拿到写锁
+pub(super) async fn purge_expired_sqlite(
+    pool: &SqlitePool,
+    cutoff: i64,
+    max_db_size_mb: Option<u64>,
+) -> Result<PurgeOutcome> {
+    // 尚未计入长期汇总的原始请求日志先保留
+    let request_logs_cutoff = match rollup_watermark(pool).await {
+        Some(rolled_until) => cutoff.min(rolled_until),
+        None => cutoff,
+    };
+
+    let mut expired_rows = 0u64;
+    for (table, cutoff) in [
+        ("request_logs", request_logs_cutoff),
+        ("ws_sessions", cutoff),
+        ("stream_logs", cutoff),
+    ] {
+        loop {
+            let n = delete_in_batches(pool, table, Some(cutoff)).await;
+            expired_rows += n;
+            if n < RETENTION_PURGE_BATCH as u64 {
+                break;
+            }
+            tokio::time::sleep(RETENTION_PURGE_BATCH_PAUSE).await;
+        }
+    }
+
+    let mut trimmed_rows = 0u64;
+    if let Some(max_mb) = max_db_size_mb {
+        let max_bytes = (max_mb as i64).saturating_mul(1024 * 1024);
+        while db_used_bytes(pool)
+            .await
+            .is_some_and(|used| used > max_bytes)
+        {
+            let n = delete_in_batches(pool, "request_logs", None).await;
+            if n == 0 {
+                break;
+            }
+            trimmed_rows += n;
+            tokio::time::sleep(RETENTION_PURGE_BATCH_PAUSE).await;
+        }
+    }
+
+    reclaim_after_bulk_delete(pool, expired_rows + trimmed_rows).await;
+
+    let remaining_request_logs: Option<i64> =
+        sqlx::query_scalar("SELECT COUNT(1) FROM request_logs")
+            .fetch_one(pool)
+            .await
+            .ok();
+    Ok(PurgeOutcome {
+        expired_rows,
+        trimmed_rows,
+        remaining_request_logs,
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClearRequestLogsRequest {
+    pub start_time: i64,
+    pub end_time: i64,
+    pub listen_addr: Option<String>,
+    pub client_ip: Option<String>,
+    /// 无过滤条件且时间范围覆盖全部日志时必须为 true，防止误删全部
+    #[serde(default)]
+    pub confirm_full_range: bool,
+    /// 删除后回收空间（incremental vacuum / 满足阈值时 VACUUM）
+    #[serde(default)]
+    pub vacuum: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClearRequestLogsResult {
+    pub deleted_rows: u64,
+}
+
+/// 按时间范围与可选的监听地址/客户端 IP 分批删除请求日志
+pub async fn clear_request_logs(req: ClearRequestLogsRequest) -> Result<ClearRequestLogsResult> {
+    let Some(backend) = db_backend() else {
+        return Err(anyhow!("数据库未初始化"));
+    };
+    let deleted_rows = delete_request_logs_in_batches(&*backend, &req).await?;
+    if req.vacuum && deleted_rows > 0 {
+        backend
+            .reclaim_after_clear(deleted_rows)
+            .await
+            .context("回收空间失败")?;
+    }
+    if deleted_rows > 0 {
+        crate::proxy::logging::push_log_line(format!(
+            "Cleared {} request logs between {} and {}",
+            deleted_rows, req.start_time, req.end_time
+        ));
+    }
+    Ok(ClearRequestLogsResult { deleted_rows })
+}
+
+async fn delete_request_logs_in_batches(
+    backend: &dyn MetricsBackend,
+    req: &ClearRequestLogsRequest,
+) -> Result<u64> {
+    if req.end_time < req.start_time {
+        return Err(anyhow!("结束时间早于开始时间"));
+    }
+    let listen_addr = non_empty_trimmed(&req.listen_addr);
+    let client_ip = non_empty_trimmed(&req.client_ip);
+
+    if listen_addr.is_none() && client_ip.is_none() && !req.confirm_full_range {
+        if let (Some(min_ts), Some(max_ts)) = backend.request_logs_time_range().await? {
+            if req.start_time <= min_ts && req.end_time >= max_ts {
+                return Err(anyhow!(
+                    "时间范围覆盖全部请求日志，需要 confirm_full_range 确认"
+                ));
+            }
+        }
+    }
+
+    let mut deleted = 0u64;
+    loop {
+        let n = {
+            let _gate = db_write_gate().await;
+            backend
+                .delete_request_logs_batch(req, RETENTION_PURGE_BATCH)
+                .await
+                .context("删除请求日志失败")?
+        };
+        deleted += n;
+        if n < RETENTION_PURGE_BATCH as u64 {
+            break;
+        }
+        tokio::time::sleep(RETENTION_PURGE_BATCH_PAUSE).await;
+    }
+    Ok(deleted)
+}
+
+/// 删除一批命中的请求日志（最多 limit 行）
+pub(super) fn delete_request_logs_batch_query<'a, DB>(
+    req: &'a ClearRequestLogsRequest,
+    limit: i64,
+) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let mut qb = QueryBuilder::new(
+        "DELETE FROM request_logs WHERE id IN (SELECT id FROM request_logs WHERE timestamp >= ",
+    );
+    qb.push_bind(req.start_time)
+        .push(" AND timestamp <= ")
+        .push_bind(req.end_time);
+    if let Some(v) = non_empty_trimmed(&req.listen_addr) {
+        qb.push(" AND listen_addr = ").push_bind(v);
+    }
+    if let Some(v) = non_empty_trimmed(&req.client_ip) {
+        qb.push(" AND client_ip = ").push_bind(v);
+    }
+    qb.push(" LIMIT ").push_bind(limit).push(")");
+    qb
+}
+
 pub fn deinit_db() {
+    cancel_db_recovery();
+    *DB_BACKEND.write() = None;
     *DB_POOL.write() = None;
+    *DB_READ_POOL.write() = None;
+}
+
+#[inline]
+fn is_postgres_backend(backend: Option<&str>) -> bool {
+    backend.is_some_and(|b| b.trim().eq_ignore_ascii_case("postgres"))
+}
+
+/// 按 metrics_storage.backend 选择 SQLite（默认）或 Postgres；会取消正在进行的自动重连
+pub async fn init_storage(storage: &crate::config::MetricsStorage) -> Result<()> {
+    cancel_db_recovery();
+    open_storage(storage).await
+}
+
+pub(super) async fn open_storage(storage: &crate::config::MetricsStorage) -> Result<()> {
+    if storage.anonymize_ips && storage.anonymize_mode == crate::config::IpAnonymizeMode::Hash {
+        super::anonymize::preload_ip_hash_salt();
+    }
+    if is_postgres_backend(storage.backend.as_deref()) {
+        let url = crate::secrets::resolve_optional(storage.url.as_deref(), "metrics_storage.url")?;
+        init_postgres(url.unwrap_or_default()).await
+    } else {
+        init_db(storage.db_path.clone()).await
+    }
+}
+
+async fn init_postgres(url: String) -> Result<()> {
+    let result: Result<()> = async move {
+        let url = url.trim();
+        if url.is_empty() {
+            return Err(anyhow!("未配置 Postgres 连接地址"));
+        }
+        let backend = PostgresBackend::connect(url).await?;
+        backend.migrate().await?;
+        refresh_blacklist_cache_internal(&backend).await.ok();
+
+        let backend: Arc<dyn MetricsBackend> = Arc::new(backend);
+        *DB_POOL.write() = None;
+        *DB_READ_POOL.write() = None;
+        *DB_BACKEND.write() = Some(backend.clone());
+        *DB_PATH.write() = redact_url(url);
+        *DB_ERROR.write() = None;
+
+        tauri::async_runtime::spawn(run_request_log_rollups(backend));
+
+        Ok(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        *DB_ERROR.write() = Some(e.to_string());
+        return Err(e);
+    }
+
+    Ok(())
 }
 
 pub async fn init_db(db_path: String) -> Result<()> {
@@ -143,229 +463,50 @@ pub async fn init_db(db_path: String) -> Result<()> {
         // 关键性能优化：启用 WAL 模式和 Normal 同步
         opt = opt.journal_mode(SqliteJournalMode::Wal);
         opt = opt.synchronous(SqliteSynchronous::Normal);
-
-        let pool = SqlitePoolOptions::new()
-            .max_connections(3)
-            .connect_with(opt)
-            .await
-            .with_context(|| format!("连接数据库失败: {}", path.display()))?;
-
-        // 连接级 PRAGMA（提升稳定性/性能）
+        // 连接级 PRAGMA 放在连接选项里，保证池内每个连接都生效
         // - busy_timeout：避免高并发下立即报 database is locked
-        // - cache_size：增大 page cache（负数表示 KB）
+        // - cache_size：增大 page cache 到 64MB（负数表示 KB）
         // - temp_store：临时表尽量走内存
-        // 性能优化 PRAGMA 设置
-        let _ = sqlx::query("PRAGMA busy_timeout = 5000;")
-            .execute(&pool)
-            .await;
-        // 增大缓存到 64MB（-64000 KB）以提升查询性能
-        let _ = sqlx::query("PRAGMA cache_size = -64000;")
-            .execute(&pool)
-            .await;
-        let _ = sqlx::query("PRAGMA temp_store = MEMORY;")
-            .execute(&pool)
-            .await;
-        // 启用 mmap 以提升读取性能（256MB）
-        let _ = sqlx::query("PRAGMA mmap_size = 268435456;")
-            .execute(&pool)
-            .await;
-
-        // 轻量迁移：尽量通过 ALTER TABLE 添加缺失列，避免丢历史数据
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS request_logs (
-              id INTEGER PRIMARY KEY AUTOINCREMENT,
-              timestamp INTEGER NOT NULL,
-              listen_addr TEXT NOT NULL,
-              client_ip TEXT NOT NULL,
-              remote_ip TEXT NOT NULL,
-              method TEXT NOT NULL,
-              request_path TEXT NOT NULL,
-              request_host TEXT NOT NULL,
-              status_code INTEGER NOT NULL,
-              upstream TEXT NOT NULL,
-              latency_ms REAL NOT NULL,
-              guard_ms REAL NOT NULL DEFAULT 0,
-              prepare_ms REAL NOT NULL DEFAULT 0,
-              upstream_ms REAL NOT NULL DEFAULT 0,
-              user_agent TEXT NOT NULL,
-              referer TEXT NOT NULL,
-              matched_route_id TEXT NOT NULL DEFAULT ''
-            );
-            "#,
-        )
-        .execute(&pool)
-        .await
-        .context("创建 request_logs 表失败")?;
-
-        // 为旧库补列（SQLite 不支持 IF NOT EXISTS，需要先探测列是否存在）
-        // PRAGMA table_info 返回列：cid,name,type,notnull,dflt_value,pk
-        // 这里我们只取 name 字段（第 2 列）
-        let cols: Vec<(i64, String, String, i64, Option<String>, i64)> =
-            sqlx::query_as("PRAGMA table_info(request_logs)")
-                .fetch_all(&pool)
-                .await
-                .context("读取 request_logs 表结构失败")?;
-        let has_matched_route_id = cols
-            .iter()
-            .any(|(_, name, _, _, _, _)| name == "matched_route_id");
-        if !has_matched_route_id {
-            sqlx::query(
-                "ALTER TABLE request_logs ADD COLUMN matched_route_id TEXT NOT NULL DEFAULT ''",
-            )
-            .execute(&pool)
-            .await
-            .context("迁移 request_logs.matched_route_id 失败")?;
-        }
-
-        let has_guard_ms = cols.iter().any(|(_, name, _, _, _, _)| name == "guard_ms");
-        if !has_guard_ms {
-            sqlx::query(
-                "ALTER TABLE request_logs ADD COLUMN guard_ms REAL NOT NULL DEFAULT 0",
-            )
-            .execute(&pool)
-            .await
-            .context("迁移 request_logs.guard_ms 失败")?;
-        }
-
-        let has_prepare_ms = cols
-            .iter()
-            .any(|(_, name, _, _, _, _)| name == "prepare_ms");
-        if !has_prepare_ms {
-            sqlx::query(
-                "ALTER TABLE request_logs ADD COLUMN prepare_ms REAL NOT NULL DEFAULT 0",
-            )
-            .execute(&pool)
-            .await
-            .context("迁移 request_logs.prepare_ms 失败")?;
-        }
-
-        let has_upstream_ms = cols
-            .iter()
-            .any(|(_, name, _, _, _, _)| name == "upstream_ms");
-        if !has_upstream_ms {
-            sqlx::query(
-                "ALTER TABLE request_logs ADD COLUMN upstream_ms REAL NOT NULL DEFAULT 0",
-            )
-            .execute(&pool)
-            .await
-            .context("迁移 request_logs.upstream_ms 失败")?;
-        }
-
-        sqlx::query(
-            r#"CREATE INDEX IF NOT EXISTS idx_request_logs_ts ON request_logs(timestamp);"#,
-        )
-        .execute(&pool)
-        .await
-        .context("创建 request_logs.timestamp 索引失败")?;
+        // - mmap_size：启用 mmap 以提升读取性能（256MB）
+        opt = opt.busy_timeout(DB_BUSY_TIMEOUT);
+        opt = opt.pragma("cache_size", "-64000");
+        opt = opt.pragma("temp_store", "MEMORY");
+        opt = opt.pragma("mmap_size", "268435456");
 
-        sqlx::query(
-            r#"CREATE INDEX IF NOT EXISTS idx_request_logs_listen_ts ON request_logs(listen_addr, timestamp);"#,
-        )
-        .execute(&pool)
-        .await
-        .context("创建 request_logs.listen_addr+timestamp 索引失败")?;
-
-        // 常用查询条件索引（提升日志筛选/统计性能）
-        sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_request_logs_status_ts ON request_logs(status_code, timestamp);"#)
-            .execute(&pool)
-            .await
-            .context("创建 request_logs.status_code+timestamp 索引失败")?;
-
-        sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_request_logs_route_ts ON request_logs(matched_route_id, timestamp);"#)
-            .execute(&pool)
+        // 写池只有一个连接，所有写入天然串行，不会互相争抢写锁
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(opt.clone())
             .await
-            .context("创建 request_logs.matched_route_id+timestamp 索引失败")?;
+            .with_context(|| format!("连接数据库失败: {}", path.display()))?;
 
-        sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_request_logs_client_ip_ts ON request_logs(client_ip, timestamp);"#)
-            .execute(&pool)
-            .await
-            .context("创建 request_logs.client_ip+timestamp 索引失败")?;
+        migrate_schema(&pool).await?;
 
-        // request_path/upstream 常用于 LIKE/分组，索引对 LIKE %...% 帮助有限，但对分组与前缀匹配仍有收益
-        sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_request_logs_path_ts ON request_logs(request_path, timestamp);"#)
-            .execute(&pool)
+        // 读池：WAL 下读不阻塞写，重查询不会拖慢批量写入
+        let read_pool = SqlitePoolOptions::new()
+            .max_connections(DB_READ_POOL_SIZE)
+            .connect_with(opt.read_only(true))
             .await
-            .context("创建 request_logs.request_path+timestamp 索引失败")?;
-
-        sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_request_logs_upstream_ts ON request_logs(upstream, timestamp);"#)
-            .execute(&pool)
-            .await
-            .context("创建 request_logs.upstream+timestamp 索引失败")?;
-
-        // 系统指标历史表
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS system_metrics (
-              id INTEGER PRIMARY KEY AUTOINCREMENT,
-              timestamp INTEGER NOT NULL,
-              cpu_usage_percent REAL NOT NULL,
-              load1 REAL NOT NULL,
-              load5 REAL NOT NULL,
-              load15 REAL NOT NULL,
-              mem_total_bytes INTEGER NOT NULL,
-              mem_available_bytes INTEGER NOT NULL,
-              mem_used_bytes INTEGER NOT NULL,
-              mem_used_percent REAL NOT NULL,
-              swap_total_bytes INTEGER NOT NULL,
-              swap_free_bytes INTEGER NOT NULL,
-              swap_used_bytes INTEGER NOT NULL,
-              swap_used_percent REAL NOT NULL,
-              net_rx_bytes INTEGER NOT NULL,
-              net_tx_bytes INTEGER NOT NULL,
-              net_rx_bps REAL NOT NULL,
-              net_tx_bps REAL NOT NULL,
-              disk_read_bytes INTEGER NOT NULL,
-              disk_write_bytes INTEGER NOT NULL,
-              disk_read_bps REAL NOT NULL,
-              disk_write_bps REAL NOT NULL,
-              tcp_established INTEGER NOT NULL,
-              tcp_time_wait INTEGER NOT NULL,
-              tcp_close_wait INTEGER NOT NULL,
-              process_count INTEGER NOT NULL,
-              fd_used INTEGER NOT NULL,
-              fd_max INTEGER NOT NULL,
-              fd_usage_percent REAL NOT NULL,
-              procs_running INTEGER NOT NULL,
-              procs_blocked INTEGER NOT NULL,
-              context_switches INTEGER NOT NULL,
-              processes_forked_total INTEGER NOT NULL,
-              uptime_seconds REAL NOT NULL
-            );
-            "#,
-        )
-        .execute(&pool)
-        .await
-        .context("创建 system_metrics 表失败")?;
-
-        sqlx::query(
-            r#"CREATE INDEX IF NOT EXISTS idx_system_metrics_ts ON system_metrics(timestamp);"#,
-        )
-        .execute(&pool)
-        .await
-        .context("创建 system_metrics.timestamp 索引失败")?;
-
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS blacklist (
-              id INTEGER PRIMARY KEY AUTOINCREMENT,
-              ip TEXT NOT NULL UNIQUE,
-              reason TEXT,
-              expires_at INTEGER NOT NULL,
-              created_at INTEGER NOT NULL
-            );
-            "#,
-        )
-        .execute(&pool)
-        .await
-        .context("创建 blacklist 表失败")?;
-
-        refresh_blacklist_cache_internal(&pool).await.ok();
+            .with_context(|| format!("连接数据库失败: {}", path.display()))?;
 
-        *DB_POOL.write() = Some(Arc::new(pool));
+        let pool = Arc::new(pool);
+        let read_pool = Arc::new(read_pool);
+        let backend = SqliteBackend {
+            write: pool.clone(),
+            read: read_pool.clone(),
+        };
+        refresh_blacklist_cache_internal(&backend).await.ok();
+
+        let backend: Arc<dyn MetricsBackend> = Arc::new(backend);
+        *DB_POOL.write() = Some(pool.clone());
+        *DB_READ_POOL.write() = Some(read_pool);
+        *DB_BACKEND.write() = Some(backend.clone());
         *DB_PATH.write() = path.to_string_lossy().to_string();
         *DB_ERROR.write() = None;
 
+        tauri::async_runtime::spawn(run_request_log_rollups(backend));
+        tauri::async_runtime::spawn(backfill_request_logs_fts(pool));
+
         Ok(())
     }
     .await;
@@ -382,7 +523,13 @@ pub async fn init_db(db_path: String) -> Result<()> {
 pub struct MetricsDBStatus {
     pub enabled: bool,
     pub initialized: bool,
+    /// sqlite / postgres
+    #[serde(default)]
+    pub backend: String,
     pub path: String,
+    /// 是否处于便携模式（配置与默认数据库位于可执行文件目录）
+    #[serde(default)]
+    pub portable: bool,
     pub error: Option<String>,
     pub file_exists: bool,
     pub dir_exists: bool,
@@ -396,6 +543,45 @@ pub struct MetricsDBStatus {
     pub request_logs_max_ts: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub db_file_size_bytes: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_purge_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_purge_deleted_rows: Option<u64>,
+    /// 自启动以来未能写入的请求日志条数
+    #[serde(default)]
+    pub request_logs_dropped: u64,
+    /// 自启动以来按抽样配置未落库的请求日志条数（不计入 request_logs_dropped）
+    #[serde(default)]
+    pub request_logs_sampled_out: u64,
+    /// 成功请求的落库比例，未开启抽样时为 None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_sampling_rate: Option<f64>,
+    /// 新写入日志的 IP 脱敏方式：off / truncate / hash
+    #[serde(default)]
+    pub ip_anonymization: String,
+    /// 实时面板是否同样脱敏
+    #[serde(default)]
+    pub ip_anonymization_realtime: bool,
+    /// 最近一次成功写入请求日志的时间（unix 秒）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_flush_at: Option<i64>,
+    /// 请求日志写入队列中等待落库的条数，写入任务未启动时为 None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_log_queue_depth: Option<u64>,
+    /// 数据库不可用时是否正在自动重连
+    #[serde(default)]
+    pub recovery_retrying: bool,
+    #[serde(default)]
+    pub recovery_consecutive_failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_next_attempt_at: Option<i64>,
+
+    // --- 实时检查（仅 get_metrics_db_status_detail 填充）---
+    /// SELECT 1 是否成功
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub db_reachable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blacklist_count: Option<i64>,
 
     // --- SQLite 参数（通过 PRAGMA 读取）---
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -425,7 +611,10 @@ pub struct MetricsDBStatus {
 }
 
 pub fn get_metrics_db_status() -> MetricsDBStatus {
-    let initialized = DB_POOL.read().is_some();
+    let backend = db_backend();
+    let initialized = backend.is_some();
+    let backend_name = backend.map_or("sqlite", |b| b.name());
+    let is_postgres = backend_name == "postgres";
     let path = DB_PATH.read().clone();
 
     let mut file_exists = false;
@@ -433,7 +622,9 @@ pub fn get_metrics_db_status() -> MetricsDBStatus {
     let mut dir_writable = false;
     let mut message: Option<String> = None;
 
-    if !path.is_empty() {
+    if is_postgres {
+        message = Some("Postgres 后端：max_db_size_mb 体积上限仅支持 SQLite".to_string());
+    } else if !path.is_empty() {
         let p = PathBuf::from(&path);
         if let Some(dir) = p.parent() {
             dir_exists = dir.exists();
@@ -452,7 +643,24 @@ pub fn get_metrics_db_status() -> MetricsDBStatus {
         }
     }
 
-    let request_logs_count: Option<i64> = None;
+    let recovery = db_recovery_state();
+    if recovery.retrying {
+        message = Some("数据库暂不可用，正在自动重连，期间日志保留在写入队列中".to_string());
+    }
+
+    let anonymization = crate::config::metrics_ip_anonymization();
+    if anonymization.is_some() && message.is_none() {
+        message = Some("IP 脱敏仅作用于开启后写入的日志，已有记录保持原样".to_string());
+    }
+    let ip_anonymization = match anonymization {
+        None => "off",
+        Some((crate::config::IpAnonymizeMode::Truncate, _)) => "truncate",
+        Some((crate::config::IpAnonymizeMode::Hash, _)) => "hash",
+    };
+
+    // 同步接口不查库，行数取自最近一次保留清理的结果
+    let last_purge = *DB_LAST_PURGE.read();
+    let request_logs_count: Option<i64> = last_purge.and_then(|p| p.remaining_request_logs);
     let request_logs_min_ts: Option<i64> = None;
     let request_logs_max_ts: Option<i64> = None;
 
@@ -469,7 +677,9 @@ pub fn get_metrics_db_status() -> MetricsDBStatus {
     MetricsDBStatus {
         enabled: initialized,
         initialized,
+        backend: backend_name.to_string(),
         path,
+        portable: crate::app_paths::is_portable(),
         error: DB_ERROR.read().clone(),
         file_exists,
         dir_exists,
@@ -479,6 +689,23 @@ pub fn get_metrics_db_status() -> MetricsDBStatus {
         request_logs_min_ts,
         request_logs_max_ts,
         db_file_size_bytes,
+        last_purge_at: last_purge.map(|p| p.at),
+        last_purge_deleted_rows: last_purge.map(|p| p.deleted_rows),
+        request_logs_dropped: REQUEST_LOG_DROPPED.load(Ordering::Relaxed),
+        request_logs_sampled_out: REQUEST_LOG_SAMPLED_OUT.load(Ordering::Relaxed),
+        log_sampling_rate: crate::config::metrics_log_sampling().map(|s| s.success_rate),
+        ip_anonymization: ip_anonymization.to_string(),
+        ip_anonymization_realtime: anonymization.is_some_and(|(_, realtime)| realtime),
+        last_flush_at: Some(REQUEST_LOG_LAST_FLUSH_AT.load(Ordering::Relaxed)).filter(|ts| *ts > 0),
+        request_log_queue_depth: REQUEST_LOG_TX
+            .read()
+            .as_ref()
+            .map(|tx| (tx.max_capacity() - tx.capacity()) as u64),
+        recovery_retrying: recovery.retrying,
+        recovery_consecutive_failures: recovery.consecutive_failures,
+        recovery_next_attempt_at: recovery.next_attempt_at.filter(|_| recovery.retrying),
+        db_reachable: None,
+        blacklist_count: None,
         sqlite_version: None,
         journal_mode: None,
         synchronous: None,
@@ -499,58 +726,77 @@ pub async fn get_metrics_db_status_detail() -> Result<MetricsDBStatus> {
         return Ok(base);
     }
 
-    let Some(pool) = pool() else {
+    let Some(backend) = db_backend() else {
         return Ok(base);
     };
+    backend.status_detail(base).await
+}
+
+pub(super) async fn status_detail_sqlite(
+    pool: &SqlitePool,
+    base: MetricsDBStatus,
+) -> Result<MetricsDBStatus> {
+    if let Err(e) = sqlx::query("SELECT 1").execute(pool).await {
+        return Ok(MetricsDBStatus {
+            db_reachable: Some(false),
+            error: Some(format!("数据库连接检查失败: {}", e)),
+            ..base
+        });
+    }
 
     let (cnt, min_ts, max_ts) = sqlx::query_as::<_, (i64, Option<i64>, Option<i64>)>(
         "SELECT COUNT(1) AS cnt, MIN(timestamp) AS min_ts, MAX(timestamp) AS max_ts FROM request_logs",
     )
-    .fetch_one(&*pool)
+    .fetch_one(pool)
     .await?;
 
+    let blacklist_count: Option<i64> = sqlx::query_scalar("SELECT COUNT(1) FROM blacklist")
+        .fetch_one(pool)
+        .await
+        .ok();
+
     let sqlite_version: Option<String> = sqlx::query_scalar("SELECT sqlite_version()")
-        .fetch_one(&*pool)
+        .fetch_one(pool)
         .await
         .ok();
 
     let journal_mode: Option<String> = sqlx::query_scalar("PRAGMA journal_mode")
-        .fetch_one(&*pool)
+        .fetch_one(pool)
         .await
         .ok();
 
     let synchronous: Option<String> = sqlx::query_scalar("PRAGMA synchronous")
-        .fetch_one(&*pool)
+        .fetch_one(pool)
         .await
         .ok();
 
     let wal_autocheckpoint: Option<i64> = sqlx::query_scalar("PRAGMA wal_autocheckpoint")
-        .fetch_one(&*pool)
+        .fetch_one(pool)
         .await
         .ok();
 
     let page_size: Option<i64> = sqlx::query_scalar("PRAGMA page_size")
-        .fetch_one(&*pool)
+        .fetch_one(pool)
         .await
         .ok();
 
     let page_count: Option<i64> = sqlx::query_scalar("PRAGMA page_count")
-        .fetch_one(&*pool)
+        .fetch_one(pool)
         .await
         .ok();
 
     let freelist_count: Option<i64> = sqlx::query_scalar("PRAGMA freelist_count")
-        .fetch_one(&*pool)
+        .fetch_one(pool)
         .await
         .ok();
 
     let cache_size: Option<i64> = sqlx::query_scalar("PRAGMA cache_size")
-        .fetch_one(&*pool)
+        .fetch_one(pool)
         .await
         .ok();
 
     let busy_timeout_ms: Option<i64> = sqlx::query_scalar("PRAGMA busy_timeout")
-        .fetch_one(&*pool)
+        .fetch_one(pool)
         .await
         .ok();
 
@@ -574,6 +820,8 @@ pub async fn get_metrics_db_status_detail() -> Result<MetricsDBStatus> {
         request_logs_count: Some(cnt),
         request_logs_min_ts: min_ts,
         request_logs_max_ts: max_ts,
+        db_reachable: Some(true),
+        blacklist_count,
         sqlite_version,
         journal_mode,
         synchronous,
@@ -589,7 +837,172 @@ pub async fn get_metrics_db_status_detail() -> Result<MetricsDBStatus> {
     })
 }
 
-pub async fn test_metrics_db_connection(db_path: String) -> Result<(bool, String)> {
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsDBTableStats {
+    pub name: String,
+    pub row_count: i64,
+    pub min_ts: Option<i64>,
+    pub max_ts: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct MetricsDBStats {
+    pub initialized: bool,
+    pub path: String,
+    pub file_size_bytes: i64,
+    pub wal_file_size_bytes: i64,
+    pub page_size: i64,
+    pub page_count: i64,
+    pub freelist_count: i64,
+    /// VACUUM 预计可回收的字节数（freelist_count * page_size）
+    pub reclaimable_bytes: i64,
+    pub tables: Vec<MetricsDBTableStats>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VacuumResult {
+    pub size_before_bytes: i64,
+    pub size_after_bytes: i64,
+    pub duration_ms: f64,
+}
+
+// (表名, 时间列)
+const STATS_TABLES: [(&str, &str); 7] = [
+    ("request_logs", "timestamp"),
+    ("request_logs_rollup_1m", "bucket"),
+    ("request_logs_rollup_1h", "bucket"),
+    ("ws_sessions", "timestamp"),
+    ("stream_logs", "timestamp"),
+    ("system_metrics", "timestamp"),
+    ("blacklist", "created_at"),
+];
+
+pub(super) fn file_len(path: &str) -> i64 {
+    std::fs::metadata(path).map(|m| m.len() as i64).unwrap_or(0)
+}
+
+/// 数据库未初始化时返回 initialized=false 的空统计，而不是报错
+pub async fn get_metrics_db_stats() -> Result<MetricsDBStats> {
+    let path = DB_PATH.read().clone();
+    let Some(backend) = db_backend() else {
+        return Ok(MetricsDBStats {
+            path,
+            ..Default::default()
+        });
+    };
+    backend.db_stats(path).await
+}
+
+pub(super) async fn db_stats_sqlite(pool: &SqlitePool, path: String) -> Result<MetricsDBStats> {
+    let mut tables = Vec::with_capacity(STATS_TABLES.len());
+    for (name, ts_col) in STATS_TABLES {
+        let (row_count, min_ts, max_ts) = sqlx::query_as::<_, (i64, Option<i64>, Option<i64>)>(
+            &format!("SELECT COUNT(1), MIN({ts_col}), MAX({ts_col}) FROM {name}"),
+        )
+        .fetch_one(pool)
+        .await
+        .with_context(|| format!("统计 {} 失败", name))?;
+        tables.push(MetricsDBTableStats {
+            name: name.to_string(),
+            row_count,
+            min_ts,
+            max_ts,
+        });
+    }
+
+    let page_size: i64 = sqlx::query_scalar("PRAGMA page_size")
+        .fetch_one(pool)
+        .await
+        .unwrap_or(0);
+    let page_count: i64 = sqlx::query_scalar("PRAGMA page_count")
+        .fetch_one(pool)
+        .await
+        .unwrap_or(0);
+    let freelist_count: i64 = sqlx::query_scalar("PRAGMA freelist_count")
+        .fetch_one(pool)
+        .await
+        .unwrap_or(0);
+
+    Ok(MetricsDBStats {
+        initialized: true,
+        file_size_bytes: file_len(&path),
+        wal_file_size_bytes: file_len(&format!("{}-wal", path)),
+        path,
+        page_size,
+        page_count,
+        freelist_count,
+        reclaimable_bytes: freelist_count * page_size,
+        tables,
+    })
+}
+
+/// 手动 VACUUM；同一时间只允许一个
+pub async fn vacuum_metrics_db() -> Result<VacuumResult> {
+    let Some(backend) = db_backend() else {
+        return Err(anyhow!("数据库未初始化"));
+    };
+    if DB_VACUUM_RUNNING.swap(true, Ordering::SeqCst) {
+        return Err(anyhow!("VACUUM 正在执行中"));
+    }
+
+    let size_before_bytes = backend.storage_size_bytes().await.unwrap_or(0);
+    let started = Instant::now();
+    crate::proxy::logging::push_log_line(format!(
+        "Metrics DB VACUUM started ({} bytes)",
+        size_before_bytes
+    ));
+
+    let result = backend.vacuum().await;
+    DB_VACUUM_RUNNING.store(false, Ordering::SeqCst);
+
+    if let Err(e) = result {
+        crate::proxy::logging::push_log_line(format!("Metrics DB VACUUM failed: {:#}", e));
+        return Err(e.context("执行 VACUUM 失败"));
+    }
+    *DB_LAST_VACUUM_AT.write() = Some(Instant::now());
+
+    let size_after_bytes = backend.storage_size_bytes().await.unwrap_or(0);
+    let duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+    crate::proxy::logging::push_log_line(format!(
+        "Metrics DB VACUUM finished in {:.0}ms: {} -> {} bytes",
+        duration_ms, size_before_bytes, size_after_bytes
+    ));
+
+    Ok(VacuumResult {
+        size_before_bytes,
+        size_after_bytes,
+        duration_ms,
+    })
+}
+
+/// SQLite：持有写入闸门期间批量写入暂停（日志暂存在队列中），完成后自动恢复
+pub(super) async fn vacuum_sqlite(pool: &SqlitePool) -> Result<()> {
+    let _gate = DB_WRITE_GATE.write().await;
+    sqlx::query("VACUUM").execute(pool).await?;
+    let _ = sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+        .execute(pool)
+        .await;
+    Ok(())
+}
+
+/// backend/url 为空时回落到当前配置中的后端
+pub async fn test_metrics_db_connection(
+    db_path: String,
+    backend: Option<String>,
+    url: Option<String>,
+) -> Result<(bool, String)> {
+    let configured = crate::config::get_config().metrics_storage;
+    let backend = backend.or_else(|| configured.as_ref().and_then(|m| m.backend.clone()));
+    if is_postgres_backend(backend.as_deref()) {
+        let url = url
+            .filter(|u| !u.trim().is_empty())
+            .or_else(|| configured.and_then(|m| m.url))
+            .ok_or_else(|| anyhow!("未配置 Postgres 连接地址"))?;
+        let url = crate::secrets::resolve(&url)?;
+        PostgresBackend::connect(url.trim()).await?.ping().await?;
+        return Ok((true, "OK".to_string()));
+    }
+
     let path = resolve_db_path(db_path)?;
     if let Some(dir) = path.parent() {
         tokio::fs::create_dir_all(dir)
@@ -611,18 +1024,15 @@ pub async fn test_metrics_db_connection(db_path: String) -> Result<(bool, String
 }
 
 pub async fn refresh_blacklist_cache() -> Result<()> {
-    let Some(pool) = pool() else { return Ok(()) };
-    refresh_blacklist_cache_internal(&pool).await
+    let Some(backend) = db_backend() else {
+        return Ok(());
+    };
+    refresh_blacklist_cache_internal(&*backend).await
 }
 
-pub(super) async fn refresh_blacklist_cache_internal(pool: &SqlitePool) -> Result<()> {
+pub(super) async fn refresh_blacklist_cache_internal(backend: &dyn MetricsBackend) -> Result<()> {
     let now = chrono::Utc::now().timestamp();
-    let rows = sqlx::query_as::<_, (String, i64)>(
-        "SELECT ip, expires_at FROM blacklist WHERE expires_at=0 OR expires_at>?",
-    )
-    .bind(now)
-    .fetch_all(pool)
-    .await?;
+    let rows = backend.active_blacklist(now).await?;
 
     let mut cache = BLACKLIST_CACHE.write();
     cache.clear();
@@ -637,7 +1047,7 @@ pub async fn add_blacklist_entry(
     reason: String,
     duration_seconds: i32,
 ) -> Result<BlacklistEntry> {
-    let Some(pool) = pool() else {
+    let Some(backend) = db_backend() else {
         return Err(anyhow!("数据库未初始化"));
     };
 
@@ -648,15 +1058,9 @@ pub async fn add_blacklist_entry(
         now + duration_seconds as i64
     };
 
-    let rec = sqlx::query_as::<_, BlacklistEntry>(
-        "INSERT OR REPLACE INTO blacklist(ip, reason, expires_at, created_at) VALUES(?,?,?,?) RETURNING id, ip, reason, expires_at, created_at",
-    )
-    .bind(ip)
-    .bind(Some(reason))
-    .bind(expires_at)
-    .bind(now)
-    .fetch_one(&*pool)
-    .await?;
+    let rec = backend
+        .upsert_blacklist(&ip, &reason, expires_at, now)
+        .await?;
 
     BLACKLIST_CACHE
         .write()
@@ -666,32 +1070,252 @@ pub async fn add_blacklist_entry(
 }
 
 pub async fn remove_blacklist_entry(ip: String) -> Result<()> {
-    let Some(pool) = pool() else { return Ok(()) };
+    let Some(backend) = db_backend() else {
+        return Ok(());
+    };
 
-    sqlx::query("DELETE FROM blacklist WHERE ip=?")
-        .bind(&ip)
-        .execute(&*pool)
-        .await?;
+    backend.delete_blacklist(&ip).await?;
 
     BLACKLIST_CACHE.write().remove(&normalize_ip_key(&ip));
     Ok(())
 }
 
 pub async fn get_blacklist_entries() -> Result<Vec<BlacklistEntry>> {
-    let Some(pool) = pool() else {
+    let Some(backend) = db_backend() else {
         return Ok(vec![]);
     };
 
-    let rows = sqlx::query_as::<_, BlacklistEntry>(
-        "SELECT id, ip, reason, expires_at, created_at FROM blacklist ORDER BY created_at DESC",
-    )
-    .fetch_all(&*pool)
-    .await?;
+    backend.list_blacklist().await
+}
 
-    Ok(rows)
+pub async fn insert_ws_session(mut rec: WsSessionInsert) -> Result<()> {
+    let Some(backend) = db_backend() else {
+        return Ok(());
+    };
+    if let Some((mode, _)) = crate::config::metrics_ip_anonymization() {
+        rec.client_ip = super::anonymize::anonymize_ip(&rec.client_ip, mode);
+    }
+    let _gate = db_write_gate().await;
+
+    backend.insert_ws_session(&rec).await
+}
+
+/// 系统指标批量落库；数据库未初始化时直接丢弃
+pub(crate) async fn insert_system_metrics(points: &[SystemMetricsPoint]) -> Result<()> {
+    let Some(backend) = db_backend() else {
+        return Ok(());
+    };
+    let _gate = db_write_gate().await;
+    backend.insert_system_metrics(points).await
+}
+
+/// 删除 cutoff 之前的系统指标；数据库未初始化时返回 0
+pub(crate) async fn purge_system_metrics(cutoff: i64) -> Result<u64> {
+    let Some(backend) = db_backend() else {
+        return Ok(0);
+    };
+    let _gate = db_write_gate().await;
+    backend.purge_system_metrics(cutoff).await
+}
+
+/// 按粒度聚合的系统指标历史；数据库未初始化时返回 None
+pub(crate) async fn system_metrics_history(
+    start: i64,
+    end: i64,
+    granularity: i64,
+) -> Result<Option<Vec<HistoricalRow>>> {
+    let Some(backend) = db_backend() else {
+        return Ok(None);
+    };
+    backend
+        .system_metrics_history(start, end, granularity)
+        .await
+        .map(Some)
 }
 
 // 请求日志写入队列
 pub(super) static REQUEST_LOG_TX: Lazy<
     RwLock<Option<tokio::sync::mpsc::Sender<RequestLogInsert>>>,
 > = Lazy::new(|| RwLock::new(None));
+
+// stream 会话日志写入队列
+pub(super) static STREAM_LOG_TX: Lazy<RwLock<Option<tokio::sync::mpsc::Sender<StreamLogInsert>>>> =
+    Lazy::new(|| RwLock::new(None));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_log(i: i64) -> RequestLogInsert {
+        RequestLogInsert {
+            timestamp: chrono::Utc::now().timestamp(),
+            listen_addr: ":18443".to_string(),
+            client_ip: "10.0.0.1".to_string(),
+            remote_ip: "10.0.0.1".to_string(),
+            method: "GET".to_string(),
+            request_path: format!("/flood/{}", i % 50),
+            request_host: "example.com".to_string(),
+            status_code: 200,
+            upstream: "http://127.0.0.1:8080".to_string(),
+            latency_ms: 1.0,
+            guard_ms: 0.0,
+            prepare_ms: 0.0,
+            upstream_ms: 0.5,
+            user_agent: "test".to_string(),
+            referer: "-".to_string(),
+            matched_route_id: "r1".to_string(),
+            bytes_sent: 128,
+            bytes_received: 0,
+            country: String::new(),
+            asn: String::new(),
+            sample_rate: 1.0,
+        }
+    }
+
+    // 读池与单连接写池分离：持续查询期间批量写入不应失败或丢数据
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn queries_do_not_block_request_log_writes() {
+        let dir = std::env::temp_dir().join(format!("sslpm-metrics-{}", std::process::id()));
+        let path = dir.join("metrics.db");
+
+        // 全局数据库只在本测试中初始化，顺带覆盖未初始化/已初始化两种状态的详情接口
+        let status = get_metrics_db_status_detail().await.unwrap();
+        assert!(!status.initialized);
+        assert_eq!(status.db_reachable, None);
+        assert_eq!(status.request_logs_count, None);
+
+        init_db(path.to_string_lossy().to_string()).await.unwrap();
+        init_request_log_writer().await;
+
+        const TOTAL: i64 = 4_000;
+        let now = chrono::Utc::now().timestamp();
+        let reader = tokio::spawn(async move {
+            for _ in 0..50 {
+                query_request_logs(QueryRequestLogsRequest {
+                    start_time: now - 60,
+                    end_time: now + 60,
+                    listen_addr: None,
+                    upstream: None,
+                    request_path: None,
+                    client_ip: None,
+                    status_code: None,
+                    method: None,
+                    page: 1,
+                    page_size: 100,
+                    matched_route_id: None,
+                    sort_by: Some("latency_ms".to_string()),
+                    sort_order: Some("desc".to_string()),
+                    request_host: None,
+                    min_latency_ms: None,
+                    max_latency_ms: None,
+                    status_class: None,
+                    country: None,
+                    text_match: None,
+                    search: None,
+                    cursor: None,
+                    include_total: false,
+                })
+                .await
+                .unwrap();
+            }
+        });
+
+        for i in 0..TOTAL {
+            try_enqueue_request_log(sample_log(i));
+        }
+        reader.await.unwrap();
+
+        let read_pool = DB_READ_POOL.read().clone().unwrap();
+        let mut count = 0i64;
+        for _ in 0..100 {
+            count = sqlx::query_scalar("SELECT COUNT(1) FROM request_logs")
+                .fetch_one(&*read_pool)
+                .await
+                .unwrap();
+            if count >= TOTAL {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        let status = get_metrics_db_status_detail().await;
+
+        deinit_db();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(count, TOTAL);
+
+        let status = status.unwrap();
+        assert!(status.initialized);
+        assert_eq!(status.db_reachable, Some(true));
+        assert_eq!(status.request_logs_count, Some(TOTAL));
+        assert_eq!(status.blacklist_count, Some(0));
+        assert!(status.last_flush_at.is_some());
+        assert!(status.request_log_queue_depth.is_some());
+    }
+
+    #[tokio::test]
+    async fn clear_request_logs_filters_and_guards_full_range() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        migrate_schema(&pool).await.unwrap();
+        let now = chrono::Utc::now().timestamp();
+        let mut rows: Vec<RequestLogInsert> = (0..10).map(sample_log).collect();
+        for (i, row) in rows.iter_mut().enumerate() {
+            row.timestamp = now - i as i64;
+            if i % 2 == 0 {
+                row.client_ip = "10.0.0.2".to_string();
+            }
+        }
+        super::super::backend::request_log_insert_builder::<sqlx::Sqlite>(&rows)
+            .build()
+            .execute(&pool)
+            .await
+            .unwrap();
+        let pool = Arc::new(pool);
+        let backend = SqliteBackend {
+            write: pool.clone(),
+            read: pool,
+        };
+
+        let mut req = ClearRequestLogsRequest {
+            start_time: now - 100,
+            end_time: now + 100,
+            listen_addr: None,
+            client_ip: None,
+            confirm_full_range: false,
+            vacuum: false,
+        };
+        assert!(delete_request_logs_in_batches(&backend, &req)
+            .await
+            .is_err());
+
+        req.client_ip = Some("10.0.0.2".to_string());
+        assert_eq!(
+            delete_request_logs_in_batches(&backend, &req)
+                .await
+                .unwrap(),
+            5
+        );
+
+        req.client_ip = None;
+        req.start_time = now - 2;
+        assert_eq!(
+            delete_request_logs_in_batches(&backend, &req)
+                .await
+                .unwrap(),
+            1
+        );
+
+        req.start_time = now - 100;
+        req.confirm_full_range = true;
+        assert_eq!(
+            delete_request_logs_in_batches(&backend, &req)
+                .await
+                .unwrap(),
+            4
+        );
+    }
+}
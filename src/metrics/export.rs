@@ -0,0 +1,180 @@
+use super::*;
+use std::borrow::Cow;
+use std::io::Write;
+use std::path::Path;
+
+const EXPORT_DEFAULT_MAX_ROWS: u64 = 100_000;
+const EXPORT_PAGE_SIZE: i32 = 200;
+
+const CSV_HEADER: &str = "id,timestamp,listen_addr,client_ip,remote_ip,method,request_path,request_host,status_code,upstream,latency_ms,guard_ms,prepare_ms,upstream_ms,user_agent,referer,matched_route_id,bytes_sent";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "jsonl" | "ndjson" => Ok(Self::Jsonl),
+            other => Err(anyhow!("unsupported export format: {}", other)),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Jsonl => "jsonl",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportRequestLogsResult {
+    /// 实际写出的行数；超出上限未导出时为命中的总行数
+    pub rows: i64,
+    /// 取消保存或超出上限时为 None
+    pub path: Option<String>,
+    pub max_rows: u64,
+    /// 命中行数超过 max_rows 且未传 force
+    pub over_limit: bool,
+}
+
+pub fn export_max_rows() -> u64 {
+    crate::config::metrics_export_max_rows()
+        .filter(|v| *v > 0)
+        .unwrap_or(EXPORT_DEFAULT_MAX_ROWS)
+}
+
+/// 导出前统计命中行数，用于决定是否需要 force
+pub async fn count_request_logs_for_export(req: &QueryRequestLogsRequest) -> Result<i64> {
+    let Some(backend) = db_backend() else {
+        return Ok(0);
+    };
+    let mut req = req.clone();
+    req.cursor = Some(String::new());
+    req.include_total = true;
+    req.page_size = 1;
+    Ok(backend.query_request_logs(req).await?.total)
+}
+
+/// 按游标分批读取并写入文件，内存中只保留一页
+pub async fn export_request_logs_to_file(
+    req: QueryRequestLogsRequest,
+    format: ExportFormat,
+    path: &Path,
+) -> Result<i64> {
+    let Some(backend) = db_backend() else {
+        return Err(anyhow!("metrics database is not initialized"));
+    };
+
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("创建导出文件失败: {}", path.display()))?;
+    let mut out = std::io::BufWriter::new(file);
+    if format == ExportFormat::Csv {
+        writeln!(out, "{}", CSV_HEADER)?;
+    }
+
+    let mut req = req;
+    req.cursor = Some(String::new());
+    req.include_total = false;
+    req.page_size = EXPORT_PAGE_SIZE;
+
+    let mut rows = 0i64;
+    loop {
+        let resp = backend.query_request_logs(req.clone()).await?;
+        for log in resp.logs.iter() {
+            match format {
+                ExportFormat::Csv => write_csv_row(&mut out, log)?,
+                ExportFormat::Jsonl => {
+                    serde_json::to_writer(&mut out, log)?;
+                    out.write_all(b"\n")?;
+                }
+            }
+        }
+        rows += resp.logs.len() as i64;
+        match resp.next_cursor {
+            Some(c) => req.cursor = Some(c),
+            None => break,
+        }
+    }
+    out.flush()
+        .with_context(|| format!("写入导出文件失败: {}", path.display()))?;
+    Ok(rows)
+}
+
+/// RFC 4180：含逗号、引号或换行的字段加引号，内部引号加倍
+fn csv_field(s: &str) -> Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", s.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+fn write_csv_row(out: &mut impl Write, log: &RequestLog) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+        log.id,
+        log.timestamp,
+        csv_field(&log.listen_addr),
+        csv_field(&log.client_ip),
+        csv_field(&log.remote_ip),
+        csv_field(&log.method),
+        csv_field(&log.request_path),
+        csv_field(&log.request_host),
+        log.status_code,
+        csv_field(&log.upstream),
+        log.latency_ms,
+        log.guard_ms,
+        log.prepare_ms,
+        log.upstream_ms,
+        csv_field(&log.user_agent),
+        csv_field(&log.referer),
+        csv_field(&log.matched_route_id),
+        log.bytes_sent
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_quotes_commas_quotes_and_newlines() {
+        let log = RequestLog {
+            id: 7,
+            timestamp: 1_700_000_000,
+            listen_addr: ":443".to_string(),
+            client_ip: "10.0.0.1".to_string(),
+            remote_ip: "10.0.0.1".to_string(),
+            method: "GET".to_string(),
+            request_path: "/a,b".to_string(),
+            request_host: "example.com".to_string(),
+            status_code: 200,
+            upstream: "http://127.0.0.1:8080".to_string(),
+            latency_ms: 1.5,
+            guard_ms: 0.0,
+            prepare_ms: 0.0,
+            upstream_ms: 1.0,
+            user_agent: "Mozilla/5.0 (X11; \"quoted\")".to_string(),
+            referer: "line1\nline2".to_string(),
+            matched_route_id: "r1".to_string(),
+            bytes_sent: 42,
+            country: "US".to_string(),
+            asn: String::new(),
+            sample_rate: 1.0,
+        };
+        let mut buf = Vec::new();
+        write_csv_row(&mut buf, &log).unwrap();
+        let line = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            line,
+            "7,1700000000,:443,10.0.0.1,10.0.0.1,GET,\"/a,b\",example.com,200,http://127.0.0.1:8080,1.5,0,0,1,\"Mozilla/5.0 (X11; \"\"quoted\"\")\",\"line1\nline2\",r1,42\n"
+        );
+        assert_eq!(CSV_HEADER.split(',').count(), 18);
+    }
+}
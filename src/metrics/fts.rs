@@ -0,0 +1,97 @@
+use super::*;
+use sqlx::SqliteConnection;
+
+// 请求日志全文索引（FTS5 trigram，contentless，rowid = request_logs.id）。
+// 新行由批量写入在同一事务内补入；迁移前已有的行由后台任务按 id 从新到旧回填，
+// request_logs_fts_state.backfill_below 以下的行尚未建索引，检索时对这部分退回 LIKE。
+
+const FTS_BACKFILL_BATCH: i64 = 5_000;
+const FTS_BACKFILL_PAUSE: Duration = Duration::from_millis(50);
+// trigram 分词至少需要 3 个字符才能命中
+const FTS_MIN_PATTERN_CHARS: usize = 3;
+
+const FTS_INSERT_SELECT: &str = "INSERT INTO request_logs_fts(rowid, request_path, request_host, user_agent, referer) SELECT id, request_path, request_host, user_agent, referer FROM request_logs";
+
+/// 为 id > after_id 的请求日志建立全文索引，需与插入在同一事务中调用
+pub(super) async fn index_request_logs_after(
+    conn: &mut SqliteConnection,
+    after_id: i64,
+) -> Result<()> {
+    sqlx::query(&format!("{FTS_INSERT_SELECT} WHERE id > ?"))
+        .bind(after_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// 回填进度；表不存在（未迁移）时返回 None
+pub(super) async fn fts_backfill_below(pool: &SqlitePool) -> Option<i64> {
+    sqlx::query_scalar("SELECT backfill_below FROM request_logs_fts_state WHERE id = 1")
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+}
+
+/// 转成 FTS5 短语查询；过短的模式无法走 trigram 索引，返回 None
+pub(super) fn fts_phrase(pattern: &str) -> Option<String> {
+    if pattern.chars().count() < FTS_MIN_PATTERN_CHARS {
+        return None;
+    }
+    Some(format!("\"{}\"", pattern.replace('"', "\"\"")))
+}
+
+/// 后台回填迁移前已有的请求日志；数据库被关闭或切换后自动退出
+pub(super) async fn backfill_request_logs_fts(pool: Arc<SqlitePool>) {
+    let mut indexed = 0u64;
+    loop {
+        if !db_pool().is_some_and(|p| Arc::ptr_eq(&p, &pool)) {
+            return;
+        }
+        let Some(below) = fts_backfill_below(&pool).await.filter(|b| *b > 0) else {
+            break;
+        };
+
+        let result: Result<u64> = async {
+            let _gate = db_write_gate().await;
+            let mut tx = pool.begin().await?;
+            // 按实际存在的 id 取下一批，跳过保留策略删掉的空洞
+            let lo: i64 = sqlx::query_scalar(
+                "SELECT COALESCE(MIN(id), 0) FROM (SELECT id FROM request_logs WHERE id < ? ORDER BY id DESC LIMIT ?)",
+            )
+            .bind(below)
+            .bind(FTS_BACKFILL_BATCH)
+            .fetch_one(&mut *tx)
+            .await?;
+            let n = sqlx::query(&format!("{FTS_INSERT_SELECT} WHERE id >= ? AND id < ?"))
+                .bind(lo)
+                .bind(below)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            sqlx::query("UPDATE request_logs_fts_state SET backfill_below = ? WHERE id = 1")
+                .bind(lo)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            Ok(n)
+        }
+        .await;
+
+        match result {
+            Ok(n) => indexed += n,
+            Err(e) => {
+                eprintln!("Request log search index backfill failed: {}", e);
+                return;
+            }
+        }
+        tokio::time::sleep(FTS_BACKFILL_PAUSE).await;
+    }
+
+    if indexed > 0 {
+        crate::proxy::logging::push_log_line(format!(
+            "Request log search index built for {} existing rows",
+            indexed
+        ));
+    }
+}
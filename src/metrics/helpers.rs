@@ -43,3 +43,80 @@ pub fn normalize_upstream_for_top(upstream: &str) -> String {
     let host = s.split('/').next().unwrap_or(s);
     host.to_string()
 }
+
+const TOP_LABEL_MAX_LEN: usize = 160;
+
+/// UA 中的版本号统一替换为 `*`，让同一客户端的不同版本归为一组
+#[inline]
+pub fn normalize_user_agent_for_top(ua: &str) -> String {
+    let s = ua.trim();
+    if s.is_empty() || s == "-" {
+        return "(empty)".to_string();
+    }
+
+    let mut out = String::with_capacity(s.len().min(TOP_LABEL_MAX_LEN));
+    let mut in_version = false;
+    let mut prev = ' ';
+    for c in s.chars() {
+        let version_char = if in_version {
+            c.is_ascii_digit() || c == '.' || c == '_'
+        } else {
+            // 紧跟字母的数字（如 x64、Win64）不是版本号
+            c.is_ascii_digit() && !prev.is_ascii_alphanumeric()
+        };
+        if version_char {
+            if !in_version {
+                out.push('*');
+                in_version = true;
+            }
+        } else {
+            in_version = false;
+            out.push(c);
+        }
+        prev = c;
+        if out.len() >= TOP_LABEL_MAX_LEN {
+            break;
+        }
+    }
+    out
+}
+
+/// 去掉 query/fragment，只按来源页面分组
+#[inline]
+pub fn normalize_referer_for_top(referer: &str) -> String {
+    let s = referer.trim();
+    if s.is_empty() || s == "-" {
+        return "(direct)".to_string();
+    }
+    let s = s.split(['?', '#']).next().unwrap_or(s);
+    s.chars().take(TOP_LABEL_MAX_LEN).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_agent_versions_are_collapsed() {
+        let a = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) Chrome/120.0.6099.109 Safari/537.36";
+        let b = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) Chrome/121.0.6167.85 Safari/537.36";
+        assert_eq!(
+            normalize_user_agent_for_top(a),
+            "Mozilla/* (Windows NT *; Win64; x64) Chrome/* Safari/*"
+        );
+        assert_eq!(
+            normalize_user_agent_for_top(a),
+            normalize_user_agent_for_top(b)
+        );
+        assert_eq!(normalize_user_agent_for_top("-"), "(empty)");
+    }
+
+    #[test]
+    fn referer_query_is_stripped() {
+        assert_eq!(
+            normalize_referer_for_top("https://example.com/a?utm=1#top"),
+            "https://example.com/a"
+        );
+        assert_eq!(normalize_referer_for_top("-"), "(direct)");
+    }
+}
@@ -0,0 +1,149 @@
+// 对数分桶：相邻桶上限之比为 1.02，桶内取几何中点，相对误差约 1%
+const HIST_MIN_MS: f64 = 0.01;
+const HIST_GROWTH: f64 = 1.02;
+// 覆盖到约 1 小时（0.01ms * 1.02^1000 ≈ 4e6ms）
+const HIST_BUCKETS: usize = 1000;
+
+/// 固定分桶的耗时直方图，用于流式估算分位数，内存占用与行数无关
+#[derive(Debug, Clone)]
+pub(super) struct LatencyHistogram {
+    buckets: Box<[u64]>,
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; HIST_BUCKETS + 1].into_boxed_slice(),
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: 0.0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    #[inline]
+    fn bucket_index(v: f64) -> usize {
+        if v <= HIST_MIN_MS {
+            return 0;
+        }
+        let i = ((v / HIST_MIN_MS).ln() / HIST_GROWTH.ln()).ceil() as usize;
+        i.clamp(1, HIST_BUCKETS)
+    }
+
+    #[inline]
+    fn bucket_value(i: usize) -> f64 {
+        if i == 0 {
+            0.0
+        } else {
+            HIST_MIN_MS * HIST_GROWTH.powf(i as f64 - 0.5)
+        }
+    }
+
+    #[inline]
+    pub(super) fn add(&mut self, v: f64) {
+        if !v.is_finite() {
+            return;
+        }
+        let v = v.max(0.0);
+        self.buckets[Self::bucket_index(v)] += 1;
+        self.count += 1;
+        self.sum += v;
+        self.min = self.min.min(v);
+        self.max = self.max.max(v);
+    }
+
+    pub(super) fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub(super) fn avg(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum / self.count as f64
+        }
+    }
+
+    /// 与排序后取 round((n-1)*p) 下标的精确分位数口径一致
+    pub(super) fn percentile(&self, p: f64) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let rank = ((self.count - 1) as f64 * p.clamp(0.0, 1.0)).round() as u64;
+        let mut acc = 0u64;
+        for (i, c) in self.buckets.iter().enumerate() {
+            acc += *c;
+            if acc > rank {
+                return Self::bucket_value(i).clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exact_percentile(sorted: &[f64], p: f64) -> f64 {
+        let idx = ((sorted.len() - 1) as f64 * p).round() as usize;
+        sorted[idx.min(sorted.len() - 1)]
+    }
+
+    #[test]
+    fn percentiles_match_exact_within_error_bound() {
+        // 简单 LCG 生成长尾分布：大部分 1~50ms，少量 1~5s
+        let mut seed: u64 = 42;
+        let mut next = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let mut values = Vec::with_capacity(100_000);
+        let mut hist = LatencyHistogram::default();
+        for _ in 0..100_000 {
+            let u = next();
+            let v = if u < 0.97 {
+                1.0 + next() * 49.0
+            } else {
+                1000.0 + next() * 4000.0
+            };
+            values.push(v);
+            hist.add(v);
+        }
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        for p in [0.5, 0.9, 0.95, 0.99, 0.999] {
+            let exact = exact_percentile(&values, p);
+            let approx = hist.percentile(p);
+            let err = (approx - exact).abs() / exact;
+            assert!(
+                err < 0.02,
+                "p{} exact={} approx={} err={}",
+                p,
+                exact,
+                approx,
+                err
+            );
+        }
+        let exact_avg = values.iter().sum::<f64>() / values.len() as f64;
+        assert!((hist.avg() - exact_avg).abs() < 1e-6);
+    }
+
+    #[test]
+    fn empty_and_zero_values() {
+        let mut hist = LatencyHistogram::default();
+        assert_eq!(hist.percentile(0.99), 0.0);
+        hist.add(0.0);
+        hist.add(0.0);
+        assert_eq!(hist.percentile(0.5), 0.0);
+        assert_eq!(hist.count(), 2);
+    }
+}
@@ -0,0 +1,246 @@
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+use super::models::RequestLogInsert;
+use super::parse_status_class;
+
+/// 每个订阅每秒最多推送的记录数，超出部分只计数，下一秒汇总推送
+const MAX_EVENTS_PER_SEC: u32 = 50;
+
+static SUBSCRIPTIONS: Lazy<DashMap<u64, Subscription>> = Lazy::new(DashMap::new);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+static TICKER_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// 实时日志的过滤条件，均为空时推送全部请求
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LiveTailFilter {
+    #[serde(default)]
+    pub listen_addr: Option<String>,
+    /// "2xx" / "3xx" / "4xx" / "5xx"
+    #[serde(default)]
+    pub status_class: Option<String>,
+    #[serde(default)]
+    pub path_prefix: Option<String>,
+    #[serde(default)]
+    pub client_ip: Option<String>,
+}
+
+struct CompiledFilter {
+    listen_addr: Option<String>,
+    status: Option<(i32, i32)>,
+    path_prefix: Option<String>,
+    client_ip: Option<String>,
+}
+
+fn non_empty(v: Option<String>) -> Option<String> {
+    v.map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+impl CompiledFilter {
+    fn new(filter: LiveTailFilter) -> Self {
+        Self {
+            status: non_empty(filter.status_class).and_then(|s| parse_status_class(&s)),
+            listen_addr: non_empty(filter.listen_addr),
+            path_prefix: non_empty(filter.path_prefix),
+            client_ip: non_empty(filter.client_ip),
+        }
+    }
+
+    fn matches(&self, log: &RequestLogInsert) -> bool {
+        self.listen_addr
+            .as_deref()
+            .is_none_or(|a| log.listen_addr.trim() == a)
+            && self
+                .status
+                .is_none_or(|(lo, hi)| (lo..=hi).contains(&log.status_code))
+            && self
+                .path_prefix
+                .as_deref()
+                .is_none_or(|p| log.request_path.starts_with(p))
+            && self
+                .client_ip
+                .as_deref()
+                .is_none_or(|ip| log.client_ip == ip)
+    }
+}
+
+/// 当前一秒内的推送计数
+#[derive(Debug, Default)]
+struct RateWindow {
+    sent: u32,
+    suppressed: u64,
+}
+
+impl RateWindow {
+    fn admit(&mut self) -> bool {
+        if self.sent < MAX_EVENTS_PER_SEC {
+            self.sent += 1;
+            true
+        } else {
+            self.suppressed += 1;
+            false
+        }
+    }
+
+    /// 进入下一秒，返回上一秒被略过的条数
+    fn roll(&mut self) -> u64 {
+        self.sent = 0;
+        std::mem::take(&mut self.suppressed)
+    }
+}
+
+struct Subscription {
+    app: AppHandle,
+    webview: String,
+    filter: CompiledFilter,
+    window: Mutex<RateWindow>,
+}
+
+#[derive(Clone, Serialize)]
+struct LiveRequestLog<'a> {
+    subscription_id: u64,
+    log: &'a RequestLogInsert,
+}
+
+#[derive(Clone, Serialize)]
+struct LiveTailSummary {
+    subscription_id: u64,
+    /// 因超过每秒上限未推送的条数
+    suppressed: u64,
+}
+
+/// 注册订阅，匹配的请求记录以 `request-log-live` 事件推送给该 webview
+pub fn subscribe_request_logs(app: AppHandle, webview: &str, filter: LiveTailFilter) -> u64 {
+    start_ticker();
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    SUBSCRIPTIONS.insert(
+        id,
+        Subscription {
+            app,
+            webview: webview.to_string(),
+            filter: CompiledFilter::new(filter),
+            window: Mutex::new(RateWindow::default()),
+        },
+    );
+    id
+}
+
+pub fn unsubscribe_request_logs(id: u64) -> bool {
+    SUBSCRIPTIONS.remove(&id).is_some()
+}
+
+/// 页面重新加载时移除该 webview 的全部订阅
+pub fn unsubscribe_webview(webview: &str) {
+    SUBSCRIPTIONS.retain(|_, sub| sub.webview != webview);
+}
+
+/// 由 try_enqueue_request_log 调用，不经过数据库
+pub(super) fn publish(log: &RequestLogInsert) {
+    if SUBSCRIPTIONS.is_empty() {
+        return;
+    }
+    for sub in SUBSCRIPTIONS.iter() {
+        if !sub.filter.matches(log) || !sub.window.lock().admit() {
+            continue;
+        }
+        let _ = sub.app.emit_to(
+            sub.webview.as_str(),
+            "request-log-live",
+            LiveRequestLog {
+                subscription_id: *sub.key(),
+                log,
+            },
+        );
+    }
+}
+
+/// 每秒重置各订阅的计数，并推送上一秒被略过的条数
+fn start_ticker() {
+    if TICKER_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    tauri::async_runtime::spawn(async {
+        let mut ticker = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            ticker.tick().await;
+            for sub in SUBSCRIPTIONS.iter() {
+                let suppressed = sub.window.lock().roll();
+                if suppressed > 0 {
+                    let _ = sub.app.emit_to(
+                        sub.webview.as_str(),
+                        "request-log-live-summary",
+                        LiveTailSummary {
+                            subscription_id: *sub.key(),
+                            suppressed,
+                        },
+                    );
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(listen_addr: &str, status_code: i32, path: &str, client_ip: &str) -> RequestLogInsert {
+        RequestLogInsert {
+            timestamp: 0,
+            listen_addr: listen_addr.into(),
+            client_ip: client_ip.into(),
+            remote_ip: client_ip.into(),
+            method: "GET".into(),
+            request_path: path.into(),
+            request_host: String::new(),
+            status_code,
+            upstream: String::new(),
+            latency_ms: 0.0,
+            guard_ms: 0.0,
+            prepare_ms: 0.0,
+            upstream_ms: 0.0,
+            user_agent: String::new(),
+            referer: String::new(),
+            matched_route_id: String::new(),
+            bytes_sent: 0,
+            bytes_received: 0,
+            country: String::new(),
+            asn: String::new(),
+            sample_rate: 1.0,
+        }
+    }
+
+    #[test]
+    fn filter_combines_all_conditions() {
+        let filter = CompiledFilter::new(LiveTailFilter {
+            listen_addr: Some(" 127.0.0.1:18080 ".into()),
+            status_class: Some("5xx".into()),
+            path_prefix: Some("/api/".into()),
+            client_ip: Some("".into()),
+        });
+        assert!(filter.matches(&log("127.0.0.1:18080", 502, "/api/users", "10.0.0.1")));
+        assert!(!filter.matches(&log("127.0.0.1:18080", 404, "/api/users", "10.0.0.1")));
+        assert!(!filter.matches(&log("127.0.0.1:18081", 502, "/api/users", "10.0.0.1")));
+        assert!(!filter.matches(&log("127.0.0.1:18080", 502, "/static/a.js", "10.0.0.1")));
+
+        let all = CompiledFilter::new(LiveTailFilter::default());
+        assert!(all.matches(&log("", 200, "/", "10.0.0.2")));
+    }
+
+    #[test]
+    fn rate_window_caps_and_reports_suppressed() {
+        let mut window = RateWindow::default();
+        let admitted = (0..MAX_EVENTS_PER_SEC + 7)
+            .filter(|_| window.admit())
+            .count();
+        assert_eq!(admitted, MAX_EVENTS_PER_SEC as usize);
+        assert_eq!(window.roll(), 7);
+        assert!(window.admit());
+        assert_eq!(window.roll(), 0);
+    }
+}
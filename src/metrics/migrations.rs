@@ -0,0 +1,465 @@
+use super::*;
+use sqlx::SqliteConnection;
+
+/// 当前 schema 版本，新增迁移时同步递增
+pub(super) const SCHEMA_VERSION: i64 = 13;
+
+/// 按版本顺序执行迁移，每个版本在独立事务中完成并记录到 schema_version。
+/// 新列一律通过 ALTER TABLE ADD COLUMN（带默认值）添加；
+/// 只有列类型/约束必须变化时才复制到新表，任何情况下都不直接删表。
+pub(super) async fn migrate_schema(pool: &SqlitePool) -> Result<()> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER PRIMARY KEY, applied_at INTEGER NOT NULL)",
+    )
+    .execute(pool)
+    .await
+    .context("创建 schema_version 表失败")?;
+
+    let current: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(version), 0) FROM schema_version")
+        .fetch_one(pool)
+        .await
+        .context("读取 schema_version 失败")?;
+
+    for version in (current + 1)..=SCHEMA_VERSION {
+        let mut tx = pool.begin().await?;
+        apply_migration(&mut tx, version)
+            .await
+            .with_context(|| format!("数据库迁移到 v{} 失败", version))?;
+        sqlx::query("INSERT INTO schema_version(version, applied_at) VALUES(?, ?)")
+            .bind(version)
+            .bind(chrono::Utc::now().timestamp())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+// 旧库没有 schema_version，会从 v1 开始重放；因此每一步都必须幂等
+async fn apply_migration(conn: &mut SqliteConnection, version: i64) -> Result<()> {
+    match version {
+        1 => {
+            execute_all(
+                conn,
+                &[
+                    r#"CREATE TABLE IF NOT EXISTS request_logs (
+                      id INTEGER PRIMARY KEY AUTOINCREMENT,
+                      timestamp INTEGER NOT NULL,
+                      listen_addr TEXT NOT NULL,
+                      client_ip TEXT NOT NULL,
+                      remote_ip TEXT NOT NULL,
+                      method TEXT NOT NULL,
+                      request_path TEXT NOT NULL,
+                      request_host TEXT NOT NULL,
+                      status_code INTEGER NOT NULL,
+                      upstream TEXT NOT NULL,
+                      latency_ms REAL NOT NULL,
+                      user_agent TEXT NOT NULL,
+                      referer TEXT NOT NULL
+                    )"#,
+                    r#"CREATE TABLE IF NOT EXISTS system_metrics (
+                      id INTEGER PRIMARY KEY AUTOINCREMENT,
+                      timestamp INTEGER NOT NULL,
+                      cpu_usage_percent REAL NOT NULL,
+                      load1 REAL NOT NULL,
+                      load5 REAL NOT NULL,
+                      load15 REAL NOT NULL,
+                      mem_total_bytes INTEGER NOT NULL,
+                      mem_available_bytes INTEGER NOT NULL,
+                      mem_used_bytes INTEGER NOT NULL,
+                      mem_used_percent REAL NOT NULL,
+                      swap_total_bytes INTEGER NOT NULL,
+                      swap_free_bytes INTEGER NOT NULL,
+                      swap_used_bytes INTEGER NOT NULL,
+                      swap_used_percent REAL NOT NULL,
+                      net_rx_bytes INTEGER NOT NULL,
+                      net_tx_bytes INTEGER NOT NULL,
+                      net_rx_bps REAL NOT NULL,
+                      net_tx_bps REAL NOT NULL,
+                      disk_read_bytes INTEGER NOT NULL,
+                      disk_write_bytes INTEGER NOT NULL,
+                      disk_read_bps REAL NOT NULL,
+                      disk_write_bps REAL NOT NULL,
+                      tcp_established INTEGER NOT NULL,
+                      tcp_time_wait INTEGER NOT NULL,
+                      tcp_close_wait INTEGER NOT NULL,
+                      process_count INTEGER NOT NULL,
+                      fd_used INTEGER NOT NULL,
+                      fd_max INTEGER NOT NULL,
+                      fd_usage_percent REAL NOT NULL,
+                      procs_running INTEGER NOT NULL,
+                      procs_blocked INTEGER NOT NULL,
+                      context_switches INTEGER NOT NULL,
+                      processes_forked_total INTEGER NOT NULL,
+                      uptime_seconds REAL NOT NULL
+                    )"#,
+                    r#"CREATE TABLE IF NOT EXISTS blacklist (
+                      id INTEGER PRIMARY KEY AUTOINCREMENT,
+                      ip TEXT NOT NULL UNIQUE,
+                      reason TEXT,
+                      expires_at INTEGER NOT NULL,
+                      created_at INTEGER NOT NULL
+                    )"#,
+                ],
+            )
+            .await?;
+            // 早期版本没有 remote_ip，以前会因此整表重建，这里改为补列
+            add_column_if_missing(
+                conn,
+                "request_logs",
+                "remote_ip",
+                "TEXT NOT NULL DEFAULT ''",
+            )
+            .await?;
+            execute_all(
+                conn,
+                &[
+                    "CREATE INDEX IF NOT EXISTS idx_request_logs_ts ON request_logs(timestamp)",
+                    "CREATE INDEX IF NOT EXISTS idx_request_logs_listen_ts ON request_logs(listen_addr, timestamp)",
+                    "CREATE INDEX IF NOT EXISTS idx_request_logs_status_ts ON request_logs(status_code, timestamp)",
+                    "CREATE INDEX IF NOT EXISTS idx_request_logs_client_ip_ts ON request_logs(client_ip, timestamp)",
+                    // request_path/upstream 常用于 LIKE/分组，索引对 LIKE %...% 帮助有限，但对分组与前缀匹配仍有收益
+                    "CREATE INDEX IF NOT EXISTS idx_request_logs_path_ts ON request_logs(request_path, timestamp)",
+                    "CREATE INDEX IF NOT EXISTS idx_request_logs_upstream_ts ON request_logs(upstream, timestamp)",
+                    "CREATE INDEX IF NOT EXISTS idx_system_metrics_ts ON system_metrics(timestamp)",
+                ],
+            )
+            .await
+        }
+        2 => {
+            add_column_if_missing(
+                conn,
+                "request_logs",
+                "matched_route_id",
+                "TEXT NOT NULL DEFAULT ''",
+            )
+            .await?;
+            execute_all(
+                conn,
+                &["CREATE INDEX IF NOT EXISTS idx_request_logs_route_ts ON request_logs(matched_route_id, timestamp)"],
+            )
+            .await
+        }
+        3 => {
+            for col in ["guard_ms", "prepare_ms", "upstream_ms"] {
+                add_column_if_missing(conn, "request_logs", col, "REAL NOT NULL DEFAULT 0").await?;
+            }
+            Ok(())
+        }
+        4 => {
+            // WS 会话表：升级记录写 request_logs，断开时在此记录时长与流量
+            execute_all(
+                conn,
+                &[
+                    r#"CREATE TABLE IF NOT EXISTS ws_sessions (
+                      id INTEGER PRIMARY KEY AUTOINCREMENT,
+                      timestamp INTEGER NOT NULL,
+                      listen_addr TEXT NOT NULL,
+                      client_ip TEXT NOT NULL,
+                      request_path TEXT NOT NULL,
+                      upstream TEXT NOT NULL,
+                      duration_ms REAL NOT NULL,
+                      messages_in INTEGER NOT NULL,
+                      messages_out INTEGER NOT NULL,
+                      bytes_in INTEGER NOT NULL,
+                      bytes_out INTEGER NOT NULL
+                    )"#,
+                    "CREATE INDEX IF NOT EXISTS idx_ws_sessions_listen_ts ON ws_sessions(listen_addr, timestamp)",
+                ],
+            )
+            .await
+        }
+        5 => {
+            // stream 会话表：TCP/UDP 会话结束时记录
+            execute_all(
+                conn,
+                &[
+                    r#"CREATE TABLE IF NOT EXISTS stream_logs (
+                      id INTEGER PRIMARY KEY AUTOINCREMENT,
+                      timestamp INTEGER NOT NULL,
+                      listen_port INTEGER NOT NULL,
+                      protocol TEXT NOT NULL,
+                      client_ip TEXT NOT NULL,
+                      upstream_addr TEXT NOT NULL,
+                      duration_ms REAL NOT NULL,
+                      bytes_in INTEGER NOT NULL,
+                      bytes_out INTEGER NOT NULL,
+                      disconnect_reason TEXT NOT NULL
+                    )"#,
+                    "CREATE INDEX IF NOT EXISTS idx_stream_logs_ts ON stream_logs(timestamp)",
+                    "CREATE INDEX IF NOT EXISTS idx_stream_logs_port_ts ON stream_logs(listen_port, timestamp)",
+                ],
+            )
+            .await
+        }
+        6 => {
+            add_column_if_missing(
+                conn,
+                "stream_logs",
+                "attempts",
+                "INTEGER NOT NULL DEFAULT 1",
+            )
+            .await
+        }
+        7 => {
+            add_column_if_missing(
+                conn,
+                "request_logs",
+                "bytes_sent",
+                "INTEGER NOT NULL DEFAULT 0",
+            )
+            .await
+        }
+        8 => {
+            execute_all(
+                conn,
+                &["CREATE INDEX IF NOT EXISTS idx_request_logs_host_ts ON request_logs(request_host, timestamp)"],
+            )
+            .await
+        }
+        9 => {
+            // 请求日志全文索引；已有行的索引由 fts::backfill_request_logs_fts 在后台回填
+            execute_all(
+                conn,
+                &[
+                    "CREATE VIRTUAL TABLE IF NOT EXISTS request_logs_fts USING fts5(request_path, request_host, user_agent, referer, content='', contentless_delete=1, tokenize='trigram')",
+                    "CREATE TRIGGER IF NOT EXISTS request_logs_fts_ad AFTER DELETE ON request_logs BEGIN DELETE FROM request_logs_fts WHERE rowid = old.id; END",
+                    "CREATE TABLE IF NOT EXISTS request_logs_fts_state (id INTEGER PRIMARY KEY CHECK (id = 1), backfill_below INTEGER NOT NULL)",
+                    "INSERT OR IGNORE INTO request_logs_fts_state(id, backfill_below) SELECT 1, COALESCE(MAX(id), 0) + 1 FROM request_logs",
+                ],
+            )
+            .await
+        }
+        10 => {
+            // 请求日志长期汇总，由 rollup::run_request_log_rollups 从原始行增量写入
+            let mut statements = Vec::new();
+            for table in ["request_logs_rollup_1m", "request_logs_rollup_1h"] {
+                statements.push(format!(
+                    r#"CREATE TABLE IF NOT EXISTS {table} (
+                      bucket INTEGER NOT NULL,
+                      listen_addr TEXT NOT NULL,
+                      matched_route_id TEXT NOT NULL,
+                      total INTEGER NOT NULL,
+                      s2xx INTEGER NOT NULL,
+                      s3xx INTEGER NOT NULL,
+                      s4xx INTEGER NOT NULL,
+                      s5xx INTEGER NOT NULL,
+                      latency_sum_ms REAL NOT NULL,
+                      latency_max_ms REAL NOT NULL,
+                      upstream_count INTEGER NOT NULL,
+                      upstream_sum_ms REAL NOT NULL,
+                      bytes_sent INTEGER NOT NULL,
+                      PRIMARY KEY (bucket, listen_addr, matched_route_id)
+                    ) WITHOUT ROWID"#
+                ));
+            }
+            let statements: Vec<&str> = statements.iter().map(String::as_str).collect();
+            execute_all(conn, &statements).await?;
+            execute_all(
+                conn,
+                &[
+                    "CREATE TABLE IF NOT EXISTS request_logs_rollup_state (id INTEGER PRIMARY KEY CHECK (id = 1), rolled_until INTEGER NOT NULL)",
+                    "INSERT OR IGNORE INTO request_logs_rollup_state(id, rolled_until) SELECT 1, COALESCE((MIN(timestamp) / 60) * 60, 0) FROM request_logs",
+                ],
+            )
+            .await
+        }
+        11 => {
+            // GeoIP 国家与 ASN，未启用 GeoIP 时保持空串
+            for column in ["country", "asn"] {
+                add_column_if_missing(
+                    conn,
+                    "request_logs",
+                    column,
+                    "TEXT NOT NULL DEFAULT ''",
+                )
+                .await?;
+            }
+            execute_all(
+                conn,
+                &["CREATE INDEX IF NOT EXISTS idx_request_logs_country_ts ON request_logs(country, timestamp)"],
+            )
+            .await
+        }
+        12 => {
+            // 抽样落库时记录比例，统计时按 1 / sample_rate 还原请求量
+            add_column_if_missing(
+                conn,
+                "request_logs",
+                "sample_rate",
+                "REAL NOT NULL DEFAULT 1",
+            )
+            .await
+        }
+        13 => {
+            // 错误日志只占少量行：按状态类筛 4xx/5xx 时走部分索引，不扫描整个时间范围
+            execute_all(
+                conn,
+                &["CREATE INDEX IF NOT EXISTS idx_request_logs_errors_ts ON request_logs(timestamp) WHERE status_code >= 400"],
+            )
+            .await
+        }
+        _ => Err(anyhow!("未知的 schema 版本: {}", version)),
+    }
+}
+
+async fn execute_all(conn: &mut SqliteConnection, statements: &[&str]) -> Result<()> {
+    for sql in statements {
+        sqlx::query(sql).execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+async fn add_column_if_missing(
+    conn: &mut SqliteConnection,
+    table: &str,
+    column: &str,
+    ddl: &str,
+) -> Result<()> {
+    // PRAGMA table_info 返回列：cid,name,type,notnull,dflt_value,pk，这里只取 name
+    let cols: Vec<(i64, String, String, i64, Option<String>, i64)> =
+        sqlx::query_as(&format!("PRAGMA table_info({})", table))
+            .fetch_all(&mut *conn)
+            .await
+            .with_context(|| format!("读取 {} 表结构失败", table))?;
+    if cols.iter().any(|(_, name, _, _, _, _)| name == column) {
+        return Ok(());
+    }
+
+    sqlx::query(&format!(
+        "ALTER TABLE {} ADD COLUMN {} {}",
+        table, column, ddl
+    ))
+    .execute(&mut *conn)
+    .await
+    .with_context(|| format!("迁移 {}.{} 失败", table, column))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn memory_pool() -> SqlitePool {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn upgrade_from_v1_keeps_request_logs() {
+        let pool = memory_pool().await;
+        sqlx::query(
+            r#"CREATE TABLE request_logs (
+              id INTEGER PRIMARY KEY AUTOINCREMENT,
+              timestamp INTEGER NOT NULL,
+              listen_addr TEXT NOT NULL,
+              client_ip TEXT NOT NULL,
+              remote_ip TEXT NOT NULL,
+              method TEXT NOT NULL,
+              request_path TEXT NOT NULL,
+              request_host TEXT NOT NULL,
+              status_code INTEGER NOT NULL,
+              upstream TEXT NOT NULL,
+              latency_ms REAL NOT NULL,
+              user_agent TEXT NOT NULL,
+              referer TEXT NOT NULL
+            )"#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO request_logs(timestamp, listen_addr, client_ip, remote_ip, method, request_path, request_host, status_code, upstream, latency_ms, user_agent, referer) VALUES(1700000000, ':443', '10.0.0.1', '10.0.0.1', 'GET', '/a', 'example.com', 200, 'http://up', 12.5, 'curl', '-')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        migrate_schema(&pool).await.unwrap();
+        // 重复执行不应出错
+        migrate_schema(&pool).await.unwrap();
+
+        let (path, status, route, upstream_ms, bytes_sent): (String, i64, String, f64, i64) =
+            sqlx::query_as(
+                "SELECT request_path, status_code, matched_route_id, upstream_ms, bytes_sent FROM request_logs",
+            )
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(path, "/a");
+        assert_eq!(status, 200);
+        assert_eq!(route, "");
+        assert_eq!(upstream_ms, 0.0);
+        assert_eq!(bytes_sent, 0);
+
+        let version: i64 = sqlx::query_scalar("SELECT MAX(version) FROM schema_version")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+    }
+
+    #[tokio::test]
+    async fn upgrade_adds_remote_ip_and_attempts_without_dropping_rows() {
+        let pool = memory_pool().await;
+        sqlx::query(
+            r#"CREATE TABLE request_logs (
+              id INTEGER PRIMARY KEY AUTOINCREMENT,
+              timestamp INTEGER NOT NULL,
+              listen_addr TEXT NOT NULL,
+              client_ip TEXT NOT NULL,
+              method TEXT NOT NULL,
+              request_path TEXT NOT NULL,
+              request_host TEXT NOT NULL,
+              status_code INTEGER NOT NULL,
+              upstream TEXT NOT NULL,
+              latency_ms REAL NOT NULL,
+              user_agent TEXT NOT NULL,
+              referer TEXT NOT NULL
+            )"#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO request_logs(timestamp, listen_addr, client_ip, method, request_path, request_host, status_code, upstream, latency_ms, user_agent, referer) VALUES(1, ':80', '1.1.1.1', 'GET', '/', 'h', 404, '', 1.0, '', '')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "CREATE TABLE stream_logs (id INTEGER PRIMARY KEY AUTOINCREMENT, timestamp INTEGER NOT NULL, listen_port INTEGER NOT NULL, protocol TEXT NOT NULL, client_ip TEXT NOT NULL, upstream_addr TEXT NOT NULL, duration_ms REAL NOT NULL, bytes_in INTEGER NOT NULL, bytes_out INTEGER NOT NULL, disconnect_reason TEXT NOT NULL)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO stream_logs(timestamp, listen_port, protocol, client_ip, upstream_addr, duration_ms, bytes_in, bytes_out, disconnect_reason) VALUES(1, 9000, 'tcp', '1.1.1.1', '2.2.2.2:22', 5.0, 10, 20, 'eof')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        migrate_schema(&pool).await.unwrap();
+
+        let (remote_ip, status): (String, i64) =
+            sqlx::query_as("SELECT remote_ip, status_code FROM request_logs")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(remote_ip, "");
+        assert_eq!(status, 404);
+
+        let (port, attempts): (i64, i64) =
+            sqlx::query_as("SELECT listen_port, attempts FROM stream_logs")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(port, 9000);
+        assert_eq!(attempts, 1);
+    }
+}
@@ -1,15 +1,35 @@
+mod anonymize;
+mod backend;
 mod db;
+mod export;
+mod fts;
 mod helpers;
+mod histogram;
+mod live_tail;
+mod migrations;
 mod models;
+mod postgres;
+mod prometheus;
 mod query;
+mod recovery;
+mod rollup;
+mod window;
 mod writer;
 
-use self::helpers::{normalize_request_path_for_top, normalize_upstream_for_top};
+use self::backend::{db_backend, MetricsBackend};
+use self::helpers::{
+    normalize_referer_for_top, normalize_request_path_for_top, normalize_upstream_for_top,
+    normalize_user_agent_for_top,
+};
 pub use self::models::{
     BlacklistEntry, DashboardStatsPoint, DashboardStatsRequest, DashboardStatsResponse, KeyValue,
     MetricsPayload, MetricsSeries, PhaseMetricStats, PhaseTimingStats, QueryMetricsRequest,
-    QueryMetricsResponse, QueryRequestLogsRequest, QueryRequestLogsResponse, RequestLog,
-    RequestLogInsert, TopListItem,
+    QueryMetricsResponse, QueryRequestLogsRequest, QueryRequestLogsResponse,
+    QueryStreamLogsRequest, QueryStreamLogsResponse, RequestLog, RequestLogInsert, RouteStatsItem,
+    RouteStatsRequest, RouteTrafficItem, StatusCodeBreakdownRequest, StatusCodeBreakdownResponse,
+    StatusCodeCount, StatusCodeSeries, StreamLog, StreamLogInsert, StreamMetricsItem,
+    StreamTrafficStats, StreamUpstreamMetricsItem, TopListItem, UpstreamStatsItem,
+    UpstreamStatsRequest, WsMetricsItem, WsRouteMetricsItem, WsSessionInsert, WsTrafficStats,
 };
 use anyhow::{anyhow, Context, Result};
 use once_cell::sync::Lazy;
@@ -22,26 +42,47 @@ use sqlx::sqlite::{
 use sqlx::{ConnectOptions, QueryBuilder}; // 移除了未使用的 Row
 use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 // 增加批量大小以利用 Bulk Insert 优势
 const DB_FLUSH_BATCH_SIZE: usize = 2000;
 const DB_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+const DB_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+const DB_READ_POOL_SIZE: u32 = 4;
+/// 退出时等待日志落盘的最长时间
+pub const SHUTDOWN_FLUSH_TIMEOUT: Duration = Duration::from_secs(3);
 
 const REQUEST_LOG_RETENTION_DAYS: i64 = 730;
 const REQUEST_LOG_RETENTION_CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+// 分批删除，避免单条 DELETE 长时间持有写锁
+const RETENTION_PURGE_BATCH: i64 = 5_000;
+const RETENTION_PURGE_BATCH_PAUSE: Duration = Duration::from_millis(50);
 const DB_SPACE_RECLAIM_VACUUM_COOLDOWN: Duration = Duration::from_secs(24 * 60 * 60);
 const DB_SPACE_RECLAIM_MIN_DELETED_ROWS: u64 = 20_000;
 const DB_SPACE_RECLAIM_MIN_FREELIST_PAGES: i64 = 16_384; // 约 64MB（按 4KB 页估算）
 const DB_SPACE_RECLAIM_MIN_FREELIST_RATIO: f64 = 0.20;
 
 static DB_POOL: Lazy<RwLock<Option<Arc<SqlitePool>>>> = Lazy::new(|| RwLock::new(None));
+static DB_READ_POOL: Lazy<RwLock<Option<Arc<SqlitePool>>>> = Lazy::new(|| RwLock::new(None));
+// 当前生效的持久化后端（SQLite 或 Postgres）
+static DB_BACKEND: Lazy<RwLock<Option<Arc<dyn MetricsBackend>>>> = Lazy::new(|| RwLock::new(None));
 static DB_PATH: Lazy<RwLock<String>> = Lazy::new(|| RwLock::new(String::new()));
 static DB_ERROR: Lazy<RwLock<Option<String>>> = Lazy::new(|| RwLock::new(None));
 static DB_LAST_VACUUM_AT: Lazy<RwLock<Option<Instant>>> = Lazy::new(|| RwLock::new(None));
 static DB_VACUUM_RUNNING: AtomicBool = AtomicBool::new(false);
+// 写入闸门：批量写入持读锁，手动 VACUUM 持写锁以暂停所有写入
+static DB_WRITE_GATE: Lazy<tokio::sync::RwLock<()>> = Lazy::new(|| tokio::sync::RwLock::new(()));
+// 请求日志丢弃计数：队列满、数据库未就绪或写入失败
+static REQUEST_LOG_DROPPED: AtomicU64 = AtomicU64::new(0);
+// 按抽样配置主动不落库的请求日志条数，与 REQUEST_LOG_DROPPED 分开统计
+static REQUEST_LOG_SAMPLED_OUT: AtomicU64 = AtomicU64::new(0);
+static REQUEST_LOG_SAMPLE_SEQ: AtomicU64 = AtomicU64::new(0);
+// 最近一次成功写入请求日志的时间（unix 秒），0 表示尚未写入
+static REQUEST_LOG_LAST_FLUSH_AT: AtomicI64 = AtomicI64::new(0);
+static DB_PURGE_RUNNING: AtomicBool = AtomicBool::new(false);
+static DB_LAST_PURGE: Lazy<RwLock<Option<PurgeRecord>>> = Lazy::new(|| RwLock::new(None));
 
 static BLACKLIST_CACHE: Lazy<RwLock<HashMap<String, i64>>> =
     Lazy::new(|| RwLock::new(HashMap::new()));
@@ -88,6 +129,13 @@ static METRICS_CACHE: Lazy<RwLock<Option<(Instant, MetricsPayload)>>> =
 
 // --- Models ---
 
+#[derive(Debug, Clone, Copy)]
+struct PurgeRecord {
+    at: i64,
+    deleted_rows: u64,
+    remaining_request_logs: Option<i64>,
+}
+
 #[derive(Debug, Clone, Default)]
 struct RtBucket {
     ts: i64,
@@ -99,12 +147,29 @@ struct RtBucket {
     s0: i64,
     latency_sum_ms: f64,
     latency_max_ms: f64,
+    /// 仅统计经过上游的请求（upstream_ms > 0）
+    upstream_count: i64,
+    upstream_sum_ms: f64,
+    /// 请求体 / 响应体字节数
+    bytes_in: i64,
+    bytes_out: i64,
+    /// 采样到的进行中请求数，分钟桶取最大值
+    in_flight: i64,
 }
 
 impl RtBucket {
     #[inline]
-    fn add(&mut self, status_code: i32, latency_ms: f64) {
+    fn add(
+        &mut self,
+        status_code: i32,
+        latency_ms: f64,
+        upstream_ms: f64,
+        bytes_in: i64,
+        bytes_out: i64,
+    ) {
         self.count += 1;
+        self.bytes_in += bytes_in.max(0);
+        self.bytes_out += bytes_out.max(0);
         match status_code {
             200..=299 => self.s2xx += 1,
             300..=399 => self.s3xx += 1,
@@ -120,6 +185,11 @@ impl RtBucket {
                 self.latency_max_ms = v;
             }
         }
+
+        if upstream_ms.is_finite() && upstream_ms > 0.0 {
+            self.upstream_count += 1;
+            self.upstream_sum_ms += upstream_ms;
+        }
     }
 
     #[inline]
@@ -130,6 +200,15 @@ impl RtBucket {
             self.latency_sum_ms / (self.count as f64)
         }
     }
+
+    #[inline]
+    fn avg_upstream_ms(&self) -> f64 {
+        if self.upstream_count == 0 {
+            0.0
+        } else {
+            self.upstream_sum_ms / (self.upstream_count as f64)
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -138,14 +217,21 @@ struct RtSeriesAgg {
 }
 
 impl RtSeriesAgg {
-    fn add(&mut self, ts: i64, status_code: i32, latency_ms: f64) {
-        self.buckets
-            .entry(ts)
-            .or_insert_with(|| RtBucket {
-                ts,
-                ..Default::default()
-            })
-            .add(status_code, latency_ms);
+    fn bucket(&mut self, ts: i64) -> &mut RtBucket {
+        self.buckets.entry(ts).or_insert_with(|| RtBucket {
+            ts,
+            ..Default::default()
+        })
+    }
+
+    fn add(&mut self, ts: i64, log: &RequestLogInsert) {
+        self.bucket(ts).add(
+            log.status_code,
+            log.latency_ms,
+            log.upstream_ms,
+            log.bytes_received,
+            log.bytes_sent,
+        );
     }
 
     fn trim_older_than(&mut self, min_ts: i64) {
@@ -170,6 +256,10 @@ impl RtSeriesAgg {
             s0: Vec::with_capacity(len),
             avg_latency_ms: Vec::with_capacity(len),
             max_latency_ms: Vec::with_capacity(len),
+            avg_upstream_ms: Vec::with_capacity(len),
+            bytes_in: Some(Vec::with_capacity(len)),
+            bytes_out: Some(Vec::with_capacity(len)),
+            in_flight: Some(Vec::with_capacity(len)),
             p50: None,
             p95: None,
             p99: None,
@@ -191,6 +281,17 @@ impl RtSeriesAgg {
                 .push((b.avg_latency_ms() * 10000.0).round() / 10000.0);
             res.max_latency_ms
                 .push((b.latency_max_ms * 10000.0).round() / 10000.0);
+            res.avg_upstream_ms
+                .push((b.avg_upstream_ms() * 10000.0).round() / 10000.0);
+            if let Some(v) = res.bytes_in.as_mut() {
+                v.push(b.bytes_in);
+            }
+            if let Some(v) = res.bytes_out.as_mut() {
+                v.push(b.bytes_out);
+            }
+            if let Some(v) = res.in_flight.as_mut() {
+                v.push(b.in_flight);
+            }
         }
         res
     }
@@ -215,6 +316,12 @@ fn merge_rt_series_map(dst: &mut HashMap<String, RtSeriesAgg>, src: &HashMap<Str
             if b.latency_max_ms > out.latency_max_ms {
                 out.latency_max_ms = b.latency_max_ms;
             }
+            out.upstream_count += b.upstream_count;
+            out.upstream_sum_ms += b.upstream_sum_ms;
+            out.bytes_in += b.bytes_in;
+            out.bytes_out += b.bytes_out;
+            // 同一 key 的采样只写入一个分片，相加即为原值
+            out.in_flight += b.in_flight;
         }
     }
 }
@@ -241,38 +348,472 @@ struct RequestLogQueryFilters<'a> {
     request_path: Option<&'a str>,
     client_ip: Option<&'a str>,
     status_code: Option<i32>,
+    method: Option<&'a str>,
     matched_route_id: Option<&'a str>,
+    request_host: Option<&'a str>,
+    min_latency_ms: Option<f64>,
+    max_latency_ms: Option<f64>,
+    /// 闭区间 [lo, hi]
+    status_class: Option<(i32, i32)>,
+    country: Option<&'a str>,
+    text_match: TextMatch,
+    search: Option<&'a str>,
+    /// 可用全文索引时为 Some((FTS 短语, 尚未回填索引的 id 上界))，仅 SQLite
+    search_fts: Option<(&'a str, i64)>,
+}
+
+#[inline]
+fn non_empty_trimmed(v: &Option<String>) -> Option<&str> {
+    v.as_deref().map(str::trim).filter(|s| !s.is_empty())
+}
+
+impl<'a> RequestLogQueryFilters<'a> {
+    /// method 需要转大写，由调用方通过 normalized_method 生成并持有
+    fn from_request(req: &'a QueryRequestLogsRequest, method: Option<&'a str>) -> Self {
+        Self {
+            start_time: req.start_time,
+            end_time: req.end_time,
+            listen_addr: non_empty_trimmed(&req.listen_addr),
+            upstream: non_empty_trimmed(&req.upstream),
+            request_path: non_empty_trimmed(&req.request_path),
+            client_ip: non_empty_trimmed(&req.client_ip),
+            status_code: req.status_code.filter(|c| *c > 0),
+            method,
+            matched_route_id: non_empty_trimmed(&req.matched_route_id),
+            request_host: non_empty_trimmed(&req.request_host),
+            min_latency_ms: req.min_latency_ms.filter(|v| v.is_finite() && *v > 0.0),
+            max_latency_ms: req.max_latency_ms.filter(|v| v.is_finite() && *v > 0.0),
+            status_class: non_empty_trimmed(&req.status_class).and_then(parse_status_class),
+            country: non_empty_trimmed(&req.country),
+            text_match: TextMatch::parse(non_empty_trimmed(&req.text_match)),
+            search: non_empty_trimmed(&req.search),
+            search_fts: None,
+        }
+    }
+
+    /// 只有范围类过滤（前缀、Host）可走索引、且没有等值过滤时为 true。
+    /// 此时 SQLite 会偏向 timestamp 范围索引，需要提示它改用过滤列上的索引
+    fn prefers_filter_index(&self) -> bool {
+        let text_filter =
+            self.upstream.is_some() || self.request_path.is_some() || self.client_ip.is_some();
+        let range =
+            self.request_host.is_some() || (text_filter && self.text_match == TextMatch::Prefix);
+        let equality = self.listen_addr.is_some()
+            || self.status_code.is_some()
+            || self.matched_route_id.is_some()
+            || self.country.is_some()
+            || (text_filter && self.text_match == TextMatch::Exact);
+        range && !equality
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextMatch {
+    Contains,
+    Prefix,
+    Exact,
+}
+
+impl TextMatch {
+    fn parse(s: Option<&str>) -> Self {
+        match s.map(str::to_ascii_lowercase).as_deref() {
+            Some("prefix") => Self::Prefix,
+            Some("exact") => Self::Exact,
+            _ => Self::Contains,
+        }
+    }
 }
 
-fn append_request_logs_where<'a>(
-    qb: &mut QueryBuilder<'a, sqlx::Sqlite>,
+/// "4xx" / "4XX" / "4" -> (400, 499)；5xx 包含 5xx 以上的非标准状态码
+fn parse_status_class(s: &str) -> Option<(i32, i32)> {
+    let digit = s.trim_end_matches(['x', 'X']);
+    match digit {
+        "1" => Some((100, 199)),
+        "2" => Some((200, 299)),
+        "3" => Some((300, 399)),
+        "4" => Some((400, 499)),
+        "5" => Some((500, i32::MAX)),
+        _ => None,
+    }
+}
+
+fn normalized_method(req: &QueryRequestLogsRequest) -> Option<String> {
+    non_empty_trimmed(&req.method).map(str::to_ascii_uppercase)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RequestLogsPage {
+    Offset {
+        page_size: i64,
+        offset: i64,
+    },
+    /// 键集分页，after 为上一页最后一行的 (timestamp, id)；深分页无需扫描前面的行
+    Cursor {
+        page_size: i64,
+        after: Option<(i64, i64)>,
+    },
+}
+
+impl RequestLogsPage {
+    fn from_request(req: &QueryRequestLogsRequest) -> Result<Self> {
+        let page_size = req.page_size.clamp(1, 200) as i64;
+        let Some(cursor) = req.cursor.as_deref().map(str::trim) else {
+            let page = req.page.max(1) as i64;
+            return Ok(Self::Offset {
+                page_size,
+                offset: (page - 1) * page_size,
+            });
+        };
+        if cursor.is_empty() {
+            return Ok(Self::Cursor {
+                page_size,
+                after: None,
+            });
+        }
+        let after = cursor
+            .split_once('_')
+            .and_then(|(ts, id)| Some((ts.parse().ok()?, id.parse().ok()?)))
+            .ok_or_else(|| anyhow!("invalid cursor: {}", cursor))?;
+        Ok(Self::Cursor {
+            page_size,
+            after: Some(after),
+        })
+    }
+
+    fn page_size(&self) -> i64 {
+        match *self {
+            Self::Offset { page_size, .. } | Self::Cursor { page_size, .. } => page_size,
+        }
+    }
+
+    fn wants_total(&self, req: &QueryRequestLogsRequest) -> bool {
+        matches!(self, Self::Offset { .. }) || req.include_total
+    }
+
+    /// 本页取满时返回最后一行的游标
+    fn next_cursor(&self, logs: &[RequestLog]) -> Option<String> {
+        match *self {
+            Self::Cursor { page_size, .. } if logs.len() as i64 >= page_size => {
+                logs.last().map(|l| format!("{}_{}", l.timestamp, l.id))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn total_pages(total: i64, page_size: i64) -> i64 {
+    if total <= 0 {
+        0
+    } else {
+        (total + page_size - 1) / page_size
+    }
+}
+
+/// 追加排序与分页，需在 append_request_logs_where 之后调用
+fn push_request_logs_page<'a, DB>(
+    qb: &mut QueryBuilder<'a, DB>,
+    req: &QueryRequestLogsRequest,
+    page: RequestLogsPage,
+) where
+    DB: sqlx::Database,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let (sort_column, sort_dir) = request_logs_order(req);
+    match page {
+        RequestLogsPage::Offset { page_size, offset } => {
+            qb.push(" ORDER BY ")
+                .push(sort_column)
+                .push(" ")
+                .push(sort_dir)
+                .push(", id DESC LIMIT ")
+                .push_bind(page_size)
+                .push(" OFFSET ")
+                .push_bind(offset);
+        }
+        RequestLogsPage::Cursor { page_size, after } => {
+            if let Some((ts, id)) = after {
+                let cmp = if sort_dir == "ASC" { ">" } else { "<" };
+                qb.push(format!(" AND (timestamp, id) {cmp} ("))
+                    .push_bind(ts)
+                    .push(", ")
+                    .push_bind(id)
+                    .push(")");
+            }
+            qb.push(format!(
+                " ORDER BY timestamp {sort_dir}, id {sort_dir} LIMIT "
+            ))
+            .push_bind(page_size);
+        }
+    }
+}
+
+/// 返回 (排序列, 排序方向)，只允许白名单内的列名拼进 SQL
+fn request_logs_order(req: &QueryRequestLogsRequest) -> (&'static str, &'static str) {
+    let sort_column = match non_empty_trimmed(&req.sort_by) {
+        Some("id") => "id",
+        Some("timestamp") => "timestamp",
+        Some("listen_addr") | Some("listenAddr") => "listen_addr",
+        Some("client_ip") | Some("clientIP") => "client_ip",
+        Some("remote_ip") | Some("remoteIP") => "remote_ip",
+        Some("method") => "method",
+        Some("request_path") | Some("requestPath") => "request_path",
+        Some("request_host") | Some("requestHost") => "request_host",
+        Some("status_code") | Some("statusCode") => "status_code",
+        Some("upstream") => "upstream",
+        Some("latency_ms") | Some("latencyMs") => "latency_ms",
+        Some("user_agent") | Some("userAgent") => "user_agent",
+        Some("referer") => "referer",
+        Some("bytes_sent") | Some("bytesSent") => "bytes_sent",
+        _ => "timestamp",
+    };
+    let sort_order = req
+        .sort_order
+        .as_deref()
+        .map(str::trim)
+        .map(|s| s.to_ascii_lowercase());
+    let sort_dir = match sort_order.as_deref() {
+        Some("asc") | Some("ascending") | Some("ascend") => "ASC",
+        _ => "DESC",
+    };
+    (sort_column, sort_dir)
+}
+
+fn append_request_logs_where<'a, DB>(
+    qb: &mut QueryBuilder<'a, DB>,
     filters: RequestLogQueryFilters<'a>,
-) {
-    qb.push(" WHERE timestamp >= ")
+) where
+    DB: sqlx::Database,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    i32: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    f64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    String: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let sqlite = DB::NAME == "SQLite";
+    // 一元 + 让 SQLite 不用 timestamp 索引，转而使用前缀/Host 过滤列上的索引
+    let ts_col = if sqlite && filters.prefers_filter_index() {
+        "+timestamp"
+    } else {
+        "timestamp"
+    };
+    qb.push(format!(" WHERE {ts_col} >= "))
         .push_bind(filters.start_time)
-        .push(" AND timestamp <= ")
+        .push(format!(" AND {ts_col} <= "))
         .push_bind(filters.end_time);
 
     if let Some(v) = filters.listen_addr {
         qb.push(" AND listen_addr = ").push_bind(v);
     }
-    if let Some(v) = filters.upstream {
-        qb.push(" AND upstream LIKE ").push_bind(format!("%{}%", v));
-    }
-    if let Some(v) = filters.request_path {
-        qb.push(" AND request_path LIKE ")
-            .push_bind(format!("%{}%", v));
-    }
-    if let Some(v) = filters.client_ip {
-        qb.push(" AND client_ip LIKE ")
-            .push_bind(format!("%{}%", v));
+    for (col, v) in [
+        ("upstream", filters.upstream),
+        ("request_path", filters.request_path),
+        ("client_ip", filters.client_ip),
+    ] {
+        if let Some(v) = v {
+            push_text_filter(qb, col, v, filters.text_match, sqlite);
+        }
     }
     if let Some(v) = filters.status_code {
         qb.push(" AND status_code = ").push_bind(v);
     }
+    if let Some(v) = filters.method {
+        qb.push(" AND method = ").push_bind(v);
+    }
     if let Some(v) = filters.matched_route_id {
         qb.push(" AND matched_route_id = ").push_bind(v);
     }
+    if let Some(v) = filters.request_host {
+        // Host 头可能带端口；SQLite 的 LIKE 不走 BINARY 索引，先用 [v, "v;") 的范围圈定（':' 的下一个字符是 ';'）
+        if sqlite {
+            qb.push(" AND request_host >= ")
+                .push_bind(v)
+                .push(" AND request_host < ")
+                .push_bind(format!("{};", v));
+        }
+        qb.push(" AND (request_host = ")
+            .push_bind(v)
+            .push(" OR request_host LIKE ")
+            .push_bind(format!("{}:%", v))
+            .push(")");
+    }
+    if let Some(v) = filters.min_latency_ms {
+        qb.push(" AND latency_ms >= ").push_bind(v);
+    }
+    if let Some(v) = filters.max_latency_ms {
+        qb.push(" AND latency_ms <= ").push_bind(v);
+    }
+    if let Some((lo, hi)) = filters.status_class {
+        // 显式写出 status_code >= 400，部分索引 idx_request_logs_errors_ts 才能被选中
+        if lo >= 400 {
+            qb.push(" AND status_code >= 400");
+        }
+        qb.push(" AND status_code BETWEEN ")
+            .push_bind(lo)
+            .push(" AND ")
+            .push_bind(hi);
+    }
+    if let Some(v) = filters.country {
+        qb.push(" AND country = ").push_bind(v);
+    }
+    if let Some(v) = filters.search {
+        match filters.search_fts {
+            Some((phrase, backfill_below)) => {
+                qb.push(
+                    " AND (id IN (SELECT rowid FROM request_logs_fts WHERE request_logs_fts MATCH ",
+                )
+                .push_bind(phrase)
+                .push(")");
+                // 尚未回填索引的旧行仍按 LIKE 匹配
+                if backfill_below > 0 {
+                    qb.push(" OR (id < ").push_bind(backfill_below).push(" AND");
+                    push_search_like(qb, v);
+                    qb.push(")");
+                }
+                qb.push(")");
+            }
+            None => {
+                qb.push(" AND");
+                push_search_like(qb, v);
+            }
+        }
+    }
+}
+
+fn push_text_filter<'a, DB>(
+    qb: &mut QueryBuilder<'a, DB>,
+    col: &str,
+    v: &'a str,
+    mode: TextMatch,
+    sqlite: bool,
+) where
+    DB: sqlx::Database,
+    String: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    match mode {
+        TextMatch::Contains => {
+            qb.push(format!(" AND {col} LIKE "))
+                .push_bind(format!("%{}%", v));
+        }
+        TextMatch::Exact => {
+            qb.push(format!(" AND {col} = ")).push_bind(v);
+        }
+        // SQLite 的 LIKE 默认不区分大小写，用不上 BINARY 索引，改写成等价的区间比较
+        TextMatch::Prefix if sqlite => {
+            qb.push(format!(" AND {col} >= "))
+                .push_bind(v)
+                .push(format!(" AND {col} < "))
+                .push_bind(format!("{}\u{10FFFF}", v));
+        }
+        TextMatch::Prefix => {
+            qb.push(format!(" AND {col} LIKE "))
+                .push_bind(format!("{}%", escape_like(v)));
+        }
+    }
+}
+
+/// 转义 LIKE 通配符，使用默认的反斜杠转义（Postgres）
+fn escape_like(v: &str) -> String {
+    let mut out = String::with_capacity(v.len());
+    for c in v.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn push_search_like<'a, DB>(qb: &mut QueryBuilder<'a, DB>, pattern: &str)
+where
+    DB: sqlx::Database,
+    String: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let like = format!("%{}%", pattern.to_lowercase());
+    qb.push(" (");
+    for (i, col) in ["request_path", "request_host", "user_agent", "referer"]
+        .iter()
+        .enumerate()
+    {
+        if i > 0 {
+            qb.push(" OR ");
+        }
+        qb.push(format!("LOWER({col}) LIKE "))
+            .push_bind(like.clone());
+    }
+    qb.push(")");
+}
+
+struct StreamLogQueryFilters<'a> {
+    start_time: i64,
+    end_time: i64,
+    listen_port: Option<i32>,
+    protocol: Option<String>,
+    client_ip: Option<&'a str>,
+    upstream_addr: Option<&'a str>,
+}
+
+impl<'a> StreamLogQueryFilters<'a> {
+    fn from_request(req: &'a QueryStreamLogsRequest) -> Self {
+        Self {
+            start_time: req.start_time,
+            end_time: req.end_time,
+            listen_port: req.listen_port.filter(|p| *p > 0),
+            protocol: req
+                .protocol
+                .as_deref()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_ascii_lowercase),
+            client_ip: req
+                .client_ip
+                .as_deref()
+                .map(str::trim)
+                .filter(|s| !s.is_empty()),
+            upstream_addr: req
+                .upstream_addr
+                .as_deref()
+                .map(str::trim)
+                .filter(|s| !s.is_empty()),
+        }
+    }
+}
+
+/// (page_size, offset)
+fn stream_logs_page(req: &QueryStreamLogsRequest) -> (i64, i64) {
+    let page_size = req.page_size.clamp(1, 200) as i64;
+    let page = req.page.max(1) as i64;
+    (page_size, (page - 1) * page_size)
+}
+
+fn append_stream_logs_where<'a, DB>(
+    qb: &mut QueryBuilder<'a, DB>,
+    filters: &StreamLogQueryFilters<'a>,
+) where
+    DB: sqlx::Database,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    i32: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    String: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    qb.push(" WHERE timestamp >= ")
+        .push_bind(filters.start_time)
+        .push(" AND timestamp <= ")
+        .push_bind(filters.end_time);
+
+    if let Some(v) = filters.listen_port {
+        qb.push(" AND listen_port = ").push_bind(v);
+    }
+    if let Some(v) = &filters.protocol {
+        qb.push(" AND protocol = ").push_bind(v.clone());
+    }
+    if let Some(v) = filters.client_ip {
+        qb.push(" AND client_ip LIKE ")
+            .push_bind(format!("%{}%", v));
+    }
+    if let Some(v) = filters.upstream_addr {
+        qb.push(" AND upstream_addr LIKE ")
+            .push_bind(format!("%{}%", v));
+    }
 }
 
 #[derive(Debug, Default)]
@@ -284,6 +825,12 @@ struct RealtimeAgg {
     ip_counts: HashMap<String, HashMap<String, i64>>,
     upstream_error_counts: HashMap<String, HashMap<String, i64>>,
     upstream_counts: HashMap<String, HashMap<String, i64>>,
+    /// 全局按上游（归一化 host:port）的请求/错误/耗时
+    upstream_stats: HashMap<String, query::UpstreamStatAcc>,
+    /// 按监听地址的累计计数（Prometheus 导出用）
+    totals: HashMap<String, prometheus::PromTotals>,
+    /// 最近的耗时样本（告警窗口分位数用）
+    recent_latency: HashMap<String, window::LatencySamples>,
 }
 
 impl RealtimeAgg {
@@ -291,85 +838,59 @@ impl RealtimeAgg {
         Self::default()
     }
 
-    fn add(
-        &mut self,
-        listen_addr: &str,
-        ts_sec: i64,
-        status_code: i32,
-        latency_ms: f64,
-        matched_route_id: &str,
-        request_path: &str,
-        client_ip: &str,
-        upstream: &str,
-    ) {
-        self.add_one(
-            "全局",
-            ts_sec,
-            status_code,
-            latency_ms,
-            matched_route_id,
-            request_path,
-            client_ip,
-            upstream,
-        );
-        let la = listen_addr.trim();
+    fn add(&mut self, log: &RequestLogInsert) {
+        self.add_one("全局", log);
+        if !log.upstream.trim().is_empty() {
+            get_or_default_by_str(
+                &mut self.upstream_stats,
+                &normalize_upstream_for_top(&log.upstream),
+            )
+            .add(log.status_code, log.latency_ms);
+        }
+        let la = log.listen_addr.trim();
         if !la.is_empty() {
-            self.add_one(
-                la,
-                ts_sec,
-                status_code,
-                latency_ms,
-                matched_route_id,
-                request_path,
-                client_ip,
-                upstream,
-            );
+            get_or_default_by_str(&mut self.totals, la).add(log.status_code, log.latency_ms);
+            self.add_one(la, log);
         }
     }
 
-    fn add_one(
-        &mut self,
-        key: &str,
-        ts_sec: i64,
-        status_code: i32,
-        latency_ms: f64,
-        matched_route_id: &str,
-        request_path: &str,
-        client_ip: &str,
-        upstream: &str,
-    ) {
+    fn add_one(&mut self, key: &str, log: &RequestLogInsert) {
+        let ts_sec = log.timestamp;
+        let status_code = log.status_code;
         let min_ts = (ts_sec / 60) * 60;
 
         let sec = get_or_default_by_str(&mut self.per_sec, key);
-        sec.add(ts_sec, status_code, latency_ms);
+        sec.add(ts_sec, log);
         sec.trim_older_than(ts_sec - REALTIME_WINDOW_SECS);
 
         let min = get_or_default_by_str(&mut self.per_min, key);
-        min.add(min_ts, status_code, latency_ms);
+        min.add(min_ts, log);
         min.trim_older_than(ts_sec - REALTIME_MINUTE_WINDOW_SECS);
 
+        get_or_default_by_str(&mut self.recent_latency, key).push(ts_sec, log.latency_ms);
+
         // Top Routes（matched_route_id）实时聚合
-        let rid = matched_route_id.trim();
+        let rid = log.matched_route_id.trim();
         if !rid.is_empty() {
             let m = get_or_default_by_str(&mut self.route_counts, key);
             *m.entry(rid.to_string()).or_insert(0) += 1;
         }
 
         // Top request_path 实时聚合
-        let p = normalize_request_path_for_top(request_path);
+        let p = normalize_request_path_for_top(&log.request_path);
         {
             let m = get_or_default_by_str(&mut self.path_counts, key);
             *m.entry(p).or_insert(0) += 1;
         }
 
         // Top client_ip 实时聚合
-        let ip = client_ip.trim();
+        let ip = log.client_ip.trim();
         if !ip.is_empty() {
             let m = get_or_default_by_str(&mut self.ip_counts, key);
             *m.entry(ip.to_string()).or_insert(0) += 1;
         }
 
-        let normalized_upstream = normalize_upstream_for_top(upstream);
+        let normalized_upstream = normalize_upstream_for_top(&log.upstream);
 
         // Upstream 请求分布实时聚合
         {
@@ -384,6 +905,18 @@ impl RealtimeAgg {
         }
     }
 
+    /// 写入一次进行中请求数采样；只采样非零值，没有采样的桶即为 0
+    fn sample_in_flight(&mut self, key: &str, ts: i64, value: i64) {
+        let sec = get_or_default_by_str(&mut self.per_sec, key);
+        sec.bucket(ts).in_flight = value;
+        sec.trim_older_than(ts - REALTIME_WINDOW_SECS);
+
+        let min = get_or_default_by_str(&mut self.per_min, key);
+        let bucket = min.bucket((ts / 60) * 60);
+        bucket.in_flight = bucket.in_flight.max(value);
+        min.trim_older_than(ts - REALTIME_MINUTE_WINDOW_SECS);
+    }
+
     fn to_payload(&self) -> MetricsPayload {
         let mut listen_addrs: Vec<String> = self
             .per_sec
@@ -529,6 +1062,14 @@ impl RealtimeAgg {
             } else {
                 Some(top_upstream_errors)
             },
+            upstream_stats: if self.upstream_stats.is_empty() {
+                None
+            } else {
+                Some(query::upstream_stats_items(&self.upstream_stats))
+            },
+            ws_metrics: None,
+            stream_metrics: None,
+            slow_requests: None,
         }
     }
 }
@@ -536,13 +1077,35 @@ impl RealtimeAgg {
 // --- DB Utils ---
 
 pub use db::{
-    add_blacklist_entry, deinit_db, get_blacklist_entries, get_metrics_db_status,
-    get_metrics_db_status_detail, init_db, is_ip_blacklisted, refresh_blacklist_cache,
-    remove_blacklist_entry, test_metrics_db_connection, MetricsDBStatus,
+    add_blacklist_entry, clear_request_logs, deinit_db, get_blacklist_entries,
+    get_metrics_db_stats, get_metrics_db_status, get_metrics_db_status_detail, init_storage,
+    insert_ws_session, is_ip_blacklisted, refresh_blacklist_cache, remove_blacklist_entry,
+    test_metrics_db_connection, vacuum_metrics_db, ClearRequestLogsRequest, ClearRequestLogsResult,
+    MetricsDBStats, MetricsDBStatus, VacuumResult,
+};
+pub(crate) use db::{
+    db_pool, db_write_gate, insert_system_metrics, purge_system_metrics,
+    reclaim_db_space_after_delete, resolve_db_path, system_metrics_history,
+};
+pub use export::{
+    count_request_logs_for_export, export_max_rows, export_request_logs_to_file, ExportFormat,
+    ExportRequestLogsResult,
+};
+pub use live_tail::{
+    subscribe_request_logs, unsubscribe_request_logs, unsubscribe_webview, LiveTailFilter,
+};
+pub use prometheus::{
+    apply_prometheus_config, record_rate_limited, record_slow_request, record_upstream_failure,
+    slow_request_counts, start_in_flight_sampler, stop_prometheus_exporter, track_in_flight,
 };
-pub(crate) use db::{db_pool, reclaim_db_space_after_delete};
 pub use query::{
-    get_dashboard_stats, get_distinct_listen_addrs, get_metrics, query_historical_metrics,
-    query_request_logs,
+    get_dashboard_stats, get_distinct_listen_addrs, get_metrics, get_request_log, get_route_stats,
+    get_status_code_breakdown, get_upstream_stats, metrics_delta_since, query_historical_metrics,
+    query_request_logs, query_stream_logs,
+};
+pub use recovery::schedule_db_recovery;
+pub use window::{realtime_window_stats, RealtimeWindowStats, REALTIME_ALERT_WINDOW_MAX_SECS};
+pub use writer::{
+    init_request_log_writer, shutdown, shutdown_blocking, try_enqueue_request_log,
+    try_enqueue_stream_log,
 };
-pub use writer::{init_request_log_writer, try_enqueue_request_log};
@@ -31,18 +31,51 @@ pub struct QueryRequestLogsRequest {
     pub request_path: Option<String>,
     pub client_ip: Option<String>,
     pub status_code: Option<i32>,
+    pub method: Option<String>,
     pub page: i32,
     pub page_size: i32,
     pub matched_route_id: Option<String>,
     pub sort_by: Option<String>,
     pub sort_order: Option<String>,
+    /// 精确匹配 Host（忽略端口）
+    #[serde(default)]
+    pub request_host: Option<String>,
+    #[serde(default)]
+    pub min_latency_ms: Option<f64>,
+    #[serde(default)]
+    pub max_latency_ms: Option<f64>,
+    /// "2xx" / "3xx" / "4xx" / "5xx"
+    #[serde(default)]
+    pub status_class: Option<String>,
+    /// ISO 国家代码，如 "CN"、"US"，需启用 GeoIP
+    #[serde(default)]
+    pub country: Option<String>,
+    /// upstream / request_path / client_ip 的匹配方式："contains"（默认）/ "prefix" / "exact"。
+    /// contains 无法使用索引，大表上建议用 prefix 或 exact（两者区分大小写）
+    #[serde(default)]
+    pub text_match: Option<String>,
+    /// 在 path/host/UA/referer 中做子串匹配。SQLite 下走全文索引；
+    /// 少于 3 个字符或 Postgres 时退化为多列 LIKE，需扫描时间范围内全部行，较慢
+    #[serde(default)]
+    pub search: Option<String>,
+    /// 游标分页（推荐）：传空字符串取第一页，之后传上次返回的 next_cursor，page 被忽略。
+    /// 游标模式固定按时间排序，只使用 sort_order
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// 游标模式下默认不统计 total（需要扫描全部命中行）
+    #[serde(default)]
+    pub include_total: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueryRequestLogsResponse {
     pub logs: Vec<RequestLog>,
+    /// 游标模式且未要求 include_total 时为 -1
     pub total: i64,
     pub total_page: i64,
+    /// 游标模式下还有下一页时返回
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
@@ -68,6 +101,15 @@ pub struct RequestLog {
     pub referer: String,
     #[sqlx(default)]
     pub matched_route_id: String,
+    #[sqlx(default)]
+    pub bytes_sent: i64,
+    #[sqlx(default)]
+    pub country: String,
+    #[sqlx(default)]
+    pub asn: String,
+    /// 落库时的抽样比例，1 表示未抽样
+    #[sqlx(default)]
+    pub sample_rate: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -76,6 +118,12 @@ pub struct DashboardStatsRequest {
     pub end_time: i64,
     pub listen_addr: Option<String>,
     pub granularity_secs: i64,
+    /// 仅统计命中该路由的请求
+    #[serde(default)]
+    pub matched_route_id: Option<String>,
+    /// 是否统计 top UA / referer（需要按文本列分组，较慢，默认关闭）
+    #[serde(default)]
+    pub include_agents: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, Default)]
@@ -88,6 +136,8 @@ pub struct DashboardStatsPoint {
     pub server_error_requests: i64,
     #[sqlx(default)]
     pub avg_latency_ms: f64,
+    #[sqlx(default)]
+    pub avg_upstream_ms: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
@@ -96,6 +146,15 @@ pub struct TopListItem {
     pub count: i64,
 }
 
+/// 按路由汇总的请求数、错误数（status >= 400）与平均耗时
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RouteTrafficItem {
+    pub route_id: String,
+    pub requests: i64,
+    pub errors: i64,
+    pub avg_latency_ms: f64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PhaseMetricStats {
     pub avg_ms: f64,
@@ -119,14 +178,120 @@ pub struct DashboardStatsResponse {
     pub top_route_errors: Vec<TopListItem>,
     #[serde(default)]
     pub top_upstream_errors: Vec<TopListItem>,
+    /// 按响应字节数排序的路径，count 为字节数之和
+    #[serde(default)]
+    pub top_paths_by_bytes: Vec<TopListItem>,
     pub total_requests: i64,
     pub success_rate: f64,
     pub avg_latency_ms: f64,
+    #[serde(default)]
+    pub avg_upstream_ms: f64,
+    #[serde(default)]
+    pub total_bytes_sent: i64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub phase_timing: Option<PhaseTimingStats>,
+    /// include_agents 为 true 时返回，UA 已去除版本号
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_user_agents: Option<Vec<TopListItem>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_referers: Option<Vec<TopListItem>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_hosts: Option<Vec<TopListItem>>,
+    /// 启用 GeoIP 后才有数据
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_countries: Option<Vec<TopListItem>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route_breakdown: Option<Vec<RouteTrafficItem>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusCodeBreakdownRequest {
+    pub start_time: i64,
+    pub end_time: i64,
+    pub listen_addr: Option<String>,
+    /// 需要按时间分桶的状态码；为空时不返回 series
+    #[serde(default)]
+    pub series_codes: Vec<i32>,
+    #[serde(default)]
+    pub granularity_secs: Option<i64>,
+    /// 返回的状态码种类上限
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct StatusCodeCount {
+    pub status_code: i32,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusCodeSeries {
+    pub status_code: i32,
+    /// 与 StatusCodeBreakdownResponse.timestamps 一一对应
+    pub counts: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct StatusCodeBreakdownResponse {
+    /// 按 count 降序
+    pub codes: Vec<StatusCodeCount>,
+    pub timestamps: Vec<i64>,
+    pub series: Vec<StatusCodeSeries>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamStatsRequest {
+    pub start_time: i64,
+    pub end_time: i64,
+    pub listen_addr: Option<String>,
+    #[serde(default)]
+    pub matched_route_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamStatsItem {
+    /// 归一化后的 host:port
+    pub upstream: String,
+    pub requests: i64,
+    /// status >= 500
+    pub errors: i64,
+    pub avg_latency_ms: f64,
+    pub max_latency_ms: f64,
+    /// 占经过上游的请求总数的比例（0~1）
+    pub share: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteStatsRequest {
+    pub start_time: i64,
+    pub end_time: i64,
+    /// 仅统计该监听规则下的路由
+    #[serde(default)]
+    pub listen_rule_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct RouteStatsItem {
+    pub route_id: String,
+    /// 当前配置中已不存在的路由为 None
+    pub listen_rule_id: Option<String>,
+    pub host: Option<String>,
+    pub path: Option<String>,
+    /// 展示用：host + path，路由已删除时为 id
+    pub label: String,
+    pub requests: i64,
+    pub s2xx: i64,
+    pub s3xx: i64,
+    pub s4xx: i64,
+    pub s5xx: i64,
+    pub avg_latency_ms: f64,
+    pub p95_latency_ms: f64,
+    pub bytes_sent: i64,
+    pub top_paths: Vec<TopListItem>,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct RequestLogInsert {
     pub timestamp: i64,
     pub listen_addr: String,
@@ -144,6 +309,80 @@ pub struct RequestLogInsert {
     pub user_agent: String,
     pub referer: String,
     pub matched_route_id: String,
+    /// 实际发送给客户端的响应体字节数
+    pub bytes_sent: i64,
+    /// 请求体字节数，只进实时带宽统计，不落库
+    pub bytes_received: i64,
+    /// 由 try_enqueue_request_log 按 GeoIP 填充，构造时留空即可
+    pub country: String,
+    pub asn: String,
+    /// 由 try_enqueue_request_log 按抽样配置设置，构造时填 1.0
+    pub sample_rate: f64,
+}
+
+/// WS 会话记录（连接断开时写入 ws_sessions 表）
+#[derive(Debug, Clone)]
+pub struct WsSessionInsert {
+    pub timestamp: i64,
+    pub listen_addr: String,
+    pub client_ip: String,
+    pub request_path: String,
+    pub upstream: String,
+    pub duration_ms: f64,
+    pub messages_in: i64,
+    pub messages_out: i64,
+    pub bytes_in: i64,
+    pub bytes_out: i64,
+}
+
+/// stream 会话记录（TCP/UDP 会话结束时写入 stream_logs 表）
+#[derive(Debug, Clone)]
+pub struct StreamLogInsert {
+    pub timestamp: i64,
+    pub listen_port: i32,
+    pub protocol: String,
+    pub client_ip: String,
+    pub upstream_addr: String,
+    pub duration_ms: f64,
+    pub bytes_in: i64,
+    pub bytes_out: i64,
+    pub disconnect_reason: String,
+    /// 第几次尝试连上该上游（同一客户端连接内的重试计数）
+    pub attempts: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryStreamLogsRequest {
+    pub start_time: i64,
+    pub end_time: i64,
+    pub listen_port: Option<i32>,
+    pub protocol: Option<String>,
+    pub client_ip: Option<String>,
+    pub upstream_addr: Option<String>,
+    pub page: i32,
+    pub page_size: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryStreamLogsResponse {
+    pub logs: Vec<StreamLog>,
+    pub total: i64,
+    pub total_page: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct StreamLog {
+    pub id: i64,
+    pub timestamp: i64,
+    pub listen_port: i32,
+    pub protocol: String,
+    pub client_ip: String,
+    pub upstream_addr: String,
+    pub duration_ms: f64,
+    pub bytes_in: i64,
+    pub bytes_out: i64,
+    pub disconnect_reason: String,
+    pub attempts: i32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -159,6 +398,17 @@ pub struct MetricsSeries {
     pub avg_latency_ms: Vec<f64>,
     #[serde(rename = "maxLatencyMs")]
     pub max_latency_ms: Vec<f64>,
+    /// 上游首字节耗时均值，仅统计经过上游的请求
+    #[serde(rename = "avgUpstreamMs", default)]
+    pub avg_upstream_ms: Vec<f64>,
+    /// 每个时间桶的请求体 / 响应体字节数，仅实时序列提供
+    #[serde(skip_serializing_if = "Option::is_none", rename = "bytesIn", default)]
+    pub bytes_in: Option<Vec<i64>>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "bytesOut", default)]
+    pub bytes_out: Option<Vec<i64>>,
+    /// 每秒采样的进行中请求数（分钟桶为该分钟内的最大值），仅实时序列提供
+    #[serde(skip_serializing_if = "Option::is_none", rename = "inFlight", default)]
+    pub in_flight: Option<Vec<i64>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub p50: Option<Vec<f64>>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -204,4 +454,100 @@ pub struct MetricsPayload {
     pub top_client_ips: Option<Vec<TopListItem>>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "topUpstreamErrors")]
     pub top_upstream_errors: Option<Vec<TopListItem>>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "upstreamStats")]
+    pub upstream_stats: Option<Vec<UpstreamStatsItem>>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "wsMetrics")]
+    pub ws_metrics: Option<Vec<WsMetricsItem>>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "streamMetrics")]
+    pub stream_metrics: Option<Vec<StreamMetricsItem>>,
+    /// 各监听地址累计的慢请求数
+    #[serde(skip_serializing_if = "Option::is_none", rename = "slowRequests")]
+    pub slow_requests: Option<Vec<KeyValue>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct WsTrafficStats {
+    /// 当前活跃连接数
+    pub active: u64,
+    /// 累计连接数
+    pub total: u64,
+    /// 客户端 -> 上游 消息数
+    #[serde(rename = "messagesIn")]
+    pub messages_in: u64,
+    /// 上游 -> 客户端 消息数
+    #[serde(rename = "messagesOut")]
+    pub messages_out: u64,
+    #[serde(rename = "bytesIn")]
+    pub bytes_in: u64,
+    #[serde(rename = "bytesOut")]
+    pub bytes_out: u64,
+    /// 连接上游失败次数
+    #[serde(rename = "connectFailures")]
+    pub connect_failures: u64,
+    /// permessage-deflate 节省的线上字节数（两端、两个方向合计）
+    #[serde(rename = "deflateBytesSaved")]
+    pub deflate_bytes_saved: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct WsRouteMetricsItem {
+    pub path: String,
+    #[serde(flatten)]
+    pub stats: WsTrafficStats,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct WsMetricsItem {
+    #[serde(rename = "listenAddr")]
+    pub listen_addr: String,
+    #[serde(flatten)]
+    pub stats: WsTrafficStats,
+    /// 因空闲超时或心跳无响应被关闭的连接数
+    #[serde(rename = "timedOut")]
+    pub timed_out: u64,
+    pub routes: Vec<WsRouteMetricsItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct StreamTrafficStats {
+    /// 当前活跃会话数
+    pub active: u64,
+    /// 累计会话数
+    pub total: u64,
+    /// 客户端 -> 上游 字节数
+    #[serde(rename = "bytesIn")]
+    pub bytes_in: u64,
+    /// 上游 -> 客户端 字节数
+    #[serde(rename = "bytesOut")]
+    pub bytes_out: u64,
+    /// 连接上游失败次数（仅 TCP）
+    #[serde(rename = "connectFailures")]
+    pub connect_failures: u64,
+    /// 因上游全部达到 max_conns 而被拒绝的连接数（仅 TCP）
+    pub saturated: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct StreamUpstreamMetricsItem {
+    pub addr: String,
+    #[serde(flatten)]
+    pub stats: StreamTrafficStats,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct StreamMetricsItem {
+    #[serde(rename = "listenAddr")]
+    pub listen_addr: String,
+    /// tcp / udp
+    pub protocol: String,
+    /// proxy_pass 指向的 upstream 名称
+    pub upstream: String,
+    #[serde(flatten)]
+    pub stats: StreamTrafficStats,
+    /// 当前吞吐（字节/秒），用于观察限速是否生效
+    #[serde(rename = "bytesInPerSec")]
+    pub bytes_in_per_sec: f64,
+    #[serde(rename = "bytesOutPerSec")]
+    pub bytes_out_per_sec: f64,
+    pub upstreams: Vec<StreamUpstreamMetricsItem>,
 }
@@ -0,0 +1,955 @@
+use super::backend::{
+    request_log_insert_builder, stream_log_insert_builder, system_metrics_insert_builder,
+    MetricsBackend, SYSTEM_METRICS_CHUNK_SIZE,
+};
+use super::db::{delete_request_logs_batch_query, MetricsDBTableStats, PurgeOutcome};
+use super::histogram::LatencyHistogram;
+use super::query::{
+    dashboard_time_series, key_values, latency_dist_query, latency_values_query,
+    merge_normalized_top, push_dashboard_scope, route_latency_query, route_stats_query,
+    route_top_paths_query, status_code_counts_query, status_code_series_query,
+    system_metrics_history_query, top_errors_query, upstream_dist_query, upstream_stats_query,
+    HistoricalMetricsParts, HistoricalScope, LatencyBucketRow, PhaseTimingAcc, RouteStatRow,
+    RouteStatsParts, UpstreamStatRow, AGENT_GROUP_SCAN_LIMIT, DISTINCT_LISTEN_ADDRS_SQL,
+};
+use super::rollup::{
+    raw_series_query, rollup_request_logs_step_pg, rollup_series_query, series_points, RollupPoint,
+};
+use super::*;
+use crate::system_metrics::{HistoricalRow, SystemMetricsPoint};
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::Postgres;
+
+const PG_POOL_SIZE: u32 = 5;
+const PG_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// Postgres 绑定参数上限 65535，按 17 列约 3800 行
+const PG_STREAM_LOG_CHUNK_SIZE: usize = 2_000;
+
+// (表名, 时间列)，用于统计与 VACUUM
+const PG_STATS_TABLES: [(&str, &str); 7] = [
+    ("request_logs", "timestamp"),
+    ("request_logs_rollup_1m", "bucket"),
+    ("request_logs_rollup_1h", "bucket"),
+    ("ws_sessions", "timestamp"),
+    ("stream_logs", "timestamp"),
+    ("system_metrics", "timestamp"),
+    ("blacklist", "created_at"),
+];
+
+/// 多节点共享的中心化存储；表结构与 SQLite 一致，仅类型换成 Postgres 对应类型
+pub(super) struct PostgresBackend {
+    pool: PgPool,
+}
+
+impl PostgresBackend {
+    pub(super) async fn connect(url: &str) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(PG_POOL_SIZE)
+            .acquire_timeout(PG_CONNECT_TIMEOUT)
+            .connect(url)
+            .await
+            .with_context(|| format!("连接 Postgres 失败: {}", redact_url(url)))?;
+        Ok(Self { pool })
+    }
+
+    pub(super) async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    pub(super) async fn migrate(&self) -> Result<()> {
+        for sql in [
+            r#"CREATE TABLE IF NOT EXISTS request_logs (
+              id BIGSERIAL PRIMARY KEY,
+              timestamp BIGINT NOT NULL,
+              listen_addr TEXT NOT NULL,
+              client_ip TEXT NOT NULL,
+              remote_ip TEXT NOT NULL DEFAULT '',
+              method TEXT NOT NULL,
+              request_path TEXT NOT NULL,
+              request_host TEXT NOT NULL,
+              status_code INTEGER NOT NULL,
+              upstream TEXT NOT NULL,
+              latency_ms DOUBLE PRECISION NOT NULL,
+              guard_ms DOUBLE PRECISION NOT NULL DEFAULT 0,
+              prepare_ms DOUBLE PRECISION NOT NULL DEFAULT 0,
+              upstream_ms DOUBLE PRECISION NOT NULL DEFAULT 0,
+              user_agent TEXT NOT NULL,
+              referer TEXT NOT NULL,
+              matched_route_id TEXT NOT NULL DEFAULT '',
+              bytes_sent BIGINT NOT NULL DEFAULT 0,
+              country TEXT NOT NULL DEFAULT '',
+              asn TEXT NOT NULL DEFAULT '',
+              sample_rate DOUBLE PRECISION NOT NULL DEFAULT 1
+            )"#,
+            "ALTER TABLE request_logs ADD COLUMN IF NOT EXISTS country TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE request_logs ADD COLUMN IF NOT EXISTS asn TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE request_logs ADD COLUMN IF NOT EXISTS sample_rate DOUBLE PRECISION NOT NULL DEFAULT 1",
+            "CREATE INDEX IF NOT EXISTS idx_request_logs_ts ON request_logs(timestamp)",
+            "CREATE INDEX IF NOT EXISTS idx_request_logs_listen_ts ON request_logs(listen_addr, timestamp)",
+            "CREATE INDEX IF NOT EXISTS idx_request_logs_route_ts ON request_logs(matched_route_id, timestamp)",
+            "CREATE INDEX IF NOT EXISTS idx_request_logs_status_ts ON request_logs(status_code, timestamp)",
+            "CREATE INDEX IF NOT EXISTS idx_request_logs_host_ts ON request_logs(request_host, timestamp)",
+            "CREATE INDEX IF NOT EXISTS idx_request_logs_country_ts ON request_logs(country, timestamp)",
+            "CREATE INDEX IF NOT EXISTS idx_request_logs_client_ip_ts ON request_logs(client_ip, timestamp)",
+            "CREATE INDEX IF NOT EXISTS idx_request_logs_errors_ts ON request_logs(timestamp) WHERE status_code >= 400",
+            r#"CREATE TABLE IF NOT EXISTS stream_logs (
+              id BIGSERIAL PRIMARY KEY,
+              timestamp BIGINT NOT NULL,
+              listen_port INTEGER NOT NULL,
+              protocol TEXT NOT NULL,
+              client_ip TEXT NOT NULL,
+              upstream_addr TEXT NOT NULL,
+              duration_ms DOUBLE PRECISION NOT NULL,
+              bytes_in BIGINT NOT NULL,
+              bytes_out BIGINT NOT NULL,
+              disconnect_reason TEXT NOT NULL,
+              attempts INTEGER NOT NULL DEFAULT 1
+            )"#,
+            "CREATE INDEX IF NOT EXISTS idx_stream_logs_port_ts ON stream_logs(listen_port, timestamp)",
+            r#"CREATE TABLE IF NOT EXISTS ws_sessions (
+              id BIGSERIAL PRIMARY KEY,
+              timestamp BIGINT NOT NULL,
+              listen_addr TEXT NOT NULL,
+              client_ip TEXT NOT NULL,
+              request_path TEXT NOT NULL,
+              upstream TEXT NOT NULL,
+              duration_ms DOUBLE PRECISION NOT NULL,
+              messages_in BIGINT NOT NULL,
+              messages_out BIGINT NOT NULL,
+              bytes_in BIGINT NOT NULL,
+              bytes_out BIGINT NOT NULL
+            )"#,
+            "CREATE INDEX IF NOT EXISTS idx_ws_sessions_listen_ts ON ws_sessions(listen_addr, timestamp)",
+            r#"CREATE TABLE IF NOT EXISTS blacklist (
+              id BIGSERIAL PRIMARY KEY,
+              ip TEXT NOT NULL UNIQUE,
+              reason TEXT,
+              expires_at BIGINT NOT NULL,
+              created_at BIGINT NOT NULL
+            )"#,
+            r#"CREATE TABLE IF NOT EXISTS system_metrics (
+              id BIGSERIAL PRIMARY KEY,
+              timestamp BIGINT NOT NULL,
+              cpu_usage_percent DOUBLE PRECISION NOT NULL,
+              load1 DOUBLE PRECISION NOT NULL,
+              load5 DOUBLE PRECISION NOT NULL,
+              load15 DOUBLE PRECISION NOT NULL,
+              mem_total_bytes BIGINT NOT NULL,
+              mem_available_bytes BIGINT NOT NULL,
+              mem_used_bytes BIGINT NOT NULL,
+              mem_used_percent DOUBLE PRECISION NOT NULL,
+              swap_total_bytes BIGINT NOT NULL,
+              swap_free_bytes BIGINT NOT NULL,
+              swap_used_bytes BIGINT NOT NULL,
+              swap_used_percent DOUBLE PRECISION NOT NULL,
+              net_rx_bytes BIGINT NOT NULL,
+              net_tx_bytes BIGINT NOT NULL,
+              net_rx_bps DOUBLE PRECISION NOT NULL,
+              net_tx_bps DOUBLE PRECISION NOT NULL,
+              disk_read_bytes BIGINT NOT NULL,
+              disk_write_bytes BIGINT NOT NULL,
+              disk_read_bps DOUBLE PRECISION NOT NULL,
+              disk_write_bps DOUBLE PRECISION NOT NULL,
+              tcp_established BIGINT NOT NULL,
+              tcp_time_wait BIGINT NOT NULL,
+              tcp_close_wait BIGINT NOT NULL,
+              process_count BIGINT NOT NULL,
+              fd_used BIGINT NOT NULL,
+              fd_max BIGINT NOT NULL,
+              fd_usage_percent DOUBLE PRECISION NOT NULL,
+              procs_running BIGINT NOT NULL,
+              procs_blocked BIGINT NOT NULL,
+              context_switches BIGINT NOT NULL,
+              processes_forked_total BIGINT NOT NULL,
+              uptime_seconds DOUBLE PRECISION NOT NULL
+            )"#,
+            "CREATE INDEX IF NOT EXISTS idx_system_metrics_ts ON system_metrics(timestamp)",
+            "CREATE INDEX IF NOT EXISTS idx_ws_sessions_ts ON ws_sessions(timestamp)",
+            "CREATE INDEX IF NOT EXISTS idx_stream_logs_ts ON stream_logs(timestamp)",
+            r#"CREATE TABLE IF NOT EXISTS request_logs_rollup_1m (
+              bucket BIGINT NOT NULL,
+              listen_addr TEXT NOT NULL,
+              matched_route_id TEXT NOT NULL,
+              total BIGINT NOT NULL,
+              s2xx BIGINT NOT NULL,
+              s3xx BIGINT NOT NULL,
+              s4xx BIGINT NOT NULL,
+              s5xx BIGINT NOT NULL,
+              latency_sum_ms DOUBLE PRECISION NOT NULL,
+              latency_max_ms DOUBLE PRECISION NOT NULL,
+              upstream_count BIGINT NOT NULL,
+              upstream_sum_ms DOUBLE PRECISION NOT NULL,
+              bytes_sent BIGINT NOT NULL,
+              PRIMARY KEY (bucket, listen_addr, matched_route_id)
+            )"#,
+            r#"CREATE TABLE IF NOT EXISTS request_logs_rollup_1h (
+              bucket BIGINT NOT NULL,
+              listen_addr TEXT NOT NULL,
+              matched_route_id TEXT NOT NULL,
+              total BIGINT NOT NULL,
+              s2xx BIGINT NOT NULL,
+              s3xx BIGINT NOT NULL,
+              s4xx BIGINT NOT NULL,
+              s5xx BIGINT NOT NULL,
+              latency_sum_ms DOUBLE PRECISION NOT NULL,
+              latency_max_ms DOUBLE PRECISION NOT NULL,
+              upstream_count BIGINT NOT NULL,
+              upstream_sum_ms DOUBLE PRECISION NOT NULL,
+              bytes_sent BIGINT NOT NULL,
+              PRIMARY KEY (bucket, listen_addr, matched_route_id)
+            )"#,
+            r#"CREATE TABLE IF NOT EXISTS request_logs_rollup_state (
+              id INTEGER PRIMARY KEY CHECK (id = 1),
+              rolled_until BIGINT NOT NULL
+            )"#,
+            // 水位从现有最早一条原始日志所在分钟开始；多个节点同时迁移时只有第一个生效
+            r#"INSERT INTO request_logs_rollup_state (id, rolled_until)
+            SELECT 1, COALESCE((MIN(timestamp) / 60) * 60, 0) FROM request_logs
+            ON CONFLICT (id) DO NOTHING"#,
+        ] {
+            sqlx::query(sql)
+                .execute(&self.pool)
+                .await
+                .context("初始化 Postgres 表结构失败")?;
+        }
+        Ok(())
+    }
+
+    async fn database_size(&self) -> Result<i64> {
+        let size: i64 = sqlx::query_scalar("SELECT pg_database_size(current_database())")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(size)
+    }
+
+    /// 分批删除 cutoff 之前的行，批次之间短暂让出
+    async fn delete_before(&self, table: &str, cutoff: i64) -> Result<u64> {
+        let sql = format!(
+            "DELETE FROM {table} WHERE id IN (SELECT id FROM {table} WHERE timestamp < $1 LIMIT $2)"
+        );
+        let mut deleted = 0u64;
+        loop {
+            let n = sqlx::query(&sql)
+                .bind(cutoff)
+                .bind(RETENTION_PURGE_BATCH)
+                .execute(&self.pool)
+                .await
+                .with_context(|| format!("清理 {} 失败", table))?
+                .rows_affected();
+            deleted += n;
+            if n < RETENTION_PURGE_BATCH as u64 {
+                return Ok(deleted);
+            }
+            tokio::time::sleep(RETENTION_PURGE_BATCH_PAUSE).await;
+        }
+    }
+
+    async fn top_list(
+        &self,
+        req: &DashboardStatsRequest,
+        item_col: &str,
+        value_expr: &str,
+        extra_where: &str,
+    ) -> Result<Vec<TopListItem>> {
+        self.top_list_n(req, item_col, value_expr, extra_where, 10)
+            .await
+    }
+
+    async fn top_list_n(
+        &self,
+        req: &DashboardStatsRequest,
+        item_col: &str,
+        value_expr: &str,
+        extra_where: &str,
+        limit: i64,
+    ) -> Result<Vec<TopListItem>> {
+        let listen_addr = non_empty_trimmed(&req.listen_addr);
+        let route_id = non_empty_trimmed(&req.matched_route_id);
+
+        let mut qb = QueryBuilder::<Postgres>::new(format!(
+            "SELECT {item_col} AS item, {value_expr} AS count FROM request_logs WHERE timestamp >= "
+        ));
+        qb.push_bind(req.start_time)
+            .push(" AND timestamp <= ")
+            .push_bind(req.end_time)
+            .push(extra_where);
+        push_dashboard_scope(&mut qb, listen_addr, route_id);
+        qb.push(format!(" GROUP BY {item_col} ORDER BY count DESC LIMIT "))
+            .push_bind(limit);
+
+        Ok(qb
+            .build_query_as::<TopListItem>()
+            .fetch_all(&self.pool)
+            .await?)
+    }
+}
+
+/// 日志/状态中展示连接串时隐藏密码
+pub(super) fn redact_url(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(mut u) if u.password().is_some() => {
+            let _ = u.set_password(Some("***"));
+            u.to_string()
+        }
+        Ok(u) => u.to_string(),
+        Err(_) => "postgres://***".to_string(),
+    }
+}
+
+#[async_trait]
+impl MetricsBackend for PostgresBackend {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn insert_request_logs(&self, rows: &[RequestLogInsert]) -> Result<()> {
+        request_log_insert_builder::<Postgres>(rows)
+            .build()
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_stream_logs(&self, rows: &[StreamLogInsert]) -> Result<()> {
+        for chunk in rows.chunks(PG_STREAM_LOG_CHUNK_SIZE) {
+            stream_log_insert_builder::<Postgres>(chunk)
+                .build()
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_ws_session(&self, rec: &WsSessionInsert) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO ws_sessions(timestamp, listen_addr, client_ip, request_path, upstream, duration_ms, messages_in, messages_out, bytes_in, bytes_out) VALUES($1,$2,$3,$4,$5,$6,$7,$8,$9,$10)",
+        )
+        .bind(rec.timestamp)
+        .bind(&rec.listen_addr)
+        .bind(&rec.client_ip)
+        .bind(&rec.request_path)
+        .bind(&rec.upstream)
+        .bind(rec.duration_ms)
+        .bind(rec.messages_in)
+        .bind(rec.messages_out)
+        .bind(rec.bytes_in)
+        .bind(rec.bytes_out)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn query_request_logs(
+        &self,
+        req: QueryRequestLogsRequest,
+    ) -> Result<QueryRequestLogsResponse> {
+        let page = RequestLogsPage::from_request(&req)?;
+        let method = normalized_method(&req);
+        let filters = RequestLogQueryFilters::from_request(&req, method.as_deref());
+
+        let total: i64 = if page.wants_total(&req) {
+            let mut count_qb = QueryBuilder::<Postgres>::new("SELECT COUNT(1) FROM request_logs");
+            append_request_logs_where(&mut count_qb, filters);
+            count_qb
+                .build_query_as::<(i64,)>()
+                .fetch_one(&self.pool)
+                .await?
+                .0
+        } else {
+            -1
+        };
+        let total_page = total_pages(total, page.page_size());
+
+        let mut sel_qb = QueryBuilder::<Postgres>::new(
+            "SELECT id, timestamp, listen_addr, client_ip, remote_ip, method, request_path, request_host, status_code, upstream, latency_ms, guard_ms, prepare_ms, upstream_ms, user_agent, referer, matched_route_id, bytes_sent, country, asn, sample_rate FROM request_logs",
+        );
+        append_request_logs_where(&mut sel_qb, filters);
+        push_request_logs_page(&mut sel_qb, &req, page);
+        let logs = sel_qb
+            .build_query_as::<RequestLog>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(QueryRequestLogsResponse {
+            next_cursor: page.next_cursor(&logs),
+            logs,
+            total,
+            total_page,
+        })
+    }
+
+    async fn request_log_by_id(&self, id: i64) -> Result<Option<RequestLog>> {
+        let log = sqlx::query_as::<_, RequestLog>(
+            "SELECT id, timestamp, listen_addr, client_ip, remote_ip, method, request_path, request_host, status_code, upstream, latency_ms, guard_ms, prepare_ms, upstream_ms, user_agent, referer, matched_route_id, bytes_sent, country, asn, sample_rate FROM request_logs WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(log)
+    }
+
+    async fn dashboard_stats(&self, req: DashboardStatsRequest) -> Result<DashboardStatsResponse> {
+        let gran = req.granularity_secs.max(1);
+        let listen_addr = non_empty_trimmed(&req.listen_addr);
+        let route_id = non_empty_trimmed(&req.matched_route_id);
+
+        // 整分钟粒度时水位以下读长期汇总表
+        let points = series_points(
+            self,
+            req.start_time,
+            req.end_time,
+            gran,
+            listen_addr,
+            route_id,
+        )
+        .await?;
+        let time_series = dashboard_time_series(&points);
+
+        let top_paths = self.top_list(&req, "request_path", "COUNT(1)", "").await?;
+        let top_ips = self.top_list(&req, "client_ip", "COUNT(1)", "").await?;
+        let top_routes = self
+            .top_list(
+                &req,
+                "matched_route_id",
+                "COUNT(1)",
+                " AND trim(matched_route_id) != ''",
+            )
+            .await?;
+        let top_route_errors = self
+            .top_list(
+                &req,
+                "matched_route_id",
+                "COUNT(1)",
+                " AND trim(matched_route_id) != '' AND status_code >= 400",
+            )
+            .await?;
+        let top_paths_by_bytes = self
+            .top_list(
+                &req,
+                "request_path",
+                "CAST(SUM(bytes_sent) AS BIGINT)",
+                " AND bytes_sent > 0",
+            )
+            .await?;
+        let top_upstream_errors = self
+            .top_list(&req, "upstream", "COUNT(1)", " AND status_code >= 400")
+            .await?;
+        let top_hosts = self
+            .top_list(&req, "request_host", "COUNT(1)", " AND request_host != ''")
+            .await?;
+        let top_countries = self
+            .top_list(&req, "country", "COUNT(1)", " AND country != ''")
+            .await?;
+
+        let mut breakdown_qb = QueryBuilder::<Postgres>::new(
+            "SELECT matched_route_id AS route_id, COUNT(1) AS requests, CAST(SUM(CASE WHEN status_code >= 400 THEN 1 ELSE 0 END) AS BIGINT) AS errors, AVG(latency_ms) AS avg_latency_ms FROM request_logs WHERE timestamp >= ",
+        );
+        breakdown_qb
+            .push_bind(req.start_time)
+            .push(" AND timestamp <= ")
+            .push_bind(req.end_time)
+            .push(" AND trim(matched_route_id) != ''");
+        push_dashboard_scope(&mut breakdown_qb, listen_addr, route_id);
+        breakdown_qb.push(" GROUP BY matched_route_id ORDER BY requests DESC LIMIT 10");
+        let route_breakdown = breakdown_qb
+            .build_query_as::<RouteTrafficItem>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        // 总体由序列各桶累加，与序列口径一致
+        let mut total = RollupPoint::default();
+        for p in points.iter() {
+            total.merge(p);
+        }
+        let total_requests = total.total;
+        let success_rate = if total_requests > 0 {
+            total.s2xx as f64 / total_requests as f64
+        } else {
+            0.0
+        };
+
+        let mut phase_qb = QueryBuilder::<Postgres>::new(
+            "SELECT guard_ms, prepare_ms, upstream_ms FROM request_logs WHERE timestamp >= ",
+        );
+        phase_qb
+            .push_bind(req.start_time)
+            .push(" AND timestamp <= ")
+            .push_bind(req.end_time);
+        push_dashboard_scope(&mut phase_qb, listen_addr, route_id);
+        let mut phase_acc = PhaseTimingAcc::default();
+        let mut phase_rows = phase_qb
+            .build_query_as::<(f64, f64, f64)>()
+            .fetch(&self.pool);
+        while let Some(row) = phase_rows.try_next().await? {
+            phase_acc.add(row);
+        }
+        drop(phase_rows);
+
+        let (top_user_agents, top_referers) = if req.include_agents {
+            let mut tops = Vec::with_capacity(2);
+            for column in ["user_agent", "referer"] {
+                let rows = self
+                    .top_list_n(
+                        &req,
+                        column,
+                        "COUNT(1)",
+                        &format!(" AND {column} != '' AND {column} != '-'"),
+                        AGENT_GROUP_SCAN_LIMIT,
+                    )
+                    .await?;
+                tops.push(rows.into_iter().map(|r| (r.item, r.count)).collect());
+            }
+            let referers = tops.pop().unwrap_or_default();
+            let agents = tops.pop().unwrap_or_default();
+            (
+                Some(merge_normalized_top(agents, normalize_user_agent_for_top)),
+                Some(merge_normalized_top(referers, normalize_referer_for_top)),
+            )
+        } else {
+            (None, None)
+        };
+
+        Ok(DashboardStatsResponse {
+            time_series,
+            top_paths,
+            top_ips,
+            top_routes,
+            top_route_errors,
+            top_upstream_errors,
+            top_paths_by_bytes,
+            total_requests,
+            success_rate,
+            avg_latency_ms: total.avg_latency_ms(),
+            avg_upstream_ms: total.avg_upstream_ms(),
+            total_bytes_sent: total.bytes_sent,
+            phase_timing: Some(phase_acc.finish()),
+            top_user_agents,
+            top_referers,
+            top_hosts: Some(top_hosts),
+            top_countries: Some(top_countries),
+            route_breakdown: Some(route_breakdown),
+        })
+    }
+
+    async fn query_stream_logs(
+        &self,
+        req: QueryStreamLogsRequest,
+    ) -> Result<QueryStreamLogsResponse> {
+        let (page_size, offset) = stream_logs_page(&req);
+        let filters = StreamLogQueryFilters::from_request(&req);
+
+        let mut count_qb = QueryBuilder::<Postgres>::new("SELECT COUNT(1) FROM stream_logs");
+        append_stream_logs_where(&mut count_qb, &filters);
+        let total: i64 = count_qb
+            .build_query_as::<(i64,)>()
+            .fetch_one(&self.pool)
+            .await?
+            .0;
+        let total_page = total_pages(total, page_size);
+
+        let mut sel_qb = QueryBuilder::<Postgres>::new(
+            "SELECT id, timestamp, listen_port, protocol, client_ip, upstream_addr, duration_ms, bytes_in, bytes_out, disconnect_reason, attempts FROM stream_logs",
+        );
+        append_stream_logs_where(&mut sel_qb, &filters);
+        sel_qb
+            .push(" ORDER BY timestamp DESC, id DESC LIMIT ")
+            .push_bind(page_size)
+            .push(" OFFSET ")
+            .push_bind(offset);
+        let logs = sel_qb
+            .build_query_as::<StreamLog>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(QueryStreamLogsResponse {
+            logs,
+            total,
+            total_page,
+        })
+    }
+
+    async fn distinct_listen_addrs(&self) -> Result<Vec<String>> {
+        let rows = sqlx::query_scalar::<_, String>(DISTINCT_LISTEN_ADDRS_SQL)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows)
+    }
+
+    async fn status_code_counts(
+        &self,
+        req: &StatusCodeBreakdownRequest,
+        limit: i64,
+    ) -> Result<Vec<StatusCodeCount>> {
+        let rows = status_code_counts_query::<Postgres>(req, limit)
+            .build_query_as::<StatusCodeCount>()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows)
+    }
+
+    async fn status_code_series(
+        &self,
+        req: &StatusCodeBreakdownRequest,
+        codes: &[i32],
+        gran: i64,
+    ) -> Result<Vec<(i64, i32, i64)>> {
+        let rows = status_code_series_query::<Postgres>(req, codes, gran)
+            .build_query_as::<(i64, i32, i64)>()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows)
+    }
+
+    async fn upstream_stat_rows(&self, req: &UpstreamStatsRequest) -> Result<Vec<UpstreamStatRow>> {
+        let rows = upstream_stats_query::<Postgres>(req)
+            .build_query_as::<UpstreamStatRow>()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows)
+    }
+
+    async fn route_stats(
+        &self,
+        req: &RouteStatsRequest,
+        route_ids: Option<&[String]>,
+    ) -> Result<RouteStatsParts> {
+        let rows = route_stats_query::<Postgres>(req, route_ids)
+            .build_query_as::<RouteStatRow>()
+            .fetch_all(&self.pool)
+            .await
+            .context("查询路由统计失败")?;
+        if rows.is_empty() {
+            return Ok(RouteStatsParts {
+                rows,
+                latency: HashMap::new(),
+                top_paths: vec![],
+            });
+        }
+
+        let mut latency: HashMap<String, LatencyHistogram> = rows
+            .iter()
+            .map(|r| (r.0.clone(), LatencyHistogram::default()))
+            .collect();
+        let mut lat_qb = route_latency_query::<Postgres>(req, route_ids);
+        let mut lat_rows = lat_qb.build_query_as::<(String, f64)>().fetch(&self.pool);
+        while let Some((route_id, latency_ms)) = lat_rows.try_next().await? {
+            if let Some(hist) = latency.get_mut(&route_id) {
+                hist.add(latency_ms);
+            }
+        }
+        drop(lat_rows);
+
+        let top_paths = route_top_paths_query::<Postgres>(req, route_ids)
+            .build_query_as::<(String, String, i64)>()
+            .fetch_all(&self.pool)
+            .await
+            .context("查询路由 top paths 失败")?;
+
+        Ok(RouteStatsParts {
+            rows,
+            latency,
+            top_paths,
+        })
+    }
+
+    async fn historical_metrics(
+        &self,
+        scope: HistoricalScope<'_>,
+    ) -> Result<HistoricalMetricsParts> {
+        let upstream_dist = upstream_dist_query::<Postgres>(scope)
+            .build_query_as::<(String, i64)>()
+            .fetch_all(&self.pool)
+            .await?;
+        let top_route_err = top_errors_query::<Postgres>("request_path", scope)
+            .build_query_as::<(String, i64)>()
+            .fetch_all(&self.pool)
+            .await?;
+        let top_up_err = top_errors_query::<Postgres>("upstream", scope)
+            .build_query_as::<(String, i64)>()
+            .fetch_all(&self.pool)
+            .await?;
+        let latency_buckets = latency_dist_query::<Postgres>(scope)
+            .build_query_as::<LatencyBucketRow>()
+            .fetch_one(&self.pool)
+            .await?;
+
+        let mut latency = LatencyHistogram::default();
+        let mut qb = latency_values_query::<Postgres>(scope);
+        let mut rows = qb.build_query_as::<(f64,)>().fetch(&self.pool);
+        while let Some((v,)) = rows.try_next().await? {
+            latency.add(v);
+        }
+        drop(rows);
+
+        Ok(HistoricalMetricsParts {
+            upstream_dist: key_values(upstream_dist),
+            top_route_err: key_values(top_route_err),
+            top_up_err: key_values(top_up_err),
+            latency_buckets,
+            latency,
+        })
+    }
+
+    async fn rollup_watermark(&self) -> Option<i64> {
+        sqlx::query_scalar("SELECT rolled_until FROM request_logs_rollup_state WHERE id = 1")
+            .fetch_optional(&self.pool)
+            .await
+            .ok()
+            .flatten()
+    }
+
+    async fn rollup_step(&self, target: i64) -> Result<i64> {
+        rollup_request_logs_step_pg(&self.pool, target).await
+    }
+
+    async fn raw_series_points(
+        &self,
+        start: i64,
+        end: i64,
+        granularity: i64,
+        listen_addr: Option<&str>,
+        route_id: Option<&str>,
+    ) -> Result<Vec<RollupPoint>> {
+        raw_series_query::<Postgres>(start, end, granularity, listen_addr, route_id)
+            .build_query_as::<RollupPoint>()
+            .fetch_all(&self.pool)
+            .await
+            .context("查询请求日志时间序列失败")
+    }
+
+    async fn rollup_series_points(
+        &self,
+        resolution: i64,
+        start: i64,
+        end: i64,
+        granularity: i64,
+        listen_addr: Option<&str>,
+        route_id: Option<&str>,
+    ) -> Result<Vec<RollupPoint>> {
+        rollup_series_query::<Postgres>(resolution, start, end, granularity, listen_addr, route_id)
+            .build_query_as::<RollupPoint>()
+            .fetch_all(&self.pool)
+            .await
+            .context("查询请求日志汇总失败")
+    }
+
+    async fn request_logs_time_range(&self) -> Result<(Option<i64>, Option<i64>)> {
+        let range = sqlx::query_as("SELECT MIN(timestamp), MAX(timestamp) FROM request_logs")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(range)
+    }
+
+    async fn delete_request_logs_batch(
+        &self,
+        req: &ClearRequestLogsRequest,
+        limit: i64,
+    ) -> Result<u64> {
+        let n = delete_request_logs_batch_query::<Postgres>(req, limit)
+            .build()
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        Ok(n)
+    }
+
+    async fn reclaim_after_clear(&self, _deleted_rows: u64) -> Result<()> {
+        // 普通 VACUUM 只把空间标记为可复用，不会缩小库文件
+        sqlx::raw_sql("VACUUM (ANALYZE) request_logs")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn purge_expired(
+        &self,
+        cutoff: i64,
+        max_db_size_mb: Option<u64>,
+    ) -> Result<PurgeOutcome> {
+        if let Some(max_mb) = max_db_size_mb {
+            // 删除后空间要 VACUUM FULL 才会归还，无法按体积裁剪
+            crate::proxy::logging::push_log_line(format!(
+                "Metrics retention: max_db_size_mb ({} MB) is not supported on postgres, only retention_days is applied",
+                max_mb
+            ));
+        }
+
+        // 尚未计入长期汇总的原始请求日志先保留
+        let request_logs_cutoff = match self.rollup_watermark().await {
+            Some(rolled_until) => cutoff.min(rolled_until),
+            None => cutoff,
+        };
+
+        let mut expired_rows = 0u64;
+        for (table, cutoff) in [
+            ("request_logs", request_logs_cutoff),
+            ("ws_sessions", cutoff),
+            ("stream_logs", cutoff),
+        ] {
+            expired_rows += self.delete_before(table, cutoff).await?;
+        }
+
+        let remaining_request_logs: Option<i64> =
+            sqlx::query_scalar("SELECT COUNT(1) FROM request_logs")
+                .fetch_one(&self.pool)
+                .await
+                .ok();
+        Ok(PurgeOutcome {
+            expired_rows,
+            trimmed_rows: 0,
+            remaining_request_logs,
+        })
+    }
+
+    async fn db_stats(&self, path: String) -> Result<MetricsDBStats> {
+        let mut tables = Vec::with_capacity(PG_STATS_TABLES.len());
+        for (name, ts_col) in PG_STATS_TABLES {
+            let (row_count, min_ts, max_ts) = sqlx::query_as::<_, (i64, Option<i64>, Option<i64>)>(
+                &format!("SELECT COUNT(1), MIN({ts_col}), MAX({ts_col}) FROM {name}"),
+            )
+            .fetch_one(&self.pool)
+            .await
+            .with_context(|| format!("统计 {} 失败", name))?;
+            tables.push(MetricsDBTableStats {
+                name: name.to_string(),
+                row_count,
+                min_ts,
+                max_ts,
+            });
+        }
+
+        // 页/WAL 统计是 SQLite 概念，Postgres 只报告库大小
+        Ok(MetricsDBStats {
+            initialized: true,
+            path,
+            file_size_bytes: self.database_size().await.unwrap_or(0),
+            tables,
+            ..Default::default()
+        })
+    }
+
+    async fn storage_size_bytes(&self) -> Result<i64> {
+        self.database_size().await
+    }
+
+    async fn vacuum(&self) -> Result<()> {
+        let tables: Vec<&str> = PG_STATS_TABLES.iter().map(|(name, _)| *name).collect();
+        // VACUUM 不能在事务块中执行，用简单查询协议发送
+        sqlx::raw_sql(&format!("VACUUM (ANALYZE) {}", tables.join(", ")))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn status_detail(&self, base: MetricsDBStatus) -> Result<MetricsDBStatus> {
+        if let Err(e) = self.ping().await {
+            return Ok(MetricsDBStatus {
+                db_reachable: Some(false),
+                error: Some(format!("数据库连接检查失败: {}", e)),
+                ..base
+            });
+        }
+
+        let (cnt, min_ts, max_ts) = sqlx::query_as::<_, (i64, Option<i64>, Option<i64>)>(
+            "SELECT COUNT(1) AS cnt, MIN(timestamp) AS min_ts, MAX(timestamp) AS max_ts FROM request_logs",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let blacklist_count: Option<i64> = sqlx::query_scalar("SELECT COUNT(1) FROM blacklist")
+            .fetch_one(&self.pool)
+            .await
+            .ok();
+
+        Ok(MetricsDBStatus {
+            request_logs_count: Some(cnt),
+            request_logs_min_ts: min_ts,
+            request_logs_max_ts: max_ts,
+            db_file_size_bytes: self.database_size().await.ok(),
+            db_reachable: Some(true),
+            blacklist_count,
+            ..base
+        })
+    }
+
+    async fn insert_system_metrics(&self, points: &[SystemMetricsPoint]) -> Result<()> {
+        for chunk in points.chunks(SYSTEM_METRICS_CHUNK_SIZE) {
+            system_metrics_insert_builder::<Postgres>(chunk)
+                .build()
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn purge_system_metrics(&self, cutoff: i64) -> Result<u64> {
+        let deleted_rows = sqlx::query("DELETE FROM system_metrics WHERE timestamp < $1")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        Ok(deleted_rows)
+    }
+
+    async fn system_metrics_history(
+        &self,
+        start: i64,
+        end: i64,
+        granularity: i64,
+    ) -> Result<Vec<HistoricalRow>> {
+        let rows = system_metrics_history_query::<Postgres>(start, end, granularity)
+            .build_query_as::<HistoricalRow>()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows)
+    }
+
+    async fn active_blacklist(&self, now: i64) -> Result<Vec<(String, i64)>> {
+        let rows = sqlx::query_as::<_, (String, i64)>(
+            "SELECT ip, expires_at FROM blacklist WHERE expires_at=0 OR expires_at>$1",
+        )
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn upsert_blacklist(
+        &self,
+        ip: &str,
+        reason: &str,
+        expires_at: i64,
+        now: i64,
+    ) -> Result<BlacklistEntry> {
+        let rec = sqlx::query_as::<_, BlacklistEntry>(
+            "INSERT INTO blacklist(ip, reason, expires_at, created_at) VALUES($1,$2,$3,$4) ON CONFLICT (ip) DO UPDATE SET reason=EXCLUDED.reason, expires_at=EXCLUDED.expires_at, created_at=EXCLUDED.created_at RETURNING id, ip, reason, expires_at, created_at",
+        )
+        .bind(ip)
+        .bind(Some(reason))
+        .bind(expires_at)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(rec)
+    }
+
+    async fn delete_blacklist(&self, ip: &str) -> Result<()> {
+        sqlx::query("DELETE FROM blacklist WHERE ip=$1")
+            .bind(ip)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_blacklist(&self) -> Result<Vec<BlacklistEntry>> {
+        let rows = sqlx::query_as::<_, BlacklistEntry>(
+            "SELECT id, ip, reason, expires_at, created_at FROM blacklist ORDER BY created_at DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+}
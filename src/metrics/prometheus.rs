@@ -0,0 +1,563 @@
+use super::*;
+use crate::config::PrometheusConfig;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use dashmap::DashMap;
+use std::fmt::Write as _;
+use std::sync::atomic::AtomicI64;
+
+// 请求耗时直方图分桶上限（毫秒），输出时换算为秒
+const LATENCY_BUCKETS_MS: [f64; 11] = [
+    5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+];
+const STATUS_CLASSES: [&str; 5] = ["2xx", "3xx", "4xx", "5xx", "other"];
+
+/// 每个监听地址自启动以来的累计请求统计（实时窗口会裁剪，这里不裁剪）
+#[derive(Debug, Clone, Default)]
+pub(super) struct PromTotals {
+    by_status_class: [u64; 5],
+    latency_buckets: [u64; LATENCY_BUCKETS_MS.len()],
+    latency_sum_ms: f64,
+    latency_count: u64,
+}
+
+impl PromTotals {
+    #[inline]
+    pub(super) fn add(&mut self, status_code: i32, latency_ms: f64) {
+        let class = match status_code {
+            200..=299 => 0,
+            300..=399 => 1,
+            400..=499 => 2,
+            s if s >= 500 => 3,
+            _ => 4,
+        };
+        self.by_status_class[class] += 1;
+
+        if latency_ms.is_finite() {
+            let v = latency_ms.max(0.0);
+            self.latency_sum_ms += v;
+            self.latency_count += 1;
+            if let Some(i) = LATENCY_BUCKETS_MS.iter().position(|b| v <= *b) {
+                self.latency_buckets[i] += 1;
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &PromTotals) {
+        for (a, b) in self.by_status_class.iter_mut().zip(other.by_status_class) {
+            *a += b;
+        }
+        for (a, b) in self.latency_buckets.iter_mut().zip(other.latency_buckets) {
+            *a += b;
+        }
+        self.latency_sum_ms += other.latency_sum_ms;
+        self.latency_count += other.latency_count;
+    }
+}
+
+/// 不经过请求日志的计数：进行中请求、限流拒绝、上游请求失败、慢请求
+#[derive(Default)]
+struct ListenerCounters {
+    in_flight: AtomicI64,
+    rate_limited: AtomicU64,
+    upstream_failures: AtomicU64,
+    slow_requests: AtomicU64,
+}
+
+static LISTENER_COUNTERS: Lazy<DashMap<String, Arc<ListenerCounters>>> = Lazy::new(DashMap::new);
+
+fn listener_counters(listen_addr: &str) -> Arc<ListenerCounters> {
+    if let Some(c) = LISTENER_COUNTERS.get(listen_addr) {
+        return c.clone();
+    }
+    LISTENER_COUNTERS
+        .entry(listen_addr.to_string())
+        .or_default()
+        .clone()
+}
+
+/// 请求处理期间持有，drop 时进行中计数减一
+pub struct InFlightGuard(Arc<ListenerCounters>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub fn track_in_flight(listen_addr: &str) -> InFlightGuard {
+    let counters = listener_counters(listen_addr);
+    counters.in_flight.fetch_add(1, Ordering::Relaxed);
+    InFlightGuard(counters)
+}
+
+static IN_FLIGHT_SAMPLER_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// 每秒把各监听的进行中请求数写入实时聚合，便于与耗时曲线对照
+pub fn start_in_flight_sampler() {
+    if IN_FLIGHT_SAMPLER_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    tauri::async_runtime::spawn(async {
+        let mut ticker = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            ticker.tick().await;
+            sample_in_flight(chrono::Utc::now().timestamp());
+        }
+    });
+}
+
+fn sample_in_flight(now: i64) {
+    let samples: Vec<(String, i64)> = LISTENER_COUNTERS
+        .iter()
+        .map(|e| (e.key().clone(), e.value().in_flight.load(Ordering::Relaxed)))
+        .filter(|(_, n)| *n > 0)
+        .collect();
+    let total: i64 = samples.iter().map(|(_, n)| n).sum();
+    let global = (total > 0).then_some(("全局", total));
+    for (key, value) in samples.iter().map(|(k, n)| (k.as_str(), *n)).chain(global) {
+        let idx = (hash_fnv1a_64(key) as usize) % REALTIME_SHARDS;
+        REALTIME_AGG_SHARDS[idx]
+            .write()
+            .sample_in_flight(key, now, value);
+    }
+}
+
+pub fn record_rate_limited(listen_addr: &str) {
+    listener_counters(listen_addr)
+        .rate_limited
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+/// 总耗时超过慢请求阈值
+pub fn record_slow_request(listen_addr: &str) {
+    listener_counters(listen_addr)
+        .slow_requests
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+/// 各监听地址累计的慢请求数
+pub fn slow_request_counts() -> Vec<(String, u64)> {
+    LISTENER_COUNTERS
+        .iter()
+        .map(|e| {
+            (
+                e.key().clone(),
+                e.value().slow_requests.load(Ordering::Relaxed),
+            )
+        })
+        .filter(|(_, n)| *n > 0)
+        .collect()
+}
+
+/// 上游连接/请求失败（未拿到上游响应）
+pub fn record_upstream_failure(listen_addr: &str) {
+    listener_counters(listen_addr)
+        .upstream_failures
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+fn escape_label(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn write_histogram(out: &mut String, name: &str, listen_addr: &str, t: &PromTotals) {
+    let la = escape_label(listen_addr);
+    let mut cumulative = 0u64;
+    for (i, le) in LATENCY_BUCKETS_MS.iter().enumerate() {
+        cumulative += t.latency_buckets[i];
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{listen_addr=\"{la}\",le=\"{}\"}} {cumulative}",
+            le / 1000.0
+        );
+    }
+    let _ = writeln!(
+        out,
+        "{name}_bucket{{listen_addr=\"{la}\",le=\"+Inf\"}} {}",
+        t.latency_count
+    );
+    let _ = writeln!(
+        out,
+        "{name}_sum{{listen_addr=\"{la}\"}} {}",
+        t.latency_sum_ms / 1000.0
+    );
+    let _ = writeln!(
+        out,
+        "{name}_count{{listen_addr=\"{la}\"}} {}",
+        t.latency_count
+    );
+}
+
+/// 生成 Prometheus 文本格式（数据来自实时聚合分片与内存计数器，不查库）
+pub fn render_prometheus_metrics() -> String {
+    let mut totals: BTreeMap<String, PromTotals> = BTreeMap::new();
+    let mut upstream_errors: HashMap<String, HashMap<String, i64>> = HashMap::new();
+    for shard in REALTIME_AGG_SHARDS.iter() {
+        let guard = shard.read();
+        for (k, t) in guard.totals.iter() {
+            totals.entry(k.clone()).or_default().merge(t);
+        }
+        merge_count_map(&mut upstream_errors, &guard.upstream_error_counts);
+    }
+
+    let mut counters: Vec<(String, Arc<ListenerCounters>)> = LISTENER_COUNTERS
+        .iter()
+        .map(|e| (e.key().clone(), e.value().clone()))
+        .collect();
+    counters.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    let mut out = String::with_capacity(8 * 1024);
+
+    write_header(
+        &mut out,
+        "sslproxy_http_requests_total",
+        "counter",
+        "HTTP requests handled, by listener and status class.",
+    );
+    for (la, t) in totals.iter() {
+        let la = escape_label(la);
+        for (class, n) in STATUS_CLASSES.iter().zip(t.by_status_class) {
+            let _ = writeln!(
+                out,
+                "sslproxy_http_requests_total{{listen_addr=\"{la}\",status_class=\"{class}\"}} {n}"
+            );
+        }
+    }
+
+    write_header(
+        &mut out,
+        "sslproxy_http_request_duration_seconds",
+        "histogram",
+        "HTTP request latency in seconds.",
+    );
+    for (la, t) in totals.iter() {
+        write_histogram(&mut out, "sslproxy_http_request_duration_seconds", la, t);
+    }
+
+    write_header(
+        &mut out,
+        "sslproxy_http_requests_in_flight",
+        "gauge",
+        "HTTP requests currently being handled.",
+    );
+    for (la, c) in counters.iter() {
+        let _ = writeln!(
+            out,
+            "sslproxy_http_requests_in_flight{{listen_addr=\"{}\"}} {}",
+            escape_label(la),
+            c.in_flight.load(Ordering::Relaxed).max(0)
+        );
+    }
+
+    write_header(
+        &mut out,
+        "sslproxy_upstream_failures_total",
+        "counter",
+        "Upstream requests that failed without a response.",
+    );
+    for (la, c) in counters.iter() {
+        let _ = writeln!(
+            out,
+            "sslproxy_upstream_failures_total{{listen_addr=\"{}\"}} {}",
+            escape_label(la),
+            c.upstream_failures.load(Ordering::Relaxed)
+        );
+    }
+
+    write_header(
+        &mut out,
+        "sslproxy_upstream_error_responses_total",
+        "counter",
+        "Responses with status >= 400, by listener and upstream.",
+    );
+    let mut upstream_rows: Vec<(&String, &String, i64)> = upstream_errors
+        .iter()
+        .filter(|(la, _)| la.as_str() != "全局")
+        .flat_map(|(la, m)| m.iter().map(move |(up, n)| (la, up, *n)))
+        .collect();
+    upstream_rows.sort_unstable();
+    for (la, up, n) in upstream_rows {
+        let _ = writeln!(
+            out,
+            "sslproxy_upstream_error_responses_total{{listen_addr=\"{}\",upstream=\"{}\"}} {n}",
+            escape_label(la),
+            escape_label(up)
+        );
+    }
+
+    write_header(
+        &mut out,
+        "sslproxy_rate_limited_total",
+        "counter",
+        "Requests rejected by the per-IP rate limiter.",
+    );
+    for (la, c) in counters.iter() {
+        let _ = writeln!(
+            out,
+            "sslproxy_rate_limited_total{{listen_addr=\"{}\"}} {}",
+            escape_label(la),
+            c.rate_limited.load(Ordering::Relaxed)
+        );
+    }
+
+    write_header(
+        &mut out,
+        "sslproxy_slow_requests_total",
+        "counter",
+        "Requests whose total latency reached the slow request threshold.",
+    );
+    for (la, c) in counters.iter() {
+        let _ = writeln!(
+            out,
+            "sslproxy_slow_requests_total{{listen_addr=\"{}\"}} {}",
+            escape_label(la),
+            c.slow_requests.load(Ordering::Relaxed)
+        );
+    }
+
+    let ws = crate::proxy::ws_proxy::ws_metrics_snapshot();
+    write_header(
+        &mut out,
+        "sslproxy_ws_connections_active",
+        "gauge",
+        "Open WebSocket connections.",
+    );
+    for item in ws.iter() {
+        let _ = writeln!(
+            out,
+            "sslproxy_ws_connections_active{{listen_addr=\"{}\"}} {}",
+            escape_label(&item.listen_addr),
+            item.stats.active
+        );
+    }
+    write_header(
+        &mut out,
+        "sslproxy_ws_connections_total",
+        "counter",
+        "WebSocket connections accepted.",
+    );
+    for item in ws.iter() {
+        let _ = writeln!(
+            out,
+            "sslproxy_ws_connections_total{{listen_addr=\"{}\"}} {}",
+            escape_label(&item.listen_addr),
+            item.stats.total
+        );
+    }
+
+    let streams = crate::proxy::stream_proxy::stream_metrics_snapshot();
+    write_header(
+        &mut out,
+        "sslproxy_stream_sessions_active",
+        "gauge",
+        "Open TCP/UDP stream sessions.",
+    );
+    for item in streams.iter() {
+        let _ = writeln!(
+            out,
+            "sslproxy_stream_sessions_active{{listen_addr=\"{}\",protocol=\"{}\"}} {}",
+            escape_label(&item.listen_addr),
+            item.protocol,
+            item.stats.active
+        );
+    }
+    write_header(
+        &mut out,
+        "sslproxy_stream_sessions_total",
+        "counter",
+        "TCP/UDP stream sessions accepted.",
+    );
+    for item in streams.iter() {
+        let _ = writeln!(
+            out,
+            "sslproxy_stream_sessions_total{{listen_addr=\"{}\",protocol=\"{}\"}} {}",
+            escape_label(&item.listen_addr),
+            item.protocol,
+            item.stats.total
+        );
+    }
+
+    write_header(
+        &mut out,
+        "sslproxy_log_dropped_total",
+        "counter",
+        "Realtime log lines dropped because the log queue was full.",
+    );
+    let _ = writeln!(
+        out,
+        "sslproxy_log_dropped_total {}",
+        crate::proxy::logging::LOG_DROPPED.load(Ordering::Relaxed)
+    );
+    write_header(
+        &mut out,
+        "sslproxy_request_log_dropped_total",
+        "counter",
+        "Request log rows that could not be persisted.",
+    );
+    let _ = writeln!(
+        out,
+        "sslproxy_request_log_dropped_total {}",
+        REQUEST_LOG_DROPPED.load(Ordering::Relaxed)
+    );
+    write_header(
+        &mut out,
+        "sslproxy_request_log_sampled_out_total",
+        "counter",
+        "Request log rows skipped by log sampling.",
+    );
+    let _ = writeln!(
+        out,
+        "sslproxy_request_log_sampled_out_total {}",
+        REQUEST_LOG_SAMPLED_OUT.load(Ordering::Relaxed)
+    );
+
+    out
+}
+
+// 当前运行的导出监听：(生效的配置, 关闭信号)
+type RunningExporter = (PrometheusConfig, tokio::sync::oneshot::Sender<()>);
+static EXPORTER: Lazy<parking_lot::Mutex<Option<RunningExporter>>> =
+    Lazy::new(|| parking_lot::Mutex::new(None));
+
+pub fn stop_prometheus_exporter() {
+    if let Some((_, tx)) = EXPORTER.lock().take() {
+        let _ = tx.send(());
+    }
+}
+
+/// 按配置启动/重启/关闭导出监听；配置未变化时不做任何事
+pub async fn apply_prometheus_config(cfg: Option<PrometheusConfig>) -> Result<()> {
+    let cfg = cfg.filter(|c| c.enabled);
+    if EXPORTER.lock().as_ref().map(|(c, _)| c) == cfg.as_ref() {
+        return Ok(());
+    }
+    stop_prometheus_exporter();
+    let Some(cfg) = cfg else {
+        return Ok(());
+    };
+
+    let (addr, _) = crate::proxy::parse_listen_addr(&cfg.listen_addr)?;
+    let path = format!("/{}", cfg.path.trim().trim_start_matches('/'));
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Prometheus exporter bind failed: {}", addr))?;
+
+    let token =
+        crate::secrets::resolve_optional(cfg.bearer_token.as_deref(), "prometheus.bearer_token")?;
+    let token: Option<Arc<str>> = token
+        .as_deref()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(Arc::from);
+    let router = axum::Router::new()
+        .route(&path, axum::routing::get(metrics_handler))
+        .with_state(token);
+
+    let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+    *EXPORTER.lock() = Some((cfg.clone(), tx));
+
+    crate::proxy::logging::push_log_line(format!(
+        "[PROMETHEUS] Exporter listening on http://{}{}",
+        addr, path
+    ));
+    tauri::async_runtime::spawn(async move {
+        let result = axum::serve(listener, router)
+            .with_graceful_shutdown(async move {
+                let _ = rx.await;
+            })
+            .await;
+        if let Err(e) = result {
+            crate::proxy::logging::push_log_line(format!(
+                "[PROMETHEUS] Exporter on {} stopped: {}",
+                addr, e
+            ));
+        }
+    });
+
+    Ok(())
+}
+
+async fn metrics_handler(State(token): State<Option<Arc<str>>>, headers: HeaderMap) -> Response {
+    if let Some(token) = token {
+        let presented = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::trim);
+        if presented != Some(&*token) {
+            return (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                "Unauthorized",
+            )
+                .into_response();
+        }
+    }
+
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        render_prometheus_metrics(),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let mut t = PromTotals::default();
+        t.add(200, 3.0);
+        t.add(404, 40.0);
+        t.add(502, 60_000.0);
+
+        let mut out = String::new();
+        write_histogram(&mut out, "h", ":443", &t);
+
+        assert!(out.contains("h_bucket{listen_addr=\":443\",le=\"0.005\"} 1\n"));
+        assert!(out.contains("h_bucket{listen_addr=\":443\",le=\"0.05\"} 2\n"));
+        assert!(out.contains("h_bucket{listen_addr=\":443\",le=\"10\"} 2\n"));
+        assert!(out.contains("h_bucket{listen_addr=\":443\",le=\"+Inf\"} 3\n"));
+        assert!(out.contains("h_count{listen_addr=\":443\"} 3\n"));
+        assert_eq!(t.by_status_class, [1, 0, 1, 1, 0]);
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn in_flight_samples_land_in_realtime_series() {
+        let la = "127.0.0.1:39101";
+        let idx = (hash_fnv1a_64(la) as usize) % REALTIME_SHARDS;
+        let a = track_in_flight(la);
+        let b = track_in_flight(la);
+        sample_in_flight(1_000_000);
+        drop(a);
+        sample_in_flight(1_000_001);
+        drop(b);
+        sample_in_flight(1_000_002);
+
+        let series = REALTIME_AGG_SHARDS[idx].read().per_sec[la].to_metrics_series();
+        assert_eq!(series.timestamps, vec![1_000_000, 1_000_001]);
+        assert_eq!(series.in_flight, Some(vec![2, 1]));
+        assert_eq!(series.counts, vec![0, 0]);
+
+        let minute = REALTIME_AGG_SHARDS[idx].read().per_min[la].to_metrics_series();
+        assert_eq!(minute.in_flight, Some(vec![2]));
+    }
+}
@@ -1,23 +1,18 @@
+use super::backend::SqliteBackend;
+use super::fts::{fts_backfill_below, fts_phrase};
+use super::histogram::LatencyHistogram;
+use super::rollup::{series_points, RollupPoint};
 use super::*;
+use futures_util::TryStreamExt;
 
-#[inline]
-fn percentile_index(len: usize, p: f64) -> usize {
-    if len == 0 {
-        return 0;
-    }
-    let idx = ((len - 1) as f64 * p).round() as usize;
-    idx.min(len - 1)
-}
-
-fn phase_stat_from_sorted(values: &[f64]) -> PhaseMetricStats {
-    if values.is_empty() {
+fn phase_stat(hist: &LatencyHistogram) -> PhaseMetricStats {
+    if hist.count() == 0 {
         return PhaseMetricStats::default();
     }
 
-    let sum: f64 = values.iter().copied().sum();
-    let avg = sum / values.len() as f64;
-    let p95 = values[percentile_index(values.len(), 0.95)];
-    let p99 = values[percentile_index(values.len(), 0.99)];
+    let avg = hist.avg();
+    let p95 = hist.percentile(0.95);
+    let p99 = hist.percentile(0.99);
 
     PhaseMetricStats {
         avg_ms: ((avg * 10000.0).round()) / 10000.0,
@@ -27,123 +22,105 @@ fn phase_stat_from_sorted(values: &[f64]) -> PhaseMetricStats {
 }
 
 pub async fn query_request_logs(req: QueryRequestLogsRequest) -> Result<QueryRequestLogsResponse> {
-    let Some(pool) = db_pool() else {
+    let Some(backend) = db_backend() else {
         return Ok(QueryRequestLogsResponse {
             logs: vec![],
             total: 0,
             total_page: 0,
+            next_cursor: None,
         });
     };
+    backend.query_request_logs(req).await
+}
 
-    let page_size = req.page_size.clamp(1, 200) as i64;
-    let page = req.page.max(1) as i64;
-    let offset = (page - 1) * page_size;
-
-    let listen_addr = req
-        .listen_addr
-        .as_deref()
-        .map(str::trim)
-        .filter(|s| !s.is_empty());
-    let upstream = req
-        .upstream
-        .as_deref()
-        .map(str::trim)
-        .filter(|s| !s.is_empty());
-    let request_path = req
-        .request_path
-        .as_deref()
-        .map(str::trim)
-        .filter(|s| !s.is_empty());
-    let client_ip = req
-        .client_ip
-        .as_deref()
-        .map(str::trim)
-        .filter(|s| !s.is_empty());
-    let status_code = req.status_code.filter(|c| *c > 0);
-    let matched_route_id = req
-        .matched_route_id
-        .as_deref()
-        .map(str::trim)
-        .filter(|s| !s.is_empty());
-    let sort_by = req
-        .sort_by
-        .as_deref()
-        .map(str::trim)
-        .filter(|s| !s.is_empty());
-    let sort_order = req
-        .sort_order
-        .as_deref()
-        .map(str::trim)
-        .map(|s| s.to_ascii_lowercase());
-
-    let sort_column = match sort_by {
-        Some("id") => "id",
-        Some("timestamp") => "timestamp",
-        Some("listen_addr") | Some("listenAddr") => "listen_addr",
-        Some("client_ip") | Some("clientIP") => "client_ip",
-        Some("remote_ip") | Some("remoteIP") => "remote_ip",
-        Some("method") => "method",
-        Some("request_path") | Some("requestPath") => "request_path",
-        Some("request_host") | Some("requestHost") => "request_host",
-        Some("status_code") | Some("statusCode") => "status_code",
-        Some("upstream") => "upstream",
-        Some("latency_ms") | Some("latencyMs") => "latency_ms",
-        Some("user_agent") | Some("userAgent") => "user_agent",
-        Some("referer") => "referer",
-        _ => "timestamp",
-    };
-    let sort_dir = match sort_order.as_deref() {
-        Some("asc") | Some("ascending") | Some("ascend") => "ASC",
-        _ => "DESC",
-    };
-    let filters = RequestLogQueryFilters {
-        start_time: req.start_time,
-        end_time: req.end_time,
-        listen_addr,
-        upstream,
-        request_path,
-        client_ip,
-        status_code,
-        matched_route_id,
+pub async fn get_request_log(id: i64) -> Result<Option<RequestLog>> {
+    let Some(backend) = db_backend() else {
+        return Ok(None);
     };
+    backend.request_log_by_id(id).await
+}
+
+pub(super) async fn query_request_logs_sqlite(
+    pool: &SqlitePool,
+    req: QueryRequestLogsRequest,
+) -> Result<QueryRequestLogsResponse> {
+    let page = RequestLogsPage::from_request(&req)?;
+    let method = normalized_method(&req);
+    let mut filters = RequestLogQueryFilters::from_request(&req, method.as_deref());
+    let search_phrase = filters.search.and_then(fts_phrase);
+    if let Some(phrase) = search_phrase.as_deref() {
+        if let Some(backfill_below) = fts_backfill_below(pool).await {
+            filters.search_fts = Some((phrase, backfill_below));
+        }
+    }
 
     // COUNT
-    let mut count_qb = QueryBuilder::new("SELECT COUNT(1) FROM request_logs");
-    append_request_logs_where(&mut count_qb, filters);
-
-    let total: i64 = count_qb
-        .build_query_as::<(i64,)>()
-        .fetch_one(&*pool)
-        .await?
-        .0;
-    let total_page = if total == 0 {
-        0
+    let total: i64 = if page.wants_total(&req) {
+        let mut count_qb = QueryBuilder::new("SELECT COUNT(1) FROM request_logs");
+        append_request_logs_where(&mut count_qb, filters);
+        count_qb.build_query_as::<(i64,)>().fetch_one(pool).await?.0
     } else {
-        (total + page_size - 1) / page_size
+        -1
     };
+    let total_page = total_pages(total, page.page_size());
 
     // SELECT
     let mut sel_qb = QueryBuilder::new(
-        "SELECT id, timestamp, listen_addr, client_ip, remote_ip, method, request_path, request_host, status_code, upstream, latency_ms, guard_ms, prepare_ms, upstream_ms, user_agent, referer, matched_route_id FROM request_logs"
+        "SELECT id, timestamp, listen_addr, client_ip, remote_ip, method, request_path, request_host, status_code, upstream, latency_ms, guard_ms, prepare_ms, upstream_ms, user_agent, referer, matched_route_id, bytes_sent, country, asn, sample_rate FROM request_logs"
     );
     append_request_logs_where(&mut sel_qb, filters);
+    push_request_logs_page(&mut sel_qb, &req, page);
+
+    let logs = sel_qb
+        .build_query_as::<RequestLog>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(QueryRequestLogsResponse {
+        next_cursor: page.next_cursor(&logs),
+        logs,
+        total,
+        total_page,
+    })
+}
+
+pub async fn query_stream_logs(req: QueryStreamLogsRequest) -> Result<QueryStreamLogsResponse> {
+    let Some(backend) = db_backend() else {
+        return Ok(QueryStreamLogsResponse {
+            logs: vec![],
+            total: 0,
+            total_page: 0,
+        });
+    };
+    backend.query_stream_logs(req).await
+}
+
+pub(super) async fn query_stream_logs_sqlite(
+    pool: &SqlitePool,
+    req: QueryStreamLogsRequest,
+) -> Result<QueryStreamLogsResponse> {
+    let (page_size, offset) = stream_logs_page(&req);
+    let filters = StreamLogQueryFilters::from_request(&req);
+
+    let mut count_qb = QueryBuilder::new("SELECT COUNT(1) FROM stream_logs");
+    append_stream_logs_where(&mut count_qb, &filters);
+
+    let total: i64 = count_qb.build_query_as::<(i64,)>().fetch_one(pool).await?.0;
+    let total_page = total_pages(total, page_size);
 
+    let mut sel_qb = QueryBuilder::new(
+        "SELECT id, timestamp, listen_port, protocol, client_ip, upstream_addr, duration_ms, bytes_in, bytes_out, disconnect_reason, attempts FROM stream_logs",
+    );
+    append_stream_logs_where(&mut sel_qb, &filters);
     sel_qb
-        .push(" ORDER BY ")
-        .push(sort_column)
-        .push(" ")
-        .push(sort_dir)
-        .push(", id DESC LIMIT ")
+        .push(" ORDER BY timestamp DESC, id DESC LIMIT ")
         .push_bind(page_size)
         .push(" OFFSET ")
         .push_bind(offset);
 
-    let logs = sel_qb
-        .build_query_as::<RequestLog>()
-        .fetch_all(&*pool)
-        .await?;
+    let logs = sel_qb.build_query_as::<StreamLog>().fetch_all(pool).await?;
 
-    Ok(QueryRequestLogsResponse {
+    Ok(QueryStreamLogsResponse {
         logs,
         total,
         total_page,
@@ -176,9 +153,35 @@ pub fn get_metrics() -> MetricsPayload {
             &guard.upstream_error_counts,
         );
         merge_count_map(&mut merged.upstream_counts, &guard.upstream_counts);
+        for (k, acc) in guard.upstream_stats.iter() {
+            merged
+                .upstream_stats
+                .entry(k.clone())
+                .or_default()
+                .merge(acc);
+        }
     }
 
-    let payload = merged.to_payload();
+    let mut payload = merged.to_payload();
+    let ws_metrics = crate::proxy::ws_proxy::ws_metrics_snapshot();
+    if !ws_metrics.is_empty() {
+        payload.ws_metrics = Some(ws_metrics);
+    }
+    let stream_metrics = crate::proxy::stream_proxy::stream_metrics_snapshot();
+    if !stream_metrics.is_empty() {
+        payload.stream_metrics = Some(stream_metrics);
+    }
+    let slow = super::slow_request_counts();
+    if !slow.is_empty() {
+        payload.slow_requests = Some(
+            slow.into_iter()
+                .map(|(key, value)| KeyValue {
+                    key,
+                    value: value as i64,
+                })
+                .collect(),
+        );
+    }
     {
         let mut cache = METRICS_CACHE.write();
         *cache = Some((Instant::now(), payload.clone()));
@@ -186,42 +189,145 @@ pub fn get_metrics() -> MetricsPayload {
     payload
 }
 
+/// 增量推送：只保留时间戳不早于 since 的秒级桶与 since 所在分钟起的分钟桶。
+/// since 所在的桶可能在上次推送后仍有累加，因此会重复发送一次
+pub fn metrics_delta_since(mut payload: MetricsPayload, since: i64) -> MetricsPayload {
+    for s in payload.by_listen_addr.values_mut() {
+        drop_buckets_before(s, since);
+    }
+    let since_minute = since - since.rem_euclid(60);
+    for s in payload
+        .by_listen_minute
+        .iter_mut()
+        .flat_map(|m| m.values_mut())
+    {
+        drop_buckets_before(s, since_minute);
+    }
+    payload
+}
+
+fn drop_buckets_before(s: &mut MetricsSeries, since: i64) {
+    let start = s.timestamps.partition_point(|ts| *ts < since);
+    fn drop_head<T>(v: &mut Vec<T>, n: usize) {
+        v.drain(..n.min(v.len()));
+    }
+    drop_head(&mut s.timestamps, start);
+    drop_head(&mut s.counts, start);
+    drop_head(&mut s.s2xx, start);
+    drop_head(&mut s.s3xx, start);
+    drop_head(&mut s.s4xx, start);
+    drop_head(&mut s.s5xx, start);
+    drop_head(&mut s.s0, start);
+    drop_head(&mut s.avg_latency_ms, start);
+    drop_head(&mut s.max_latency_ms, start);
+    drop_head(&mut s.avg_upstream_ms, start);
+    for v in [
+        s.bytes_in.as_mut(),
+        s.bytes_out.as_mut(),
+        s.in_flight.as_mut(),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        drop_head(v, start);
+    }
+    for v in [s.p50.as_mut(), s.p95.as_mut(), s.p99.as_mut()]
+        .into_iter()
+        .flatten()
+    {
+        drop_head(v, start);
+    }
+}
+
 pub async fn get_distinct_listen_addrs() -> Result<Vec<String>> {
-    let Some(pool) = db_pool() else {
+    let Some(backend) = db_backend() else {
         return Ok(vec![]);
     };
+    backend
+        .distinct_listen_addrs()
+        .await
+        .context("查询 request_logs.listen_addr distinct 失败")
+}
 
-    let rows = sqlx::query_as::<_, (String,)>(
-        "SELECT DISTINCT listen_addr FROM request_logs WHERE trim(listen_addr) != '' ORDER BY listen_addr ASC",
-    )
-    .fetch_all(&*pool)
-    .await
-    .context("查询 request_logs.listen_addr distinct 失败")?;
+pub(super) const DISTINCT_LISTEN_ADDRS_SQL: &str =
+    "SELECT DISTINCT listen_addr FROM request_logs WHERE trim(listen_addr) != '' ORDER BY listen_addr ASC";
 
-    Ok(rows.into_iter().map(|(s,)| s).collect())
+/// 历史指标分布与排行的查询范围
+#[derive(Debug, Clone, Copy)]
+pub(super) struct HistoricalScope<'a> {
+    pub start: i64,
+    pub end: i64,
+    pub listen_addr: Option<&'a str>,
+}
+
+/// 后端查出的历史指标分布与排行；时间序列由 series_points 提供
+pub(super) struct HistoricalMetricsParts {
+    pub upstream_dist: Vec<KeyValue>,
+    pub top_route_err: Vec<KeyValue>,
+    pub top_up_err: Vec<KeyValue>,
+    pub latency_buckets: LatencyBucketRow,
+    pub latency: LatencyHistogram,
+}
+
+pub(super) type LatencyBucketRow = (
+    Option<i64>,
+    Option<i64>,
+    Option<i64>,
+    Option<i64>,
+    Option<i64>,
+    Option<i64>,
+    Option<i64>,
+    Option<i64>,
+    Option<i64>,
+    Option<i64>,
+    Option<i64>,
+    Option<i64>,
+);
+
+const LATENCY_DIST_LABELS: [&str; 12] = [
+    "<5ms",
+    "5-10ms",
+    "10-20ms",
+    "20-50ms",
+    "50-100ms",
+    "100-150ms",
+    "150-250ms",
+    "250-400ms",
+    "400-700ms",
+    "700-1000ms",
+    "1000-2000ms",
+    ">=2000ms",
+];
+
+fn empty_historical_series() -> MetricsSeries {
+    MetricsSeries {
+        timestamps: vec![],
+        counts: vec![],
+        s2xx: vec![],
+        s3xx: vec![],
+        s4xx: vec![],
+        s5xx: vec![],
+        s0: vec![],
+        avg_latency_ms: vec![],
+        max_latency_ms: vec![],
+        avg_upstream_ms: vec![],
+        bytes_in: None,
+        bytes_out: None,
+        in_flight: None,
+        p50: Some(vec![]),
+        p95: Some(vec![]),
+        p99: Some(vec![]),
+        upstream_dist: Some(vec![]),
+        top_route_err: Some(vec![]),
+        top_up_err: Some(vec![]),
+        latency_dist: Some(vec![]),
+    }
 }
 
 pub async fn query_historical_metrics(req: QueryMetricsRequest) -> Result<QueryMetricsResponse> {
-    let Some(pool) = db_pool() else {
+    let Some(backend) = db_backend() else {
         return Ok(QueryMetricsResponse {
-            series: MetricsSeries {
-                timestamps: vec![],
-                counts: vec![],
-                s2xx: vec![],
-                s3xx: vec![],
-                s4xx: vec![],
-                s5xx: vec![],
-                s0: vec![],
-                avg_latency_ms: vec![],
-                max_latency_ms: vec![],
-                p50: Some(vec![]),
-                p95: Some(vec![]),
-                p99: Some(vec![]),
-                upstream_dist: Some(vec![]),
-                top_route_err: Some(vec![]),
-                top_up_err: Some(vec![]),
-                latency_dist: Some(vec![]),
-            },
+            series: empty_historical_series(),
         });
     };
 
@@ -234,66 +340,32 @@ pub async fn query_historical_metrics(req: QueryMetricsRequest) -> Result<QueryM
     let end = req.end_time;
     if end <= start {
         return Ok(QueryMetricsResponse {
-            series: MetricsSeries {
-                timestamps: vec![],
-                counts: vec![],
-                s2xx: vec![],
-                s3xx: vec![],
-                s4xx: vec![],
-                s5xx: vec![],
-                s0: vec![],
-                avg_latency_ms: vec![],
-                max_latency_ms: vec![],
-                p50: Some(vec![]),
-                p95: Some(vec![]),
-                p99: Some(vec![]),
-                upstream_dist: Some(vec![]),
-                top_route_err: Some(vec![]),
-                top_up_err: Some(vec![]),
-                latency_dist: Some(vec![]),
-            },
+            series: empty_historical_series(),
         });
     }
 
     let span = end - start;
+    // 整分钟/整小时粒度可由长期汇总表提供，原始日志被清理后仍有序列
     let granularity = if span < 3600 {
         1
     } else if span < 48 * 3600 {
         60
-    } else {
+    } else if span < 7 * 24 * 3600 {
         300
+    } else {
+        3600
     };
 
-    // 聚合时序
-    let mut qb = QueryBuilder::new("SELECT (timestamp / ");
-    qb.push_bind(granularity);
-    qb.push(") * ");
-    qb.push_bind(granularity);
-    qb.push(
-        r#" AS bucket, 
-        COUNT(1) AS total,
-        SUM(CASE WHEN status_code BETWEEN 200 AND 299 THEN 1 ELSE 0 END) AS s2xx,
-        SUM(CASE WHEN status_code BETWEEN 300 AND 399 THEN 1 ELSE 0 END) AS s3xx,
-        SUM(CASE WHEN status_code BETWEEN 400 AND 499 THEN 1 ELSE 0 END) AS s4xx,
-        SUM(CASE WHEN status_code >= 500 THEN 1 ELSE 0 END) AS s5xx,
-        AVG(latency_ms) AS avg_latency,
-        MAX(latency_ms) AS max_latency
-    FROM request_logs
-    WHERE timestamp >= "#,
-    );
-    qb.push_bind(start);
-    qb.push(" AND timestamp <= ");
-    qb.push_bind(end);
-
-    if let Some(v) = listen_addr {
-        qb.push(" AND listen_addr = ").push_bind(v);
-    }
-    qb.push(" GROUP BY bucket ORDER BY bucket");
-
-    let rows: Vec<(i64, i64, i64, i64, i64, i64, Option<f64>, Option<f64>)> =
-        qb.build_query_as().fetch_all(&*pool).await?;
+    let parts = backend
+        .historical_metrics(HistoricalScope {
+            start,
+            end,
+            listen_addr,
+        })
+        .await?;
+    let points = series_points(&*backend, start, end, granularity, listen_addr, None).await?;
 
-    let cap = rows.len();
+    let cap = points.len();
     let mut timestamps = Vec::with_capacity(cap);
     let mut counts = Vec::with_capacity(cap);
     let mut s2xx = Vec::with_capacity(cap);
@@ -302,200 +374,34 @@ pub async fn query_historical_metrics(req: QueryMetricsRequest) -> Result<QueryM
     let mut s5xx = Vec::with_capacity(cap);
     let mut avg_latency = Vec::with_capacity(cap);
     let mut max_latency = Vec::with_capacity(cap);
+    let mut avg_upstream = Vec::with_capacity(cap);
 
-    for (bucket, total, v2, v3, v4, v5, avg_l, max_l) in rows {
-        timestamps.push(bucket);
-        counts.push(total);
-        s2xx.push(v2);
-        s3xx.push(v3);
-        s4xx.push(v4);
-        s5xx.push(v5);
-        avg_latency.push(((avg_l.unwrap_or(0.0) * 10000.0).round()) / 10000.0);
-        max_latency.push(((max_l.unwrap_or(0.0) * 10000.0).round()) / 10000.0);
+    for p in points {
+        timestamps.push(p.bucket);
+        counts.push(p.total);
+        s2xx.push(p.s2xx);
+        s3xx.push(p.s3xx);
+        s4xx.push(p.s4xx);
+        s5xx.push(p.s5xx);
+        avg_latency.push(((p.avg_latency_ms() * 10000.0).round()) / 10000.0);
+        max_latency.push(((p.latency_max_ms * 10000.0).round()) / 10000.0);
+        avg_upstream.push(((p.avg_upstream_ms() * 10000.0).round()) / 10000.0);
     }
 
-    // Top upstream 分布
-    let mut up_qb = QueryBuilder::new(
-        r#"SELECT CASE WHEN instr(h, '/') > 0 THEN substr(h, 1, instr(h, '/') - 1) ELSE h END AS k, COUNT(1) AS c FROM (
-            SELECT replace(replace(replace(upstream, 'https://', ''), 'http://', ''), 'www.', '') AS h
-            FROM request_logs WHERE timestamp >= "#,
-    );
-    up_qb
-        .push_bind(start)
-        .push(" AND timestamp <= ")
-        .push_bind(end);
-    if let Some(v) = listen_addr {
-        up_qb.push(" AND listen_addr = ").push_bind(v);
-    }
-    up_qb.push(") AS t GROUP BY k ORDER BY c DESC LIMIT 20");
-
-    let upstream_dist: Vec<KeyValue> = up_qb
-        .build_query_as::<(String, i64)>()
-        .fetch_all(&*pool)
-        .await?
-        .into_iter()
-        .map(|(k, v)| KeyValue { key: k, value: v })
+    let (b1, b2, b3, b4, b5, b6, b7, b8, b9, b10, b11, b12) = parts.latency_buckets;
+    let latency_dist = LATENCY_DIST_LABELS
+        .iter()
+        .zip([b1, b2, b3, b4, b5, b6, b7, b8, b9, b10, b11, b12])
+        .map(|(label, v)| KeyValue {
+            key: (*label).into(),
+            value: v.unwrap_or(0),
+        })
         .collect();
 
-    // Top route 错误
-    let mut re_qb = QueryBuilder::new(
-        "SELECT request_path AS k, COUNT(1) AS c FROM request_logs WHERE timestamp >= ",
-    );
-    re_qb
-        .push_bind(start)
-        .push(" AND timestamp <= ")
-        .push_bind(end)
-        .push(" AND status_code >= 400");
-    if let Some(v) = listen_addr {
-        re_qb.push(" AND listen_addr = ").push_bind(v);
-    }
-    re_qb.push(" GROUP BY request_path ORDER BY c DESC LIMIT 10");
-    let top_route_err: Vec<KeyValue> = re_qb
-        .build_query_as::<(String, i64)>()
-        .fetch_all(&*pool)
-        .await?
-        .into_iter()
-        .map(|(k, v)| KeyValue { key: k, value: v })
-        .collect();
-
-    // Top upstream 错误
-    let mut ue_qb = QueryBuilder::new(
-        "SELECT upstream AS k, COUNT(1) AS c FROM request_logs WHERE timestamp >= ",
-    );
-    ue_qb
-        .push_bind(start)
-        .push(" AND timestamp <= ")
-        .push_bind(end)
-        .push(" AND status_code >= 400");
-    if let Some(v) = listen_addr {
-        ue_qb.push(" AND listen_addr = ").push_bind(v);
-    }
-    ue_qb.push(" GROUP BY upstream ORDER BY c DESC LIMIT 10");
-    let top_up_err: Vec<KeyValue> = ue_qb
-        .build_query_as::<(String, i64)>()
-        .fetch_all(&*pool)
-        .await?
-        .into_iter()
-        .map(|(k, v)| KeyValue { key: k, value: v })
-        .collect();
-
-    // Latency Dist（12桶）
-    let mut lat_qb = QueryBuilder::new(
-        "SELECT\n        SUM(CASE WHEN latency_ms < 5 THEN 1 ELSE 0 END) AS b1,\n        SUM(CASE WHEN latency_ms >= 5 AND latency_ms < 10 THEN 1 ELSE 0 END) AS b2,\n        SUM(CASE WHEN latency_ms >= 10 AND latency_ms < 20 THEN 1 ELSE 0 END) AS b3,\n        SUM(CASE WHEN latency_ms >= 20 AND latency_ms < 50 THEN 1 ELSE 0 END) AS b4,\n        SUM(CASE WHEN latency_ms >= 50 AND latency_ms < 100 THEN 1 ELSE 0 END) AS b5,\n        SUM(CASE WHEN latency_ms >= 100 AND latency_ms < 150 THEN 1 ELSE 0 END) AS b6,\n        SUM(CASE WHEN latency_ms >= 150 AND latency_ms < 250 THEN 1 ELSE 0 END) AS b7,\n        SUM(CASE WHEN latency_ms >= 250 AND latency_ms < 400 THEN 1 ELSE 0 END) AS b8,\n        SUM(CASE WHEN latency_ms >= 400 AND latency_ms < 700 THEN 1 ELSE 0 END) AS b9,\n        SUM(CASE WHEN latency_ms >= 700 AND latency_ms < 1000 THEN 1 ELSE 0 END) AS b10,\n        SUM(CASE WHEN latency_ms >= 1000 AND latency_ms < 2000 THEN 1 ELSE 0 END) AS b11,\n        SUM(CASE WHEN latency_ms >= 2000 THEN 1 ELSE 0 END) AS b12\n        FROM request_logs WHERE timestamp >= "
-    );
-    lat_qb
-        .push_bind(start)
-        .push(" AND timestamp <= ")
-        .push_bind(end);
-    if let Some(v) = listen_addr {
-        lat_qb.push(" AND listen_addr = ").push_bind(v);
-    }
-
-    let (b1, b2, b3, b4, b5, b6, b7, b8, b9, b10, b11, b12): (
-        Option<i64>,
-        Option<i64>,
-        Option<i64>,
-        Option<i64>,
-        Option<i64>,
-        Option<i64>,
-        Option<i64>,
-        Option<i64>,
-        Option<i64>,
-        Option<i64>,
-        Option<i64>,
-        Option<i64>,
-    ) = lat_qb.build_query_as().fetch_one(&*pool).await?;
-
-    let latency_dist = vec![
-        KeyValue {
-            key: "<5ms".into(),
-            value: b1.unwrap_or(0),
-        },
-        KeyValue {
-            key: "5-10ms".into(),
-            value: b2.unwrap_or(0),
-        },
-        KeyValue {
-            key: "10-20ms".into(),
-            value: b3.unwrap_or(0),
-        },
-        KeyValue {
-            key: "20-50ms".into(),
-            value: b4.unwrap_or(0),
-        },
-        KeyValue {
-            key: "50-100ms".into(),
-            value: b5.unwrap_or(0),
-        },
-        KeyValue {
-            key: "100-150ms".into(),
-            value: b6.unwrap_or(0),
-        },
-        KeyValue {
-            key: "150-250ms".into(),
-            value: b7.unwrap_or(0),
-        },
-        KeyValue {
-            key: "250-400ms".into(),
-            value: b8.unwrap_or(0),
-        },
-        KeyValue {
-            key: "400-700ms".into(),
-            value: b9.unwrap_or(0),
-        },
-        KeyValue {
-            key: "700-1000ms".into(),
-            value: b10.unwrap_or(0),
-        },
-        KeyValue {
-            key: "1000-2000ms".into(),
-            value: b11.unwrap_or(0),
-        },
-        KeyValue {
-            key: ">=2000ms".into(),
-            value: b12.unwrap_or(0),
-        },
-    ];
-
-    // P50/P95/P99（近似）：使用 12 桶估算
-    let dist_counts = [
-        b1.unwrap_or(0),
-        b2.unwrap_or(0),
-        b3.unwrap_or(0),
-        b4.unwrap_or(0),
-        b5.unwrap_or(0),
-        b6.unwrap_or(0),
-        b7.unwrap_or(0),
-        b8.unwrap_or(0),
-        b9.unwrap_or(0),
-        b10.unwrap_or(0),
-        b11.unwrap_or(0),
-        b12.unwrap_or(0),
-    ];
-    let total_lat = dist_counts.iter().sum::<i64>();
-
-    let dist_medians = [
-        2.5, 7.5, 15.0, 35.0, 75.0, 125.0, 200.0, 325.0, 550.0, 850.0, 1500.0, 3000.0,
-    ];
-
-    let approx_percentile = |p: f64| -> f64 {
-        if total_lat <= 0 {
-            return 0.0;
-        }
-        let target = (total_lat as f64 * p).ceil() as i64;
-        let mut acc = 0i64;
-        for i in 0..dist_counts.len() {
-            acc += dist_counts[i];
-            if acc >= target {
-                return dist_medians[i];
-            }
-        }
-        dist_medians[dist_medians.len() - 1]
-    };
-
-    let p50 = approx_percentile(0.50);
-    let p95 = approx_percentile(0.95);
-    let p99 = approx_percentile(0.99);
+    let round4 = |v: f64| (v * 10000.0).round() / 10000.0;
+    let p50 = round4(parts.latency.percentile(0.50));
+    let p95 = round4(parts.latency.percentile(0.95));
+    let p99 = round4(parts.latency.percentile(0.99));
 
     Ok(QueryMetricsResponse {
         series: MetricsSeries {
@@ -508,54 +414,338 @@ pub async fn query_historical_metrics(req: QueryMetricsRequest) -> Result<QueryM
             s0: vec![0; cap],
             avg_latency_ms: avg_latency,
             max_latency_ms: max_latency,
+            avg_upstream_ms: avg_upstream,
+            bytes_in: None,
+            bytes_out: None,
+            in_flight: None,
             p50: Some(vec![p50; cap]),
             p95: Some(vec![p95; cap]),
             p99: Some(vec![p99; cap]),
-            upstream_dist: Some(upstream_dist),
-            top_route_err: Some(top_route_err),
-            top_up_err: Some(top_up_err),
+            upstream_dist: Some(parts.upstream_dist),
+            top_route_err: Some(parts.top_route_err),
+            top_up_err: Some(parts.top_up_err),
             latency_dist: Some(latency_dist),
         },
     })
 }
 
-pub async fn get_dashboard_stats(req: DashboardStatsRequest) -> Result<DashboardStatsResponse> {
-    let Some(pool) = db_pool() else {
-        return Ok(DashboardStatsResponse::default());
+fn push_historical_scope<'a, DB>(qb: &mut QueryBuilder<'a, DB>, scope: HistoricalScope<'a>)
+where
+    DB: sqlx::Database,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    qb.push_bind(scope.start)
+        .push(" AND timestamp <= ")
+        .push_bind(scope.end);
+    if let Some(v) = scope.listen_addr {
+        qb.push(" AND listen_addr = ").push_bind(v);
+    }
+}
+
+/// Top upstream 分布：去掉协议与 www. 前缀，按 host[:port] 分组
+pub(super) fn upstream_dist_query<'a, DB>(scope: HistoricalScope<'a>) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let host_expr = if DB::NAME == "SQLite" {
+        "CASE WHEN instr(h, '/') > 0 THEN substr(h, 1, instr(h, '/') - 1) ELSE h END"
+    } else {
+        "split_part(h, '/', 1)"
     };
+    let mut qb = QueryBuilder::new(format!(
+        r#"SELECT {host_expr} AS k, COUNT(1) AS c FROM (
+            SELECT replace(replace(replace(upstream, 'https://', ''), 'http://', ''), 'www.', '') AS h
+            FROM request_logs WHERE timestamp >= "#
+    ));
+    push_historical_scope(&mut qb, scope);
+    qb.push(") AS t GROUP BY k ORDER BY c DESC LIMIT 20");
+    qb
+}
 
-    let gran = req.granularity_secs.max(1);
-    let listen_addr = req
-        .listen_addr
-        .as_deref()
-        .map(str::trim)
-        .filter(|s| !s.is_empty());
+/// 按 column 分组的错误（status >= 400）Top 10
+pub(super) fn top_errors_query<'a, DB>(
+    column: &str,
+    scope: HistoricalScope<'a>,
+) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let mut qb = QueryBuilder::new(format!(
+        "SELECT {column} AS k, COUNT(1) AS c FROM request_logs WHERE timestamp >= "
+    ));
+    push_historical_scope(&mut qb, scope);
+    qb.push(format!(
+        " AND status_code >= 400 GROUP BY {column} ORDER BY c DESC LIMIT 10"
+    ));
+    qb
+}
 
-    // Series
-    let mut series_qb = QueryBuilder::new("SELECT (timestamp / ");
-    series_qb.push_bind(gran).push(") * ").push_bind(gran).push(
-        r#" AS time_bucket,
-        COUNT(1) AS total_requests,
-        SUM(CASE WHEN status_code BETWEEN 200 AND 299 THEN 1 ELSE 0 END) AS success_requests,
-        SUM(CASE WHEN status_code BETWEEN 300 AND 399 THEN 1 ELSE 0 END) AS redirect_requests,
-        SUM(CASE WHEN status_code BETWEEN 400 AND 499 THEN 1 ELSE 0 END) AS client_error_requests,
-        SUM(CASE WHEN status_code >= 500 THEN 1 ELSE 0 END) AS server_error_requests,
-        AVG(latency_ms) AS avg_latency_ms
-    FROM request_logs WHERE timestamp >= "#,
+/// 延迟分布（12 桶），结果按 LatencyBucketRow 读取
+pub(super) fn latency_dist_query<'a, DB>(scope: HistoricalScope<'a>) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let mut qb = QueryBuilder::new(
+        "SELECT\n        SUM(CASE WHEN latency_ms < 5 THEN 1 ELSE 0 END) AS b1,\n        SUM(CASE WHEN latency_ms >= 5 AND latency_ms < 10 THEN 1 ELSE 0 END) AS b2,\n        SUM(CASE WHEN latency_ms >= 10 AND latency_ms < 20 THEN 1 ELSE 0 END) AS b3,\n        SUM(CASE WHEN latency_ms >= 20 AND latency_ms < 50 THEN 1 ELSE 0 END) AS b4,\n        SUM(CASE WHEN latency_ms >= 50 AND latency_ms < 100 THEN 1 ELSE 0 END) AS b5,\n        SUM(CASE WHEN latency_ms >= 100 AND latency_ms < 150 THEN 1 ELSE 0 END) AS b6,\n        SUM(CASE WHEN latency_ms >= 150 AND latency_ms < 250 THEN 1 ELSE 0 END) AS b7,\n        SUM(CASE WHEN latency_ms >= 250 AND latency_ms < 400 THEN 1 ELSE 0 END) AS b8,\n        SUM(CASE WHEN latency_ms >= 400 AND latency_ms < 700 THEN 1 ELSE 0 END) AS b9,\n        SUM(CASE WHEN latency_ms >= 700 AND latency_ms < 1000 THEN 1 ELSE 0 END) AS b10,\n        SUM(CASE WHEN latency_ms >= 1000 AND latency_ms < 2000 THEN 1 ELSE 0 END) AS b11,\n        SUM(CASE WHEN latency_ms >= 2000 THEN 1 ELSE 0 END) AS b12\n        FROM request_logs WHERE timestamp >= "
     );
-    series_qb
-        .push_bind(req.start_time)
+    push_historical_scope(&mut qb, scope);
+    qb
+}
+
+/// P50/P95/P99 的原始延迟值，调用方逐行流式写入直方图
+pub(super) fn latency_values_query<'a, DB>(scope: HistoricalScope<'a>) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let mut qb = QueryBuilder::new("SELECT latency_ms FROM request_logs WHERE timestamp >= ");
+    push_historical_scope(&mut qb, scope);
+    qb
+}
+
+pub(super) fn key_values(rows: Vec<(String, i64)>) -> Vec<KeyValue> {
+    rows.into_iter()
+        .map(|(k, v)| KeyValue { key: k, value: v })
+        .collect()
+}
+
+pub(super) async fn historical_metrics_sqlite(
+    pool: &SqlitePool,
+    scope: HistoricalScope<'_>,
+) -> Result<HistoricalMetricsParts> {
+    let upstream_dist = upstream_dist_query::<sqlx::Sqlite>(scope)
+        .build_query_as::<(String, i64)>()
+        .fetch_all(pool)
+        .await?;
+    let top_route_err = top_errors_query::<sqlx::Sqlite>("request_path", scope)
+        .build_query_as::<(String, i64)>()
+        .fetch_all(pool)
+        .await?;
+    let top_up_err = top_errors_query::<sqlx::Sqlite>("upstream", scope)
+        .build_query_as::<(String, i64)>()
+        .fetch_all(pool)
+        .await?;
+    let latency_buckets = latency_dist_query::<sqlx::Sqlite>(scope)
+        .build_query_as::<LatencyBucketRow>()
+        .fetch_one(pool)
+        .await?;
+
+    // 逐行流式写入对数分桶直方图，内存占用与时间范围无关
+    let mut latency = LatencyHistogram::default();
+    let mut qb = latency_values_query::<sqlx::Sqlite>(scope);
+    let mut rows = qb.build_query_as::<(f64,)>().fetch(pool);
+    while let Some((v,)) = rows.try_next().await? {
+        latency.add(v);
+    }
+    drop(rows);
+
+    Ok(HistoricalMetricsParts {
+        upstream_dist: key_values(upstream_dist),
+        top_route_err: key_values(top_route_err),
+        top_up_err: key_values(top_up_err),
+        latency_buckets,
+        latency,
+    })
+}
+
+/// 系统指标按粒度分桶聚合；Postgres 中整数列的 AVG 是 NUMERIC，统一转为浮点
+pub(super) fn system_metrics_history_query<'a, DB>(
+    start: i64,
+    end: i64,
+    granularity: i64,
+) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let mut qb = QueryBuilder::new("SELECT (timestamp / ");
+    qb.push_bind(granularity)
+        .push(") * ")
+        .push_bind(granularity)
+        .push(
+            r#" AS bucket,
+              CAST(AVG(cpu_usage_percent) AS DOUBLE PRECISION) AS cpu_usage_percent,
+              CAST(AVG(load1) AS DOUBLE PRECISION) AS load1,
+              CAST(AVG(load5) AS DOUBLE PRECISION) AS load5,
+              CAST(AVG(load15) AS DOUBLE PRECISION) AS load15,
+              MAX(mem_total_bytes) AS mem_total_bytes,
+              CAST(AVG(mem_available_bytes) AS DOUBLE PRECISION) AS mem_available_bytes,
+              CAST(AVG(mem_used_bytes) AS DOUBLE PRECISION) AS mem_used_bytes,
+              CAST(AVG(mem_used_percent) AS DOUBLE PRECISION) AS mem_used_percent,
+              MAX(swap_total_bytes) AS swap_total_bytes,
+              CAST(AVG(swap_free_bytes) AS DOUBLE PRECISION) AS swap_free_bytes,
+              CAST(AVG(swap_used_bytes) AS DOUBLE PRECISION) AS swap_used_bytes,
+              CAST(AVG(swap_used_percent) AS DOUBLE PRECISION) AS swap_used_percent,
+              MAX(net_rx_bytes) AS net_rx_bytes,
+              MAX(net_tx_bytes) AS net_tx_bytes,
+              CAST(AVG(net_rx_bps) AS DOUBLE PRECISION) AS net_rx_bps,
+              CAST(AVG(net_tx_bps) AS DOUBLE PRECISION) AS net_tx_bps,
+              MAX(disk_read_bytes) AS disk_read_bytes,
+              MAX(disk_write_bytes) AS disk_write_bytes,
+              CAST(AVG(disk_read_bps) AS DOUBLE PRECISION) AS disk_read_bps,
+              CAST(AVG(disk_write_bps) AS DOUBLE PRECISION) AS disk_write_bps,
+              CAST(AVG(tcp_established) AS DOUBLE PRECISION) AS tcp_established,
+              CAST(AVG(tcp_time_wait) AS DOUBLE PRECISION) AS tcp_time_wait,
+              CAST(AVG(tcp_close_wait) AS DOUBLE PRECISION) AS tcp_close_wait,
+              CAST(AVG(process_count) AS DOUBLE PRECISION) AS process_count,
+              CAST(AVG(fd_used) AS DOUBLE PRECISION) AS fd_used,
+              MAX(fd_max) AS fd_max,
+              CAST(AVG(fd_usage_percent) AS DOUBLE PRECISION) AS fd_usage_percent,
+              CAST(AVG(procs_running) AS DOUBLE PRECISION) AS procs_running,
+              CAST(AVG(procs_blocked) AS DOUBLE PRECISION) AS procs_blocked,
+              MAX(context_switches) AS context_switches,
+              MAX(processes_forked_total) AS processes_forked_total,
+              CAST(AVG(uptime_seconds) AS DOUBLE PRECISION) AS uptime_seconds
+            FROM system_metrics
+            WHERE timestamp >= "#,
+        )
+        .push_bind(start)
         .push(" AND timestamp <= ")
-        .push_bind(req.end_time);
+        .push_bind(end)
+        .push(" GROUP BY bucket ORDER BY bucket ASC");
+    qb
+}
 
+/// 仪表盘各查询共用的监听地址/路由过滤条件
+pub(super) fn push_dashboard_scope<'a, DB>(
+    qb: &mut QueryBuilder<'a, DB>,
+    listen_addr: Option<&'a str>,
+    route_id: Option<&'a str>,
+) where
+    DB: sqlx::Database,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
     if let Some(v) = listen_addr {
-        series_qb.push(" AND listen_addr = ").push_bind(v);
+        qb.push(" AND listen_addr = ").push_bind(v);
     }
-    series_qb.push(" GROUP BY time_bucket ORDER BY time_bucket");
-    let time_series = series_qb
-        .build_query_as::<DashboardStatsPoint>()
-        .fetch_all(&*pool)
-        .await?;
+    if let Some(v) = route_id {
+        qb.push(" AND matched_route_id = ").push_bind(v);
+    }
+}
+
+// top UA/referer：先取出现次数最多的原始值，再归一化合并
+pub(super) const AGENT_GROUP_SCAN_LIMIT: i64 = 500;
+
+pub(super) fn merge_normalized_top(
+    rows: Vec<(String, i64)>,
+    normalize: fn(&str) -> String,
+) -> Vec<TopListItem> {
+    let mut merged: HashMap<String, i64> = HashMap::with_capacity(rows.len());
+    for (raw, count) in rows {
+        *merged.entry(normalize(&raw)).or_insert(0) += count;
+    }
+    let mut v: Vec<TopListItem> = merged
+        .into_iter()
+        .map(|(item, count)| TopListItem { item, count })
+        .collect();
+    v.sort_unstable_by(|a, b| b.count.cmp(&a.count).then_with(|| a.item.cmp(&b.item)));
+    v.truncate(10);
+    v
+}
+
+async fn top_raw_values_sqlite(
+    pool: &SqlitePool,
+    req: &DashboardStatsRequest,
+    column: &'static str,
+) -> Result<Vec<(String, i64)>> {
+    let mut qb = QueryBuilder::new(format!(
+        "SELECT {column}, COUNT(1) AS c FROM request_logs WHERE timestamp >= "
+    ));
+    qb.push_bind(req.start_time)
+        .push(" AND timestamp <= ")
+        .push_bind(req.end_time)
+        .push(format!(" AND {column} != '' AND {column} != '-'"));
+    push_dashboard_scope(
+        &mut qb,
+        non_empty_trimmed(&req.listen_addr),
+        non_empty_trimmed(&req.matched_route_id),
+    );
+    qb.push(format!(" GROUP BY {column} ORDER BY c DESC LIMIT "))
+        .push_bind(AGENT_GROUP_SCAN_LIMIT);
+    Ok(qb.build_query_as().fetch_all(pool).await?)
+}
+
+/// guard/prepare/upstream 三个阶段的 avg/p95/p99，逐行流式累积
+#[derive(Default)]
+pub(super) struct PhaseTimingAcc {
+    guard: LatencyHistogram,
+    prepare: LatencyHistogram,
+    upstream: LatencyHistogram,
+}
+
+impl PhaseTimingAcc {
+    pub(super) fn add(&mut self, (g, p, u): (f64, f64, f64)) {
+        self.guard.add(g);
+        self.prepare.add(p);
+        self.upstream.add(u);
+    }
+
+    pub(super) fn finish(&self) -> PhaseTimingStats {
+        PhaseTimingStats {
+            guard: phase_stat(&self.guard),
+            prepare: phase_stat(&self.prepare),
+            upstream: phase_stat(&self.upstream),
+        }
+    }
+}
+
+pub async fn get_dashboard_stats(req: DashboardStatsRequest) -> Result<DashboardStatsResponse> {
+    let Some(backend) = db_backend() else {
+        return Ok(DashboardStatsResponse::default());
+    };
+    backend.dashboard_stats(req).await
+}
+
+/// 汇总点转为仪表盘序列，SQLite 与 Postgres 共用
+pub(super) fn dashboard_time_series(points: &[RollupPoint]) -> Vec<DashboardStatsPoint> {
+    points
+        .iter()
+        .map(|p| DashboardStatsPoint {
+            time_bucket: p.bucket,
+            total_requests: p.total,
+            success_requests: p.s2xx,
+            redirect_requests: p.s3xx,
+            client_error_requests: p.s4xx,
+            server_error_requests: p.s5xx,
+            avg_latency_ms: p.avg_latency_ms(),
+            avg_upstream_ms: p.avg_upstream_ms(),
+        })
+        .collect()
+}
+
+pub(super) async fn dashboard_stats_sqlite(
+    backend: &SqliteBackend,
+    req: DashboardStatsRequest,
+) -> Result<DashboardStatsResponse> {
+    let pool = &*backend.read;
+    let gran = req.granularity_secs.max(1);
+    let listen_addr = non_empty_trimmed(&req.listen_addr);
+    let route_id = non_empty_trimmed(&req.matched_route_id);
+
+    // Series：整分钟粒度时水位以下读长期汇总表
+    let points = series_points(
+        backend,
+        req.start_time,
+        req.end_time,
+        gran,
+        listen_addr,
+        route_id,
+    )
+    .await?;
+    let time_series = dashboard_time_series(&points);
 
     // Top paths
     let mut path_qb = QueryBuilder::new(
@@ -565,13 +755,11 @@ pub async fn get_dashboard_stats(req: DashboardStatsRequest) -> Result<Dashboard
         .push_bind(req.start_time)
         .push(" AND timestamp <= ")
         .push_bind(req.end_time);
-    if let Some(v) = listen_addr {
-        path_qb.push(" AND listen_addr = ").push_bind(v);
-    }
+    push_dashboard_scope(&mut path_qb, listen_addr, route_id);
     path_qb.push(" GROUP BY request_path ORDER BY count DESC LIMIT 10");
     let top_paths = path_qb
         .build_query_as::<TopListItem>()
-        .fetch_all(&*pool)
+        .fetch_all(pool)
         .await?;
 
     // Top IPs
@@ -582,13 +770,11 @@ pub async fn get_dashboard_stats(req: DashboardStatsRequest) -> Result<Dashboard
         .push_bind(req.start_time)
         .push(" AND timestamp <= ")
         .push_bind(req.end_time);
-    if let Some(v) = listen_addr {
-        ip_qb.push(" AND listen_addr = ").push_bind(v);
-    }
+    push_dashboard_scope(&mut ip_qb, listen_addr, route_id);
     ip_qb.push(" GROUP BY client_ip ORDER BY count DESC LIMIT 10");
     let top_ips = ip_qb
         .build_query_as::<TopListItem>()
-        .fetch_all(&*pool)
+        .fetch_all(pool)
         .await?;
 
     // Top routes
@@ -600,13 +786,11 @@ pub async fn get_dashboard_stats(req: DashboardStatsRequest) -> Result<Dashboard
         .push(" AND timestamp <= ")
         .push_bind(req.end_time);
     route_qb.push(" AND trim(matched_route_id) != ''");
-    if let Some(v) = listen_addr {
-        route_qb.push(" AND listen_addr = ").push_bind(v);
-    }
+    push_dashboard_scope(&mut route_qb, listen_addr, route_id);
     route_qb.push(" GROUP BY matched_route_id ORDER BY count DESC LIMIT 10");
     let top_routes = route_qb
         .build_query_as::<TopListItem>()
-        .fetch_all(&*pool)
+        .fetch_all(pool)
         .await?;
 
     // Top route errors
@@ -618,13 +802,27 @@ pub async fn get_dashboard_stats(req: DashboardStatsRequest) -> Result<Dashboard
         .push(" AND timestamp <= ")
         .push_bind(req.end_time);
     route_err_qb.push(" AND trim(matched_route_id) != '' AND status_code >= 400");
-    if let Some(v) = listen_addr {
-        route_err_qb.push(" AND listen_addr = ").push_bind(v);
-    }
+    push_dashboard_scope(&mut route_err_qb, listen_addr, route_id);
     route_err_qb.push(" GROUP BY matched_route_id ORDER BY count DESC LIMIT 10");
     let top_route_errors = route_err_qb
         .build_query_as::<TopListItem>()
-        .fetch_all(&*pool)
+        .fetch_all(pool)
+        .await?;
+
+    // Top paths by bytes
+    let mut path_bytes_qb = QueryBuilder::new(
+        "SELECT request_path AS item, SUM(bytes_sent) AS count FROM request_logs WHERE timestamp >= ",
+    );
+    path_bytes_qb
+        .push_bind(req.start_time)
+        .push(" AND timestamp <= ")
+        .push_bind(req.end_time);
+    path_bytes_qb.push(" AND bytes_sent > 0");
+    push_dashboard_scope(&mut path_bytes_qb, listen_addr, route_id);
+    path_bytes_qb.push(" GROUP BY request_path ORDER BY count DESC LIMIT 10");
+    let top_paths_by_bytes = path_bytes_qb
+        .build_query_as::<TopListItem>()
+        .fetch_all(pool)
         .await?;
 
     // Top upstream errors
@@ -636,29 +834,69 @@ pub async fn get_dashboard_stats(req: DashboardStatsRequest) -> Result<Dashboard
         .push(" AND timestamp <= ")
         .push_bind(req.end_time);
     up_err_qb.push(" AND status_code >= 400");
-    if let Some(v) = listen_addr {
-        up_err_qb.push(" AND listen_addr = ").push_bind(v);
-    }
+    push_dashboard_scope(&mut up_err_qb, listen_addr, route_id);
     up_err_qb.push(" GROUP BY upstream ORDER BY count DESC LIMIT 10");
     let top_upstream_errors = up_err_qb
         .build_query_as::<TopListItem>()
-        .fetch_all(&*pool)
+        .fetch_all(pool)
         .await?;
 
-    // Overall
-    let mut ov_qb = QueryBuilder::new("SELECT COUNT(1) AS total, SUM(CASE WHEN status_code BETWEEN 200 AND 299 THEN 1 ELSE 0 END) AS ok, AVG(latency_ms) AS avg_latency FROM request_logs WHERE timestamp >= ");
-    ov_qb
+    // Top hosts
+    let mut host_qb = QueryBuilder::new(
+        "SELECT request_host AS item, COUNT(1) AS count FROM request_logs WHERE timestamp >= ",
+    );
+    host_qb
         .push_bind(req.start_time)
         .push(" AND timestamp <= ")
         .push_bind(req.end_time);
-    if let Some(v) = listen_addr {
-        ov_qb.push(" AND listen_addr = ").push_bind(v);
-    }
-    let (total_requests, ok_requests, avg_latency): (i64, Option<i64>, Option<f64>) =
-        ov_qb.build_query_as().fetch_one(&*pool).await?;
+    host_qb.push(" AND request_host != ''");
+    push_dashboard_scope(&mut host_qb, listen_addr, route_id);
+    host_qb.push(" GROUP BY request_host ORDER BY count DESC LIMIT 10");
+    let top_hosts = host_qb
+        .build_query_as::<TopListItem>()
+        .fetch_all(pool)
+        .await?;
+
+    // Top countries（GeoIP）
+    let mut country_qb = QueryBuilder::new(
+        "SELECT country AS item, COUNT(1) AS count FROM request_logs WHERE timestamp >= ",
+    );
+    country_qb
+        .push_bind(req.start_time)
+        .push(" AND timestamp <= ")
+        .push_bind(req.end_time);
+    country_qb.push(" AND country != ''");
+    push_dashboard_scope(&mut country_qb, listen_addr, route_id);
+    country_qb.push(" GROUP BY country ORDER BY count DESC LIMIT 10");
+    let top_countries = country_qb
+        .build_query_as::<TopListItem>()
+        .fetch_all(pool)
+        .await?;
 
+    // Per-route breakdown
+    let mut breakdown_qb = QueryBuilder::new(
+        "SELECT matched_route_id AS route_id, COUNT(1) AS requests, SUM(CASE WHEN status_code >= 400 THEN 1 ELSE 0 END) AS errors, AVG(latency_ms) AS avg_latency_ms FROM request_logs WHERE timestamp >= ",
+    );
+    breakdown_qb
+        .push_bind(req.start_time)
+        .push(" AND timestamp <= ")
+        .push_bind(req.end_time);
+    breakdown_qb.push(" AND trim(matched_route_id) != ''");
+    push_dashboard_scope(&mut breakdown_qb, listen_addr, route_id);
+    breakdown_qb.push(" GROUP BY matched_route_id ORDER BY requests DESC LIMIT 10");
+    let route_breakdown = breakdown_qb
+        .build_query_as::<RouteTrafficItem>()
+        .fetch_all(pool)
+        .await?;
+
+    // Overall：由序列各桶累加，与序列口径一致
+    let mut total = RollupPoint::default();
+    for p in points.iter() {
+        total.merge(p);
+    }
+    let total_requests = total.total;
     let success_rate = if total_requests > 0 {
-        ok_requests.unwrap_or(0) as f64 / total_requests as f64
+        total.s2xx as f64 / total_requests as f64
     } else {
         0.0
     };
@@ -671,31 +909,26 @@ pub async fn get_dashboard_stats(req: DashboardStatsRequest) -> Result<Dashboard
         .push_bind(req.start_time)
         .push(" AND timestamp <= ")
         .push_bind(req.end_time);
-    if let Some(v) = listen_addr {
-        phase_qb.push(" AND listen_addr = ").push_bind(v);
-    }
-
-    let phase_rows: Vec<(f64, f64, f64)> = phase_qb.build_query_as().fetch_all(&*pool).await?;
-
-    let mut guard_vals = Vec::with_capacity(phase_rows.len());
-    let mut prepare_vals = Vec::with_capacity(phase_rows.len());
-    let mut upstream_vals = Vec::with_capacity(phase_rows.len());
+    push_dashboard_scope(&mut phase_qb, listen_addr, route_id);
 
-    for (g, p, u) in phase_rows {
-        guard_vals.push(g.max(0.0));
-        prepare_vals.push(p.max(0.0));
-        upstream_vals.push(u.max(0.0));
+    let mut phase_acc = PhaseTimingAcc::default();
+    let mut phase_rows = phase_qb.build_query_as::<(f64, f64, f64)>().fetch(pool);
+    while let Some(row) = phase_rows.try_next().await? {
+        phase_acc.add(row);
     }
+    drop(phase_rows);
+    let phase_timing = Some(phase_acc.finish());
 
-    guard_vals.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-    prepare_vals.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-    upstream_vals.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-
-    let phase_timing = Some(PhaseTimingStats {
-        guard: phase_stat_from_sorted(&guard_vals),
-        prepare: phase_stat_from_sorted(&prepare_vals),
-        upstream: phase_stat_from_sorted(&upstream_vals),
-    });
+    let (top_user_agents, top_referers) = if req.include_agents {
+        let agents = top_raw_values_sqlite(pool, &req, "user_agent").await?;
+        let referers = top_raw_values_sqlite(pool, &req, "referer").await?;
+        (
+            Some(merge_normalized_top(agents, normalize_user_agent_for_top)),
+            Some(merge_normalized_top(referers, normalize_referer_for_top)),
+        )
+    } else {
+        (None, None)
+    };
 
     Ok(DashboardStatsResponse {
         time_series,
@@ -704,9 +937,945 @@ pub async fn get_dashboard_stats(req: DashboardStatsRequest) -> Result<Dashboard
         top_routes,
         top_route_errors,
         top_upstream_errors,
+        top_paths_by_bytes,
         total_requests,
         success_rate,
-        avg_latency_ms: avg_latency.unwrap_or(0.0),
+        avg_latency_ms: total.avg_latency_ms(),
+        avg_upstream_ms: total.avg_upstream_ms(),
+        total_bytes_sent: total.bytes_sent,
         phase_timing,
+        top_user_agents,
+        top_referers,
+        top_hosts: Some(top_hosts),
+        top_countries: Some(top_countries),
+        route_breakdown: Some(route_breakdown),
+    })
+}
+
+const STATUS_CODE_BREAKDOWN_MAX: i64 = 50;
+const STATUS_CODE_SERIES_MAX: usize = 10;
+
+/// (time_bucket, status_code, count) 按桶对齐，缺失的桶补 0
+fn build_status_code_series(
+    rows: &[(i64, i32, i64)],
+    codes: &[i32],
+) -> (Vec<i64>, Vec<StatusCodeSeries>) {
+    let mut timestamps: Vec<i64> = rows.iter().map(|(b, _, _)| *b).collect();
+    timestamps.sort_unstable();
+    timestamps.dedup();
+
+    let mut series: Vec<StatusCodeSeries> = codes
+        .iter()
+        .map(|&status_code| StatusCodeSeries {
+            status_code,
+            counts: vec![0; timestamps.len()],
+        })
+        .collect();
+    for (bucket, code, count) in rows {
+        let (Ok(ti), Some(s)) = (
+            timestamps.binary_search(bucket),
+            series.iter_mut().find(|s| s.status_code == *code),
+        ) else {
+            continue;
+        };
+        s.counts[ti] += *count;
+    }
+    (timestamps, series)
+}
+
+pub async fn get_status_code_breakdown(
+    req: StatusCodeBreakdownRequest,
+) -> Result<StatusCodeBreakdownResponse> {
+    let Some(backend) = db_backend() else {
+        return Ok(StatusCodeBreakdownResponse::default());
+    };
+    let limit = req
+        .limit
+        .unwrap_or(STATUS_CODE_BREAKDOWN_MAX)
+        .clamp(1, STATUS_CODE_BREAKDOWN_MAX);
+
+    let codes = backend
+        .status_code_counts(&req, limit)
+        .await
+        .context("查询状态码分布失败")?;
+
+    let mut series_codes = req.series_codes.clone();
+    series_codes.sort_unstable();
+    series_codes.dedup();
+    series_codes.truncate(STATUS_CODE_SERIES_MAX);
+    if series_codes.is_empty() {
+        return Ok(StatusCodeBreakdownResponse {
+            codes,
+            timestamps: vec![],
+            series: vec![],
+        });
+    }
+
+    let gran = req.granularity_secs.unwrap_or(60).max(1);
+    let rows = backend
+        .status_code_series(&req, &series_codes, gran)
+        .await
+        .context("查询状态码时间序列失败")?;
+
+    let (timestamps, series) = build_status_code_series(&rows, &series_codes);
+    Ok(StatusCodeBreakdownResponse {
+        codes,
+        timestamps,
+        series,
+    })
+}
+
+/// 状态码分布，按次数降序取前 limit 个
+pub(super) fn status_code_counts_query<'a, DB>(
+    req: &'a StatusCodeBreakdownRequest,
+    limit: i64,
+) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let mut qb = QueryBuilder::new(
+        "SELECT status_code, COUNT(1) AS count FROM request_logs WHERE timestamp >= ",
+    );
+    qb.push_bind(req.start_time)
+        .push(" AND timestamp <= ")
+        .push_bind(req.end_time);
+    push_dashboard_scope(&mut qb, non_empty_trimmed(&req.listen_addr), None);
+    qb.push(" GROUP BY status_code ORDER BY count DESC, status_code ASC LIMIT ")
+        .push_bind(limit);
+    qb
+}
+
+/// 指定状态码的 (time_bucket, status_code, count)
+pub(super) fn status_code_series_query<'a, DB>(
+    req: &'a StatusCodeBreakdownRequest,
+    codes: &[i32],
+    gran: i64,
+) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    i32: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let mut qb = QueryBuilder::new("SELECT (timestamp / ");
+    qb.push_bind(gran)
+        .push(") * ")
+        .push_bind(gran)
+        .push(
+            " AS time_bucket, status_code, COUNT(1) AS count FROM request_logs WHERE timestamp >= ",
+        )
+        .push_bind(req.start_time)
+        .push(" AND timestamp <= ")
+        .push_bind(req.end_time);
+    push_dashboard_scope(&mut qb, non_empty_trimmed(&req.listen_addr), None);
+    qb.push(" AND status_code IN (");
+    let mut sep = qb.separated(", ");
+    for code in codes {
+        sep.push_bind(*code);
+    }
+    qb.push(") GROUP BY time_bucket, status_code ORDER BY time_bucket");
+    qb
+}
+
+const UPSTREAM_STATS_MAX: usize = 20;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct UpstreamStatAcc {
+    requests: i64,
+    errors: i64,
+    latency_sum_ms: f64,
+    latency_max_ms: f64,
+}
+
+impl UpstreamStatAcc {
+    pub(super) fn add(&mut self, status_code: i32, latency_ms: f64) {
+        self.requests += 1;
+        if status_code >= 500 {
+            self.errors += 1;
+        }
+        if latency_ms.is_finite() {
+            let v = latency_ms.max(0.0);
+            self.latency_sum_ms += v;
+            self.latency_max_ms = self.latency_max_ms.max(v);
+        }
+    }
+
+    pub(super) fn merge(&mut self, other: &Self) {
+        self.requests += other.requests;
+        self.errors += other.errors;
+        self.latency_sum_ms += other.latency_sum_ms;
+        self.latency_max_ms = self.latency_max_ms.max(other.latency_max_ms);
+    }
+}
+
+/// 按请求数降序取前 20，share 以全部上游请求为分母
+pub(super) fn upstream_stats_items(
+    accs: &HashMap<String, UpstreamStatAcc>,
+) -> Vec<UpstreamStatsItem> {
+    let total: i64 = accs.values().map(|a| a.requests).sum();
+    let mut v: Vec<UpstreamStatsItem> = accs
+        .iter()
+        .filter(|(_, a)| a.requests > 0)
+        .map(|(k, a)| UpstreamStatsItem {
+            upstream: k.clone(),
+            requests: a.requests,
+            errors: a.errors,
+            avg_latency_ms: a.latency_sum_ms / a.requests as f64,
+            max_latency_ms: a.latency_max_ms,
+            share: a.requests as f64 / total as f64,
+        })
+        .collect();
+    v.sort_unstable_by(|a, b| {
+        b.requests
+            .cmp(&a.requests)
+            .then_with(|| a.upstream.cmp(&b.upstream))
+    });
+    v.truncate(UPSTREAM_STATS_MAX);
+    v
+}
+
+/// (upstream, requests, errors, latency_sum_ms, latency_max_ms)
+pub(super) type UpstreamStatRow = (String, i64, i64, f64, f64);
+
+pub async fn get_upstream_stats(req: UpstreamStatsRequest) -> Result<Vec<UpstreamStatsItem>> {
+    let Some(backend) = db_backend() else {
+        return Ok(vec![]);
+    };
+
+    // 先按原始 upstream 聚合，再在内存中按 host:port 合并（与实时聚合口径一致）
+    let rows = backend
+        .upstream_stat_rows(&req)
+        .await
+        .context("查询上游统计失败")?;
+
+    let mut accs: HashMap<String, UpstreamStatAcc> = HashMap::new();
+    for (upstream, requests, errors, latency_sum_ms, latency_max_ms) in rows {
+        accs.entry(normalize_upstream_for_top(&upstream))
+            .or_default()
+            .merge(&UpstreamStatAcc {
+                requests,
+                errors,
+                latency_sum_ms,
+                latency_max_ms,
+            });
+    }
+    Ok(upstream_stats_items(&accs))
+}
+
+/// 按原始 upstream 分组的请求数、5xx 数与延迟，结果按 UpstreamStatRow 读取
+pub(super) fn upstream_stats_query<'a, DB>(req: &'a UpstreamStatsRequest) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let mut qb = QueryBuilder::new(
+        "SELECT upstream, COUNT(1), SUM(CASE WHEN status_code >= 500 THEN 1 ELSE 0 END), SUM(latency_ms), MAX(latency_ms) FROM request_logs WHERE timestamp >= ",
+    );
+    qb.push_bind(req.start_time)
+        .push(" AND timestamp <= ")
+        .push_bind(req.end_time)
+        .push(" AND trim(upstream) != ''");
+    push_dashboard_scope(
+        &mut qb,
+        non_empty_trimmed(&req.listen_addr),
+        non_empty_trimmed(&req.matched_route_id),
+    );
+    qb.push(" GROUP BY upstream");
+    qb
+}
+
+const ROUTE_STATS_MAX: i64 = 100;
+const ROUTE_STATS_TOP_PATHS: i64 = 5;
+
+// (listen_rule_id, host, path)
+type RouteInfo = (Option<String>, Option<String>, Option<String>);
+
+/// (route_id, requests, s2xx, s3xx, s4xx, s5xx, avg_latency_ms, bytes_sent)
+pub(super) type RouteStatRow = (String, i64, i64, i64, i64, i64, f64, i64);
+
+/// 后端查出的路由统计原始结果，由 get_route_stats 组装
+pub(super) struct RouteStatsParts {
+    pub rows: Vec<RouteStatRow>,
+    /// route_id -> 延迟直方图，只包含 rows 中的路由
+    pub latency: HashMap<String, LatencyHistogram>,
+    /// (route_id, request_path, count)，每个路由最多 ROUTE_STATS_TOP_PATHS 条
+    pub top_paths: Vec<(String, String, i64)>,
+}
+
+fn push_route_stats_scope<'a, DB>(
+    qb: &mut QueryBuilder<'a, DB>,
+    req: &'a RouteStatsRequest,
+    route_ids: Option<&'a [String]>,
+) where
+    DB: sqlx::Database,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    qb.push(" WHERE timestamp >= ")
+        .push_bind(req.start_time)
+        .push(" AND timestamp <= ")
+        .push_bind(req.end_time)
+        .push(" AND trim(matched_route_id) != ''");
+    if let Some(ids) = route_ids {
+        qb.push(" AND matched_route_id IN (");
+        let mut sep = qb.separated(", ");
+        for id in ids {
+            sep.push_bind(id.as_str());
+        }
+        sep.push_unseparated(")");
+    }
+}
+
+/// 按路由聚合的请求数与状态码分布，结果按 RouteStatRow 读取
+pub(super) fn route_stats_query<'a, DB>(
+    req: &'a RouteStatsRequest,
+    route_ids: Option<&'a [String]>,
+) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let mut qb = QueryBuilder::new(
+        r#"SELECT matched_route_id, COUNT(1) AS c,
+        SUM(CASE WHEN status_code BETWEEN 200 AND 299 THEN 1 ELSE 0 END),
+        SUM(CASE WHEN status_code BETWEEN 300 AND 399 THEN 1 ELSE 0 END),
+        SUM(CASE WHEN status_code BETWEEN 400 AND 499 THEN 1 ELSE 0 END),
+        SUM(CASE WHEN status_code >= 500 THEN 1 ELSE 0 END),
+        AVG(latency_ms), CAST(SUM(bytes_sent) AS BIGINT)
+    FROM request_logs"#,
+    );
+    push_route_stats_scope(&mut qb, req, route_ids);
+    qb.push(" GROUP BY matched_route_id ORDER BY c DESC LIMIT ")
+        .push_bind(ROUTE_STATS_MAX);
+    qb
+}
+
+/// (route_id, latency_ms) 原始值，调用方逐行流式写入直方图
+pub(super) fn route_latency_query<'a, DB>(
+    req: &'a RouteStatsRequest,
+    route_ids: Option<&'a [String]>,
+) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let mut qb = QueryBuilder::new("SELECT matched_route_id, latency_ms FROM request_logs");
+    push_route_stats_scope(&mut qb, req, route_ids);
+    qb
+}
+
+/// 每个路由内的 top paths：(route_id, request_path, count)
+pub(super) fn route_top_paths_query<'a, DB>(
+    req: &'a RouteStatsRequest,
+    route_ids: Option<&'a [String]>,
+) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let mut qb = QueryBuilder::new(
+        "SELECT r, p, c FROM (SELECT matched_route_id AS r, request_path AS p, COUNT(1) AS c, ROW_NUMBER() OVER (PARTITION BY matched_route_id ORDER BY COUNT(1) DESC) AS rn FROM request_logs",
+    );
+    push_route_stats_scope(&mut qb, req, route_ids);
+    qb.push(" GROUP BY matched_route_id, request_path) AS t WHERE rn <= ")
+        .push_bind(ROUTE_STATS_TOP_PATHS)
+        .push(" ORDER BY r, c DESC");
+    qb
+}
+
+pub(super) async fn route_stats_sqlite(
+    pool: &SqlitePool,
+    req: &RouteStatsRequest,
+    route_ids: Option<&[String]>,
+) -> Result<RouteStatsParts> {
+    let rows = route_stats_query::<sqlx::Sqlite>(req, route_ids)
+        .build_query_as::<RouteStatRow>()
+        .fetch_all(pool)
+        .await
+        .context("查询路由统计失败")?;
+    if rows.is_empty() {
+        return Ok(RouteStatsParts {
+            rows,
+            latency: HashMap::new(),
+            top_paths: vec![],
+        });
+    }
+
+    // p95：按路由流式写入直方图
+    let mut latency: HashMap<String, LatencyHistogram> = rows
+        .iter()
+        .map(|r| (r.0.clone(), LatencyHistogram::default()))
+        .collect();
+    let mut lat_qb = route_latency_query::<sqlx::Sqlite>(req, route_ids);
+    let mut lat_rows = lat_qb.build_query_as::<(String, f64)>().fetch(pool);
+    while let Some((route_id, latency_ms)) = lat_rows.try_next().await? {
+        if let Some(hist) = latency.get_mut(&route_id) {
+            hist.add(latency_ms);
+        }
+    }
+    drop(lat_rows);
+
+    let top_paths = route_top_paths_query::<sqlx::Sqlite>(req, route_ids)
+        .build_query_as::<(String, String, i64)>()
+        .fetch_all(pool)
+        .await
+        .context("查询路由 top paths 失败")?;
+
+    Ok(RouteStatsParts {
+        rows,
+        latency,
+        top_paths,
     })
 }
+
+pub async fn get_route_stats(req: RouteStatsRequest) -> Result<Vec<RouteStatsItem>> {
+    let Some(backend) = db_backend() else {
+        return Ok(vec![]);
+    };
+
+    // route_id -> (listen_rule_id, host, path)，用于回填展示信息
+    let cfg = crate::config::get_config();
+    let mut route_info: HashMap<String, RouteInfo> = HashMap::new();
+    for rule in cfg.rules.iter() {
+        for route in rule.routes.iter() {
+            if let Some(id) = route.id.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+                route_info.insert(
+                    id.to_string(),
+                    (rule.id.clone(), route.host.clone(), route.path.clone()),
+                );
+            }
+        }
+    }
+
+    let rule_filter = non_empty_trimmed(&req.listen_rule_id);
+    let rule_route_ids: Option<Vec<String>> = rule_filter.map(|rule_id| {
+        route_info
+            .iter()
+            .filter(|(_, (rid, _, _))| rid.as_deref() == Some(rule_id))
+            .map(|(id, _)| id.clone())
+            .collect()
+    });
+    if rule_route_ids.as_ref().is_some_and(|ids| ids.is_empty()) {
+        return Ok(vec![]);
+    }
+    let route_ids = rule_route_ids.as_deref();
+
+    let parts = backend.route_stats(&req, route_ids).await?;
+
+    let mut items: Vec<RouteStatsItem> = Vec::with_capacity(parts.rows.len());
+    let mut index: HashMap<String, usize> = HashMap::with_capacity(parts.rows.len());
+    for (route_id, requests, s2xx, s3xx, s4xx, s5xx, avg_latency_ms, bytes_sent) in parts.rows {
+        let (listen_rule_id, host, path) = route_info.get(&route_id).cloned().unwrap_or_default();
+        let label = match (host.as_deref(), path.as_deref()) {
+            _ if listen_rule_id.is_none() => route_id.clone(),
+            (Some(h), Some(p)) if !h.trim().is_empty() => format!("{}{}", h.trim(), p.trim()),
+            (_, Some(p)) => p.trim().to_string(),
+            (Some(h), None) => h.trim().to_string(),
+            (None, None) => "/".to_string(),
+        };
+        index.insert(route_id.clone(), items.len());
+        items.push(RouteStatsItem {
+            p95_latency_ms: parts
+                .latency
+                .get(&route_id)
+                .map_or(0.0, |h| h.percentile(0.95)),
+            route_id,
+            listen_rule_id,
+            host,
+            path,
+            label,
+            requests,
+            s2xx,
+            s3xx,
+            s4xx,
+            s5xx,
+            avg_latency_ms,
+            bytes_sent,
+            ..Default::default()
+        });
+    }
+
+    for (route_id, item, count) in parts.top_paths {
+        if let Some(&i) = index.get(&route_id) {
+            items[i].top_paths.push(TopListItem { item, count });
+        }
+    }
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_delta_keeps_recent_buckets() {
+        let mut agg = RtSeriesAgg::default();
+        for (i, ts) in [100, 101, 102, 103].into_iter().enumerate() {
+            agg.buckets.insert(
+                ts,
+                RtBucket {
+                    ts,
+                    count: 1,
+                    bytes_out: 20 + i as i64,
+                    ..Default::default()
+                },
+            );
+        }
+        let series = agg.to_metrics_series();
+        let payload = MetricsPayload {
+            window_seconds: 60,
+            listen_addrs: vec![":443".to_string()],
+            by_listen_addr: HashMap::from([(":443".to_string(), series.clone())]),
+            minute_window_seconds: Some(3600),
+            by_listen_minute: Some(HashMap::from([(":443".to_string(), series)])),
+            top_routes: None,
+            top_paths: None,
+            top_client_ips: None,
+            top_upstream_errors: None,
+            upstream_stats: None,
+            ws_metrics: None,
+            stream_metrics: None,
+            slow_requests: None,
+        };
+
+        let delta = metrics_delta_since(payload, 102);
+        let s = &delta.by_listen_addr[":443"];
+        assert_eq!(s.timestamps, vec![102, 103]);
+        assert_eq!(s.counts, vec![1, 1]);
+        assert_eq!(s.bytes_out.as_deref(), Some(&[22, 23][..]));
+        assert_eq!(s.avg_latency_ms.len(), 2);
+        // 分钟序列从 since 所在分钟（60）起保留
+        assert_eq!(delta.by_listen_minute.unwrap()[":443"].timestamps.len(), 4);
+    }
+
+    #[test]
+    fn status_code_series_aligns_buckets() {
+        let rows = vec![(60, 404, 3), (60, 429, 1), (120, 429, 5), (180, 500, 7)];
+        let (ts, series) = build_status_code_series(&rows, &[404, 429]);
+        assert_eq!(ts, vec![60, 120, 180]);
+        assert_eq!(series[0].status_code, 404);
+        assert_eq!(series[0].counts, vec![3, 0, 0]);
+        assert_eq!(series[1].counts, vec![1, 5, 0]);
+    }
+
+    #[test]
+    fn upstream_stats_share_and_order() {
+        let mut accs: HashMap<String, UpstreamStatAcc> = HashMap::new();
+        for (up, status, latency) in [
+            ("a:80", 200, 10.0),
+            ("a:80", 502, 30.0),
+            ("b:80", 200, 5.0),
+            ("a:80", 200, 20.0),
+        ] {
+            accs.entry(up.to_string()).or_default().add(status, latency);
+        }
+        let items = upstream_stats_items(&accs);
+        assert_eq!(items[0].upstream, "a:80");
+        assert_eq!(items[0].requests, 3);
+        assert_eq!(items[0].errors, 1);
+        assert!((items[0].avg_latency_ms - 20.0).abs() < 1e-9);
+        assert_eq!(items[0].max_latency_ms, 30.0);
+        assert!((items[0].share - 0.75).abs() < 1e-9);
+        assert_eq!(items[1].upstream, "b:80");
+    }
+
+    fn filter_log(
+        path: &str,
+        method: &str,
+        host: &str,
+        status_code: i32,
+        latency_ms: f64,
+        route: &str,
+    ) -> RequestLogInsert {
+        RequestLogInsert {
+            timestamp: 1_700_000_000,
+            listen_addr: ":443".to_string(),
+            client_ip: "10.0.0.1".to_string(),
+            remote_ip: "10.0.0.1".to_string(),
+            method: method.to_string(),
+            request_path: path.to_string(),
+            request_host: host.to_string(),
+            status_code,
+            upstream: "http://127.0.0.1:8080".to_string(),
+            latency_ms,
+            guard_ms: 0.0,
+            prepare_ms: 0.0,
+            upstream_ms: 0.0,
+            user_agent: "test".to_string(),
+            referer: "-".to_string(),
+            matched_route_id: route.to_string(),
+            bytes_sent: 0,
+            bytes_received: 0,
+            country: String::new(),
+            asn: String::new(),
+            sample_rate: 1.0,
+        }
+    }
+
+    fn filter_req() -> QueryRequestLogsRequest {
+        QueryRequestLogsRequest {
+            start_time: 1_699_999_000,
+            end_time: 1_700_001_000,
+            listen_addr: None,
+            upstream: None,
+            request_path: None,
+            client_ip: None,
+            status_code: None,
+            method: Some("post".to_string()),
+            page: 1,
+            page_size: 50,
+            matched_route_id: Some("r1".to_string()),
+            sort_by: Some("request_path".to_string()),
+            sort_order: Some("asc".to_string()),
+            request_host: Some("api.example.com".to_string()),
+            min_latency_ms: Some(1000.0),
+            max_latency_ms: None,
+            status_class: Some("5xx".to_string()),
+            country: None,
+            text_match: None,
+            search: None,
+            cursor: None,
+            include_total: false,
+        }
+    }
+
+    async fn query_plan(pool: &SqlitePool, req: &QueryRequestLogsRequest) -> String {
+        let page = RequestLogsPage::from_request(req).unwrap();
+        let method = normalized_method(req);
+        let filters = RequestLogQueryFilters::from_request(req, method.as_deref());
+        let mut qb = QueryBuilder::new("EXPLAIN QUERY PLAN SELECT id FROM request_logs");
+        append_request_logs_where(&mut qb, filters);
+        push_request_logs_page(&mut qb, req, page);
+        let rows: Vec<(i64, i64, i64, String)> = qb.build_query_as().fetch_all(pool).await.unwrap();
+        rows.into_iter().map(|r| r.3).collect::<Vec<_>>().join("; ")
+    }
+
+    #[tokio::test]
+    async fn request_log_filters_use_indexes() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        super::super::migrations::migrate_schema(&pool)
+            .await
+            .unwrap();
+        let base = || QueryRequestLogsRequest {
+            method: None,
+            matched_route_id: None,
+            sort_by: None,
+            sort_order: None,
+            request_host: None,
+            min_latency_ms: None,
+            status_class: None,
+            ..filter_req()
+        };
+
+        let mut cases: Vec<(QueryRequestLogsRequest, &str)> = Vec::new();
+        let mut req = base();
+        req.client_ip = Some("10.0.0.1".to_string());
+        req.text_match = Some("exact".to_string());
+        cases.push((req, "idx_request_logs_client_ip_ts"));
+        let mut req = base();
+        req.client_ip = Some("10.0.".to_string());
+        req.text_match = Some("prefix".to_string());
+        cases.push((req, "idx_request_logs_client_ip_ts"));
+        let mut req = base();
+        req.request_path = Some("/api/".to_string());
+        req.text_match = Some("prefix".to_string());
+        cases.push((req, "idx_request_logs_path_ts"));
+        let mut req = base();
+        req.status_code = Some(404);
+        cases.push((req, "idx_request_logs_status_ts"));
+        let mut req = base();
+        req.status_class = Some("5xx".to_string());
+        cases.push((req, "idx_request_logs_errors_ts"));
+        let mut req = base();
+        req.request_host = Some("api.example.com".to_string());
+        cases.push((req, "idx_request_logs_host_ts"));
+        let mut req = base();
+        req.listen_addr = Some(":443".to_string());
+        req.status_class = Some("4xx".to_string());
+        cases.push((req, "idx_request_logs_listen_ts"));
+        let mut req = base();
+        req.client_ip = Some("10.0.0.1".to_string());
+        req.text_match = Some("exact".to_string());
+        req.status_code = Some(404);
+        req.cursor = Some(String::new());
+        cases.push((req, "idx_request_logs_client_ip_ts"));
+
+        for (req, index) in cases {
+            let plan = query_plan(&pool, &req).await;
+            assert!(plan.contains(index), "expected {index}, got: {plan}");
+        }
+
+        // 前缀/精确匹配的结果与子串匹配的语义一致
+        let rows: Vec<RequestLogInsert> = ["10.0.0.1", "10.0.0.12", "10.1.0.1"]
+            .into_iter()
+            .map(|ip| RequestLogInsert {
+                client_ip: ip.to_string(),
+                ..filter_log("/ip", "GET", "api.example.com", 200, 1.0, "r1")
+            })
+            .collect();
+        super::super::backend::request_log_insert_builder::<sqlx::Sqlite>(&rows)
+            .build()
+            .execute(&pool)
+            .await
+            .unwrap();
+        for (mode, ip, expected) in [
+            ("contains", "0.1", 3),
+            ("prefix", "10.0.", 2),
+            ("exact", "10.0.0.1", 1),
+        ] {
+            let mut req = base();
+            req.client_ip = Some(ip.to_string());
+            req.text_match = Some(mode.to_string());
+            req.include_total = true;
+            let resp = query_request_logs_sqlite(&pool, req).await.unwrap();
+            assert_eq!(resp.total, expected, "{mode} {ip}");
+        }
+    }
+
+    #[tokio::test]
+    async fn request_log_filters_combine() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        super::super::migrations::migrate_schema(&pool)
+            .await
+            .unwrap();
+        let rows = vec![
+            filter_log("/hit", "POST", "api.example.com:8443", 503, 2500.0, "r1"),
+            filter_log("/ok", "POST", "api.example.com", 200, 2500.0, "r1"),
+            filter_log("/get", "GET", "api.example.com", 503, 2500.0, "r1"),
+            filter_log("/other-host", "POST", "www.example.com", 503, 2500.0, "r1"),
+            filter_log(
+                "/suffix-host",
+                "POST",
+                "xapi.example.com",
+                503,
+                2500.0,
+                "r1",
+            ),
+            filter_log("/fast", "POST", "api.example.com", 503, 10.0, "r1"),
+            filter_log("/slowest", "POST", "api.example.com", 502, 9000.0, "r1"),
+            filter_log("/other-route", "POST", "api.example.com", 503, 2500.0, "r2"),
+        ];
+        super::super::backend::request_log_insert_builder::<sqlx::Sqlite>(&rows)
+            .build()
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let resp = query_request_logs_sqlite(&pool, filter_req())
+            .await
+            .unwrap();
+        let paths: Vec<&str> = resp.logs.iter().map(|l| l.request_path.as_str()).collect();
+        assert_eq!(resp.total, 2);
+        assert_eq!(paths, vec!["/hit", "/slowest"]);
+
+        let mut req = filter_req();
+        req.status_class = None;
+        req.max_latency_ms = Some(3000.0);
+        let resp = query_request_logs_sqlite(&pool, req).await.unwrap();
+        let paths: Vec<&str> = resp.logs.iter().map(|l| l.request_path.as_str()).collect();
+        assert_eq!(resp.total, 2);
+        assert_eq!(paths, vec!["/hit", "/ok"]);
+
+        let mut req = filter_req();
+        req.status_class = Some("bogus".to_string());
+        req.request_host = None;
+        req.min_latency_ms = None;
+        let resp = query_request_logs_sqlite(&pool, req).await.unwrap();
+        assert_eq!(resp.total, 6);
+
+        let mut geo = filter_log("/geo", "POST", "api.example.com", 503, 2500.0, "r1");
+        geo.country = "CN".to_string();
+        super::super::backend::request_log_insert_builder::<sqlx::Sqlite>([&geo])
+            .build()
+            .execute(&pool)
+            .await
+            .unwrap();
+        let mut req = filter_req();
+        req.country = Some(" CN ".to_string());
+        let resp = query_request_logs_sqlite(&pool, req).await.unwrap();
+        let paths: Vec<&str> = resp.logs.iter().map(|l| l.request_path.as_str()).collect();
+        assert_eq!(paths, vec!["/geo"]);
+        assert_eq!(resp.logs[0].country, "CN");
+    }
+
+    #[tokio::test]
+    async fn request_log_search_covers_indexed_and_backfill_rows() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        super::super::migrations::migrate_schema(&pool)
+            .await
+            .unwrap();
+
+        // 模拟迁移前已存在、尚未回填索引的行
+        let mut old = filter_log("/old", "GET", "a.example.com", 200, 1.0, "r1");
+        old.user_agent = "sqlmap/1.7".to_string();
+        super::super::backend::request_log_insert_builder::<sqlx::Sqlite>([&old])
+            .build()
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "UPDATE request_logs_fts_state SET backfill_below = (SELECT MAX(id) + 1 FROM request_logs)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let backend = super::super::backend::SqliteBackend {
+            write: Arc::new(pool.clone()),
+            read: Arc::new(pool.clone()),
+        };
+        backend
+            .insert_request_logs(&[
+                filter_log("/q/SqlMap-probe", "GET", "a.example.com", 404, 1.0, "r1"),
+                filter_log("/clean", "GET", "a.example.com", 200, 1.0, "r1"),
+            ])
+            .await
+            .unwrap();
+
+        let search_req = |search: &str| {
+            let mut req = filter_req();
+            req.method = None;
+            req.matched_route_id = None;
+            req.request_host = None;
+            req.min_latency_ms = None;
+            req.status_class = None;
+            req.search = Some(search.to_string());
+            req
+        };
+
+        let resp = query_request_logs_sqlite(&pool, search_req("sqlmap"))
+            .await
+            .unwrap();
+        let paths: Vec<&str> = resp.logs.iter().map(|l| l.request_path.as_str()).collect();
+        assert_eq!(paths, vec!["/old", "/q/SqlMap-probe"]);
+
+        // 过短的模式走 LIKE
+        let resp = query_request_logs_sqlite(&pool, search_req("cl"))
+            .await
+            .unwrap();
+        assert_eq!(resp.total, 1);
+
+        // 回填完成后旧行只能通过索引命中（此处未回填，因此不再出现）
+        sqlx::query("UPDATE request_logs_fts_state SET backfill_below = 0")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let resp = query_request_logs_sqlite(&pool, search_req("sqlmap"))
+            .await
+            .unwrap();
+        assert_eq!(resp.total, 1);
+    }
+
+    #[tokio::test]
+    async fn request_log_cursor_pages_cover_all_rows_once() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        super::super::migrations::migrate_schema(&pool)
+            .await
+            .unwrap();
+        // 同一秒内多行，翻页边界需要靠 id 区分
+        let rows: Vec<RequestLogInsert> = [0, 0, 0, 100, 200]
+            .iter()
+            .enumerate()
+            .map(|(i, dt)| {
+                let mut log = filter_log(&format!("/p{}", i), "GET", "h", 200, 1.0, "r1");
+                log.timestamp += dt;
+                log
+            })
+            .collect();
+        super::super::backend::request_log_insert_builder::<sqlx::Sqlite>(&rows)
+            .build()
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let mut req = filter_req();
+        req.method = None;
+        req.matched_route_id = None;
+        req.request_host = None;
+        req.min_latency_ms = None;
+        req.status_class = None;
+        req.page_size = 2;
+        req.sort_order = None;
+        req.cursor = Some(String::new());
+
+        let mut seen = Vec::new();
+        for _ in 0..5 {
+            let resp = query_request_logs_sqlite(&pool, req.clone()).await.unwrap();
+            assert_eq!(resp.total, -1);
+            seen.extend(resp.logs.iter().map(|l| l.request_path.clone()));
+            match resp.next_cursor {
+                Some(c) => req.cursor = Some(c),
+                None => break,
+            }
+        }
+        assert_eq!(seen, vec!["/p4", "/p3", "/p2", "/p1", "/p0"]);
+
+        req.cursor = Some("garbage".to_string());
+        assert!(query_request_logs_sqlite(&pool, req).await.is_err());
+    }
+
+    #[test]
+    fn historical_queries_use_postgres_dialect() {
+        let scope = HistoricalScope {
+            start: 0,
+            end: 60,
+            listen_addr: Some(":443"),
+        };
+        let sql = upstream_dist_query::<sqlx::Postgres>(scope).into_sql();
+        assert!(sql.contains("split_part(h, '/', 1)"));
+        assert!(!sql.contains("instr("));
+        assert!(sql.contains("listen_addr = $3"));
+
+        let sql = crate::metrics::rollup::raw_series_query::<sqlx::Postgres>(0, 60, 60, None, None)
+            .into_sql();
+        assert!(!sql.contains("TOTAL("));
+        assert!(!sql.contains('?'));
+
+        let sql = crate::metrics::rollup::rollup_series_query::<sqlx::Postgres>(
+            3600,
+            0,
+            7200,
+            3600,
+            Some(":443"),
+            None,
+        )
+        .into_sql();
+        assert!(sql.contains("FROM request_logs_rollup_1h"));
+        assert!(sql.contains("CAST(SUM(total) AS BIGINT)"));
+        assert!(!sql.contains("TOTAL("));
+        assert!(!sql.contains('?'));
+
+        let sql = system_metrics_history_query::<sqlx::Postgres>(0, 60, 60).into_sql();
+        assert!(sql.contains("CAST(AVG(mem_used_bytes) AS DOUBLE PRECISION)"));
+        assert!(!sql.contains('?'));
+    }
+}
@@ -0,0 +1,142 @@
+use super::*;
+
+const RECOVERY_BACKOFF_BASE: Duration = Duration::from_secs(1);
+const RECOVERY_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct DbRecoveryState {
+    pub retrying: bool,
+    /// 连续失败次数（初始化失败或写入失败），成功写入一批日志后清零
+    pub consecutive_failures: u32,
+    pub next_attempt_at: Option<i64>,
+}
+
+static DB_RECOVERY: Lazy<RwLock<DbRecoveryState>> =
+    Lazy::new(|| RwLock::new(DbRecoveryState::default()));
+// 每次显式初始化/关闭数据库时递增，旧的重试任务据此退出
+static DB_RECOVERY_GEN: AtomicU64 = AtomicU64::new(0);
+
+pub(super) fn db_recovery_state() -> DbRecoveryState {
+    *DB_RECOVERY.read()
+}
+
+pub(super) fn is_db_recovering() -> bool {
+    DB_RECOVERY.read().retrying
+}
+
+/// 配置变更（重新初始化或关闭数据库）时取消正在进行的重试
+pub(super) fn cancel_db_recovery() {
+    DB_RECOVERY_GEN.fetch_add(1, Ordering::SeqCst);
+    *DB_RECOVERY.write() = DbRecoveryState::default();
+}
+
+pub(super) fn note_db_write_ok() {
+    let mut state = DB_RECOVERY.write();
+    if state.consecutive_failures > 0 && !state.retrying {
+        state.consecutive_failures = 0;
+    }
+}
+
+/// 连接池超时/关闭、库被锁、磁盘满或 I/O 错误等可通过重新初始化恢复的错误
+pub(super) fn is_recoverable_db_error(e: &anyhow::Error) -> bool {
+    let msg = format!("{e:#}").to_ascii_lowercase();
+    [
+        "database is locked",
+        "disk is full",
+        "disk i/o error",
+        "unable to open database",
+        "readonly database",
+        "pool timed out",
+        "pool closed",
+        "connection refused",
+        "connection reset",
+        "broken pipe",
+    ]
+    .iter()
+    .any(|k| msg.contains(k))
+}
+
+fn backoff_delay(failures: u32) -> Duration {
+    let exp = failures.saturating_sub(1).min(16);
+    (RECOVERY_BACKOFF_BASE * 2u32.pow(exp)).min(RECOVERY_BACKOFF_MAX)
+}
+
+/// 记录一次失败并在后台按指数退避重新初始化存储；已有重试任务时只累加失败次数
+pub fn schedule_db_recovery(reason: &str) {
+    let generation = DB_RECOVERY_GEN.load(Ordering::SeqCst);
+    {
+        let mut state = DB_RECOVERY.write();
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        if state.retrying {
+            return;
+        }
+        state.retrying = true;
+    }
+    crate::proxy::logging::push_log_line(format!(
+        "Metrics database unavailable, scheduling reconnect: {}",
+        reason
+    ));
+
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let delay = backoff_delay(DB_RECOVERY.read().consecutive_failures);
+            DB_RECOVERY.write().next_attempt_at =
+                Some(chrono::Utc::now().timestamp() + delay.as_secs() as i64);
+            tokio::time::sleep(delay).await;
+            if DB_RECOVERY_GEN.load(Ordering::SeqCst) != generation {
+                return;
+            }
+
+            let storage = crate::config::get_config()
+                .metrics_storage
+                .filter(|s| s.enabled);
+            let Some(storage) = storage else {
+                *DB_RECOVERY.write() = DbRecoveryState::default();
+                return;
+            };
+
+            match super::db::open_storage(&storage).await {
+                Ok(()) if DB_RECOVERY_GEN.load(Ordering::SeqCst) == generation => {
+                    let mut state = DB_RECOVERY.write();
+                    state.retrying = false;
+                    state.next_attempt_at = None;
+                    crate::proxy::logging::push_log_line(format!(
+                        "Metrics database reconnected after {} failed attempt(s)",
+                        state.consecutive_failures
+                    ));
+                    return;
+                }
+                Ok(()) => return,
+                Err(e) => {
+                    let mut state = DB_RECOVERY.write();
+                    state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+                    eprintln!("Metrics database reconnect failed: {e:#}");
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_and_caps() {
+        assert_eq!(backoff_delay(0), Duration::from_secs(1));
+        assert_eq!(backoff_delay(1), Duration::from_secs(1));
+        assert_eq!(backoff_delay(4), Duration::from_secs(8));
+        assert_eq!(backoff_delay(7), RECOVERY_BACKOFF_MAX);
+        assert_eq!(backoff_delay(u32::MAX), RECOVERY_BACKOFF_MAX);
+
+        assert!(is_recoverable_db_error(&anyhow!(
+            "error returned from database: (code: 13) database or disk is full"
+        )));
+        assert!(is_recoverable_db_error(
+            &anyhow!("database is locked").context("批量写入失败")
+        ));
+        assert!(!is_recoverable_db_error(&anyhow!(
+            "UNIQUE constraint failed: request_logs.id"
+        )));
+    }
+}
@@ -0,0 +1,596 @@
+use super::backend::MetricsBackend;
+use super::query::push_dashboard_scope;
+use super::*;
+use sqlx::postgres::PgPool;
+
+// 请求日志长期汇总：request_logs_rollup_1m / _1h 按 (桶, listen_addr, matched_route_id) 聚合。
+// request_logs_rollup_state.rolled_until（分钟对齐）之前的原始行都已计入汇总表，
+// 保留清理只删除水位以下的原始行；历史查询对水位以下读汇总表，水位之后读原始表。
+
+// 原始行落盘有批量延迟，且耗时长的请求在结束时才写入，只汇总足够旧的分钟
+const ROLLUP_LAG_SECS: i64 = 120;
+const ROLLUP_INTERVAL: Duration = Duration::from_secs(60);
+// 单个事务最多汇总的原始时间跨度，首次回填大库时分段进行
+pub(super) const ROLLUP_CHUNK_SECS: i64 = 6 * 60 * 60;
+const ROLLUP_CHUNK_PAUSE: Duration = Duration::from_millis(50);
+// 请求跨度达到该值且粒度为整小时时读小时表
+const ROLLUP_1H_MIN_SPAN_SECS: i64 = 7 * 24 * 60 * 60;
+
+pub(super) const ROLLUP_COLUMNS: &str = "bucket, listen_addr, matched_route_id, total, s2xx, s3xx, s4xx, s5xx, latency_sum_ms, latency_max_ms, upstream_count, upstream_sum_ms, bytes_sent";
+
+// 原始行的聚合表达式，按 1 / sample_rate 还原抽样前的请求量；未抽样的行 sample_rate = 1
+const RAW_AGGREGATES: &str = r#"CAST(ROUND(TOTAL(1.0 / sample_rate)) AS INTEGER) AS total,
+    CAST(ROUND(TOTAL(CASE WHEN status_code BETWEEN 200 AND 299 THEN 1.0 / sample_rate END)) AS INTEGER) AS s2xx,
+    CAST(ROUND(TOTAL(CASE WHEN status_code BETWEEN 300 AND 399 THEN 1.0 / sample_rate END)) AS INTEGER) AS s3xx,
+    CAST(ROUND(TOTAL(CASE WHEN status_code BETWEEN 400 AND 499 THEN 1.0 / sample_rate END)) AS INTEGER) AS s4xx,
+    CAST(ROUND(TOTAL(CASE WHEN status_code >= 500 THEN 1.0 / sample_rate END)) AS INTEGER) AS s5xx,
+    TOTAL(latency_ms / sample_rate) AS latency_sum_ms,
+    MAX(latency_ms) AS latency_max_ms,
+    CAST(ROUND(TOTAL(CASE WHEN upstream_ms > 0 THEN 1.0 / sample_rate END)) AS INTEGER) AS upstream_count,
+    TOTAL(CASE WHEN upstream_ms > 0 THEN upstream_ms / sample_rate END) AS upstream_sum_ms,
+    CAST(ROUND(TOTAL(bytes_sent / sample_rate)) AS INTEGER) AS bytes_sent"#;
+
+// RAW_AGGREGATES 的 Postgres 写法：SUM 无行时为 NULL，浮点求和结果转回 BIGINT
+pub(super) const PG_RAW_AGGREGATES: &str = r#"CAST(ROUND(COALESCE(SUM(1.0 / sample_rate), 0)) AS BIGINT) AS total,
+    CAST(ROUND(COALESCE(SUM(CASE WHEN status_code BETWEEN 200 AND 299 THEN 1.0 / sample_rate END), 0)) AS BIGINT) AS s2xx,
+    CAST(ROUND(COALESCE(SUM(CASE WHEN status_code BETWEEN 300 AND 399 THEN 1.0 / sample_rate END), 0)) AS BIGINT) AS s3xx,
+    CAST(ROUND(COALESCE(SUM(CASE WHEN status_code BETWEEN 400 AND 499 THEN 1.0 / sample_rate END), 0)) AS BIGINT) AS s4xx,
+    CAST(ROUND(COALESCE(SUM(CASE WHEN status_code >= 500 THEN 1.0 / sample_rate END), 0)) AS BIGINT) AS s5xx,
+    COALESCE(SUM(latency_ms / sample_rate), 0) AS latency_sum_ms,
+    COALESCE(MAX(latency_ms), 0) AS latency_max_ms,
+    CAST(ROUND(COALESCE(SUM(CASE WHEN upstream_ms > 0 THEN 1.0 / sample_rate END), 0)) AS BIGINT) AS upstream_count,
+    COALESCE(SUM(CASE WHEN upstream_ms > 0 THEN upstream_ms / sample_rate END), 0) AS upstream_sum_ms,
+    CAST(ROUND(COALESCE(SUM(bytes_sent / sample_rate), 0)) AS BIGINT) AS bytes_sent"#;
+
+/// 一个时间桶的可合并聚合值；均值由 sum/count 在读取时计算
+#[derive(Debug, Clone, Default, PartialEq, sqlx::FromRow)]
+pub(super) struct RollupPoint {
+    pub bucket: i64,
+    pub total: i64,
+    pub s2xx: i64,
+    pub s3xx: i64,
+    pub s4xx: i64,
+    pub s5xx: i64,
+    pub latency_sum_ms: f64,
+    pub latency_max_ms: f64,
+    pub upstream_count: i64,
+    pub upstream_sum_ms: f64,
+    pub bytes_sent: i64,
+}
+
+impl RollupPoint {
+    pub(super) fn merge(&mut self, other: &RollupPoint) {
+        self.total += other.total;
+        self.s2xx += other.s2xx;
+        self.s3xx += other.s3xx;
+        self.s4xx += other.s4xx;
+        self.s5xx += other.s5xx;
+        self.latency_sum_ms += other.latency_sum_ms;
+        self.latency_max_ms = self.latency_max_ms.max(other.latency_max_ms);
+        self.upstream_count += other.upstream_count;
+        self.upstream_sum_ms += other.upstream_sum_ms;
+        self.bytes_sent += other.bytes_sent;
+    }
+
+    pub(super) fn avg_latency_ms(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.latency_sum_ms / self.total as f64
+        }
+    }
+
+    pub(super) fn avg_upstream_ms(&self) -> f64 {
+        if self.upstream_count == 0 {
+            0.0
+        } else {
+            self.upstream_sum_ms / self.upstream_count as f64
+        }
+    }
+}
+
+/// 汇总水位；表不存在（未迁移）时返回 None
+pub(super) async fn rollup_watermark(pool: &SqlitePool) -> Option<i64> {
+    sqlx::query_scalar("SELECT rolled_until FROM request_logs_rollup_state WHERE id = 1")
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+}
+
+/// 汇总一段 [水位, min(水位 + 分段, target)) 的原始行并推进水位；返回本次推进后的水位
+pub(super) async fn rollup_request_logs_step(pool: &SqlitePool, target: i64) -> Result<i64> {
+    let _gate = db_write_gate().await;
+    let mut tx = pool.begin().await?;
+
+    let watermark: i64 =
+        sqlx::query_scalar("SELECT rolled_until FROM request_logs_rollup_state WHERE id = 1")
+            .fetch_one(&mut *tx)
+            .await
+            .context("读取汇总水位失败")?;
+    if watermark >= target {
+        return Ok(watermark);
+    }
+
+    // 跳过没有原始行的空档
+    let next_ts: Option<i64> =
+        sqlx::query_scalar("SELECT MIN(timestamp) FROM request_logs WHERE timestamp >= ?")
+            .bind(watermark)
+            .fetch_one(&mut *tx)
+            .await?;
+    let from = match next_ts {
+        Some(ts) => watermark.max((ts / 60) * 60).min(target),
+        None => target,
+    };
+    let to = from.saturating_add(ROLLUP_CHUNK_SECS).min(target);
+
+    if from < to {
+        sqlx::query(&format!(
+            r#"INSERT INTO request_logs_rollup_1m ({ROLLUP_COLUMNS})
+            SELECT (timestamp / 60) * 60 AS b, listen_addr, matched_route_id, {RAW_AGGREGATES}
+            FROM request_logs WHERE timestamp >= ? AND timestamp < ?
+            GROUP BY b, listen_addr, matched_route_id"#
+        ))
+        .bind(from)
+        .bind(to)
+        .execute(&mut *tx)
+        .await
+        .context("写入分钟汇总失败")?;
+
+        // 涉及的小时整体由分钟表重算，首尾不完整的小时下次会再次覆盖
+        sqlx::query(&format!(
+            r#"INSERT OR REPLACE INTO request_logs_rollup_1h ({ROLLUP_COLUMNS})
+            SELECT (bucket / 3600) * 3600 AS b, listen_addr, matched_route_id,
+                SUM(total), SUM(s2xx), SUM(s3xx), SUM(s4xx), SUM(s5xx),
+                TOTAL(latency_sum_ms), MAX(latency_max_ms),
+                SUM(upstream_count), TOTAL(upstream_sum_ms), SUM(bytes_sent)
+            FROM request_logs_rollup_1m WHERE bucket >= ? AND bucket < ?
+            GROUP BY b, listen_addr, matched_route_id"#
+        ))
+        .bind((from / 3600) * 3600)
+        .bind(to)
+        .execute(&mut *tx)
+        .await
+        .context("写入小时汇总失败")?;
+    }
+
+    sqlx::query("UPDATE request_logs_rollup_state SET rolled_until = ? WHERE id = 1")
+        .bind(to)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(to)
+}
+
+/// Postgres 版本：状态行加行锁，多个节点共用一个库时汇总任务串行推进水位
+pub(super) async fn rollup_request_logs_step_pg(pool: &PgPool, target: i64) -> Result<i64> {
+    let mut tx = pool.begin().await?;
+
+    let watermark: i64 = sqlx::query_scalar(
+        "SELECT rolled_until FROM request_logs_rollup_state WHERE id = 1 FOR UPDATE",
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .context("读取汇总水位失败")?;
+    if watermark >= target {
+        return Ok(watermark);
+    }
+
+    let next_ts: Option<i64> =
+        sqlx::query_scalar("SELECT MIN(timestamp) FROM request_logs WHERE timestamp >= $1")
+            .bind(watermark)
+            .fetch_one(&mut *tx)
+            .await?;
+    let from = match next_ts {
+        Some(ts) => watermark.max((ts / 60) * 60).min(target),
+        None => target,
+    };
+    let to = from.saturating_add(ROLLUP_CHUNK_SECS).min(target);
+
+    if from < to {
+        sqlx::query(&format!(
+            r#"INSERT INTO request_logs_rollup_1m ({ROLLUP_COLUMNS})
+            SELECT (timestamp / 60) * 60 AS b, listen_addr, matched_route_id, {PG_RAW_AGGREGATES}
+            FROM request_logs WHERE timestamp >= $1 AND timestamp < $2
+            GROUP BY b, listen_addr, matched_route_id"#
+        ))
+        .bind(from)
+        .bind(to)
+        .execute(&mut *tx)
+        .await
+        .context("写入分钟汇总失败")?;
+
+        let updates = ROLLUP_COLUMNS
+            .split(", ")
+            .skip(3)
+            .map(|c| format!("{c} = EXCLUDED.{c}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        sqlx::query(&format!(
+            r#"INSERT INTO request_logs_rollup_1h ({ROLLUP_COLUMNS})
+            SELECT (bucket / 3600) * 3600 AS b, listen_addr, matched_route_id,
+                CAST(SUM(total) AS BIGINT), CAST(SUM(s2xx) AS BIGINT), CAST(SUM(s3xx) AS BIGINT),
+                CAST(SUM(s4xx) AS BIGINT), CAST(SUM(s5xx) AS BIGINT),
+                COALESCE(SUM(latency_sum_ms), 0), MAX(latency_max_ms),
+                CAST(SUM(upstream_count) AS BIGINT), COALESCE(SUM(upstream_sum_ms), 0),
+                CAST(SUM(bytes_sent) AS BIGINT)
+            FROM request_logs_rollup_1m WHERE bucket >= $1 AND bucket < $2
+            GROUP BY b, listen_addr, matched_route_id
+            ON CONFLICT (bucket, listen_addr, matched_route_id) DO UPDATE SET {updates}"#
+        ))
+        .bind((from / 3600) * 3600)
+        .bind(to)
+        .execute(&mut *tx)
+        .await
+        .context("写入小时汇总失败")?;
+    }
+
+    sqlx::query("UPDATE request_logs_rollup_state SET rolled_until = $1 WHERE id = 1")
+        .bind(to)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(to)
+}
+
+/// 汇总到 now - ROLLUP_LAG_SECS 所在分钟为止
+pub(super) async fn rollup_request_logs(backend: &dyn MetricsBackend, now: i64) -> Result<()> {
+    let target = ((now - ROLLUP_LAG_SECS) / 60) * 60;
+    while backend.rollup_step(target).await? < target {
+        tokio::time::sleep(ROLLUP_CHUNK_PAUSE).await;
+    }
+    Ok(())
+}
+
+/// 后台定时汇总；数据库被关闭或切换后自动退出
+pub(super) async fn run_request_log_rollups(backend: Arc<dyn MetricsBackend>) {
+    loop {
+        if !db_backend().is_some_and(|b| Arc::ptr_eq(&b, &backend)) {
+            return;
+        }
+        if backend.rollup_watermark().await.is_none() {
+            return;
+        }
+        if let Err(e) = rollup_request_logs(&*backend, chrono::Utc::now().timestamp()).await {
+            eprintln!("Request log rollup failed: {}", e);
+        }
+        tokio::time::sleep(ROLLUP_INTERVAL).await;
+    }
+}
+
+/// 按跨度与粒度选择汇总表分辨率；粒度不是整分钟时只能读原始表
+pub(super) fn rollup_resolution(span: i64, granularity: i64) -> Option<i64> {
+    if granularity % 3600 == 0 && span >= ROLLUP_1H_MIN_SPAN_SECS {
+        Some(3600)
+    } else if granularity % 60 == 0 {
+        Some(60)
+    } else {
+        None
+    }
+}
+
+/// 时间序列聚合：能用汇总表时，水位以下读汇总表、水位之后读原始表再按桶合并。
+/// 走汇总表时首尾桶按整桶计入
+pub(super) async fn series_points(
+    backend: &dyn MetricsBackend,
+    start: i64,
+    end: i64,
+    granularity: i64,
+    listen_addr: Option<&str>,
+    route_id: Option<&str>,
+) -> Result<Vec<RollupPoint>> {
+    let granularity = granularity.max(1);
+    let resolution = rollup_resolution(end - start, granularity);
+    let watermark = match resolution {
+        Some(_) => backend.rollup_watermark().await,
+        None => None,
+    };
+    let (Some(resolution), Some(watermark)) = (resolution, watermark) else {
+        return backend
+            .raw_series_points(start, end, granularity, listen_addr, route_id)
+            .await;
+    };
+
+    let mut merged: BTreeMap<i64, RollupPoint> = BTreeMap::new();
+    if start < watermark {
+        let rows = backend
+            .rollup_series_points(resolution, start, end, granularity, listen_addr, route_id)
+            .await?;
+        for p in rows {
+            merged.insert(p.bucket, p);
+        }
+    }
+    if end >= watermark {
+        let rows = backend
+            .raw_series_points(
+                start.max(watermark),
+                end,
+                granularity,
+                listen_addr,
+                route_id,
+            )
+            .await?;
+        for p in rows {
+            merged
+                .entry(p.bucket)
+                .or_insert_with(|| RollupPoint {
+                    bucket: p.bucket,
+                    ..Default::default()
+                })
+                .merge(&p);
+        }
+    }
+    Ok(merged.into_values().collect())
+}
+
+/// 原始表按粒度分桶的聚合查询；TOTAL 为 SQLite 专有，Postgres 用 COALESCE(SUM) 并转回 BIGINT
+pub(super) fn raw_series_query<'a, DB>(
+    start: i64,
+    end: i64,
+    granularity: i64,
+    listen_addr: Option<&'a str>,
+    route_id: Option<&'a str>,
+) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let aggregates = if DB::NAME == "SQLite" {
+        RAW_AGGREGATES
+    } else {
+        PG_RAW_AGGREGATES
+    };
+    let mut qb = QueryBuilder::new("SELECT (timestamp / ");
+    qb.push_bind(granularity)
+        .push(") * ")
+        .push_bind(granularity)
+        .push(" AS bucket, ")
+        .push(aggregates)
+        .push(" FROM request_logs WHERE timestamp >= ");
+    qb.push_bind(start)
+        .push(" AND timestamp <= ")
+        .push_bind(end);
+    push_dashboard_scope(&mut qb, listen_addr, route_id);
+    qb.push(" GROUP BY bucket ORDER BY bucket");
+    qb
+}
+
+pub(super) async fn raw_series_points(
+    pool: &SqlitePool,
+    start: i64,
+    end: i64,
+    granularity: i64,
+    listen_addr: Option<&str>,
+    route_id: Option<&str>,
+) -> Result<Vec<RollupPoint>> {
+    raw_series_query::<sqlx::Sqlite>(start, end, granularity, listen_addr, route_id)
+        .build_query_as::<RollupPoint>()
+        .fetch_all(pool)
+        .await
+        .context("查询请求日志时间序列失败")
+}
+
+/// 汇总表按粒度分桶的查询；Postgres 的 SUM(BIGINT) 是 NUMERIC，需转回 BIGINT
+pub(super) fn rollup_series_query<'a, DB>(
+    resolution: i64,
+    start: i64,
+    end: i64,
+    granularity: i64,
+    listen_addr: Option<&'a str>,
+    route_id: Option<&'a str>,
+) -> QueryBuilder<'a, DB>
+where
+    DB: sqlx::Database,
+    <DB as sqlx::Database>::Arguments<'a>: Default,
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    &'a str: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let table = if resolution >= 3600 {
+        "request_logs_rollup_1h"
+    } else {
+        "request_logs_rollup_1m"
+    };
+    let aggregates = if DB::NAME == "SQLite" {
+        "SUM(total) AS total, SUM(s2xx) AS s2xx, SUM(s3xx) AS s3xx, SUM(s4xx) AS s4xx, SUM(s5xx) AS s5xx, \
+        TOTAL(latency_sum_ms) AS latency_sum_ms, MAX(latency_max_ms) AS latency_max_ms, \
+        SUM(upstream_count) AS upstream_count, TOTAL(upstream_sum_ms) AS upstream_sum_ms, SUM(bytes_sent) AS bytes_sent"
+    } else {
+        "CAST(SUM(total) AS BIGINT) AS total, CAST(SUM(s2xx) AS BIGINT) AS s2xx, CAST(SUM(s3xx) AS BIGINT) AS s3xx, \
+        CAST(SUM(s4xx) AS BIGINT) AS s4xx, CAST(SUM(s5xx) AS BIGINT) AS s5xx, \
+        COALESCE(SUM(latency_sum_ms), 0) AS latency_sum_ms, MAX(latency_max_ms) AS latency_max_ms, \
+        CAST(SUM(upstream_count) AS BIGINT) AS upstream_count, COALESCE(SUM(upstream_sum_ms), 0) AS upstream_sum_ms, \
+        CAST(SUM(bytes_sent) AS BIGINT) AS bytes_sent"
+    };
+    // 别名与列同名，分组按序号引用表达式，WHERE 中的 bucket 仍是原列
+    let mut qb = QueryBuilder::new("SELECT (bucket / ");
+    qb.push_bind(granularity)
+        .push(") * ")
+        .push_bind(granularity)
+        .push(" AS bucket, ")
+        .push(aggregates)
+        .push(" FROM ")
+        .push(table)
+        .push(" WHERE bucket >= ")
+        .push_bind((start / resolution) * resolution)
+        .push(" AND bucket <= ")
+        .push_bind(end);
+    push_dashboard_scope(&mut qb, listen_addr, route_id);
+    qb.push(" GROUP BY 1 ORDER BY 1");
+    qb
+}
+
+pub(super) async fn rollup_series_points(
+    pool: &SqlitePool,
+    resolution: i64,
+    start: i64,
+    end: i64,
+    granularity: i64,
+    listen_addr: Option<&str>,
+    route_id: Option<&str>,
+) -> Result<Vec<RollupPoint>> {
+    rollup_series_query::<sqlx::Sqlite>(resolution, start, end, granularity, listen_addr, route_id)
+        .build_query_as::<RollupPoint>()
+        .fetch_all(pool)
+        .await
+        .context("查询请求日志汇总失败")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::backend::SqliteBackend;
+    use super::*;
+
+    // 整点对齐的起始时间
+    const BASE: i64 = 1_699_999_200;
+
+    fn rollup_log(i: i64, ts: i64) -> RequestLogInsert {
+        RequestLogInsert {
+            timestamp: ts,
+            listen_addr: [":443", ":80"][(i % 2) as usize].to_string(),
+            client_ip: "10.0.0.1".to_string(),
+            remote_ip: "10.0.0.1".to_string(),
+            method: "GET".to_string(),
+            request_path: format!("/p/{}", i % 7),
+            request_host: "example.com".to_string(),
+            status_code: [200, 201, 301, 404, 500, 0][(i % 6) as usize],
+            upstream: "http://127.0.0.1:8080".to_string(),
+            latency_ms: (i % 97) as f64 * 0.5,
+            guard_ms: 0.0,
+            prepare_ms: 0.0,
+            upstream_ms: if i % 4 == 0 {
+                0.0
+            } else {
+                (i % 13) as f64 * 0.25
+            },
+            user_agent: "test".to_string(),
+            referer: "-".to_string(),
+            matched_route_id: ["a", "b", ""][(i % 3) as usize].to_string(),
+            bytes_sent: i * 10,
+            bytes_received: 0,
+            country: String::new(),
+            asn: String::new(),
+            sample_rate: 1.0,
+        }
+    }
+
+    async fn insert_logs(pool: &SqlitePool, rows: &[RequestLogInsert]) {
+        for chunk in rows.chunks(super::super::backend::REQUEST_LOG_CHUNK_SIZE) {
+            super::super::backend::request_log_insert_builder::<sqlx::Sqlite>(chunk)
+                .build()
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn rollup_test_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        super::super::migrations::migrate_schema(&pool)
+            .await
+            .unwrap();
+        pool
+    }
+
+    fn sqlite_backend(pool: &SqlitePool) -> SqliteBackend {
+        SqliteBackend {
+            write: Arc::new(pool.clone()),
+            read: Arc::new(pool.clone()),
+        }
+    }
+
+    const SCOPES: [(Option<&str>, Option<&str>); 4] = [
+        (None, None),
+        (Some(":443"), None),
+        (None, Some("a")),
+        (Some(":80"), Some("")),
+    ];
+
+    #[tokio::test]
+    async fn rollups_match_raw_aggregation() {
+        let pool = rollup_test_pool().await;
+        let rows: Vec<RequestLogInsert> = (0..3000)
+            .map(|i| rollup_log(i, BASE + (i * 37) % (3 * 3600)))
+            .collect();
+        insert_logs(&pool, &rows).await;
+
+        rollup_request_logs(&sqlite_backend(&pool), BASE + 3 * 3600 + ROLLUP_LAG_SECS)
+            .await
+            .unwrap();
+        assert_eq!(rollup_watermark(&pool).await, Some(BASE + 3 * 3600));
+
+        let end = BASE + 3 * 3600 - 1;
+        for (resolution, granularity) in [(60, 60), (60, 300), (60, 3600), (3600, 3600)] {
+            for (listen_addr, route_id) in SCOPES {
+                let rolled = rollup_series_points(
+                    &pool,
+                    resolution,
+                    BASE,
+                    end,
+                    granularity,
+                    listen_addr,
+                    route_id,
+                )
+                .await
+                .unwrap();
+                let raw = raw_series_points(&pool, BASE, end, granularity, listen_addr, route_id)
+                    .await
+                    .unwrap();
+                assert!(!raw.is_empty());
+                assert_eq!(
+                    rolled, raw,
+                    "resolution={} granularity={} scope={:?}/{:?}",
+                    resolution, granularity, listen_addr, route_id
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn series_merges_rollups_with_unrolled_tail_and_survives_purge() {
+        let pool = rollup_test_pool().await;
+        let rows: Vec<RequestLogInsert> = (0..2000)
+            .map(|i| rollup_log(i, BASE + (i * 53) % (4 * 3600)))
+            .collect();
+        insert_logs(&pool, &rows).await;
+
+        // 只汇总到 3.5 小时处，之后的行仍在原始表
+        let target = BASE + 3 * 3600 + 1800;
+        let backend = sqlite_backend(&pool);
+        rollup_request_logs(&backend, target + ROLLUP_LAG_SECS)
+            .await
+            .unwrap();
+        assert_eq!(rollup_watermark(&pool).await, Some(target));
+
+        let end = BASE + 4 * 3600 - 1;
+        let mut expected = Vec::new();
+        for (listen_addr, route_id) in SCOPES {
+            let merged = series_points(&backend, BASE, end, 60, listen_addr, route_id)
+                .await
+                .unwrap();
+            let raw = raw_series_points(&pool, BASE, end, 60, listen_addr, route_id)
+                .await
+                .unwrap();
+            assert_eq!(merged, raw);
+            expected.push(raw);
+        }
+
+        // 保留清理删掉已汇总的原始行后，序列仍由汇总表提供
+        sqlx::query("DELETE FROM request_logs WHERE timestamp < ?")
+            .bind(target)
+            .execute(&pool)
+            .await
+            .unwrap();
+        for ((listen_addr, route_id), expected) in SCOPES.into_iter().zip(expected) {
+            let merged = series_points(&backend, BASE, end, 60, listen_addr, route_id)
+                .await
+                .unwrap();
+            assert_eq!(merged, expected);
+        }
+    }
+}
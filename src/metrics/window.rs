@@ -0,0 +1,109 @@
+use super::histogram::LatencyHistogram;
+use super::*;
+use std::collections::VecDeque;
+
+/// 告警评估窗口上限（秒），最近耗时样本只保留这么久
+pub const REALTIME_ALERT_WINDOW_MAX_SECS: i64 = 900;
+// 每个分片每个监听地址最多保留的耗时样本数，高流量下分位数取最近的样本
+const LATENCY_SAMPLES_PER_SHARD: usize = 4096;
+
+/// 最近一段时间的请求耗时样本，用于估算窗口分位数
+#[derive(Debug, Default)]
+pub(super) struct LatencySamples {
+    samples: VecDeque<(i64, f64)>,
+}
+
+impl LatencySamples {
+    pub(super) fn push(&mut self, ts: i64, latency_ms: f64) {
+        if !latency_ms.is_finite() {
+            return;
+        }
+        self.samples.push_back((ts, latency_ms.max(0.0)));
+        while self.samples.len() > LATENCY_SAMPLES_PER_SHARD {
+            self.samples.pop_front();
+        }
+        let min_ts = ts - REALTIME_ALERT_WINDOW_MAX_SECS;
+        while self.samples.front().is_some_and(|(t, _)| *t < min_ts) {
+            self.samples.pop_front();
+        }
+    }
+
+    fn collect_since(&self, since: i64, hist: &mut LatencyHistogram) {
+        for (_, v) in self.samples.iter().rev().take_while(|(t, _)| *t >= since) {
+            hist.add(*v);
+        }
+    }
+}
+
+/// 实时聚合中某个监听地址最近 window 秒的汇总
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RealtimeWindowStats {
+    pub requests: i64,
+    pub s5xx: i64,
+    pub p95_latency_ms: f64,
+    pub p99_latency_ms: f64,
+}
+
+impl RealtimeWindowStats {
+    /// 5xx 占比（百分比）
+    pub fn error_rate_5xx(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.s5xx as f64 * 100.0 / self.requests as f64
+        }
+    }
+}
+
+/// 从实时聚合分片汇总最近 window_secs 秒的请求数、5xx 与耗时分位数；
+/// listen_addr 为空表示全局
+pub fn realtime_window_stats(listen_addr: &str, window_secs: i64, now: i64) -> RealtimeWindowStats {
+    let key = match listen_addr.trim() {
+        "" => "全局",
+        v => v,
+    };
+    let since = now - window_secs.clamp(1, REALTIME_ALERT_WINDOW_MAX_SECS) + 1;
+
+    let mut stats = RealtimeWindowStats::default();
+    let mut hist = LatencyHistogram::default();
+    for shard in REALTIME_AGG_SHARDS.iter() {
+        let guard = shard.read();
+        if let Some(series) = guard.per_sec.get(key) {
+            for b in series.buckets.range(since..=now).map(|(_, b)| b) {
+                stats.requests += b.count;
+                stats.s5xx += b.s5xx;
+            }
+        }
+        if let Some(samples) = guard.recent_latency.get(key) {
+            samples.collect_since(since, &mut hist);
+        }
+    }
+    if hist.count() > 0 {
+        stats.p95_latency_ms = hist.percentile(0.95);
+        stats.p99_latency_ms = hist.percentile(0.99);
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_samples_drop_old_and_excess_entries() {
+        let mut s = LatencySamples::default();
+        s.push(0, 5.0);
+        s.push(REALTIME_ALERT_WINDOW_MAX_SECS + 1, 7.0);
+        assert_eq!(s.samples.len(), 1);
+
+        for i in 0..(LATENCY_SAMPLES_PER_SHARD + 10) {
+            s.push(REALTIME_ALERT_WINDOW_MAX_SECS + 2, i as f64);
+        }
+        assert_eq!(s.samples.len(), LATENCY_SAMPLES_PER_SHARD);
+
+        let mut hist = LatencyHistogram::default();
+        s.collect_since(REALTIME_ALERT_WINDOW_MAX_SECS + 2, &mut hist);
+        assert_eq!(hist.count(), LATENCY_SAMPLES_PER_SHARD as u64);
+    }
+}
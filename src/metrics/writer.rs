@@ -1,6 +1,23 @@
-use super::db::REQUEST_LOG_TX;
+use super::anonymize::anonymize_ip;
+use super::backend::REQUEST_LOG_CHUNK_SIZE;
+use super::db::{purge_expired_logs, REQUEST_LOG_TX, STREAM_LOG_TX};
+use super::recovery::{
+    is_db_recovering, is_recoverable_db_error, note_db_write_ok, schedule_db_recovery,
+};
 use super::*;
 
+// 写入任务退出时回传最终批次的落盘与丢弃条数
+#[derive(Debug, Default)]
+struct FinalFlush {
+    requests: usize,
+    requests_dropped: usize,
+    streams: usize,
+    streams_dropped: usize,
+}
+
+type WriterDone = tokio::sync::oneshot::Receiver<FinalFlush>;
+static WRITER_DONE_RX: Lazy<RwLock<Option<WriterDone>>> = Lazy::new(|| RwLock::new(None));
+
 pub async fn init_request_log_writer() {
     if REQUEST_LOG_TX.read().is_some() {
         return;
@@ -8,25 +25,42 @@ pub async fn init_request_log_writer() {
 
     let (tx, mut rx) = tokio::sync::mpsc::channel::<RequestLogInsert>(50_000);
     *REQUEST_LOG_TX.write() = Some(tx);
+    let (stream_tx, mut stream_rx) = tokio::sync::mpsc::channel::<StreamLogInsert>(10_000);
+    *STREAM_LOG_TX.write() = Some(stream_tx);
+    let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+    *WRITER_DONE_RX.write() = Some(done_rx);
 
     tauri::async_runtime::spawn(async move {
         let mut buf: Vec<RequestLogInsert> = Vec::with_capacity(DB_FLUSH_BATCH_SIZE);
+        let mut stream_buf: Vec<StreamLogInsert> = Vec::new();
         let mut last_flush = Instant::now();
         let mut last_cleanup = Instant::now();
-        let mut last_retention_check = Instant::now();
+        let mut last_retention_check: Option<Instant> = None;
 
         loop {
             tokio::select! {
-                Some(item) = rx.recv() => {
+                // 缓冲因数据库故障积压到一批时暂停接收，新日志留在通道里直到容量上限
+                item = rx.recv(), if buf.len() < DB_FLUSH_BATCH_SIZE => {
+                    // 发送端被 shutdown 取走后，队列中剩余条目收完即返回 None
+                    let Some(item) = item else { break };
                     buf.push(item);
                     if buf.len() >= DB_FLUSH_BATCH_SIZE {
                         flush_request_logs(&mut buf).await;
                         last_flush = Instant::now();
                     }
                 }
+                Some(item) = stream_rx.recv() => {
+                    stream_buf.push(item);
+                    if stream_buf.len() >= DB_FLUSH_BATCH_SIZE {
+                        flush_stream_logs(&mut stream_buf).await;
+                    }
+                }
                 _ = tokio::time::sleep(Duration::from_millis(200)) => {
-                    if !buf.is_empty() && last_flush.elapsed() >= DB_FLUSH_INTERVAL {
+                    if (!buf.is_empty() || !stream_buf.is_empty())
+                        && last_flush.elapsed() >= DB_FLUSH_INTERVAL
+                    {
                         flush_request_logs(&mut buf).await;
+                        flush_stream_logs(&mut stream_buf).await;
                         last_flush = Instant::now();
                     }
                 }
@@ -35,99 +69,257 @@ pub async fn init_request_log_writer() {
             // 在后台线程做黑名单清理
             if last_cleanup.elapsed().as_secs() > 10 {
                 // 修复点：先获取 Option<Arc>，不要持有 ReadLockGuard 过 await
-                let pool_opt = db_pool();
-                if let Some(pool) = pool_opt {
-                    let _ = super::db::refresh_blacklist_cache_internal(&pool).await;
+                let backend_opt = db_backend();
+                if let Some(backend) = backend_opt {
+                    let _ = super::db::refresh_blacklist_cache_internal(&*backend).await;
                 }
                 last_cleanup = Instant::now();
             }
 
-            // request_logs 日志保留：每天检查一次
-            if last_retention_check.elapsed() >= REQUEST_LOG_RETENTION_CHECK_INTERVAL {
-                let pool_opt = db_pool();
-                if let Some(pool) = pool_opt {
-                    let cutoff =
-                        chrono::Utc::now().timestamp() - REQUEST_LOG_RETENTION_DAYS * 24 * 60 * 60;
-                    let deleted_rows = sqlx::query("DELETE FROM request_logs WHERE timestamp < ?")
-                        .bind(cutoff)
-                        .execute(&*pool)
-                        .await
-                        .map(|r| r.rows_affected())
-                        .unwrap_or(0);
-
-                    reclaim_db_space_after_delete(&pool, deleted_rows).await;
+            // 日志保留：启动后首次检查，之后每天一次；清理放到独立任务，不阻塞批量写入
+            let retention_due = last_retention_check
+                .is_none_or(|t| t.elapsed() >= REQUEST_LOG_RETENTION_CHECK_INTERVAL);
+            if retention_due {
+                if let Some(backend) = db_backend() {
+                    tauri::async_runtime::spawn(async move {
+                        purge_expired_logs(&*backend).await;
+                    });
+                    last_retention_check = Some(Instant::now());
                 }
-                last_retention_check = Instant::now();
             }
         }
+
+        while let Ok(item) = stream_rx.try_recv() {
+            stream_buf.push(item);
+        }
+        // 数据库仍在重连时缓冲会原样留下，按实际写入数统计，其余计为丢弃
+        let (pending, pending_streams) = (buf.len(), stream_buf.len());
+        let requests = flush_request_logs(&mut buf).await;
+        let streams = flush_stream_logs(&mut stream_buf).await;
+        let flushed = FinalFlush {
+            requests,
+            requests_dropped: pending - requests,
+            streams,
+            streams_dropped: pending_streams - streams,
+        };
+        if let Some(pool) = db_pool() {
+            let _ = sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+                .execute(&*pool)
+                .await;
+        }
+        let _ = done_tx.send(flushed);
     });
 }
 
-pub fn try_enqueue_request_log(log: RequestLogInsert) {
+/// 关闭写入队列并等待写入任务落盘剩余日志、checkpoint WAL；超时后直接返回，避免退出卡住
+pub async fn shutdown(timeout: Duration) {
+    let request_tx = REQUEST_LOG_TX.write().take();
+    let stream_tx = STREAM_LOG_TX.write().take();
+    let done_rx = WRITER_DONE_RX.write().take();
+    if request_tx.is_none() && stream_tx.is_none() {
+        return;
+    }
+    drop(request_tx);
+    drop(stream_tx);
+
+    let Some(done_rx) = done_rx else { return };
+    match tokio::time::timeout(timeout, done_rx).await {
+        Ok(Ok(f)) => {
+            eprintln!(
+                "Metrics shutdown flushed {} request logs and {} stream logs",
+                f.requests, f.streams
+            );
+            if f.requests_dropped > 0 || f.streams_dropped > 0 {
+                eprintln!(
+                    "Metrics shutdown dropped {} request logs and {} stream logs that could not be written",
+                    f.requests_dropped, f.streams_dropped
+                );
+            }
+        }
+        Ok(Err(_)) => {}
+        Err(_) => eprintln!(
+            "Metrics shutdown timed out after {}ms, pending logs were dropped",
+            timeout.as_millis()
+        ),
+    }
+}
+
+/// 同步上下文（退出命令、窗口销毁回调）使用
+pub fn shutdown_blocking(timeout: Duration) {
+    tauri::async_runtime::block_on(shutdown(timeout));
+}
+
+pub fn try_enqueue_request_log(mut log: RequestLogInsert) {
+    let geo = crate::geoip::lookup(&log.client_ip);
+    log.country = geo.country;
+    log.asn = geo.asn;
+
+    let anonymization = crate::config::metrics_ip_anonymization();
+    if let Some((mode, true)) = anonymization {
+        anonymize_request_log_ips(&mut log, mode);
+    }
+
     let la = log.listen_addr.trim();
     let shard_key = if la.is_empty() { "全局" } else { la };
     let idx = (hash_fnv1a_64(shard_key) as usize) % REALTIME_SHARDS;
 
     {
         let mut agg = REALTIME_AGG_SHARDS[idx].write();
-        agg.add(
-            &log.listen_addr,
-            log.timestamp,
-            log.status_code,
-            log.latency_ms,
-            &log.matched_route_id,
-            &log.request_path,
-            &log.client_ip,
-            &log.upstream,
-        );
+        agg.add(&log);
+    }
+
+    // 实时聚合保留完整 IP 时，只在入队落库前脱敏
+    if let Some((mode, false)) = anonymization {
+        anonymize_request_log_ips(&mut log, mode);
+    }
+
+    super::live_tail::publish(&log);
+
+    // 抽样只影响落库，实时聚合已在上面按全量更新
+    if let Some(sampling) = crate::config::metrics_log_sampling() {
+        if is_sampling_candidate(&log, &sampling) {
+            let seq = REQUEST_LOG_SAMPLE_SEQ.fetch_add(1, Ordering::Relaxed);
+            if !sampled_in(seq, sampling.success_rate) {
+                REQUEST_LOG_SAMPLED_OUT.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            log.sample_rate = sampling.success_rate;
+        }
     }
 
     if let Some(tx) = REQUEST_LOG_TX.read().as_ref() {
+        if tx.try_send(log).is_err() {
+            REQUEST_LOG_DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// 只有不慢的 2xx/3xx 请求参与抽样
+fn is_sampling_candidate(
+    log: &RequestLogInsert,
+    sampling: &crate::config::LogSamplingConfig,
+) -> bool {
+    (200..400).contains(&log.status_code) && log.latency_ms < sampling.slow_threshold_ms
+}
+
+/// 按序号均匀抽样：每 1/rate 条保留一条，比例精确且不依赖随机数
+fn sampled_in(seq: u64, rate: f64) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+    let rate = rate.max(0.0);
+    ((seq + 1) as f64 * rate).floor() > (seq as f64 * rate).floor()
+}
+
+fn anonymize_request_log_ips(log: &mut RequestLogInsert, mode: crate::config::IpAnonymizeMode) {
+    log.client_ip = anonymize_ip(&log.client_ip, mode);
+    log.remote_ip = anonymize_ip(&log.remote_ip, mode);
+}
+
+pub fn try_enqueue_stream_log(mut log: StreamLogInsert) {
+    if let Some((mode, _)) = crate::config::metrics_ip_anonymization() {
+        log.client_ip = anonymize_ip(&log.client_ip, mode);
+    }
+    if let Some(tx) = STREAM_LOG_TX.read().as_ref() {
         let _ = tx.try_send(log);
     }
 }
 
-async fn flush_request_logs(buf: &mut Vec<RequestLogInsert>) {
-    let Some(pool) = db_pool() else {
+/// 返回实际写入的条数
+async fn flush_stream_logs(buf: &mut Vec<StreamLogInsert>) -> usize {
+    let Some(backend) = db_backend() else {
         buf.clear();
-        return;
+        return 0;
     };
     if buf.is_empty() {
-        return;
+        return 0;
     }
+    let _gate = db_write_gate().await;
+
+    let inserted = match backend.insert_stream_logs(buf).await {
+        Ok(()) => buf.len(),
+        Err(e) => {
+            eprintln!("Bulk insert stream logs failed: {}", e);
+            0
+        }
+    };
 
-    // 使用 QueryBuilder 进行批量插入
-    const CHUNK_SIZE: usize = 500;
+    buf.clear();
+    inserted
+}
 
-    for chunk in buf.chunks(CHUNK_SIZE) {
-        let mut query_builder = QueryBuilder::new(
-            "INSERT INTO request_logs (timestamp, listen_addr, client_ip, remote_ip, method, request_path, request_host, status_code, upstream, latency_ms, guard_ms, prepare_ms, upstream_ms, user_agent, referer, matched_route_id) "
+/// 返回实际写入的条数；重连期间未写入的行留在缓冲里
+async fn flush_request_logs(buf: &mut Vec<RequestLogInsert>) -> usize {
+    if buf.is_empty() {
+        return 0;
+    }
+    // 自动重连期间保留缓冲，等数据库恢复后再写
+    if is_db_recovering() {
+        return 0;
+    }
+    // 运行中数据库被关闭（如配置热重载）时，计入丢弃数而不是静默清空
+    let Some(backend) = db_backend() else {
+        REQUEST_LOG_DROPPED.fetch_add(buf.len() as u64, Ordering::Relaxed);
+        buf.clear();
+        return 0;
+    };
+    let _gate = db_write_gate().await;
+
+    let mut inserted = 0;
+    let mut written = 0;
+    let mut outage: Option<anyhow::Error> = None;
+    for chunk in buf.chunks(REQUEST_LOG_CHUNK_SIZE) {
+        let Err(e) = backend.insert_request_logs(chunk).await else {
+            inserted += chunk.len();
+            written += chunk.len();
+            continue;
+        };
+        // 库被锁、磁盘满等整体故障：保留未写入的行并触发重连，而不是逐行重试后丢弃
+        if is_recoverable_db_error(&e) {
+            outage = Some(e);
+            break;
+        }
+        eprintln!(
+            "Bulk insert request logs failed, retrying row by row: {}",
+            e
         );
 
-        query_builder.push_values(chunk, |mut b, it| {
-            b.push_bind(it.timestamp)
-                .push_bind(&it.listen_addr)
-                .push_bind(&it.client_ip)
-                .push_bind(&it.remote_ip)
-                .push_bind(&it.method)
-                .push_bind(&it.request_path)
-                .push_bind(&it.request_host)
-                .push_bind(it.status_code)
-                .push_bind(&it.upstream)
-                .push_bind(it.latency_ms)
-                .push_bind(it.guard_ms)
-                .push_bind(it.prepare_ms)
-                .push_bind(it.upstream_ms)
-                .push_bind(&it.user_agent)
-                .push_bind(&it.referer)
-                .push_bind(&it.matched_route_id);
-        });
-
-        let query = query_builder.build();
-        if let Err(e) = query.execute(&*pool).await {
-            eprintln!("Bulk insert request logs failed: {}", e);
+        // 逐行重试，隔离出有问题的记录
+        for row in chunk {
+            match backend.insert_request_logs(std::slice::from_ref(row)).await {
+                Ok(()) => inserted += 1,
+                Err(e) => {
+                    REQUEST_LOG_DROPPED.fetch_add(1, Ordering::Relaxed);
+                    eprintln!("Insert request log failed: {}", e);
+                }
+            }
         }
+        written += chunk.len();
+    }
+    if inserted > 0 {
+        REQUEST_LOG_LAST_FLUSH_AT.store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+        note_db_write_ok();
     }
 
+    if let Some(e) = outage {
+        buf.drain(..written);
+        schedule_db_recovery(&e.to_string());
+        return inserted;
+    }
     buf.clear();
+    inserted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sampled_in;
+
+    #[test]
+    fn sampled_in_keeps_exact_ratio() {
+        let kept = (0..1000).filter(|seq| sampled_in(*seq, 0.1)).count();
+        assert_eq!(kept, 100);
+        let kept = (0..1000).filter(|seq| sampled_in(*seq, 0.25)).count();
+        assert_eq!(kept, 250);
+        assert!((0..10).all(|seq| sampled_in(seq, 1.0)));
+    }
 }
@@ -104,7 +104,11 @@ impl TcpOptimizer {
     /// 优化 tokio TcpListener
     pub async fn optimize_listener(&self, addr: SocketAddr) -> Result<tokio::net::TcpListener> {
         let socket = self.create_optimized_socket(&addr)?;
-        socket.bind(&addr.into())?;
+        if let Err(e) = socket.bind(&addr.into()) {
+            // 端口被占用时在错误中带上占用的进程，原始 io::Error 保留为根因
+            let detail = crate::port_owner::describe_bind_error(addr, &e);
+            return Err(anyhow::Error::new(e).context(detail));
+        }
         socket.listen(1024)?; // backlog = 1024
 
         // 转换为 tokio TcpListener
@@ -0,0 +1,641 @@
+use crate::config::{Config, ListenRule, Route, StreamProxyConfig, Upstream};
+use crate::proxy::ws_proxy::{WsListenRule, WsRoute};
+
+// 与 access_control::is_lan_ip 判定范围一致
+const LAN_RANGES: [&str; 6] = [
+    "10.0.0.0/8",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "169.254.0.0/16",
+    "fc00::/7",
+    "fe80::/10",
+];
+
+#[derive(Default)]
+struct Writer {
+    out: String,
+    depth: usize,
+}
+
+impl Writer {
+    fn line(&mut self, s: impl AsRef<str>) {
+        for _ in 0..self.depth {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(s.as_ref());
+        self.out.push('\n');
+    }
+
+    fn comment(&mut self, s: impl AsRef<str>) {
+        self.line(format!("# {}", s.as_ref()));
+    }
+
+    fn blank(&mut self) {
+        self.out.push('\n');
+    }
+
+    fn open(&mut self, s: impl AsRef<str>) {
+        self.line(format!("{} {{", s.as_ref()));
+        self.depth += 1;
+    }
+
+    fn close(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+        self.line("}");
+    }
+}
+
+/// 将当前配置尽量翻译为 nginx 配置；无法对应的功能以注释形式保留
+pub fn render_nginx_config(cfg: &Config) -> String {
+    let mut w = Writer::default();
+    w.comment(format!(
+        "Generated by SSLProxyManager {} at {}",
+        env!("CARGO_PKG_VERSION"),
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+    ));
+    w.comment("Best-effort translation, review before use.");
+    w.blank();
+    w.line("events {}");
+    w.blank();
+
+    w.open("http");
+    w.open("map $http_upgrade $connection_upgrade");
+    w.line("default upgrade;");
+    w.line("'' close;");
+    w.close();
+    w.line(format!(
+        "proxy_connect_timeout {}ms;",
+        cfg.upstream_connect_timeout_ms
+    ));
+    w.line(format!(
+        "proxy_read_timeout {}ms;",
+        cfg.upstream_read_timeout_ms
+    ));
+    w.line(format!("client_max_body_size {};", cfg.max_body_size));
+    if cfg.compression_enabled && cfg.compression_gzip {
+        w.line("gzip on;");
+        w.line(format!("gzip_comp_level {};", cfg.compression_gzip_level));
+        w.line(format!("gzip_min_length {};", cfg.compression_min_length));
+    }
+    if cfg.compression_enabled && cfg.compression_brotli {
+        w.comment("brotli compression requires the ngx_brotli module");
+    }
+
+    for (i, rule) in cfg.rules.iter().enumerate() {
+        w.blank();
+        if !rule.enabled {
+            w.comment(format!("listen rule {} is disabled", rule.listen_addr));
+            continue;
+        }
+        render_http_rule(&mut w, cfg, i, rule);
+    }
+
+    if cfg.ws_proxy_enabled {
+        for (i, rule) in cfg.ws_proxy.iter().flatten().enumerate() {
+            w.blank();
+            if !rule.enabled {
+                w.comment(format!("WS rule {} is disabled", rule.listen_addr));
+                continue;
+            }
+            render_ws_rule(&mut w, cfg, i, rule);
+        }
+    }
+    w.close();
+
+    if cfg.stream.enabled && !cfg.stream.servers.is_empty() {
+        w.blank();
+        render_stream(&mut w, &cfg.stream);
+    }
+
+    w.out
+}
+
+fn listen_directives(addr: &str, suffix: &str) -> Vec<String> {
+    let addr = addr.trim();
+    match addr.strip_prefix(':') {
+        Some(port) => vec![
+            format!("listen {port}{suffix};"),
+            format!("listen [::]:{port}{suffix};"),
+        ],
+        None => vec![format!("listen {addr}{suffix};")],
+    }
+}
+
+fn rule_listen_addrs(rule: &ListenRule) -> Vec<&str> {
+    if rule.listen_addrs.is_empty() {
+        vec![rule.listen_addr.as_str()]
+    } else {
+        rule.listen_addrs.iter().map(String::as_str).collect()
+    }
+}
+
+/// nginx 的 .example.com 同时匹配主域名与子域名，与 *.example.com 路由的行为一致
+fn server_name(host: Option<&str>) -> String {
+    match host {
+        Some(h) => h
+            .split(',')
+            .map(str::trim)
+            .filter(|h| !h.is_empty())
+            .map(|h| match h.strip_prefix("*.") {
+                Some(suffix) => format!(".{suffix}"),
+                None => h.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        None => "_".to_string(),
+    }
+}
+
+type HostGroup<'a, T> = (Option<String>, Vec<(usize, &'a T)>);
+
+/// 按 host 分组，每组对应一个 server 块；保持路由的出现顺序
+fn group_by_host<'a, T>(
+    items: impl Iterator<Item = (usize, &'a T)>,
+    host: impl Fn(&T) -> Option<&str>,
+) -> Vec<HostGroup<'a, T>> {
+    let mut groups: Vec<HostGroup<'a, T>> = Vec::new();
+    for (idx, item) in items {
+        let key = host(item)
+            .map(str::trim)
+            .filter(|h| !h.is_empty())
+            .map(str::to_string);
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => v.push((idx, item)),
+            None => groups.push((key, vec![(idx, item)])),
+        }
+    }
+    groups
+}
+
+/// 输出 upstream 块并返回 proxy_pass 使用的 scheme://name
+fn render_upstream_block(w: &mut Writer, name: &str, upstreams: &[Upstream]) -> Option<String> {
+    let mut scheme = None;
+    let mut servers = Vec::new();
+    for upstream in upstreams {
+        let Ok(url) = url::Url::parse(upstream.url.trim()) else {
+            w.comment(format!("invalid upstream URL skipped: {}", upstream.url));
+            continue;
+        };
+        let Some(host) = url.host_str() else {
+            continue;
+        };
+        let host = if host.contains(':') {
+            format!("[{host}]")
+        } else {
+            host.to_string()
+        };
+        let s = match url.scheme() {
+            "https" | "wss" => "https",
+            _ => "http",
+        };
+        if scheme.is_some_and(|prev| prev != s) {
+            w.comment(format!(
+                "upstream {} mixes http and https, scheme of the first server is used",
+                name
+            ));
+        }
+        scheme.get_or_insert(s);
+        if url.path() != "/" && !url.path().is_empty() {
+            w.comment(format!(
+                "path of upstream {} is not translated: {}",
+                upstream.url,
+                url.path()
+            ));
+        }
+        let port = url.port_or_known_default().unwrap_or(80);
+        servers.push(format!(
+            "server {host}:{port} weight={};",
+            upstream.weight.max(1)
+        ));
+    }
+    let scheme = scheme?;
+    w.open(format!("upstream {name}"));
+    for s in servers {
+        w.line(s);
+    }
+    w.close();
+    Some(format!("{scheme}://{name}"))
+}
+
+/// proxy_pass 带 URI 时 nginx 用它替换 location 前缀；按路由前缀的结尾斜杠对齐，
+/// 与 build_upstream_url 的拼接结果保持一致
+fn proxy_pass_uri(route_path: &str, proxy_pass_path: Option<&str>) -> String {
+    let Some(pp) = proxy_pass_path else {
+        return String::new();
+    };
+    let pp = pp.trim();
+    let pp = if pp.is_empty() { "/" } else { pp };
+    let pp = if pp.starts_with('/') {
+        pp.to_string()
+    } else {
+        format!("/{pp}")
+    };
+    match (route_path.ends_with('/'), pp.ends_with('/')) {
+        (true, false) => format!("{pp}/"),
+        (false, true) if pp.len() > 1 => pp.trim_end_matches('/').to_string(),
+        _ => pp,
+    }
+}
+
+fn render_access_control(w: &mut Writer, cfg: &Config, enabled: bool) {
+    if !enabled || cfg.allow_all_ip {
+        return;
+    }
+    w.line("allow 127.0.0.0/8;");
+    w.line("allow ::1;");
+    for entry in &cfg.whitelist {
+        let ip = entry.ip.trim();
+        if !ip.is_empty() {
+            w.line(format!("allow {ip};"));
+        }
+    }
+    if cfg.allow_all_lan {
+        for range in LAN_RANGES {
+            w.line(format!("allow {range};"));
+        }
+    }
+    w.line("deny all;");
+    w.comment("IP blacklist entries are stored in the metrics database and are not exported");
+}
+
+fn render_http_rule(w: &mut Writer, cfg: &Config, rule_idx: usize, rule: &ListenRule) {
+    let zone = format!("rule{rule_idx}");
+    let rate_limited = rule.rate_limit_enabled.unwrap_or(false);
+    if rate_limited {
+        w.line(format!(
+            "limit_req_zone $binary_remote_addr zone={zone}:10m rate={}r/s;",
+            rule.rate_limit_requests_per_second.unwrap_or(10)
+        ));
+    }
+
+    let routes = rule.routes.iter().enumerate().filter(|(_, r)| r.enabled);
+    for (host, routes) in group_by_host(routes, |r: &Route| r.host.as_deref()) {
+        let mut passes = Vec::with_capacity(routes.len());
+        for (route_idx, route) in &routes {
+            passes.push(if route.static_dir.is_some() {
+                None
+            } else {
+                render_upstream_block(
+                    w,
+                    &format!("rule{rule_idx}_route{route_idx}"),
+                    &route.upstreams,
+                )
+            });
+        }
+
+        w.open("server");
+        let suffix = if rule.ssl_enable { " ssl" } else { "" };
+        for addr in rule_listen_addrs(rule) {
+            for l in listen_directives(addr, suffix) {
+                w.line(l);
+            }
+        }
+        w.line(format!("server_name {};", server_name(host.as_deref())));
+        if rule.ssl_enable {
+            w.line(format!("ssl_certificate {};", rule.cert_file));
+            w.line(format!("ssl_certificate_key {};", rule.key_file));
+            if cfg.enable_http2 {
+                w.line("http2 on;");
+            }
+        }
+        render_access_control(w, cfg, cfg.http_access_control_enabled);
+        if rule.basic_auth_enable {
+            w.line("auth_basic \"Restricted\";");
+            w.line("auth_basic_user_file /etc/nginx/.htpasswd;");
+            w.comment(format!(
+                "create .htpasswd for user \"{}\", passwords are not exported",
+                rule.basic_auth_username
+            ));
+        }
+        if rate_limited {
+            w.line(format!(
+                "limit_req zone={zone} burst={} nodelay;",
+                rule.rate_limit_burst_size.unwrap_or(20)
+            ));
+            if rule.rate_limit_ban_seconds.unwrap_or(0) > 0 {
+                w.comment(format!(
+                    "rate limit auto-ban ({}s) is not supported by nginx",
+                    rule.rate_limit_ban_seconds.unwrap_or(0)
+                ));
+            }
+        }
+        if let Some(rate) = rule.bandwidth_limit_bytes_per_sec.filter(|r| *r > 0) {
+            w.comment("per-client bandwidth limit is approximated per connection");
+            w.line(format!("limit_rate {rate};"));
+        }
+
+        for ((_, route), pass) in routes.iter().zip(passes) {
+            w.blank();
+            render_http_location(w, rule, route, pass.as_deref());
+        }
+        w.close();
+    }
+}
+
+fn render_http_location(w: &mut Writer, rule: &ListenRule, route: &Route, pass: Option<&str>) {
+    let path = route.path.as_deref().unwrap_or("/");
+    w.open(format!("location {path}"));
+    if let Some(methods) = route.methods.as_ref().filter(|m| !m.is_empty()) {
+        w.comment(format!(
+            "route only matches methods: {} (not translated)",
+            methods.join(", ")
+        ));
+    }
+    if let Some(headers) = route.headers.as_ref().filter(|h| !h.is_empty()) {
+        let mut pairs: Vec<_> = headers.iter().map(|(k, v)| format!("{k}={v}")).collect();
+        pairs.sort();
+        w.comment(format!(
+            "route only matches headers: {} (not translated)",
+            pairs.join(", ")
+        ));
+    }
+    if route.exclude_basic_auth.unwrap_or(false) {
+        w.line("auth_basic off;");
+    } else if route.basic_auth_enable.unwrap_or(false) {
+        w.line("auth_basic \"Restricted\";");
+        w.line("auth_basic_user_file /etc/nginx/.htpasswd;");
+        w.comment(format!(
+            "route-level basic auth user \"{}\"",
+            route.basic_auth_username.as_deref().unwrap_or("")
+        ));
+    }
+    for rw in route
+        .url_rewrite_rules
+        .iter()
+        .flatten()
+        .filter(|r| r.enabled)
+    {
+        w.line(format!("rewrite {} {} break;", rw.pattern, rw.replacement));
+    }
+    if route
+        .request_body_replace
+        .as_ref()
+        .is_some_and(|r| r.iter().any(|r| r.enabled))
+    {
+        w.comment("request body replace rules are not supported by nginx");
+    }
+    if route
+        .response_body_replace
+        .as_ref()
+        .is_some_and(|r| r.iter().any(|r| r.enabled))
+    {
+        w.comment("response body replace rules are not translated (see sub_filter)");
+    }
+    if let Some(enabled) = route.compression_enabled {
+        w.line(format!("gzip {};", if enabled { "on" } else { "off" }));
+    }
+
+    if let Some(dir) = route.static_dir.as_deref() {
+        w.line(format!("root {dir};"));
+        w.close();
+        return;
+    }
+    let Some(pass) = pass else {
+        w.comment("no valid upstream configured");
+        w.close();
+        return;
+    };
+
+    w.line(format!(
+        "proxy_pass {pass}{};",
+        proxy_pass_uri(path, route.proxy_pass_path.as_deref())
+    ));
+    if route.follow_redirects {
+        w.comment("follow_redirects is not supported by nginx, redirects are passed to the client");
+    }
+    w.line("proxy_http_version 1.1;");
+    w.line("proxy_set_header Host $http_host;");
+    w.line("proxy_set_header Upgrade $http_upgrade;");
+    w.line("proxy_set_header Connection $connection_upgrade;");
+    w.line("proxy_set_header X-Real-IP $remote_addr;");
+    w.line("proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;");
+    w.line("proxy_set_header X-Forwarded-Proto $scheme;");
+    if rule.basic_auth_enable && !rule.basic_auth_forward_header {
+        w.line("proxy_set_header Authorization \"\";");
+    }
+    if let Some(headers) = route.set_headers.as_ref() {
+        let mut headers: Vec<_> = headers.iter().collect();
+        headers.sort();
+        for (k, v) in headers {
+            w.line(format!(
+                "proxy_set_header {} \"{}\";",
+                k.trim(),
+                v.replace('"', "\\\"")
+            ));
+        }
+    }
+    for name in route.remove_headers.iter().flatten() {
+        let name = name.trim();
+        if !name.is_empty() {
+            w.line(format!("proxy_hide_header {name};"));
+        }
+    }
+    w.close();
+}
+
+fn render_ws_rule(w: &mut Writer, cfg: &Config, rule_idx: usize, rule: &WsListenRule) {
+    let routes = rule.routes.iter().enumerate();
+    for (host, routes) in group_by_host(routes, |r: &WsRoute| r.host.as_deref()) {
+        let mut passes = Vec::with_capacity(routes.len());
+        for (route_idx, route) in &routes {
+            passes.push(render_upstream_block(
+                w,
+                &format!("ws{rule_idx}_route{route_idx}"),
+                &route.upstreams,
+            ));
+        }
+
+        w.open("server");
+        let suffix = if rule.ssl_enable { " ssl" } else { "" };
+        for l in listen_directives(&rule.listen_addr, suffix) {
+            w.line(l);
+        }
+        w.line(format!("server_name {};", server_name(host.as_deref())));
+        if rule.ssl_enable {
+            w.line(format!("ssl_certificate {};", rule.cert_file));
+            w.line(format!("ssl_certificate_key {};", rule.key_file));
+        }
+        render_access_control(w, cfg, cfg.ws_access_control_enabled);
+        if rule.idle_timeout_secs > 0 {
+            w.line(format!("proxy_read_timeout {}s;", rule.idle_timeout_secs));
+        }
+
+        for ((_, route), pass) in routes.iter().zip(passes) {
+            w.blank();
+            w.open(format!("location {}", route.path));
+            match pass {
+                Some(pass) => {
+                    w.line(format!(
+                        "proxy_pass {pass}{};",
+                        proxy_pass_uri(&route.path, route.proxy_pass_path.as_deref())
+                    ));
+                    w.line("proxy_http_version 1.1;");
+                    w.line("proxy_set_header Upgrade $http_upgrade;");
+                    w.line("proxy_set_header Connection $connection_upgrade;");
+                    w.line("proxy_set_header Host $http_host;");
+                    w.line("proxy_set_header X-Real-IP $remote_addr;");
+                    w.line("proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;");
+                }
+                None => w.comment("no valid upstream configured"),
+            }
+            w.close();
+        }
+        w.close();
+    }
+}
+
+fn render_stream(w: &mut Writer, stream: &StreamProxyConfig) {
+    w.open("stream");
+    for upstream in &stream.upstreams {
+        w.open(format!("upstream {}", upstream.name));
+        if upstream.least_conn {
+            w.line("least_conn;");
+        } else if upstream.consistent {
+            w.line("hash $remote_addr consistent;");
+        } else {
+            w.line("hash $remote_addr;");
+        }
+        if let Some(timeout) = upstream.queue_timeout.as_deref() {
+            w.comment(format!("queue_timeout {timeout} is not supported by nginx"));
+        }
+        for server in &upstream.servers {
+            let mut line = format!(
+                "server {} weight={} max_fails={} fail_timeout={}",
+                server.addr,
+                server.weight.max(1),
+                server.max_fails.max(0),
+                server.fail_timeout
+            );
+            if server.max_conns > 0 {
+                line.push_str(&format!(" max_conns={}", server.max_conns));
+            }
+            line.push(';');
+            w.line(line);
+        }
+        w.close();
+    }
+
+    for (i, server) in stream.servers.iter().enumerate() {
+        w.blank();
+        if !server.enabled {
+            w.comment(format!("stream server #{i} is disabled"));
+            continue;
+        }
+        let sni_var = format!("$stream{i}_upstream");
+        if !server.sni_routing.is_empty() {
+            w.open(format!("map $ssl_preread_server_name {sni_var}"));
+            w.line("hostnames;");
+            for route in &server.sni_routing {
+                let sni = match route.sni.strip_prefix("*.") {
+                    Some(suffix) => format!(".{suffix}"),
+                    None => route.sni.clone(),
+                };
+                w.line(format!("{sni} {};", route.proxy_pass));
+            }
+            if !server.proxy_pass.is_empty() {
+                w.line(format!("default {};", server.proxy_pass));
+            }
+            w.close();
+        }
+
+        w.open("server");
+        let addr = match (server.listen_addr.as_deref(), server.listen_port) {
+            (Some(a), _) if !a.trim().is_empty() => a.trim().to_string(),
+            (_, Some(p)) => format!("127.0.0.1:{p}"),
+            _ => String::new(),
+        };
+        for l in listen_directives(&addr, if server.udp { " udp" } else { "" }) {
+            w.line(l);
+        }
+        if server.sni_routing.is_empty() {
+            w.line(format!("proxy_pass {};", server.proxy_pass));
+        } else {
+            w.line("ssl_preread on;");
+            w.line(format!("proxy_pass {sni_var};"));
+        }
+        w.line(format!(
+            "proxy_connect_timeout {};",
+            server.proxy_connect_timeout
+        ));
+        w.line(format!("proxy_timeout {};", server.proxy_timeout));
+        if server.proxy_protocol {
+            w.line("proxy_protocol on;");
+        }
+        if !server.udp && server.connect_retries > 0 {
+            w.line("proxy_next_upstream on;");
+            w.line(format!(
+                "proxy_next_upstream_tries {};",
+                server.connect_retries + 1
+            ));
+        }
+        if let Some(rate) = server.limit_rate.filter(|r| *r > 0) {
+            w.line(format!("proxy_upload_rate {rate};"));
+            w.line(format!("proxy_download_rate {rate};"));
+        }
+        if let Some(d) = server.max_session_duration.as_deref() {
+            w.comment(format!(
+                "max_session_duration {d} is not supported by nginx"
+            ));
+        }
+        if server.limit_rate_total.is_some_and(|r| r > 0) {
+            w.comment("limit_rate_total is not supported by nginx");
+        }
+        if server.limit_pps.is_some_and(|r| r > 0) {
+            w.comment("limit_pps is not supported by nginx");
+        }
+        w.close();
+    }
+    w.close();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_server_location_and_upstream() {
+        let cfg: Config = toml::from_str(
+            r#"
+            allow_all_lan = false
+            allow_all_ip = true
+            whitelist = []
+
+            [[rules]]
+            listen_addr = ":443"
+            ssl_enable = true
+            cert_file = "/etc/ssl/site.crt"
+            key_file = "/etc/ssl/site.key"
+            basic_auth_enable = false
+            basic_auth_username = ""
+            basic_auth_password = ""
+            basic_auth_forward_header = false
+
+            [[rules.routes]]
+            host = "*.example.com"
+            path = "/api/"
+            proxy_pass_path = "/v1"
+            set_headers = { X-Upstream = "$host" }
+            response_body_replace = [{ find = "a", replace = "b" }]
+            upstreams = [{ url = "http://10.0.0.1:8080", weight = 3 }, { url = "http://10.0.0.2:8080" }]
+            "#,
+        )
+        .unwrap();
+
+        let out = render_nginx_config(&cfg);
+        for expected in [
+            "upstream rule0_route0 {\n        server 10.0.0.1:8080 weight=3;\n        server 10.0.0.2:8080 weight=1;\n    }",
+            "listen 443 ssl;",
+            "listen [::]:443 ssl;",
+            "server_name .example.com;",
+            "ssl_certificate /etc/ssl/site.crt;",
+            "location /api/ {",
+            "proxy_pass http://rule0_route0/v1/;",
+            "proxy_set_header X-Upstream \"$host\";",
+            "# response body replace rules are not translated",
+        ] {
+            assert!(out.contains(expected), "missing {expected:?} in:\n{out}");
+        }
+        assert!(!out.contains("deny all;"));
+        assert!(!out.contains("stream {"));
+    }
+}
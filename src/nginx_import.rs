@@ -0,0 +1,828 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::config::{Config, ListenRule, Route, Upstream};
+
+// include 嵌套上限，防止循环引用
+const MAX_INCLUDE_DEPTH: usize = 8;
+// proxy_set_header 中运行时能展开的变量，见 expand_proxy_header_value
+const SUPPORTED_HEADER_VARS: [&str; 4] = [
+    "$remote_addr",
+    "$host",
+    "$scheme",
+    "$proxy_add_x_forwarded_for",
+];
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NginxImportReport {
+    pub rules_added: usize,
+    pub routes_added: usize,
+    pub imported: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+impl NginxImportReport {
+    fn skip(&mut self, d: &Directive, reason: &str) {
+        self.skipped
+            .push(format!("line {}: {} ({})", d.line, d.text(), reason));
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Directive {
+    name: String,
+    args: Vec<String>,
+    block: Option<Vec<Directive>>,
+    line: usize,
+}
+
+impl Directive {
+    fn text(&self) -> String {
+        if self.args.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} {}", self.name, self.args.join(" "))
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Semi,
+    Open,
+    Close,
+}
+
+fn tokenize(src: &str) -> Result<Vec<(Token, usize)>> {
+    let mut tokens = Vec::new();
+    let mut chars = src.chars().peekable();
+    let mut line = 1usize;
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '\n' => {
+                line += 1;
+                chars.next();
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '#' => {
+                while chars.peek().is_some_and(|c| *c != '\n') {
+                    chars.next();
+                }
+            }
+            ';' | '{' | '}' => {
+                chars.next();
+                let token = match c {
+                    ';' => Token::Semi,
+                    '{' => Token::Open,
+                    _ => Token::Close,
+                };
+                tokens.push((token, line));
+            }
+            '"' | '\'' => {
+                let start = line;
+                chars.next();
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err(anyhow!("unterminated quote starting at line {start}")),
+                        Some('\\') => {
+                            if let Some(next) = chars.next() {
+                                word.push(next);
+                            }
+                        }
+                        Some(q) if q == c => break,
+                        Some(other) => {
+                            if other == '\n' {
+                                line += 1;
+                            }
+                            word.push(other);
+                        }
+                    }
+                }
+                tokens.push((Token::Word(word), start));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, ';' | '{' | '}') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push((Token::Word(word), line));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_block(tokens: &[(Token, usize)], pos: &mut usize, nested: bool) -> Result<Vec<Directive>> {
+    let mut out = Vec::new();
+    while *pos < tokens.len() {
+        let (token, line) = &tokens[*pos];
+        *pos += 1;
+        let name = match token {
+            Token::Word(w) => w.clone(),
+            Token::Semi => continue,
+            Token::Close if nested => return Ok(out),
+            Token::Close => return Err(anyhow!("unexpected '}}' at line {line}")),
+            Token::Open => return Err(anyhow!("unexpected '{{' at line {line}")),
+        };
+
+        let mut args = Vec::new();
+        loop {
+            let Some((token, _)) = tokens.get(*pos) else {
+                return Err(anyhow!(
+                    "directive '{name}' at line {line} is not terminated"
+                ));
+            };
+            *pos += 1;
+            match token {
+                Token::Word(w) => args.push(w.clone()),
+                Token::Semi => {
+                    out.push(Directive {
+                        name,
+                        args,
+                        block: None,
+                        line: *line,
+                    });
+                    break;
+                }
+                Token::Open => {
+                    let block = parse_block(tokens, pos, true)?;
+                    out.push(Directive {
+                        name,
+                        args,
+                        block: Some(block),
+                        line: *line,
+                    });
+                    break;
+                }
+                Token::Close => {
+                    return Err(anyhow!("directive '{name}' at line {line} is missing ';'"))
+                }
+            }
+        }
+    }
+    if nested {
+        return Err(anyhow!("unexpected end of file, missing '}}'"));
+    }
+    Ok(out)
+}
+
+fn parse(src: &str) -> Result<Vec<Directive>> {
+    let tokens = tokenize(src)?;
+    let mut pos = 0;
+    parse_block(&tokens, &mut pos, false)
+}
+
+/// 展开 include；找不到的文件只记入报告，不视为错误
+fn expand_includes(
+    directives: Vec<Directive>,
+    base_dir: Option<&Path>,
+    depth: usize,
+    report: &mut NginxImportReport,
+) -> Vec<Directive> {
+    let mut out = Vec::with_capacity(directives.len());
+    for mut d in directives {
+        if d.name != "include" {
+            if let Some(block) = d.block.take() {
+                d.block = Some(expand_includes(block, base_dir, depth, report));
+            }
+            out.push(d);
+            continue;
+        }
+        let Some(pattern) = d.args.first() else {
+            continue;
+        };
+        if depth >= MAX_INCLUDE_DEPTH {
+            report.skip(&d, "include nesting too deep");
+            continue;
+        }
+        let files = crate::config_include::expand_glob(pattern, base_dir);
+        if files.is_empty() {
+            report.skip(&d, "included file not found");
+            continue;
+        }
+        for file in files {
+            let parsed = std::fs::read_to_string(&file)
+                .map_err(anyhow::Error::from)
+                .and_then(|s| parse(&s));
+            match parsed {
+                Ok(inner) => out.extend(expand_includes(
+                    inner,
+                    file.parent().or(base_dir),
+                    depth + 1,
+                    report,
+                )),
+                Err(e) => report.skip(&d, &format!("{}: {e:#}", file.display())),
+            }
+        }
+    }
+    out
+}
+
+struct ImportedServer {
+    listens: Vec<String>,
+    ssl: bool,
+    hosts: Vec<Option<String>>,
+    cert_file: String,
+    key_file: String,
+    routes: Vec<Route>,
+}
+
+/// listen 参数转为本程序的监听地址格式；仅端口时使用 ":port"（双栈）
+fn listen_addr(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    if raw.starts_with("unix:") {
+        return None;
+    }
+    if raw.parse::<u16>().is_ok() {
+        return Some(format!(":{raw}"));
+    }
+    let (host, port) = raw.rsplit_once(':')?;
+    port.parse::<u16>().ok()?;
+    match host {
+        "*" | "[::]" | "0.0.0.0" => Some(format!(":{port}")),
+        "localhost" => Some(format!("127.0.0.1:{port}")),
+        _ => Some(raw.to_string()),
+    }
+}
+
+fn server_hosts(names: &[String]) -> Vec<Option<String>> {
+    let mut hosts = Vec::new();
+    for name in names {
+        let host = match name.as_str() {
+            "" | "_" | "localhost" => None,
+            n if n.starts_with('.') => Some(format!("*{n}")),
+            n => Some(n.to_string()),
+        };
+        if !hosts.contains(&host) {
+            hosts.push(host);
+        }
+    }
+    if hosts.is_empty() {
+        hosts.push(None);
+    }
+    hosts
+}
+
+fn new_route(path: &str) -> Route {
+    Route {
+        id: None,
+        enabled: true,
+        host: None,
+        path: Some(path.to_string()),
+        proxy_pass_path: None,
+        set_headers: None,
+        static_dir: None,
+        exclude_basic_auth: None,
+        basic_auth_enable: None,
+        basic_auth_username: None,
+        basic_auth_password: None,
+        basic_auth_forward_header: None,
+        follow_redirects: false,
+        compression_enabled: None,
+        compression_gzip: None,
+        compression_brotli: None,
+        compression_min_length: None,
+        url_rewrite_rules: None,
+        request_body_replace: None,
+        response_body_replace: None,
+        remove_headers: None,
+        methods: None,
+        headers: None,
+        upstreams: vec![],
+    }
+}
+
+/// proxy_pass 拆成上游列表与 URI 部分；URI 部分对应 proxy_pass_path
+fn proxy_pass_target(
+    raw: &str,
+    upstreams: &HashMap<String, Vec<Upstream>>,
+) -> Result<(Vec<Upstream>, Option<String>), &'static str> {
+    if raw.contains('$') {
+        return Err("proxy_pass with variables is not supported");
+    }
+    let (scheme, rest) = raw
+        .split_once("://")
+        .ok_or("proxy_pass without scheme is not supported")?;
+    if !matches!(scheme, "http" | "https") {
+        return Err("only http/https proxy_pass is supported");
+    }
+    let (authority, uri) = match rest.find('/') {
+        Some(i) => (&rest[..i], Some(rest[i..].to_string())),
+        None => (rest, None),
+    };
+    if authority.is_empty() {
+        return Err("proxy_pass without host");
+    }
+    let servers = match upstreams.get(authority) {
+        Some(servers) => servers
+            .iter()
+            .map(|u| Upstream {
+                url: format!("{scheme}://{}", u.url),
+                weight: u.weight,
+            })
+            .collect(),
+        None => vec![Upstream {
+            url: format!("{scheme}://{authority}"),
+            weight: 1,
+        }],
+    };
+    Ok((servers, uri))
+}
+
+/// 返回不可导入的原因；Connection/Upgrade 由代理自动处理
+fn header_skip_reason(name: &str, value: &str) -> Option<&'static str> {
+    if name.eq_ignore_ascii_case("connection") || name.eq_ignore_ascii_case("upgrade") {
+        return Some("handled automatically for WebSocket upgrades");
+    }
+    let mut rest = value;
+    while let Some(i) = rest.find('$') {
+        let var = &rest[i..];
+        let Some(supported) = SUPPORTED_HEADER_VARS.iter().find(|v| var.starts_with(**v)) else {
+            return Some("header value uses an unsupported variable");
+        };
+        rest = &var[supported.len()..];
+    }
+    None
+}
+
+fn collect_headers(
+    directives: &[Directive],
+    report: &mut NginxImportReport,
+) -> Vec<(String, String)> {
+    let mut headers = Vec::new();
+    for d in directives.iter().filter(|d| d.name == "proxy_set_header") {
+        let [name, value] = d.args.as_slice() else {
+            report.skip(d, "expected a header name and value");
+            continue;
+        };
+        if let Some(reason) = header_skip_reason(name, value) {
+            report.skip(d, reason);
+            continue;
+        }
+        headers.push((name.clone(), value.clone()));
+    }
+    headers
+}
+
+fn convert_location(
+    d: &Directive,
+    inherited_headers: &[(String, String)],
+    upstreams: &HashMap<String, Vec<Upstream>>,
+    report: &mut NginxImportReport,
+) -> Option<Route> {
+    let path = match d.args.as_slice() {
+        [path] if !path.starts_with('@') => path.as_str(),
+        [modifier, path] if modifier == "^~" => path.as_str(),
+        [modifier, path] if modifier == "=" => {
+            report.skip(d, "exact match imported as a prefix route");
+            path.as_str()
+        }
+        _ => {
+            report.skip(d, "regex and named locations are not supported");
+            return None;
+        }
+    };
+    let block = d.block.as_deref().unwrap_or_default();
+    let mut route = new_route(path);
+
+    let own_headers = collect_headers(block, report);
+    let headers = if block.iter().any(|d| d.name == "proxy_set_header") {
+        own_headers
+    } else {
+        inherited_headers.to_vec()
+    };
+
+    for inner in block {
+        match inner.name.as_str() {
+            "proxy_set_header" => {}
+            "proxy_pass" => match proxy_pass_target(
+                inner.args.first().map(String::as_str).unwrap_or(""),
+                upstreams,
+            ) {
+                Ok((servers, uri)) => {
+                    route.upstreams = servers;
+                    route.proxy_pass_path = uri;
+                }
+                Err(reason) => {
+                    report.skip(inner, reason);
+                    report.skip(d, "location has no usable proxy_pass");
+                    return None;
+                }
+            },
+            "root" if inner.args.len() == 1 => {
+                route.static_dir = Some(inner.args[0].clone());
+            }
+            _ => report.skip(inner, "unsupported directive"),
+        }
+    }
+    if route.upstreams.is_empty() && route.static_dir.is_none() {
+        report.skip(d, "location has neither proxy_pass nor root");
+        return None;
+    }
+    if route.upstreams.is_empty() {
+        // 静态目录路由不转发请求头
+        return Some(route);
+    }
+    if !headers.is_empty() {
+        route.set_headers = Some(headers.into_iter().collect());
+    }
+    Some(route)
+}
+
+fn convert_server(
+    d: &Directive,
+    upstreams: &HashMap<String, Vec<Upstream>>,
+    report: &mut NginxImportReport,
+) -> Option<ImportedServer> {
+    let block = d.block.as_deref().unwrap_or_default();
+    let mut server = ImportedServer {
+        listens: Vec::new(),
+        ssl: false,
+        hosts: vec![None],
+        cert_file: String::new(),
+        key_file: String::new(),
+        routes: Vec::new(),
+    };
+    let headers = collect_headers(block, report);
+    let mut server_root = None;
+
+    for inner in block {
+        match inner.name.as_str() {
+            "listen" => {
+                let Some(addr) = inner.args.first().and_then(|a| listen_addr(a)) else {
+                    report.skip(inner, "unsupported listen address");
+                    continue;
+                };
+                if inner.args.iter().skip(1).any(|a| a == "ssl") {
+                    server.ssl = true;
+                }
+                if !server.listens.contains(&addr) {
+                    server.listens.push(addr);
+                }
+            }
+            "server_name" => server.hosts = server_hosts(&inner.args),
+            "ssl" if inner.args.first().is_some_and(|a| a == "on") => server.ssl = true,
+            "ssl_certificate" if inner.args.len() == 1 => server.cert_file = inner.args[0].clone(),
+            "ssl_certificate_key" if inner.args.len() == 1 => {
+                server.key_file = inner.args[0].clone()
+            }
+            "root" if inner.args.len() == 1 => server_root = Some(inner.args[0].clone()),
+            "proxy_set_header" => {}
+            "location" => {
+                if let Some(route) = convert_location(inner, &headers, upstreams, report) {
+                    server.routes.push(route);
+                }
+            }
+            _ => report.skip(inner, "unsupported directive"),
+        }
+    }
+
+    // 没有 location / 时，server 级 root 作为根路由的静态目录
+    if let Some(root) = server_root {
+        if !server.routes.iter().any(|r| r.path.as_deref() == Some("/")) {
+            let mut route = new_route("/");
+            route.static_dir = Some(root);
+            server.routes.push(route);
+        }
+    }
+
+    if server.listens.is_empty() {
+        server.listens.push(":80".to_string());
+    }
+    if server.routes.is_empty() {
+        report.skip(d, "server block has no importable location");
+        return None;
+    }
+    Some(server)
+}
+
+fn collect_upstreams(
+    directives: &[Directive],
+    report: &mut NginxImportReport,
+) -> HashMap<String, Vec<Upstream>> {
+    let mut out = HashMap::new();
+    for d in directives.iter().filter(|d| d.name == "upstream") {
+        let Some(name) = d.args.first() else {
+            continue;
+        };
+        let mut servers = Vec::new();
+        for inner in d.block.as_deref().unwrap_or_default() {
+            let Some(addr) = inner.args.first().filter(|_| inner.name == "server") else {
+                report.skip(inner, "unsupported upstream directive");
+                continue;
+            };
+            if inner.args.iter().any(|a| a == "backup" || a == "down") {
+                report.skip(inner, "backup/down servers are not supported");
+                continue;
+            }
+            let mut weight = 1;
+            for flag in &inner.args[1..] {
+                match flag.strip_prefix("weight=").map(str::parse::<i32>) {
+                    Some(Ok(w)) => weight = w,
+                    _ => report.skip(inner, &format!("upstream server flag '{flag}' ignored")),
+                }
+            }
+            servers.push(Upstream {
+                url: addr.clone(),
+                weight,
+            });
+        }
+        out.insert(name.clone(), servers);
+    }
+    out
+}
+
+fn rule_addrs(rule: &ListenRule) -> Vec<String> {
+    let mut addrs = if rule.listen_addrs.is_empty() {
+        vec![rule.listen_addr.clone()]
+    } else {
+        rule.listen_addrs.clone()
+    };
+    addrs.sort();
+    addrs
+}
+
+/// 把 server 块追加到配置中：监听地址相同的 server 合并为一条监听规则，
+/// 已存在相同监听地址的规则则只追加路由
+fn merge_server(cfg: &mut Config, server: ImportedServer, report: &mut NginxImportReport) {
+    let mut listens = server.listens.clone();
+    listens.sort();
+
+    let idx = match cfg.rules.iter().position(|r| rule_addrs(r) == listens) {
+        Some(idx) => {
+            let rule = &cfg.rules[idx];
+            if server.ssl && rule.ssl_enable && rule.cert_file != server.cert_file {
+                report.skipped.push(format!(
+                    "ssl_certificate {} (listen {} already uses {})",
+                    server.cert_file, rule.listen_addr, rule.cert_file
+                ));
+            }
+            idx
+        }
+        None => {
+            let mut enabled = true;
+            if server.ssl {
+                for file in [&server.cert_file, &server.key_file] {
+                    if file.is_empty() || !Path::new(file).exists() {
+                        enabled = false;
+                        report.skipped.push(format!(
+                            "listen {}: certificate file '{}' not found, rule imported disabled",
+                            server.listens.join(","),
+                            file
+                        ));
+                    }
+                }
+            }
+            cfg.rules.push(ListenRule {
+                id: None,
+                enabled,
+                listen_addr: server.listens[0].clone(),
+                listen_addrs: if server.listens.len() > 1 {
+                    server.listens.clone()
+                } else {
+                    vec![]
+                },
+                ssl_enable: server.ssl,
+                cert_file: server.cert_file.clone(),
+                key_file: server.key_file.clone(),
+                basic_auth_enable: false,
+                basic_auth_username: String::new(),
+                basic_auth_password: String::new(),
+                basic_auth_forward_header: false,
+                routes: vec![],
+                rate_limit_enabled: None,
+                rate_limit_requests_per_second: None,
+                rate_limit_burst_size: None,
+                rate_limit_window_seconds: None,
+                rate_limit_ban_seconds: None,
+                bandwidth_limit_bytes_per_sec: None,
+                client_overrides: None,
+            });
+            report.rules_added += 1;
+            cfg.rules.len() - 1
+        }
+    };
+
+    let rule = &mut cfg.rules[idx];
+    for host in &server.hosts {
+        for route in &server.routes {
+            let exists = rule
+                .routes
+                .iter()
+                .any(|r| r.host == *host && r.path == route.path);
+            let label = format!(
+                "{}{} on {}",
+                host.as_deref().unwrap_or(""),
+                route.path.as_deref().unwrap_or("/"),
+                rule.listen_addr
+            );
+            if exists {
+                report
+                    .skipped
+                    .push(format!("route {label} (already exists)"));
+                continue;
+            }
+            let mut route = route.clone();
+            route.host = host.clone();
+            rule.routes.push(route);
+            report.routes_added += 1;
+            report.imported.push(format!("route {label}"));
+        }
+    }
+}
+
+/// 解析 nginx 配置内容并追加到 cfg；base_dir 用于解析相对路径的 include
+pub fn import_nginx_str(
+    content: &str,
+    base_dir: Option<&Path>,
+    cfg: &mut Config,
+) -> Result<NginxImportReport> {
+    let mut report = NginxImportReport::default();
+    let directives = expand_includes(parse(content)?, base_dir, 0, &mut report);
+
+    // 顶层可以是完整的 nginx.conf，也可以是只包含 server 块的站点文件
+    let mut scope = Vec::new();
+    for d in directives {
+        match d.name.as_str() {
+            "http" => scope.extend(d.block.unwrap_or_default()),
+            "server" | "upstream" => scope.push(d),
+            _ => report.skip(&d, "ignored outside server blocks"),
+        }
+    }
+
+    let upstreams = collect_upstreams(&scope, &mut report);
+    for d in &scope {
+        match d.name.as_str() {
+            "upstream" => {}
+            "server" if d.block.is_some() => {
+                if let Some(server) = convert_server(d, &upstreams, &mut report) {
+                    merge_server(cfg, server, &mut report);
+                }
+            }
+            _ => report.skip(d, "ignored outside server blocks"),
+        }
+    }
+    Ok(report)
+}
+
+pub fn import_nginx_file(path: &Path, cfg: &mut Config) -> Result<NginxImportReport> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read nginx config: {}", path.display()))?;
+    import_nginx_str(&content, path.parent(), cfg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn empty_config() -> Config {
+        toml::from_str("allow_all_lan = true\nwhitelist = []\nrules = []").unwrap()
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/nginx")
+            .join(name)
+    }
+
+    #[test]
+    fn imports_full_nginx_conf() {
+        let mut cfg = empty_config();
+        let report = import_nginx_file(&fixture("full_site.conf"), &mut cfg).unwrap();
+
+        // 80 端口的 server 只有 return，不产生规则
+        assert_eq!(report.rules_added, 1);
+        assert_eq!(report.routes_added, 6);
+        let rule = &cfg.rules[0];
+        assert_eq!(rule.listen_addr, ":443");
+        assert!(rule.listen_addrs.is_empty());
+        assert!(rule.ssl_enable);
+        assert!(!rule.enabled, "missing certificate files disable the rule");
+        assert_eq!(
+            rule.cert_file,
+            "/etc/letsencrypt/live/example.com/fullchain.pem"
+        );
+
+        let root = &rule.routes[0];
+        assert_eq!(root.host.as_deref(), Some("example.com"));
+        let urls: Vec<_> = root
+            .upstreams
+            .iter()
+            .map(|u| (u.url.as_str(), u.weight))
+            .collect();
+        assert_eq!(
+            urls,
+            vec![("http://10.0.0.11:3000", 3), ("http://10.0.0.12:3000", 1),]
+        );
+        assert_eq!(root.set_headers.as_ref().map(|h| h.len()), Some(2));
+
+        let api = &rule.routes[1];
+        assert_eq!(api.upstreams[0].url, "http://127.0.0.1:8081");
+        assert_eq!(api.proxy_pass_path.as_deref(), Some("/v2/"));
+        let api_headers = api.set_headers.as_ref().unwrap();
+        assert_eq!(api_headers.len(), 2);
+        assert_eq!(api_headers["X-Forwarded-Proto"], "$scheme");
+
+        assert_eq!(
+            rule.routes[2].static_dir.as_deref(),
+            Some("/var/www/example")
+        );
+        assert_eq!(rule.routes[3].host.as_deref(), Some("www.example.com"));
+
+        let skipped = report.skipped.join("\n");
+        assert!(skipped.contains("include /etc/nginx/mime.types (included file not found)"));
+        assert!(skipped.contains("return 301"));
+        assert!(skipped.contains("least_conn"));
+        assert!(skipped.contains("server 10.0.0.13:3000 backup (backup/down"));
+        assert!(skipped.contains("proxy_read_timeout 90s"));
+    }
+
+    #[test]
+    fn imports_site_files_and_merges_shared_listen() {
+        let mut cfg = empty_config();
+        let report = import_nginx_file(&fixture("sites_enabled.conf"), &mut cfg).unwrap();
+
+        assert_eq!(report.rules_added, 1);
+        let rule = &cfg.rules[0];
+        assert_eq!(rule.listen_addr, ":8443");
+        assert_eq!(rule.cert_file, "/etc/ssl/internal/grafana.crt");
+
+        let routes: Vec<_> = rule
+            .routes
+            .iter()
+            .map(|r| {
+                (
+                    r.host.as_deref().unwrap_or(""),
+                    r.path.as_deref().unwrap_or(""),
+                )
+            })
+            .collect();
+        assert_eq!(
+            routes,
+            vec![
+                ("grafana.internal", "/"),
+                ("grafana.internal", "/healthz"),
+                ("*.apps.internal", "/docs"),
+            ]
+        );
+        let grafana = &rule.routes[0];
+        assert_eq!(grafana.upstreams[0].url, "https://192.168.1.20:3000");
+        let headers = grafana.set_headers.as_ref().unwrap();
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers["X-Custom"], "value with ; and { braces }");
+        assert!(rule.routes[2].set_headers.is_none());
+
+        let skipped = report.skipped.join("\n");
+        for expected in [
+            "proxy_set_header Upgrade $http_upgrade (handled automatically",
+            "location ~* \\.(png|jpg)$ (regex and named locations",
+            "location @fallback",
+            "location = /healthz (exact match imported as a prefix route)",
+            "include snippets/missing-proxy-params.conf (included file not found)",
+            "proxy_pass http://$upstream_host:8080 (proxy_pass with variables",
+            "proxy_set_header X-Trace $request_id (header value uses an unsupported variable)",
+            "ssl_certificate /etc/ssl/internal/wildcard.crt",
+        ] {
+            assert!(
+                skipped.contains(expected),
+                "missing {expected:?} in:\n{skipped}"
+            );
+        }
+
+        // 再次导入时已存在的路由不重复添加
+        let again = import_nginx_file(&fixture("sites_enabled.conf"), &mut cfg).unwrap();
+        assert_eq!((again.rules_added, again.routes_added), (0, 0));
+    }
+
+    #[test]
+    fn rejects_unbalanced_braces_without_panicking() {
+        let mut cfg = empty_config();
+        let err = import_nginx_file(&fixture("unbalanced.conf"), &mut cfg).unwrap_err();
+        assert!(err.to_string().contains("missing '}'"));
+        assert!(cfg.rules.is_empty());
+
+        for src in [
+            "}",
+            "server {",
+            "listen 80",
+            "{",
+            "proxy_pass 'unterminated",
+        ] {
+            assert!(import_nginx_str(src, None, &mut cfg).is_err(), "{src}");
+        }
+        let report = import_nginx_str("foo bar { baz; }\nhttp { qux 1; }", None, &mut cfg).unwrap();
+        assert_eq!(report.skipped.len(), 2);
+    }
+}
@@ -0,0 +1,285 @@
+use serde::Serialize;
+use std::net::SocketAddr;
+
+/// 占用端口的进程
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PortOwner {
+    pub pid: u32,
+    /// 进程名；权限不足时可能为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl PortOwner {
+    /// 是否为本进程自己（例如代理已在该端口运行）
+    pub fn is_self(&self) -> bool {
+        self.pid == std::process::id()
+    }
+}
+
+impl std::fmt::Display for PortOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{} (pid {})", name, self.pid),
+            None => write!(f, "pid {}", self.pid),
+        }
+    }
+}
+
+/// 查找在该 TCP 端口上监听的进程；平台不支持或没有权限查看时返回 None
+pub fn find_tcp_listener(port: u16) -> Option<PortOwner> {
+    platform::find_tcp_listener(port)
+}
+
+/// 绑定失败的说明；端口被占用时附上占用的进程
+pub fn describe_bind_error(addr: SocketAddr, err: &std::io::Error) -> String {
+    if err.kind() == std::io::ErrorKind::AddrInUse {
+        if let Some(owner) = find_tcp_listener(addr.port()) {
+            return format!("{err}; port {} is used by {owner}", addr.port());
+        }
+    }
+    err.to_string()
+}
+
+/// 从 /proc/net/tcp(6) 中取出处于 LISTEN 状态、本地端口为 port 的 socket inode
+#[cfg(any(target_os = "linux", test))]
+fn listening_inodes(table: &str, port: u16) -> Vec<u64> {
+    const TCP_LISTEN: &str = "0A";
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            let local_port = cols.get(1)?.rsplit(':').next()?;
+            let local_port = u16::from_str_radix(local_port, 16).ok()?;
+            if local_port != port || *cols.get(3)? != TCP_LISTEN {
+                return None;
+            }
+            cols.get(9)?.parse().ok()
+        })
+        .collect()
+}
+
+/// 解析 `lsof -F pc` 的输出：p 开头为 pid，c 开头为进程名
+#[cfg(any(target_os = "macos", test))]
+fn parse_lsof_output(output: &str) -> Option<PortOwner> {
+    let mut pid = None;
+    let mut name = None;
+    for line in output.lines() {
+        if let Some(v) = line.strip_prefix('p') {
+            if pid.is_some() {
+                break;
+            }
+            pid = v.trim().parse().ok();
+        } else if let Some(v) = line.strip_prefix('c') {
+            name = Some(v.trim().to_string());
+        }
+    }
+    pid.map(|pid| PortOwner { pid, name })
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{listening_inodes, PortOwner};
+    use std::fs;
+
+    pub fn find_tcp_listener(port: u16) -> Option<PortOwner> {
+        let inodes: Vec<u64> = ["/proc/net/tcp", "/proc/net/tcp6"]
+            .iter()
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .flat_map(|table| listening_inodes(&table, port))
+            .collect();
+        if inodes.is_empty() {
+            return None;
+        }
+
+        for entry in fs::read_dir("/proc").ok()?.flatten() {
+            let Some(pid) = entry
+                .file_name()
+                .to_str()
+                .and_then(|s| s.parse::<u32>().ok())
+            else {
+                continue;
+            };
+            // 其他用户的进程通常无权读取 fd 目录，跳过
+            let Ok(fds) = fs::read_dir(entry.path().join("fd")) else {
+                continue;
+            };
+            let owns = fds.flatten().any(|fd| {
+                fs::read_link(fd.path()).is_ok_and(|target| {
+                    target
+                        .to_str()
+                        .and_then(|t| t.strip_prefix("socket:["))
+                        .and_then(|t| t.strip_suffix(']'))
+                        .and_then(|t| t.parse::<u64>().ok())
+                        .is_some_and(|inode| inodes.contains(&inode))
+                })
+            });
+            if owns {
+                let name = fs::read_to_string(entry.path().join("comm"))
+                    .ok()
+                    .map(|s| s.trim().to_string());
+                return Some(PortOwner { pid, name });
+            }
+        }
+        None
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::{parse_lsof_output, PortOwner};
+
+    pub fn find_tcp_listener(port: u16) -> Option<PortOwner> {
+        let output = std::process::Command::new("lsof")
+            .args(["-nP", &format!("-iTCP:{port}"), "-sTCP:LISTEN", "-Fpc"])
+            .output()
+            .ok()?;
+        parse_lsof_output(&String::from_utf8_lossy(&output.stdout))
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::PortOwner;
+    use windows_sys::Win32::Foundation::{CloseHandle, ERROR_INSUFFICIENT_BUFFER, NO_ERROR};
+    use windows_sys::Win32::NetworkManagement::IpHelper::{
+        GetExtendedTcpTable, MIB_TCP6TABLE_OWNER_PID, MIB_TCPTABLE_OWNER_PID,
+        TCP_TABLE_OWNER_PID_LISTENER,
+    };
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    const AF_INET: u32 = 2;
+    const AF_INET6: u32 = 23;
+
+    /// 按 u32 对齐分配缓冲区，表结构首字段为 u32
+    fn listener_table(af: u32) -> Option<Vec<u32>> {
+        let mut size: u32 = 0;
+        let status = unsafe {
+            GetExtendedTcpTable(
+                std::ptr::null_mut(),
+                &mut size,
+                0,
+                af,
+                TCP_TABLE_OWNER_PID_LISTENER,
+                0,
+            )
+        };
+        if status != ERROR_INSUFFICIENT_BUFFER && status != NO_ERROR {
+            return None;
+        }
+        let mut buf = vec![0u32; (size as usize).div_ceil(4)];
+        let status = unsafe {
+            GetExtendedTcpTable(
+                buf.as_mut_ptr().cast(),
+                &mut size,
+                0,
+                af,
+                TCP_TABLE_OWNER_PID_LISTENER,
+                0,
+            )
+        };
+        (status == NO_ERROR).then_some(buf)
+    }
+
+    /// dwLocalPort 的低 16 位为网络字节序
+    fn local_port(raw: u32) -> u16 {
+        u16::from_be(raw as u16)
+    }
+
+    fn listener_pid(port: u16) -> Option<u32> {
+        if let Some(buf) = listener_table(AF_INET) {
+            let table = unsafe { &*(buf.as_ptr() as *const MIB_TCPTABLE_OWNER_PID) };
+            let rows = unsafe {
+                std::slice::from_raw_parts(table.table.as_ptr(), table.dwNumEntries as usize)
+            };
+            if let Some(row) = rows.iter().find(|r| local_port(r.dwLocalPort) == port) {
+                return Some(row.dwOwningPid);
+            }
+        }
+        let buf = listener_table(AF_INET6)?;
+        let table = unsafe { &*(buf.as_ptr() as *const MIB_TCP6TABLE_OWNER_PID) };
+        let rows = unsafe {
+            std::slice::from_raw_parts(table.table.as_ptr(), table.dwNumEntries as usize)
+        };
+        rows.iter()
+            .find(|r| local_port(r.dwLocalPort) == port)
+            .map(|r| r.dwOwningPid)
+    }
+
+    fn process_name(pid: u32) -> Option<String> {
+        let handle = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid) };
+        if handle.is_null() {
+            return None;
+        }
+        let mut buf = [0u16; 1024];
+        let mut len = buf.len() as u32;
+        let ok = unsafe { QueryFullProcessImageNameW(handle, 0, buf.as_mut_ptr(), &mut len) };
+        unsafe { CloseHandle(handle) };
+        if ok == 0 {
+            return None;
+        }
+        let path = String::from_utf16_lossy(&buf[..len as usize]);
+        path.rsplit('\\').next().map(str::to_string)
+    }
+
+    pub fn find_tcp_listener(port: u16) -> Option<PortOwner> {
+        let pid = listener_pid(port)?;
+        Some(PortOwner {
+            pid,
+            name: process_name(pid),
+        })
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+mod platform {
+    use super::PortOwner;
+
+    pub fn find_tcp_listener(_port: u16) -> Option<PortOwner> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listening_inodes_match_port_and_listen_state() {
+        let table = "\
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 4242 1 0000000000000000 100 0 0 10 0
+   1: 0100007F:1F90 0100007F:D431 01 00000000:00000000 00:00000000 00000000  1000        0 4343 1 0000000000000000 20 4 30 10 -1
+   2: 00000000:0050 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 4444 1 0000000000000000 100 0 0 10 0
+";
+        assert_eq!(listening_inodes(table, 8080), vec![4242]);
+        assert_eq!(listening_inodes(table, 80), vec![4444]);
+        assert!(listening_inodes(table, 443).is_empty());
+    }
+
+    #[test]
+    fn lsof_output_yields_first_process() {
+        let owner = parse_lsof_output("p812\ncnginx\np813\ncnginx\n").unwrap();
+        assert_eq!(
+            owner,
+            PortOwner {
+                pid: 812,
+                name: Some("nginx".into())
+            }
+        );
+        assert_eq!(owner.to_string(), "nginx (pid 812)");
+        assert!(parse_lsof_output("").is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn finds_this_process_listening() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let owner = find_tcp_listener(port).unwrap();
+        assert!(owner.is_self());
+    }
+}
@@ -0,0 +1,550 @@
+use anyhow::{anyhow, Context, Result};
+use axum::http::StatusCode;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::sync::Arc;
+
+use super::context::RequestContext;
+use super::logging::LOG_DROPPED;
+
+/// 与 nginx 默认 combined 完全一致，GoAccess / awstats 可直接解析
+pub const COMBINED_FORMAT: &str = r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent""#;
+const TIMING_SUFFIX: &str = " $request_time $upstream_response_time $upstream_addr";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Var {
+    RemoteAddr,
+    RemoteUser,
+    TimeLocal,
+    TimeIso8601,
+    Request,
+    RequestMethod,
+    RequestUri,
+    Uri,
+    Status,
+    BodyBytesSent,
+    HttpReferer,
+    HttpUserAgent,
+    HttpXForwardedFor,
+    Host,
+    RequestTime,
+    UpstreamResponseTime,
+    UpstreamAddr,
+    ListenAddr,
+}
+
+const VARS: &[(&str, Var)] = &[
+    ("remote_addr", Var::RemoteAddr),
+    ("remote_user", Var::RemoteUser),
+    ("time_local", Var::TimeLocal),
+    ("time_iso8601", Var::TimeIso8601),
+    ("request", Var::Request),
+    ("request_method", Var::RequestMethod),
+    ("request_uri", Var::RequestUri),
+    ("uri", Var::Uri),
+    ("status", Var::Status),
+    ("body_bytes_sent", Var::BodyBytesSent),
+    ("bytes_sent", Var::BodyBytesSent),
+    ("http_referer", Var::HttpReferer),
+    ("http_user_agent", Var::HttpUserAgent),
+    ("http_x_forwarded_for", Var::HttpXForwardedFor),
+    ("host", Var::Host),
+    ("request_time", Var::RequestTime),
+    ("upstream_response_time", Var::UpstreamResponseTime),
+    ("upstream_addr", Var::UpstreamAddr),
+    ("listen_addr", Var::ListenAddr),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Var(Var),
+}
+
+/// 解析后的访问日志模板，变量写法同 nginx：`$name` 或 `${name}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessLogFormat {
+    segments: Vec<Segment>,
+}
+
+impl AccessLogFormat {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+        while let Some(pos) = rest.find('$') {
+            literal.push_str(&rest[..pos]);
+            let after = &rest[pos + 1..];
+            let (name, consumed) = if let Some(braced) = after.strip_prefix('{') {
+                let end = braced
+                    .find('}')
+                    .ok_or_else(|| anyhow!("unclosed '${{' in access log format"))?;
+                (&braced[..end], end + 2)
+            } else {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            };
+            if name.is_empty() {
+                literal.push('$');
+            } else {
+                let var = VARS
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, v)| *v)
+                    .ok_or_else(|| anyhow!("unknown access log variable: ${}", name))?;
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Var(var));
+            }
+            rest = &after[consumed..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Self { segments })
+    }
+
+    /// access_log_format 配置值："default"（或留空）保持内置格式，
+    /// "combined" / "combined_timing" 为预设，其余按模板解析
+    pub fn from_config(value: Option<&str>) -> Result<Option<Self>> {
+        match value.map(str::trim).filter(|v| !v.is_empty()) {
+            None | Some("default") => Ok(None),
+            Some("combined") => Self::parse(COMBINED_FORMAT).map(Some),
+            Some("combined_timing") => {
+                Self::parse(&format!("{COMBINED_FORMAT}{TIMING_SUFFIX}")).map(Some)
+            }
+            Some(template) => Self::parse(template).map(Some),
+        }
+    }
+}
+
+const ACCESS_LOG_FILE_QUEUE: usize = 10_000;
+
+#[derive(Default)]
+struct AccessLogSettings {
+    format: Option<Arc<AccessLogFormat>>,
+    /// 访问日志文件使用 JSON 行
+    json: bool,
+    /// 实时日志（log-line 事件与内存缓冲）也使用 JSON 行
+    json_events: bool,
+    file: Option<AccessLogFile>,
+}
+
+static ACCESS_LOG: Lazy<RwLock<AccessLogSettings>> =
+    Lazy::new(|| RwLock::new(AccessLogSettings::default()));
+
+/// 追加写入访问日志文件的后台线程；发送端被替换或丢弃后线程退出
+struct AccessLogFile {
+    path: String,
+    tx: std::sync::mpsc::SyncSender<String>,
+}
+
+impl AccessLogFile {
+    fn open(path: &str) -> Result<Self> {
+        use std::io::Write;
+
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open access log file: {path}"))?;
+        let (tx, rx) = std::sync::mpsc::sync_channel::<String>(ACCESS_LOG_FILE_QUEUE);
+        std::thread::Builder::new()
+            .name("access-log-writer".to_string())
+            .spawn(move || {
+                let mut out = std::io::BufWriter::new(file);
+                while let Ok(line) = rx.recv() {
+                    let _ = writeln!(out, "{line}");
+                    while let Ok(line) = rx.try_recv() {
+                        let _ = writeln!(out, "{line}");
+                    }
+                    let _ = out.flush();
+                }
+            })
+            .context("failed to spawn access log writer")?;
+        Ok(Self {
+            path: path.to_string(),
+            tx,
+        })
+    }
+}
+
+/// 启动及保存配置后调用；格式无效时保持原有格式，文件无法打开时停用文件输出
+pub fn apply_access_log_config(cfg: &crate::config::Config) -> Result<()> {
+    let format = AccessLogFormat::from_config(cfg.access_log_format.as_deref());
+    let path = cfg
+        .access_log_file
+        .as_deref()
+        .map(str::trim)
+        .filter(|p| !p.is_empty());
+    let unchanged =
+        path.is_some() && ACCESS_LOG.read().file.as_ref().map(|f| f.path.as_str()) == path;
+    let file = match path {
+        Some(_) if unchanged => None,
+        Some(p) => Some(AccessLogFile::open(p)),
+        None => None,
+    };
+
+    let mut settings = ACCESS_LOG.write();
+    settings.json = cfg.access_log_json;
+    settings.json_events = cfg.access_log_json && cfg.access_log_json_events;
+    if let Ok(format) = &format {
+        settings.format = format.clone().map(Arc::new);
+    }
+    let opened = match file {
+        Some(Ok(file)) => {
+            settings.file = Some(file);
+            Ok(())
+        }
+        Some(Err(e)) => {
+            settings.file = None;
+            Err(e)
+        }
+        None if unchanged => Ok(()),
+        None => {
+            settings.file = None;
+            Ok(())
+        }
+    };
+    drop(settings);
+
+    format.map(|_| ()).and(opened)
+}
+
+/// 请求阶段采集的访问日志字段，响应结束后再结合字节数与耗时输出
+pub(crate) struct AccessLogEntry {
+    node: String,
+    remote_addr: String,
+    time: chrono::DateTime<chrono::Local>,
+    method: String,
+    request_uri: String,
+    path: Arc<str>,
+    status: u16,
+    referer: Arc<str>,
+    user_agent: Arc<str>,
+    forwarded_for: Arc<str>,
+    host: Arc<str>,
+    request_id: Option<Arc<str>>,
+}
+
+impl AccessLogEntry {
+    pub fn capture(node: &str, ctx: &RequestContext, status: StatusCode) -> Self {
+        let ip: &str = if !ctx.client_ip.is_empty() {
+            &ctx.client_ip
+        } else if !ctx.client_ip_header.is_empty() && ctx.client_ip_header.as_ref() != "-" {
+            ctx.client_ip_header.split(',').next().unwrap_or("-").trim()
+        } else if !ctx.real_ip_header.is_empty() && ctx.real_ip_header.as_ref() != "-" {
+            &ctx.real_ip_header
+        } else {
+            "-"
+        };
+        Self {
+            node: node.to_string(),
+            remote_addr: ip.to_string(),
+            time: chrono::Local::now(),
+            method: ctx.method.as_str().to_string(),
+            request_uri: ctx.uri.to_string(),
+            path: ctx.path.clone(),
+            status: status.as_u16(),
+            referer: ctx.referer_header.clone(),
+            user_agent: ctx.user_agent_header.clone(),
+            forwarded_for: ctx.client_ip_header.clone(),
+            host: ctx.host_header.clone(),
+            request_id: ctx.request_id_header.clone(),
+        }
+    }
+
+    /// 写入访问日志文件（如已配置），并返回实时日志中显示的一行。
+    /// bytes_sent 未知时输出 "-"；upstream 为完整上游 URL，未经过上游时为 None
+    pub fn log_line(
+        &self,
+        bytes_sent: Option<u64>,
+        request_time_s: f64,
+        upstream_s: Option<f64>,
+        upstream: Option<&str>,
+    ) -> String {
+        let settings = ACCESS_LOG.read();
+        let text = || match settings.format.as_deref() {
+            Some(format) => {
+                self.render_template(format, bytes_sent, request_time_s, upstream_s, upstream)
+            }
+            None => self.render_default(bytes_sent, request_time_s, upstream_s),
+        };
+        let json = settings
+            .json
+            .then(|| self.render_json(bytes_sent, request_time_s, upstream));
+
+        if let Some(file) = settings.file.as_ref() {
+            let line = json.clone().unwrap_or_else(text);
+            if file.tx.try_send(line).is_err() {
+                LOG_DROPPED.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            }
+        }
+        match json {
+            Some(json) if settings.json_events => json,
+            _ => text(),
+        }
+    }
+
+    /// 字段固定，缺失值输出 null，便于下游按固定 schema 解析
+    fn render_json(
+        &self,
+        bytes_sent: Option<u64>,
+        request_time_s: f64,
+        upstream: Option<&str>,
+    ) -> String {
+        #[derive(Serialize)]
+        struct Record<'a> {
+            ts: String,
+            node: &'a str,
+            client_ip: Option<&'a str>,
+            method: &'a str,
+            path: &'a str,
+            host: Option<&'a str>,
+            status: u16,
+            latency_ms: f64,
+            upstream: Option<&'a str>,
+            ua: Option<&'a str>,
+            referer: Option<&'a str>,
+            request_id: Option<&'a str>,
+            bytes_sent: Option<u64>,
+        }
+
+        fn present(s: &str) -> Option<&str> {
+            (!s.is_empty() && s != "-").then_some(s)
+        }
+
+        serde_json::to_string(&Record {
+            ts: self
+                .time
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+            node: &self.node,
+            client_ip: present(&self.remote_addr),
+            method: &self.method,
+            path: &self.path,
+            host: present(&self.host),
+            status: self.status,
+            latency_ms: (request_time_s * 1_000_000.0).round() / 1000.0,
+            upstream: upstream.and_then(present),
+            ua: present(&self.user_agent),
+            referer: present(&self.referer),
+            request_id: self.request_id.as_deref(),
+            bytes_sent,
+        })
+        .unwrap_or_default()
+    }
+
+    fn render_default(
+        &self,
+        bytes_sent: Option<u64>,
+        request_time_s: f64,
+        upstream_s: Option<f64>,
+    ) -> String {
+        let sent = match bytes_sent {
+            Some(n) => n.to_string(),
+            None => "-".to_string(),
+        };
+        let upstream = match upstream_s {
+            Some(u) => format!("{:.3}", u),
+            None => "-".to_string(),
+        };
+        format!(
+            "[NODE {}] [-] {} - - [{}] \"{} {} HTTP/1.1\" {} {} \"{}\" \"{}\" {:.3}s {}",
+            self.node,
+            self.remote_addr,
+            self.time.format("%y.%m.%d %H:%M:%S"),
+            self.method,
+            self.request_uri,
+            self.status,
+            sent,
+            self.referer,
+            self.user_agent,
+            request_time_s,
+            upstream
+        )
+    }
+
+    fn render_template(
+        &self,
+        format: &AccessLogFormat,
+        bytes_sent: Option<u64>,
+        request_time_s: f64,
+        upstream_s: Option<f64>,
+        upstream: Option<&str>,
+    ) -> String {
+        use std::fmt::Write;
+
+        let mut out = String::with_capacity(256);
+        for seg in &format.segments {
+            let var = match seg {
+                Segment::Literal(s) => {
+                    out.push_str(s);
+                    continue;
+                }
+                Segment::Var(v) => *v,
+            };
+            let _ = match var {
+                Var::RemoteAddr => write!(out, "{}", self.remote_addr),
+                Var::RemoteUser => write!(out, "-"),
+                Var::TimeLocal => write!(out, "{}", self.time.format("%d/%b/%Y:%H:%M:%S %z")),
+                Var::TimeIso8601 => write!(out, "{}", self.time.format("%Y-%m-%dT%H:%M:%S%:z")),
+                Var::Request => {
+                    push_escaped(
+                        &mut out,
+                        &format!("{} {} HTTP/1.1", self.method, self.request_uri),
+                    );
+                    Ok(())
+                }
+                Var::RequestMethod => write!(out, "{}", self.method),
+                Var::RequestUri => {
+                    push_escaped(&mut out, &self.request_uri);
+                    Ok(())
+                }
+                Var::Uri => {
+                    push_escaped(&mut out, &self.path);
+                    Ok(())
+                }
+                Var::Status => write!(out, "{}", self.status),
+                Var::BodyBytesSent => match bytes_sent {
+                    Some(n) => write!(out, "{}", n),
+                    None => write!(out, "-"),
+                },
+                Var::HttpReferer => {
+                    push_escaped(&mut out, &self.referer);
+                    Ok(())
+                }
+                Var::HttpUserAgent => {
+                    push_escaped(&mut out, &self.user_agent);
+                    Ok(())
+                }
+                Var::HttpXForwardedFor => {
+                    push_escaped(&mut out, &self.forwarded_for);
+                    Ok(())
+                }
+                Var::Host => {
+                    push_escaped(&mut out, or_dash(&self.host));
+                    Ok(())
+                }
+                Var::RequestTime => write!(out, "{:.3}", request_time_s),
+                Var::UpstreamResponseTime => match upstream_s {
+                    Some(u) => write!(out, "{:.3}", u),
+                    None => write!(out, "-"),
+                },
+                Var::UpstreamAddr => write!(out, "{}", upstream.map_or("-", upstream_addr)),
+                Var::ListenAddr => write!(out, "{}", self.node),
+            };
+        }
+        out
+    }
+}
+
+#[inline]
+fn or_dash(s: &str) -> &str {
+    if s.is_empty() {
+        "-"
+    } else {
+        s
+    }
+}
+
+/// 与 nginx escape=default 相同：双引号、反斜杠及不可打印字节输出为 \xHH
+fn push_escaped(out: &mut String, s: &str) {
+    use std::fmt::Write;
+
+    for b in s.bytes() {
+        if b == b'"' || b == b'\\' || !(0x20..0x7f).contains(&b) {
+            let _ = write!(out, "\\x{:02X}", b);
+        } else {
+            out.push(b as char);
+        }
+    }
+}
+
+/// "http://10.0.0.2:8080/api" -> "10.0.0.2:8080"
+fn upstream_addr(upstream: &str) -> &str {
+    let s = upstream
+        .split_once("://")
+        .map_or(upstream, |(_, rest)| rest);
+    let end = s.find(['/', '?']).unwrap_or(s.len());
+    or_dash(&s[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entry() -> AccessLogEntry {
+        AccessLogEntry {
+            node: "0.0.0.0:443".to_string(),
+            remote_addr: "203.0.113.7".to_string(),
+            time: chrono::Local
+                .with_ymd_and_hms(2024, 3, 9, 8, 5, 1)
+                .single()
+                .unwrap(),
+            method: "GET".to_string(),
+            request_uri: "/a?b=1".to_string(),
+            path: Arc::from("/a"),
+            status: 200,
+            referer: Arc::from("-"),
+            user_agent: Arc::from("curl/8.0 \"x\""),
+            forwarded_for: Arc::from("-"),
+            host: Arc::from("example.com"),
+            request_id: None,
+        }
+    }
+
+    #[test]
+    fn combined_matches_nginx_layout() {
+        let e = entry();
+        let tz = e.time.format("%z").to_string();
+        let format = AccessLogFormat::from_config(Some("combined_timing"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            e.render_template(
+                &format,
+                Some(612),
+                0.0456,
+                Some(0.03),
+                Some("http://10.0.0.2:8080/api")
+            ),
+            format!(
+                "203.0.113.7 - - [09/Mar/2024:08:05:01 {tz}] \"GET /a?b=1 HTTP/1.1\" 200 612 \"-\" \"curl/8.0 \\x22x\\x22\" 0.046 0.030 10.0.0.2:8080"
+            )
+        );
+
+        let format = AccessLogFormat::parse("${status}$$ ${uri}-$host").unwrap();
+        assert_eq!(
+            e.render_template(&format, None, 0.0, None, None),
+            "200$$ /a-example.com"
+        );
+        assert_eq!(
+            AccessLogFormat::from_config(Some(" default ")).unwrap(),
+            None
+        );
+        assert!(AccessLogFormat::parse("$remote_addr $nope").is_err());
+        assert!(AccessLogFormat::parse("${status").is_err());
+    }
+
+    #[test]
+    fn json_record_keeps_every_field() {
+        let e = entry();
+        let v: serde_json::Value =
+            serde_json::from_str(&e.render_json(None, 0.0123456, None)).unwrap();
+        let obj = v.as_object().unwrap();
+        assert_eq!(obj.len(), 13);
+        assert_eq!(v["client_ip"], "203.0.113.7");
+        assert_eq!(v["path"], "/a");
+        assert_eq!(v["latency_ms"], 12.346);
+        assert_eq!(v["ua"], "curl/8.0 \"x\"");
+        for key in ["referer", "upstream", "request_id", "bytes_sent"] {
+            assert!(obj[key].is_null(), "{key}");
+        }
+    }
+}
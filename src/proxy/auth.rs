@@ -82,6 +82,8 @@ mod tests {
             rate_limit_burst_size: None,
             rate_limit_window_seconds: None,
             rate_limit_ban_seconds: None,
+            bandwidth_limit_bytes_per_sec: None,
+            client_overrides: None,
         }
     }
 
@@ -0,0 +1,244 @@
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// 登记条目的上限，超出后新连接照常处理但不再登记
+const MAX_ACTIVE: usize = 10_000;
+
+static ACTIVE: Lazy<DashMap<u64, ActiveEntry>> = Lazy::new(DashMap::new);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionKind {
+    Http,
+    Ws,
+    Stream,
+}
+
+/// 进行中的 HTTP 请求、WS 会话或 stream 会话
+#[derive(Debug, Clone)]
+pub struct ActiveEntry {
+    kind: ConnectionKind,
+    listen_addr: Arc<str>,
+    client_ip: String,
+    method: Option<String>,
+    path: Option<String>,
+    upstream: Option<String>,
+    udp: bool,
+    started: Instant,
+}
+
+impl ActiveEntry {
+    fn new(kind: ConnectionKind, listen_addr: &str, client_ip: String) -> Self {
+        Self {
+            kind,
+            listen_addr: Arc::from(listen_addr),
+            client_ip,
+            method: None,
+            path: None,
+            upstream: None,
+            udp: false,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn http(listen_addr: &str, client_ip: String, method: &str, path: &str) -> Self {
+        Self {
+            method: Some(method.to_string()),
+            path: Some(path.to_string()),
+            ..Self::new(ConnectionKind::Http, listen_addr, client_ip)
+        }
+    }
+
+    pub fn ws(listen_addr: &str, client_ip: &str, path: &str, upstream: &str) -> Self {
+        Self {
+            path: Some(path.to_string()),
+            upstream: Some(upstream.to_string()),
+            ..Self::new(ConnectionKind::Ws, listen_addr, client_ip.to_string())
+        }
+    }
+
+    pub fn stream(listen_addr: &str, client: SocketAddr, upstream: &str, udp: bool) -> Self {
+        Self {
+            upstream: Some(upstream.to_string()),
+            udp,
+            ..Self::new(ConnectionKind::Stream, listen_addr, client.ip().to_string())
+        }
+    }
+}
+
+/// 登记 id，放入 HTTP 请求的 extensions 供后续阶段补充信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActiveId(u64);
+
+/// 连接结束（含客户端断开、任务被中止）时随 drop 移除登记
+#[derive(Debug)]
+pub struct ActiveGuard(Option<u64>);
+
+impl ActiveGuard {
+    /// 达到上限未登记时为 None
+    pub fn id(&self) -> Option<ActiveId> {
+        self.0.map(ActiveId)
+    }
+}
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        if let Some(id) = self.0 {
+            ACTIVE.remove(&id);
+        }
+    }
+}
+
+pub fn track(entry: ActiveEntry) -> ActiveGuard {
+    if ACTIVE.len() >= MAX_ACTIVE {
+        return ActiveGuard(None);
+    }
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    ACTIVE.insert(id, entry);
+    ActiveGuard(Some(id))
+}
+
+/// 按请求头解析出真实客户端 IP 后更新
+pub fn set_client_ip(id: ActiveId, client_ip: &str) {
+    if let Some(mut entry) = ACTIVE.get_mut(&id.0) {
+        entry.client_ip = client_ip.to_string();
+    }
+}
+
+/// 选定上游后更新
+pub fn set_upstream(id: ActiveId, upstream: &str) {
+    if let Some(mut entry) = ACTIVE.get_mut(&id.0) {
+        entry.upstream = Some(upstream.to_string());
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveConnection {
+    pub id: u64,
+    pub kind: ConnectionKind,
+    pub client_ip: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>,
+    pub udp: bool,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ListenerConnections {
+    pub listen_addr: String,
+    pub connections: Vec<ActiveConnection>,
+}
+
+/// 当前登记的连接，按监听地址分组，组内按开始时间排序
+pub fn active_connections() -> Vec<ListenerConnections> {
+    let mut grouped: BTreeMap<Arc<str>, Vec<ActiveConnection>> = BTreeMap::new();
+    for item in ACTIVE.iter() {
+        let entry = item.value();
+        grouped
+            .entry(entry.listen_addr.clone())
+            .or_default()
+            .push(ActiveConnection {
+                id: *item.key(),
+                kind: entry.kind,
+                client_ip: entry.client_ip.clone(),
+                method: entry.method.clone(),
+                path: entry.path.clone(),
+                upstream: entry.upstream.clone(),
+                udp: entry.udp,
+                elapsed_ms: entry.started.elapsed().as_millis() as u64,
+            });
+    }
+    grouped
+        .into_iter()
+        .map(|(listen_addr, mut connections)| {
+            connections.sort_by_key(|c| c.id);
+            ListenerConnections {
+                listen_addr: listen_addr.to_string(),
+                connections,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listed(listen_addr: &str) -> Vec<ActiveConnection> {
+        active_connections()
+            .into_iter()
+            .find(|g| g.listen_addr == listen_addr)
+            .map(|g| g.connections)
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn entries_are_grouped_by_listener_and_removed_on_drop() {
+        let http = track(ActiveEntry::http(
+            "127.0.0.1:39001",
+            "10.0.0.1".into(),
+            "GET",
+            "/api",
+        ));
+        let ws = track(ActiveEntry::ws(
+            "127.0.0.1:39001",
+            "10.0.0.2",
+            "/ws",
+            "ws://127.0.0.1:13010",
+        ));
+        let udp = track(ActiveEntry::stream(
+            "127.0.0.1:39002",
+            "10.0.0.3:5353".parse().unwrap(),
+            "127.0.0.1:15353",
+            true,
+        ));
+        set_upstream(http.id().unwrap(), "http://127.0.0.1:13001");
+        set_client_ip(http.id().unwrap(), "203.0.113.7");
+
+        let first = listed("127.0.0.1:39001");
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[0].kind, ConnectionKind::Http);
+        assert_eq!(first[0].client_ip, "203.0.113.7");
+        assert_eq!(first[0].upstream.as_deref(), Some("http://127.0.0.1:13001"));
+        assert_eq!(first[1].kind, ConnectionKind::Ws);
+        let second = listed("127.0.0.1:39002");
+        assert_eq!(second.len(), 1);
+        assert!(second[0].udp);
+        assert_eq!(second[0].client_ip, "10.0.0.3");
+
+        drop(http);
+        assert_eq!(listed("127.0.0.1:39001").len(), 1);
+        drop((ws, udp));
+        assert!(listed("127.0.0.1:39001").is_empty());
+        assert!(listed("127.0.0.1:39002").is_empty());
+    }
+
+    #[test]
+    fn serializes_kind_lowercase_and_skips_missing_fields() {
+        let conn = ActiveConnection {
+            id: 1,
+            kind: ConnectionKind::Stream,
+            client_ip: "10.0.0.3".into(),
+            method: None,
+            path: None,
+            upstream: Some("127.0.0.1:15353".into()),
+            udp: false,
+            elapsed_ms: 5,
+        };
+        let v = serde_json::to_value(&conn).unwrap();
+        assert_eq!(v["kind"], "stream");
+        assert!(v.get("method").is_none());
+        assert_eq!(v["upstream"], "127.0.0.1:15353");
+    }
+}
@@ -2,6 +2,8 @@ use axum::http::{HeaderMap, Method, StatusCode, Uri};
 use std::net::SocketAddr;
 use std::sync::Arc;
 
+use super::access_log::AccessLogEntry;
+use super::logging::{push_log, LogLevel};
 use crate::{access_control, metrics};
 
 pub(crate) struct RequestContext {
@@ -12,6 +14,7 @@ pub(crate) struct RequestContext {
     pub host_header: Arc<str>,
     pub referer_header: Arc<str>,
     pub user_agent_header: Arc<str>,
+    pub request_id_header: Option<Arc<str>>,
     pub method: Method,
     pub uri: Uri,
     pub path: Arc<str>,
@@ -43,6 +46,11 @@ impl RequestContext {
 
         let referer = header_to_arc_str(headers, "referer");
         let ua = header_to_arc_str(headers, "user-agent");
+        let request_id = headers
+            .get("x-request-id")
+            .and_then(|v| v.to_str().ok())
+            .filter(|s| !s.is_empty())
+            .map(Arc::from);
 
         Self {
             client_ip: Arc::from(access_control::client_ip_from_headers(&remote, headers)),
@@ -52,6 +60,7 @@ impl RequestContext {
             host_header: Arc::from(host),
             referer_header: referer,
             user_agent_header: ua,
+            request_id_header: request_id,
             method: method.clone(),
             uri: uri.clone(),
             path: Arc::from(path),
@@ -70,45 +79,46 @@ impl RequestContext {
 }
 
 #[inline]
-pub fn time_local_string() -> String {
-    let now = chrono::Local::now();
-    now.format("%y.%m.%d %H:%M:%S").to_string()
+pub fn format_access_log(node: &str, ctx: &RequestContext, status: StatusCode) -> String {
+    format_access_log_sent(node, ctx, status, None)
 }
 
-#[inline]
-pub fn request_line(method: &Method, uri: &Uri) -> String {
-    format!("{} {} HTTP/1.1", method.as_str(), uri)
+/// bytes_sent 对应 nginx 的 $body_bytes_sent，未知时输出 "-"；配置了访问日志文件时同时写入文件
+pub fn format_access_log_sent(
+    node: &str,
+    ctx: &RequestContext,
+    status: StatusCode,
+    bytes_sent: Option<u64>,
+) -> String {
+    AccessLogEntry::capture(node, ctx, status).log_line(bytes_sent, ctx.elapsed_s(), None, None)
 }
 
 #[inline]
-pub fn format_access_log(node: &str, ctx: &RequestContext, status: StatusCode) -> String {
-    let ip: &str = if !ctx.client_ip.is_empty() {
-        &ctx.client_ip
-    } else if !ctx.client_ip_header.is_empty() && ctx.client_ip_header.as_ref() != "-" {
-        ctx.client_ip_header.split(',').next().unwrap_or("-").trim()
-    } else if !ctx.real_ip_header.is_empty() && ctx.real_ip_header.as_ref() != "-" {
-        &ctx.real_ip_header
-    } else {
-        "-"
-    };
-    let time_local = time_local_string();
-    let req_line = request_line(&ctx.method, &ctx.uri);
-
-    format!(
-        "[NODE {}] [-] {} - - [{}] \"{}\" {} - \"{}\" \"{}\" {:.3}s",
+pub fn enqueue_request_log(
+    node: &str,
+    ctx: &RequestContext,
+    remote: &SocketAddr,
+    status: StatusCode,
+    upstream: &str,
+    matched_route_id: &str,
+    guard_ms: f64,
+    prepare_ms: f64,
+    upstream_ms: f64,
+) {
+    metrics::try_enqueue_request_log(request_log_record(
         node,
-        ip,
-        time_local,
-        req_line,
-        status.as_u16(),
-        ctx.referer_header,
-        ctx.user_agent_header,
-        ctx.elapsed_s()
-    )
+        ctx,
+        remote,
+        status,
+        upstream,
+        matched_route_id,
+        guard_ms,
+        prepare_ms,
+        upstream_ms,
+    ));
 }
 
-#[inline]
-pub fn enqueue_request_log(
+pub fn request_log_record(
     node: &str,
     ctx: &RequestContext,
     remote: &SocketAddr,
@@ -118,8 +128,8 @@ pub fn enqueue_request_log(
     guard_ms: f64,
     prepare_ms: f64,
     upstream_ms: f64,
-) {
-    metrics::try_enqueue_request_log(metrics::RequestLogInsert {
+) -> metrics::RequestLogInsert {
+    metrics::RequestLogInsert {
         timestamp: chrono::Utc::now().timestamp(),
         listen_addr: node.to_string(),
         client_ip: ctx.client_ip.as_ref().to_string(),
@@ -136,7 +146,54 @@ pub fn enqueue_request_log(
         user_agent: ctx.user_agent_header.as_ref().to_string(),
         referer: ctx.referer_header.as_ref().to_string(),
         matched_route_id: matched_route_id.to_string(),
-    });
+        bytes_sent: 0,
+        bytes_received: 0,
+        country: String::new(),
+        asn: String::new(),
+        sample_rate: 1.0,
+    }
+}
+
+/// 响应体发送结束后才写入的访问日志与请求记录，此时才知道实际发送的字节数
+pub(crate) struct PendingRequestLog {
+    access: AccessLogEntry,
+    started_at: std::time::Instant,
+    record: metrics::RequestLogInsert,
+}
+
+impl PendingRequestLog {
+    pub fn new(
+        node: &str,
+        ctx: &RequestContext,
+        status: StatusCode,
+        record: metrics::RequestLogInsert,
+    ) -> Self {
+        Self {
+            access: AccessLogEntry::capture(node, ctx, status),
+            started_at: ctx.started_at,
+            record,
+        }
+    }
+
+    /// 响应体发送完毕后调用：latency_ms 记录含响应体传输在内的总耗时，
+    /// upstream_ms 保持为上游首字节耗时
+    pub fn finish(mut self, bytes_sent: u64) {
+        let elapsed_s = self.started_at.elapsed().as_secs_f64();
+        let upstream_s = (self.record.upstream_ms > 0.0).then(|| self.record.upstream_ms / 1000.0);
+        push_log(
+            LogLevel::for_status(self.record.status_code as u16),
+            Some(self.record.listen_addr.as_str()),
+            self.access.log_line(
+                Some(bytes_sent),
+                elapsed_s,
+                upstream_s,
+                Some(self.record.upstream.as_str()),
+            ),
+        );
+        self.record.latency_ms = elapsed_s * 1000.0;
+        self.record.bytes_sent = bytes_sent as i64;
+        metrics::try_enqueue_request_log(self.record);
+    }
 }
 
 #[inline]
@@ -6,7 +6,7 @@ use super::auth::unauthorized_response;
 use super::context::{
     enqueue_request_log, format_access_log, format_headers_for_log, RequestContext,
 };
-use super::logging::{push_log_lazy, send_log_with_app};
+use super::logging::{push_log_lazy, send_log_with_app, LogLevel};
 use super::AppState;
 use crate::{access_control, metrics, rate_limit};
 
@@ -25,11 +25,18 @@ pub fn handle_access_control(
 
     if metrics::is_ip_blacklisted(&ctx.client_ip) {
         let status = StatusCode::FORBIDDEN;
-        push_log_lazy(&state.app, || format_access_log(node, ctx, status));
+        push_log_lazy(
+            &state.app,
+            LogLevel::for_status(status.as_u16()),
+            Some(node),
+            || format_access_log(node, ctx, status),
+        );
 
         let inbound_headers_line = format_headers_for_log(req_headers);
         send_log_with_app(
             &state.app,
+            LogLevel::Warn,
+            Some(node),
             format!(
             "Reverse proxy error (IN): {} {} -> [IP Blacklist] status={} | inbound_headers=[{}]",
             ctx.method.as_str(),
@@ -53,11 +60,12 @@ pub fn handle_access_control(
         return Some((status, "IP Forbidden").into_response());
     }
 
+    let lists = state.access_lists.load();
     let allowed = access_control::is_allowed_remote_ip(
         remote,
-        state.allow_all_lan,
-        state.allow_all_ip,
-        &state.whitelist,
+        lists.allow_all_lan,
+        lists.allow_all_ip,
+        &lists.whitelist,
     );
 
     if !allowed {
@@ -66,13 +74,18 @@ pub fn handle_access_control(
             "Access denied: client_ip={}, remote_ip={}, allow_all_lan={}, whitelist_len={}",
             ctx.client_ip,
             remote.ip(),
-            state.allow_all_lan,
-            state.whitelist.len()
+            lists.allow_all_lan,
+            lists.whitelist.len()
+        );
+        push_log_lazy(
+            &state.app,
+            LogLevel::for_status(status.as_u16()),
+            Some(node),
+            || format_access_log(node, ctx, status),
         );
-        push_log_lazy(&state.app, || format_access_log(node, ctx, status));
 
         let inbound_headers_line = format_headers_for_log(req_headers);
-        send_log_with_app(&state.app, format!(
+        send_log_with_app(&state.app, LogLevel::Warn, Some(node), format!(
             "Reverse proxy error (IN): {} {} -> [Access Control Denied] status={} | inbound_headers=[{}] | client_ip={}, remote_ip={}, allow_all_lan={}, allow_all_ip={}, whitelist_len={}",
             ctx.method.as_str(),
             ctx.uri,
@@ -80,9 +93,9 @@ pub fn handle_access_control(
             inbound_headers_line,
             ctx.client_ip,
             remote.ip(),
-            state.allow_all_lan,
-            state.allow_all_ip,
-            state.whitelist.len()
+            lists.allow_all_lan,
+            lists.allow_all_ip,
+            lists.whitelist.len()
         ));
 
         enqueue_request_log(
@@ -121,12 +134,14 @@ pub fn handle_rate_limit(
     if allowed {
         return None;
     }
+    metrics::record_rate_limited(node);
 
     if should_ban {
         let ban_seconds = state.rule.rate_limit_ban_seconds.unwrap_or(0) as i32;
         if ban_seconds > 0 {
             let ip_str: String = ctx.client_ip.as_ref().into();
             let app_clone = state.app.clone();
+            let node = node.to_string();
             tokio::spawn(async move {
                 if let Err(e) = metrics::add_blacklist_entry(
                     ip_str.clone(),
@@ -139,6 +154,8 @@ pub fn handle_rate_limit(
                 } else {
                     send_log_with_app(
                         &app_clone,
+                        LogLevel::Warn,
+                        Some(node.as_str()),
                         format!(
                         "[Rate Limit] IP {} was banned for {} seconds due to rate limit exceeded",
                         ip_str, ban_seconds
@@ -150,7 +167,12 @@ pub fn handle_rate_limit(
     }
 
     let status = StatusCode::TOO_MANY_REQUESTS;
-    push_log_lazy(&state.app, || format_access_log(node, ctx, status));
+    push_log_lazy(
+        &state.app,
+        LogLevel::for_status(status.as_u16()),
+        Some(node),
+        || format_access_log(node, ctx, status),
+    );
     enqueue_request_log(
         node,
         ctx,
@@ -179,11 +201,18 @@ pub fn handle_basic_auth_failure(
 
     let node = &*state.listen_addr;
     let status = StatusCode::UNAUTHORIZED;
-    push_log_lazy(&state.app, || format_access_log(node, ctx, status));
+    push_log_lazy(
+        &state.app,
+        LogLevel::for_status(status.as_u16()),
+        Some(node),
+        || format_access_log(node, ctx, status),
+    );
 
     let inbound_headers_line = format_headers_for_log(req_headers);
     send_log_with_app(
         &state.app,
+        LogLevel::Warn,
+        Some(node),
         format!(
         "Reverse proxy error (IN): {} {} -> [Basic Auth Failed] status={} | inbound_headers=[{}]",
         ctx.method.as_str(),
@@ -215,7 +244,12 @@ pub fn handle_missing_route(
 ) -> Response {
     let node = &*state.listen_addr;
     let status = StatusCode::NOT_FOUND;
-    push_log_lazy(&state.app, || format_access_log(node, ctx, status));
+    push_log_lazy(
+        &state.app,
+        LogLevel::for_status(status.as_u16()),
+        Some(node),
+        || format_access_log(node, ctx, status),
+    );
     enqueue_request_log(
         node,
         ctx,
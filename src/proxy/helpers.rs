@@ -1,7 +1,9 @@
 use anyhow::{anyhow, Result};
-use axum::body::Bytes;
+use axum::body::{Body, Bytes};
 use axum::http::{HeaderMap, HeaderValue};
+use axum::response::Response;
 use dashmap::DashMap;
+use http_body_util::BodyExt;
 use regex::Regex;
 use std::net::SocketAddr;
 use std::sync::Arc;
@@ -297,14 +299,28 @@ pub fn expand_proxy_header_value(
     out
 }
 
+/// 把 guard 挂到响应体上：流式响应发送完毕或客户端断开导致响应体被 drop 时才释放
+pub fn hold_until_body_end<G: Send + 'static>(resp: Response, guard: G) -> Response {
+    let (parts, body) = resp.into_parts();
+    let body = body.map_frame(move |frame| {
+        let _ = &guard;
+        frame
+    });
+    Response::from_parts(parts, Body::new(body))
+}
+
 #[cfg(test)]
 mod tests {
     use super::{
         cached_content_types, check_etag_match, content_type_allowed, expand_proxy_header_value,
-        pure_content_type_from_headers,
+        hold_until_body_end, pure_content_type_from_headers,
     };
+    use axum::body::Body;
     use axum::http::{HeaderMap, HeaderValue};
+    use axum::response::Response;
+    use http_body_util::BodyExt;
     use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::sync::Arc;
 
     #[test]
     fn cached_content_types_trims_and_normalizes_values() {
@@ -362,4 +378,18 @@ mod tests {
         let expanded = expand_proxy_header_value("fixed-value", &remote, &HeaderMap::new(), false);
         assert_eq!(expanded, "fixed-value");
     }
+
+    #[tokio::test]
+    async fn hold_until_body_end_releases_guard_after_body_is_consumed_or_dropped() {
+        let guard = Arc::new(());
+        let resp = hold_until_body_end(Response::new(Body::from("payload")), guard.clone());
+        assert_eq!(Arc::strong_count(&guard), 2);
+        let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&bytes[..], b"payload");
+        assert_eq!(Arc::strong_count(&guard), 1);
+
+        let resp = hold_until_body_end(Response::new(Body::from("payload")), guard.clone());
+        drop(resp);
+        assert_eq!(Arc::strong_count(&guard), 1);
+    }
 }
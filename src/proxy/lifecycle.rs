@@ -1,13 +1,140 @@
+use arc_swap::ArcSwap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::async_runtime::JoinHandle;
+use tokio::sync::watch;
+
+use crate::app_events::AppEvents;
+use crate::config;
+
+/// 强制断开后等待任务自行退出的时间，之后直接中止
+const CUT_GRACE: Duration = Duration::from_secs(1);
+
 pub struct ServerHandle {
-    pub handle: tauri::async_runtime::JoinHandle<()>,
-    pub shutdown_tx: tokio::sync::oneshot::Sender<()>,
+    pub handle: JoinHandle<()>,
+    /// 停止信号，附带等待进行中请求的 drain 时长
+    pub shutdown_tx: tokio::sync::oneshot::Sender<Duration>,
+    /// 监听正在使用的规则，替换后新请求立即生效
+    pub rule: Arc<ArcSwap<config::ListenRule>>,
+    pub in_flight: Arc<InFlight>,
 }
 
 impl ServerHandle {
     pub fn abort(self) {
-        let _ = self.shutdown_tx.send(());
+        let _ = self.shutdown_tx.send(Duration::ZERO);
         self.handle.abort();
     }
+
+    /// 停止接收新连接，进行中的请求在 drain 内完成，超时后强制断开；
+    /// 返回的任务结束时监听已退出
+    pub fn shutdown(self, drain: Duration) -> JoinHandle<DrainReport> {
+        let before = self.in_flight.count();
+        let _ = self.shutdown_tx.send(drain);
+        tauri::async_runtime::spawn(drain_listener(self.handle, self.in_flight, before, drain))
+    }
+}
+
+/// 停止监听时连接的处理结果
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DrainReport {
+    /// 在 drain 时长内正常结束的连接
+    pub drained: usize,
+    /// 超时后被强制断开的连接
+    pub cut: usize,
+}
+
+impl std::ops::AddAssign for DrainReport {
+    fn add_assign(&mut self, other: Self) {
+        self.drained += other.drained;
+        self.cut += other.cut;
+    }
+}
+
+/// 监听上进行中的连接（HTTP 为请求）：停止时先等待其结束，超过 drain 时长后统一强制断开
+pub struct InFlight {
+    count: AtomicUsize,
+    cut_tx: watch::Sender<bool>,
+}
+
+struct InFlightGuard(Arc<InFlight>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.count.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl InFlight {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            count: AtomicUsize::new(0),
+            cut_tx: watch::channel(false).0,
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// 登记并运行一个连接；被强制断开时丢弃 fut 并返回 None
+    pub async fn track<F: Future>(self: &Arc<Self>, fut: F) -> Option<F::Output> {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let _guard = InFlightGuard(self.clone());
+        let mut cut_rx = self.cut_tx.subscribe();
+        tokio::select! {
+            out = fut => Some(out),
+            _ = cut_rx.wait_for(|cut| *cut) => None,
+        }
+    }
+
+    fn cut(&self) {
+        self.cut_tx.send_replace(true);
+    }
+
+    /// 等待全部连接结束
+    async fn idle(&self) {
+        while self.count() > 0 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
+
+/// 已发送停止信号后：等待监听任务退出且进行中的连接结束，超过 drain 时长则强制断开剩余连接。
+/// before 为发送停止信号时进行中的连接数
+pub async fn drain_listener(
+    mut task: JoinHandle<()>,
+    in_flight: Arc<InFlight>,
+    before: usize,
+    drain: Duration,
+) -> DrainReport {
+    let mut exited = false;
+    let finished = tokio::time::timeout(drain, async {
+        let _ = (&mut task).await;
+        exited = true;
+        in_flight.idle().await;
+    })
+    .await
+    .is_ok();
+    if finished {
+        return DrainReport {
+            drained: before,
+            cut: 0,
+        };
+    }
+
+    let cut = in_flight.count().min(before);
+    in_flight.cut();
+    if !exited && tokio::time::timeout(CUT_GRACE, &mut task).await.is_err() {
+        task.abort();
+    }
+    DrainReport {
+        drained: before - cut,
+        cut,
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -18,24 +145,281 @@ pub enum Phase {
     Failed,
 }
 
-pub struct ProxyState {
-    pub phase: Phase,
+/// HTTP 监听任务的标识：(规则 id, 监听地址)
+pub type ListenerKey = (String, String);
+
+/// listener-started / listener-stopped / listener-failed 事件内容
+#[derive(Debug, Clone, Serialize)]
+pub struct ListenerEvent {
+    /// http / ws / stream
+    pub kind: &'static str,
+    pub listen_addr: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule_id: Option<String>,
+    /// stream 监听是否为 UDP，其余类型恒为 false
+    pub udp: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ListenerEvent {
+    pub fn new(kind: &'static str, listen_addr: String, rule_id: Option<String>) -> Self {
+        Self {
+            kind,
+            listen_addr,
+            rule_id,
+            udp: false,
+            error: None,
+        }
+    }
+
+    pub fn started(self, app: &AppEvents) {
+        self.emit(app, "listener-started");
+    }
+
+    pub fn stopped(self, app: &AppEvents) {
+        self.emit(app, "listener-stopped");
+    }
+
+    pub fn failed(mut self, app: &AppEvents, error: String) {
+        self.error = Some(error);
+        self.emit(app, "listener-failed");
+    }
+
+    fn emit(&self, app: &AppEvents, event: &str) {
+        let _ = app.emit(event, self);
+        crate::tray::refresh_tray_proxy_state();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListenerState {
+    Running,
+    Stopped,
+    Failed,
+}
+
+/// 单个监听（HTTP 地址、WS 规则或 stream 服务）的运行状态
+#[derive(Debug, Clone, Serialize)]
+pub struct ListenerStatus {
+    /// http / ws / stream
+    pub kind: &'static str,
+    pub listen_addr: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule_id: Option<String>,
+    /// stream 监听是否为 UDP，其余类型恒为 false
+    pub udp: bool,
+    pub state: ListenerState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 开始监听的时间（Unix 秒）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<i64>,
+    /// 进行中的连接数，HTTP 为进行中的请求数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_connections: Option<u64>,
+}
+
+impl ListenerStatus {
+    pub fn stopped(kind: &'static str, listen_addr: String) -> Self {
+        Self {
+            kind,
+            listen_addr,
+            rule_id: None,
+            udp: false,
+            state: ListenerState::Stopped,
+            error: None,
+            started_at: None,
+            active_connections: None,
+        }
+    }
+
+    pub fn running(mut self, started_at: i64, active_connections: u64) -> Self {
+        self.state = ListenerState::Running;
+        self.started_at = Some(started_at);
+        self.active_connections = Some(active_connections);
+        self
+    }
+
+    pub fn failed(mut self, error: String) -> Self {
+        self.state = ListenerState::Failed;
+        self.error = Some(error);
+        self
+    }
+}
+
+/// 一次整体启动的进度；generation 变化后，旧任务的回报一律忽略
+pub struct StartSession {
     pub generation: u64,
     pub expected: usize,
     pub started: usize,
-    pub handles: Vec<ServerHandle>,
+    pub failed: usize,
+    /// 有监听失败时保留其余监听，而不是整体回滚
+    pub partial_allowed: bool,
+}
+
+impl StartSession {
+    pub const fn new(generation: u64) -> Self {
+        Self {
+            generation,
+            expected: 0,
+            started: 0,
+            failed: 0,
+            partial_allowed: false,
+        }
+    }
+}
+
+pub struct ProxyState {
+    pub phase: Phase,
+    pub session: StartSession,
+    pub handles: BTreeMap<ListenerKey, ServerHandle>,
+    /// 已通过预检、正在监听的任务及开始监听的时间（Unix 秒）
+    pub running: BTreeMap<ListenerKey, i64>,
+    /// 预检失败的监听及原因，重新启动该监听或停止服务时清除
+    pub failed: BTreeMap<ListenerKey, String>,
 }
 
 impl ProxyState {
     pub const fn new() -> Self {
         Self {
             phase: Phase::Stopped,
-            generation: 0,
-            expected: 0,
-            started: 0,
-            handles: Vec::new(),
+            session: StartSession::new(0),
+            handles: BTreeMap::new(),
+            running: BTreeMap::new(),
+            failed: BTreeMap::new(),
         }
     }
+
+    /// 作废当前启动并清空运行状态，返回全部 HTTP 监听句柄；failed 保留，供查询失败原因
+    pub fn reset(&mut self, phase: Phase) -> BTreeMap<ListenerKey, ServerHandle> {
+        self.phase = phase;
+        self.session = StartSession::new(self.session.generation.wrapping_add(1));
+        self.running.clear();
+        std::mem::take(&mut self.handles)
+    }
+
+    /// 整体启动的全部监听都有结果后确定最终阶段：至少一个监听启动成功为 Running，否则为 Failed；
+    /// 仍有监听未完成预检时返回 None
+    pub fn settle_start(&mut self) -> Option<Phase> {
+        let session = &self.session;
+        if self.phase != Phase::Starting || session.started + session.failed < session.expected {
+            return None;
+        }
+        let phase = if session.started > 0 {
+            Phase::Running
+        } else {
+            Phase::Failed
+        };
+        self.phase = phase;
+        Some(phase)
+    }
 }
 
 pub static PROXY_STATE: parking_lot::Mutex<ProxyState> = parking_lot::Mutex::new(ProxyState::new());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn drain_waits_for_in_flight_then_cuts_stragglers() {
+        let in_flight = InFlight::new();
+        let quick = tokio::spawn({
+            let in_flight = in_flight.clone();
+            async move {
+                in_flight
+                    .track(tokio::time::sleep(Duration::from_millis(20)))
+                    .await
+            }
+        });
+        let stuck = tokio::spawn({
+            let in_flight = in_flight.clone();
+            async move { in_flight.track(std::future::pending::<()>()).await }
+        });
+        while in_flight.count() < 2 {
+            tokio::task::yield_now().await;
+        }
+
+        let listener = tauri::async_runtime::spawn(async {});
+        let report =
+            drain_listener(listener, in_flight.clone(), 2, Duration::from_millis(200)).await;
+
+        assert_eq!(report, DrainReport { drained: 1, cut: 1 });
+        assert_eq!(quick.await.unwrap(), Some(()));
+        assert_eq!(stuck.await.unwrap(), None);
+        assert_eq!(in_flight.count(), 0);
+    }
+
+    #[test]
+    fn start_settles_once_every_listener_reported() {
+        let mut state = ProxyState::new();
+        state.phase = Phase::Starting;
+        state.session = StartSession {
+            expected: 2,
+            started: 1,
+            partial_allowed: true,
+            ..StartSession::new(1)
+        };
+        assert_eq!(state.settle_start(), None);
+        assert_eq!(state.phase, Phase::Starting);
+
+        state.session.failed += 1;
+        assert_eq!(state.settle_start(), Some(Phase::Running));
+        assert_eq!(state.settle_start(), None);
+
+        state.phase = Phase::Starting;
+        state.session = StartSession {
+            expected: 1,
+            failed: 1,
+            ..StartSession::new(2)
+        };
+        assert_eq!(state.settle_start(), Some(Phase::Failed));
+    }
+
+    #[test]
+    fn reset_invalidates_the_session_but_keeps_failures() {
+        let mut state = ProxyState::new();
+        state.session = StartSession::new(7);
+        let key: ListenerKey = ("site".to_string(), "127.0.0.1:18080".to_string());
+        state.running.insert(key.clone(), 0);
+        state
+            .failed
+            .insert(key.clone(), "bad certificate".to_string());
+
+        assert!(state.reset(Phase::Failed).is_empty());
+        assert_eq!(state.phase, Phase::Failed);
+        assert_eq!(state.session.generation, 8);
+        assert!(state.running.is_empty());
+        assert_eq!(state.failed[&key], "bad certificate");
+    }
+
+    #[test]
+    fn listener_status_serializes_only_known_fields() {
+        let stopped = ListenerStatus::stopped("ws", "0.0.0.0:8081".to_string());
+        assert_eq!(
+            serde_json::to_value(&stopped).unwrap(),
+            serde_json::json!({
+                "kind": "ws",
+                "listen_addr": "0.0.0.0:8081",
+                "udp": false,
+                "state": "stopped",
+            })
+        );
+
+        let running =
+            ListenerStatus::stopped("http", "127.0.0.1:8080".to_string()).running(1_700_000_000, 3);
+        let value = serde_json::to_value(&running).unwrap();
+        assert_eq!(value["state"], "running");
+        assert_eq!(value["started_at"], 1_700_000_000);
+        assert_eq!(value["active_connections"], 3);
+
+        let failed = ListenerStatus::stopped("stream", "127.0.0.1:53".to_string())
+            .failed("address in use".to_string());
+        let value = serde_json::to_value(&failed).unwrap();
+        assert_eq!(value["state"], "failed");
+        assert_eq!(value["error"], "address in use");
+        assert!(value.get("started_at").is_none());
+    }
+}
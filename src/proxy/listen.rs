@@ -1,7 +1,19 @@
 use anyhow::{Context, Result};
+use axum_server::tls_rustls::RustlsConfig;
 use std::net::SocketAddr;
 
 use crate::config;
+use crate::network_optimizer::TcpOptimizer;
+use crate::port_owner::{self, PortOwner};
+
+/// 预检时已绑定的监听 socket，原样交给 start_rule_server，避免释放后再次绑定被其他进程抢占
+pub struct BoundListener {
+    pub listener: tokio::net::TcpListener,
+    pub addr: SocketAddr,
+    pub need_dual_stack: bool,
+    /// 启用 TLS 时预检加载好的证书
+    pub tls: Option<RustlsConfig>,
+}
 
 pub fn parse_listen_addr(s: &str) -> Result<(SocketAddr, bool)> {
     let trimmed = s.trim();
@@ -27,30 +39,73 @@ pub fn parse_listen_addr(s: &str) -> Result<(SocketAddr, bool)> {
     Ok((normalized, need_dual_stack))
 }
 
-pub async fn precheck_rule(rule: &config::ListenRule, listen_addr: &str) -> Result<()> {
-    let (addr, _need_dual_stack) = parse_listen_addr(listen_addr)?;
-
-    if rule.ssl_enable {
-        let _ = axum_server::tls_rustls::RustlsConfig::from_pem_file(
-            rule.cert_file.clone(),
-            rule.key_file.clone(),
-        )
-        .await
-        .with_context(|| "Failed to load TLS certificate/private key")?;
+pub async fn precheck_rule(rule: &config::ListenRule, listen_addr: &str) -> Result<BoundListener> {
+    let (addr, need_dual_stack) = parse_listen_addr(listen_addr)?;
 
-        let listener = tokio::net::TcpListener::bind(addr).await?;
-        drop(listener);
+    let tls = if rule.ssl_enable {
+        let tls_cfg = RustlsConfig::from_pem_file(rule.cert_file.clone(), rule.key_file.clone())
+            .await
+            .with_context(|| "Failed to load TLS certificate/private key")?;
+        Some(tls_cfg)
     } else {
-        let listener = tokio::net::TcpListener::bind(addr).await?;
-        drop(listener);
+        None
+    };
+
+    let listener = TcpOptimizer::default().optimize_listener(addr).await?;
+
+    Ok(BoundListener {
+        listener,
+        addr,
+        need_dual_stack,
+        tls,
+    })
+}
+
+/// 端口可用性检查结果
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PortAvailability {
+    pub listen_addr: String,
+    pub bind_addr: String,
+    pub available: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 端口被占用且能查到时的占用进程
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<PortOwner>,
+    /// 占用者是本进程（通常是已在运行的监听）
+    pub owned_by_self: bool,
+}
+
+/// 按启动时相同的方式试绑定一次，随即释放
+pub async fn check_port_available(listen_addr: &str) -> Result<PortAvailability> {
+    let (addr, _) = parse_listen_addr(listen_addr)?;
+    let mut result = PortAvailability {
+        listen_addr: listen_addr.trim().to_string(),
+        bind_addr: addr.to_string(),
+        available: true,
+        error: None,
+        owner: None,
+        owned_by_self: false,
+    };
+
+    if let Err(e) = TcpOptimizer::default().optimize_listener(addr).await {
+        let in_use = e
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|io| io.kind() == std::io::ErrorKind::AddrInUse);
+        result.available = false;
+        result.error = Some(e.to_string());
+        if in_use {
+            result.owner = port_owner::find_tcp_listener(addr.port());
+            result.owned_by_self = result.owner.as_ref().is_some_and(PortOwner::is_self);
+        }
     }
 
-    Ok(())
+    Ok(result)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::parse_listen_addr;
+    use super::{check_port_available, parse_listen_addr, precheck_rule};
     use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 
     #[test]
@@ -89,4 +144,46 @@ mod tests {
         let err = parse_listen_addr("not-an-addr").unwrap_err().to_string();
         assert!(err.contains("Failed to parse listen_addr"));
     }
+
+    #[tokio::test]
+    async fn precheck_rule_keeps_the_listener_bound() {
+        let rule: crate::config::ListenRule = toml::from_str(
+            r#"
+listen_addr = "127.0.0.1:0"
+ssl_enable = false
+cert_file = ""
+key_file = ""
+basic_auth_enable = false
+basic_auth_username = ""
+basic_auth_password = ""
+basic_auth_forward_header = false
+routes = []
+"#,
+        )
+        .unwrap();
+
+        let bound = precheck_rule(&rule, "127.0.0.1:0").await.unwrap();
+        assert!(bound.tls.is_none());
+        let local = bound.listener.local_addr().unwrap();
+        assert_ne!(local.port(), 0);
+        // 预检后端口仍被占用，启动阶段不需要也不可能重新绑定
+        assert!(std::net::TcpListener::bind(local).is_err());
+    }
+
+    #[tokio::test]
+    async fn check_port_available_reports_busy_port() {
+        let held = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = held.local_addr().unwrap().to_string();
+
+        let busy = check_port_available(&addr).await.unwrap();
+        assert!(!busy.available);
+        assert!(busy.error.is_some());
+        #[cfg(target_os = "linux")]
+        assert!(busy.owned_by_self);
+
+        drop(held);
+        let free = check_port_available(&addr).await.unwrap();
+        assert!(free.available);
+        assert!(free.owner.is_none());
+    }
 }
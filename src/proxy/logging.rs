@@ -1,18 +1,127 @@
 use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashSet, VecDeque};
-use tauri::Emitter;
+use std::sync::atomic::{AtomicU64, Ordering};
 
+use crate::app_events::AppEvents;
 use crate::config;
 
-pub const LOG_QUEUE_CAPACITY: usize = 10_000;
+// 日志丢弃告警的最短间隔
+const LOG_DROP_WARN_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
 
-pub static LOG_TX: once_cell::sync::Lazy<RwLock<Option<tokio::sync::mpsc::Sender<String>>>> =
+pub static LOG_TX: once_cell::sync::Lazy<RwLock<Option<tokio::sync::mpsc::Sender<LogEntry>>>> =
     once_cell::sync::Lazy::new(|| RwLock::new(None));
 
-pub static LOG_DROPPED: once_cell::sync::Lazy<std::sync::atomic::AtomicU64> =
-    once_cell::sync::Lazy::new(|| std::sync::atomic::AtomicU64::new(0));
+pub static LOG_DROPPED: once_cell::sync::Lazy<AtomicU64> =
+    once_cell::sync::Lazy::new(|| AtomicU64::new(0));
 
-pub static LOGS: RwLock<VecDeque<String>> = RwLock::new(VecDeque::new());
+pub static LOGS: RwLock<VecDeque<LogEntry>> = RwLock::new(VecDeque::new());
+
+static LOG_SEQ: AtomicU64 = AtomicU64::new(0);
+// 已在告警中报告过的丢弃条数
+static LOG_DROPPED_REPORTED: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    #[serde(alias = "warning")]
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    /// 访问日志按状态码定级：5xx 为 error，其余为 info
+    #[inline]
+    pub fn for_status(status: u16) -> Self {
+        if status >= 500 {
+            Self::Error
+        } else {
+            Self::Info
+        }
+    }
+
+    /// 未标注级别的 push_log_line 调用按内容粗略推断
+    fn infer(message: &str) -> Self {
+        let lower = message.to_ascii_lowercase();
+        if lower.contains("error")
+            || lower.contains("failed")
+            || message.contains("异常")
+            || message.contains("失败")
+        {
+            Self::Error
+        } else if lower.contains("warn") {
+            Self::Warn
+        } else {
+            Self::Info
+        }
+    }
+}
+
+/// 内存日志条目，timestamp 为毫秒时间戳；seq 在写入缓冲时分配，单调递增，清空日志后也不重置
+#[derive(Debug, Clone, Serialize)]
+pub struct LogEntry {
+    pub seq: u64,
+    pub timestamp: i64,
+    pub level: LogLevel,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+    pub message: String,
+}
+
+impl LogEntry {
+    pub fn new(level: LogLevel, node: Option<&str>, message: String) -> Self {
+        Self {
+            seq: 0,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            level,
+            node: node.map(str::to_string),
+            message,
+        }
+    }
+
+    /// 旧版纯文本格式，供尚未改用结构化条目的前端使用
+    #[inline]
+    pub fn render(&self) -> &str {
+        &self.message
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LogFilter {
+    /// 只返回不低于该级别的日志
+    #[serde(default)]
+    pub min_level: Option<LogLevel>,
+    #[serde(default)]
+    pub node: Option<String>,
+    /// 不区分大小写的子串匹配
+    #[serde(default)]
+    pub contains: Option<String>,
+    /// 只返回 seq 大于该值的日志，供界面增量轮询
+    #[serde(default)]
+    pub since_seq: Option<u64>,
+    /// 最多返回条数：指定 since_seq 时保留最早的部分以便继续翻页，否则保留最新的部分
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl LogFilter {
+    fn matches(&self, entry: &LogEntry, needle: Option<&str>) -> bool {
+        if self.min_level.is_some_and(|min| entry.level < min) {
+            return false;
+        }
+        if let Some(node) = self.node.as_deref().filter(|n| !n.is_empty()) {
+            if entry.node.as_deref() != Some(node) {
+                return false;
+            }
+        }
+        match needle {
+            Some(n) => entry.message.to_lowercase().contains(n),
+            None => true,
+        }
+    }
+}
 
 pub static SKIP_HEADERS: once_cell::sync::Lazy<HashSet<axum::http::HeaderName>> =
     once_cell::sync::Lazy::new(|| {
@@ -26,87 +135,269 @@ pub static SKIP_HEADERS: once_cell::sync::Lazy<HashSet<axum::http::HeaderName>>
         set
     });
 
-pub fn get_logs() -> Vec<String> {
-    LOGS.read().iter().cloned().collect()
+pub fn get_log_entries(filter: &LogFilter) -> Vec<LogEntry> {
+    select_log_entries(&LOGS.read(), filter)
 }
 
+fn select_log_entries(logs: &VecDeque<LogEntry>, filter: &LogFilter) -> Vec<LogEntry> {
+    let needle = filter
+        .contains
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_lowercase);
+    // 缓冲按 seq 递增排列，可二分定位起点
+    let start = filter
+        .since_seq
+        .map_or(0, |since| logs.partition_point(|e| e.seq <= since));
+    let matched = logs
+        .range(start..)
+        .filter(|e| filter.matches(e, needle.as_deref()));
+
+    match filter.limit.filter(|n| *n > 0) {
+        Some(n) if filter.since_seq.is_some() => matched.take(n).cloned().collect(),
+        Some(n) => {
+            let mut out: Vec<LogEntry> = matched.rev().take(n).cloned().collect();
+            out.reverse();
+            out
+        }
+        None => matched.cloned().collect(),
+    }
+}
+
+pub fn get_logs(filter: &LogFilter) -> Vec<String> {
+    get_log_entries(filter)
+        .into_iter()
+        .map(|e| e.message)
+        .collect()
+}
+
+/// 只清空缓冲与丢弃计数，seq 继续递增，已连接的界面按 since_seq 轮询不会错乱
 pub fn clear_logs() {
     LOGS.write().clear();
+    LOG_DROPPED.store(0, Ordering::Relaxed);
+    LOG_DROPPED_REPORTED.store(0, Ordering::Relaxed);
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogStats {
+    /// 日志队列已满而丢弃的条数（clear_logs 后重新计数）
+    pub dropped: u64,
+    pub queue_depth: usize,
+    pub queue_capacity: usize,
+    pub buffered: usize,
+    pub buffer_capacity: usize,
+    pub last_seq: u64,
+    pub syslog_dropped: u64,
+}
+
+pub fn get_log_stats() -> LogStats {
+    let (queue_depth, queue_capacity) = LOG_TX
+        .read()
+        .as_ref()
+        .map(|tx| (tx.max_capacity() - tx.capacity(), tx.max_capacity()))
+        .unwrap_or((0, config::log_queue_capacity()));
+    LogStats {
+        dropped: LOG_DROPPED.load(Ordering::Relaxed),
+        queue_depth,
+        queue_capacity,
+        buffered: LOGS.read().len(),
+        buffer_capacity: config::log_buffer_size(),
+        last_seq: LOG_SEQ.load(Ordering::Relaxed),
+        syslog_dropped: super::syslog::SYSLOG_DROPPED.load(Ordering::Relaxed),
+    }
+}
+
+/// 有新的丢弃时写一条告警；由日志任务定时调用，因此每分钟最多一条
+fn warn_log_dropped(app: &AppEvents) {
+    let total = LOG_DROPPED.load(Ordering::Relaxed);
+    let reported = LOG_DROPPED_REPORTED.swap(total, Ordering::Relaxed);
+    if total > reported {
+        send_log_with_app(
+            app,
+            LogLevel::Warn,
+            None,
+            format!(
+                "[LOG] 已丢弃 {} 条访问日志（日志队列已满，累计 {}）",
+                total - reported,
+                total
+            ),
+        );
+    }
 }
 
+/// 写入环形缓冲并分配 seq，超出 log_buffer_size 时丢弃最旧的条目
 #[inline]
-pub fn append_log(logs: &mut VecDeque<String>, message: String) {
-    const MAX_LOGS: usize = 3000;
-    if logs.len() >= MAX_LOGS {
+pub fn append_log(logs: &mut VecDeque<LogEntry>, mut entry: LogEntry) -> &LogEntry {
+    let cap = config::log_buffer_size();
+    while logs.len() >= cap {
         logs.pop_front();
     }
-    logs.push_back(message);
+    if logs.capacity() < cap {
+        logs.reserve_exact(cap - logs.len());
+    }
+    entry.seq = LOG_SEQ.fetch_add(1, Ordering::Relaxed) + 1;
+    super::syslog::forward(&entry);
+    // 无界面模式没有日志面板，直接输出到标准输出
+    if crate::headless::is_headless() {
+        println!("{}", entry.render());
+    }
+    logs.push_back(entry);
+    &logs[logs.len() - 1]
 }
 
-pub fn send_log(message: impl Into<String>) {
-    append_log(&mut LOGS.write(), message.into());
+/// 同时推送旧版 log-line 字符串事件与结构化的 log-entry 事件
+fn emit_log_entry(app: &AppEvents, entry: &LogEntry) {
+    let _ = app.emit("log-line", entry.render());
+    let _ = app.emit("log-entry", entry);
 }
 
-pub fn send_log_with_app(app: &tauri::AppHandle, message: impl Into<String>) {
-    let message = message.into();
-    {
-        append_log(&mut LOGS.write(), message.clone());
-    }
+pub fn send_log(level: LogLevel, node: Option<&str>, message: impl Into<String>) {
+    append_log(
+        &mut LOGS.write(),
+        LogEntry::new(level, node, message.into()),
+    );
+}
 
+pub fn send_log_with_app(
+    app: &AppEvents,
+    level: LogLevel,
+    node: Option<&str>,
+    message: impl Into<String>,
+) {
     let (show_realtime_logs, realtime_logs_only_errors) = config::realtime_logs_settings();
-    if !show_realtime_logs {
-        return;
-    }
+    let emit = show_realtime_logs && !(realtime_logs_only_errors && level < LogLevel::Error);
 
-    if realtime_logs_only_errors {
-        let lower = message.to_ascii_lowercase();
-        if !(lower.contains("error")
-            || lower.contains("failed")
-            || lower.contains("异常")
-            || lower.contains("失败"))
-        {
+    let entry = {
+        let mut logs = LOGS.write();
+        let entry = append_log(&mut logs, LogEntry::new(level, node, message.into()));
+        if !emit {
             return;
         }
-    }
-
-    let _ = app.emit("log-line", message);
+        entry.clone()
+    };
+    emit_log_entry(app, &entry);
 }
 
-pub fn push_log_lazy<F>(_app: &tauri::AppHandle, f: F)
+pub fn push_log_lazy<F>(_app: &AppEvents, level: LogLevel, node: Option<&str>, f: F)
 where
     F: FnOnce() -> String,
 {
-    let line = f();
+    push_log(level, node, f());
+}
 
+/// 经日志任务写入缓冲并推送日志事件；不需要 AppHandle，适合后台任务调用
+pub fn push_log(level: LogLevel, node: Option<&str>, message: String) {
+    let entry = LogEntry::new(level, node, message);
     if !config::show_realtime_logs_enabled() {
-        append_log(&mut LOGS.write(), line);
+        append_log(&mut LOGS.write(), entry);
         return;
     }
 
     if let Some(tx) = LOG_TX.read().as_ref() {
-        if tx.try_send(line).is_err() {
-            LOG_DROPPED.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        if tx.try_send(entry).is_err() {
+            LOG_DROPPED.fetch_add(1, Ordering::Relaxed);
         }
     } else {
-        append_log(&mut LOGS.write(), line);
+        append_log(&mut LOGS.write(), entry);
     }
 }
 
-pub fn init_log_task(app: tauri::AppHandle) {
+#[inline]
+pub fn push_log_line(line: String) {
+    push_log(LogLevel::infer(&line), None, line);
+}
+
+pub fn init_log_task(app: AppEvents) {
     if LOG_TX.read().is_some() {
         return;
     }
 
-    let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(LOG_QUEUE_CAPACITY);
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<LogEntry>(config::log_queue_capacity());
     *LOG_TX.write() = Some(tx);
 
     tauri::async_runtime::spawn(async move {
-        while let Some(line) = rx.recv().await {
-            {
-                append_log(&mut LOGS.write(), line.clone());
+        let mut drop_check = tokio::time::interval(LOG_DROP_WARN_INTERVAL);
+        drop_check.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                entry = rx.recv() => {
+                    let Some(entry) = entry else {
+                        break;
+                    };
+                    let entry = append_log(&mut LOGS.write(), entry).clone();
+                    emit_log_entry(&app, &entry);
+                }
+                _ = drop_check.tick() => warn_log_dropped(&app),
             }
-
-            let _ = app.emit("log-line", line);
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_filter_matches_level_node_and_text() {
+        let entry = LogEntry::new(
+            LogLevel::Warn,
+            Some("0.0.0.0:443"),
+            "Reverse proxy error (IN): GET /Admin".to_string(),
+        );
+        let filter = |json: &str| serde_json::from_str::<LogFilter>(json).unwrap();
+        let matches = |f: &LogFilter| {
+            let needle = f.contains.as_deref().map(str::to_lowercase);
+            f.matches(&entry, needle.as_deref())
+        };
+
+        assert!(matches(&filter(
+            r#"{"min_level":"warn","node":"0.0.0.0:443"}"#
+        )));
+        assert!(matches(&filter(
+            r#"{"min_level":"warning","contains":"/admin"}"#
+        )));
+        assert!(!matches(&filter(r#"{"min_level":"error"}"#)));
+        assert!(!matches(&filter(r#"{"node":"0.0.0.0:80"}"#)));
+        assert!(!matches(&filter(r#"{"contains":"stream"}"#)));
+
+        let json = serde_json::to_value(&entry).unwrap();
+        assert_eq!(json["level"], "warn");
+        assert_eq!(json["node"], "0.0.0.0:443");
+        assert_eq!(LogLevel::infer("Failed to bind"), LogLevel::Error);
+        assert_eq!(LogLevel::for_status(502), LogLevel::Error);
+        assert_eq!(LogLevel::for_status(404), LogLevel::Info);
+    }
+
+    #[test]
+    fn log_entries_page_by_seq() {
+        let mut logs = VecDeque::new();
+        for i in 0..5 {
+            append_log(
+                &mut logs,
+                LogEntry::new(LogLevel::Info, None, format!("line {i}")),
+            );
+        }
+        let first = logs[0].seq;
+        assert!(logs
+            .iter()
+            .zip(logs.iter().skip(1))
+            .all(|(a, b)| b.seq == a.seq + 1));
+
+        let page = |since_seq: Option<u64>, limit: Option<usize>| {
+            let filter = LogFilter {
+                since_seq,
+                limit,
+                ..Default::default()
+            };
+            select_log_entries(&logs, &filter)
+                .into_iter()
+                .map(|e| e.message)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(page(Some(first + 2), None), ["line 3", "line 4"]);
+        assert_eq!(page(Some(first), Some(2)), ["line 1", "line 2"]);
+        assert_eq!(page(None, Some(2)), ["line 3", "line 4"]);
+        assert!(page(Some(first + 4), None).is_empty());
+    }
+}
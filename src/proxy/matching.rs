@@ -74,6 +74,54 @@ pub fn host_matches(route_host: &str, request_host: &str) -> bool {
     false
 }
 
+/// 路由优先级：先看是否指定了 host，再看路径前缀长度（HTTP 与 WS 路由共用）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RouteRank {
+    has_host: bool,
+    path_len: usize,
+}
+
+/// host 与路径前缀都命中时返回该路由的优先级；request_host 需已经过 normalize_host
+#[inline]
+pub fn route_rank(
+    route_host: Option<&str>,
+    route_path: &str,
+    request_host: &str,
+    path: &str,
+) -> Option<RouteRank> {
+    if !path.starts_with(route_path) {
+        return None;
+    }
+
+    let route_host = route_host.map(str::trim).filter(|s| !s.is_empty());
+    if let Some(h) = route_host {
+        if !host_matches(h, request_host) {
+            return None;
+        }
+    }
+
+    Some(RouteRank {
+        has_host: route_host.is_some(),
+        path_len: route_path.len(),
+    })
+}
+
+/// 取优先级最高的候选；优先级相同时保留先出现的
+#[inline]
+pub fn best_ranked<T>(candidates: impl IntoIterator<Item = (T, RouteRank)>) -> Option<T> {
+    let mut best: Option<(T, RouteRank)> = None;
+    for (item, rank) in candidates {
+        let better = match &best {
+            None => true,
+            Some((_, best_rank)) => rank > *best_rank,
+        };
+        if better {
+            best = Some((item, rank));
+        }
+    }
+    best.map(|(item, _)| item)
+}
+
 #[inline]
 fn wildcard_match_ignore_ascii_case(pattern: &str, value: &str) -> bool {
     let pattern = pattern.trim();
@@ -110,73 +158,32 @@ pub fn match_route<'a>(
 ) -> (Option<&'a config::Route>, String) {
     let host = normalize_host(request_host);
 
-    let mut best: Option<(&config::Route, bool, usize)> = None;
-
-    for r in routes {
-        if !r.enabled {
-            continue;
-        }
-
-        let p = match r.path.as_deref() {
-            Some(v) => v,
-            None => continue,
-        };
-        if !path.starts_with(p) {
-            continue;
-        }
-
-        let host_ok = match r.host.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
-            None => true,
-            Some(h) => host_matches(h, host),
-        };
-        if !host_ok {
-            continue;
-        }
+    let candidates = routes.iter().filter(|r| r.enabled).filter_map(|r| {
+        let rank = route_rank(r.host.as_deref(), r.path.as_deref()?, host, path)?;
 
         if let Some(ref methods) = r.methods {
             if !methods
                 .iter()
                 .any(|m| m.eq_ignore_ascii_case(method.as_str()))
             {
-                continue;
+                return None;
             }
         }
 
         if let Some(ref required_headers) = r.headers {
-            let mut headers_ok = true;
             for (key, expected) in required_headers {
                 let actual = headers.get(key).and_then(|v| v.to_str().ok()).unwrap_or("");
 
                 if !wildcard_match_ignore_ascii_case(expected, actual) {
-                    headers_ok = false;
-                    break;
+                    return None;
                 }
             }
-            if !headers_ok {
-                continue;
-            }
         }
 
-        let cand = (r, r.host.as_ref().is_some(), p.len());
-        best = match best {
-            None => Some(cand),
-            Some((best_r, best_has_host, best_plen)) => {
-                if cand.1 != best_has_host {
-                    if cand.1 {
-                        Some(cand)
-                    } else {
-                        Some((best_r, best_has_host, best_plen))
-                    }
-                } else if cand.2 > best_plen {
-                    Some(cand)
-                } else {
-                    Some((best_r, best_has_host, best_plen))
-                }
-            }
-        };
-    }
+        Some((r, rank))
+    });
 
-    if let Some((r, _, _)) = best {
+    if let Some(r) = best_ranked(candidates) {
         (Some(r), r.id.as_deref().unwrap_or("").to_string())
     } else {
         (None, String::new())
@@ -1,4 +1,6 @@
+pub mod access_log;
 pub mod auth;
+pub mod connections;
 pub mod context;
 pub mod dispatch;
 pub mod early;
@@ -7,21 +9,35 @@ pub mod lifecycle;
 pub mod listen;
 pub mod logging;
 pub mod matching;
+pub mod replay;
 pub mod request;
 pub mod response;
 pub mod runtime;
 pub mod server;
 pub mod static_files;
 pub mod stream_proxy;
+pub mod syslog;
 pub mod types;
 pub mod upstream;
+pub mod upstream_health;
+pub mod ws_deflate;
 pub mod ws_proxy;
 
 pub use auth::healthz;
 pub use helpers::{cached_content_types, cached_regex};
+pub use lifecycle::{DrainReport, ListenerStatus};
 pub use listen::parse_listen_addr;
-pub use logging::{clear_logs, get_logs, send_log_with_app};
-pub use runtime::{is_effectively_running, is_running, start_server, stop_server};
+pub use logging::{
+    clear_logs, get_log_entries, get_log_stats, get_logs, send_log_with_app, LogEntry, LogFilter,
+    LogLevel, LogStats,
+};
+pub use runtime::{
+    is_effectively_running, is_running, listen_rule_statuses, listener_counts, listener_statuses,
+    phase_name, restart_server, rule_listen_addrs, start_listen_rule, start_listeners_after_reload,
+    start_server, start_server_after_restart, stop_for_restart, stop_listen_rule,
+    stop_listeners_for_reload, stop_server, stop_server_gracefully, update_listen_rule,
+    ListenRuleStatus,
+};
 use types::AppState;
 pub use types::RuleStartErrorPayload;
 
@@ -45,16 +61,32 @@ use hyper_util::rt::tokio::WithTokioIo;
 use std::net::SocketAddr;
 use tokio_tungstenite::{connect_async, tungstenite::client::IntoClientRequest, tungstenite::protocol::Role, MaybeTlsStream, WebSocketStream};
 
-#[inline]
 pub(crate) async fn proxy_handler(
     State(state): State<AppState>,
     ConnectInfo(remote): ConnectInfo<SocketAddr>,
     req: Request<Body>,
 ) -> Response {
+    // 进行中计数持续到响应体发送完毕，客户端中途断开时随响应体 drop 归还
+    let in_flight = crate::metrics::track_in_flight(&state.listen_addr);
+    let resp = handle_proxy_request(state, remote, req).await;
+    helpers::hold_until_body_end(resp, in_flight)
+}
+
+#[inline]
+async fn handle_proxy_request(
+    mut state: AppState,
+    remote: SocketAddr,
+    req: Request<Body>,
+) -> Response {
+    state.rule = state.rule_slot.load_full();
     let method = req.method().clone();
     let uri = req.uri().clone();
+    let active_id = req.extensions().get::<connections::ActiveId>().copied();
 
     let ctx = RequestContext::new(remote, req.headers(), &method, &uri);
+    if let Some(id) = active_id {
+        connections::set_client_ip(id, &ctx.client_ip);
+    }
     let t_guard = std::time::Instant::now();
     let GuardOutcome {
         route,
@@ -88,6 +120,7 @@ pub(crate) async fn proxy_handler(
 
     let t_prepare = std::time::Instant::now();
     let request::PreparedProxyRequest {
+        upstream,
         target,
         req_body_size,
         outbound_headers_snapshot,
@@ -97,16 +130,25 @@ pub(crate) async fn proxy_handler(
         Err(resp) => return resp,
     };
     let prepare_ms = t_prepare.elapsed().as_secs_f64() * 1000.0;
+    if let Some(id) = active_id {
+        connections::set_upstream(id, &target);
+    }
 
     let client = if route.follow_redirects {
         state.client_follow.clone()
     } else {
         state.client_nofollow.clone()
     };
+    let upstream_in_flight = upstream_health::track_in_flight(&upstream);
     let t_upstream = std::time::Instant::now();
     let resp = match client.execute(upstream_req).await {
-        Ok(r) => r,
+        Ok(r) => {
+            upstream_health::record_http_success(&upstream);
+            r
+        }
         Err(e) => {
+            crate::metrics::record_upstream_failure(&state.listen_addr);
+            upstream_health::record_http_failure(&upstream, &e.to_string());
             return (
                 StatusCode::BAD_GATEWAY,
                 format!("upstream request failed: {e}"),
@@ -116,7 +158,7 @@ pub(crate) async fn proxy_handler(
     };
     let upstream_ms = t_upstream.elapsed().as_secs_f64() * 1000.0;
 
-    handle_upstream_response(
+    let resp = handle_upstream_response(
         &state,
         route,
         &ctx,
@@ -133,7 +175,8 @@ pub(crate) async fn proxy_handler(
             upstream_ms,
         },
     )
-    .await
+    .await;
+    helpers::hold_until_body_end(resp, upstream_in_flight)
 }
 
 fn is_websocket_upgrade(method: &Method, headers: &HeaderMap) -> bool {
@@ -194,13 +237,13 @@ async fn handle_websocket_upgrade(
             .into_response()
     });
 
-    let target_upstream = match target_upstream {
+    let picked_upstream = match target_upstream {
         Ok(v) => v,
         Err(resp) => return resp,
     };
 
     let final_uri = request::rewrite_uri(route, &ctx.uri);
-    let target_upstream = request::select_upstream_url(state, &target_upstream);
+    let target_upstream = request::select_upstream_url(state, &picked_upstream);
     let target_url = match upstream::build_upstream_url(
         &ws_upstream_url(&target_upstream),
         route.path.as_deref(),
@@ -210,7 +253,12 @@ async fn handle_websocket_upgrade(
         Ok(u) => u,
         Err(e) => {
             let status = StatusCode::BAD_GATEWAY;
-            push_log_lazy(&state.app, || format_access_log(&*state.listen_addr, ctx, status));
+            push_log_lazy(
+                &state.app,
+                LogLevel::for_status(status.as_u16()),
+                Some(&*state.listen_addr),
+                || format_access_log(&*state.listen_addr, ctx, status),
+            );
             enqueue_request_log(
                 &*state.listen_addr,
                 ctx,
@@ -324,8 +372,12 @@ async fn handle_websocket_upgrade(
 
     let t_prepare = std::time::Instant::now();
     let (upstream_ws, response) = match connect_async(request).await {
-        Ok((ws, resp)) => (ws, resp),
+        Ok((ws, resp)) => {
+            upstream_health::record_http_success(&picked_upstream);
+            (ws, resp)
+        }
         Err(tokio_tungstenite::tungstenite::Error::Http(resp)) => {
+            upstream_health::record_http_success(&picked_upstream);
             return log_and_build_ws_error_response(
                 state,
                 ctx,
@@ -337,8 +389,15 @@ async fn handle_websocket_upgrade(
             );
         }
         Err(e) => {
+            crate::metrics::record_upstream_failure(&state.listen_addr);
+            upstream_health::record_http_failure(&picked_upstream, &e.to_string());
             let status = StatusCode::BAD_GATEWAY;
-            push_log_lazy(&state.app, || format_access_log(&*state.listen_addr, ctx, status));
+            push_log_lazy(
+                &state.app,
+                LogLevel::for_status(status.as_u16()),
+                Some(&*state.listen_addr),
+                || format_access_log(&*state.listen_addr, ctx, status),
+            );
             enqueue_request_log(
                 &*state.listen_addr,
                 ctx,
@@ -356,7 +415,12 @@ async fn handle_websocket_upgrade(
     let upstream_ms = t_prepare.elapsed().as_secs_f64() * 1000.0;
 
     let status = StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
-    push_log_lazy(&state.app, || format_access_log(&*state.listen_addr, ctx, status));
+    push_log_lazy(
+        &state.app,
+        LogLevel::for_status(status.as_u16()),
+        Some(&*state.listen_addr),
+        || format_access_log(&*state.listen_addr, ctx, status),
+    );
     enqueue_request_log(
         &*state.listen_addr,
         ctx,
@@ -376,9 +440,25 @@ async fn handle_websocket_upgrade(
     }
 
     let app = state.app.clone();
+    let in_flight = state.in_flight.clone();
+    let active = connections::track(connections::ActiveEntry::ws(
+        &state.listen_addr,
+        &ctx.client_ip,
+        &ctx.path,
+        &target_url,
+    ));
+    let upstream_in_flight = upstream_health::track_in_flight(&picked_upstream);
     tokio::spawn(async move {
-        if let Err(e) = proxy_websocket_streams(on_upgrade, upstream_ws).await {
-            send_log_with_app(&app, format!("WS proxy tunnel error: {e}"));
+        let _active = active;
+        let _upstream_in_flight = upstream_in_flight;
+        let tunnel = in_flight.track(proxy_websocket_streams(on_upgrade, upstream_ws));
+        if let Some(Err(e)) = tunnel.await {
+            send_log_with_app(
+                &app,
+                LogLevel::Error,
+                None,
+                format!("WS proxy tunnel error: {e}"),
+            );
         }
     });
 
@@ -395,7 +475,12 @@ fn log_and_build_ws_error_response<B>(
     response: HttpResponse<B>,
 ) -> Response {
     let status = StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
-    push_log_lazy(&state.app, || format_access_log(&*state.listen_addr, ctx, status));
+    push_log_lazy(
+        &state.app,
+        LogLevel::for_status(status.as_u16()),
+        Some(&*state.listen_addr),
+        || format_access_log(&*state.listen_addr, ctx, status),
+    );
     enqueue_request_log(
         &*state.listen_addr,
         ctx,
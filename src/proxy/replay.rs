@@ -0,0 +1,273 @@
+use anyhow::{anyhow, Context, Result};
+use axum::http::{header, HeaderMap, HeaderValue, Method, Uri};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use super::listen::parse_listen_addr;
+use super::logging::push_log_line;
+use super::matching::match_route;
+use super::request::{build_outbound_headers, rewrite_uri, substitute_server_port};
+use super::server::build_upstream_clients;
+use super::upstream::{build_upstream_url, pick_upstream_smooth};
+use crate::{config, metrics};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayTarget {
+    /// 发往日志中记录的那个上游
+    SameUpstream,
+    /// 按当前路由配置重新选择上游
+    Route,
+}
+
+impl ReplayTarget {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "same_upstream" => Ok(Self::SameUpstream),
+            "route" => Ok(Self::Route),
+            other => Err(anyhow!("unsupported replay target: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayResult {
+    pub log_id: i64,
+    pub method: String,
+    pub url: String,
+    pub status_code: u16,
+    pub latency_ms: f64,
+    pub headers: Vec<(String, String)>,
+    pub recorded: bool,
+}
+
+/// 用日志中的方法、路径、Host、UA、Referer 重建请求并按代理相同的方式发往上游。
+/// 日志不保存查询串与请求体，重放请求不带这两部分
+pub async fn replay_logged_request(
+    log_id: i64,
+    target: ReplayTarget,
+    confirm: bool,
+    record: bool,
+) -> Result<ReplayResult> {
+    let log = metrics::get_request_log(log_id)
+        .await?
+        .ok_or_else(|| anyhow!("request log {} not found", log_id))?;
+
+    let method = Method::from_bytes(log.method.as_bytes())
+        .with_context(|| format!("invalid method in request log: {}", log.method))?;
+    if !method.is_idempotent() && !confirm {
+        return Err(anyhow!(
+            "{} is not idempotent; confirm is required to replay it",
+            method
+        ));
+    }
+
+    let cfg = config::get_config();
+    let rule = cfg
+        .rules
+        .iter()
+        .find(|r| rule_listens_on(r, &log.listen_addr))
+        .ok_or_else(|| anyhow!("no listen rule for {} in current config", log.listen_addr))?;
+    let (listen, _) = parse_listen_addr(&log.listen_addr)?;
+
+    let inbound_headers = replay_inbound_headers(&log);
+    let uri: Uri = log
+        .request_path
+        .parse()
+        .with_context(|| format!("invalid request path in log: {}", log.request_path))?;
+
+    let matched = if log.matched_route_id.is_empty() {
+        match_route(
+            &rule.routes,
+            &log.request_host,
+            &log.request_path,
+            &method,
+            &inbound_headers,
+        )
+        .0
+    } else {
+        rule.routes
+            .iter()
+            .find(|r| r.id.as_deref() == Some(log.matched_route_id.as_str()))
+    };
+    let route =
+        matched.ok_or_else(|| anyhow!("route for request log {} no longer exists", log_id))?;
+
+    let upstream_base = match target {
+        ReplayTarget::SameUpstream => {
+            logged_upstream_base(&route.upstreams, &log.upstream, listen.port())
+                .ok_or_else(|| anyhow!("upstream {} is no longer configured", log.upstream))?
+        }
+        ReplayTarget::Route => pick_upstream_smooth(route)
+            .map(|u| substitute_server_port(&u, listen.port()))
+            .ok_or_else(|| anyhow!("route has no upstream configured"))?,
+    };
+    let url = build_upstream_url(
+        &upstream_base,
+        route.path.as_deref(),
+        route.proxy_pass_path.as_deref(),
+        &rewrite_uri(route, &uri),
+    )?;
+
+    let remote = SocketAddr::new(
+        log.remote_ip
+            .parse()
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        0,
+    );
+    let outbound_headers = build_outbound_headers(rule, route, &remote, &inbound_headers);
+
+    let (client_follow, client_nofollow) =
+        build_upstream_clients(&config::UpstreamClientSettings::for_rule(&cfg, rule))?;
+    let client = if route.follow_redirects {
+        client_follow
+    } else {
+        client_nofollow
+    };
+
+    let started = std::time::Instant::now();
+    let resp = client
+        .request(method.clone(), url.as_str())
+        .headers(outbound_headers)
+        .send()
+        .await
+        .with_context(|| format!("upstream request failed: {}", url))?;
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    let status_code = resp.status().as_u16();
+    let headers = resp
+        .headers()
+        .iter()
+        .map(|(k, v)| {
+            (
+                k.as_str().to_string(),
+                v.to_str().unwrap_or("[invalid utf8]").to_string(),
+            )
+        })
+        .collect();
+
+    if record {
+        metrics::try_enqueue_request_log(metrics::RequestLogInsert {
+            timestamp: chrono::Utc::now().timestamp(),
+            listen_addr: log.listen_addr.clone(),
+            client_ip: log.client_ip.clone(),
+            remote_ip: log.remote_ip.clone(),
+            method: log.method.clone(),
+            request_path: log.request_path.clone(),
+            request_host: log.request_host.clone(),
+            status_code: status_code as i32,
+            upstream: url.clone(),
+            latency_ms,
+            guard_ms: 0.0,
+            prepare_ms: 0.0,
+            upstream_ms: latency_ms,
+            user_agent: log.user_agent.clone(),
+            referer: log.referer.clone(),
+            matched_route_id: log.matched_route_id.clone(),
+            bytes_sent: 0,
+            bytes_received: 0,
+            country: String::new(),
+            asn: String::new(),
+            sample_rate: 1.0,
+        });
+    }
+
+    push_log_line(format!(
+        "Replayed request log #{}: {} {} -> {} ({:.1}ms)",
+        log_id, method, url, status_code, latency_ms
+    ));
+
+    Ok(ReplayResult {
+        log_id,
+        method: method.to_string(),
+        url,
+        status_code,
+        latency_ms,
+        headers,
+        recorded: record,
+    })
+}
+
+fn rule_listens_on(rule: &config::ListenRule, listen_addr: &str) -> bool {
+    if rule.listen_addrs.iter().any(|a| a.trim() == listen_addr) {
+        return true;
+    }
+    rule.listen_addrs.iter().all(|a| a.trim().is_empty()) && rule.listen_addr.trim() == listen_addr
+}
+
+/// 日志中的 Host/UA/Referer 还原为入站请求头；"-" 表示原请求没有该头
+fn replay_inbound_headers(log: &metrics::RequestLog) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in [
+        (header::HOST, &log.request_host),
+        (header::USER_AGENT, &log.user_agent),
+        (header::REFERER, &log.referer),
+    ] {
+        if value.is_empty() || value == "-" {
+            continue;
+        }
+        if let Ok(v) = HeaderValue::from_str(value) {
+            headers.insert(name, v);
+        }
+    }
+    if log.client_ip != log.remote_ip {
+        if let Ok(v) = HeaderValue::from_str(&log.client_ip) {
+            headers.insert("x-forwarded-for", v);
+        }
+    }
+    headers
+}
+
+/// 日志里记录的是完整目标 URL，找出它对应的已配置上游地址
+fn logged_upstream_base(
+    upstreams: &[config::Upstream],
+    logged_target: &str,
+    server_port: u16,
+) -> Option<String> {
+    upstreams
+        .iter()
+        .map(|u| substitute_server_port(&u.url, server_port))
+        .filter(|base| {
+            let base = base.trim_end_matches('/');
+            logged_target
+                .strip_prefix(base)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '?']))
+        })
+        .max_by_key(|base| base.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::logged_upstream_base;
+    use crate::config::Upstream;
+
+    #[test]
+    fn logged_upstream_base_picks_longest_configured_prefix() {
+        let upstreams: Vec<Upstream> = [
+            "http://127.0.0.1:8080",
+            "http://127.0.0.1:8080/v2/",
+            "http://127.0.0.1:$server_port",
+        ]
+        .into_iter()
+        .map(|url| Upstream {
+            url: url.into(),
+            weight: 1,
+        })
+        .collect();
+
+        assert_eq!(
+            logged_upstream_base(&upstreams, "http://127.0.0.1:8080/v2/users", 443).as_deref(),
+            Some("http://127.0.0.1:8080/v2/")
+        );
+        assert_eq!(
+            logged_upstream_base(&upstreams, "http://127.0.0.1:8080/api", 443).as_deref(),
+            Some("http://127.0.0.1:8080")
+        );
+        assert_eq!(
+            logged_upstream_base(&upstreams, "http://127.0.0.1:443/", 443).as_deref(),
+            Some("http://127.0.0.1:443")
+        );
+        assert_eq!(
+            logged_upstream_base(&upstreams, "http://127.0.0.1:80801/", 443),
+            None
+        );
+    }
+}
@@ -9,10 +9,12 @@ use super::context::{enqueue_request_log, format_access_log, RequestContext};
 use super::helpers::{
     cached_regex, content_type_allowed, expand_proxy_header_value, is_hop_header_fast,
 };
-use super::logging::{push_log_lazy, SKIP_HEADERS};
+use super::logging::{push_log_lazy, LogLevel, SKIP_HEADERS};
 use super::{upstream::build_upstream_url, AppState};
 
 pub(crate) struct PreparedProxyRequest {
+    /// 选中的上游（配置中的原始地址）
+    pub upstream: String,
     pub target: String,
     pub req_body_size: Option<usize>,
     pub outbound_headers_snapshot: HeaderMap,
@@ -42,8 +44,12 @@ pub fn rewrite_uri(route: &crate::config::Route, uri: &Uri) -> Uri {
 }
 
 pub fn select_upstream_url(state: &AppState, upstream_url: &str) -> String {
+    substitute_server_port(upstream_url, state.server_port)
+}
+
+pub fn substitute_server_port(upstream_url: &str, server_port: u16) -> String {
     if upstream_url.contains("$server_port") {
-        upstream_url.replace("$server_port", &state.server_port.to_string())
+        upstream_url.replace("$server_port", &server_port.to_string())
     } else {
         upstream_url.to_string()
     }
@@ -58,13 +64,8 @@ pub async fn prepare_proxy_request(
     req: Request<Body>,
 ) -> Result<PreparedProxyRequest, Response> {
     let node = &*state.listen_addr;
-    let has_enabled_response_body_replace = route
-        .response_body_replace
-        .as_ref()
-        .map(|rules| rules.iter().any(|r| r.enabled))
-        .unwrap_or(false);
 
-    let mut upstream_url = super::upstream::pick_upstream_smooth(route).ok_or_else(|| {
+    let picked_upstream = super::upstream::pick_upstream_smooth(route).ok_or_else(|| {
         (
             StatusCode::NOT_FOUND,
             "No static directory or upstream configured",
@@ -73,7 +74,7 @@ pub async fn prepare_proxy_request(
     })?;
 
     let final_uri = rewrite_uri(route, &ctx.uri);
-    upstream_url = select_upstream_url(state, &upstream_url);
+    let upstream_url = select_upstream_url(state, &picked_upstream);
 
     let target: String = match build_upstream_url(
         &upstream_url,
@@ -84,7 +85,12 @@ pub async fn prepare_proxy_request(
         Ok(u) => u,
         Err(e) => {
             let status = StatusCode::BAD_GATEWAY;
-            push_log_lazy(&state.app, || format_access_log(node, ctx, status));
+            push_log_lazy(
+                &state.app,
+                LogLevel::for_status(status.as_u16()),
+                Some(node),
+                || format_access_log(node, ctx, status),
+            );
             enqueue_request_log(
                 node,
                 ctx,
@@ -176,6 +182,48 @@ pub async fn prepare_proxy_request(
         (reqwest::Body::from(final_bytes), Some(len))
     };
 
+    let final_headers = build_outbound_headers(&state.rule, route, remote, &inbound_headers);
+
+    let mut builder = client.request(method_up, target.clone());
+    builder = builder.body(reqwest_body);
+
+    let mut upstream_req = match builder.build() {
+        Ok(r) => r,
+        Err(e) => {
+            return Err((
+                StatusCode::BAD_GATEWAY,
+                format!("build upstream request failed: {e}"),
+            )
+                .into_response());
+        }
+    };
+
+    upstream_req.headers_mut().clear();
+    upstream_req.headers_mut().extend(final_headers);
+    let outbound_headers_snapshot = upstream_req.headers().clone();
+
+    Ok(PreparedProxyRequest {
+        upstream: picked_upstream,
+        target,
+        req_body_size,
+        outbound_headers_snapshot,
+        upstream_req,
+    })
+}
+
+/// 由入站请求头组装发往上游的请求头（逐跳头过滤、X-Forwarded-*、路由 set/remove_headers）
+pub(crate) fn build_outbound_headers(
+    rule: &crate::config::ListenRule,
+    route: &crate::config::Route,
+    remote: &SocketAddr,
+    inbound_headers: &HeaderMap,
+) -> HeaderMap {
+    let has_enabled_response_body_replace = route
+        .response_body_replace
+        .as_ref()
+        .map(|rules| rules.iter().any(|r| r.enabled))
+        .unwrap_or(false);
+
     let mut final_headers = HeaderMap::with_capacity(inbound_headers.len() + 8);
     for (k, v) in inbound_headers.iter() {
         if SKIP_HEADERS.contains(k) || is_hop_header_fast(k.as_str()) {
@@ -212,11 +260,7 @@ pub async fn prepare_proxy_request(
 
     final_headers.insert(
         HeaderName::from_static("x-forwarded-proto"),
-        HeaderValue::from_static(if state.rule.ssl_enable {
-            "https"
-        } else {
-            "http"
-        }),
+        HeaderValue::from_static(if rule.ssl_enable { "https" } else { "http" }),
     );
 
     if has_enabled_response_body_replace {
@@ -241,8 +285,7 @@ pub async fn prepare_proxy_request(
                 continue;
             }
 
-            let expanded =
-                expand_proxy_header_value(v, remote, &inbound_headers, state.rule.ssl_enable);
+            let expanded = expand_proxy_header_value(v, remote, inbound_headers, rule.ssl_enable);
 
             let name = match HeaderName::from_bytes(key.as_bytes()) {
                 Ok(n) => n,
@@ -263,7 +306,7 @@ pub async fn prepare_proxy_request(
         }
     }
 
-    if state.rule.basic_auth_enable && !state.rule.basic_auth_forward_header {
+    if rule.basic_auth_enable && !rule.basic_auth_forward_header {
         final_headers.remove(axum::http::header::AUTHORIZATION);
     }
 
@@ -279,30 +322,7 @@ pub async fn prepare_proxy_request(
         }
     }
 
-    let mut builder = client.request(method_up, target.clone());
-    builder = builder.body(reqwest_body);
-
-    let mut upstream_req = match builder.build() {
-        Ok(r) => r,
-        Err(e) => {
-            return Err((
-                StatusCode::BAD_GATEWAY,
-                format!("build upstream request failed: {e}"),
-            )
-                .into_response());
-        }
-    };
-
-    upstream_req.headers_mut().clear();
-    upstream_req.headers_mut().extend(final_headers);
-    let outbound_headers_snapshot = upstream_req.headers().clone();
-
-    Ok(PreparedProxyRequest {
-        target,
-        req_body_size,
-        outbound_headers_snapshot,
-        upstream_req,
-    })
+    final_headers
 }
 
 #[cfg(test)]
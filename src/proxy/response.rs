@@ -1,17 +1,25 @@
 use super::context::{
-    enqueue_request_log, format_access_log, format_headers_for_log, RequestContext,
+    format_headers_for_log, request_log_record, PendingRequestLog, RequestContext,
 };
 use super::helpers::{content_type_allowed, is_hop_header_fast};
-use super::logging::push_log_lazy;
+use super::logging::LogLevel;
 use super::{cached_regex, send_log_with_app, AppState};
 use crate::config;
+use crate::rate_limit::{BandwidthLimiter, BANDWIDTH_LIMITERS};
 use axum::body::Bytes;
 use axum::{
     body::Body,
     http::{HeaderMap, HeaderName, StatusCode},
     response::{IntoResponse, Response},
 };
+use futures_util::{Stream, StreamExt};
 use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// 带宽限速时缓冲响应体的切片大小
+const THROTTLE_CHUNK_SIZE: usize = 16 * 1024;
 
 pub(crate) struct ProxyResponseMeta<'a> {
     pub target: &'a str,
@@ -35,16 +43,9 @@ pub async fn handle_upstream_response(
     let node = &*state.listen_addr;
     let status = resp.status();
     let response_headers = resp.headers().clone();
+    let out_status = StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
 
-    push_log_lazy(&state.app, || {
-        format_access_log(
-            node,
-            ctx,
-            StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY),
-        )
-    });
-
-    enqueue_request_log(
+    let mut record = request_log_record(
         node,
         ctx,
         meta.remote,
@@ -55,9 +56,21 @@ pub async fn handle_upstream_response(
         meta.prepare_ms,
         meta.upstream_ms,
     );
+    // 流式转发时请求体未缓冲，退回 Content-Length
+    record.bytes_received = meta
+        .req_body_size
+        .map(|n| n as i64)
+        .or_else(|| {
+            meta.inbound_headers
+                .get(axum::http::header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<i64>().ok())
+        })
+        .unwrap_or(0);
+    let request_log = PendingRequestLog::new(node, ctx, out_status, record);
 
     let mut out = Response::new(Body::empty());
-    *out.status_mut() = StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    *out.status_mut() = out_status;
 
     for (k, v) in response_headers.iter() {
         if is_hop_header_fast(k.as_str()) {
@@ -78,13 +91,22 @@ pub async fn handle_upstream_response(
         }
     }
 
+    let bandwidth = BANDWIDTH_LIMITERS.get(node).map(|l| l.value().clone());
+
     if state.stream_proxy {
         let stream = resp.bytes_stream();
-        *out.body_mut() = Body::from_stream(stream);
+        *out.body_mut() = match bandwidth {
+            Some(limiter) => Body::from_stream(CountedBody::new(
+                throttle_body_stream(limiter, ctx.client_ip.clone(), stream),
+                request_log,
+            )),
+            None => Body::from_stream(CountedBody::new(stream, request_log)),
+        };
     } else {
         let bytes = match resp.bytes().await {
             Ok(b) => b,
             Err(e) => {
+                request_log.finish(0);
                 return (
                     StatusCode::BAD_GATEWAY,
                     format!("read upstream body failed: {e}"),
@@ -94,6 +116,7 @@ pub async fn handle_upstream_response(
         };
 
         if state.max_response_body_size > 0 && bytes.len() > state.max_response_body_size {
+            request_log.finish(0);
             return (
                 StatusCode::BAD_GATEWAY,
                 format!(
@@ -105,7 +128,20 @@ pub async fn handle_upstream_response(
         }
 
         let final_bytes = apply_response_body_replace(route, &response_headers, bytes);
-        *out.body_mut() = Body::from(final_bytes);
+        request_log.finish(final_bytes.len() as u64);
+        *out.body_mut() = match bandwidth {
+            Some(limiter) => {
+                let chunks = split_bytes(final_bytes, THROTTLE_CHUNK_SIZE);
+                Body::from_stream(throttle_body_stream(
+                    limiter,
+                    ctx.client_ip.clone(),
+                    futures_util::stream::iter(
+                        chunks.into_iter().map(Ok::<_, std::convert::Infallible>),
+                    ),
+                ))
+            }
+            None => Body::from(final_bytes),
+        };
     }
 
     if !status.is_success() {
@@ -115,6 +151,8 @@ pub async fn handle_upstream_response(
 
         send_log_with_app(
             &state.app,
+            LogLevel::Error,
+            Some(&*state.listen_addr),
             format!(
                 "Reverse proxy error (IN): {} {} -> {} status={} | inbound_headers=[{}] | phase_ms[guard={:.2},prepare={:.2},upstream={:.2},total={:.2}]",
                 ctx.method.as_str(),
@@ -129,7 +167,7 @@ pub async fn handle_upstream_response(
             ),
         );
 
-        send_log_with_app(&state.app, format!(
+        send_log_with_app(&state.app, LogLevel::Error, Some(&*state.listen_addr), format!(
             "Reverse proxy error (OUT): {} {} -> {} status={} | outbound_headers=[{}] | req_body_size={} | phase_ms[guard={:.2},prepare={:.2},upstream={:.2},total={:.2}]",
             ctx.method.as_str(),
             ctx.uri,
@@ -146,6 +184,121 @@ pub async fn handle_upstream_response(
         ));
     }
 
+    if state.slow_request_threshold_ms > 0.0 {
+        log_slow_request(state, ctx, &meta, status.as_u16());
+    }
+
+    out
+}
+
+fn log_slow_request(
+    state: &AppState,
+    ctx: &RequestContext,
+    meta: &ProxyResponseMeta<'_>,
+    status: u16,
+) {
+    let total_ms = ctx.elapsed_ms();
+    if total_ms < state.slow_request_threshold_ms {
+        return;
+    }
+    crate::metrics::record_slow_request(&state.listen_addr);
+    send_log_with_app(
+        &state.app,
+        LogLevel::Warn,
+        Some(&*state.listen_addr),
+        format!(
+            "[SLOW] {} {} -> {} status={} | phase_ms[guard={:.2},prepare={:.2},upstream={:.2},total={:.2}]",
+            ctx.method.as_str(),
+            ctx.uri.path(),
+            meta.target,
+            status,
+            meta.guard_ms,
+            meta.prepare_ms,
+            meta.upstream_ms,
+            total_ms,
+        ),
+    );
+}
+
+/// 按客户端 IP 对响应体限速：超出预算时延迟发送分片，而不是报错
+fn throttle_body_stream<S, E>(
+    limiter: Arc<BandwidthLimiter>,
+    client_ip: Arc<str>,
+    stream: S,
+) -> impl Stream<Item = Result<Bytes, E>> + Send + 'static
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Send + 'static,
+{
+    stream.then(move |chunk| {
+        let limiter = limiter.clone();
+        let client_ip = client_ip.clone();
+        async move {
+            if let Ok(bytes) = &chunk {
+                let wait = limiter.reserve(&client_ip, bytes.len());
+                if !wait.is_zero() {
+                    tokio::time::sleep(wait).await;
+                }
+            }
+            chunk
+        }
+    })
+}
+
+/// 统计实际发给客户端的响应体字节数；流结束或被提前丢弃（客户端断开）时写入请求日志
+struct CountedBody<S> {
+    inner: Pin<Box<S>>,
+    sent: u64,
+    log: Option<PendingRequestLog>,
+}
+
+impl<S> CountedBody<S> {
+    fn new(inner: S, log: PendingRequestLog) -> Self {
+        Self {
+            inner: Box::pin(inner),
+            sent: 0,
+            log: Some(log),
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(log) = self.log.take() {
+            log.finish(self.sent);
+        }
+    }
+}
+
+impl<S, E> Stream for CountedBody<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let polled = self.inner.as_mut().poll_next(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(bytes))) => self.sent += bytes.len() as u64,
+            Poll::Ready(None) => self.finish(),
+            _ => {}
+        }
+        polled
+    }
+}
+
+impl<S> Drop for CountedBody<S> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn split_bytes(mut bytes: Bytes, chunk_size: usize) -> Vec<Bytes> {
+    let mut out = Vec::with_capacity(bytes.len() / chunk_size + 1);
+    while bytes.len() > chunk_size {
+        out.push(bytes.split_to(chunk_size));
+    }
+    if !bytes.is_empty() {
+        out.push(bytes);
+    }
     out
 }
 
@@ -205,10 +358,12 @@ fn apply_response_body_replace(
 
 #[cfg(test)]
 mod tests {
-    use super::apply_response_body_replace;
+    use super::{apply_response_body_replace, split_bytes, CountedBody};
     use crate::config::{BodyReplaceRule, Route, Upstream};
+    use crate::proxy::context::{request_log_record, PendingRequestLog, RequestContext};
     use axum::body::Bytes;
-    use axum::http::{HeaderMap, HeaderValue};
+    use axum::http::{HeaderMap, HeaderValue, Method, StatusCode};
+    use futures_util::StreamExt;
 
     fn sample_route() -> Route {
         Route {
@@ -309,4 +464,51 @@ mod tests {
         let out = apply_response_body_replace(&route, &HeaderMap::new(), raw.clone());
         assert_eq!(out, raw);
     }
+
+    #[test]
+    fn split_bytes_preserves_content_and_caps_chunk_size() {
+        let chunks = split_bytes(Bytes::from(vec![7u8; 10]), 4);
+        assert_eq!(
+            chunks.iter().map(|c| c.len()).collect::<Vec<_>>(),
+            vec![4, 4, 2]
+        );
+        assert!(split_bytes(Bytes::new(), 4).is_empty());
+    }
+
+    #[tokio::test]
+    async fn counted_body_passes_chunks_through_and_counts_bytes() {
+        let remote = "127.0.0.1:40000".parse().unwrap();
+        let ctx = RequestContext::new(
+            remote,
+            &HeaderMap::new(),
+            &Method::GET,
+            &"/download".parse().unwrap(),
+        );
+        let record = request_log_record(
+            "127.0.0.1:8080",
+            &ctx,
+            &remote,
+            StatusCode::OK,
+            "http://backend",
+            "",
+            0.0,
+            0.0,
+            0.0,
+        );
+        let log = PendingRequestLog::new("127.0.0.1:8080", &ctx, StatusCode::OK, record);
+        let chunks = vec![
+            Ok::<_, std::convert::Infallible>(Bytes::from("abc")),
+            Ok(Bytes::from("de")),
+        ];
+        let mut body = CountedBody::new(futures_util::stream::iter(chunks), log);
+
+        let mut received = Vec::new();
+        while let Some(chunk) = body.next().await {
+            received.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(received, b"abcde");
+        assert_eq!(body.sent, 5);
+        // 流结束时即写入日志，drop 时不会重复写入
+        assert!(body.log.is_none());
+    }
 }
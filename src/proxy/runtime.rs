@@ -1,63 +1,100 @@
-use anyhow::Result;
-use tauri::Emitter;
-use tracing::{error, info};
-
-use super::lifecycle::{Phase, ServerHandle, PROXY_STATE};
-use super::listen::precheck_rule;
-use super::logging::{init_log_task, send_log, send_log_with_app, LOG_TX};
+use anyhow::{anyhow, Result};
+use arc_swap::ArcSwap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::async_runtime::JoinHandle;
+use tracing::{error, info, warn};
+
+use super::lifecycle::{
+    DrainReport, InFlight, ListenerEvent, ListenerKey, ListenerState, ListenerStatus, Phase,
+    ServerHandle, StartSession, PROXY_STATE,
+};
+use super::listen::{parse_listen_addr, precheck_rule};
+use super::logging::{init_log_task, send_log, send_log_with_app, LogLevel, LOG_TX};
 use super::server::start_rule_server;
 use super::{stream_proxy, ws_proxy};
+use crate::app_events::AppEvents;
 use crate::config;
 
-pub fn start_server(app: tauri::AppHandle) -> Result<()> {
+/// drain 时长之外，再等待监听任务退出的上限
+const STOP_JOIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// 任务退出后等待旧端口可重新绑定的上限
+const PORT_RELEASE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 串行化重启，避免托盘与界面同时触发时交错停止/启动
+static RESTART_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+pub fn start_server(app: AppEvents) -> Result<()> {
+    start_server_inner(app, "stopped")
+}
+
+/// 在 stop_for_restart 之后启动：监听就绪前保持 "restarting" 状态，界面不会闪回已停止
+pub fn start_server_after_restart(app: AppEvents) -> Result<()> {
+    start_server_inner(app, "restarting")
+}
+
+/// 重启服务：停止并等待监听真正退出、端口可重新绑定后再启动
+pub async fn restart_server(app: AppEvents) -> Result<()> {
+    let _guard = RESTART_LOCK.lock().await;
+    stop_for_restart(&app, true).await;
+    start_server_after_restart(app)
+}
+
+fn start_server_inner(app: AppEvents, starting_status: &str) -> Result<()> {
     init_log_task(app.clone());
 
     let cfg = config::get_config();
 
-    send_log("[WS] Listener startup");
+    // 启动任何监听前先检查端口冲突，否则只会在后绑定的一方看到 "Address already in use"
+    if let Some(conflict) = crate::config_check::port_conflicts(&cfg).into_iter().next() {
+        send_log(
+            LogLevel::Error,
+            None,
+            format!("Failed to start: {conflict}"),
+        );
+        return Err(conflict.into());
+    }
+
+    send_log(LogLevel::Info, None, "[WS] Listener startup");
     if !cfg.ws_proxy_enabled {
-        send_log("[WS] Disabled");
+        send_log(LogLevel::Info, None, "[WS] Disabled");
     } else if let Err(e) = ws_proxy::start_ws_servers(app.clone()) {
-        send_log(format!("[WS] Failed to start listener: {e}"));
+        send_log(
+            LogLevel::Error,
+            None,
+            format!("[WS] Failed to start listener: {e}"),
+        );
     }
 
     {
-        send_log("[STREAM] Listener startup");
+        send_log(LogLevel::Info, None, "[STREAM] Listener startup");
 
         let stream_cfg = cfg.stream.clone();
         let app2 = app.clone();
         if !stream_cfg.enabled {
-            send_log("[STREAM] Disabled");
-        } else {
-            tauri::async_runtime::spawn(async move {
-                if let Err(e) = stream_proxy::start_stream_servers(app2.clone(), &stream_cfg).await
-                {
-                    send_log_with_app(&app2, format!("[STREAM] Failed to start listener: {e}"));
-                }
-            });
+            send_log(LogLevel::Info, None, "[STREAM] Disabled");
         }
+        // 禁用时也要调用：重载场景下会关闭此前保留的监听
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = stream_proxy::start_stream_servers(app2.clone(), &stream_cfg).await {
+                send_log_with_app(
+                    &app2,
+                    LogLevel::Error,
+                    None,
+                    format!("[STREAM] Failed to start listener: {e}"),
+                );
+            }
+        });
     }
 
-    send_log("[HTTP] Listener startup");
+    send_log(LogLevel::Info, None, "[HTTP] Listener startup");
 
     let rules: Vec<_> = cfg.rules.into_iter().filter(|r| r.enabled).collect();
 
-    let expected: usize = rules
-        .iter()
-        .map(|r| {
-            let n = r
-                .listen_addrs
-                .iter()
-                .map(|s| s.trim())
-                .filter(|s| !s.is_empty())
-                .count();
-            if n == 0 {
-                1
-            } else {
-                n
-            }
-        })
-        .sum();
+    let expected: usize = rules.iter().map(|r| rule_listen_addrs(r).len()).sum();
 
     let generation = {
         let mut state = PROXY_STATE.lock();
@@ -68,119 +105,41 @@ pub fn start_server(app: tauri::AppHandle) -> Result<()> {
             state.phase = Phase::Stopped;
             drop(state);
             let _ = app.emit("status", "stopped");
-            send_log("No listen rules configured; service remains stopped");
+            send_log(
+                LogLevel::Warn,
+                None,
+                "No listen rules configured; service remains stopped",
+            );
             return Ok(());
         }
         state.phase = Phase::Starting;
-        state.expected = expected;
-        state.started = 0;
-        state.generation = state.generation.wrapping_add(1);
-        state.generation
+        state.failed.clear();
+        state.session = StartSession {
+            expected,
+            partial_allowed: cfg.partial_start_allowed,
+            ..StartSession::new(state.session.generation.wrapping_add(1))
+        };
+        state.session.generation
     };
 
-    let _ = app.emit("status", "stopped");
+    let _ = app.emit("status", starting_status);
 
-    let mut handles = Vec::new();
+    let mut handles = BTreeMap::new();
 
     for rule in rules {
-        let addrs: Vec<String> = {
-            let mut v: Vec<String> = rule
-                .listen_addrs
-                .iter()
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty())
-                .collect();
-            if v.is_empty() {
-                v.push(rule.listen_addr.clone());
-            }
-            v
-        };
-
-        for listen_addr in addrs {
-            let app_handle = app.clone();
-            let rule_clone = rule.clone();
-            let listen_addr_clone = listen_addr.clone();
-            let gen = generation;
-            let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
-
-            let handle = tauri::async_runtime::spawn(async move {
-                if let Err(e) = precheck_rule(&rule_clone, &listen_addr_clone).await {
-                    error!("Failed to start listener({listen_addr_clone}): {e}");
-                    send_log(format!(
-                        "Failed to start listener({listen_addr_clone}): {e}"
-                    ));
-
-                    let payload = super::RuleStartErrorPayload {
-                        listen_addr: listen_addr_clone.clone(),
-                        error: e.to_string(),
-                    };
-                    let _ = app_handle.emit("server-start-error", payload);
-                    crate::alerting::notify_server_start_error(
-                        &app_handle,
-                        &listen_addr_clone,
-                        &e.to_string(),
-                    );
-
-                    {
-                        let mut state = PROXY_STATE.lock();
-                        if state.generation == gen {
-                            state.phase = Phase::Failed;
-                        }
-                    }
-                    let _ = app_handle.emit("status", "stopped");
-                    return;
-                }
-
-                let transition_to_running = {
-                    let mut state = PROXY_STATE.lock();
-                    if state.generation != gen {
-                        return;
-                    }
-                    state.started += 1;
-                    if matches!(state.phase, Phase::Starting) && state.started == state.expected {
-                        state.phase = Phase::Running;
-                        true
-                    } else {
-                        false
-                    }
-                };
-
-                if transition_to_running {
-                    let _ = app_handle.emit("status", "running");
-                }
-
-                match start_rule_server(
-                    app_handle.clone(),
-                    rule_clone,
-                    listen_addr_clone.clone(),
-                    shutdown_rx,
-                )
-                .await
-                {
-                    Ok(_) => {}
-                    Err(e) => {
-                        error!("Failed to serve on {listen_addr_clone}: {e}");
-                        send_log_with_app(
-                            &app_handle,
-                            format!("Failed to serve on {listen_addr_clone}: {e}"),
-                        );
-                    }
-                }
-            });
-
-            handles.push(ServerHandle {
-                handle,
-                shutdown_tx,
-            });
+        for listen_addr in rule_listen_addrs(&rule) {
+            let key = listener_key(&rule, &listen_addr);
+            let handle = spawn_listener(app.clone(), rule.clone(), listen_addr, generation, true);
+            handles.insert(key, handle);
         }
     }
 
     {
         let mut state = PROXY_STATE.lock();
-        if state.generation == generation {
+        if state.session.generation == generation {
             state.handles = handles;
         } else {
-            for h in handles {
+            for h in handles.into_values() {
                 h.abort();
             }
         }
@@ -190,52 +149,637 @@ pub fn start_server(app: tauri::AppHandle) -> Result<()> {
     Ok(())
 }
 
-pub fn stop_server(app: tauri::AppHandle) -> Result<()> {
-    ws_proxy::stop_ws_servers();
-    *LOG_TX.write() = None;
+/// 停止时等待进行中连接结束的上限
+fn drain_timeout() -> Duration {
+    Duration::from_secs(config::get_config().drain_timeout_secs)
+}
 
-    tauri::async_runtime::spawn(async {
-        stream_proxy::stop_stream_servers().await;
+/// 停止服务但不等待连接 drain（托盘、窗口关闭等），drain 在后台完成后记录统计
+pub fn stop_server(app: AppEvents) -> Result<()> {
+    let drain = drain_timeout();
+    let tasks = stop_listeners(&app, "stopped", drain);
+    tauri::async_runtime::spawn(async move {
+        drain_listeners(&app, tasks, drain, true).await;
     });
+    Ok(())
+}
 
-    let handles = {
+/// 停止服务并等待连接 drain 完成，返回正常结束与被强制断开的连接数
+pub async fn stop_server_gracefully(app: AppEvents) -> DrainReport {
+    let drain = drain_timeout();
+    let tasks = stop_listeners(&app, "stopped", drain);
+    drain_listeners(&app, tasks, drain, true).await
+}
+
+/// 重启前停止：等待 HTTP/WS 监听 drain 完成、任务退出并确认旧端口可重新绑定，状态为 "restarting"。
+/// stop_stream 为 false 时 stream 监听保留，由随后的启动按差异更新
+pub async fn stop_for_restart(app: &AppEvents, stop_stream: bool) {
+    let addrs = listener_socket_addrs(&config::get_config());
+    let drain = drain_timeout();
+    let tasks = stop_listeners(app, "restarting", drain);
+    wait_for_listeners_exit(app, tasks, drain, stop_stream, &addrs).await;
+}
+
+/// 按差异重载前停止：只停止给定 id 的 HTTP 规则和给定地址的 WS 监听，等待其退出并释放端口，
+/// 其余监听保持运行。需在新配置生效前调用
+pub async fn stop_listeners_for_reload(
+    app: &AppEvents,
+    listen_rule_ids: &[String],
+    ws_listen_addrs: &[String],
+) {
+    let drain = drain_timeout();
+    let mut tasks = Vec::new();
+    let mut raw_addrs = Vec::new();
+    for id in listen_rule_ids {
+        for ((_, addr), handle) in take_rule_handles(id) {
+            tasks.push(handle.shutdown(drain));
+            log_listener_stopped(app, &addr);
+            raw_addrs.push(addr);
+        }
+    }
+    if !ws_listen_addrs.is_empty() {
+        tasks.extend(ws_proxy::stop_ws_listeners(ws_listen_addrs, drain));
+        raw_addrs.extend_from_slice(ws_listen_addrs);
+    }
+
+    let addrs: Vec<SocketAddr> = raw_addrs
+        .iter()
+        .filter_map(|s| parse_listen_addr(s).ok().map(|(addr, _)| addr))
+        .collect();
+    wait_for_listeners_exit(app, tasks, drain, false, &addrs).await;
+}
+
+/// 按差异重载后启动：启动变更的 HTTP 规则和 WS 规则。
+/// 没有任何 HTTP 监听时与整体启动一致，状态回到已停止
+pub fn start_listeners_after_reload(
+    app: &AppEvents,
+    rules: &[config::ListenRule],
+    ws_rules: &[ws_proxy::WsListenRule],
+) {
+    ws_proxy::start_ws_rules(app, ws_rules);
+
+    for rule in rules {
+        spawn_rule_listeners(app, rule);
+    }
+
+    let idle = {
         let mut state = PROXY_STATE.lock();
-        state.phase = Phase::Stopped;
-        state.generation = state.generation.wrapping_add(1);
-        state.expected = 0;
-        state.started = 0;
-        std::mem::take(&mut state.handles)
+        let idle = state.handles.is_empty();
+        if idle {
+            state.phase = Phase::Stopped;
+        }
+        idle
+    };
+    if idle {
+        let _ = app.emit("status", "stopped");
+        send_log_with_app(
+            app,
+            LogLevel::Warn,
+            None,
+            "No listen rules configured; service remains stopped",
+        );
+    }
+}
+
+/// 等待已停止的监听 drain 完成（stop_stream 时同时 drain stream 会话），记录并返回连接统计
+async fn drain_listeners(
+    app: &AppEvents,
+    tasks: Vec<JoinHandle<DrainReport>>,
+    drain: Duration,
+    stop_stream: bool,
+) -> DrainReport {
+    let stream = async {
+        if stop_stream {
+            stream_proxy::stop_stream_servers(app, drain).await
+        } else {
+            DrainReport::default()
+        }
+    };
+    let joined = tokio::time::timeout(
+        drain + STOP_JOIN_TIMEOUT,
+        futures_util::future::join(futures_util::future::join_all(tasks), stream),
+    )
+    .await;
+    let Ok((reports, mut total)) = joined else {
+        warn!("Timed out waiting for listener tasks to exit");
+        return DrainReport::default();
     };
+    for report in reports.into_iter().flatten() {
+        total += report;
+    }
+
+    send_log_with_app(
+        app,
+        LogLevel::Info,
+        None,
+        format!(
+            "Listeners stopped: {} connections drained, {} cut after {}s drain timeout",
+            total.drained,
+            total.cut,
+            drain.as_secs()
+        ),
+    );
+    total
+}
+
+/// 等待已停止的监听 drain 完成、任务退出，并确认旧端口可重新绑定
+async fn wait_for_listeners_exit(
+    app: &AppEvents,
+    tasks: Vec<JoinHandle<DrainReport>>,
+    drain: Duration,
+    stop_stream: bool,
+    addrs: &[SocketAddr],
+) {
+    drain_listeners(app, tasks, drain, stop_stream).await;
+
+    let busy = wait_for_ports_released(addrs, PORT_RELEASE_TIMEOUT).await;
+    if !busy.is_empty() {
+        let busy = busy
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        send_log_with_app(
+            app,
+            LogLevel::Warn,
+            None,
+            format!("Ports still in use after stop, starting anyway: {busy}"),
+        );
+    }
+}
+
+/// 已启用的 HTTP 与 WS 监听地址
+fn listener_socket_addrs(cfg: &config::Config) -> Vec<SocketAddr> {
+    let mut raw: Vec<String> = Vec::new();
+    for rule in cfg.rules.iter().filter(|r| r.enabled) {
+        raw.extend(rule_listen_addrs(rule));
+    }
+    raw.extend(ws_listen_addrs(cfg));
+    raw.iter()
+        .filter_map(|s| parse_listen_addr(s).ok().map(|(addr, _)| addr))
+        .collect()
+}
+
+/// 已启用的 WS 监听地址
+fn ws_listen_addrs(cfg: &config::Config) -> Vec<String> {
+    if !cfg.ws_proxy_enabled {
+        return Vec::new();
+    }
+    cfg.ws_proxy
+        .iter()
+        .flatten()
+        .filter(|r| r.enabled)
+        .map(|r| r.listen_addr.clone())
+        .collect()
+}
 
-    for handle in handles {
-        handle.abort();
+/// 轮询直到所有地址都能重新绑定，返回超时后仍被占用的地址
+async fn wait_for_ports_released(addrs: &[SocketAddr], timeout: Duration) -> Vec<SocketAddr> {
+    let deadline = Instant::now() + timeout;
+    let mut busy: Vec<SocketAddr> = addrs.to_vec();
+    loop {
+        busy.retain(|addr| std::net::TcpListener::bind(addr).is_err());
+        if busy.is_empty() || Instant::now() >= deadline {
+            return busy;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
     }
+}
+
+/// 停止 HTTP 与 WS 监听，返回各监听的 drain 任务
+fn stop_listeners(app: &AppEvents, status: &str, drain: Duration) -> Vec<JoinHandle<DrainReport>> {
+    let mut tasks = ws_proxy::stop_ws_servers(drain);
+    *LOG_TX.write() = None;
+
+    let handles = {
+        let mut state = PROXY_STATE.lock();
+        state.failed.clear();
+        state.reset(Phase::Stopped)
+    };
 
-    let _ = app.emit("status", "stopped");
+    tasks.extend(handles.into_values().map(|h| h.shutdown(drain)));
+
+    let _ = app.emit("status", status);
 
     let cfg = config::get_config();
     for r in &cfg.rules {
-        let addrs: Vec<String> = {
-            let mut v: Vec<String> = r
-                .listen_addrs
-                .iter()
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty())
-                .collect();
-            if v.is_empty() {
-                v.push(r.listen_addr.clone());
+        for addr in rule_listen_addrs(r) {
+            log_listener_stopped(app, &addr);
+        }
+    }
+
+    info!("Proxy server stopped");
+    tasks
+}
+
+/// 规则实际使用的监听地址：listen_addrs 为空时回退到 listen_addr
+pub fn rule_listen_addrs(rule: &config::ListenRule) -> Vec<String> {
+    let addrs: Vec<String> = rule
+        .listen_addrs
+        .iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    if addrs.is_empty() {
+        vec![rule.listen_addr.clone()]
+    } else {
+        addrs
+    }
+}
+
+fn listener_key(rule: &config::ListenRule, listen_addr: &str) -> ListenerKey {
+    (rule.id.clone().unwrap_or_default(), listen_addr.to_string())
+}
+
+fn log_listener_stopped(app: &AppEvents, addr: &str) {
+    send_log_with_app(
+        app,
+        LogLevel::Info,
+        Some(addr),
+        format!("[HTTP NODE {}] Server stopped", addr),
+    );
+}
+
+/// 启动单个监听任务。initial 为 true 表示整体启动的一部分，计入启动进度，预检失败按 partial_start_allowed
+/// 回滚或保留其余监听；单独启动规则时失败只影响该规则
+fn spawn_listener(
+    app: AppEvents,
+    rule: config::ListenRule,
+    listen_addr: String,
+    generation: u64,
+    initial: bool,
+) -> ServerHandle {
+    let key = listener_key(&rule, &listen_addr);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+    let rule_slot = Arc::new(ArcSwap::from_pointee(rule.clone()));
+    let task_rule_slot = rule_slot.clone();
+    let in_flight = InFlight::new();
+    let task_in_flight = in_flight.clone();
+
+    let handle = tauri::async_runtime::spawn(async move {
+        let rule_id = rule.id.clone();
+        let bound = match precheck_rule(&rule, &listen_addr).await {
+            Ok(bound) => bound,
+            Err(e) => {
+                error!("Failed to start listener({listen_addr}): {e}");
+                send_log(
+                    LogLevel::Error,
+                    Some(listen_addr.as_str()),
+                    format!("Failed to start listener({listen_addr}): {e}"),
+                );
+
+                let payload = super::RuleStartErrorPayload {
+                    listen_addr: listen_addr.clone(),
+                    error: e.to_string(),
+                };
+                let _ = app.emit("server-start-error", payload);
+                crate::alerting::notify_server_start_error(&app, &listen_addr, &e.to_string());
+                ListenerEvent::new("http", listen_addr.clone(), rule_id)
+                    .failed(&app, e.to_string());
+
+                record_start_failure(&app, key, generation, initial, e.to_string());
+                return;
+            }
+        };
+
+        let settled = {
+            let mut state = PROXY_STATE.lock();
+            if state.session.generation != generation {
+                return;
+            }
+            state.failed.remove(&key);
+            state
+                .running
+                .insert(key.clone(), chrono::Utc::now().timestamp());
+            if initial {
+                state.session.started += 1;
+                state.settle_start()
+            } else {
+                None
+            }
+        };
+
+        ListenerEvent::new("http", listen_addr.clone(), rule_id.clone()).started(&app);
+        if settled.is_some() {
+            emit_status(&app, "running");
+        }
+
+        let result = start_rule_server(
+            app.clone(),
+            rule,
+            listen_addr.clone(),
+            bound,
+            task_rule_slot,
+            task_in_flight,
+            shutdown_rx,
+        )
+        .await;
+
+        // 停止时会先从 running 中移除；仍在其中说明监听是自行退出的，记为失败
+        let error = {
+            let mut state = PROXY_STATE.lock();
+            let exited =
+                state.session.generation == generation && state.running.remove(&key).is_some();
+            let error = match result {
+                Err(e) => Some(e.to_string()),
+                Ok(()) if exited => Some("Listener exited unexpectedly".to_string()),
+                Ok(()) => None,
+            };
+            if let (true, Some(error)) = (exited, &error) {
+                state.failed.insert(key, error.clone());
             }
-            v
+            error
         };
-        for addr in addrs {
-            send_log_with_app(&app, format!("[HTTP NODE {}] Server stopped", addr));
+
+        let event = ListenerEvent::new("http", listen_addr.clone(), rule_id);
+        match error {
+            Some(e) => {
+                error!("Failed to serve on {listen_addr}: {e}");
+                send_log_with_app(
+                    &app,
+                    LogLevel::Error,
+                    Some(listen_addr.as_str()),
+                    format!("Failed to serve on {listen_addr}: {e}"),
+                );
+                event.failed(&app, e);
+            }
+            None => event.stopped(&app),
         }
+    });
+
+    ServerHandle {
+        handle,
+        shutdown_tx,
+        rule: rule_slot,
+        in_flight,
     }
+}
 
-    info!("Proxy server stopped");
+/// 记录监听预检失败。整体启动中失败时，允许部分启动则计入启动进度，全部失败或不允许部分启动时回滚
+fn record_start_failure(
+    app: &AppEvents,
+    key: ListenerKey,
+    generation: u64,
+    initial: bool,
+    error: String,
+) {
+    let mut state = PROXY_STATE.lock();
+    if state.session.generation != generation {
+        return;
+    }
+    state.failed.insert(key, error);
+    if !initial {
+        return;
+    }
+    state.session.failed += 1;
+    if state.session.partial_allowed {
+        match state.settle_start() {
+            None => return,
+            Some(Phase::Running) => {
+                drop(state);
+                send_log_with_app(
+                    app,
+                    LogLevel::Warn,
+                    None,
+                    "Started with some listeners failed (partial_start_allowed)",
+                );
+                emit_status(app, "running");
+                return;
+            }
+            Some(_) => {}
+        }
+    }
+    let handles = state.reset(Phase::Failed);
+    drop(state);
+    roll_back_start(app, handles);
+}
+
+/// 整体启动失败：停止本次已启动的 HTTP、WS 与 stream 监听，不留下部分运行的状态
+fn roll_back_start(app: &AppEvents, handles: BTreeMap<ListenerKey, ServerHandle>) {
+    let mut tasks = ws_proxy::stop_ws_servers(Duration::ZERO);
+    for ((_, addr), handle) in handles {
+        tasks.push(handle.shutdown(Duration::ZERO));
+        log_listener_stopped(app, &addr);
+    }
+    send_log_with_app(
+        app,
+        LogLevel::Error,
+        None,
+        "Startup failed; stopped all listeners started so far",
+    );
+    emit_status(app, "stopped");
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        drain_listeners(&app, tasks, Duration::ZERO, true).await;
+    });
+}
+
+/// 发送整体状态，并附带各规则的运行与失败情况
+fn emit_status(app: &AppEvents, status: &str) {
+    let _ = app.emit("status", status);
+    let _ = app.emit("listen-rule-status", listen_rule_statuses());
+}
+
+/// 把规则的新内容交给正在运行的监听：不重启监听，已有连接不受影响，新请求使用新规则。
+/// 返回更新的监听数，服务未运行或规则未在监听时为 0
+pub fn update_listen_rule(rule: &config::ListenRule) -> Result<usize> {
+    let resolved = Arc::new(crate::secrets::resolve_rule(rule)?);
+    let state = PROXY_STATE.lock();
+    Ok(swap_rule_snapshots(&state.handles, resolved))
+}
+
+fn swap_rule_snapshots(
+    handles: &BTreeMap<ListenerKey, ServerHandle>,
+    rule: Arc<config::ListenRule>,
+) -> usize {
+    let id = rule.id.clone().unwrap_or_default();
+    handles
+        .iter()
+        .filter(|((rule_id, _), _)| *rule_id == id)
+        .map(|(_, handle)| handle.rule.store(rule.clone()))
+        .count()
+}
+
+/// 启用并启动单条监听规则，其他监听不受影响；服务未运行时只更新配置，下次启动生效
+pub fn start_listen_rule(app: AppEvents, listen_rule_id: &str) -> Result<()> {
+    let rule = set_rule_enabled(listen_rule_id, true)?;
+    if is_effectively_running() {
+        spawn_rule_listeners(&app, &rule);
+    }
     Ok(())
 }
 
+/// 在当前运行中启动规则的各个监听地址，仍在运行的监听跳过
+fn spawn_rule_listeners(app: &AppEvents, rule: &config::ListenRule) {
+    for listen_addr in rule_listen_addrs(rule) {
+        let key = listener_key(rule, &listen_addr);
+        let mut state = PROXY_STATE.lock();
+        // 预检失败或已退出的任务允许重新启动
+        if state
+            .handles
+            .get(&key)
+            .is_some_and(|h| !h.handle.inner().is_finished())
+        {
+            continue;
+        }
+        let handle = spawn_listener(
+            app.clone(),
+            rule.clone(),
+            listen_addr,
+            state.session.generation,
+            false,
+        );
+        state.handles.insert(key, handle);
+    }
+}
+
+/// 停用并停止单条监听规则，等待其连接 drain 完成、监听任务退出
+pub async fn stop_listen_rule(app: AppEvents, listen_rule_id: &str) -> Result<()> {
+    set_rule_enabled(listen_rule_id, false)?;
+
+    let drain = drain_timeout();
+    let stopped = take_rule_handles(listen_rule_id);
+    let mut tasks = Vec::with_capacity(stopped.len());
+    for ((_, addr), handle) in stopped {
+        tasks.push(handle.shutdown(drain));
+        log_listener_stopped(&app, &addr);
+    }
+    drain_listeners(&app, tasks, drain, false).await;
+    Ok(())
+}
+
+/// 从运行状态中取出规则的全部监听句柄
+fn take_rule_handles(listen_rule_id: &str) -> Vec<(ListenerKey, ServerHandle)> {
+    let mut state = PROXY_STATE.lock();
+    state.failed.retain(|(id, _), _| id != listen_rule_id);
+    let keys: Vec<ListenerKey> = state
+        .handles
+        .keys()
+        .filter(|(id, _)| id == listen_rule_id)
+        .cloned()
+        .collect();
+    keys.into_iter()
+        .filter_map(|key| {
+            state.running.remove(&key);
+            state.handles.remove(&key).map(|h| (key, h))
+        })
+        .collect()
+}
+
+/// 更新并保存规则的 enabled，返回更新后的规则
+fn set_rule_enabled(listen_rule_id: &str, enabled: bool) -> Result<config::ListenRule> {
+    let mut cfg = config::get_config();
+    let rule = cfg
+        .rules
+        .iter_mut()
+        .find(|r| r.id.as_deref() == Some(listen_rule_id))
+        .ok_or_else(|| anyhow!("Listen rule not found: {listen_rule_id}"))?;
+    rule.enabled = enabled;
+    let rule = rule.clone();
+
+    config::set_config(cfg);
+    config::save_config()?;
+    Ok(rule)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ListenRuleStatus {
+    pub listen_rule_id: String,
+    pub enabled: bool,
+    /// 已通过预检并正在监听的地址
+    pub running_addrs: Vec<String>,
+    pub running: bool,
+    /// 启动失败的地址及原因
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub failed_addrs: BTreeMap<String, String>,
+}
+
+/// 各监听规则的运行状态
+pub fn listen_rule_statuses() -> Vec<ListenRuleStatus> {
+    let cfg = config::get_config();
+    let state = PROXY_STATE.lock();
+    cfg.rules
+        .iter()
+        .map(|rule| {
+            let id = rule.id.clone().unwrap_or_default();
+            let addrs = rule_listen_addrs(rule);
+            let running_addrs: Vec<String> = addrs
+                .iter()
+                .filter(|addr| state.running.contains_key(&(id.clone(), (*addr).clone())))
+                .cloned()
+                .collect();
+            let failed_addrs = addrs
+                .iter()
+                .filter_map(|addr| {
+                    let error = state.failed.get(&(id.clone(), addr.clone()))?;
+                    Some((addr.clone(), error.clone()))
+                })
+                .collect();
+            ListenRuleStatus {
+                running: !running_addrs.is_empty() && running_addrs.len() == addrs.len(),
+                listen_rule_id: id,
+                enabled: rule.enabled,
+                running_addrs,
+                failed_addrs,
+            }
+        })
+        .collect()
+}
+
+/// 全部监听的运行状态：HTTP 按规则的每个地址，WS 按规则，stream 按服务；包含未启用或未运行的条目
+pub fn listener_statuses() -> Vec<ListenerStatus> {
+    let cfg = config::get_config();
+    let mut statuses = http_listener_statuses(&cfg);
+    statuses.extend(ws_proxy::ws_listener_statuses(&cfg));
+    statuses.extend(stream_proxy::stream_listener_statuses(&cfg.stream));
+    statuses
+}
+
+fn http_listener_statuses(cfg: &config::Config) -> Vec<ListenerStatus> {
+    let state = PROXY_STATE.lock();
+    let mut statuses = Vec::new();
+    for rule in &cfg.rules {
+        for listen_addr in rule_listen_addrs(rule) {
+            let key = listener_key(rule, &listen_addr);
+            let mut status = ListenerStatus::stopped("http", listen_addr);
+            status.rule_id = rule.id.clone();
+            if let Some(started_at) = state.running.get(&key) {
+                let active = state.handles.get(&key).map_or(0, |h| h.in_flight.count());
+                status = status.running(*started_at, active as u64);
+            } else if let Some(error) = state.failed.get(&key) {
+                status = status.failed(error.clone());
+            }
+            statuses.push(status);
+        }
+    }
+    statuses
+}
+
+/// (正在运行的监听数, 应在运行的监听数)，失败的监听计入后者
+pub fn listener_counts() -> (usize, usize) {
+    let statuses = listener_statuses();
+    let running = statuses
+        .iter()
+        .filter(|s| s.state == ListenerState::Running)
+        .count();
+    let total = statuses
+        .iter()
+        .filter(|s| s.state != ListenerState::Stopped)
+        .count();
+    (running, total)
+}
+
+/// 整体阶段：running / starting / stopped / failed
+pub fn phase_name() -> &'static str {
+    match PROXY_STATE.lock().phase {
+        Phase::Stopped => "stopped",
+        Phase::Starting => "starting",
+        Phase::Running => "running",
+        Phase::Failed => "failed",
+    }
+}
+
 pub fn is_running() -> bool {
     matches!(PROXY_STATE.lock().phase, Phase::Running)
 }
@@ -243,3 +787,201 @@ pub fn is_running() -> bool {
 pub fn is_effectively_running() -> bool {
     matches!(PROXY_STATE.lock().phase, Phase::Starting | Phase::Running)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: &str, listen_addr: &str) -> config::ListenRule {
+        toml::from_str(&format!(
+            r#"
+id = "{id}"
+listen_addr = "{listen_addr}"
+ssl_enable = false
+cert_file = ""
+key_file = ""
+basic_auth_enable = false
+basic_auth_username = ""
+basic_auth_password = ""
+basic_auth_forward_header = false
+
+[[routes]]
+id = "{id}-root"
+path = "/"
+upstreams = []
+"#
+        ))
+        .unwrap()
+    }
+
+    fn handle_for(rule: &config::ListenRule) -> ServerHandle {
+        let (shutdown_tx, _) = tokio::sync::oneshot::channel();
+        ServerHandle {
+            handle: tauri::async_runtime::spawn(async {}),
+            shutdown_tx,
+            rule: Arc::new(ArcSwap::from_pointee(rule.clone())),
+            in_flight: InFlight::new(),
+        }
+    }
+
+    /// 在随机端口上启动真实监听，返回实际监听地址
+    async fn serve(rule: &config::ListenRule) -> (String, ServerHandle) {
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        let (addr_tx, addr_rx) = tokio::sync::oneshot::channel();
+        let rule_slot = Arc::new(ArcSwap::from_pointee(rule.clone()));
+        let in_flight = InFlight::new();
+        let (task_rule, task_slot, task_in_flight) =
+            (rule.clone(), rule_slot.clone(), in_flight.clone());
+        let handle = tauri::async_runtime::spawn(async move {
+            let bound = precheck_rule(&task_rule, "127.0.0.1:0").await.unwrap();
+            let listen_addr = bound.listener.local_addr().unwrap().to_string();
+            let _ = addr_tx.send(listen_addr.clone());
+            let _ = start_rule_server(
+                AppEvents::headless(),
+                task_rule,
+                listen_addr,
+                bound,
+                task_slot,
+                task_in_flight,
+                shutdown_rx,
+            )
+            .await;
+        });
+        let listen_addr = addr_rx.await.unwrap();
+        (
+            listen_addr,
+            ServerHandle {
+                handle,
+                shutdown_tx,
+                rule: rule_slot,
+                in_flight,
+            },
+        )
+    }
+
+    /// 在已建立的连接上发一个 keep-alive 请求，返回状态码与响应体
+    async fn get_on(conn: &mut tokio::net::TcpStream, path: &str) -> (u16, String) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        conn.write_all(format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        let read = async {
+            loop {
+                let mut chunk = [0u8; 4096];
+                let n = conn.read(&mut chunk).await.unwrap();
+                assert!(n > 0, "connection closed");
+                buf.extend_from_slice(&chunk[..n]);
+
+                let text = String::from_utf8_lossy(&buf).to_string();
+                let Some((head, body)) = text.split_once("\r\n\r\n") else {
+                    continue;
+                };
+                let head = head.to_ascii_lowercase();
+                let status = head[9..12].parse().unwrap();
+                let length = head
+                    .lines()
+                    .find_map(|l| l.strip_prefix("content-length:"))
+                    .map(|v| v.trim().parse::<usize>().unwrap());
+                match length {
+                    Some(len) if body.len() >= len => return (status, body[..len].to_string()),
+                    None if body.ends_with("0\r\n\r\n") => {
+                        let mut decoded = String::new();
+                        let mut rest = body;
+                        while let Some((size, tail)) = rest.split_once("\r\n") {
+                            let size = usize::from_str_radix(size.trim(), 16).unwrap();
+                            if size == 0 {
+                                break;
+                            }
+                            decoded.push_str(&tail[..size]);
+                            rest = &tail[size + 2..];
+                        }
+                        return (status, decoded);
+                    }
+                    _ => {}
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), read)
+            .await
+            .expect("response timed out")
+    }
+
+    #[tokio::test]
+    async fn route_toggle_keeps_other_listener_connections_open() {
+        let upstream = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_url = format!("http://{}", upstream.local_addr().unwrap());
+        tokio::spawn(async move {
+            let app = axum::Router::new().fallback(|| async { "ok" });
+            axum::serve(upstream, app).await.unwrap();
+        });
+
+        let mut a = rule("toggle-a", "127.0.0.1:0");
+        let mut b = rule("toggle-b", "127.0.0.1:0");
+        for r in [&mut a, &mut b] {
+            r.routes[0].upstreams = vec![config::Upstream {
+                url: upstream_url.clone(),
+                weight: 1,
+            }];
+        }
+        let (a_addr, a_handle) = serve(&a).await;
+        let (b_addr, b_handle) = serve(&b).await;
+        let a_before = a_handle.rule.load_full();
+        {
+            let mut state = PROXY_STATE.lock();
+            state.handles.insert(listener_key(&a, &a_addr), a_handle);
+            state.handles.insert(listener_key(&b, &b_addr), b_handle);
+        }
+
+        let mut a_conn = tokio::net::TcpStream::connect(&a_addr).await.unwrap();
+        let mut b_conn = tokio::net::TcpStream::connect(&b_addr).await.unwrap();
+        assert_eq!(get_on(&mut a_conn, "/").await, (200, "ok".to_string()));
+        assert_eq!(get_on(&mut b_conn, "/").await.0, 200);
+
+        // set_route_enabled 保存配置后把规则交给运行中的监听
+        let mut updated = b.clone();
+        updated.routes[0].enabled = false;
+        assert_eq!(update_listen_rule(&updated).unwrap(), 1);
+
+        // 另一条监听上已建立的连接继续可用，规则快照未被替换
+        assert_eq!(get_on(&mut a_conn, "/").await, (200, "ok".to_string()));
+        // 目标监听未重建：同一连接上的下一个请求就使用了新规则
+        assert_eq!(get_on(&mut b_conn, "/").await.0, 404);
+
+        let handles = [listener_key(&a, &a_addr), listener_key(&b, &b_addr)]
+            .map(|key| PROXY_STATE.lock().handles.remove(&key).unwrap());
+        assert!(Arc::ptr_eq(&a_before, &handles[0].rule.load_full()));
+        for handle in handles {
+            assert!(!handle.handle.inner().is_finished());
+            handle.abort();
+        }
+    }
+
+    #[test]
+    fn rule_snapshot_swap_leaves_other_listeners_untouched() {
+        let a = rule("site-a", "127.0.0.1:18080");
+        let b = rule("site-b", "127.0.0.1:18081");
+        let mut handles = BTreeMap::new();
+        for r in [&a, &b] {
+            handles.insert(listener_key(r, &r.listen_addr), handle_for(r));
+        }
+        handles.insert(listener_key(&a, "127.0.0.1:18090"), handle_for(&a));
+        let b_key = listener_key(&b, &b.listen_addr);
+        let b_before = handles[&b_key].rule.load_full();
+
+        let mut updated = a.clone();
+        updated.routes[0].enabled = false;
+        assert_eq!(swap_rule_snapshots(&handles, Arc::new(updated)), 2);
+
+        for ((id, _), handle) in &handles {
+            let current = handle.rule.load();
+            if id == "site-a" {
+                assert!(!current.routes[0].enabled);
+            } else {
+                assert!(Arc::ptr_eq(&b_before, &handle.rule.load_full()));
+                assert!(current.routes[0].enabled);
+            }
+        }
+    }
+}
@@ -1,28 +1,42 @@
 use anyhow::{anyhow, Context, Result};
-use axum::{routing::any, Router};
+use arc_swap::ArcSwap;
+use axum::{
+    extract::{connect_info::ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::any,
+    Router,
+};
 use reqwest::redirect::Policy;
 use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tower_http::compression::{CompressionLayer, CompressionLevel};
 use tracing::info;
 
-use super::listen::parse_listen_addr;
-use super::logging::send_log;
+use super::connections::{self, ActiveEntry};
+use super::helpers::hold_until_body_end;
+use super::lifecycle::InFlight;
+use super::listen::BoundListener;
+use super::logging::{send_log, LogLevel};
 use super::{healthz, proxy_handler, AppState};
+use crate::app_events::AppEvents;
 use crate::{config, rate_limit};
 
-fn build_upstream_clients(cfg: &config::Config) -> Result<(reqwest::Client, reqwest::Client)> {
+pub(crate) fn build_upstream_clients(
+    settings: &config::UpstreamClientSettings,
+) -> Result<(reqwest::Client, reqwest::Client)> {
     let client_builder = || {
         let mut builder = reqwest::Client::builder()
             .redirect(Policy::limited(10))
             .danger_accept_invalid_certs(true)
-            .pool_max_idle_per_host(cfg.upstream_pool_max_idle)
-            .pool_idle_timeout(Duration::from_secs(cfg.upstream_pool_idle_timeout_sec))
+            .pool_max_idle_per_host(settings.pool_max_idle)
+            .pool_idle_timeout(Duration::from_secs(settings.pool_idle_timeout_sec))
             .tcp_keepalive(Duration::from_secs(60))
             .tcp_nodelay(true)
-            .connect_timeout(Duration::from_millis(cfg.upstream_connect_timeout_ms))
-            .timeout(Duration::from_millis(cfg.upstream_read_timeout_ms));
+            .connect_timeout(Duration::from_millis(settings.connect_timeout_ms))
+            .timeout(Duration::from_millis(settings.read_timeout_ms));
 
-        if cfg.enable_http2 {
+        if settings.enable_http2 {
             builder = builder
                 .http2_keep_alive_interval(Duration::from_secs(10))
                 .http2_keep_alive_timeout(Duration::from_secs(20))
@@ -48,16 +62,18 @@ fn build_upstream_clients(cfg: &config::Config) -> Result<(reqwest::Client, reqw
 }
 
 fn build_app_state(
-    app: &tauri::AppHandle,
-    rule: &config::ListenRule,
+    app: &AppEvents,
+    rule_slot: Arc<ArcSwap<config::ListenRule>>,
     listen_addr: &str,
     server_port: u16,
     cfg: config::Config,
-    client_follow: reqwest::Client,
-    client_nofollow: reqwest::Client,
+    (client_follow, client_nofollow): (reqwest::Client, reqwest::Client),
+    in_flight: Arc<InFlight>,
 ) -> AppState {
     AppState {
-        rule: rule.clone(),
+        rule: rule_slot.load_full(),
+        rule_slot,
+        in_flight,
         client_follow,
         client_nofollow,
         app: app.clone(),
@@ -66,33 +82,79 @@ fn build_app_state(
         stream_proxy: cfg.stream_proxy,
         max_body_size: cfg.max_body_size,
         max_response_body_size: cfg.max_response_body_size,
+        slow_request_threshold_ms: cfg.slow_request_threshold_ms as f64,
         http_access_control_enabled: cfg.http_access_control_enabled,
-        allow_all_lan: cfg.allow_all_lan,
-        allow_all_ip: cfg.allow_all_ip,
-        whitelist: Arc::from(cfg.whitelist),
+        access_lists: crate::access_control::access_lists_slot(),
     }
 }
 
+/// 登记进行中的请求；停止超过 drain 时长仍未完成的请求直接返回 503
+async fn track_in_flight(
+    State(in_flight): State<Arc<InFlight>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    in_flight
+        .track(next.run(req))
+        .await
+        .unwrap_or_else(|| StatusCode::SERVICE_UNAVAILABLE.into_response())
+}
+
+/// 登记到活跃连接表；登记随响应体一起释放，流式响应发送完毕或客户端断开时移除
+async fn track_active(
+    State(listen_addr): State<Arc<str>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let client_ip = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(remote)| remote.ip().to_string())
+        .unwrap_or_default();
+    let guard = connections::track(ActiveEntry::http(
+        &listen_addr,
+        client_ip,
+        req.method().as_str(),
+        req.uri().path(),
+    ));
+    if let Some(id) = guard.id() {
+        req.extensions_mut().insert(id);
+    }
+    hold_until_body_end(next.run(req).await, guard)
+}
+
 pub async fn start_rule_server(
-    app: tauri::AppHandle,
+    app: AppEvents,
     rule: config::ListenRule,
     listen_addr: String,
-    shutdown_rx: tokio::sync::oneshot::Receiver<()>,
+    bound: BoundListener,
+    rule_slot: Arc<ArcSwap<config::ListenRule>>,
+    in_flight: Arc<InFlight>,
+    shutdown_rx: tokio::sync::oneshot::Receiver<Duration>,
 ) -> Result<()> {
-    let (addr, need_dual_stack) = parse_listen_addr(&listen_addr)?;
+    let BoundListener {
+        listener,
+        addr,
+        need_dual_stack,
+        tls,
+    } = bound;
     let server_port = addr.port();
 
     let cfg = crate::config::get_config();
-    let (client_follow, client_nofollow) = build_upstream_clients(&cfg)?;
+    let client_settings = config::UpstreamClientSettings::for_rule(&cfg, &rule);
+    let clients = build_upstream_clients(&client_settings)?;
+    // 运行时副本使用解析后的密码，配置本身仍保留 keyring 引用
+    let rule = crate::secrets::resolve_rule(&rule)?;
+    rule_slot.store(Arc::new(rule.clone()));
 
     let state = build_app_state(
         &app,
-        &rule,
+        rule_slot,
         &listen_addr,
         server_port,
         cfg.clone(),
-        client_follow,
-        client_nofollow,
+        clients,
+        in_flight.clone(),
     );
 
     if let Some(enabled) = rule.rate_limit_enabled {
@@ -107,6 +169,13 @@ pub async fn start_rule_server(
         }
     }
 
+    match rule.bandwidth_limit_bytes_per_sec.filter(|v| *v > 0) {
+        Some(bytes_per_sec) => {
+            rate_limit::get_bandwidth_limiter(&listen_addr, bytes_per_sec);
+        }
+        None => rate_limit::remove_bandwidth_limiter(&listen_addr),
+    }
+
     let router = Router::new().route("/healthz", any(healthz));
     let mut app_router = router.fallback(any(proxy_handler)).with_state(state);
 
@@ -130,7 +199,13 @@ pub async fn start_rule_server(
         app_router = app_router.layer(compression_layer);
     }
 
-    let app_router = app_router.into_make_service_with_connect_info::<SocketAddr>();
+    let app_router = app_router
+        .layer(middleware::from_fn_with_state(
+            Arc::<str>::from(listen_addr.as_str()),
+            track_active,
+        ))
+        .layer(middleware::from_fn_with_state(in_flight, track_in_flight))
+        .into_make_service_with_connect_info::<SocketAddr>();
 
     let routes_summary = rule
         .routes
@@ -145,76 +220,96 @@ pub async fn start_rule_server(
         .collect::<Vec<_>>()
         .join(", ");
 
-    send_log(format!(
-        "[HTTP] Listening address: {} -> {}",
-        listen_addr, addr
-    ));
-    info!("[HTTP] Listening address: {} -> {}", listen_addr, addr);
-
-    if rule.ssl_enable {
-        let tls_cfg = axum_server::tls_rustls::RustlsConfig::from_pem_file(
-            rule.cert_file.clone(),
-            rule.key_file.clone(),
-        )
-        .await
-        .with_context(|| "Failed to load TLS certificate/private key")?;
+    send_log(
+        LogLevel::Info,
+        Some(listen_addr.as_str()),
+        format!(
+            "[HTTP] Listening address: {} -> {} (upstream client: {})",
+            listen_addr, addr, client_settings
+        ),
+    );
+    info!(
+        "[HTTP] Listening address: {} -> {} (upstream client: {})",
+        listen_addr, addr, client_settings
+    );
 
-        send_log(format!("[HTTP] HTTPS enabled: {}", addr));
+    if let Some(tls_cfg) = tls {
+        send_log(
+            LogLevel::Info,
+            Some(listen_addr.as_str()),
+            format!("[HTTP] HTTPS enabled: {}", addr),
+        );
 
         if need_dual_stack && addr.is_ipv6() {
-            send_log(format!(
-                "[HTTP] Listening on IPv6 (dual-stack): {} (supports both IPv4 and IPv6)",
-                addr
-            ));
+            send_log(
+                LogLevel::Info,
+                Some(listen_addr.as_str()),
+                format!(
+                    "[HTTP] Listening on IPv6 (dual-stack): {} (supports both IPv4 and IPv6)",
+                    addr
+                ),
+            );
             info!(
                 "[HTTP] Listening on IPv6 (dual-stack): {} (supports both IPv4 and IPv6)",
                 addr
             );
         }
 
-        send_log(format!(
-            "[HTTP NODE {}] Server started | SSL: {} | Routes: [{}] | Allow all LAN: {}",
-            listen_addr, rule.ssl_enable, routes_summary, cfg.allow_all_lan
-        ));
+        send_log(
+            LogLevel::Info,
+            Some(listen_addr.as_str()),
+            format!(
+                "[HTTP NODE {}] Server started | SSL: {} | Routes: [{}] | Allow all LAN: {}",
+                listen_addr, rule.ssl_enable, routes_summary, cfg.allow_all_lan
+            ),
+        );
 
         let ax_handle = axum_server::Handle::new();
         let ax_shutdown_handle = ax_handle.clone();
         tauri::async_runtime::spawn(async move {
-            let _ = shutdown_rx.await;
+            let drain = shutdown_rx.await.unwrap_or_default();
             info!(
                 "Shutdown signal received, HTTPS service {} is stopping",
                 addr
             );
-            ax_shutdown_handle.graceful_shutdown(Some(Duration::from_secs(5)));
+            ax_shutdown_handle.graceful_shutdown(Some(drain));
         });
 
-        axum_server::bind_rustls(addr, tls_cfg)
+        axum_server::tls_rustls::from_tcp_rustls(listener.into_std()?, tls_cfg)?
             .handle(ax_handle)
             .serve(app_router)
             .await
             .map_err(|e| anyhow!("HTTPS service failed: {e}"))?;
     } else {
-        send_log(format!("[HTTP] HTTP enabled: {}", addr));
+        send_log(
+            LogLevel::Info,
+            Some(listen_addr.as_str()),
+            format!("[HTTP] HTTP enabled: {}", addr),
+        );
 
         if need_dual_stack && addr.is_ipv6() {
-            send_log(format!(
-                "[HTTP] Listening on IPv6 (dual-stack): {} (supports both IPv4 and IPv6)",
-                addr
-            ));
+            send_log(
+                LogLevel::Info,
+                Some(listen_addr.as_str()),
+                format!(
+                    "[HTTP] Listening on IPv6 (dual-stack): {} (supports both IPv4 and IPv6)",
+                    addr
+                ),
+            );
             info!(
                 "[HTTP] Listening on IPv6 (dual-stack): {} (supports both IPv4 and IPv6)",
                 addr
             );
         }
 
-        send_log(format!(
-            "[HTTP NODE {}] Server started | SSL: {} | Routes: [{}] | Allow all LAN: {}",
-            listen_addr, rule.ssl_enable, routes_summary, cfg.allow_all_lan
-        ));
-
-        use crate::network_optimizer::TcpOptimizer;
-        let optimizer = TcpOptimizer::default();
-        let listener = optimizer.optimize_listener(addr).await?;
+        send_log(
+            LogLevel::Info,
+            Some(listen_addr.as_str()),
+            format!(
+                "[HTTP NODE {}] Server started | SSL: {} | Routes: [{}] | Allow all LAN: {}",
+                listen_addr, rule.ssl_enable, routes_summary, cfg.allow_all_lan
+            ),
+        );
 
         axum::serve(listener, app_router)
             .with_graceful_shutdown(async move {
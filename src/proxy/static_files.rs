@@ -8,7 +8,7 @@ use super::context::{enqueue_request_log, format_access_log, RequestContext};
 use super::helpers::{
     cached_index_html, cached_serve_dir, check_etag_match, get_or_create_etag, is_asset_path,
 };
-use super::logging::push_log_lazy;
+use super::logging::{push_log_lazy, LogLevel};
 use super::AppState;
 
 pub async fn serve_static_owned(
@@ -39,7 +39,12 @@ pub async fn serve_static_owned(
         if let Some(etag) = get_or_create_etag(full_path) {
             if check_etag_match(request_etag.as_deref(), &etag) {
                 let status = StatusCode::NOT_MODIFIED;
-                push_log_lazy(&state.app, || format_access_log(node, ctx, status));
+                push_log_lazy(
+                    &state.app,
+                    LogLevel::for_status(status.as_u16()),
+                    Some(node),
+                    || format_access_log(node, ctx, status),
+                );
                 enqueue_request_log(
                     node,
                     ctx,
@@ -60,7 +65,12 @@ pub async fn serve_static_owned(
                 HeaderValue::from_str(&etag).unwrap_or_else(|_| HeaderValue::from_static("")),
             );
 
-            push_log_lazy(&state.app, || format_access_log(node, ctx, status));
+            push_log_lazy(
+                &state.app,
+                LogLevel::for_status(status.as_u16()),
+                Some(node),
+                || format_access_log(node, ctx, status),
+            );
             enqueue_request_log(
                 node,
                 ctx,
@@ -75,7 +85,12 @@ pub async fn serve_static_owned(
             return resp;
         }
 
-        push_log_lazy(&state.app, || format_access_log(node, ctx, status));
+        push_log_lazy(
+            &state.app,
+            LogLevel::for_status(status.as_u16()),
+            Some(node),
+            || format_access_log(node, ctx, status),
+        );
         enqueue_request_log(
             node,
             ctx,
@@ -104,7 +119,12 @@ pub async fn serve_static_owned(
             let etag = format!("\"spa-{:x}\"", bytes.len());
             if check_etag_match(request_etag.as_deref(), &etag) {
                 let status = StatusCode::NOT_MODIFIED;
-                push_log_lazy(&state.app, || format_access_log(node, ctx, status));
+                push_log_lazy(
+                    &state.app,
+                    LogLevel::for_status(status.as_u16()),
+                    Some(node),
+                    || format_access_log(node, ctx, status),
+                );
                 enqueue_request_log(
                     node,
                     ctx,
@@ -124,7 +144,12 @@ pub async fn serve_static_owned(
             );
 
             let status = StatusCode::OK;
-            push_log_lazy(&state.app, || format_access_log(node, ctx, status));
+            push_log_lazy(
+                &state.app,
+                LogLevel::for_status(status.as_u16()),
+                Some(node),
+                || format_access_log(node, ctx, status),
+            );
             enqueue_request_log(
                 node,
                 ctx,
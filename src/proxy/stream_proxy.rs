@@ -1,57 +1,460 @@
 use anyhow::{anyhow, Context, Result};
+use arc_swap::ArcSwap;
 use dashmap::DashMap;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use std::collections::hash_map::DefaultHasher;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
-use std::net::SocketAddr;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::io;
-use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{lookup_host, TcpListener, TcpStream, UdpSocket};
 use tokio::sync::mpsc;
 use tokio::time;
 
+use super::connections::{self, ActiveEntry, ActiveGuard};
+use super::lifecycle::{DrainReport, ListenerEvent, ListenerStatus};
+use super::logging::LogLevel;
+use super::matching;
+use crate::app_events::AppEvents;
 use crate::config::{StreamProxyConfig, StreamServer, StreamUpstream, StreamUpstreamServer};
+use crate::rate_limit::ByteBucket;
 use crate::{access_control, config};
 
 static STREAM_SERVERS: once_cell::sync::Lazy<RwLock<Vec<StreamServerHandle>>> =
     once_cell::sync::Lazy::new(|| RwLock::new(Vec::new()));
 
+/// 最近一次启动/重载中启动失败的监听：(监听地址, 是否 UDP) -> 原因
+static STREAM_START_ERRORS: once_cell::sync::Lazy<RwLock<HashMap<(String, bool), String>>> =
+    once_cell::sync::Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// 串行化 stream 监听的启动/重载/停止
+static STREAM_RELOAD_LOCK: once_cell::sync::Lazy<tokio::sync::Mutex<()>> =
+    once_cell::sync::Lazy::new(|| tokio::sync::Mutex::new(()));
+
+/// 未配置 drain_timeout 时，重载后旧监听上的会话最多保留的时长
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
 struct StreamServerHandle {
-    task: Option<tokio::task::JoinHandle<()>>,
-    shutdown_tx: mpsc::Sender<()>,
+    /// 启动该监听时的配置；重载时配置未变则保留监听
+    server: StreamServer,
+    stats_key: (String, bool),
+    /// 开始监听的时间（Unix 秒）
+    started_at: i64,
+    route: ListenerRoute,
+    access: Arc<RwLock<StreamAccess>>,
+    /// 监听任务，退出时返回已建立会话的 drain 任务（UDP 与无会话时为 None）
+    task: Option<tokio::task::JoinHandle<Option<RelayDrain>>>,
+    /// 发送停止信号，附带已建立会话的 drain 时长
+    shutdown_tx: mpsc::Sender<Duration>,
+}
+
+impl StreamServerHandle {
+    /// 配置未变的监听：原地替换 upstream，只影响之后的新会话
+    fn update_route(&self, config: &StreamProxyConfig) -> Result<()> {
+        match &self.route {
+            ListenerRoute::Tcp(router) => {
+                *router.write() = TcpUpstreamRouter::new(config, &self.server)?;
+            }
+            ListenerRoute::Udp(upstream) => {
+                let next = expand_upstream(find_stream_upstream(
+                    config,
+                    &self.server,
+                    &self.server.proxy_pass,
+                )?);
+                check_udp_upstream_addrs(&next)?;
+                *upstream.write() = next;
+            }
+        }
+        Ok(())
+    }
+
+    fn event(&self) -> ListenerEvent {
+        listener_event(self.stats_key.clone())
+    }
+
+    /// 停止接收新连接并等待监听 socket 关闭；已建立的 TCP 会话在 drain 内自然结束，
+    /// 返回其 drain 任务，丢弃即在后台继续
+    async fn shutdown(mut self, drain: Duration) -> Option<RelayDrain> {
+        let _ = self.shutdown_tx.send(drain).await;
+        let task = self.task.take()?;
+        time::timeout(Duration::from_secs(5), task)
+            .await
+            .ok()?
+            .ok()?
+    }
+}
+
+type RelayDrain = tokio::task::JoinHandle<DrainReport>;
+
+fn listener_event((listen_addr, udp): (String, bool)) -> ListenerEvent {
+    let mut event = ListenerEvent::new("stream", listen_addr, None);
+    event.udp = udp;
+    event
+}
+
+enum ListenerRoute {
+    Tcp(Arc<RwLock<TcpUpstreamRouter>>),
+    Udp(Arc<RwLock<StreamUpstream>>),
+}
+
+/// 访问控制/黑名单快照：stream 没有 headers，仅按 remote ip 判定；名单与 HTTP/WS 共用
+#[derive(Clone)]
+struct StreamAccess {
+    enabled: bool,
+    lists: Arc<ArcSwap<access_control::AccessLists>>,
+}
+
+impl StreamAccess {
+    fn from_config(cfg: &config::Config) -> Self {
+        Self {
+            enabled: cfg.stream_access_control_enabled,
+            lists: access_control::access_lists_slot(),
+        }
+    }
+
+    fn allows(&self, client_addr: &SocketAddr) -> bool {
+        if !self.enabled {
+            return true;
+        }
+        let lists = self.lists.load();
+        access_control::is_allowed_fast(
+            client_addr,
+            &axum::http::HeaderMap::new(),
+            lists.allow_all_lan,
+            lists.allow_all_ip,
+            &lists.whitelist,
+        )
+    }
 }
 
 #[derive(Debug, Clone)]
-struct FailState {
-    fails: u32,
+pub(crate) struct FailState {
+    pub fails: u32,
     down_until: Option<Instant>,
+    pub last_error: Option<String>,
+    /// 最近一次连接结果的时间（unix 秒）
+    pub last_check: i64,
+}
+
+/// 上游地址 -> 当前活跃 TCP 会话数（跨 stream server 汇总，用于 least_conn）
+static UPSTREAM_ACTIVE: once_cell::sync::Lazy<DashMap<String, Arc<AtomicU64>>> =
+    once_cell::sync::Lazy::new(DashMap::new);
+
+/// 活跃会话计数，drop 时自动减一
+struct UpstreamActiveGuard(Arc<AtomicU64>);
+
+impl UpstreamActiveGuard {
+    #[cfg(test)]
+    fn acquire(addr: &str) -> Self {
+        Self::try_acquire(addr, 0).expect("unlimited acquire")
+    }
+
+    /// 原子占用一个连接名额；max_conns > 0 且已满时返回 None
+    fn try_acquire(addr: &str, max_conns: u32) -> Option<Self> {
+        let counter = UPSTREAM_ACTIVE.entry(addr.to_string()).or_default().clone();
+        counter
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (max_conns == 0 || n < max_conns as u64).then_some(n + 1)
+            })
+            .ok()?;
+        Some(Self(counter))
+    }
+}
+
+impl Drop for UpstreamActiveGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub(crate) fn upstream_active(addr: &str) -> u64 {
+    UPSTREAM_ACTIVE
+        .get(addr)
+        .map(|c| c.load(Ordering::Relaxed))
+        .unwrap_or(0)
+}
+
+fn is_saturated(server: &StreamUpstreamServer) -> bool {
+    server.max_conns > 0 && upstream_active(&server.addr) >= server.max_conns as u64
+}
+
+/// 可参与选择：未被标记故障且未达到 max_conns
+fn is_available(server: &StreamUpstreamServer) -> bool {
+    !is_down(&server.addr) && !is_saturated(server)
 }
 
 // 使用 DashMap 替代 RwLock<HashMap> 以提升并发性能
 static FAIL_MAP: once_cell::sync::Lazy<DashMap<String, FailState>> =
     once_cell::sync::Lazy::new(DashMap::new);
 
+/// 流量统计（按 监听地址 + 是否 UDP），仅在停止 stream 代理时清空
+static STREAM_STATS: once_cell::sync::Lazy<DashMap<(String, bool), Arc<StreamServerStats>>> =
+    once_cell::sync::Lazy::new(DashMap::new);
+
+#[derive(Default)]
+struct StreamServerStats {
+    listen_addr: String,
+    upstream_name: String,
+    traffic: StreamTraffic,
+    /// 上游地址 -> 上游级别统计
+    upstreams: DashMap<String, Arc<StreamTraffic>>,
+    throughput: Mutex<ThroughputSample>,
+}
+
+/// 吞吐采样：两次采样间隔至少 1 秒，按字节差计算速率
+#[derive(Default)]
+struct ThroughputSample {
+    at: Option<Instant>,
+    bytes_in: u64,
+    bytes_out: u64,
+    in_per_sec: f64,
+    out_per_sec: f64,
+}
+
+/// 会话与字节计数器（原子更新）
+#[derive(Default)]
+struct StreamTraffic {
+    active: AtomicU64,
+    total: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    connect_failures: AtomicU64,
+    saturated: AtomicU64,
+}
+
+impl StreamTraffic {
+    fn snapshot(&self) -> crate::metrics::StreamTrafficStats {
+        crate::metrics::StreamTrafficStats {
+            active: self.active.load(Ordering::Relaxed),
+            total: self.total.load(Ordering::Relaxed),
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            bytes_out: self.bytes_out.load(Ordering::Relaxed),
+            connect_failures: self.connect_failures.load(Ordering::Relaxed),
+            saturated: self.saturated.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl StreamServerStats {
+    fn upstream(&self, addr: &str) -> Arc<StreamTraffic> {
+        self.upstreams.entry(addr.to_string()).or_default().clone()
+    }
+
+    /// 当前吞吐（字节/秒）：(客户端 -> 上游, 上游 -> 客户端)
+    fn throughput(&self) -> (f64, f64) {
+        let now = Instant::now();
+        let bytes_in = self.traffic.bytes_in.load(Ordering::Relaxed);
+        let bytes_out = self.traffic.bytes_out.load(Ordering::Relaxed);
+
+        let mut sample = self.throughput.lock();
+        match sample.at {
+            Some(at) if now.duration_since(at) < Duration::from_secs(1) => {}
+            Some(at) => {
+                let secs = now.duration_since(at).as_secs_f64();
+                sample.in_per_sec = bytes_in.saturating_sub(sample.bytes_in) as f64 / secs;
+                sample.out_per_sec = bytes_out.saturating_sub(sample.bytes_out) as f64 / secs;
+                sample.at = Some(now);
+                sample.bytes_in = bytes_in;
+                sample.bytes_out = bytes_out;
+            }
+            None => {
+                sample.at = Some(now);
+                sample.bytes_in = bytes_in;
+                sample.bytes_out = bytes_out;
+            }
+        }
+        (sample.in_per_sec, sample.out_per_sec)
+    }
+}
+
+fn stream_stats(listen_addr: &str, udp: bool, upstream_name: &str) -> Arc<StreamServerStats> {
+    STREAM_STATS
+        .entry((listen_addr.to_string(), udp))
+        .or_insert_with(|| {
+            Arc::new(StreamServerStats {
+                listen_addr: listen_addr.to_string(),
+                upstream_name: upstream_name.to_string(),
+                ..Default::default()
+            })
+        })
+        .clone()
+}
+
+/// 单个会话的计数入口：同时更新 stream server、上游与会话自身三级统计
+#[derive(Clone)]
+struct StreamSessionStats {
+    server: Arc<StreamServerStats>,
+    upstream: Arc<StreamTraffic>,
+    session: Arc<StreamTraffic>,
+    upstream_addr: Arc<str>,
+    /// 第几次尝试连上该上游（含重试）
+    attempts: u32,
+}
+
+impl StreamSessionStats {
+    fn new(server: Arc<StreamServerStats>, upstream_addr: &str) -> Self {
+        let upstream = server.upstream(upstream_addr);
+        Self {
+            server,
+            upstream,
+            session: Arc::default(),
+            upstream_addr: Arc::from(upstream_addr),
+            attempts: 1,
+        }
+    }
+
+    fn with_attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    fn levels(&self) -> [&StreamTraffic; 3] {
+        [&self.server.traffic, &self.upstream, &self.session]
+    }
+
+    /// 会话结束时写入 stream_logs（未启用指标数据库时由写入队列丢弃）
+    fn log_session(
+        &self,
+        listen_port: u16,
+        udp: bool,
+        client_addr: SocketAddr,
+        started: Instant,
+        reason: &str,
+    ) {
+        crate::metrics::try_enqueue_stream_log(crate::metrics::StreamLogInsert {
+            timestamp: chrono::Utc::now().timestamp(),
+            listen_port: listen_port as i32,
+            protocol: if udp { "udp" } else { "tcp" }.to_string(),
+            client_ip: client_addr.ip().to_canonical().to_string(),
+            upstream_addr: self.upstream_addr.to_string(),
+            duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+            bytes_in: self.session.bytes_in.load(Ordering::Relaxed) as i64,
+            bytes_out: self.session.bytes_out.load(Ordering::Relaxed) as i64,
+            disconnect_reason: reason.to_string(),
+            attempts: self.attempts as i32,
+        });
+        stream_conn_log(|| {
+            format!(
+                "{} session {} -> {} ended ({}): sent={}B received={}B duration={:?} attempts={}",
+                if udp { "UDP" } else { "TCP" },
+                client_addr,
+                self.upstream_addr,
+                reason,
+                self.session.bytes_in.load(Ordering::Relaxed),
+                self.session.bytes_out.load(Ordering::Relaxed),
+                started.elapsed(),
+                self.attempts
+            )
+        });
+    }
+
+    fn on_open(&self) {
+        for t in self.levels() {
+            t.total.fetch_add(1, Ordering::Relaxed);
+            t.active.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn on_close(&self) {
+        for t in self.levels() {
+            t.active.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    fn on_connect_failure(&self) {
+        for t in self.levels() {
+            t.connect_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 记录转发字节，to_upstream 为 true 表示 客户端 -> 上游
+    fn on_bytes(&self, to_upstream: bool, n: u64) {
+        for t in self.levels() {
+            if to_upstream {
+                t.bytes_in.fetch_add(n, Ordering::Relaxed);
+            } else {
+                t.bytes_out.fetch_add(n, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+pub fn stream_metrics_snapshot() -> Vec<crate::metrics::StreamMetricsItem> {
+    let mut out: Vec<crate::metrics::StreamMetricsItem> = STREAM_STATS
+        .iter()
+        .map(|entry| {
+            let (listen_addr, udp) = entry.key();
+            let stats = entry.value();
+            let mut upstreams: Vec<crate::metrics::StreamUpstreamMetricsItem> = stats
+                .upstreams
+                .iter()
+                .map(|u| crate::metrics::StreamUpstreamMetricsItem {
+                    addr: u.key().clone(),
+                    stats: u.value().snapshot(),
+                })
+                .collect();
+            upstreams.sort_unstable_by(|a, b| a.addr.cmp(&b.addr));
+            let (bytes_in_per_sec, bytes_out_per_sec) = stats.throughput();
+
+            crate::metrics::StreamMetricsItem {
+                listen_addr: listen_addr.clone(),
+                protocol: if *udp { "udp" } else { "tcp" }.to_string(),
+                upstream: stats.upstream_name.clone(),
+                stats: stats.traffic.snapshot(),
+                bytes_in_per_sec,
+                bytes_out_per_sec,
+                upstreams,
+            }
+        })
+        .collect();
+    out.sort_unstable_by(|a, b| (&a.listen_addr, &a.protocol).cmp(&(&b.listen_addr, &b.protocol)));
+    out
+}
+
 #[inline]
-fn stream_log(app: &tauri::AppHandle, message: impl Into<String>) {
-    crate::proxy::send_log_with_app(app, format!("[STREAM] {}", message.into()));
+fn stream_log(app: Option<&AppEvents>, message: impl Into<String>) {
+    let message = format!("[STREAM] {}", message.into());
+    match app {
+        Some(app) => crate::proxy::send_log_with_app(app, LogLevel::Info, None, message),
+        None => tracing::info!("{}", message),
+    }
 }
 
-fn normalize_stream_listen_addr(raw: &str) -> String {
-    let s = raw.trim();
-    if s.starts_with(':') {
-        format!("127.0.0.1{}", s)
-    } else {
-        s.to_string()
+/// 单连接事件每秒最多写入实时日志的行数，超出部分只计数，避免刷满日志缓冲
+const CONN_LOG_PER_SEC: f64 = 20.0;
+
+static CONN_LOG_BUCKET: once_cell::sync::Lazy<Mutex<ByteBucket>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(ByteBucket::new(CONN_LOG_PER_SEC, CONN_LOG_PER_SEC)));
+
+static CONN_LOG_SUPPRESSED: AtomicU64 = AtomicU64::new(0);
+
+/// 单连接事件写入实时日志，需开启 stream_log_connections
+fn stream_conn_log(message: impl FnOnce() -> String) {
+    if !config::stream_log_connections_enabled() {
+        return;
     }
+    if !CONN_LOG_BUCKET.lock().try_take(1) {
+        CONN_LOG_SUPPRESSED.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    let mut line = format!("[STREAM] {}", message());
+    let suppressed = CONN_LOG_SUPPRESSED.swap(0, Ordering::Relaxed);
+    if suppressed > 0 {
+        line.push_str(&format!(
+            " ({} earlier connection events suppressed)",
+            suppressed
+        ));
+    }
+    crate::proxy::logging::push_log_line(line);
 }
 
+/// 与 HTTP/WS 监听一致：":port" 绑定 [::]（双栈），其余按完整地址解析（支持 IPv6）
 fn parse_stream_listen_addr(raw: &str) -> Result<SocketAddr> {
-    let normalized = normalize_stream_listen_addr(raw);
-    normalized
-        .parse::<SocketAddr>()
+    super::parse_listen_addr(raw)
+        .map(|(addr, _)| addr)
         .map_err(|e| anyhow!("invalid stream listen_addr '{}': {}", raw, e))
 }
 
@@ -59,7 +462,7 @@ fn server_label(server: &StreamServer) -> String {
     if let Some(addr) = server.listen_addr.as_deref() {
         let trimmed = addr.trim();
         if !trimmed.is_empty() {
-            return normalize_stream_listen_addr(trimmed);
+            return trimmed.to_string();
         }
     }
     match server.listen_port {
@@ -68,7 +471,7 @@ fn server_label(server: &StreamServer) -> String {
     }
 }
 
-fn resolve_listen_addr(server: &StreamServer) -> Result<String> {
+pub(crate) fn resolve_listen_addr(server: &StreamServer) -> Result<String> {
     if let Some(addr) = server.listen_addr.as_deref() {
         let trimmed = addr.trim();
         if !trimmed.is_empty() {
@@ -88,64 +491,360 @@ fn resolve_listen_addr(server: &StreamServer) -> Result<String> {
     ))
 }
 
-pub async fn start_stream_servers(app: tauri::AppHandle, config: &StreamProxyConfig) -> Result<()> {
-    stop_stream_servers().await;
+pub async fn start_stream_servers(app: AppEvents, config: &StreamProxyConfig) -> Result<()> {
+    reload_stream_servers(Some(&app), config).await
+}
+
+/// 按差异应用 stream 配置：配置未变的监听保留，新增的启动，
+/// 移除或变更的停止接收新连接，已建立的会话在 drain_timeout 内自然结束
+async fn reload_stream_servers(app: Option<&AppEvents>, config: &StreamProxyConfig) -> Result<()> {
+    let _guard = STREAM_RELOAD_LOCK.lock().await;
+
+    let drain = config
+        .drain_timeout
+        .as_deref()
+        .and_then(|v| parse_duration(v).ok())
+        .unwrap_or(DEFAULT_DRAIN_TIMEOUT);
 
     if !config.enabled {
+        shutdown_all_stream_servers(app, drain).await;
         return Ok(());
     }
+    STREAM_START_ERRORS.write().clear();
 
     validate_stream_config(config)?;
+    resolve_stream_upstreams(config).await?;
+    let access = StreamAccess::from_config(&config::get_config());
 
+    let mut retired = std::mem::take(&mut *STREAM_SERVERS.write());
     let mut handles = Vec::new();
+    let mut pending = Vec::new();
+    let mut first_err = None;
+
+    for server in config.servers.iter().filter(|s| s.enabled) {
+        match retired.iter().position(|h| h.server == *server) {
+            Some(idx) => {
+                let handle = retired.swap_remove(idx);
+                if let Err(e) = handle.update_route(config) {
+                    first_err.get_or_insert(e);
+                }
+                *handle.access.write() = access.clone();
+                handles.push(handle);
+            }
+            None => pending.push(server),
+        }
+    }
 
-    for server in &config.servers {
-        if !server.enabled {
-            continue;
+    // 先关闭被移除/变更的监听释放端口，再启动新监听
+    for handle in retired {
+        stream_log(
+            app,
+            format!(
+                "Stopping listener {} (draining sessions up to {:?})",
+                handle.stats_key.0, drain
+            ),
+        );
+        STREAM_STATS.remove(&handle.stats_key);
+        let event = handle.event();
+        handle.shutdown(drain).await;
+        if let Some(app) = app {
+            event.stopped(app);
         }
+    }
 
-        let upstream = config
-            .upstreams
-            .iter()
-            .find(|u| u.name == server.proxy_pass)
-            .ok_or_else(|| {
-                anyhow!(
-                    "stream server (listen_addr={}) references missing upstream '{}'",
-                    server_label(server),
-                    server.proxy_pass
-                )
-            })?;
-
-        let connect_timeout = parse_duration(&server.proxy_connect_timeout)
-            .unwrap_or_else(|_| Duration::from_secs(300));
-        let proxy_timeout =
-            parse_duration(&server.proxy_timeout).unwrap_or_else(|_| Duration::from_secs(600));
-
-        if server.udp {
-            start_udp_server(
-                &app,
-                server,
-                upstream,
-                connect_timeout,
-                proxy_timeout,
-                &mut handles,
+    for server in pending {
+        match start_stream_server(app, config, server, &access).await {
+            Ok(handle) => {
+                if let Some(app) = app {
+                    handle.event().started(app);
+                }
+                handles.push(handle);
+            }
+            Err(e) => {
+                let error = format!("{e:#}");
+                STREAM_START_ERRORS
+                    .write()
+                    .insert(status_key(server), error.clone());
+                if let Some(app) = app {
+                    listener_event(status_key(server)).failed(app, error);
+                }
+                first_err.get_or_insert(e);
+            }
+        }
+    }
+
+    *STREAM_SERVERS.write() = handles;
+    spawn_host_refresh(config);
+    first_err.map_or(Ok(()), Err)
+}
+
+async fn start_stream_server(
+    app: Option<&AppEvents>,
+    config: &StreamProxyConfig,
+    server: &StreamServer,
+    access: &StreamAccess,
+) -> Result<StreamServerHandle> {
+    let connect_timeout =
+        parse_duration(&server.proxy_connect_timeout).unwrap_or_else(|_| Duration::from_secs(300));
+    let proxy_timeout =
+        parse_duration(&server.proxy_timeout).unwrap_or_else(|_| Duration::from_secs(600));
+    let max_session_duration = server
+        .max_session_duration
+        .as_deref()
+        .and_then(|v| parse_duration(v).ok());
+
+    if server.udp {
+        let upstream = expand_upstream(find_stream_upstream(config, server, &server.proxy_pass)?);
+        start_udp_server(app, server, &upstream, proxy_timeout, access.clone()).await
+    } else {
+        start_tcp_server(
+            app,
+            server,
+            TcpUpstreamRouter::new(config, server)?,
+            connect_timeout,
+            SessionTimeouts {
+                idle: proxy_timeout,
+                max_duration: max_session_duration,
+            },
+            access.clone(),
+        )
+        .await
+    }
+}
+
+fn status_key(server: &StreamServer) -> (String, bool) {
+    let addr = resolve_listen_addr(server).unwrap_or_else(|_| server_label(server));
+    (addr, server.udp)
+}
+
+/// 各 stream 服务的监听状态，包括未启用的服务
+pub fn stream_listener_statuses(config: &StreamProxyConfig) -> Vec<ListenerStatus> {
+    let servers = STREAM_SERVERS.read();
+    let errors = STREAM_START_ERRORS.read();
+    config
+        .servers
+        .iter()
+        .map(|server| {
+            let key = status_key(server);
+            let mut status = ListenerStatus::stopped("stream", key.0.clone());
+            status.udp = server.udp;
+            if let Some(handle) = servers.iter().find(|h| h.stats_key == key) {
+                let active = STREAM_STATS
+                    .get(&key)
+                    .map_or(0, |s| s.traffic.active.load(Ordering::Relaxed));
+                status.running(handle.started_at, active)
+            } else if let Some(error) = errors.get(&key) {
+                status.failed(error.clone())
+            } else {
+                status
+            }
+        })
+        .collect()
+}
+
+fn find_stream_upstream<'a>(
+    config: &'a StreamProxyConfig,
+    server: &StreamServer,
+    name: &str,
+) -> Result<&'a StreamUpstream> {
+    config
+        .upstreams
+        .iter()
+        .find(|u| u.name == name.trim())
+        .ok_or_else(|| {
+            anyhow!(
+                "stream server (listen_addr={}) references missing upstream '{}'",
+                server_label(server),
+                name
             )
-            .await?;
+        })
+}
+
+/// 主机名形式的上游地址 -> 最近一次解析成功的地址；重新解析失败时保留旧值
+static RESOLVED_HOSTS: once_cell::sync::Lazy<DashMap<String, Arc<[SocketAddr]>>> =
+    once_cell::sync::Lazy::new(DashMap::new);
+
+static HOST_REFRESH_TASK: once_cell::sync::Lazy<Mutex<Option<tokio::task::JoinHandle<()>>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(None));
+
+const HOST_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 配置中所有非 ip:port 形式的上游地址（去重）
+fn stream_upstream_hosts(cfg: &StreamProxyConfig) -> Vec<String> {
+    let hosts: HashSet<String> = cfg
+        .upstreams
+        .iter()
+        .flat_map(|u| u.servers.iter())
+        .map(|s| s.addr.trim())
+        .filter(|a| !a.is_empty() && a.parse::<SocketAddr>().is_err())
+        .map(str::to_string)
+        .collect();
+    let mut hosts: Vec<String> = hosts.into_iter().collect();
+    hosts.sort();
+    hosts
+}
+
+async fn resolve_host(host: &str) -> Result<Arc<[SocketAddr]>> {
+    let mut addrs: Vec<SocketAddr> = time::timeout(Duration::from_secs(5), lookup_host(host))
+        .await
+        .map_err(|_| anyhow!("lookup timed out"))??
+        .collect();
+    addrs.sort();
+    addrs.dedup();
+    if addrs.is_empty() {
+        return Err(anyhow!("no addresses found"));
+    }
+    Ok(addrs.into())
+}
+
+/// 解析所有主机名形式的上游地址并写入缓存，任一主机解析失败即返回错误
+pub async fn resolve_stream_upstreams(cfg: &StreamProxyConfig) -> Result<()> {
+    for host in stream_upstream_hosts(cfg) {
+        let addrs = resolve_host(&host)
+            .await
+            .with_context(|| format!("failed to resolve stream upstream host '{}'", host))?;
+        RESOLVED_HOSTS.insert(host, addrs);
+    }
+    Ok(())
+}
+
+/// 主机名按解析结果展开为多个成员（每个 A/AAAA 记录一个），沿用原服务器的权重等参数
+fn expand_upstream(upstream: &StreamUpstream) -> StreamUpstream {
+    let mut expanded = upstream.clone();
+    expanded.servers = upstream
+        .servers
+        .iter()
+        .flat_map(|s| match RESOLVED_HOSTS.get(s.addr.trim()) {
+            Some(addrs) => addrs
+                .iter()
+                .map(|a| StreamUpstreamServer {
+                    addr: a.to_string(),
+                    ..s.clone()
+                })
+                .collect(),
+            None => vec![s.clone()],
+        })
+        .collect();
+    expanded
+}
+
+/// 重新解析主机名，返回是否有地址发生变化；失败时保留上一次的结果
+async fn refresh_hosts(hosts: &[String]) -> bool {
+    let mut changed = false;
+    for host in hosts {
+        match resolve_host(host).await {
+            Ok(addrs) => {
+                let same = RESOLVED_HOSTS
+                    .get(host)
+                    .is_some_and(|old| old.value()[..] == addrs[..]);
+                if !same {
+                    tracing::info!("Stream upstream host {} resolved to {:?}", host, addrs);
+                    RESOLVED_HOSTS.insert(host.clone(), addrs);
+                    changed = true;
+                }
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to re-resolve stream upstream host {}: {} (keeping last good addresses)",
+                    host,
+                    e
+                );
+            }
+        }
+    }
+    changed
+}
+
+/// 按固定间隔重新解析上游主机名，地址变化后更新各监听的 upstream（只影响新会话）
+fn spawn_host_refresh(config: &StreamProxyConfig) {
+    let mut slot = HOST_REFRESH_TASK.lock();
+    if let Some(task) = slot.take() {
+        task.abort();
+    }
+    let hosts = stream_upstream_hosts(config);
+    if hosts.is_empty() {
+        return;
+    }
+
+    let config = config.clone();
+    *slot = Some(tokio::spawn(async move {
+        let mut ticker = time::interval(HOST_REFRESH_INTERVAL);
+        // 首次 tick 立即返回，启动时已经解析过
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if !refresh_hosts(&hosts).await {
+                continue;
+            }
+            let _guard = STREAM_RELOAD_LOCK.lock().await;
+            for handle in STREAM_SERVERS.read().iter() {
+                if let Err(e) = handle.update_route(&config) {
+                    tracing::warn!("Failed to apply re-resolved stream upstreams: {}", e);
+                }
+            }
+        }
+    }));
+}
+
+/// TCP upstream 选择：配置了 sni_routing 时按 ClientHello 的 SNI 选择，未命中使用默认 upstream
+#[derive(Clone)]
+struct TcpUpstreamRouter {
+    default: Option<StreamUpstream>,
+    sni: Arc<[(String, StreamUpstream)]>,
+}
+
+impl TcpUpstreamRouter {
+    fn new(config: &StreamProxyConfig, server: &StreamServer) -> Result<Self> {
+        let default = if server.proxy_pass.trim().is_empty() {
+            None
         } else {
-            start_tcp_server(
-                &app,
+            Some(expand_upstream(find_stream_upstream(
+                config,
                 server,
-                upstream,
-                connect_timeout,
-                proxy_timeout,
-                &mut handles,
-            )
-            .await?;
+                &server.proxy_pass,
+            )?))
+        };
+        let sni = server
+            .sni_routing
+            .iter()
+            .map(|r| {
+                let upstream = find_stream_upstream(config, server, &r.proxy_pass)?;
+                Ok((r.sni.trim().to_string(), expand_upstream(upstream)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            default,
+            sni: sni.into(),
+        })
+    }
+
+    fn sni_enabled(&self) -> bool {
+        !self.sni.is_empty()
+    }
+
+    fn label(&self) -> String {
+        match (&self.default, self.sni_enabled()) {
+            (Some(u), false) => u.name.clone(),
+            (Some(u), true) => format!("sni|{}", u.name),
+            (None, _) => "sni".to_string(),
         }
     }
 
-    *STREAM_SERVERS.write() = handles;
-    Ok(())
+    /// 精确匹配优先于通配匹配
+    fn select(&self, sni: Option<&str>) -> Option<&StreamUpstream> {
+        sni.and_then(|name| {
+            self.sni
+                .iter()
+                .find(|(pattern, _)| pattern.eq_ignore_ascii_case(name))
+                .or_else(|| {
+                    self.sni
+                        .iter()
+                        .find(|(pattern, _)| matching::host_matches(pattern, name))
+                })
+                .map(|(_, u)| u)
+        })
+        .or(self.default.as_ref())
+    }
 }
 
 pub fn validate_stream_config(cfg: &StreamProxyConfig) -> Result<()> {
@@ -199,6 +898,10 @@ pub fn validate_stream_config(cfg: &StreamProxyConfig) -> Result<()> {
             let _ = parse_duration(&sv.fail_timeout)
                 .map_err(|e| anyhow!("invalid fail_timeout for {}: {}", sv.addr, e))?;
         }
+        if let Some(v) = u.queue_timeout.as_deref() {
+            let _ = parse_duration(v)
+                .map_err(|e| anyhow!("invalid queue_timeout for upstream '{}': {}", name, e))?;
+        }
     }
 
     for s in &cfg.servers {
@@ -206,26 +909,69 @@ pub fn validate_stream_config(cfg: &StreamProxyConfig) -> Result<()> {
             continue;
         }
         let pp = s.proxy_pass.trim();
-        if pp.is_empty() {
+        if pp.is_empty() && s.sni_routing.is_empty() {
             return Err(anyhow!(
                 "stream server (listen_addr={}) proxy_pass cannot be empty",
                 server_label(s)
             ));
         }
-        let Some(u) = cfg.upstreams.iter().find(|u| u.name == pp) else {
+        if !pp.is_empty() {
+            let Some(u) = cfg.upstreams.iter().find(|u| u.name == pp) else {
+                return Err(anyhow!(
+                    "stream server (listen_addr={}) proxy_pass references missing upstream: {}",
+                    server_label(s),
+                    pp
+                ));
+            };
+            if u.servers.is_empty() {
+                return Err(anyhow!(
+                    "stream server (listen_addr={}) proxy_pass upstream '{}' has no servers",
+                    server_label(s),
+                    pp
+                ));
+            }
+        }
+
+        if s.proxy_protocol && s.udp {
+            return Err(anyhow!(
+                "stream server (listen_addr={}) proxy_protocol is only supported for TCP",
+                server_label(s)
+            ));
+        }
+        if s.udp && (s.limit_rate.is_some() || s.limit_rate_total.is_some()) {
             return Err(anyhow!(
-                "stream server (listen_addr={}) proxy_pass references missing upstream: {}",
-                server_label(s),
-                pp
+                "stream server (listen_addr={}) limit_rate is only supported for TCP, use limit_pps for UDP",
+                server_label(s)
             ));
-        };
-        if u.servers.is_empty() {
+        }
+        if !s.udp && s.limit_pps.is_some() {
             return Err(anyhow!(
-                "stream server (listen_addr={}) proxy_pass upstream '{}' has no servers",
-                server_label(s),
-                pp
+                "stream server (listen_addr={}) limit_pps is only supported for UDP",
+                server_label(s)
+            ));
+        }
+        if !s.sni_routing.is_empty() && s.udp {
+            return Err(anyhow!(
+                "stream server (listen_addr={}) sni_routing is only supported for TCP",
+                server_label(s)
             ));
         }
+        for r in &s.sni_routing {
+            if r.sni.trim().is_empty() {
+                return Err(anyhow!(
+                    "stream server (listen_addr={}) has sni_routing entry with empty sni",
+                    server_label(s)
+                ));
+            }
+            if !cfg.upstreams.iter().any(|u| u.name == r.proxy_pass.trim()) {
+                return Err(anyhow!(
+                    "stream server (listen_addr={}) sni_routing '{}' references missing upstream: {}",
+                    server_label(s),
+                    r.sni,
+                    r.proxy_pass
+                ));
+            }
+        }
 
         let _ = parse_duration(&s.proxy_connect_timeout).map_err(|e| {
             anyhow!(
@@ -236,86 +982,122 @@ pub fn validate_stream_config(cfg: &StreamProxyConfig) -> Result<()> {
         })?;
         let _ = parse_duration(&s.proxy_timeout)
             .map_err(|e| anyhow!("invalid proxy_timeout: {} ({})", s.proxy_timeout, e))?;
+        if let Some(max) = s.max_session_duration.as_deref() {
+            let _ = parse_duration(max)
+                .map_err(|e| anyhow!("invalid max_session_duration: {} ({})", max, e))?;
+        }
+    }
+
+    if let Some(drain) = cfg.drain_timeout.as_deref() {
+        let _ = parse_duration(drain)
+            .map_err(|e| anyhow!("invalid drain_timeout: {} ({})", drain, e))?;
     }
 
     Ok(())
 }
 
 async fn start_tcp_server(
-    app: &tauri::AppHandle,
+    app: Option<&AppEvents>,
     server: &StreamServer,
-    upstream: &StreamUpstream,
+    router: TcpUpstreamRouter,
     connect_timeout: Duration,
-    proxy_timeout: Duration,
-    servers: &mut Vec<StreamServerHandle>,
-) -> Result<()> {
+    timeouts: SessionTimeouts,
+    access: StreamAccess,
+) -> Result<StreamServerHandle> {
     // listen_addr 为空时兼容旧配置 listen_port，并默认回环地址
     let listen_addr = resolve_listen_addr(server)?;
-    let listener = TcpListener::bind(&listen_addr)
-        .await
-        .with_context(|| format!("Failed to bind stream tcp listener: {}", listen_addr))?;
+    let listener = match TcpListener::bind(&listen_addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            let detail = listen_addr
+                .parse::<SocketAddr>()
+                .map(|addr| crate::port_owner::describe_bind_error(addr, &e))
+                .unwrap_or_else(|_| e.to_string());
+            return Err(anyhow::Error::new(e).context(format!(
+                "Failed to bind stream tcp listener: {}: {}",
+                listen_addr, detail
+            )));
+        }
+    };
 
     let bound_addr = listener
         .local_addr()
         .map(|a| a.to_string())
         .unwrap_or_else(|_| listen_addr.clone());
 
+    let upstream_label = router.label();
     tracing::info!(
         "Stream TCP server listening on {} -> {}",
         bound_addr,
-        upstream.name
+        upstream_label
     );
     stream_log(
         app,
         format!(
             "TCP listening address: {} -> {} (upstream={})",
-            listen_addr, bound_addr, upstream.name
+            listen_addr, bound_addr, upstream_label
         ),
     );
 
-    let cfg = config::get_config();
-    let access_control_enabled = cfg.stream_access_control_enabled;
-    let allow_all_lan = cfg.allow_all_lan;
-    let allow_all_ip = cfg.allow_all_ip;
-    let whitelist: Arc<[config::WhitelistEntry]> = Arc::from(cfg.whitelist);
+    let stats = stream_stats(&listen_addr, false, &upstream_label);
+    let router = Arc::new(RwLock::new(router));
+    let access = Arc::new(RwLock::new(access));
+    let opts = Arc::new(TcpSessionOptions {
+        connect_timeout,
+        timeouts,
+        proxy_protocol: server.proxy_protocol,
+        connect_retries: server.connect_retries,
+        limit_rate: server.limit_rate.filter(|r| *r > 0),
+        total_buckets: server
+            .limit_rate_total
+            .filter(|r| *r > 0)
+            .map(|r| Arc::new(direction_buckets(r))),
+    });
 
     let (shutdown_tx, mut shutdown_rx) = mpsc::channel(1);
     let server_task = tokio::spawn({
-        let upstream = upstream.clone();
-        let whitelist = whitelist.clone();
+        let router = router.clone();
+        let access = access.clone();
+        let stats = stats.clone();
+        let listen_addr = listen_addr.clone();
         async move {
-            loop {
+            // 会话任务归属于该监听，停止监听后按 drain 时长等待其结束
+            let mut relays = tokio::task::JoinSet::new();
+            let drain = loop {
                 tokio::select! {
                     result = listener.accept() => {
                         match result {
                             Ok((client_socket, client_addr)) => {
-                                // 访问控制/黑名单：TCP stream 没有 headers，仅按 remote ip 判定
-                                if access_control_enabled {
-                                    let headers = axum::http::HeaderMap::new();
-                                    if !access_control::is_allowed_fast(
-                                        &client_addr,
-                                        &headers,
-                                        allow_all_lan,
-                                        allow_all_ip,
-                                        &whitelist,
-                                    ) {
-                                        tracing::warn!(
-                                            "STREAM TCP forbidden: ip={} upstream={}",
-                                            client_addr.ip(),
-                                            upstream.name
-                                        );
-                                        continue;
-                                    }
+                                if !access.read().allows(&client_addr) {
+                                    tracing::warn!(
+                                        "STREAM TCP forbidden: ip={} upstream={}",
+                                        client_addr.ip(),
+                                        upstream_label
+                                    );
+                                    stream_conn_log(|| {
+                                        format!(
+                                            "TCP rejected {} on {} by access control",
+                                            client_addr,
+                                            listen_addr
+                                        )
+                                    });
+                                    continue;
                                 }
+                                stream_conn_log(|| {
+                                    format!("TCP accepted {} on {}", client_addr, listen_addr)
+                                });
 
-                                let upstream = upstream.clone();
-                                tokio::spawn(async move {
+                                // upstream 在会话建立时取快照，重载只影响新会话
+                                let router = router.read().clone();
+                                let stats = stats.clone();
+                                let opts = opts.clone();
+                                relays.spawn(async move {
                                     if let Err(e) = handle_tcp_client(
                                         client_socket,
                                         client_addr,
-                                        &upstream,
-                                        connect_timeout,
-                                        proxy_timeout,
+                                        &router,
+                                        &opts,
+                                        &stats,
                                     )
                                     .await
                                     {
@@ -328,124 +1110,709 @@ async fn start_tcp_server(
                             }
                         }
                     }
-                    _ = shutdown_rx.recv() => {
+                    Some(_) = relays.join_next(), if !relays.is_empty() => {}
+                    drain = shutdown_rx.recv() => {
                         tracing::info!("Shutting down TCP server {}", listen_addr);
-                        break;
+                        crate::proxy::logging::push_log_line(format!(
+                            "[STREAM] TCP listener {} stopped",
+                            listen_addr
+                        ));
+                        break drain.unwrap_or_default();
                     }
                 }
-            }
+            };
+            drop(listener);
+            (!relays.is_empty()).then(|| tokio::spawn(drain_relays(relays, drain, listen_addr)))
         }
     });
 
-    servers.push(StreamServerHandle {
+    Ok(StreamServerHandle {
+        server: server.clone(),
+        stats_key: (listen_addr, false),
+        started_at: chrono::Utc::now().timestamp(),
+        route: ListenerRoute::Tcp(router),
+        access,
         task: Some(server_task),
         shutdown_tx,
-    });
+    })
+}
 
-    Ok(())
+/// 等待已停止监听上的会话自然结束，超过 drain 时长后中止剩余会话
+async fn drain_relays(
+    mut relays: tokio::task::JoinSet<()>,
+    drain: Duration,
+    listen_addr: String,
+) -> DrainReport {
+    let before = relays.len();
+    let drained = time::timeout(drain, async { while relays.join_next().await.is_some() {} })
+        .await
+        .is_ok();
+    let cut = if drained { 0 } else { relays.len() };
+    if cut > 0 {
+        tracing::info!(
+            "Aborting {} TCP sessions on {} after drain timeout",
+            cut,
+            listen_addr
+        );
+        relays.shutdown().await;
+    }
+    DrainReport {
+        drained: before - cut,
+        cut,
+    }
 }
 
 async fn handle_tcp_client(
     client_socket: TcpStream,
     client_addr: SocketAddr,
-    upstream: &StreamUpstream,
-    connect_timeout: Duration,
-    proxy_timeout: Duration,
+    router: &TcpUpstreamRouter,
+    opts: &TcpSessionOptions,
+    stats: &Arc<StreamServerStats>,
 ) -> Result<()> {
-    let Some(server) = select_upstream_server_with_failover(upstream, &client_addr) else {
-        return Err(anyhow!(
-            "no available upstream servers (all down?) upstream={}",
-            upstream.name
-        ));
+    let connect_timeout = opts.connect_timeout;
+    let mut client_socket = client_socket;
+    let listen_port = client_socket.local_addr().map(|a| a.port()).unwrap_or(0);
+    // SNI 路由需要先读出 ClientHello，这部分字节在连上上游后原样补发
+    let (upstream, preread) = if router.sni_enabled() {
+        let (sni, preread) = read_client_hello_sni(&mut client_socket).await?;
+        let Some(upstream) = router.select(sni.as_deref()) else {
+            return Err(anyhow!(
+                "no stream upstream matches SNI {:?}, closing connection",
+                sni.as_deref().unwrap_or("")
+            ));
+        };
+        (upstream, preread)
+    } else {
+        let Some(upstream) = router.default.as_ref() else {
+            return Err(anyhow!("stream server has no default upstream"));
+        };
+        (upstream, Vec::new())
     };
 
-    let server_addr = server.addr.clone();
-
-    let server_socket: TcpStream =
-        match time::timeout(connect_timeout, TcpStream::connect(&server_addr)).await {
-            Ok(Ok(socket)) => socket,
-            Ok(Err(e)) => {
-                record_upstream_failure(&server_addr, server.max_fails, &server.fail_timeout);
-                return Err(anyhow!(
-                    "Failed to connect to upstream {}: {}",
-                    server_addr,
-                    e
-                ));
-            }
-            Err(_) => {
-                record_upstream_failure(&server_addr, server.max_fails, &server.fail_timeout);
-                return Err(anyhow!(
-                    "Connection to upstream {} timed out after {:?}",
-                    server_addr,
-                    connect_timeout
-                ));
+    let queue_timeout = upstream
+        .queue_timeout
+        .as_deref()
+        .and_then(|v| parse_duration(v).ok())
+        .unwrap_or_default();
+    let started = Instant::now();
+    // 连接失败时换下一个候选重试，已尝试过的服务器不再参与选择
+    let max_attempts = opts.connect_retries as usize + 1;
+    let mut tried: Vec<String> = Vec::new();
+    let (server_addr, server_socket, session_stats, _active) =
+        loop {
+            let (server, active) =
+                match acquire_tcp_upstream_server(upstream, &client_addr, queue_timeout, &tried)
+                    .await
+                {
+                    Ok(v) => v,
+                    Err(e) if e.is::<StreamSaturated>() => {
+                        stats.traffic.saturated.fetch_add(1, Ordering::Relaxed);
+                        tracing::warn!(
+                            "STREAM TCP upstream '{}' saturated, closing client {}",
+                            upstream.name,
+                            client_addr
+                        );
+                        return Err(e.context(format!("upstream={}", upstream.name)));
+                    }
+                    Err(e) if tried.is_empty() => return Err(e),
+                    Err(e) => {
+                        return Err(e.context(format!(
+                            "gave up after {} failed attempt(s): {}",
+                            tried.len(),
+                            tried.join(", ")
+                        )))
+                    }
+                };
+
+            let server_addr = server.addr.clone();
+            let session_stats = StreamSessionStats::new(stats.clone(), &server_addr)
+                .with_attempts(tried.len() as u32 + 1);
+            let attempt_started = Instant::now();
+
+            let (reason, err) =
+                match time::timeout(connect_timeout, TcpStream::connect(&server_addr)).await {
+                    Ok(Ok(socket)) => break (server_addr, socket, session_stats, active),
+                    Ok(Err(e)) => (
+                        "connect failed",
+                        anyhow!("Failed to connect to upstream {}: {}", server_addr, e),
+                    ),
+                    Err(_) => (
+                        "connect timeout",
+                        anyhow!(
+                            "Connection to upstream {} timed out after {:?}",
+                            server_addr,
+                            connect_timeout
+                        ),
+                    ),
+                };
+
+            session_stats.on_connect_failure();
+            session_stats.log_session(listen_port, false, client_addr, attempt_started, reason);
+            record_upstream_failure(
+                &server_addr,
+                server.max_fails,
+                &server.fail_timeout,
+                &err.to_string(),
+            );
+            tried.push(server_addr);
+            if tried.len() >= max_attempts {
+                return Err(err.context(format!("gave up after {} attempt(s)", tried.len())));
             }
+            tracing::debug!(
+                "TCP client {} retrying next upstream after {} (attempt {}/{})",
+                client_addr,
+                err,
+                tried.len(),
+                max_attempts
+            );
         };
 
     record_upstream_success(&server_addr);
+    let _active = connections::track(ActiveEntry::stream(
+        &stats.listen_addr,
+        client_addr,
+        &server_addr,
+        false,
+    ));
+    stream_conn_log(|| {
+        format!(
+            "TCP {} -> upstream {} (upstream group {}, attempt {})",
+            client_addr, server_addr, upstream.name, session_stats.attempts
+        )
+    });
 
     let mut client = client_socket;
     let mut upstream_conn = server_socket;
 
+    session_stats.on_open();
+    let activity = SessionActivity::new();
+    let throttle = RelayThrottle::new(opts);
     let relay = async {
-        let _ = io::copy_bidirectional(&mut client, &mut upstream_conn).await?;
+        if opts.proxy_protocol {
+            let local = client.local_addr()?;
+            upstream_conn
+                .write_all(&proxy_v2_header(client_addr, local))
+                .await?;
+        }
+        if !preread.is_empty() {
+            upstream_conn.write_all(&preread).await?;
+            session_stats.on_bytes(true, preread.len() as u64);
+        }
+        let (mut c_rd, mut c_wr) = client.split();
+        let (mut u_rd, mut u_wr) = upstream_conn.split();
+        tokio::try_join!(
+            copy_counted(&mut c_rd, &mut u_wr, |n| {
+                activity.touch();
+                session_stats.on_bytes(true, n);
+                throttle.delay(true, n)
+            }),
+            copy_counted(&mut u_rd, &mut c_wr, |n| {
+                activity.touch();
+                session_stats.on_bytes(false, n);
+                throttle.delay(false, n)
+            }),
+        )?;
         Ok::<_, std::io::Error>(())
     };
 
-    match time::timeout(proxy_timeout, relay).await {
-        Ok(res) => {
-            if let Err(e) = res {
-                tracing::debug!(
-                    "TCP relay io error (client={} upstream={}): {}",
-                    client_addr,
-                    server_addr,
-                    e
-                );
-            }
+    let relay_result = tokio::select! {
+        res = relay => Ok(res),
+        expired = wait_session_expired(&activity, opts.timeouts) => Err(expired),
+    };
+    session_stats.on_close();
+    tracing::debug!(
+        "TCP session closed (client={} upstream={} attempts={} duration={:?})",
+        client_addr,
+        server_addr,
+        session_stats.attempts,
+        activity.elapsed()
+    );
+
+    let reason = match relay_result {
+        Ok(Ok(())) => "closed".to_string(),
+        Ok(Err(e)) => {
+            tracing::debug!(
+                "TCP relay io error (client={} upstream={}): {}",
+                client_addr,
+                server_addr,
+                e
+            );
+            format!("io error: {e}")
         }
-        Err(_) => {
+        Err(expired) => {
             tracing::debug!(
-                "TCP relay timeout (client={} upstream={} timeout={:?})",
+                "TCP relay {} (client={} upstream={} timeouts={:?})",
+                expired,
                 client_addr,
                 server_addr,
-                proxy_timeout
+                opts.timeouts
             );
+            expired.to_string()
         }
-    }
+    };
+    session_stats.log_session(listen_port, false, client_addr, started, &reason);
 
     Ok(())
 }
 
-#[derive(Clone)]
-struct UdpSessionEntry {
-    upstream_addr: SocketAddr,
-    last_seen_ms: u64,
+/// 每个 TCP 连接共享的监听级参数
+struct TcpSessionOptions {
+    connect_timeout: Duration,
+    timeouts: SessionTimeouts,
+    proxy_protocol: bool,
+    connect_retries: u32,
+    /// 单连接限速（字节/秒）
+    limit_rate: Option<u64>,
+    /// 监听级总限速令牌桶，所有连接共享
+    total_buckets: Option<Arc<DirectionBuckets>>,
+}
+
+/// 按方向的令牌桶：[客户端 -> 上游, 上游 -> 客户端]
+type DirectionBuckets = [Mutex<ByteBucket>; 2];
+
+fn direction_buckets(rate: u64) -> DirectionBuckets {
+    let rate = rate as f64;
+    [
+        Mutex::new(ByteBucket::new(rate, rate)),
+        Mutex::new(ByteBucket::new(rate, rate)),
+    ]
+}
+
+/// 单个连接的限速状态：同时受单连接与监听总量两级令牌桶约束
+struct RelayThrottle<'a> {
+    conn: Option<DirectionBuckets>,
+    total: Option<&'a DirectionBuckets>,
+}
+
+impl<'a> RelayThrottle<'a> {
+    fn new(opts: &'a TcpSessionOptions) -> Self {
+        Self {
+            conn: opts.limit_rate.map(direction_buckets),
+            total: opts.total_buckets.as_deref(),
+        }
+    }
+
+    /// 记账并返回继续转发前需要等待的时长
+    fn delay(&self, to_upstream: bool, n: u64) -> Duration {
+        let dir = if to_upstream { 0 } else { 1 };
+        [self.conn.as_ref(), self.total]
+            .into_iter()
+            .flatten()
+            .map(|b| b[dir].lock().reserve(n as usize))
+            .max()
+            .unwrap_or(Duration::ZERO)
+    }
+}
+
+/// TCP 会话超时：idle 为空闲超时（proxy_timeout），max_duration 为可选的绝对时长上限
+#[derive(Debug, Clone, Copy)]
+struct SessionTimeouts {
+    idle: Duration,
+    max_duration: Option<Duration>,
+}
+
+/// 记录会话最近一次收发数据的时间（相对会话开始的毫秒数）
+struct SessionActivity {
+    started: Instant,
+    last_ms: AtomicU64,
+}
+
+impl SessionActivity {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_ms: AtomicU64::new(0),
+        }
+    }
+
+    fn touch(&self) {
+        let ms = self.started.elapsed().as_millis() as u64;
+        self.last_ms.fetch_max(ms, Ordering::Relaxed);
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    fn idle(&self) -> Duration {
+        self.elapsed()
+            .saturating_sub(Duration::from_millis(self.last_ms.load(Ordering::Relaxed)))
+    }
+}
+
+/// 等待会话超时：空闲超过 idle 或总时长超过 max_duration 时返回原因
+async fn wait_session_expired(
+    activity: &SessionActivity,
+    timeouts: SessionTimeouts,
+) -> &'static str {
+    loop {
+        let idle = activity.idle();
+        if idle >= timeouts.idle {
+            return "idle timeout";
+        }
+        let mut wait = timeouts.idle - idle;
+        if let Some(max) = timeouts.max_duration {
+            let elapsed = activity.elapsed();
+            if elapsed >= max {
+                return "max session duration reached";
+            }
+            wait = wait.min(max - elapsed);
+        }
+        time::sleep(wait).await;
+    }
+}
+
+/// 单向转发并按块上报字节数；读到 EOF 后关闭写端，与 copy_bidirectional 行为一致。
+/// on_bytes 返回的时长用于限速：写完本块后等待该时长再继续读取
+async fn copy_counted<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    on_bytes: impl Fn(u64) -> Duration,
+) -> io::Result<()>
+where
+    R: io::AsyncRead + Unpin,
+    W: io::AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; 16 * 1024];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            writer.shutdown().await?;
+            return Ok(());
+        }
+        writer.write_all(&buf[..n]).await?;
+        let wait = on_bytes(n as u64);
+        if !wait.is_zero() {
+            time::sleep(wait).await;
+        }
+    }
+}
+
+/// PROXY protocol v2 头（PROXY 命令 + TCP），地址族不一致时统一映射为 IPv6
+fn proxy_v2_header(src: SocketAddr, dst: SocketAddr) -> Vec<u8> {
+    const SIGNATURE: [u8; 12] = [
+        0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+    ];
+
+    let mut out = SIGNATURE.to_vec();
+    out.push(0x21); // v2 + PROXY
+    match (src.ip().to_canonical(), dst.ip().to_canonical()) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => {
+            out.push(0x11); // TCP over IPv4
+            out.extend_from_slice(&12u16.to_be_bytes());
+            out.extend_from_slice(&s.octets());
+            out.extend_from_slice(&d.octets());
+        }
+        (s, d) => {
+            let v6 = |ip: IpAddr| match ip {
+                IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+                IpAddr::V6(v6) => v6,
+            };
+            out.push(0x21); // TCP over IPv6
+            out.extend_from_slice(&36u16.to_be_bytes());
+            out.extend_from_slice(&v6(s).octets());
+            out.extend_from_slice(&v6(d).octets());
+        }
+    }
+    out.extend_from_slice(&src.port().to_be_bytes());
+    out.extend_from_slice(&dst.port().to_be_bytes());
+    out
+}
+
+/// ClientHello 读取上限与超时（防止慢速或恶意客户端占住连接）
+const CLIENT_HELLO_MAX_BYTES: usize = 64 * 1024;
+const CLIENT_HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, PartialEq, Eq)]
+enum SniParse {
+    /// 需要更多数据
+    Incomplete,
+    /// 完整解析（非 TLS 或未携带 SNI 时为 None）
+    Done(Option<String>),
+}
+
+/// 从客户端读取直到能解析出 SNI，返回 SNI 与已读取的全部字节
+async fn read_client_hello_sni(client: &mut TcpStream) -> Result<(Option<String>, Vec<u8>)> {
+    let mut buf = Vec::with_capacity(4096);
+    let read = async {
+        let mut chunk = [0u8; 4096];
+        loop {
+            if let SniParse::Done(sni) = parse_client_hello_sni(&buf) {
+                return Ok(sni);
+            }
+            if buf.len() >= CLIENT_HELLO_MAX_BYTES {
+                return Ok(None);
+            }
+            let n = client.read(&mut chunk).await?;
+            if n == 0 {
+                return Err(anyhow!(
+                    "client closed before sending a complete ClientHello"
+                ));
+            }
+            buf.extend_from_slice(&chunk[..n]);
+        }
+    };
+    let sni = time::timeout(CLIENT_HELLO_TIMEOUT, read)
+        .await
+        .map_err(|_| anyhow!("timed out waiting for TLS ClientHello"))??;
+    Ok((sni, buf))
+}
+
+/// 解析 TLS ClientHello 中的 server_name 扩展；支持 ClientHello 跨多个 TLS record
+fn parse_client_hello_sni(buf: &[u8]) -> SniParse {
+    const RECORD_HANDSHAKE: u8 = 0x16;
+    const HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+
+    let mut handshake = Vec::new();
+    let mut pos = 0usize;
+    loop {
+        let Some(header) = buf.get(pos..pos + 5) else {
+            return SniParse::Incomplete;
+        };
+        if header[0] != RECORD_HANDSHAKE {
+            return SniParse::Done(None);
+        }
+        let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+        let Some(payload) = buf.get(pos + 5..pos + 5 + len) else {
+            return SniParse::Incomplete;
+        };
+        handshake.extend_from_slice(payload);
+        pos += 5 + len;
+
+        if handshake.len() < 4 {
+            continue;
+        }
+        if handshake[0] != HANDSHAKE_CLIENT_HELLO {
+            return SniParse::Done(None);
+        }
+        let hs_len = u32::from_be_bytes([0, handshake[1], handshake[2], handshake[3]]) as usize;
+        if let Some(body) = handshake.get(4..4 + hs_len) {
+            return SniParse::Done(client_hello_server_name(body));
+        }
+    }
+}
+
+fn client_hello_server_name(body: &[u8]) -> Option<String> {
+    fn take<'a>(data: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+        if data.len() < n {
+            return None;
+        }
+        let (head, rest) = data.split_at(n);
+        *data = rest;
+        Some(head)
+    }
+    fn take_u8_prefixed<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+        let n = take(data, 1)?[0] as usize;
+        take(data, n)
+    }
+    fn take_u16_prefixed<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+        let len = take(data, 2)?;
+        take(data, u16::from_be_bytes([len[0], len[1]]) as usize)
+    }
+
+    let mut data = body;
+    take(&mut data, 2 + 32)?; // legacy_version + random
+    take_u8_prefixed(&mut data)?; // session_id
+    take_u16_prefixed(&mut data)?; // cipher_suites
+    take_u8_prefixed(&mut data)?; // compression_methods
+    let mut extensions = take_u16_prefixed(&mut data)?;
+
+    while !extensions.is_empty() {
+        let ext_type = take(&mut extensions, 2)?;
+        let mut ext = take_u16_prefixed(&mut extensions)?;
+        if ext_type != [0x00, 0x00] {
+            continue;
+        }
+        let mut names = take_u16_prefixed(&mut ext)?;
+        while !names.is_empty() {
+            let name_type = take(&mut names, 1)?[0];
+            let name = take_u16_prefixed(&mut names)?;
+            if name_type == 0 {
+                return std::str::from_utf8(name)
+                    .ok()
+                    .map(|s| s.trim_end_matches('.').to_ascii_lowercase());
+            }
+        }
+        return None;
+    }
+    None
+}
+
+/// 每个 UDP 监听的最大并发会话数（每个会话占用一个上游 socket）
+const MAX_UDP_SESSIONS: usize = 4096;
+
+/// UDP 收包遇到非瞬时错误后的重试间隔，避免空转占满 CPU
+const UDP_RECV_ERROR_BACKOFF: Duration = Duration::from_millis(50);
+
+/// ICMP 不可达等逐包产生的错误，可立即继续收包
+fn is_transient_udp_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+    )
+}
+
+/// UDP 会话：每个客户端独占一个已 connect 的上游 socket，回包只转发给该客户端
+struct UdpSession {
+    server: StreamUpstreamServer,
+    socket: Arc<UdpSocket>,
+    /// 上游返回 ICMP 不可达（ECONNREFUSED）时置位，下一个包到来时换上游重建会话
+    unreachable: Arc<AtomicBool>,
+    last_seen_ms: Arc<AtomicU64>,
+    stats: StreamSessionStats,
+    reader: tokio::task::JoinHandle<()>,
+    client_addr: SocketAddr,
+    listen_port: u16,
+    started: Instant,
+    /// 写入 stream_logs 的断开原因，默认视为随监听停止而关闭
+    close_reason: &'static str,
+    /// 客户端 -> 上游 方向的包速率限制（limit_pps）
+    pps_in: Option<ByteBucket>,
+    _active: ActiveGuard,
+}
+
+fn packet_bucket(limit_pps: Option<u64>) -> Option<ByteBucket> {
+    limit_pps
+        .filter(|p| *p > 0)
+        .map(|p| ByteBucket::new(p as f64, p as f64))
+}
+
+impl UdpSession {
+    async fn open(
+        listen: Arc<UdpSocket>,
+        client_addr: SocketAddr,
+        server: StreamUpstreamServer,
+        stats: StreamSessionStats,
+        limit_pps: Option<u64>,
+    ) -> io::Result<Self> {
+        let upstream_addr: SocketAddr = server
+            .addr
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let local_any = if upstream_addr.is_ipv6() {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        };
+        let socket = UdpSocket::bind(local_any).await?;
+        socket.connect(upstream_addr).await?;
+        let socket = Arc::new(socket);
+        let last_seen_ms = Arc::new(AtomicU64::new(now_ms()));
+        let unreachable = Arc::new(AtomicBool::new(false));
+        let listen_port = listen.local_addr().map(|a| a.port()).unwrap_or(0);
+
+        let reader = tokio::spawn({
+            let socket = socket.clone();
+            let last_seen_ms = last_seen_ms.clone();
+            let unreachable = unreachable.clone();
+            let stats = stats.clone();
+            let mut pps_out = packet_bucket(limit_pps);
+            async move {
+                let mut buf = vec![0u8; 65536];
+                loop {
+                    // 已 connect 的 socket 会以 ECONNREFUSED 的形式收到 ICMP 不可达
+                    let n = match socket.recv(&mut buf).await {
+                        Ok(n) => n,
+                        Err(e) => {
+                            if e.kind() == io::ErrorKind::ConnectionRefused {
+                                unreachable.store(true, Ordering::Relaxed);
+                            }
+                            if is_transient_udp_error(&e) {
+                                continue;
+                            }
+                            // socket 已不可用，停止读取，会话随后按空闲超时回收
+                            tracing::warn!(
+                                "UDP upstream recv failed for {}, stop reading: {}",
+                                client_addr,
+                                e
+                            );
+                            break;
+                        }
+                    };
+                    last_seen_ms.fetch_max(now_ms(), Ordering::Relaxed);
+                    if pps_out.as_mut().is_some_and(|b| !b.try_take(1)) {
+                        continue;
+                    }
+                    if listen.send_to(&buf[..n], client_addr).await.is_ok() {
+                        stats.on_bytes(false, n as u64);
+                    }
+                }
+            }
+        });
+
+        stats.on_open();
+        let _active = connections::track(ActiveEntry::stream(
+            &stats.server.listen_addr,
+            client_addr,
+            &server.addr,
+            true,
+        ));
+        Ok(Self {
+            server,
+            socket,
+            unreachable,
+            last_seen_ms,
+            stats,
+            reader,
+            client_addr,
+            listen_port,
+            started: Instant::now(),
+            close_reason: "shutdown",
+            pps_in: packet_bucket(limit_pps),
+            _active,
+        })
+    }
+
+    fn touch(&self) {
+        self.last_seen_ms.fetch_max(now_ms(), Ordering::Relaxed);
+    }
+
+    fn last_seen_ms(&self) -> u64 {
+        self.last_seen_ms.load(Ordering::Relaxed)
+    }
+
+    fn is_unreachable(&self) -> bool {
+        self.unreachable.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for UdpSession {
+    fn drop(&mut self) {
+        self.reader.abort();
+        self.stats.on_close();
+        self.stats.log_session(
+            self.listen_port,
+            true,
+            self.client_addr,
+            self.started,
+            self.close_reason,
+        );
+    }
 }
 
-static UDP_NOW_MS: AtomicU64 = AtomicU64::new(0);
 static UDP_START_TIME: once_cell::sync::Lazy<std::time::Instant> =
     once_cell::sync::Lazy::new(std::time::Instant::now);
 
+/// 单调时钟：相对进程内首次调用的毫秒数，用于 UDP 会话的最近活跃时间
 fn now_ms() -> u64 {
-    let ms = UDP_START_TIME.elapsed().as_millis() as u64;
-    UDP_NOW_MS.fetch_max(ms, Ordering::Relaxed);
-    ms
+    UDP_START_TIME.elapsed().as_millis() as u64
+}
+
+/// 最近活跃时间距今超过 ttl 的会话视为过期
+fn udp_session_expired(last_seen_ms: u64, now_ms: u64, ttl: Duration) -> bool {
+    now_ms.saturating_sub(last_seen_ms) > ttl.as_millis() as u64
 }
 
 async fn start_udp_server(
-    app: &tauri::AppHandle,
+    app: Option<&AppEvents>,
     server: &StreamServer,
     upstream: &StreamUpstream,
-    _connect_timeout: Duration,
     proxy_timeout: Duration,
-    servers: &mut Vec<StreamServerHandle>,
-) -> Result<()> {
-    let cfg = config::get_config();
-    let access_control_enabled = cfg.stream_access_control_enabled;
-    let allow_all_lan = cfg.allow_all_lan;
-    let allow_all_ip = cfg.allow_all_ip;
-    let whitelist: Arc<[config::WhitelistEntry]> = Arc::from(cfg.whitelist);
-
+    access: StreamAccess,
+) -> Result<StreamServerHandle> {
     // listen_addr 为空时兼容旧配置 listen_port，并默认回环地址
     let listen_addr = resolve_listen_addr(server)?;
     let listen_sock = UdpSocket::bind(&listen_addr)
@@ -465,133 +1832,192 @@ async fn start_udp_server(
         ),
     );
 
-    // 使用 DashMap 替代 Mutex<HashMap> 以提升并发性能
-    let sessions: Arc<DashMap<SocketAddr, UdpSessionEntry>> = Arc::new(DashMap::new());
-
-    let listen_sock = Arc::new(listen_sock);
+    check_udp_upstream_addrs(upstream)?;
+
+    let stats = stream_stats(&listen_addr, true, &upstream.name);
+    let session_ttl = proxy_timeout.max(Duration::from_secs(10));
+    let upstream = Arc::new(RwLock::new(upstream.clone()));
+    let access = Arc::new(RwLock::new(access));
+
+    let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
+    let udp_server = run_udp_server(
+        Arc::new(listen_sock),
+        upstream.clone(),
+        stats,
+        session_ttl,
+        server.limit_pps,
+        {
+            let access = access.clone();
+            move |client_addr: &SocketAddr| access.read().allows(client_addr)
+        },
+        shutdown_rx,
+    );
+    let server_task = tokio::spawn(async move {
+        udp_server.await;
+        None
+    });
 
-    // 为每个 upstream_addr 复用一个已 connect 的 UDP socket：避免每包 bind/connect
-    let upstream_socks: Arc<DashMap<SocketAddr, Arc<UdpSocket>>> = Arc::new(DashMap::new());
+    Ok(StreamServerHandle {
+        server: server.clone(),
+        stats_key: (listen_addr, true),
+        started_at: chrono::Utc::now().timestamp(),
+        route: ListenerRoute::Udp(upstream),
+        access,
+        task: Some(server_task),
+        shutdown_tx,
+    })
+}
 
-    let mut upstream_readers = Vec::new();
+fn check_udp_upstream_addrs(upstream: &StreamUpstream) -> Result<()> {
     for s in &upstream.servers {
-        let upstream_addr: SocketAddr = s
-            .addr
-            .parse()
+        s.addr
+            .parse::<SocketAddr>()
             .with_context(|| format!("Invalid upstream udp addr: {}", s.addr))?;
-
-        if upstream_socks.contains_key(&upstream_addr) {
-            continue;
-        }
-
-        let upstream_socket = UdpSocket::bind("0.0.0.0:0").await?;
-        upstream_socket.connect(upstream_addr).await?;
-        let upstream_socket = Arc::new(upstream_socket);
-
-        upstream_socks.insert(upstream_addr, upstream_socket.clone());
-
-        let sessions2 = sessions.clone();
-        let listen2 = listen_sock.clone();
-
-        let h = tokio::spawn(async move {
-            let mut buf = vec![0u8; 65536];
-            loop {
-                match upstream_socket.recv(&mut buf).await {
-                    Ok(n) => {
-                        let payload = &buf[..n];
-
-                        // 使用 DashMap 的迭代器，无需锁定整个 map
-                        for entry in sessions2.iter() {
-                            if entry.value().upstream_addr == upstream_addr {
-                                let _ = listen2.send_to(payload, *entry.key()).await;
-                            }
-                        }
-                    }
-                    Err(_) => {}
-                }
-            }
-        });
-
-        upstream_readers.push((upstream_addr, h));
     }
+    Ok(())
+}
 
-    let (shutdown_tx, mut shutdown_rx) = mpsc::channel(1);
-    let server_task = tokio::spawn({
-        let upstream = upstream.clone();
-        let sessions = sessions.clone();
-        let listen = listen_sock.clone();
-        let whitelist = whitelist.clone();
-        let upstream_socks = upstream_socks.clone();
-
-        async move {
-            let mut buf = vec![0u8; 65536];
-            let cleanup_interval = Duration::from_secs(10);
-            let session_ttl = proxy_timeout.max(Duration::from_secs(10));
-            let mut ticker = time::interval(cleanup_interval);
-
-            loop {
-                tokio::select! {
-                    _ = shutdown_rx.recv() => {
-                        tracing::info!("Shutting down UDP server {}", listen_addr);
-                        break;
+/// UDP 没有连接可 drain，停止时会话随监听一起关闭
+async fn run_udp_server(
+    listen: Arc<UdpSocket>,
+    upstream: Arc<RwLock<StreamUpstream>>,
+    stats: Arc<StreamServerStats>,
+    session_ttl: Duration,
+    limit_pps: Option<u64>,
+    is_allowed: impl Fn(&SocketAddr) -> bool,
+    mut shutdown_rx: mpsc::Receiver<Duration>,
+) {
+    // 会话表只在本任务内访问；会话被移除时 drop 会关闭其上游 socket 和读任务
+    let mut sessions: HashMap<SocketAddr, UdpSession> = HashMap::new();
+    let mut buf = vec![0u8; 65536];
+    let mut ticker = time::interval(Duration::from_secs(10));
+    let listen_label = listen
+        .local_addr()
+        .map(|a| a.to_string())
+        .unwrap_or_default();
+
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => {
+                tracing::info!("Shutting down UDP server {}", listen_label);
+                crate::proxy::logging::push_log_line(format!(
+                    "[STREAM] UDP listener {} stopped",
+                    listen_label
+                ));
+                break;
+            }
+            _ = ticker.tick() => {
+                let now = now_ms();
+                sessions.retain(|_, s| {
+                    let expired = udp_session_expired(s.last_seen_ms(), now, session_ttl);
+                    if expired {
+                        s.close_reason = "idle timeout";
                     }
-                    _ = ticker.tick() => {
-                        let deadline = now_ms().saturating_sub(session_ttl.as_millis() as u64);
-                        sessions.retain(|_, v| v.last_seen_ms >= deadline);
+                    !expired
+                });
+            }
+            res = listen.recv_from(&mut buf) => {
+                let (n, client_addr) = match res {
+                    Ok(v) => v,
+                    Err(e) if is_transient_udp_error(&e) => continue,
+                    Err(e) => {
+                        tracing::warn!("UDP listener {} recv failed: {}", listen_label, e);
+                        tokio::time::sleep(UDP_RECV_ERROR_BACKOFF).await;
+                        continue;
                     }
-                    res = listen.recv_from(&mut buf) => {
-                        match res {
-                            Ok((n, client_addr)) => {
-                                // 访问控制/黑名单：UDP 仅按 remote ip 判定
-                                if access_control_enabled {
-                                    let headers = axum::http::HeaderMap::new();
-                                    if !access_control::is_allowed_fast(
-                                        &client_addr,
-                                        &headers,
-                                        allow_all_lan,
-                                        allow_all_ip,
-                                        &whitelist,
-                                    ) {
-                                        continue;
-                                    }
-                                }
+                };
+                if !is_allowed(&client_addr) {
+                    continue;
+                }
 
-                                let up_server = select_upstream_server(&upstream, &client_addr);
-                                let upstream_addr: SocketAddr = match up_server.addr.parse() {
-                                    Ok(a) => a,
-                                    Err(_) => continue,
-                                };
+                // 上游不可达：记一次失败，关闭旧会话并排除该上游重新选择
+                let mut exclude = Vec::new();
+                if sessions.get(&client_addr).is_some_and(UdpSession::is_unreachable) {
+                    if let Some(mut old) = sessions.remove(&client_addr) {
+                        record_upstream_failure(
+                            &old.server.addr,
+                            old.server.max_fails,
+                            &old.server.fail_timeout,
+                            "upstream unreachable",
+                        );
+                        old.close_reason = "upstream unreachable";
+                        exclude.push(old.server.addr.clone());
+                    }
+                }
 
-                                sessions.insert(
+                if !sessions.contains_key(&client_addr) {
+                    if sessions.len() >= MAX_UDP_SESSIONS {
+                        tracing::warn!(
+                            "UDP session limit {} reached on {}, dropping packet from {}",
+                            MAX_UDP_SESSIONS,
+                            listen_label,
+                            client_addr
+                        );
+                        continue;
+                    }
+                    // 上游在会话建立时按当前配置选定，会话存续期间保持不变
+                    let upstream = upstream.read().clone();
+                    let up_server =
+                        select_upstream_server_excluding(&upstream, &client_addr, &exclude)
+                            .unwrap_or_else(|| select_upstream_server(&upstream, &client_addr));
+                    let session_stats = StreamSessionStats::new(stats.clone(), &up_server.addr)
+                        .with_attempts(exclude.len() as u32 + 1);
+                    match UdpSession::open(
+                        listen.clone(),
+                        client_addr,
+                        up_server.clone(),
+                        session_stats.clone(),
+                        limit_pps,
+                    )
+                    .await
+                    {
+                        Ok(session) => {
+                            sessions.insert(client_addr, session);
+                            stream_conn_log(|| {
+                                format!(
+                                    "UDP session {} on {} -> upstream {} (attempt {})",
                                     client_addr,
-                                    UdpSessionEntry {
-                                        upstream_addr,
-                                        last_seen_ms: now_ms(),
-                                    },
-                                );
-
-                                if let Some(s) = upstream_socks.get(&upstream_addr) {
-                                    let _ = s.send(&buf[..n]).await;
-                                }
-                            }
-                            Err(_) => {}
+                                    listen_label,
+                                    up_server.addr,
+                                    exclude.len() + 1
+                                )
+                            });
+                        }
+                        Err(e) => {
+                            session_stats.on_connect_failure();
+                            tracing::error!(
+                                "Failed to open UDP upstream socket to {}: {}",
+                                up_server.addr,
+                                e
+                            );
+                            stream_conn_log(|| {
+                                format!(
+                                    "UDP session {} failed to open upstream {}: {}",
+                                    client_addr, up_server.addr, e
+                                )
+                            });
+                            continue;
                         }
                     }
                 }
-            }
 
-            for (_, h) in upstream_readers {
-                h.abort();
+                let Some(session) = sessions.get_mut(&client_addr) else {
+                    continue;
+                };
+                session.touch();
+                if session.pps_in.as_mut().is_some_and(|b| !b.try_take(1)) {
+                    continue;
+                }
+                match session.socket.send(&buf[..n]).await {
+                    Ok(_) => session.stats.on_bytes(true, n as u64),
+                    Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                        session.unreachable.store(true, Ordering::Relaxed);
+                    }
+                    Err(_) => {}
+                }
             }
         }
-    });
-
-    servers.push(StreamServerHandle {
-        task: Some(server_task),
-        shutdown_tx,
-    });
-
-    Ok(())
+    }
 }
 
 fn select_upstream_server<'a>(
@@ -603,26 +2029,26 @@ fn select_upstream_server<'a>(
         panic!("No servers available in upstream '{}'", upstream.name);
     }
 
-    let key = upstream.hash_key.trim();
-    let use_hash = key == "$remote_addr" || key.is_empty();
-
-    if use_hash {
-        let mut hasher = DefaultHasher::new();
-        client_addr.ip().to_string().hash(&mut hasher);
-        let h = hasher.finish() as usize;
-        let idx = h % servers.len();
-        return &servers[idx];
-    }
-
-    static COUNTER: AtomicU64 = AtomicU64::new(0);
-    let idx = (COUNTER.fetch_add(1, Ordering::Relaxed) as usize) % servers.len();
-    &servers[idx]
+    // UDP 与 TCP 共用选择逻辑（权重、备用服务器）；全部不可用时仍转发给第一个
+    select_upstream_server_with_failover(upstream, client_addr).unwrap_or(&servers[0])
 }
 
+/// 按权重选择可用的上游；weight <= 0 的服务器仅在其余服务器全部不可用时作为备用
 fn select_upstream_server_with_failover<'a>(
     upstream: &'a StreamUpstream,
     client_addr: &SocketAddr,
 ) -> Option<&'a StreamUpstreamServer> {
+    select_upstream_server_excluding(upstream, client_addr, &[])
+}
+
+/// 同上，但跳过 exclude 中的服务器（同一连接内重试时排除已失败的上游）。
+/// 一致性哈希下等价于沿哈希环顺延到下一个节点
+fn select_upstream_server_excluding<'a>(
+    upstream: &'a StreamUpstream,
+    client_addr: &SocketAddr,
+    exclude: &[String],
+) -> Option<&'a StreamUpstreamServer> {
+    let is_candidate = |s: &StreamUpstreamServer| is_available(s) && !exclude.contains(&s.addr);
     let servers = &upstream.servers;
     if servers.is_empty() {
         return None;
@@ -630,58 +2056,134 @@ fn select_upstream_server_with_failover<'a>(
 
     let key = upstream.hash_key.trim();
     let use_hash = key == "$remote_addr" || key.is_empty();
+    let client_ip = client_addr.ip().to_string();
 
     if upstream.consistent && use_hash {
         let ring = get_or_build_ring(upstream);
         let mut hasher = DefaultHasher::new();
-        client_addr.ip().to_string().hash(&mut hasher);
+        client_ip.hash(&mut hasher);
         let h = hasher.finish();
 
-        if ring.is_empty() {
-            return None;
-        }
-
-        let mut idx = match ring.binary_search_by_key(&h, |(k, _)| *k) {
-            Ok(i) => i,
-            Err(i) => {
-                if i >= ring.len() {
-                    0
-                } else {
-                    i
+        if !ring.is_empty() {
+            let mut idx = match ring.binary_search_by_key(&h, |(k, _)| *k) {
+                Ok(i) => i,
+                Err(i) => {
+                    if i >= ring.len() {
+                        0
+                    } else {
+                        i
+                    }
                 }
-            }
-        };
+            };
 
-        for _ in 0..ring.len() {
-            let (_, sidx) = ring[idx];
-            let s = &servers[sidx];
-            if !is_down(&s.addr) {
-                return Some(s);
+            for _ in 0..ring.len() {
+                let (_, sidx) = ring[idx];
+                let s = &servers[sidx];
+                if is_candidate(s) {
+                    return Some(s);
+                }
+                idx = (idx + 1) % ring.len();
             }
-            idx = (idx + 1) % ring.len();
         }
 
-        return None;
+        return select_backup_server(servers, exclude);
     }
 
-    let start_idx = if use_hash {
-        let mut hasher = DefaultHasher::new();
-        client_addr.ip().to_string().hash(&mut hasher);
-        (hasher.finish() as usize) % servers.len()
+    let candidates: Vec<config::Upstream> = servers
+        .iter()
+        .filter(|s| s.weight > 0 && is_candidate(s))
+        .map(|s| config::Upstream {
+            url: s.addr.clone(),
+            weight: s.weight,
+        })
+        .collect();
+    let picked = if use_hash {
+        super::upstream::pick_weighted_hash(&client_ip, &candidates)
     } else {
-        static COUNTER: AtomicU64 = AtomicU64::new(0);
-        (COUNTER.fetch_add(1, Ordering::Relaxed) as usize) % servers.len()
+        super::upstream::pick_smooth_weighted(&format!("stream|{}", upstream.name), &candidates)
     };
 
-    for step in 0..servers.len() {
-        let idx = (start_idx + step) % servers.len();
-        let s = &servers[idx];
-        if !is_down(&s.addr) {
-            return Some(s);
+    match picked {
+        Some(addr) => servers.iter().find(|s| s.addr == addr),
+        None => select_backup_server(servers, exclude),
+    }
+}
+
+/// 选择上游并占用连接名额；所有服务器都达到 max_conns 时在 queue_timeout 内等待空位
+async fn acquire_tcp_upstream_server<'a>(
+    upstream: &'a StreamUpstream,
+    client_addr: &SocketAddr,
+    queue_timeout: Duration,
+    exclude: &[String],
+) -> Result<(&'a StreamUpstreamServer, UpstreamActiveGuard)> {
+    let deadline = Instant::now() + queue_timeout;
+    loop {
+        let selected = if upstream.least_conn {
+            select_least_conn_server(upstream, exclude)
+        } else {
+            select_upstream_server_excluding(upstream, client_addr, exclude)
+        };
+        match selected {
+            Some(server) => {
+                // 并发连接可能在选择后抢先占满名额，此时重新选择
+                if let Some(guard) =
+                    UpstreamActiveGuard::try_acquire(&server.addr, server.max_conns)
+                {
+                    return Ok((server, guard));
+                }
+            }
+            None if !upstream
+                .servers
+                .iter()
+                .any(|s| is_saturated(s) && !exclude.contains(&s.addr)) =>
+            {
+                return Err(anyhow!(
+                    "no available upstream servers (all down?) upstream={}",
+                    upstream.name
+                ));
+            }
+            None => {
+                if Instant::now() >= deadline {
+                    return Err(StreamSaturated.into());
+                }
+                time::sleep(Duration::from_millis(20)).await;
+            }
         }
     }
+}
 
-    None
+/// 上游组内所有可用服务器都达到 max_conns
+#[derive(Debug)]
+struct StreamSaturated;
+
+impl std::fmt::Display for StreamSaturated {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("all upstream servers reached max_conns")
+    }
+}
+
+impl std::error::Error for StreamSaturated {}
+
+/// 最少连接：活跃会话数最少者优先，相同时权重大者优先
+fn select_least_conn_server<'a>(
+    upstream: &'a StreamUpstream,
+    exclude: &[String],
+) -> Option<&'a StreamUpstreamServer> {
+    upstream
+        .servers
+        .iter()
+        .filter(|s| s.weight > 0 && is_available(s) && !exclude.contains(&s.addr))
+        .min_by_key(|s| (upstream_active(&s.addr), std::cmp::Reverse(s.weight)))
+        .or_else(|| select_backup_server(&upstream.servers, exclude))
+}
+
+fn select_backup_server<'a>(
+    servers: &'a [StreamUpstreamServer],
+    exclude: &[String],
+) -> Option<&'a StreamUpstreamServer> {
+    servers
+        .iter()
+        .find(|s| s.weight <= 0 && is_available(s) && !exclude.contains(&s.addr))
 }
 
 // 缓存一致性哈希环，避免重复构建
@@ -692,7 +2194,7 @@ fn get_or_build_ring(upstream: &StreamUpstream) -> Arc<Vec<(u64, usize)>> {
     let cache_key = upstream
         .servers
         .iter()
-        .map(|s| s.addr.as_str())
+        .map(|s| format!("{}#{}", s.addr, s.weight))
         .collect::<Vec<_>>()
         .join("|");
 
@@ -705,15 +2207,17 @@ fn get_or_build_ring(upstream: &StreamUpstream) -> Arc<Vec<(u64, usize)>> {
     ring
 }
 
+/// 每个服务器的虚拟节点数 = VNODES * weight；weight <= 0 不进入哈希环
 fn build_ring(servers: &[StreamUpstreamServer]) -> Vec<(u64, usize)> {
     const VNODES: u32 = 160;
 
-    let mut ring: Vec<(u64, usize)> = Vec::with_capacity(servers.len() * VNODES as usize);
+    let total_weight: u32 = servers.iter().map(|s| s.weight.max(0) as u32).sum();
+    let mut ring: Vec<(u64, usize)> = Vec::with_capacity((total_weight * VNODES) as usize);
     for (i, s) in servers.iter().enumerate() {
-        if s.addr.trim().is_empty() {
+        if s.addr.trim().is_empty() || s.weight <= 0 {
             continue;
         }
-        for v in 0..VNODES {
+        for v in 0..VNODES * s.weight as u32 {
             let mut hasher = DefaultHasher::new();
             format!("{}#{}", s.addr, v).hash(&mut hasher);
             ring.push((hasher.finish(), i));
@@ -724,7 +2228,7 @@ fn build_ring(servers: &[StreamUpstreamServer]) -> Vec<(u64, usize)> {
     ring
 }
 
-fn is_down(addr: &str) -> bool {
+pub(crate) fn is_down(addr: &str) -> bool {
     let now = Instant::now();
     if let Some(st) = FAIL_MAP.get(addr) {
         match st.down_until {
@@ -736,35 +2240,52 @@ fn is_down(addr: &str) -> bool {
     }
 }
 
-fn record_upstream_success(addr: &str) {
-    FAIL_MAP.remove(addr);
+/// 被动健康检查状态，供健康概览展示
+pub(crate) fn fail_state(addr: &str) -> Option<FailState> {
+    FAIL_MAP.get(addr).map(|st| st.clone())
+}
+
+/// 连接成功后清零失败计数，保留条目以记录最近检查时间
+pub(crate) fn record_upstream_success(addr: &str) {
+    let now = chrono::Utc::now().timestamp();
+    if let Some(mut st) = FAIL_MAP.get_mut(addr) {
+        st.fails = 0;
+        st.down_until = None;
+        st.last_check = now;
+        return;
+    }
+    FAIL_MAP.insert(
+        addr.to_string(),
+        FailState {
+            fails: 0,
+            down_until: None,
+            last_error: None,
+            last_check: now,
+        },
+    );
 }
 
-fn record_upstream_failure(addr: &str, max_fails: i32, fail_timeout: &str) {
+pub(crate) fn record_upstream_failure(addr: &str, max_fails: i32, fail_timeout: &str, error: &str) {
     let max_fails = if max_fails <= 0 { 1 } else { max_fails as u32 };
     let ft = parse_duration(fail_timeout).unwrap_or_else(|_| Duration::from_secs(30));
 
-    FAIL_MAP
+    let mut entry = FAIL_MAP
         .entry(addr.to_string())
-        .and_modify(|entry| {
-            entry.fails = entry.fails.saturating_add(1);
-            if entry.fails >= max_fails {
-                entry.down_until = Some(Instant::now() + ft);
-            }
-        })
-        .or_insert_with(|| {
-            let mut state = FailState {
-                fails: 1,
-                down_until: None,
-            };
-            if state.fails >= max_fails {
-                state.down_until = Some(Instant::now() + ft);
-            }
-            state
+        .or_insert_with(|| FailState {
+            fails: 0,
+            down_until: None,
+            last_error: None,
+            last_check: 0,
         });
+    entry.fails = entry.fails.saturating_add(1);
+    entry.last_error = Some(error.to_string());
+    entry.last_check = chrono::Utc::now().timestamp();
+    if entry.fails >= max_fails {
+        entry.down_until = Some(Instant::now() + ft);
+    }
 }
 
-fn parse_duration(s: &str) -> Result<Duration> {
+pub(crate) fn parse_duration(s: &str) -> Result<Duration> {
     let s = s.trim().to_lowercase();
 
     if s.ends_with('s') {
@@ -790,48 +2311,91 @@ fn parse_duration(s: &str) -> Result<Duration> {
     }
 }
 
-pub async fn stop_stream_servers() {
-    let servers: Vec<StreamServerHandle> = {
-        let mut guard = STREAM_SERVERS.write();
-        std::mem::take(&mut *guard)
+/// 停止全部监听，等待已建立的 TCP 会话在 drain 内结束，返回正常结束与被中止的会话数
+pub async fn stop_stream_servers(app: &AppEvents, drain: Duration) -> DrainReport {
+    let drains = {
+        let _guard = STREAM_RELOAD_LOCK.lock().await;
+        shutdown_all_stream_servers(Some(app), drain).await
     };
 
-    for mut server in servers {
-        let _ = server.shutdown_tx.send(()).await;
-        if let Some(task) = server.task.take() {
-            let _ = time::timeout(Duration::from_secs(5), task).await;
+    let mut total = DrainReport::default();
+    for report in futures_util::future::join_all(drains)
+        .await
+        .into_iter()
+        .flatten()
+    {
+        total += report;
+    }
+    total
+}
+
+/// 停止全部监听，返回各监听的会话 drain 任务；调用方需持有 STREAM_RELOAD_LOCK
+async fn shutdown_all_stream_servers(app: Option<&AppEvents>, drain: Duration) -> Vec<RelayDrain> {
+    if let Some(task) = HOST_REFRESH_TASK.lock().take() {
+        task.abort();
+    }
+
+    let servers = std::mem::take(&mut *STREAM_SERVERS.write());
+    let mut drains = Vec::new();
+    for server in servers {
+        let event = server.event();
+        drains.extend(server.shutdown(drain).await);
+        if let Some(app) = app {
+            event.stopped(app);
         }
     }
+
+    STREAM_STATS.clear();
+    STREAM_START_ERRORS.write().clear();
+    drains
 }
 
 #[cfg(test)]
 mod tests {
     use super::{
-        is_down, parse_duration, record_upstream_failure, record_upstream_success,
-        select_upstream_server, select_upstream_server_with_failover, validate_stream_config,
-        FAIL_MAP, HASH_RING_CACHE,
+        acquire_tcp_upstream_server, copy_counted, direction_buckets, expand_upstream,
+        handle_tcp_client, is_down, is_transient_udp_error, now_ms, parse_client_hello_sni,
+        parse_duration, parse_stream_listen_addr, proxy_v2_header, record_upstream_failure,
+        record_upstream_success, reload_stream_servers, resolve_listen_addr,
+        resolve_stream_upstreams, run_udp_server, select_least_conn_server, select_upstream_server,
+        select_upstream_server_with_failover, stream_stats, udp_session_expired, upstream_active,
+        validate_stream_config, wait_session_expired, RelayThrottle, SessionActivity,
+        SessionTimeouts, SniParse, StreamSaturated, StreamSessionStats, TcpSessionOptions,
+        TcpUpstreamRouter, UdpSession, UpstreamActiveGuard, FAIL_MAP, HASH_RING_CACHE,
+        RESOLVED_HOSTS, STREAM_SERVERS,
     };
     use crate::config::{StreamProxyConfig, StreamServer, StreamUpstream, StreamUpstreamServer};
+    use parking_lot::RwLock;
+    use std::io;
     use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
     use std::time::Duration;
+    use tokio::net::UdpSocket;
+    use tokio::sync::mpsc;
+    use tokio::time;
 
     fn sample_upstream() -> StreamUpstream {
         StreamUpstream {
             name: "backend".into(),
             hash_key: "$remote_addr".into(),
             consistent: true,
+            least_conn: false,
+            queue_timeout: None,
             servers: vec![
                 StreamUpstreamServer {
                     addr: "127.0.0.1:10001".into(),
                     weight: 1,
                     max_fails: 1,
                     fail_timeout: "30s".into(),
+                    max_conns: 0,
                 },
                 StreamUpstreamServer {
                     addr: "127.0.0.1:10002".into(),
                     weight: 1,
                     max_fails: 1,
                     fail_timeout: "30s".into(),
+                    max_conns: 0,
                 },
             ],
         }
@@ -847,12 +2411,43 @@ mod tests {
                 proxy_pass: "backend".into(),
                 proxy_connect_timeout: "3s".into(),
                 proxy_timeout: "30s".into(),
+                max_session_duration: None,
                 udp: false,
                 listen_addr: Some("127.0.0.1:7000".into()),
+                sni_routing: vec![],
+                proxy_protocol: false,
+                connect_retries: 2,
+                limit_rate: None,
+                limit_rate_total: None,
+                limit_pps: None,
             }],
+            drain_timeout: None,
         }
     }
 
+    // 重载测试共用全局的 STREAM_SERVERS，需串行执行
+    static RELOAD_TEST_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    fn free_addr() -> String {
+        let probe = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        probe.local_addr().unwrap().to_string()
+    }
+
+    fn listen_server(addr: &str) -> StreamServer {
+        toml::from_str(&format!(
+            "enabled = true\nproxy_pass = \"backend\"\nlisten_addr = \"{addr}\""
+        ))
+        .unwrap()
+    }
+
+    fn running_servers() -> Vec<StreamServer> {
+        STREAM_SERVERS
+            .read()
+            .iter()
+            .map(|h| h.server.clone())
+            .collect()
+    }
+
     fn sample_client() -> SocketAddr {
         SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 9)), 45678)
     }
@@ -886,8 +2481,15 @@ mod tests {
             proxy_pass: "backend".into(),
             proxy_connect_timeout: "3s".into(),
             proxy_timeout: "30s".into(),
+            max_session_duration: None,
             udp: false,
             listen_addr: Some("127.0.0.1:7000".into()),
+            sni_routing: vec![],
+            proxy_protocol: false,
+            connect_retries: 2,
+            limit_rate: None,
+            limit_rate_total: None,
+            limit_pps: None,
         });
 
         let err = validate_stream_config(&cfg).unwrap_err().to_string();
@@ -903,8 +2505,15 @@ mod tests {
             proxy_pass: "backend".into(),
             proxy_connect_timeout: "3s".into(),
             proxy_timeout: "30s".into(),
+            max_session_duration: None,
             udp: true,
             listen_addr: Some("127.0.0.1:7000".into()),
+            sni_routing: vec![],
+            proxy_protocol: false,
+            connect_retries: 2,
+            limit_rate: None,
+            limit_rate_total: None,
+            limit_pps: None,
         });
 
         validate_stream_config(&cfg).unwrap();
@@ -960,7 +2569,7 @@ mod tests {
             .addr
             .clone();
 
-        record_upstream_failure(&primary, 1, "30s");
+        record_upstream_failure(&primary, 1, "30s", "connect failed");
         assert!(is_down(&primary));
 
         let fallback = select_upstream_server_with_failover(&upstream, &client)
@@ -974,4 +2583,817 @@ mod tests {
         FAIL_MAP.clear();
         HASH_RING_CACHE.clear();
     }
+
+    #[tokio::test]
+    async fn copy_counted_reports_bytes_and_shuts_down_writer() {
+        use std::sync::atomic::AtomicU64;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (mut src_tx, mut src_rx) = tokio::io::duplex(64);
+        let (mut dst_tx, mut dst_rx) = tokio::io::duplex(64);
+        src_tx.write_all(b"hello stream").await.unwrap();
+        drop(src_tx);
+
+        let counted = AtomicU64::new(0);
+        copy_counted(&mut src_rx, &mut dst_tx, |n| {
+            counted.fetch_add(n, Ordering::Relaxed);
+            Duration::ZERO
+        })
+        .await
+        .unwrap();
+
+        let mut out = Vec::new();
+        dst_rx.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, b"hello stream");
+        assert_eq!(counted.load(Ordering::Relaxed), 12);
+    }
+
+    #[tokio::test]
+    async fn active_session_outlives_idle_timeout_while_idle_one_expires() {
+        let timeouts = SessionTimeouts {
+            idle: Duration::from_millis(150),
+            max_duration: None,
+        };
+
+        // 持续有流量的会话：总时长远超 idle，仍不过期
+        let active = SessionActivity::new();
+        let expired = tokio::select! {
+            reason = wait_session_expired(&active, timeouts) => Some(reason),
+            _ = async {
+                for _ in 0..12 {
+                    time::sleep(Duration::from_millis(40)).await;
+                    active.touch();
+                }
+            } => None,
+        };
+        assert_eq!(expired, None);
+        assert!(active.elapsed() > Duration::from_millis(400));
+
+        // 无流量的会话按空闲超时关闭
+        let idle = SessionActivity::new();
+        let reason = time::timeout(
+            Duration::from_secs(2),
+            wait_session_expired(&idle, timeouts),
+        )
+        .await
+        .unwrap();
+        assert_eq!(reason, "idle timeout");
+        assert!(idle.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[tokio::test]
+    async fn max_session_duration_closes_active_session() {
+        let timeouts = SessionTimeouts {
+            idle: Duration::from_millis(150),
+            max_duration: Some(Duration::from_millis(250)),
+        };
+        let activity = SessionActivity::new();
+        let reason = tokio::select! {
+            reason = wait_session_expired(&activity, timeouts) => Some(reason),
+            _ = async {
+                for _ in 0..50 {
+                    time::sleep(Duration::from_millis(40)).await;
+                    activity.touch();
+                }
+            } => None,
+        };
+        assert_eq!(reason, Some("max session duration reached"));
+        assert!(activity.elapsed() >= Duration::from_millis(250));
+    }
+
+    #[test]
+    fn validate_stream_config_rejects_rate_limits_for_wrong_protocol() {
+        let mut cfg = sample_config();
+        cfg.servers[0].limit_pps = Some(100);
+        let err = validate_stream_config(&cfg).unwrap_err().to_string();
+        assert!(err.contains("limit_pps is only supported for UDP"));
+
+        cfg.servers[0].limit_pps = None;
+        cfg.servers[0].udp = true;
+        cfg.servers[0].limit_rate = Some(1024);
+        let err = validate_stream_config(&cfg).unwrap_err().to_string();
+        assert!(err.contains("limit_rate is only supported for TCP"));
+    }
+
+    #[test]
+    fn validate_stream_config_rejects_invalid_max_session_duration() {
+        let mut cfg = sample_config();
+        cfg.servers[0].max_session_duration = Some("forever".into());
+        let err = validate_stream_config(&cfg).unwrap_err().to_string();
+        assert!(err.contains("invalid max_session_duration"));
+    }
+
+    #[tokio::test]
+    async fn udp_replies_go_only_to_owning_client_session() {
+        // 上游回显：回包内容带上发送方数据，便于识别归属
+        let upstream_sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let upstream_addr = upstream_sock.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            loop {
+                let (n, from) = upstream_sock.recv_from(&mut buf).await.unwrap();
+                let mut reply = b"echo:".to_vec();
+                reply.extend_from_slice(&buf[..n]);
+                upstream_sock.send_to(&reply, from).await.unwrap();
+            }
+        });
+
+        let mut upstream = sample_upstream();
+        upstream.servers.truncate(1);
+        upstream.servers[0].addr = upstream_addr.to_string();
+
+        let listen = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let listen_addr = listen.local_addr().unwrap();
+        let stats = stream_stats("udp-fanout-test", true, &upstream.name);
+        let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
+        let server = tokio::spawn(run_udp_server(
+            listen,
+            Arc::new(RwLock::new(upstream)),
+            stats.clone(),
+            Duration::from_secs(30),
+            None,
+            |_: &SocketAddr| true,
+            shutdown_rx,
+        ));
+
+        let a = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let b = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        a.send_to(b"from-a", listen_addr).await.unwrap();
+        b.send_to(b"from-b", listen_addr).await.unwrap();
+
+        let mut buf = [0u8; 1024];
+        for (client, expected) in [(&a, &b"echo:from-a"[..]), (&b, &b"echo:from-b"[..])] {
+            let n = time::timeout(Duration::from_secs(2), client.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(&buf[..n], expected);
+            // 不应再收到其他客户端的回包
+            assert!(
+                time::timeout(Duration::from_millis(200), client.recv(&mut buf))
+                    .await
+                    .is_err()
+            );
+        }
+        assert_eq!(stats.traffic.active.load(Ordering::Relaxed), 2);
+
+        shutdown_tx.send(Duration::ZERO).await.unwrap();
+        server.await.unwrap();
+        assert_eq!(stats.traffic.active.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn relay_throttle_applies_connection_and_listener_limits_per_direction() {
+        let opts = TcpSessionOptions {
+            connect_timeout: Duration::from_secs(1),
+            timeouts: SessionTimeouts {
+                idle: Duration::from_secs(30),
+                max_duration: None,
+            },
+            proxy_protocol: false,
+            connect_retries: 2,
+            limit_rate: Some(1000),
+            total_buckets: Some(Arc::new(direction_buckets(500))),
+        };
+
+        let a = RelayThrottle::new(&opts);
+        let b = RelayThrottle::new(&opts);
+        // 单连接 1000B/s 未超，但监听总量 500B/s 已透支 500B
+        let wait = a.delay(true, 1000);
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+        // 另一连接同方向共享总量令牌桶，反方向互不影响
+        assert!(b.delay(true, 1) > Duration::from_millis(900));
+        assert_eq!(b.delay(false, 400), Duration::ZERO);
+
+        let unlimited = TcpSessionOptions {
+            limit_rate: None,
+            total_buckets: None,
+            ..opts
+        };
+        assert_eq!(
+            RelayThrottle::new(&unlimited).delay(true, 1 << 30),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn session_stats_keep_per_session_bytes_separate() {
+        let stats = stream_stats("session-bytes-test", false, "backend");
+        let a = StreamSessionStats::new(stats.clone(), "127.0.0.1:1");
+        let b = StreamSessionStats::new(stats.clone(), "127.0.0.1:1");
+        a.on_bytes(true, 10);
+        a.on_bytes(false, 20);
+        b.on_bytes(true, 5);
+
+        assert_eq!(a.session.bytes_in.load(Ordering::Relaxed), 10);
+        assert_eq!(a.session.bytes_out.load(Ordering::Relaxed), 20);
+        assert_eq!(b.session.bytes_in.load(Ordering::Relaxed), 5);
+        assert_eq!(stats.traffic.bytes_in.load(Ordering::Relaxed), 15);
+        assert_eq!(a.upstream.bytes_out.load(Ordering::Relaxed), 20);
+    }
+
+    #[test]
+    fn udp_session_expiry_respects_ttl() {
+        let ttl = Duration::from_secs(10);
+        assert!(!udp_session_expired(1_000, 1_000, ttl));
+        assert!(!udp_session_expired(1_000, 11_000, ttl));
+        assert!(udp_session_expired(1_000, 11_001, ttl));
+        // 时间戳异常回退时不应误判过期
+        assert!(!udp_session_expired(5_000, 1_000, ttl));
+    }
+
+    #[test]
+    fn udp_recv_errors_split_transient_from_fatal() {
+        let err = |kind: io::ErrorKind| io::Error::new(kind, "test");
+        assert!(is_transient_udp_error(&err(
+            io::ErrorKind::ConnectionRefused
+        )));
+        assert!(is_transient_udp_error(&err(io::ErrorKind::ConnectionReset)));
+        assert!(!is_transient_udp_error(&err(io::ErrorKind::NotConnected)));
+        assert!(!is_transient_udp_error(&err(io::ErrorKind::Other)));
+    }
+
+    #[tokio::test]
+    async fn udp_session_last_seen_advances_with_clock() {
+        let listen = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let upstream_addr = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut server = sample_upstream().servers.remove(0);
+        server.addr = upstream_addr.to_string();
+        let stats = stream_stats("udp-clock-test", true, "backend");
+        let session = UdpSession::open(
+            listen.clone(),
+            listen.local_addr().unwrap(),
+            server,
+            StreamSessionStats::new(stats, &upstream_addr.to_string()),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let ttl = Duration::from_millis(100);
+        let created = session.last_seen_ms();
+        time::sleep(Duration::from_millis(150)).await;
+        assert!(now_ms() >= created + 150);
+        assert!(udp_session_expired(session.last_seen_ms(), now_ms(), ttl));
+
+        // 有新流量后重新计时
+        session.touch();
+        assert!(session.last_seen_ms() >= created + 150);
+        assert!(!udp_session_expired(session.last_seen_ms(), now_ms(), ttl));
+    }
+
+    fn client_hello(sni: &str) -> Vec<u8> {
+        let name = sni.as_bytes();
+        let mut server_name = vec![0x00];
+        server_name.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        server_name.extend_from_slice(name);
+        let mut ext = (server_name.len() as u16).to_be_bytes().to_vec();
+        ext.extend_from_slice(&server_name);
+
+        let mut extensions = vec![0x00, 0x0b, 0x00, 0x02, 0x01, 0x00]; // ec_point_formats
+        extensions.extend_from_slice(&[0x00, 0x00]);
+        extensions.extend_from_slice(&(ext.len() as u16).to_be_bytes());
+        extensions.extend_from_slice(&ext);
+
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[7u8; 32]);
+        body.push(0); // session_id
+        body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]);
+        body.extend_from_slice(&[0x01, 0x00]);
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut handshake = vec![0x01];
+        handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(&body);
+        handshake
+    }
+
+    fn tls_records(handshake: &[u8], split_at: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        for part in [&handshake[..split_at], &handshake[split_at..]] {
+            out.extend_from_slice(&[0x16, 0x03, 0x01]);
+            out.extend_from_slice(&(part.len() as u16).to_be_bytes());
+            out.extend_from_slice(part);
+        }
+        out
+    }
+
+    #[test]
+    fn parse_client_hello_sni_handles_fragmented_records() {
+        let handshake = client_hello("Chat.Example.com");
+        let wire = tls_records(&handshake, 20);
+
+        assert_eq!(
+            parse_client_hello_sni(&wire),
+            SniParse::Done(Some("chat.example.com".into()))
+        );
+        for cut in [3, 10, 30, wire.len() - 1] {
+            assert_eq!(parse_client_hello_sni(&wire[..cut]), SniParse::Incomplete);
+        }
+        assert_eq!(
+            parse_client_hello_sni(b"GET / HTTP/1.1\r\n"),
+            SniParse::Done(None)
+        );
+    }
+
+    #[test]
+    fn tcp_router_prefers_exact_sni_then_wildcard_then_default() {
+        let mut cfg = StreamProxyConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        for name in ["a", "wild", "fallback"] {
+            let mut u = sample_upstream();
+            u.name = name.into();
+            cfg.upstreams.push(u);
+        }
+        let mut server: StreamServer = toml::from_str(
+            r#"
+enabled = true
+listen_addr = "127.0.0.1:8443"
+proxy_pass = "fallback"
+sni_routing = [
+  { sni = "*.example.com", proxy_pass = "wild" },
+  { sni = "a.example.com", proxy_pass = "a" },
+]
+"#,
+        )
+        .unwrap();
+        validate_stream_config(&StreamProxyConfig {
+            servers: vec![server.clone()],
+            ..cfg.clone()
+        })
+        .unwrap();
+
+        let router = TcpUpstreamRouter::new(&cfg, &server).unwrap();
+        let pick = |sni: Option<&str>| router.select(sni).map(|u| u.name.clone());
+        assert_eq!(pick(Some("a.example.com")).as_deref(), Some("a"));
+        assert_eq!(pick(Some("b.example.com")).as_deref(), Some("wild"));
+        assert_eq!(pick(Some("other.org")).as_deref(), Some("fallback"));
+        assert_eq!(pick(None).as_deref(), Some("fallback"));
+
+        server.proxy_pass.clear();
+        let router = TcpUpstreamRouter::new(&cfg, &server).unwrap();
+        assert!(router.select(Some("other.org")).is_none());
+    }
+
+    #[tokio::test]
+    async fn proxy_v2_header_reaches_upstream_with_exact_bytes() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::{TcpListener, TcpStream};
+
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_addr = upstream.local_addr().unwrap();
+
+        let src: SocketAddr = "192.0.2.10:51000".parse().unwrap();
+        let dst: SocketAddr = "[::ffff:198.51.100.1]:443".parse().unwrap();
+        let header = proxy_v2_header(src, dst);
+
+        let mut conn = TcpStream::connect(upstream_addr).await.unwrap();
+        conn.write_all(&header).await.unwrap();
+        let (mut accepted, _) = upstream.accept().await.unwrap();
+        let mut got = vec![0u8; 28];
+        accepted.read_exact(&mut got).await.unwrap();
+
+        let mut expected = vec![
+            0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A, 0x21, 0x11,
+            0x00, 0x0C,
+        ];
+        expected.extend_from_slice(&[192, 0, 2, 10, 198, 51, 100, 1]);
+        expected.extend_from_slice(&51000u16.to_be_bytes());
+        expected.extend_from_slice(&443u16.to_be_bytes());
+        assert_eq!(got, expected);
+
+        let v6 = proxy_v2_header("[2001:db8::1]:40000".parse().unwrap(), dst);
+        assert_eq!(&v6[12..16], &[0x21, 0x21, 0x00, 0x24]);
+        assert_eq!(
+            &v6[16..32],
+            &"2001:db8::1"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets()
+        );
+        assert_eq!(
+            &v6[32..48],
+            &"::ffff:198.51.100.1"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets()
+        );
+        assert_eq!(v6.len(), 16 + 36);
+    }
+
+    #[tokio::test]
+    async fn stream_listen_addrs_bind_ipv4_and_ipv6_loopback_on_same_port() {
+        let probe = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = probe.local_addr().unwrap().port();
+        drop(probe);
+
+        let mut cfg = StreamProxyConfig {
+            enabled: true,
+            upstreams: vec![sample_upstream()],
+            servers: vec![],
+            drain_timeout: None,
+        };
+        for addr in [format!("127.0.0.1:{port}"), format!("[::1]:{port}")] {
+            let server: StreamServer = toml::from_str(&format!(
+                "enabled = true\nproxy_pass = \"backend\"\nlisten_addr = \"{addr}\""
+            ))
+            .unwrap();
+            cfg.servers.push(server);
+        }
+        validate_stream_config(&cfg).unwrap();
+
+        assert_eq!(
+            resolve_listen_addr(&cfg.servers[1]).unwrap(),
+            format!("[::1]:{port}")
+        );
+        let v4 = tokio::net::TcpListener::bind(resolve_listen_addr(&cfg.servers[0]).unwrap())
+            .await
+            .unwrap();
+        // 部分 CI 环境未启用 IPv6，此时只校验 IPv4 部分
+        if let Ok(v6) =
+            tokio::net::TcpListener::bind(resolve_listen_addr(&cfg.servers[1]).unwrap()).await
+        {
+            assert!(v6.local_addr().unwrap().is_ipv6());
+        }
+        assert_eq!(v4.local_addr().unwrap().port(), port);
+
+        assert!(parse_stream_listen_addr(&format!(":{port}"))
+            .unwrap()
+            .ip()
+            .is_unspecified());
+    }
+
+    fn weighted_upstream(consistent: bool, hash_key: &str, weights: [i32; 3]) -> StreamUpstream {
+        let mut upstream = sample_upstream();
+        upstream.name = format!("weighted-{consistent}-{hash_key}");
+        upstream.consistent = consistent;
+        upstream.hash_key = hash_key.into();
+        upstream.servers = weights
+            .iter()
+            .enumerate()
+            .map(|(i, w)| StreamUpstreamServer {
+                addr: format!("127.0.0.1:{}", 11001 + i),
+                weight: *w,
+                max_fails: 1,
+                fail_timeout: "30s".into(),
+                max_conns: 0,
+            })
+            .collect();
+        upstream
+    }
+
+    fn distribution(upstream: &StreamUpstream) -> [usize; 3] {
+        let mut counts = [0usize; 3];
+        for i in 0..20_000u32 {
+            let client = SocketAddr::new(IpAddr::V4(Ipv4Addr::from(0x0A00_0000 + i)), 40000);
+            let addr = &select_upstream_server_with_failover(upstream, &client)
+                .unwrap()
+                .addr;
+            let idx = upstream
+                .servers
+                .iter()
+                .position(|s| &s.addr == addr)
+                .unwrap();
+            counts[idx] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn weighted_selection_follows_weights_and_skips_zero_weight() {
+        for (consistent, hash_key) in [
+            (true, "$remote_addr"),
+            (false, "$remote_addr"),
+            (false, "rr"),
+        ] {
+            let upstream = weighted_upstream(consistent, hash_key, [3, 1, 0]);
+            let counts = distribution(&upstream);
+            let share = counts[0] as f64 / (counts[0] + counts[1]) as f64;
+            assert!(
+                (0.70..=0.80).contains(&share),
+                "consistent={consistent} hash_key={hash_key} counts={counts:?}"
+            );
+            assert_eq!(counts[2], 0);
+        }
+
+        // 权重为 0 的服务器只在其余服务器全部不可用时被选中
+        let upstream = weighted_upstream(true, "$remote_addr", [3, 1, 0]);
+        record_upstream_failure(&upstream.servers[0].addr, 1, "30s", "connect failed");
+        record_upstream_failure(&upstream.servers[1].addr, 1, "30s", "connect failed");
+        assert_eq!(distribution(&upstream)[2], 20_000);
+
+        for server in &upstream.servers {
+            record_upstream_success(&server.addr);
+        }
+    }
+
+    #[test]
+    fn least_conn_picks_fewest_active_then_heavier_weight() {
+        let mut upstream = weighted_upstream(false, "rr", [1, 2, 0]);
+        upstream.least_conn = true;
+        upstream.servers[0].addr = "127.0.0.1:12001".into();
+        upstream.servers[1].addr = "127.0.0.1:12002".into();
+        upstream.servers[2].addr = "127.0.0.1:12003".into();
+
+        let pick = |u: &StreamUpstream| select_least_conn_server(u, &[]).unwrap().addr.clone();
+        assert_eq!(pick(&upstream), "127.0.0.1:12002");
+
+        let a = UpstreamActiveGuard::acquire("127.0.0.1:12002");
+        assert_eq!(pick(&upstream), "127.0.0.1:12001");
+        let b = UpstreamActiveGuard::acquire("127.0.0.1:12001");
+        assert_eq!(pick(&upstream), "127.0.0.1:12002");
+
+        drop(a);
+        drop(b);
+        assert_eq!(upstream_active("127.0.0.1:12001"), 0);
+        assert_eq!(upstream_active("127.0.0.1:12002"), 0);
+    }
+
+    #[tokio::test]
+    async fn max_conns_skips_capped_servers_and_waits_in_queue() {
+        let mut upstream = weighted_upstream(false, "rr", [1, 1, 0]);
+        upstream.name = "max-conns".into();
+        upstream.servers.truncate(2);
+        upstream.servers[0].addr = "127.0.0.1:13001".into();
+        upstream.servers[1].addr = "127.0.0.1:13002".into();
+        for s in &mut upstream.servers {
+            s.max_conns = 2;
+        }
+        let client = sample_client();
+
+        // 两台各 2 个名额，占满后不再超发
+        let mut guards = Vec::new();
+        for _ in 0..4 {
+            let (_, guard) = acquire_tcp_upstream_server(&upstream, &client, Duration::ZERO, &[])
+                .await
+                .unwrap();
+            guards.push(guard);
+        }
+        assert_eq!(upstream_active("127.0.0.1:13001"), 2);
+        assert_eq!(upstream_active("127.0.0.1:13002"), 2);
+        assert!(UpstreamActiveGuard::try_acquire("127.0.0.1:13001", 2).is_none());
+
+        let err = acquire_tcp_upstream_server(&upstream, &client, Duration::ZERO, &[])
+            .await
+            .err()
+            .unwrap();
+        assert!(err.is::<StreamSaturated>());
+
+        // 排队期间有连接释放，则拿到空出的名额
+        let released = guards.pop().unwrap();
+        let (server, _guard) = tokio::join!(
+            acquire_tcp_upstream_server(&upstream, &client, Duration::from_secs(2), &[]),
+            async move {
+                time::sleep(Duration::from_millis(100)).await;
+                drop(released);
+            }
+        )
+        .0
+        .unwrap();
+        assert!(server.addr == "127.0.0.1:13001" || server.addr == "127.0.0.1:13002");
+        assert_eq!(
+            upstream_active("127.0.0.1:13001") + upstream_active("127.0.0.1:13002"),
+            4
+        );
+    }
+
+    #[tokio::test]
+    async fn tcp_client_retries_next_upstream_after_connect_failure() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::{TcpListener, TcpStream};
+
+        // 先占用再释放端口，得到一个必然拒绝连接的地址
+        let dead_addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let live = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live_addr = live.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut sock, _) = live.accept().await.unwrap();
+            let mut buf = [0u8; 4];
+            sock.read_exact(&mut buf).await.unwrap();
+            sock.write_all(&buf).await.unwrap();
+        });
+
+        // least_conn 在活跃数相同时按顺序选择，保证先尝试不可用的服务器
+        let mut upstream = weighted_upstream(false, "rr", [1, 1, 0]);
+        upstream.least_conn = true;
+        upstream.servers.truncate(2);
+        upstream.servers[0].addr = dead_addr.clone();
+        upstream.servers[1].addr = live_addr.clone();
+        let router = TcpUpstreamRouter {
+            default: Some(upstream),
+            sni: Arc::from(Vec::new()),
+        };
+        let opts = TcpSessionOptions {
+            connect_timeout: Duration::from_secs(2),
+            timeouts: SessionTimeouts {
+                idle: Duration::from_secs(5),
+                max_duration: None,
+            },
+            proxy_protocol: false,
+            connect_retries: 1,
+            limit_rate: None,
+            total_buckets: None,
+        };
+
+        let front = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(front.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (accepted, client_addr) = front.accept().await.unwrap();
+        let stats = stream_stats("tcp-retry-test", false, "backend");
+        let proxy = tokio::spawn(async move {
+            handle_tcp_client(accepted, client_addr, &router, &opts, &stats).await
+        });
+
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        drop(client);
+        proxy.await.unwrap().unwrap();
+
+        assert!(is_down(&dead_addr));
+        record_upstream_success(&dead_addr);
+    }
+
+    #[tokio::test]
+    async fn reload_keeps_unchanged_tcp_listener_and_drains_removed_one() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::{TcpListener, TcpStream};
+
+        let _serial = RELOAD_TEST_LOCK.lock().await;
+
+        async fn roundtrip(sock: &mut TcpStream, msg: &[u8]) {
+            sock.write_all(msg).await.unwrap();
+            let mut buf = vec![0u8; msg.len()];
+            time::timeout(Duration::from_secs(2), sock.read_exact(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(buf, msg);
+        }
+
+        let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let echo_addr = echo.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((mut sock, _)) = echo.accept().await {
+                tokio::spawn(async move {
+                    let (mut r, mut w) = sock.split();
+                    let _ = tokio::io::copy(&mut r, &mut w).await;
+                });
+            }
+        });
+
+        let kept_addr = free_addr();
+        let removed_addr = free_addr();
+        let mut upstream = sample_upstream();
+        upstream.servers.truncate(1);
+        upstream.servers[0].addr = echo_addr;
+        let mut cfg = StreamProxyConfig {
+            enabled: true,
+            upstreams: vec![upstream],
+            servers: vec![listen_server(&kept_addr), listen_server(&removed_addr)],
+            drain_timeout: Some("5s".into()),
+        };
+        reload_stream_servers(None, &cfg).await.unwrap();
+
+        let mut kept = TcpStream::connect(&kept_addr).await.unwrap();
+        let mut removed = TcpStream::connect(&removed_addr).await.unwrap();
+        roundtrip(&mut kept, b"one").await;
+        roundtrip(&mut removed, b"one").await;
+
+        // 修改 upstream 分组并移除第二个监听，第一个监听的配置保持不变
+        cfg.upstreams[0].servers[0].weight = 2;
+        cfg.servers.truncate(1);
+        reload_stream_servers(None, &cfg).await.unwrap();
+
+        roundtrip(&mut kept, b"two").await;
+        // 被移除的监听不再接收新连接，已建立的会话在 drain 期间继续转发
+        roundtrip(&mut removed, b"two").await;
+        assert!(TcpStream::connect(&removed_addr).await.is_err());
+        let mut fresh = TcpStream::connect(&kept_addr).await.unwrap();
+        roundtrip(&mut fresh, b"three").await;
+
+        reload_stream_servers(None, &StreamProxyConfig::default())
+            .await
+            .unwrap();
+        assert!(TcpStream::connect(&kept_addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reload_does_not_reuse_listener_across_tcp_and_udp() {
+        use tokio::net::TcpStream;
+
+        let _serial = RELOAD_TEST_LOCK.lock().await;
+        let addr = free_addr();
+        let tcp = listen_server(&addr);
+        let mut udp = tcp.clone();
+        udp.udp = true;
+        let mut cfg = StreamProxyConfig {
+            enabled: true,
+            upstreams: vec![sample_upstream()],
+            servers: vec![tcp.clone()],
+            drain_timeout: Some("1s".into()),
+        };
+        reload_stream_servers(None, &cfg).await.unwrap();
+
+        // 同一地址在 TCP 之前加上 UDP：UDP 不能占用 TCP 的监听，TCP 也不应重新绑定
+        cfg.servers.insert(0, udp.clone());
+        reload_stream_servers(None, &cfg).await.unwrap();
+        let mut kinds: Vec<bool> = running_servers().iter().map(|s| s.udp).collect();
+        kinds.sort();
+        assert_eq!(kinds, vec![false, true]);
+        assert!(TcpStream::connect(&addr).await.is_ok());
+
+        // 只留下 UDP：TCP 监听停止
+        cfg.servers = vec![udp];
+        reload_stream_servers(None, &cfg).await.unwrap();
+        assert!(running_servers().iter().all(|s| s.udp));
+        assert!(TcpStream::connect(&addr).await.is_err());
+
+        reload_stream_servers(None, &StreamProxyConfig::default())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn reload_restarts_listener_when_only_timeout_changes() {
+        use tokio::net::TcpStream;
+
+        let _serial = RELOAD_TEST_LOCK.lock().await;
+        let addr = free_addr();
+        let mut cfg = StreamProxyConfig {
+            enabled: true,
+            upstreams: vec![sample_upstream()],
+            servers: vec![listen_server(&addr)],
+            drain_timeout: Some("1s".into()),
+        };
+        reload_stream_servers(None, &cfg).await.unwrap();
+
+        cfg.servers[0].proxy_timeout = "42s".into();
+        reload_stream_servers(None, &cfg).await.unwrap();
+        let running = running_servers();
+        assert_eq!(running.len(), 1);
+        assert_eq!(running[0].proxy_timeout, "42s");
+        assert!(TcpStream::connect(&addr).await.is_ok());
+
+        reload_stream_servers(None, &StreamProxyConfig::default())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn hostname_servers_expand_to_every_resolved_address() {
+        let host = "db.expand-test.internal:5432";
+        RESOLVED_HOSTS.insert(
+            host.to_string(),
+            Arc::from(vec![
+                "10.0.0.1:5432".parse::<SocketAddr>().unwrap(),
+                "10.0.0.2:5432".parse().unwrap(),
+            ]),
+        );
+        let mut upstream = sample_upstream();
+        upstream.servers[0].addr = host.into();
+        upstream.servers[0].weight = 3;
+
+        let expanded = expand_upstream(&upstream);
+        let addrs: Vec<(&str, i32)> = expanded
+            .servers
+            .iter()
+            .map(|s| (s.addr.as_str(), s.weight))
+            .collect();
+        assert_eq!(
+            addrs,
+            vec![
+                ("10.0.0.1:5432", 3),
+                ("10.0.0.2:5432", 3),
+                ("127.0.0.1:10002", 1)
+            ]
+        );
+        RESOLVED_HOSTS.remove(host);
+    }
+
+    #[tokio::test]
+    async fn resolve_stream_upstreams_names_unresolvable_host() {
+        let mut cfg = sample_config();
+        cfg.upstreams[0].servers[0].addr = "backend.invalid:5432".into();
+        validate_stream_config(&cfg).unwrap();
+        let err = resolve_stream_upstreams(&cfg)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("backend.invalid"));
+    }
 }
@@ -0,0 +1,297 @@
+use anyhow::{anyhow, Context, Result};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+
+use super::logging::{push_log_line, LogEntry, LogLevel};
+use crate::config::SyslogConfig;
+
+const SYSLOG_QUEUE_CAPACITY: usize = 10_000;
+const RECONNECT_BACKOFF_BASE: Duration = Duration::from_secs(1);
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(60);
+// 超长的单条日志在 UDP 下截断，避免超过数据报上限
+const MAX_UDP_MESSAGE_BYTES: usize = 8 * 1024;
+const APP_NAME: &str = "sslproxymanager";
+
+/// 队列已满或发送失败而丢弃的日志条数
+pub static SYSLOG_DROPPED: AtomicU64 = AtomicU64::new(0);
+
+// 当前生效的配置与发送队列；替换或关闭时丢弃发送端，后台任务随之退出
+type ActiveSink = (SyslogConfig, mpsc::Sender<LogEntry>);
+static SYSLOG: Lazy<RwLock<Option<ActiveSink>>> = Lazy::new(|| RwLock::new(None));
+static SYSLOG_GEN: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transport {
+    Udp,
+    Tcp,
+}
+
+pub fn parse_transport(protocol: &str) -> Result<()> {
+    transport(protocol).map(|_| ())
+}
+
+fn transport(protocol: &str) -> Result<Transport> {
+    match protocol.trim().to_ascii_lowercase().as_str() {
+        "" | "udp" => Ok(Transport::Udp),
+        "tcp" => Ok(Transport::Tcp),
+        other => Err(anyhow!("unsupported syslog protocol: {}", other)),
+    }
+}
+
+pub fn parse_facility(name: &str) -> Result<u8> {
+    let name = name.trim().to_ascii_lowercase();
+    let code = match name.as_str() {
+        "kern" => 0,
+        "user" => 1,
+        "mail" => 2,
+        "daemon" => 3,
+        "auth" => 4,
+        "syslog" => 5,
+        "lpr" => 6,
+        "news" => 7,
+        "uucp" => 8,
+        "cron" => 9,
+        "authpriv" => 10,
+        "ftp" => 11,
+        _ => match name
+            .strip_prefix("local")
+            .and_then(|n| n.parse::<u8>().ok())
+        {
+            Some(n @ 0..=7) => 16 + n,
+            _ => return Err(anyhow!("unknown syslog facility: {}", name)),
+        },
+    };
+    Ok(code)
+}
+
+/// 结构化日志级别对应的 syslog severity
+fn severity(level: LogLevel) -> u8 {
+    match level {
+        LogLevel::Error => 3,
+        LogLevel::Warn => 4,
+        LogLevel::Info => 6,
+        LogLevel::Debug => 7,
+    }
+}
+
+/// 由日志写入缓冲时调用，只做非阻塞入队
+pub(crate) fn forward(entry: &LogEntry) {
+    let sink = SYSLOG.read();
+    let Some((_, tx)) = sink.as_ref() else {
+        return;
+    };
+    if tx.try_send(entry.clone()).is_err() {
+        SYSLOG_DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// 按配置启动/重启/关闭 syslog 输出；配置未变化时不做任何事
+pub fn apply_syslog_config(cfg: Option<SyslogConfig>) -> Result<()> {
+    let cfg = cfg.filter(|c| c.enabled);
+    if SYSLOG.read().as_ref().map(|(c, _)| c) == cfg.as_ref() {
+        return Ok(());
+    }
+    let Some(cfg) = cfg else {
+        SYSLOG_GEN.fetch_add(1, Ordering::SeqCst);
+        *SYSLOG.write() = None;
+        return Ok(());
+    };
+
+    let target = SyslogTarget::from_config(&cfg)?;
+    let (tx, rx) = mpsc::channel(SYSLOG_QUEUE_CAPACITY);
+    let generation = SYSLOG_GEN.fetch_add(1, Ordering::SeqCst) + 1;
+    *SYSLOG.write() = Some((cfg, tx));
+    tauri::async_runtime::spawn(run_sink(target, rx, generation));
+    Ok(())
+}
+
+struct SyslogTarget {
+    transport: Transport,
+    address: String,
+    facility: u8,
+    hostname: String,
+    pid: u32,
+}
+
+impl SyslogTarget {
+    fn from_config(cfg: &SyslogConfig) -> Result<Self> {
+        let address = cfg.address.trim();
+        if address.is_empty() {
+            return Err(anyhow!("syslog address is empty"));
+        }
+        let hostname = cfg
+            .hostname
+            .as_deref()
+            .map(str::trim)
+            .filter(|h| !h.is_empty())
+            .map(str::to_string)
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .or_else(|| std::env::var("COMPUTERNAME").ok())
+            .filter(|h| !h.is_empty())
+            .unwrap_or_else(|| "-".to_string());
+        Ok(Self {
+            transport: transport(&cfg.protocol)?,
+            address: address.to_string(),
+            facility: parse_facility(&cfg.facility)?,
+            hostname,
+            pid: std::process::id(),
+        })
+    }
+
+    /// RFC 5424：<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID SD MSG
+    fn format(&self, entry: &LogEntry) -> String {
+        let pri = u16::from(self.facility) * 8 + u16::from(severity(entry.level));
+        let ts = chrono::DateTime::from_timestamp_millis(entry.timestamp)
+            .unwrap_or_else(chrono::Utc::now)
+            .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        format!(
+            "<{}>1 {} {} {} {} - - {}",
+            pri, ts, self.hostname, APP_NAME, self.pid, entry.message
+        )
+    }
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+impl Connection {
+    async fn open(target: &SyslogTarget) -> Result<Self> {
+        let addr: SocketAddr = tokio::net::lookup_host(&target.address)
+            .await
+            .with_context(|| format!("failed to resolve syslog address: {}", target.address))?
+            .next()
+            .ok_or_else(|| anyhow!("syslog address not resolved: {}", target.address))?;
+        match target.transport {
+            Transport::Udp => {
+                let bind: SocketAddr = if addr.is_ipv4() {
+                    ([0, 0, 0, 0], 0).into()
+                } else {
+                    ([0u16; 8], 0).into()
+                };
+                let socket = UdpSocket::bind(bind).await?;
+                socket.connect(addr).await?;
+                Ok(Self::Udp(socket))
+            }
+            Transport::Tcp => Ok(Self::Tcp(TcpStream::connect(addr).await?)),
+        }
+    }
+
+    async fn send(&mut self, message: &str) -> std::io::Result<()> {
+        match self {
+            Self::Udp(socket) => {
+                let mut end = message.len().min(MAX_UDP_MESSAGE_BYTES);
+                while !message.is_char_boundary(end) {
+                    end -= 1;
+                }
+                socket.send(&message.as_bytes()[..end]).await.map(|_| ())
+            }
+            // TCP 使用 RFC 6587 的长度前缀分帧
+            Self::Tcp(stream) => {
+                let framed = format!("{} {}", message.len(), message);
+                stream.write_all(framed.as_bytes()).await
+            }
+        }
+    }
+}
+
+fn backoff_delay(failures: u32) -> Duration {
+    let exp = failures.saturating_sub(1).min(16);
+    (RECONNECT_BACKOFF_BASE * 2u32.pow(exp)).min(RECONNECT_BACKOFF_MAX)
+}
+
+async fn run_sink(target: SyslogTarget, mut rx: mpsc::Receiver<LogEntry>, generation: u64) {
+    let mut failures = 0u32;
+    // 连接断开时未发出的那一条，重连后先补发
+    let mut pending: Option<String> = None;
+
+    loop {
+        if SYSLOG_GEN.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        let mut conn = match Connection::open(&target).await {
+            Ok(conn) => {
+                if failures > 0 {
+                    push_log_line(format!(
+                        "[SYSLOG] Reconnected to {} after {} failed attempt(s)",
+                        target.address, failures
+                    ));
+                }
+                conn
+            }
+            Err(e) => {
+                failures = failures.saturating_add(1);
+                if failures == 1 {
+                    push_log_line(format!(
+                        "[SYSLOG] Connection to {} failed: {e:#}",
+                        target.address
+                    ));
+                } else {
+                    tracing::warn!("Syslog connection to {} failed: {e:#}", target.address);
+                }
+                tokio::time::sleep(backoff_delay(failures)).await;
+                continue;
+            }
+        };
+
+        loop {
+            let message = match pending.take() {
+                Some(m) => m,
+                None => match rx.recv().await {
+                    Some(entry) => target.format(&entry),
+                    None => return,
+                },
+            };
+            if let Err(e) = conn.send(&message).await {
+                failures = 1;
+                pending = Some(message);
+                push_log_line(format!(
+                    "[SYSLOG] Connection to {} lost: {e}, reconnecting",
+                    target.address
+                ));
+                tokio::time::sleep(backoff_delay(failures)).await;
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_rfc5424_with_level_severity() {
+        let cfg = SyslogConfig {
+            enabled: true,
+            protocol: "tcp".to_string(),
+            address: "127.0.0.1:514".to_string(),
+            facility: "local3".to_string(),
+            hostname: Some("edge-1".to_string()),
+        };
+        let target = SyslogTarget::from_config(&cfg).unwrap();
+        assert_eq!(target.transport, Transport::Tcp);
+
+        let mut entry = LogEntry::new(LogLevel::Error, None, "upstream failed".to_string());
+        entry.timestamp = 1_700_000_000_123;
+        assert_eq!(
+            target.format(&entry),
+            format!(
+                "<155>1 2023-11-14T22:13:20.123Z edge-1 sslproxymanager {} - - upstream failed",
+                std::process::id()
+            )
+        );
+
+        assert_eq!(parse_facility("LOCAL7").unwrap(), 23);
+        assert!(parse_facility("local8").is_err());
+        assert!(parse_transport("tls").is_err());
+        assert_eq!(backoff_delay(7), RECONNECT_BACKOFF_MAX);
+    }
+}
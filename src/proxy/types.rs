@@ -1,22 +1,30 @@
+use arc_swap::ArcSwap;
 use std::sync::Arc;
 
+use crate::app_events::AppEvents;
 use crate::config;
 
 #[derive(Clone)]
 pub(crate) struct AppState {
-    pub(crate) rule: config::ListenRule,
+    /// 本次请求使用的规则快照，proxy_handler 入口从 rule_slot 刷新
+    pub(crate) rule: Arc<config::ListenRule>,
+    /// 可在运行中替换的规则（如切换路由启用状态），不需要重启监听
+    pub(crate) rule_slot: Arc<ArcSwap<config::ListenRule>>,
+    /// 进行中的请求与 WebSocket 隧道，停止时据此 drain
+    pub(crate) in_flight: Arc<super::lifecycle::InFlight>,
     pub(crate) client_follow: reqwest::Client,
     pub(crate) client_nofollow: reqwest::Client,
-    pub(crate) app: tauri::AppHandle,
+    pub(crate) app: AppEvents,
     pub(crate) listen_addr: Arc<str>,
     pub(crate) server_port: u16,
     pub(crate) stream_proxy: bool,
     pub(crate) max_body_size: usize,
     pub(crate) max_response_body_size: usize,
+    /// 慢请求阈值（毫秒），0 表示关闭
+    pub(crate) slow_request_threshold_ms: f64,
     pub(crate) http_access_control_enabled: bool,
-    pub(crate) allow_all_lan: bool,
-    pub(crate) allow_all_ip: bool,
-    pub(crate) whitelist: Arc<[config::WhitelistEntry]>,
+    /// 全局访问名单，热重载时就地替换
+    pub(crate) access_lists: Arc<ArcSwap<crate::access_control::AccessLists>>,
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -24,12 +24,11 @@ pub static UPSTREAM_LB: once_cell::sync::Lazy<
 > = once_cell::sync::Lazy::new(DashMap::new);
 
 #[inline]
-pub fn upstream_signature(route: &config::Route) -> String {
+pub fn upstream_signature(upstreams: &[config::Upstream]) -> String {
     use std::fmt::Write;
     let mut buf = crate::buffer_pool::acquire_buffer();
 
-    let mut parts: Vec<String> = route
-        .upstreams
+    let mut parts: Vec<String> = upstreams
         .iter()
         .map(|u| format!("{}#{}", u.url, u.weight))
         .collect();
@@ -47,21 +46,25 @@ pub fn upstream_signature(route: &config::Route) -> String {
 
 #[inline]
 pub fn pick_upstream_smooth(route: &config::Route) -> Option<String> {
-    match route.upstreams.len() {
-        0 => return None,
-        1 => return Some(route.upstreams[0].url.clone()),
-        _ => {}
-    }
-
     let route_id = route.id.as_deref().unwrap_or("").trim();
-    if route_id.is_empty() {
+    if route_id.is_empty() && !route.upstreams.is_empty() {
         return Some(route.upstreams[0].url.clone());
     }
+    pick_smooth_weighted(route_id, &route.upstreams)
+}
 
-    let sig = upstream_signature(route);
+/// 平滑加权轮询（nginx 风格），key 用于区分不同路由的轮询状态
+pub fn pick_smooth_weighted(key: &str, upstreams: &[config::Upstream]) -> Option<String> {
+    match upstreams.len() {
+        0 => return None,
+        1 => return Some(upstreams[0].url.clone()),
+        _ => {}
+    }
+
+    let sig = upstream_signature(upstreams);
 
     let state_lock = UPSTREAM_LB
-        .entry(route_id.to_string())
+        .entry(key.to_string())
         .or_insert_with(|| {
             Arc::new(parking_lot::Mutex::new(SmoothLbState {
                 signature: String::new(),
@@ -73,9 +76,8 @@ pub fn pick_upstream_smooth(route: &config::Route) -> Option<String> {
 
     let mut entry = state_lock.lock();
 
-    if entry.signature != sig || entry.upstreams.len() != route.upstreams.len() {
-        let ups: Vec<SmoothUpstream> = route
-            .upstreams
+    if entry.signature != sig || entry.upstreams.len() != upstreams.len() {
+        let ups: Vec<SmoothUpstream> = upstreams
             .iter()
             .map(|u| SmoothUpstream {
                 url: u.url.clone(),
@@ -106,6 +108,30 @@ pub fn pick_upstream_smooth(route: &config::Route) -> Option<String> {
     Some(entry.upstreams[best_idx].url.clone())
 }
 
+/// 按 key（通常为客户端 IP）哈希选择上游，权重越大命中概率越高
+pub fn pick_weighted_hash(key: &str, upstreams: &[config::Upstream]) -> Option<String> {
+    use std::hash::{Hash, Hasher};
+
+    if upstreams.is_empty() {
+        return None;
+    }
+
+    let total: u64 = upstreams.iter().map(|u| u.weight.max(1) as u64).sum();
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    key.hash(&mut hasher);
+    let mut point = hasher.finish() % total;
+
+    for u in upstreams {
+        let w = u.weight.max(1) as u64;
+        if point < w {
+            return Some(u.url.clone());
+        }
+        point -= w;
+    }
+
+    upstreams.last().map(|u| u.url.clone())
+}
+
 pub fn build_upstream_url(
     upstream_base: &str,
     route_path: Option<&str>,
@@ -165,7 +191,10 @@ pub fn build_upstream_url(
 
 #[cfg(test)]
 mod tests {
-    use super::{build_upstream_url, pick_upstream_smooth, upstream_signature, UPSTREAM_LB};
+    use super::{
+        build_upstream_url, pick_upstream_smooth, pick_weighted_hash, upstream_signature,
+        UPSTREAM_LB,
+    };
     use crate::config::{Route, Upstream};
     use axum::http::Uri;
     use std::collections::HashMap;
@@ -210,7 +239,10 @@ mod tests {
         let a = route_with_upstreams(Some("r1"), vec![("http://b", 2), ("http://a", 1)]);
         let b = route_with_upstreams(Some("r1"), vec![("http://a", 1), ("http://b", 2)]);
 
-        assert_eq!(upstream_signature(&a), upstream_signature(&b));
+        assert_eq!(
+            upstream_signature(&a.upstreams),
+            upstream_signature(&b.upstreams)
+        );
     }
 
     #[test]
@@ -235,6 +267,19 @@ mod tests {
         UPSTREAM_LB.clear();
     }
 
+    #[test]
+    fn pick_weighted_hash_is_stable_per_key() {
+        let route = route_with_upstreams(None, vec![("ws://a", 1), ("ws://b", 3)]);
+        let first = pick_weighted_hash("10.0.0.1", &route.upstreams).unwrap();
+        for _ in 0..5 {
+            assert_eq!(
+                pick_weighted_hash("10.0.0.1", &route.upstreams).as_deref(),
+                Some(first.as_str())
+            );
+        }
+        assert!(pick_weighted_hash("10.0.0.1", &[]).is_none());
+    }
+
     #[test]
     fn build_upstream_url_rewrites_prefix_without_double_slash() {
         let uri: Uri = "/api/v1/users".parse().unwrap();
@@ -0,0 +1,368 @@
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::stream_proxy;
+use crate::app_events::AppEvents;
+use crate::config;
+
+/// 状态变化检查间隔；熔断到期恢复没有请求触发，只能靠轮询发现
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// HTTP 上游只做被动观测，不参与上游选择
+#[derive(Debug, Clone, Default)]
+struct HttpObservation {
+    fails: u32,
+    last_error: Option<String>,
+    last_check: i64,
+}
+
+static HTTP_OBSERVED: Lazy<DashMap<String, HttpObservation>> = Lazy::new(DashMap::new);
+
+/// 上游地址 -> 进行中的 HTTP 请求 / WS 会话数（stream 使用自己的活跃计数）
+static IN_FLIGHT: Lazy<DashMap<String, Arc<AtomicU64>>> = Lazy::new(DashMap::new);
+
+static WATCHER_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// 请求/会话结束时随 drop 减一
+#[derive(Debug)]
+pub struct InFlightGuard(Arc<AtomicU64>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub fn track_in_flight(upstream: &str) -> InFlightGuard {
+    let counter = match IN_FLIGHT.get(upstream) {
+        Some(c) => c.clone(),
+        None => IN_FLIGHT.entry(upstream.to_string()).or_default().clone(),
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+    InFlightGuard(counter)
+}
+
+fn in_flight(upstream: &str) -> u64 {
+    IN_FLIGHT
+        .get(upstream)
+        .map(|c| c.load(Ordering::Relaxed))
+        .unwrap_or(0)
+}
+
+/// 收到上游响应（不论状态码）
+pub fn record_http_success(upstream: &str) {
+    let now = chrono::Utc::now().timestamp();
+    if let Some(mut obs) = HTTP_OBSERVED.get_mut(upstream) {
+        obs.fails = 0;
+        obs.last_check = now;
+        return;
+    }
+    HTTP_OBSERVED.insert(
+        upstream.to_string(),
+        HttpObservation {
+            last_check: now,
+            ..Default::default()
+        },
+    );
+}
+
+/// 连接失败、超时等没有拿到响应的情况
+pub fn record_http_failure(upstream: &str, error: &str) {
+    let mut obs = HTTP_OBSERVED.entry(upstream.to_string()).or_default();
+    obs.fails = obs.fails.saturating_add(1);
+    obs.last_error = Some(error.to_string());
+    obs.last_check = chrono::Utc::now().timestamp();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpstreamKind {
+    Http,
+    Ws,
+    Stream,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HealthState {
+    Up,
+    /// 最近连续失败，但仍参与选择
+    Down,
+    /// 达到 max_fails，在 fail_timeout 内被跳过（仅 WS / stream）
+    CircuitOpen,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UpstreamHealth {
+    pub url: String,
+    pub state: HealthState,
+    pub consecutive_failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// 最近一次连接结果的时间（unix 秒），尚无流量时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_check: Option<i64>,
+    pub in_flight: u64,
+}
+
+/// 一个 HTTP/WS 路由或一个 stream 上游组
+#[derive(Debug, Clone, Serialize)]
+pub struct UpstreamHealthGroup {
+    pub kind: UpstreamKind,
+    pub name: String,
+    pub listen_addrs: Vec<String>,
+    pub upstreams: Vec<UpstreamHealth>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UpstreamHealthChange {
+    pub kind: UpstreamKind,
+    pub name: String,
+    pub url: String,
+    pub previous: HealthState,
+    pub state: HealthState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+fn http_health(url: &str) -> UpstreamHealth {
+    let obs = HTTP_OBSERVED.get(url).map(|o| o.clone());
+    let fails = obs.as_ref().map_or(0, |o| o.fails);
+    UpstreamHealth {
+        url: url.to_string(),
+        state: if fails > 0 {
+            HealthState::Down
+        } else {
+            HealthState::Up
+        },
+        consecutive_failures: fails,
+        last_error: obs.as_ref().and_then(|o| o.last_error.clone()),
+        last_check: obs.map(|o| o.last_check),
+        in_flight: in_flight(url),
+    }
+}
+
+/// WS 与 stream 共用 stream_proxy 的被动健康检查状态
+fn passive_health(addr: &str, in_flight: u64) -> UpstreamHealth {
+    let st = stream_proxy::fail_state(addr);
+    let fails = st.as_ref().map_or(0, |s| s.fails);
+    let state = if stream_proxy::is_down(addr) {
+        HealthState::CircuitOpen
+    } else if fails > 0 {
+        HealthState::Down
+    } else {
+        HealthState::Up
+    };
+    UpstreamHealth {
+        url: addr.to_string(),
+        state,
+        consecutive_failures: fails,
+        last_error: st.as_ref().and_then(|s| s.last_error.clone()),
+        last_check: st.map(|s| s.last_check),
+        in_flight,
+    }
+}
+
+fn rule_addrs(rule: &config::ListenRule) -> Vec<String> {
+    if rule.listen_addrs.is_empty() {
+        vec![rule.listen_addr.clone()]
+    } else {
+        rule.listen_addrs.clone()
+    }
+}
+
+fn route_name(route: &config::Route) -> String {
+    route
+        .id
+        .clone()
+        .filter(|id| !id.trim().is_empty())
+        .or_else(|| route.path.clone())
+        .unwrap_or_else(|| "/".to_string())
+}
+
+/// 配置中全部启用的上游及其当前状态，按路由 / stream 上游组分组
+pub fn upstream_health(cfg: &config::Config) -> Vec<UpstreamHealthGroup> {
+    let mut groups = Vec::new();
+
+    for rule in cfg.rules.iter().filter(|r| r.enabled) {
+        for route in rule.routes.iter().filter(|r| r.enabled) {
+            if route.upstreams.is_empty() {
+                continue;
+            }
+            groups.push(UpstreamHealthGroup {
+                kind: UpstreamKind::Http,
+                name: route_name(route),
+                listen_addrs: rule_addrs(rule),
+                upstreams: route
+                    .upstreams
+                    .iter()
+                    .map(|u| http_health(&u.url))
+                    .collect(),
+            });
+        }
+    }
+
+    if cfg.ws_proxy_enabled {
+        for rule in cfg.ws_proxy.iter().flatten().filter(|r| r.enabled) {
+            for route in &rule.routes {
+                groups.push(UpstreamHealthGroup {
+                    kind: UpstreamKind::Ws,
+                    name: route.path.clone(),
+                    listen_addrs: vec![rule.listen_addr.clone()],
+                    upstreams: route
+                        .upstreams
+                        .iter()
+                        .map(|u| passive_health(&u.url, in_flight(&u.url)))
+                        .collect(),
+                });
+            }
+        }
+    }
+
+    if cfg.stream.enabled {
+        for upstream in &cfg.stream.upstreams {
+            let mut listen_addrs: Vec<String> = cfg
+                .stream
+                .servers
+                .iter()
+                .filter(|s| s.enabled && s.proxy_pass == upstream.name)
+                .filter_map(|s| stream_proxy::resolve_listen_addr(s).ok())
+                .collect();
+            listen_addrs.sort();
+            listen_addrs.dedup();
+            groups.push(UpstreamHealthGroup {
+                kind: UpstreamKind::Stream,
+                name: upstream.name.clone(),
+                listen_addrs,
+                upstreams: upstream
+                    .servers
+                    .iter()
+                    .map(|s| passive_health(&s.addr, stream_proxy::upstream_active(&s.addr)))
+                    .collect(),
+            });
+        }
+    }
+
+    groups
+}
+
+type HealthKey = (UpstreamKind, String, String);
+
+/// 与上次结果比较，返回状态发生变化的上游；首次出现的上游视为之前是 up
+fn diff_health(
+    previous: &mut HashMap<HealthKey, HealthState>,
+    groups: &[UpstreamHealthGroup],
+) -> Vec<UpstreamHealthChange> {
+    let mut current = HashMap::new();
+    let mut changes = Vec::new();
+    for group in groups {
+        for u in &group.upstreams {
+            let key = (group.kind, group.name.clone(), u.url.clone());
+            let before = previous.get(&key).copied().unwrap_or(HealthState::Up);
+            if before != u.state {
+                changes.push(UpstreamHealthChange {
+                    kind: group.kind,
+                    name: group.name.clone(),
+                    url: u.url.clone(),
+                    previous: before,
+                    state: u.state,
+                    last_error: u.last_error.clone(),
+                });
+            }
+            current.insert(key, u.state);
+        }
+    }
+    *previous = current;
+    changes
+}
+
+/// 定期检查上游状态，变化时推送 `upstream-health-changed`
+pub fn start_health_watcher(app: AppEvents) {
+    if WATCHER_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    tauri::async_runtime::spawn(async move {
+        let mut previous = HashMap::new();
+        let mut ticker = tokio::time::interval(WATCH_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let groups = upstream_health(&config::get_config());
+            for change in diff_health(&mut previous, &groups) {
+                let _ = app.emit("upstream-health-changed", change);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(url: &str) -> UpstreamHealthGroup {
+        UpstreamHealthGroup {
+            kind: UpstreamKind::Http,
+            name: "api".into(),
+            listen_addrs: vec!["127.0.0.1:18080".into()],
+            upstreams: vec![http_health(url)],
+        }
+    }
+
+    #[test]
+    fn http_observation_tracks_consecutive_failures() {
+        let url = "http://127.0.0.1:39501";
+        assert_eq!(http_health(url).state, HealthState::Up);
+        assert!(http_health(url).last_check.is_none());
+
+        record_http_failure(url, "connection refused");
+        record_http_failure(url, "connection refused");
+        let h = http_health(url);
+        assert_eq!(h.state, HealthState::Down);
+        assert_eq!(h.consecutive_failures, 2);
+        assert_eq!(h.last_error.as_deref(), Some("connection refused"));
+
+        record_http_success(url);
+        let h = http_health(url);
+        assert_eq!(h.state, HealthState::Up);
+        assert_eq!(h.consecutive_failures, 0);
+        assert!(h.last_check.is_some());
+    }
+
+    #[test]
+    fn in_flight_guard_counts_per_upstream() {
+        let url = "http://127.0.0.1:39502";
+        let a = track_in_flight(url);
+        let b = track_in_flight(url);
+        assert_eq!(http_health(url).in_flight, 2);
+        drop((a, b));
+        assert_eq!(http_health(url).in_flight, 0);
+    }
+
+    #[test]
+    fn diff_reports_only_flips() {
+        let url = "http://127.0.0.1:39503";
+        let mut previous = HashMap::new();
+        assert!(diff_health(&mut previous, &[group(url)]).is_empty());
+
+        record_http_failure(url, "timeout");
+        let changes = diff_health(&mut previous, &[group(url)]);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].previous, HealthState::Up);
+        assert_eq!(changes[0].state, HealthState::Down);
+        assert!(diff_health(&mut previous, &[group(url)]).is_empty());
+
+        record_http_success(url);
+        let changes = diff_health(&mut previous, &[group(url)]);
+        assert_eq!(changes[0].state, HealthState::Up);
+    }
+
+    #[test]
+    fn serializes_circuit_open_in_kebab_case() {
+        let v = serde_json::to_value(HealthState::CircuitOpen).unwrap();
+        assert_eq!(v, "circuit-open");
+    }
+}
@@ -0,0 +1,747 @@
+use bytes::{Buf, BufMut, BytesMut};
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+
+/// 扩展名；向上游提议时不带窗口参数（本端压缩固定使用 15 位窗口）
+pub const PERMESSAGE_DEFLATE: &str = "permessage-deflate";
+
+/// 每条压缩消息末尾省略的同步刷新标记（RFC 7692 7.2.1）
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+const RSV1: u8 = 0x40;
+
+/// 上游握手响应头的长度上限
+const MAX_RESPONSE_HEAD: usize = 64 * 1024;
+
+/// 写出缓冲积压超过该值时对上层施加背压
+const WRITE_HIGH_WATER: usize = 256 * 1024;
+
+const READ_CHUNK: usize = 16 * 1024;
+
+/// 协商结果（本端视角）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeflateParams {
+    /// 每条消息发送后重置压缩上下文（对端要求本端 no_context_takeover）
+    pub reset_compress: bool,
+}
+
+/// 压缩统计回调：(解压后字节数, 线上字节数)
+pub type DeflateObserver = Box<dyn Fn(u64, u64) + Send + Sync>;
+
+type Extension = (String, Vec<(String, Option<String>)>);
+
+/// 解析 Sec-WebSocket-Extensions：扩展名与参数名统一小写，参数值去掉引号
+fn parse_extensions(value: &str) -> Vec<Extension> {
+    value
+        .split(',')
+        .filter_map(|ext| {
+            let mut parts = ext.split(';').map(str::trim);
+            let name = parts.next().filter(|n| !n.is_empty())?.to_ascii_lowercase();
+            let params = parts
+                .filter(|p| !p.is_empty())
+                .map(|p| match p.split_once('=') {
+                    Some((k, v)) => (
+                        k.trim().to_ascii_lowercase(),
+                        Some(v.trim().trim_matches('"').to_string()),
+                    ),
+                    None => (p.to_ascii_lowercase(), None),
+                })
+                .collect();
+            Some((name, params))
+        })
+        .collect()
+}
+
+fn valid_window_bits(v: &str) -> bool {
+    matches!(v.parse::<u8>(), Ok(8..=15))
+}
+
+/// 服务端：选出客户端提议中第一个可接受的 permessage-deflate，返回本端参数与响应头值。
+/// 要求 server_max_window_bits 小于 15 的提议无法满足，跳过
+pub fn accept_offer<'a>(
+    values: impl IntoIterator<Item = &'a str>,
+) -> Option<(DeflateParams, String)> {
+    values
+        .into_iter()
+        .flat_map(parse_extensions)
+        .find_map(|(name, params)| {
+            if name != PERMESSAGE_DEFLATE {
+                return None;
+            }
+            let mut out = DeflateParams::default();
+            let mut response = PERMESSAGE_DEFLATE.to_string();
+            for (i, (k, v)) in params.iter().enumerate() {
+                if params[..i].iter().any(|(seen, _)| seen == k) {
+                    return None;
+                }
+                match (k.as_str(), v.as_deref()) {
+                    ("server_no_context_takeover", None) => {
+                        out.reset_compress = true;
+                        response.push_str("; server_no_context_takeover");
+                    }
+                    ("server_max_window_bits", Some("15")) => {
+                        response.push_str("; server_max_window_bits=15");
+                    }
+                    ("client_no_context_takeover", None) | ("client_max_window_bits", None) => {}
+                    ("client_max_window_bits", Some(v)) if valid_window_bits(v) => {}
+                    _ => return None,
+                }
+            }
+            Some((out, response))
+        })
+}
+
+/// 客户端：校验上游响应的扩展协商，未启用扩展时返回 None；上游选用了未提议的扩展或参数时报错
+pub fn parse_response<'a>(
+    values: impl IntoIterator<Item = &'a str>,
+) -> Result<Option<DeflateParams>, String> {
+    let exts: Vec<Extension> = values.into_iter().flat_map(parse_extensions).collect();
+    let [(name, params)] = exts.as_slice() else {
+        if exts.is_empty() {
+            return Ok(None);
+        }
+        return Err(format!("upstream selected {} extensions", exts.len()));
+    };
+    if name != PERMESSAGE_DEFLATE {
+        return Err(format!("upstream selected unsupported extension: {name}"));
+    }
+    let mut out = DeflateParams::default();
+    for (k, v) in params {
+        match (k.as_str(), v.as_deref()) {
+            ("client_no_context_takeover", None) => out.reset_compress = true,
+            ("server_no_context_takeover", None) => {}
+            ("server_max_window_bits", Some(v)) if valid_window_bits(v) => {}
+            _ => {
+                return Err(format!(
+                    "upstream selected unsupported permessage-deflate parameter: {k}"
+                ))
+            }
+        }
+    }
+    Ok(Some(out))
+}
+
+/// 单帧解压后的上限：取帧与消息大小限制中较小者
+pub fn inflate_limit(config: &WebSocketConfig) -> usize {
+    config
+        .max_frame_size
+        .unwrap_or(usize::MAX)
+        .min(config.max_message_size.unwrap_or(usize::MAX))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FrameHead {
+    /// FIN / RSV / opcode
+    first: u8,
+    mask: Option<[u8; 4]>,
+    len: u64,
+    /// 帧头字节数
+    size: usize,
+}
+
+impl FrameHead {
+    fn parse(buf: &[u8]) -> Option<Self> {
+        let (&first, rest) = buf.split_first()?;
+        let &second = rest.first()?;
+        let (len, mut size) = match second & 0x7f {
+            126 => (
+                u16::from_be_bytes(buf.get(2..4)?.try_into().ok()?) as u64,
+                4,
+            ),
+            127 => (u64::from_be_bytes(buf.get(2..10)?.try_into().ok()?), 10),
+            n => (n as u64, 2),
+        };
+        let mask = if second & 0x80 != 0 {
+            let key = buf.get(size..size + 4)?.try_into().ok()?;
+            size += 4;
+            Some(key)
+        } else {
+            None
+        };
+        Some(Self {
+            first,
+            mask,
+            len,
+            size,
+        })
+    }
+
+    fn fin(&self) -> bool {
+        self.first & 0x80 != 0
+    }
+
+    fn rsv1(&self) -> bool {
+        self.first & RSV1 != 0
+    }
+
+    fn opcode(&self) -> u8 {
+        self.first & 0x0f
+    }
+
+    fn is_control(&self) -> bool {
+        self.opcode() & 0x08 != 0
+    }
+
+    /// 按新的首字节写出帧，沿用原掩码；payload 为明文
+    fn write_frame(&self, first: u8, mut payload: Vec<u8>, out: &mut BytesMut) {
+        let mask_bit = if self.mask.is_some() { 0x80 } else { 0 };
+        out.put_u8(first);
+        match payload.len() {
+            n if n < 126 => out.put_u8(mask_bit | n as u8),
+            n if n <= u16::MAX as usize => {
+                out.put_u8(mask_bit | 126);
+                out.put_u16(n as u16);
+            }
+            n => {
+                out.put_u8(mask_bit | 127);
+                out.put_u64(n as u64);
+            }
+        }
+        if let Some(key) = self.mask {
+            out.put_slice(&key);
+            apply_mask(&mut payload, key);
+        }
+        out.put_slice(&payload);
+    }
+}
+
+fn apply_mask(data: &mut [u8], key: [u8; 4]) {
+    for (i, b) in data.iter_mut().enumerate() {
+        *b ^= key[i & 3];
+    }
+}
+
+fn invalid_data(e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// 已协商的压缩上下文
+struct DeflateCodec {
+    params: DeflateParams,
+    compress: Compress,
+    decompress: Decompress,
+    /// 正在读取的消息是否压缩（后续分片沿用首帧的 RSV1）
+    reading_compressed: bool,
+    /// 当前帧剩余需原样透传的负载字节
+    read_passthrough: u64,
+    /// 解压输出超过该值即停止，交给 tungstenite 按大小限制关闭连接
+    inflate_limit: usize,
+}
+
+impl DeflateCodec {
+    fn new(params: DeflateParams, inflate_limit: usize) -> Self {
+        Self {
+            params,
+            compress: Compress::new(Compression::default(), false),
+            decompress: Decompress::new(false),
+            reading_compressed: false,
+            read_passthrough: 0,
+            inflate_limit,
+        }
+    }
+
+    /// 数据帧是否属于压缩消息；分片上再带 RSV1 属于协议错误，原样交给 tungstenite 报错
+    fn is_compressed(&mut self, head: &FrameHead) -> bool {
+        if head.is_control() {
+            return false;
+        }
+        if head.opcode() != 0 {
+            self.reading_compressed = head.rsv1();
+            return head.rsv1();
+        }
+        self.reading_compressed && !head.rsv1()
+    }
+
+    fn inflate(&mut self, payload: &[u8], fin: bool) -> io::Result<Vec<u8>> {
+        let limit = self.inflate_limit.saturating_add(1);
+        let mut out = Vec::new();
+        let mut ended = inflate_into(&mut self.decompress, payload, &mut out, limit)?;
+        if fin && !ended && out.len() < limit {
+            ended = inflate_into(&mut self.decompress, &DEFLATE_TAIL, &mut out, limit)?;
+        }
+        // 对端以 BFINAL 块结束消息时，下一条消息从新的 deflate 流开始
+        if ended {
+            self.decompress.reset(false);
+        }
+        Ok(out)
+    }
+
+    fn deflate(&mut self, payload: &[u8], fin: bool) -> io::Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(payload.len() / 2 + 64);
+        let mut input = payload;
+        loop {
+            let before = self.compress.total_in();
+            self.compress
+                .compress_vec(input, &mut out, FlushCompress::Sync)
+                .map_err(io::Error::other)?;
+            input = &input[(self.compress.total_in() - before) as usize..];
+            if input.is_empty() && out.len() < out.capacity() {
+                break;
+            }
+            out.reserve(out.capacity().max(4096));
+        }
+        if fin {
+            if out.ends_with(&DEFLATE_TAIL) {
+                out.truncate(out.len() - DEFLATE_TAIL.len());
+            }
+            if self.params.reset_compress {
+                self.compress.reset();
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// 解压到 out，输出达到 limit 时提前停止；返回是否遇到 deflate 流结束
+fn inflate_into(
+    d: &mut Decompress,
+    mut input: &[u8],
+    out: &mut Vec<u8>,
+    limit: usize,
+) -> io::Result<bool> {
+    loop {
+        if out.len() >= limit {
+            return Ok(false);
+        }
+        if out.len() == out.capacity() {
+            out.reserve((input.len() * 2).max(4096).min(limit - out.len()));
+        }
+        let (in_before, out_before) = (d.total_in(), d.total_out());
+        let status = d
+            .decompress_vec(input, out, FlushDecompress::Sync)
+            .map_err(invalid_data)?;
+        if status == Status::StreamEnd {
+            return Ok(true);
+        }
+        let consumed = (d.total_in() - in_before) as usize;
+        input = &input[consumed..];
+        if out.len() < out.capacity() {
+            if input.is_empty() {
+                return Ok(false);
+            }
+            if consumed == 0 && d.total_out() == out_before {
+                return Err(invalid_data("corrupt permessage-deflate data"));
+            }
+        }
+    }
+}
+
+/// permessage-deflate 帧改写层，位于 tungstenite 与底层连接之间（tungstenite 不支持 RSV1）：
+/// 读方向把压缩帧解压为普通帧，写方向压缩数据帧并置 RSV1；控制帧与未压缩帧原样透传，未协商时整体透传
+pub struct WsDeflate<S> {
+    inner: S,
+    /// 客户端已提议压缩，等待读到上游握手响应头后确定协商结果
+    awaiting_response: bool,
+    codec: Option<DeflateCodec>,
+    inflate_limit: usize,
+    observer: Option<DeflateObserver>,
+    /// 底层读到、尚未处理的字节
+    read_raw: BytesMut,
+    /// 已改写、待交给上层的字节
+    read_out: BytesMut,
+    /// 上层写入、尚未凑成完整帧的字节
+    write_raw: BytesMut,
+    /// 已改写、待写入底层的字节
+    write_out: BytesMut,
+}
+
+impl<S> WsDeflate<S> {
+    /// 服务端：握手已由 hyper 完成，params 为 None 时整体透传
+    pub fn new(inner: S, params: Option<DeflateParams>, inflate_limit: usize) -> Self {
+        Self {
+            inner,
+            awaiting_response: false,
+            codec: params.map(|p| DeflateCodec::new(p, inflate_limit)),
+            inflate_limit,
+            observer: None,
+            read_raw: BytesMut::new(),
+            read_out: BytesMut::new(),
+            write_raw: BytesMut::new(),
+            write_out: BytesMut::new(),
+        }
+    }
+
+    /// 客户端：握手经由本层完成；offered 为 true 时从上游响应头中读取协商结果
+    pub fn client(inner: S, offered: bool, inflate_limit: usize) -> Self {
+        let mut s = Self::new(inner, None, inflate_limit);
+        s.awaiting_response = offered;
+        s
+    }
+
+    pub fn with_observer(mut self, observer: DeflateObserver) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    pub fn negotiated(&self) -> bool {
+        self.codec.is_some()
+    }
+
+    /// 读到完整的上游响应头后按其中的 Sec-WebSocket-Extensions 启用压缩，响应头原样交给上层
+    fn take_response_head(&mut self) -> io::Result<bool> {
+        let Some(end) = self.read_raw.windows(4).position(|w| w == b"\r\n\r\n") else {
+            if self.read_raw.len() > MAX_RESPONSE_HEAD {
+                return Err(invalid_data("WebSocket handshake response too large"));
+            }
+            return Ok(false);
+        };
+        let head = self.read_raw.split_to(end + 4);
+        let text = String::from_utf8_lossy(&head);
+        let values = text
+            .split("\r\n")
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .filter(|(k, _)| k.trim().eq_ignore_ascii_case("sec-websocket-extensions"))
+            .map(|(_, v)| v.trim());
+        let params = parse_response(values).map_err(invalid_data)?;
+        self.codec = params.map(|p| DeflateCodec::new(p, self.inflate_limit));
+        self.awaiting_response = false;
+        self.read_out.unsplit(head);
+        Ok(true)
+    }
+
+    /// 处理 read_raw 中的下一段数据，有输出时返回 true
+    fn decode_next(&mut self) -> io::Result<bool> {
+        let Self {
+            codec,
+            observer,
+            read_raw,
+            read_out,
+            ..
+        } = self;
+        let Some(codec) = codec.as_mut() else {
+            return Ok(false);
+        };
+
+        if codec.read_passthrough > 0 {
+            let n = codec.read_passthrough.min(read_raw.len() as u64) as usize;
+            if n == 0 {
+                return Ok(false);
+            }
+            read_out.unsplit(read_raw.split_to(n));
+            codec.read_passthrough -= n as u64;
+            return Ok(true);
+        }
+
+        let Some(head) = FrameHead::parse(read_raw) else {
+            return Ok(false);
+        };
+        let compressed = codec.is_compressed(&head);
+        if !compressed || head.len > codec.inflate_limit as u64 {
+            // 超出大小限制的压缩帧清除 RSV1 后透传帧头，由 tungstenite 按限制关闭连接（1009）
+            let mut header = read_raw.split_to(head.size);
+            if compressed {
+                header[0] &= !RSV1;
+            }
+            read_out.unsplit(header);
+            codec.read_passthrough = head.len;
+            return Ok(true);
+        }
+
+        let total = head.size + head.len as usize;
+        if read_raw.len() < total {
+            read_raw.reserve(total - read_raw.len());
+            return Ok(false);
+        }
+        let frame = read_raw.split_to(total);
+        let mut payload = frame[head.size..].to_vec();
+        if let Some(key) = head.mask {
+            apply_mask(&mut payload, key);
+        }
+        let data = codec.inflate(&payload, head.fin())?;
+        if let Some(observe) = observer {
+            observe(data.len() as u64, head.len);
+        }
+        head.write_frame(head.first & !RSV1, data, read_out);
+        Ok(true)
+    }
+
+    /// 把 write_raw 中的完整帧改写到 write_out
+    fn encode_frames(&mut self) -> io::Result<()> {
+        let Self {
+            codec,
+            observer,
+            write_raw,
+            write_out,
+            ..
+        } = self;
+        let Some(codec) = codec.as_mut() else {
+            write_out.unsplit(write_raw.split());
+            return Ok(());
+        };
+
+        while let Some(head) = FrameHead::parse(write_raw) {
+            let total = head.size + head.len as usize;
+            if write_raw.len() < total {
+                break;
+            }
+            let frame = write_raw.split_to(total);
+            if head.is_control() {
+                write_out.unsplit(frame);
+                continue;
+            }
+            let mut payload = frame[head.size..].to_vec();
+            if let Some(key) = head.mask {
+                apply_mask(&mut payload, key);
+            }
+            let data = codec.deflate(&payload, head.fin())?;
+            if let Some(observe) = observer {
+                observe(payload.len() as u64, data.len() as u64);
+            }
+            // 只在消息首帧置 RSV1
+            let first = if head.opcode() != 0 {
+                head.first | RSV1
+            } else {
+                head.first
+            };
+            head.write_frame(first, data, write_out);
+        }
+        Ok(())
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> WsDeflate<S> {
+    /// 从底层读一批数据到 read_raw，返回 false 表示 EOF
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<bool>> {
+        let mut chunk = [0u8; READ_CHUNK];
+        let mut buf = ReadBuf::new(&mut chunk);
+        ready!(Pin::new(&mut self.inner).poll_read(cx, &mut buf))?;
+        self.read_raw.extend_from_slice(buf.filled());
+        Poll::Ready(Ok(!buf.filled().is_empty()))
+    }
+
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.write_out.is_empty() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.write_out))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.write_out.advance(n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for WsDeflate<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if !this.read_out.is_empty() {
+                let n = this.read_out.len().min(buf.remaining());
+                buf.put_slice(&this.read_out[..n]);
+                this.read_out.advance(n);
+                return Poll::Ready(Ok(()));
+            }
+
+            let progressed = if this.awaiting_response {
+                this.take_response_head()?
+            } else if this.codec.is_some() {
+                this.decode_next()?
+            } else if this.read_raw.is_empty() {
+                return Pin::new(&mut this.inner).poll_read(cx, buf);
+            } else {
+                // 握手响应之后、未启用压缩时剩余的数据
+                this.read_out.unsplit(this.read_raw.split());
+                true
+            };
+            if progressed {
+                continue;
+            }
+
+            if !ready!(this.poll_fill(cx))? {
+                if this.read_raw.is_empty() {
+                    return Poll::Ready(Ok(()));
+                }
+                // 连接在帧中途关闭：剩余字节交给上层报错
+                this.awaiting_response = false;
+                this.codec = None;
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for WsDeflate<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.codec.is_none() && this.write_out.is_empty() {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+        if this.write_out.len() >= WRITE_HIGH_WATER {
+            ready!(this.poll_drain(cx))?;
+        }
+        this.write_raw.extend_from_slice(buf);
+        this.encode_frames()?;
+        // 尽量写出，未写完的在 flush 时继续
+        if let Poll::Ready(Err(e)) = this.poll_drain(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accept_offer, parse_response, DeflateParams, WsDeflate};
+    use futures_util::{SinkExt, StreamExt};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use tokio::io::AsyncWriteExt;
+    use tokio_tungstenite::tungstenite::protocol::Role;
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::WebSocketStream;
+
+    #[test]
+    fn accept_offer_picks_first_supported_offer() {
+        let (params, response) =
+            accept_offer(["permessage-deflate; client_max_window_bits"]).unwrap();
+        assert_eq!(params, DeflateParams::default());
+        assert_eq!(response, "permessage-deflate");
+
+        // 第一个提议要求更小的服务端窗口，回退到第二个
+        let (params, response) = accept_offer([
+            "permessage-deflate; server_max_window_bits=10, permessage-deflate; server_no_context_takeover",
+        ])
+        .unwrap();
+        assert!(params.reset_compress);
+        assert_eq!(response, "permessage-deflate; server_no_context_takeover");
+
+        assert!(accept_offer(["x-webkit-deflate-frame"]).is_none());
+        assert!(accept_offer(["permessage-deflate; unknown"]).is_none());
+        assert!(accept_offer([
+            "permessage-deflate; server_no_context_takeover; server_no_context_takeover"
+        ])
+        .is_none());
+    }
+
+    #[test]
+    fn parse_response_rejects_unrequested_parameters() {
+        assert_eq!(parse_response([]).unwrap(), None);
+        assert_eq!(
+            parse_response(["permessage-deflate; client_no_context_takeover"]).unwrap(),
+            Some(DeflateParams {
+                reset_compress: true
+            })
+        );
+        assert!(parse_response(["permessage-deflate; server_max_window_bits=12"]).is_ok());
+        assert!(parse_response(["permessage-deflate; client_max_window_bits=10"]).is_err());
+        assert!(parse_response(["x-custom"]).is_err());
+    }
+
+    /// RFC 7692 7.2.3.2：共享上下文的两条 "Hello"，第二条依赖前一条的滑动窗口
+    #[tokio::test]
+    async fn decodes_rfc7692_context_takeover_example() {
+        let (mut server, client) = tokio::io::duplex(1024);
+        server
+            .write_all(&[0xc1, 0x07, 0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00])
+            .await
+            .unwrap();
+        server
+            .write_all(&[0xc1, 0x05, 0xf2, 0x00, 0x11, 0x00, 0x00])
+            .await
+            .unwrap();
+
+        let io = WsDeflate::new(client, Some(DeflateParams::default()), usize::MAX);
+        let mut ws = WebSocketStream::from_raw_socket(io, Role::Client, None).await;
+        for _ in 0..2 {
+            let msg = ws.next().await.unwrap().unwrap();
+            assert_eq!(msg, Message::Text("Hello".into()));
+        }
+    }
+
+    #[tokio::test]
+    async fn round_trips_messages_and_counts_saved_bytes() {
+        let (a, b) = tokio::io::duplex(64 * 1024);
+        let saved = Arc::new(AtomicU64::new(0));
+        let counter = saved.clone();
+        let server_io = WsDeflate::new(
+            a,
+            Some(DeflateParams {
+                reset_compress: true,
+            }),
+            usize::MAX,
+        )
+        .with_observer(Box::new(move |raw, wire| {
+            counter.fetch_add(raw.saturating_sub(wire), Ordering::Relaxed);
+        }));
+        let client_io = WsDeflate::new(b, Some(DeflateParams::default()), usize::MAX);
+        let mut server = WebSocketStream::from_raw_socket(server_io, Role::Server, None).await;
+        let mut client = WebSocketStream::from_raw_socket(client_io, Role::Client, None).await;
+
+        let payload = "{\"metric\":\"cpu\",\"value\":42}".repeat(200);
+        for msg in [
+            Message::Text(payload.clone().into()),
+            Message::Binary(vec![7u8; 4096].into()),
+            Message::Text("".into()),
+            Message::Ping(b"p".to_vec().into()),
+        ] {
+            client.send(msg.clone()).await.unwrap();
+            assert_eq!(server.next().await.unwrap().unwrap(), msg);
+            server.send(msg.clone()).await.unwrap();
+            let echoed = client.next().await.unwrap().unwrap();
+            // 服务端自动回应的 Pong 先于回显的 Ping 到达
+            let echoed = match echoed {
+                Message::Pong(_) => client.next().await.unwrap().unwrap(),
+                m => m,
+            };
+            assert_eq!(echoed, msg);
+        }
+        assert!(saved.load(Ordering::Relaxed) > payload.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn oversized_inflated_frame_hits_tungstenite_limit() {
+        use tokio_tungstenite::tungstenite::error::{CapacityError, Error};
+        use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+
+        let (a, b) = tokio::io::duplex(64 * 1024);
+        let params = Some(DeflateParams::default());
+        let mut sender = WebSocketStream::from_raw_socket(
+            WsDeflate::new(a, params, usize::MAX),
+            Role::Client,
+            None,
+        )
+        .await;
+        let config = WebSocketConfig::default().max_message_size(Some(1024));
+        let mut receiver = WebSocketStream::from_raw_socket(
+            WsDeflate::new(b, params, super::inflate_limit(&config)),
+            Role::Server,
+            Some(config),
+        )
+        .await;
+
+        // 压缩后远小于限制，解压后超出
+        sender
+            .send(Message::Binary(vec![0u8; 64 * 1024].into()))
+            .await
+            .unwrap();
+        let err = receiver.next().await.unwrap().unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Capacity(CapacityError::MessageTooLong { .. })
+        ));
+    }
+}
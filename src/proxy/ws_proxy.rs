@@ -1,42 +1,371 @@
 use anyhow::{anyhow, Context, Result};
+use arc_swap::ArcSwap;
 use axum::{
-    body::Bytes,
-    extract::{
-        connect_info::ConnectInfo,
-        ws::{self},
-        FromRef, State, WebSocketUpgrade,
+    body::{Body, Bytes},
+    extract::{connect_info::ConnectInfo, FromRef, FromRequestParts, State},
+    http::{
+        header, request::Parts, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri,
+        Version,
     },
-    http::{HeaderMap, StatusCode, Uri},
     response::{IntoResponse, Response},
     routing::any,
     Router,
 };
+use dashmap::DashMap;
 use futures_util::{SinkExt, StreamExt};
+use hyper_util::rt::TokioIo;
 use parking_lot::RwLock;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use std::{net::SocketAddr, sync::Arc};
-use tauri::Emitter;
+use tokio_tungstenite::tungstenite::error::{CapacityError, UrlError};
+use tokio_tungstenite::tungstenite::protocol::{
+    frame::coding::CloseCode, CloseFrame, Role, WebSocketConfig,
+};
+use tokio_tungstenite::tungstenite::{client::IntoClientRequest, handshake::client::Request};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::{Connector, MaybeTlsStream, WebSocketStream};
 use tracing::{error, info};
 
+use super::connections::{self, ActiveEntry};
+use super::lifecycle::{drain_listener, DrainReport, InFlight, ListenerEvent, ListenerStatus};
+use super::logging::LogLevel;
+use super::upstream_health::{self, InFlightGuard};
+use super::ws_deflate::{self, DeflateObserver, DeflateParams, WsDeflate};
+use super::{matching, stream_proxy, upstream};
+use crate::app_events::AppEvents;
 use crate::{access_control, config, network_optimizer::TcpOptimizer};
 
+type UpstreamWs = WebSocketStream<WsDeflate<MaybeTlsStream<tokio::net::TcpStream>>>;
+type ClientWs = WebSocketStream<WsDeflate<TokioIo<hyper::upgrade::Upgraded>>>;
+
 static WS_SERVERS: RwLock<Vec<WsServerHandle>> = RwLock::new(Vec::new());
 
+/// WS 统计（按监听地址），仅在停止代理时清空
+static WS_STATS: once_cell::sync::Lazy<DashMap<String, Arc<WsRuleStats>>> =
+    once_cell::sync::Lazy::new(DashMap::new);
+
+#[derive(Default)]
+struct WsRuleStats {
+    timed_out: AtomicU64,
+    /// 当前活跃连接数
+    active: AtomicU64,
+    /// IP -> 当前活跃连接数
+    active_per_ip: DashMap<String, u64>,
+    /// 监听地址级别的累计流量
+    traffic: WsTraffic,
+    /// 路由路径 -> 路由级别统计
+    routes: DashMap<String, Arc<WsRouteStats>>,
+}
+
+/// 连接与消息计数器（原子更新，连接关闭后保留）
+#[derive(Default)]
+struct WsTraffic {
+    total: AtomicU64,
+    messages_in: AtomicU64,
+    messages_out: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    connect_failures: AtomicU64,
+    /// permessage-deflate 压缩前（解压后）的负载字节数
+    deflate_raw: AtomicU64,
+    /// permessage-deflate 线上实际传输的负载字节数
+    deflate_wire: AtomicU64,
+}
+
+impl WsTraffic {
+    fn snapshot(&self, active: u64) -> crate::metrics::WsTrafficStats {
+        crate::metrics::WsTrafficStats {
+            active,
+            total: self.total.load(Ordering::Relaxed),
+            messages_in: self.messages_in.load(Ordering::Relaxed),
+            messages_out: self.messages_out.load(Ordering::Relaxed),
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            bytes_out: self.bytes_out.load(Ordering::Relaxed),
+            connect_failures: self.connect_failures.load(Ordering::Relaxed),
+            deflate_bytes_saved: self
+                .deflate_raw
+                .load(Ordering::Relaxed)
+                .saturating_sub(self.deflate_wire.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[derive(Default)]
+struct WsRouteStats {
+    active: AtomicU64,
+    traffic: WsTraffic,
+}
+
+/// 单个连接的计数入口：同时更新监听地址、路由与本连接三级统计
+#[derive(Clone)]
+struct WsConnStats {
+    rule: Arc<WsRuleStats>,
+    route: Arc<WsRouteStats>,
+    session: Arc<WsTraffic>,
+}
+
+impl WsConnStats {
+    fn new(rule: Arc<WsRuleStats>, route_path: &str) -> Self {
+        let route = rule
+            .routes
+            .entry(route_path.to_string())
+            .or_default()
+            .clone();
+        Self {
+            rule,
+            route,
+            session: Arc::default(),
+        }
+    }
+
+    fn on_connect_failure(&self, n: u64) {
+        self.rule
+            .traffic
+            .connect_failures
+            .fetch_add(n, Ordering::Relaxed);
+        self.route
+            .traffic
+            .connect_failures
+            .fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn on_open(&self) {
+        self.rule.traffic.total.fetch_add(1, Ordering::Relaxed);
+        self.route.traffic.total.fetch_add(1, Ordering::Relaxed);
+        self.route.active.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_close(&self) {
+        self.route.active.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// 记录一条消息，to_upstream 为 true 表示 客户端 -> 上游
+    fn on_message(&self, to_upstream: bool, bytes: usize) {
+        for t in [&self.rule.traffic, &self.route.traffic, &*self.session] {
+            if to_upstream {
+                t.messages_in.fetch_add(1, Ordering::Relaxed);
+                t.bytes_in.fetch_add(bytes as u64, Ordering::Relaxed);
+            } else {
+                t.messages_out.fetch_add(1, Ordering::Relaxed);
+                t.bytes_out.fetch_add(bytes as u64, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// 记录一帧压缩/解压：raw 为明文字节数，wire 为线上字节数
+    fn on_deflate(&self, raw: u64, wire: u64) {
+        for t in [&self.rule.traffic, &self.route.traffic, &*self.session] {
+            t.deflate_raw.fetch_add(raw, Ordering::Relaxed);
+            t.deflate_wire.fetch_add(wire, Ordering::Relaxed);
+        }
+    }
+
+    fn deflate_observer(&self) -> DeflateObserver {
+        let stats = self.clone();
+        Box::new(move |raw, wire| stats.on_deflate(raw, wire))
+    }
+}
+
+impl WsRuleStats {
+    /// 占用一个连接名额，超出单 IP 或总数限制时返回 None（0 表示不限制）
+    fn try_acquire(
+        self: &Arc<Self>,
+        ip: &str,
+        max_per_ip: u64,
+        max_total: u64,
+    ) -> Option<WsConnGuard> {
+        let mut per_ip = self.active_per_ip.entry(ip.to_string()).or_insert(0);
+        if max_per_ip > 0 && *per_ip >= max_per_ip {
+            return None;
+        }
+        let reserved = self
+            .active
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (max_total == 0 || n < max_total).then_some(n + 1)
+            })
+            .is_ok();
+        if !reserved {
+            let empty = *per_ip == 0;
+            drop(per_ip);
+            if empty {
+                self.active_per_ip.remove_if(ip, |_, n| *n == 0);
+            }
+            return None;
+        }
+        *per_ip += 1;
+
+        Some(WsConnGuard {
+            stats: self.clone(),
+            ip: ip.to_string(),
+        })
+    }
+}
+
+/// 连接名额守卫，Drop 时归还
+struct WsConnGuard {
+    stats: Arc<WsRuleStats>,
+    ip: String,
+}
+
+impl Drop for WsConnGuard {
+    fn drop(&mut self) {
+        self.stats.active.fetch_sub(1, Ordering::AcqRel);
+        if let Some(mut n) = self.stats.active_per_ip.get_mut(&self.ip) {
+            *n = n.saturating_sub(1);
+        }
+        self.stats.active_per_ip.remove_if(&self.ip, |_, n| *n == 0);
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WsIpConnections {
+    pub ip: String,
+    pub connections: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WsListenConnections {
+    pub listen_addr: String,
+    pub total: u64,
+    pub per_ip: Vec<WsIpConnections>,
+}
+
+/// 保活 Ping 的负载，用于识别自身发出的 Ping 对应的 Pong（不转发给另一端）
+const KEEPALIVE_PAYLOAD: &[u8] = b"sslpm-keepalive";
+
+/// wss 上游默认 TLS 配置（内置 webpki 根证书）
+static DEFAULT_WS_TLS_CONFIG: once_cell::sync::Lazy<Arc<rustls::ClientConfig>> =
+    once_cell::sync::Lazy::new(|| {
+        let mut root_store = rustls::RootCertStore::empty();
+        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        Arc::new(
+            rustls::ClientConfig::builder()
+                .with_root_certificates(root_store)
+                .with_no_client_auth(),
+        )
+    });
+
+/// wss 上游 TLS 连接器缓存（key: verify + ca_file），停止服务时清空以便重新加载 CA
+static WS_TLS_CONNECTORS: once_cell::sync::Lazy<DashMap<(bool, String), Connector>> =
+    once_cell::sync::Lazy::new(DashMap::new);
+
 struct WsServerHandle {
+    listen_addr: String,
+    /// 启动监听的时间（Unix 秒）
+    started_at: i64,
     handle: tauri::async_runtime::JoinHandle<()>,
     shutdown_tx: tokio::sync::oneshot::Sender<()>,
+    /// 已升级、正在中继的 WS 连接
+    in_flight: Arc<InFlight>,
+    /// 监听异常退出（如绑定失败）的原因
+    error: Arc<once_cell::sync::OnceCell<String>>,
 }
 
 impl WsServerHandle {
-    fn abort(self) {
+    /// 停止接收新连接，已建立的 WS 连接在 drain 内自然关闭，超时后强制断开
+    fn shutdown(self, drain: Duration) -> tauri::async_runtime::JoinHandle<DrainReport> {
+        let before = self.in_flight.count();
         let _ = self.shutdown_tx.send(());
-        self.handle.abort();
+        tauri::async_runtime::spawn(drain_listener(self.handle, self.in_flight, before, drain))
     }
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
 pub struct WsRoute {
+    /// 匹配的 Host（可选，支持 *.example.com），与 HTTP 路由规则一致
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
     pub path: String,
-    pub upstream_url: String,
+    /// 上游列表（兼容旧字段 upstream_url：单个地址）
+    #[serde(alias = "upstream_url", deserialize_with = "deserialize_ws_upstreams")]
+    pub upstreams: Vec<config::Upstream>,
+    /// 上游选择策略
+    #[serde(default)]
+    pub lb_strategy: WsLbStrategy,
+    /// 替换匹配到的路径前缀（与 HTTP 路由的 proxy_pass_path 语义一致）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_pass_path: Option<String>,
+    /// 握手时转发给上游的客户端请求头（不区分大小写）
+    #[serde(default = "default_ws_forward_headers")]
+    pub forward_headers: Vec<String>,
+    /// wss:// 上游的 TLS 选项
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<WsUpstreamTls>,
+    /// 所有上游都尝试过后的额外重试次数（按候选顺序循环）
+    #[serde(default)]
+    pub retry_count: u32,
+    /// 被动健康检查：连续失败 max_fails 次后在 fail_timeout 内跳过该上游（与 stream 上游一致）
+    #[serde(default = "default_ws_max_fails")]
+    pub max_fails: i32,
+    #[serde(default = "default_ws_fail_timeout")]
+    pub fail_timeout: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct WsUpstreamTls {
+    /// 是否校验上游证书
+    #[serde(default = "default_true")]
+    pub verify: bool,
+    /// 额外信任的 CA 证书（PEM）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_file: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WsLbStrategy {
+    /// 平滑加权轮询
+    #[default]
+    RoundRobin,
+    /// 按客户端 IP 哈希（同一 IP 固定到同一上游）
+    IpHash,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn deserialize_ws_upstreams<'de, D>(deserializer: D) -> Result<Vec<config::Upstream>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum UpstreamsField {
+        Single(String),
+        List(Vec<config::Upstream>),
+    }
+
+    Ok(
+        match <UpstreamsField as serde::Deserialize>::deserialize(deserializer)? {
+            UpstreamsField::Single(url) => vec![config::Upstream { url, weight: 1 }],
+            UpstreamsField::List(list) => list,
+        },
+    )
+}
+
+fn default_ws_max_fails() -> i32 {
+    1
+}
+
+fn default_ws_fail_timeout() -> String {
+    "30s".to_string()
+}
+
+fn default_ws_forward_headers() -> Vec<String> {
+    [
+        "Cookie",
+        "Authorization",
+        "Origin",
+        "Sec-WebSocket-Protocol",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
@@ -47,23 +376,50 @@ pub struct WsListenRule {
     pub cert_file: String,
     pub key_file: String,
     pub routes: Vec<WsRoute>,
+    /// 向两端发送 Ping 的间隔（秒），0 表示不发送
+    #[serde(default)]
+    pub ping_interval_secs: u64,
+    /// 两端均无任何帧的空闲超时（秒），0 表示不限制
+    #[serde(default)]
+    pub idle_timeout_secs: u64,
+    /// 连续未收到 Pong 的次数达到该值时关闭连接
+    #[serde(default = "default_max_missed_pings")]
+    pub max_missed_pings: u32,
+    /// 单条消息最大字节数（两端共用），为空使用默认值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_message_size: Option<usize>,
+    /// 单帧最大字节数（两端共用），为空使用默认值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_frame_size: Option<usize>,
+    /// 单个客户端 IP 的最大并发连接数，为空或 0 表示不限制
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_connections_per_ip: Option<u64>,
+    /// 监听地址的最大并发连接数，为空或 0 表示不限制
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_total_connections: Option<u64>,
+    /// 启用 permessage-deflate：与客户端、上游分别协商，两端结果不同时由代理解压/重新压缩
+    #[serde(default)]
+    pub permessage_deflate: bool,
+}
+
+fn default_max_missed_pings() -> u32 {
+    3
 }
 
 #[derive(Clone)]
 struct WsAppState {
     rule: WsListenRule,
-    app: tauri::AppHandle,
+    app: AppEvents,
     ws_access_control_enabled: bool,
-    allow_all_lan: bool,
-    allow_all_ip: bool,
-    whitelist: Arc<[config::WhitelistEntry]>,
+    access_lists: Arc<ArcSwap<access_control::AccessLists>>,
+    in_flight: Arc<InFlight>,
 }
 
 #[derive(Clone)]
 struct WsRuleState(Arc<WsListenRule>);
 
 #[derive(Clone)]
-struct AppHandleState(tauri::AppHandle);
+struct AppEventsState(AppEvents);
 
 impl FromRef<WsAppState> for WsRuleState {
     fn from_ref(input: &WsAppState) -> Self {
@@ -71,18 +427,18 @@ impl FromRef<WsAppState> for WsRuleState {
     }
 }
 
-impl FromRef<WsAppState> for AppHandleState {
+impl FromRef<WsAppState> for AppEventsState {
     fn from_ref(input: &WsAppState) -> Self {
         Self(input.app.clone())
     }
 }
 
 #[inline]
-fn ws_log(app: &tauri::AppHandle, message: impl Into<String>) {
-    crate::proxy::send_log_with_app(app, format!("[WS] {}", message.into()));
+fn ws_log(app: &AppEvents, level: LogLevel, node: Option<&str>, message: impl Into<String>) {
+    crate::proxy::send_log_with_app(app, level, node, format!("[WS] {}", message.into()));
 }
 
-pub fn start_ws_servers(app: tauri::AppHandle) -> Result<()> {
+pub fn start_ws_servers(app: AppEvents) -> Result<()> {
     let cfg = config::get_config();
 
     if !cfg.ws_proxy_enabled {
@@ -93,39 +449,179 @@ pub fn start_ws_servers(app: tauri::AppHandle) -> Result<()> {
         return Ok(());
     };
 
-    for ws_rule in ws_rules {
-        if !ws_rule.enabled {
-            continue;
-        }
+    for ws_rule in ws_rules.into_iter().filter(|r| r.enabled) {
+        spawn_ws_server(&app, ws_rule);
+    }
 
-        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
-        let app2 = app.clone();
-        let handle = tauri::async_runtime::spawn(async move {
-            let listen_addr = ws_rule.listen_addr.clone();
-            if let Err(e) = start_ws_rule_server(app2.clone(), ws_rule, shutdown_rx).await {
-                error!("WS server failed({listen_addr}): {e}");
-            }
-        });
+    Ok(())
+}
 
-        WS_SERVERS.write().push(WsServerHandle {
-            handle,
-            shutdown_tx,
-        });
+/// 按差异重载时只启动给定的 WS 规则，其余监听保持运行
+pub fn start_ws_rules(app: &AppEvents, rules: &[WsListenRule]) {
+    for ws_rule in rules {
+        spawn_ws_server(app, ws_rule.clone());
     }
+}
 
-    Ok(())
+fn spawn_ws_server(app: &AppEvents, ws_rule: WsListenRule) {
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+    let in_flight = InFlight::new();
+    let error = Arc::new(once_cell::sync::OnceCell::new());
+    let listen_addr = ws_rule.listen_addr.clone();
+    let app2 = app.clone();
+    let task_in_flight = in_flight.clone();
+    let task_error = error.clone();
+    let handle = tauri::async_runtime::spawn(async move {
+        let listen_addr = ws_rule.listen_addr.clone();
+        let result = start_ws_rule_server(app2.clone(), ws_rule, task_in_flight, shutdown_rx).await;
+        // 停止时句柄会先从 WS_SERVERS 取出；仍在其中说明监听是自行退出的
+        let registered = WS_SERVERS
+            .read()
+            .iter()
+            .any(|s| Arc::ptr_eq(&s.error, &task_error));
+        let error = match result {
+            Err(e) => Some(e.to_string()),
+            Ok(()) if registered => Some("Listener exited unexpectedly".to_string()),
+            Ok(()) => None,
+        };
+        let event = ListenerEvent::new("ws", listen_addr.clone(), None);
+        match error {
+            Some(e) => {
+                error!("WS server failed({listen_addr}): {e}");
+                let _ = task_error.set(e.clone());
+                event.failed(&app2, e);
+            }
+            None => event.stopped(&app2),
+        }
+    });
+
+    WS_SERVERS.write().push(WsServerHandle {
+        listen_addr,
+        started_at: chrono::Utc::now().timestamp(),
+        handle,
+        shutdown_tx,
+        in_flight,
+        error,
+    });
 }
 
-pub fn stop_ws_servers() {
+/// 返回各监听的 drain 任务，调用方可等待连接结束、监听真正释放
+pub fn stop_ws_servers(drain: Duration) -> Vec<tauri::async_runtime::JoinHandle<DrainReport>> {
     let handles = std::mem::take(&mut *WS_SERVERS.write());
-    for h in handles {
-        h.abort();
+    WS_TLS_CONNECTORS.clear();
+    WS_STATS.clear();
+    handles.into_iter().map(|h| h.shutdown(drain)).collect()
+}
+
+/// 只停止给定监听地址的 WS 监听，其余监听及其连接不受影响
+pub fn stop_ws_listeners(
+    listen_addrs: &[String],
+    drain: Duration,
+) -> Vec<tauri::async_runtime::JoinHandle<DrainReport>> {
+    let stopped: Vec<WsServerHandle> = {
+        let mut servers = WS_SERVERS.write();
+        let (stopped, kept) = std::mem::take(&mut *servers)
+            .into_iter()
+            .partition(|h| listen_addrs.contains(&h.listen_addr));
+        *servers = kept;
+        stopped
+    };
+    // 连接器按 CA 文件缓存，清空后按新配置重新加载；其他监听下次连接时重建
+    WS_TLS_CONNECTORS.clear();
+    for addr in listen_addrs {
+        WS_STATS.remove(addr);
     }
+    stopped.into_iter().map(|h| h.shutdown(drain)).collect()
+}
+
+/// 各 WS 规则的监听状态，包括未启用的规则
+pub fn ws_listener_statuses(cfg: &config::Config) -> Vec<ListenerStatus> {
+    let servers = WS_SERVERS.read();
+    cfg.ws_proxy
+        .iter()
+        .flatten()
+        .map(|rule| {
+            let status = ListenerStatus::stopped("ws", rule.listen_addr.clone());
+            let Some(server) = servers.iter().find(|s| s.listen_addr == rule.listen_addr) else {
+                return status;
+            };
+            if let Some(error) = server.error.get() {
+                status.failed(error.clone())
+            } else if server.handle.inner().is_finished() {
+                status
+            } else {
+                status.running(server.started_at, server.in_flight.count() as u64)
+            }
+        })
+        .collect()
+}
+
+fn ws_stats(listen_addr: &str) -> Arc<WsRuleStats> {
+    WS_STATS.entry(listen_addr.to_string()).or_default().clone()
+}
+
+/// 当前各监听地址的 WS 连接数（按 IP 统计，连接数降序）
+pub fn get_ws_connections() -> Vec<WsListenConnections> {
+    let mut out: Vec<WsListenConnections> = WS_STATS
+        .iter()
+        .map(|entry| {
+            let stats = entry.value();
+            let mut per_ip: Vec<WsIpConnections> = stats
+                .active_per_ip
+                .iter()
+                .filter(|e| *e.value() > 0)
+                .map(|e| WsIpConnections {
+                    ip: e.key().clone(),
+                    connections: *e.value(),
+                })
+                .collect();
+            per_ip.sort_unstable_by_key(|e| std::cmp::Reverse(e.connections));
+            WsListenConnections {
+                listen_addr: entry.key().clone(),
+                total: stats.active.load(Ordering::Relaxed),
+                per_ip,
+            }
+        })
+        .collect();
+    out.sort_unstable_by(|a, b| a.listen_addr.cmp(&b.listen_addr));
+    out
+}
+
+/// 当前 WS 统计快照（供实时指标推送）
+pub fn ws_metrics_snapshot() -> Vec<crate::metrics::WsMetricsItem> {
+    let mut out: Vec<crate::metrics::WsMetricsItem> = WS_STATS
+        .iter()
+        .map(|entry| {
+            let stats = entry.value();
+            let mut routes: Vec<crate::metrics::WsRouteMetricsItem> = stats
+                .routes
+                .iter()
+                .map(|r| crate::metrics::WsRouteMetricsItem {
+                    path: r.key().clone(),
+                    stats: r
+                        .value()
+                        .traffic
+                        .snapshot(r.value().active.load(Ordering::Relaxed)),
+                })
+                .collect();
+            routes.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+
+            crate::metrics::WsMetricsItem {
+                listen_addr: entry.key().clone(),
+                stats: stats.traffic.snapshot(stats.active.load(Ordering::Relaxed)),
+                timed_out: stats.timed_out.load(Ordering::Relaxed),
+                routes,
+            }
+        })
+        .collect();
+    out.sort_unstable_by(|a, b| a.listen_addr.cmp(&b.listen_addr));
+    out
 }
 
 async fn start_ws_rule_server(
-    app: tauri::AppHandle,
+    app: AppEvents,
     rule: WsListenRule,
+    in_flight: Arc<InFlight>,
     shutdown_rx: tokio::sync::oneshot::Receiver<()>,
 ) -> Result<()> {
     let (addr, need_dual_stack) = parse_listen_addr(&rule.listen_addr)?;
@@ -136,9 +632,8 @@ async fn start_ws_rule_server(
         rule: rule.clone(),
         app: app.clone(),
         ws_access_control_enabled: cfg.ws_access_control_enabled,
-        allow_all_lan: cfg.allow_all_lan,
-        allow_all_ip: cfg.allow_all_ip,
-        whitelist: Arc::from(cfg.whitelist),
+        access_lists: access_control::access_lists_slot(),
+        in_flight,
     };
 
     let router = Router::new().route("/healthz", any(|| async { (StatusCode::OK, "OK") }));
@@ -148,19 +643,35 @@ async fn start_ws_rule_server(
     info!("WS listen {} -> {}", rule.listen_addr, addr);
     ws_log(
         &app,
+        LogLevel::Info,
+        Some(rule.listen_addr.as_str()),
         format!("Listening address: {} -> {}", rule.listen_addr, addr),
     );
 
-    if rule.ssl_enable {
-        ws_log(&app, format!("HTTPS enabled: {}", addr));
-
+    let tls_cfg = if rule.ssl_enable {
         let tls_cfg = axum_server::tls_rustls::RustlsConfig::from_pem_file(
             rule.cert_file.clone(),
             rule.key_file.clone(),
         )
         .await
         .with_context(|| "Failed to load WS TLS certificate/private key")?;
+        Some(tls_cfg)
+    } else {
+        None
+    };
 
+    let listener = TcpOptimizer::default().optimize_listener(addr).await?;
+    ListenerEvent::new("ws", rule.listen_addr.clone(), None).started(&app);
+
+    if let Some(tls_cfg) = tls_cfg {
+        ws_log(
+            &app,
+            LogLevel::Info,
+            Some(rule.listen_addr.as_str()),
+            format!("HTTPS enabled: {}", addr),
+        );
+
+        let server = axum_server::tls_rustls::from_tcp_rustls(listener.into_std()?, tls_cfg)?;
         let mut shutdown_rx = shutdown_rx;
 
         if need_dual_stack && addr.is_ipv6() {
@@ -173,13 +684,15 @@ async fn start_ws_rule_server(
             );
             ws_log(
                 &app,
+                LogLevel::Info,
+                Some(rule.listen_addr.as_str()),
                 format!(
                     "Listening on IPv6 (dual-stack): {} (supports both IPv4 and IPv6)",
                     addr
                 ),
             );
 
-            let server_future = axum_server::bind_rustls(addr, tls_cfg).serve(app_router);
+            let server_future = server.serve(app_router);
             tokio::select! {
                 res = server_future => {
                     res.map_err(|e| anyhow!("WS HTTPS service failed: {e}"))?;
@@ -189,7 +702,7 @@ async fn start_ws_rule_server(
                 }
             }
         } else {
-            let server_future = axum_server::bind_rustls(addr, tls_cfg).serve(app_router);
+            let server_future = server.serve(app_router);
             tokio::select! {
                 res = server_future => {
                     res.map_err(|e| anyhow!(e))?;
@@ -200,7 +713,12 @@ async fn start_ws_rule_server(
             }
         }
     } else {
-        ws_log(&app, format!("HTTP enabled: {}", addr));
+        ws_log(
+            &app,
+            LogLevel::Info,
+            Some(rule.listen_addr.as_str()),
+            format!("HTTP enabled: {}", addr),
+        );
 
         let mut shutdown_rx = shutdown_rx;
 
@@ -214,14 +732,14 @@ async fn start_ws_rule_server(
             );
             ws_log(
                 &app,
+                LogLevel::Info,
+                Some(rule.listen_addr.as_str()),
                 format!(
                     "Listening on IPv6 (dual-stack): {} (supports both IPv4 and IPv6)",
                     addr
                 ),
             );
 
-            let optimizer = TcpOptimizer::default();
-            let listener = optimizer.optimize_listener(addr).await?;
             let server_future = axum::serve(listener, app_router);
             tokio::select! {
                 res = server_future => {
@@ -232,8 +750,6 @@ async fn start_ws_rule_server(
                 }
             }
         } else {
-            let optimizer = TcpOptimizer::default();
-            let listener = optimizer.optimize_listener(addr).await?;
             let server_future = axum::serve(listener, app_router);
             tokio::select! {
                 res = server_future => {
@@ -249,127 +765,1136 @@ async fn start_ws_rule_server(
     Ok(())
 }
 
+/// WS 升级请求的日志上下文：升级结果写入 request_logs（method 为 "WS"），断开时写入 ws_sessions
+struct WsRequestLog {
+    listen_addr: String,
+    client_ip: String,
+    remote_ip: String,
+    path: String,
+    host: String,
+    user_agent: String,
+    referer: String,
+    started: Instant,
+}
+
+impl WsRequestLog {
+    fn new(listen_addr: &str, remote: &SocketAddr, headers: &HeaderMap, path: &str) -> Self {
+        let header_str = |name: HeaderName| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("")
+                .to_string()
+        };
+        Self {
+            listen_addr: listen_addr.to_string(),
+            client_ip: access_control::client_ip_from_headers(remote, headers).to_string(),
+            remote_ip: remote.ip().to_string(),
+            path: path.to_string(),
+            host: header_str(header::HOST),
+            user_agent: header_str(header::USER_AGENT),
+            referer: header_str(header::REFERER),
+            started: Instant::now(),
+        }
+    }
+
+    /// 记录升级决策：成功为 101，拒绝为对应的 HTTP 状态码
+    fn record(&self, status: StatusCode, upstream: &str) {
+        crate::metrics::try_enqueue_request_log(crate::metrics::RequestLogInsert {
+            timestamp: chrono::Utc::now().timestamp(),
+            listen_addr: self.listen_addr.clone(),
+            client_ip: self.client_ip.clone(),
+            remote_ip: self.remote_ip.clone(),
+            method: "WS".to_string(),
+            request_path: self.path.clone(),
+            request_host: self.host.clone(),
+            status_code: status.as_u16() as i32,
+            upstream: upstream.to_string(),
+            latency_ms: self.started.elapsed().as_secs_f64() * 1000.0,
+            guard_ms: 0.0,
+            prepare_ms: 0.0,
+            upstream_ms: 0.0,
+            user_agent: self.user_agent.clone(),
+            referer: self.referer.clone(),
+            matched_route_id: String::new(),
+            bytes_sent: 0,
+            bytes_received: 0,
+            country: String::new(),
+            asn: String::new(),
+            sample_rate: 1.0,
+        });
+    }
+
+    fn reject(&self, status: StatusCode, upstream: &str, body: &'static str) -> Response {
+        self.record(status, upstream);
+        (status, body).into_response()
+    }
+
+    fn session(
+        &self,
+        upstream: &str,
+        duration: Duration,
+        traffic: &WsTraffic,
+    ) -> crate::metrics::WsSessionInsert {
+        let load = |v: &AtomicU64| v.load(Ordering::Relaxed) as i64;
+        crate::metrics::WsSessionInsert {
+            timestamp: chrono::Utc::now().timestamp(),
+            listen_addr: self.listen_addr.clone(),
+            client_ip: self.client_ip.clone(),
+            request_path: self.path.clone(),
+            upstream: upstream.to_string(),
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            messages_in: load(&traffic.messages_in),
+            messages_out: load(&traffic.messages_out),
+            bytes_in: load(&traffic.bytes_in),
+            bytes_out: load(&traffic.bytes_out),
+        }
+    }
+}
+
+/// 客户端的 WS 升级请求。握手由本端完成（而非 axum WebSocketUpgrade），
+/// 以便在升级后的连接上插入 permessage-deflate 帧改写层
+struct ClientUpgrade {
+    /// HTTP/1.1 的 Sec-WebSocket-Key；HTTP/2 扩展 CONNECT 为 None
+    key: Option<HeaderValue>,
+    on_upgrade: hyper::upgrade::OnUpgrade,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientUpgrade {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let reject = |status: StatusCode, msg: &'static str| (status, msg).into_response();
+        let headers = &parts.headers;
+        let has_token = |name: HeaderName, token: &str| {
+            headers
+                .get_all(name)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(','))
+                .any(|t| t.trim().eq_ignore_ascii_case(token))
+        };
+
+        let key = if parts.version <= Version::HTTP_11 {
+            if parts.method != Method::GET {
+                return Err(reject(
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    "Request method must be `GET`",
+                ));
+            }
+            if !has_token(header::CONNECTION, "upgrade") {
+                return Err(reject(
+                    StatusCode::BAD_REQUEST,
+                    "Connection header did not include 'upgrade'",
+                ));
+            }
+            if !has_token(header::UPGRADE, "websocket") {
+                return Err(reject(
+                    StatusCode::BAD_REQUEST,
+                    "`Upgrade` header did not include 'websocket'",
+                ));
+            }
+            let Some(key) = headers.get(header::SEC_WEBSOCKET_KEY) else {
+                return Err(reject(
+                    StatusCode::BAD_REQUEST,
+                    "`Sec-WebSocket-Key` header missing",
+                ));
+            };
+            Some(key.clone())
+        } else {
+            if parts.method != Method::CONNECT {
+                return Err(reject(
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    "Request method must be `CONNECT`",
+                ));
+            }
+            None
+        };
+
+        if headers
+            .get(header::SEC_WEBSOCKET_VERSION)
+            .is_none_or(|v| v.as_bytes() != b"13")
+        {
+            return Err(reject(
+                StatusCode::BAD_REQUEST,
+                "`Sec-WebSocket-Version` header did not include '13'",
+            ));
+        }
+
+        let Some(on_upgrade) = parts.extensions.remove::<hyper::upgrade::OnUpgrade>() else {
+            return Err(reject(
+                StatusCode::UPGRADE_REQUIRED,
+                "WebSocket request couldn't be upgraded since no upgrade state was present",
+            ));
+        };
+
+        Ok(Self { key, on_upgrade })
+    }
+}
+
+impl ClientUpgrade {
+    /// 升级响应：回写选中的子协议与扩展
+    fn response(&self, protocol: Option<&str>, extensions: Option<&str>) -> Response {
+        let mut response = match &self.key {
+            Some(key) => Response::builder()
+                .status(StatusCode::SWITCHING_PROTOCOLS)
+                .header(header::CONNECTION, "upgrade")
+                .header(header::UPGRADE, "websocket")
+                .header(
+                    header::SEC_WEBSOCKET_ACCEPT,
+                    tokio_tungstenite::tungstenite::handshake::derive_accept_key(key.as_bytes()),
+                )
+                .body(Body::empty())
+                .unwrap_or_default(),
+            // HTTP/2 扩展 CONNECT 以 2xx 表示成功（RFC 8441）
+            None => Response::new(Body::empty()),
+        };
+        let out = response.headers_mut();
+        for (name, value) in [
+            (header::SEC_WEBSOCKET_PROTOCOL, protocol),
+            (header::SEC_WEBSOCKET_EXTENSIONS, extensions),
+        ] {
+            if let Some(v) = value.and_then(|v| HeaderValue::from_str(v).ok()) {
+                out.insert(name, v);
+            }
+        }
+        response
+    }
+
+    /// 等待 hyper 交出升级后的连接，并按协商结果包装为服务端 WebSocket
+    async fn into_socket(
+        self,
+        deflate: Option<DeflateParams>,
+        config: WebSocketConfig,
+        observer: DeflateObserver,
+    ) -> Result<ClientWs> {
+        let upgraded = self.on_upgrade.await.context("WS upgrade failed")?;
+        let io = WsDeflate::new(
+            TokioIo::new(upgraded),
+            deflate,
+            ws_deflate::inflate_limit(&config),
+        )
+        .with_observer(observer);
+        Ok(WebSocketStream::from_raw_socket(io, Role::Server, Some(config)).await)
+    }
+}
+
 async fn ws_handler(
     ConnectInfo(remote): ConnectInfo<SocketAddr>,
     State(WsRuleState(rule)): State<WsRuleState>,
-    State(AppHandleState(app)): State<AppHandleState>,
+    State(AppEventsState(app)): State<AppEventsState>,
     State(state): State<WsAppState>,
     uri: Uri,
-    ws: WebSocketUpgrade,
     headers: HeaderMap,
+    upgrade: ClientUpgrade,
 ) -> Response {
+    let path = uri.path().to_string();
+    let req_log = WsRequestLog::new(&rule.listen_addr, &remote, &headers, &path);
+
     // 访问控制（与 HTTP 代理一致）：黑名单优先，其次白名单，再次 allow_all_lan
+    let lists = state.access_lists.load();
     if state.ws_access_control_enabled
         && !access_control::is_allowed_fast(
             &remote,
             &headers,
-            state.allow_all_lan,
-            state.allow_all_ip,
-            &state.whitelist,
+            lists.allow_all_lan,
+            lists.allow_all_ip,
+            &lists.whitelist,
         )
     {
-        let ip = access_control::client_ip_from_headers(&remote, &headers);
-        let _ = app.emit(
-            "log-line",
-            format!("WS forbidden: ip={ip} path={}", uri.path()),
+        crate::proxy::send_log_with_app(
+            &app,
+            LogLevel::Warn,
+            Some(rule.listen_addr.as_str()),
+            format!("WS forbidden: ip={} path={path}", req_log.client_ip),
         );
-        return (StatusCode::FORBIDDEN, "Forbidden").into_response();
+        return req_log.reject(StatusCode::FORBIDDEN, "", "Forbidden");
     }
 
-    let path = uri.path().to_string();
-
-    let route = match_ws_route(&rule.routes, &path);
+    let request_host = if req_log.host.is_empty() {
+        uri.host().unwrap_or("")
+    } else {
+        req_log.host.as_str()
+    };
+    let route = match_ws_route(&rule.routes, request_host, &path);
     let Some(route) = route else {
-        return (StatusCode::NOT_FOUND, "No WS route").into_response();
+        return req_log.reject(StatusCode::NOT_FOUND, "", "No WS route");
+    };
+
+    let client_ip = req_log.client_ip.clone();
+    let mut candidates: Vec<WsCandidate> =
+        ws_upstream_candidates(&rule.listen_addr, route, &client_ip)
+            .into_iter()
+            .filter_map(|upstream| {
+                let target = ws_target_url(&upstream, route, &uri).ok()?;
+                Some(WsCandidate { upstream, target })
+            })
+            .collect();
+    // 近期连续失败的上游排到最后，全部不可用时仍会按顺序尝试
+    candidates.sort_by_key(|c| stream_proxy::is_down(&c.upstream));
+    if candidates.is_empty() {
+        return req_log.reject(StatusCode::BAD_GATEWAY, "", "No WS upstream");
+    }
+
+    let route = route.clone();
+    let is_tls = rule.ssl_enable;
+    let keepalive = WsKeepalive::from_rule(&rule);
+    let stats = ws_stats(&rule.listen_addr);
+    let conn_stats = WsConnStats::new(stats.clone(), &route.path);
+
+    let Some(conn_guard) = stats.try_acquire(
+        &client_ip,
+        rule.max_connections_per_ip.unwrap_or(0),
+        rule.max_total_connections.unwrap_or(0),
+    ) else {
+        ws_log(
+            &app,
+            LogLevel::Warn,
+            Some(rule.listen_addr.as_str()),
+            format!("Connection limit exceeded: ip={client_ip} path={path}"),
+        );
+        return req_log.reject(StatusCode::TOO_MANY_REQUESTS, "", "Too many WS connections");
+    };
+
+    // 两端共用同一套大小限制
+    let mut ws_config = WebSocketConfig::default();
+    if let Some(n) = rule.max_message_size.filter(|n| *n > 0) {
+        ws_config = ws_config.max_message_size(Some(n));
+    }
+    if let Some(n) = rule.max_frame_size.filter(|n| *n > 0) {
+        ws_config = ws_config.max_frame_size(Some(n));
+    }
+
+    // 与客户端独立协商压缩；与上游的协商在握手时进行，两端结果可以不同
+    let client_deflate = if rule.permessage_deflate {
+        ws_deflate::accept_offer(
+            headers
+                .get_all(header::SEC_WEBSOCKET_EXTENSIONS)
+                .iter()
+                .filter_map(|v| v.to_str().ok()),
+        )
+    } else {
+        None
+    };
+
+    let connected = connect_upstream(
+        &app,
+        &candidates,
+        &route,
+        &headers,
+        &remote,
+        is_tls,
+        UpstreamOptions {
+            config: ws_config,
+            deflate: rule.permessage_deflate,
+            stats: &conn_stats,
+        },
+    )
+    .await;
+    let UpstreamConn {
+        ws: upstream,
+        url: upstream_url,
+        protocol,
+        failed_attempts,
+        in_flight: upstream_in_flight,
+    } = match connected {
+        Ok(v) => v,
+        Err(e) => {
+            conn_stats.on_connect_failure(connect_attempts(&route, candidates.len()));
+            crate::proxy::send_log_with_app(
+                &app,
+                LogLevel::Error,
+                Some(rule.listen_addr.as_str()),
+                format!("WS proxy error: {e:#}"),
+            );
+            return req_log.reject(
+                StatusCode::BAD_GATEWAY,
+                &candidates[0].target,
+                "WS upstream unavailable",
+            );
+        }
+    };
+    if failed_attempts > 0 {
+        conn_stats.on_connect_failure(failed_attempts);
+    }
+
+    // 子协议：将上游选中的协议回写给客户端；上游选了客户端未提供的协议则拒绝升级
+    if let Some(protocol) = &protocol {
+        if !client_offered_protocol(&headers, protocol) {
+            crate::proxy::send_log_with_app(
+                &app,
+                LogLevel::Error,
+                Some(rule.listen_addr.as_str()),
+                format!(
+                    "WS proxy error: upstream={upstream_url} selected subprotocol '{protocol}' not offered by client ip={client_ip} path={path}"
+                ),
+            );
+            return req_log.reject(
+                StatusCode::BAD_GATEWAY,
+                &upstream_url,
+                "WS subprotocol mismatch",
+            );
+        }
+    }
+
+    let deflate = if rule.permessage_deflate {
+        format!(
+            " deflate(client={}, upstream={})",
+            client_deflate.is_some(),
+            upstream.get_ref().negotiated()
+        )
+    } else {
+        String::new()
     };
+    ws_log(
+        &app,
+        LogLevel::Info,
+        Some(rule.listen_addr.as_str()),
+        format!("Connected: ip={client_ip} path={path} upstream={upstream_url}{deflate}"),
+    );
 
-    let upstream = route.upstream_url.clone();
+    req_log.record(StatusCode::SWITCHING_PROTOCOLS, &upstream_url);
 
-    ws.on_upgrade(move |socket| async move {
-        if let Err(e) = proxy_ws(socket, upstream).await {
-            let _ = app.emit("log-line", format!("WS proxy error: {e}"));
+    let response = upgrade.response(
+        protocol.as_deref(),
+        client_deflate.as_ref().map(|(_, ext)| ext.as_str()),
+    );
+    let in_flight = state.in_flight.clone();
+    tokio::spawn(async move {
+        let socket = match upgrade
+            .into_socket(
+                client_deflate.map(|(params, _)| params),
+                ws_config,
+                conn_stats.deflate_observer(),
+            )
+            .await
+        {
+            Ok(socket) => socket,
+            Err(e) => {
+                ws_log(
+                    &app,
+                    LogLevel::Warn,
+                    Some(req_log.listen_addr.as_str()),
+                    format!("{e:#}: ip={client_ip} path={path}"),
+                );
+                return;
+            }
+        };
+        let _conn_guard = conn_guard;
+        let _upstream_in_flight = upstream_in_flight;
+        let _active = connections::track(ActiveEntry::ws(
+            &req_log.listen_addr,
+            &client_ip,
+            &path,
+            &upstream_url,
+        ));
+        let opened_at = Instant::now();
+        conn_stats.on_open();
+        let relay = in_flight
+            .track(proxy_ws(socket, upstream, keepalive, &conn_stats))
+            .await
+            .unwrap_or(Ok(RelayEnd::Cut));
+        conn_stats.on_close();
+
+        let session = req_log.session(&upstream_url, opened_at.elapsed(), &conn_stats.session);
+        if let Err(e) = crate::metrics::insert_ws_session(session).await {
+            error!("Failed to record WS session: {e:#}");
+        }
+
+        match relay {
+            Ok(RelayEnd::Closed) => {}
+            Ok(RelayEnd::TimedOut(reason)) => {
+                stats.timed_out.fetch_add(1, Ordering::Relaxed);
+                ws_log(
+                    &app,
+                    LogLevel::Info,
+                    Some(req_log.listen_addr.as_str()),
+                    format!(
+                        "Closed ({reason}): ip={client_ip} path={path} upstream={upstream_url}"
+                    ),
+                );
+            }
+            Ok(RelayEnd::MessageTooBig(detail)) => {
+                ws_log(
+                    &app,
+                    LogLevel::Warn,
+                    Some(req_log.listen_addr.as_str()),
+                    format!(
+                        "Closed (message too big, 1009): ip={client_ip} path={path} upstream={upstream_url}: {detail}"
+                    ),
+                );
+            }
+            Ok(RelayEnd::Cut) => {
+                ws_log(
+                    &app,
+                    LogLevel::Info,
+                    Some(req_log.listen_addr.as_str()),
+                    format!(
+                        "Closed (proxy stopped, drain timeout): ip={client_ip} path={path} upstream={upstream_url}"
+                    ),
+                );
+            }
+            Err(e) => {
+                crate::proxy::send_log_with_app(
+                    &app,
+                    LogLevel::Error,
+                    Some(req_log.listen_addr.as_str()),
+                    format!("WS proxy error: upstream={upstream_url}: {e:#}"),
+                );
+            }
         }
-    })
+    });
+    response
 }
 
-async fn proxy_ws(client: ws::WebSocket, upstream_url: String) -> Result<()> {
-    let (upstream, _) = tokio_tungstenite::connect_async(&upstream_url)
-        .await
-        .with_context(|| format!("connect upstream ws failed: {upstream_url}"))?;
+/// 客户端 Sec-WebSocket-Protocol 中是否包含指定协议（协议名区分大小写）
+fn client_offered_protocol(headers: &HeaderMap, protocol: &str) -> bool {
+    headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|p| p.trim() == protocol)
+}
+
+/// WS 连接保活参数
+#[derive(Debug, Clone, Copy)]
+struct WsKeepalive {
+    ping_interval: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    max_missed_pings: u32,
+}
 
-    let (mut u_tx, mut u_rx) = upstream.split();
-    let (mut c_tx, mut c_rx) = client.split();
+impl WsKeepalive {
+    fn from_rule(rule: &WsListenRule) -> Self {
+        Self {
+            ping_interval: (rule.ping_interval_secs > 0)
+                .then(|| Duration::from_secs(rule.ping_interval_secs)),
+            idle_timeout: (rule.idle_timeout_secs > 0)
+                .then(|| Duration::from_secs(rule.idle_timeout_secs)),
+            max_missed_pings: rule.max_missed_pings.max(1),
+        }
+    }
 
-    let c_to_u = async {
-        while let Some(msg) = c_rx.next().await {
-            let msg = msg.map_err(|e| anyhow!(e))?;
-            let tmsg = match msg {
-                ws::Message::Text(s) => {
-                    tokio_tungstenite::tungstenite::Message::Text(s.to_string().into())
+    fn enabled(&self) -> bool {
+        self.ping_interval.is_some() || self.idle_timeout.is_some()
+    }
+
+    /// 定时检查间隔：默认 1 秒，配置的时长更短时按更短的检查
+    fn tick(&self) -> Duration {
+        [self.ping_interval, self.idle_timeout]
+            .into_iter()
+            .flatten()
+            .fold(Duration::from_secs(1), Duration::min)
+    }
+}
+
+/// 中继结束原因
+enum RelayEnd {
+    Closed,
+    /// 停止服务时超过 drain 时长被强制断开
+    Cut,
+    TimedOut(&'static str),
+    MessageTooBig(String),
+}
+
+type ClientSink = futures_util::stream::SplitSink<ClientWs, Message>;
+type UpstreamSink = futures_util::stream::SplitSink<UpstreamWs, Message>;
+
+/// 尽力向两端发送 Close 帧（发送失败忽略）
+async fn close_pair(c_tx: &mut ClientSink, u_tx: &mut UpstreamSink, code: CloseCode, reason: &str) {
+    let frame = CloseFrame {
+        code,
+        reason: reason.to_string().into(),
+    };
+    let _ = c_tx.send(Message::Close(Some(frame.clone()))).await;
+    let _ = u_tx.send(Message::Close(Some(frame))).await;
+}
+
+/// 读取错误是否为超出消息/帧大小限制（两端均为 tungstenite 错误）
+fn is_size_error(e: &WsError) -> bool {
+    matches!(e, WsError::Capacity(CapacityError::MessageTooLong { .. }))
+}
+
+/// 生成本次连接的上游尝试顺序：按策略选出首选上游，其余按配置顺序作为故障转移候选
+fn ws_upstream_candidates(listen_addr: &str, route: &WsRoute, client_ip: &str) -> Vec<String> {
+    let primary = match route.lb_strategy {
+        WsLbStrategy::RoundRobin => {
+            let key = format!("ws|{}|{}", listen_addr, route.path);
+            upstream::pick_smooth_weighted(&key, &route.upstreams)
+        }
+        WsLbStrategy::IpHash => upstream::pick_weighted_hash(client_ip, &route.upstreams),
+    };
+
+    let mut out: Vec<String> = Vec::with_capacity(route.upstreams.len());
+    if let Some(p) = primary {
+        out.push(p);
+    }
+    for u in &route.upstreams {
+        if !out.iter().any(|x| x == &u.url) {
+            out.push(u.url.clone());
+        }
+    }
+    out
+}
+
+/// 由上游基础地址 + 改写后的请求路径和查询串生成最终上游地址
+fn ws_target_url(base: &str, route: &WsRoute, uri: &Uri) -> Result<String> {
+    upstream::build_upstream_url(
+        base,
+        Some(route.path.as_str()),
+        route.proxy_pass_path.as_deref(),
+        uri,
+    )
+}
+
+/// 已完成握手的上游连接
+struct WsCandidate {
+    /// 配置中的上游地址（被动健康检查的 key）
+    upstream: String,
+    /// 拼接请求路径后的实际连接地址
+    target: String,
+}
+
+/// 一次握手最多尝试的次数：每个候选一次，再加 retry_count 次
+fn connect_attempts(route: &WsRoute, candidates: usize) -> u64 {
+    candidates as u64 + route.retry_count as u64
+}
+
+struct UpstreamConn {
+    ws: UpstreamWs,
+    url: String,
+    /// 上游选中的子协议
+    protocol: Option<String>,
+    /// 成功前失败的尝试次数
+    failed_attempts: u64,
+    /// 计入该上游的进行中会话
+    in_flight: InFlightGuard,
+}
+
+/// 上游握手参数
+struct UpstreamOptions<'a> {
+    config: WebSocketConfig,
+    /// 是否向上游提议 permessage-deflate
+    deflate: bool,
+    stats: &'a WsConnStats,
+}
+
+/// 依次尝试候选上游，返回第一个握手成功的连接及其地址
+async fn connect_upstream(
+    app: &AppEvents,
+    candidates: &[WsCandidate],
+    route: &WsRoute,
+    inbound_headers: &HeaderMap,
+    remote: &SocketAddr,
+    is_tls: bool,
+    opts: UpstreamOptions<'_>,
+) -> Result<UpstreamConn> {
+    let connector = tls_connector(route.tls.as_ref())?;
+    let mut last_err: Option<anyhow::Error> = None;
+
+    let attempts = connect_attempts(route, candidates.len()) as usize;
+    for (i, candidate) in candidates.iter().cycle().take(attempts).enumerate() {
+        let upstream_url = &candidate.target;
+        let attempt = async {
+            let request = build_upstream_request(
+                upstream_url,
+                &route.forward_headers,
+                inbound_headers,
+                remote,
+                is_tls,
+            )?;
+            let (ws, resp) = open_upstream(request, connector.clone(), &opts)
+                .await
+                .map_err(|e| {
+                    anyhow!(
+                        "connect upstream ws failed ({}): {upstream_url}: {e}",
+                        classify_connect_error(&e)
+                    )
+                })?;
+            let protocol = resp
+                .headers()
+                .get(header::SEC_WEBSOCKET_PROTOCOL)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty());
+            Result::<UpstreamConn>::Ok(UpstreamConn {
+                ws,
+                url: upstream_url.clone(),
+                protocol,
+                failed_attempts: i as u64,
+                in_flight: upstream_health::track_in_flight(&candidate.upstream),
+            })
+        };
+
+        match attempt.await {
+            Ok(conn) => {
+                stream_proxy::record_upstream_success(&candidate.upstream);
+                return Ok(conn);
+            }
+            Err(e) => {
+                stream_proxy::record_upstream_failure(
+                    &candidate.upstream,
+                    route.max_fails,
+                    &route.fail_timeout,
+                    &format!("{e:#}"),
+                );
+                if i + 1 < attempts {
+                    ws_log(
+                        app,
+                        LogLevel::Warn,
+                        None,
+                        format!("{e:#}, trying next upstream"),
+                    );
                 }
-                ws::Message::Binary(b) => tokio_tungstenite::tungstenite::Message::Binary(b),
-                ws::Message::Ping(b) => tokio_tungstenite::tungstenite::Message::Ping(b),
-                ws::Message::Pong(b) => tokio_tungstenite::tungstenite::Message::Pong(b),
-                ws::Message::Close(c) => {
-                    let frame = c.map(|c| tokio_tungstenite::tungstenite::protocol::CloseFrame {
-                        code: tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode::from(
-                            u16::from(c.code),
-                        ),
-                        reason: c.reason.to_string().into(),
-                    });
-                    tokio_tungstenite::tungstenite::Message::Close(frame)
+                last_err = Some(e);
+            }
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| anyhow!("No WS upstream available")))
+}
+
+/// 建立到上游的 TCP/TLS 连接并完成握手；帧改写层位于 TLS 之上、tungstenite 之下
+async fn open_upstream(
+    mut request: Request,
+    connector: Option<Connector>,
+    opts: &UpstreamOptions<'_>,
+) -> std::result::Result<
+    (
+        UpstreamWs,
+        tokio_tungstenite::tungstenite::handshake::client::Response,
+    ),
+    WsError,
+> {
+    if opts.deflate {
+        request.headers_mut().insert(
+            header::SEC_WEBSOCKET_EXTENSIONS,
+            HeaderValue::from_static(ws_deflate::PERMESSAGE_DEFLATE),
+        );
+    }
+    let uri = request.uri();
+    let tls = match uri.scheme_str() {
+        Some("wss") => true,
+        Some("ws") => false,
+        _ => return Err(WsError::Url(UrlError::UnsupportedUrlScheme)),
+    };
+    let host = uri
+        .host()
+        .ok_or(WsError::Url(UrlError::NoHostName))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+
+    let tcp = tokio::net::TcpStream::connect((host.as_str(), port)).await?;
+    let stream = if tls {
+        let config = match connector {
+            Some(Connector::Rustls(config)) => config,
+            _ => DEFAULT_WS_TLS_CONFIG.clone(),
+        };
+        let domain = ServerName::try_from(host).map_err(|_| {
+            WsError::Tls(tokio_tungstenite::tungstenite::error::TlsError::InvalidDnsName)
+        })?;
+        MaybeTlsStream::Rustls(
+            tokio_rustls::TlsConnector::from(config)
+                .connect(domain, tcp)
+                .await?,
+        )
+    } else {
+        MaybeTlsStream::Plain(tcp)
+    };
+
+    let io = WsDeflate::client(
+        stream,
+        opts.deflate,
+        ws_deflate::inflate_limit(&opts.config),
+    )
+    .with_observer(opts.stats.deflate_observer());
+    tokio_tungstenite::client_async_with_config(request, io, Some(opts.config)).await
+}
+
+/// 根据路由 TLS 选项构造连接器；默认配置返回 None（使用内置 webpki 根证书）
+fn tls_connector(tls: Option<&WsUpstreamTls>) -> Result<Option<Connector>> {
+    let Some(tls) = tls else {
+        return Ok(None);
+    };
+    let ca_file = tls
+        .ca_file
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty());
+    if tls.verify && ca_file.is_none() {
+        return Ok(None);
+    }
+
+    let key = (tls.verify, ca_file.unwrap_or_default().to_string());
+    if let Some(c) = WS_TLS_CONNECTORS.get(&key) {
+        return Ok(Some(c.clone()));
+    }
+
+    let config = if tls.verify {
+        let mut root_store = rustls::RootCertStore::empty();
+        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+        let path = &key.1;
+        let pem = std::fs::read(path)
+            .with_context(|| format!("Failed to read WS upstream CA file: {path}"))?;
+        let mut added = 0usize;
+        for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+            let cert = cert.with_context(|| format!("Invalid certificate in CA file: {path}"))?;
+            root_store
+                .add(cert)
+                .with_context(|| format!("Invalid certificate in CA file: {path}"))?;
+            added += 1;
+        }
+        if added == 0 {
+            return Err(anyhow!("No certificate found in CA file: {path}"));
+        }
+
+        rustls::ClientConfig::builder()
+            .with_root_certificates(root_store)
+            .with_no_client_auth()
+    } else {
+        rustls::ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoCertVerifier::new()))
+            .with_no_client_auth()
+    };
+
+    let connector = Connector::Rustls(Arc::new(config));
+    WS_TLS_CONNECTORS.insert(key, connector.clone());
+    Ok(Some(connector))
+}
+
+/// 跳过证书校验（仅用于 tls.verify = false），签名仍按 provider 校验
+#[derive(Debug)]
+pub(crate) struct NoCertVerifier(Arc<rustls::crypto::CryptoProvider>);
+
+impl NoCertVerifier {
+    pub(crate) fn new() -> Self {
+        let provider = rustls::crypto::CryptoProvider::get_default()
+            .cloned()
+            .unwrap_or_else(|| Arc::new(rustls::crypto::aws_lc_rs::default_provider()));
+        Self(provider)
+    }
+}
+
+impl ServerCertVerifier for NoCertVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// 区分上游连接失败原因，便于在日志中定位（TLS 校验失败 / 连接被拒绝 / 其他）
+fn classify_connect_error(e: &tokio_tungstenite::tungstenite::Error) -> &'static str {
+    use tokio_tungstenite::tungstenite::Error;
+
+    match e {
+        Error::Tls(_) => "TLS error",
+        Error::Io(io) => {
+            if io
+                .get_ref()
+                .is_some_and(|inner| inner.downcast_ref::<rustls::Error>().is_some())
+            {
+                return "TLS error";
+            }
+            match io.kind() {
+                std::io::ErrorKind::ConnectionRefused => "connection refused",
+                std::io::ErrorKind::TimedOut => "connection timed out",
+                _ => "I/O error",
+            }
+        }
+        Error::Http(_) => "upstream rejected handshake",
+        _ => "handshake error",
+    }
+}
+
+/// 构造上游握手请求：按路由白名单复制客户端请求头，并追加 X-Forwarded-* 头
+fn build_upstream_request(
+    upstream_url: &str,
+    forward_headers: &[String],
+    inbound_headers: &HeaderMap,
+    remote: &SocketAddr,
+    is_tls: bool,
+) -> Result<Request> {
+    let mut request = upstream_url
+        .into_client_request()
+        .with_context(|| format!("invalid upstream ws url: {upstream_url}"))?;
+    let out = request.headers_mut();
+
+    // 子协议协商依赖上游看到客户端请求的协议列表，始终转发
+    for v in inbound_headers.get_all(header::SEC_WEBSOCKET_PROTOCOL) {
+        out.append(header::SEC_WEBSOCKET_PROTOCOL, v.clone());
+    }
+
+    for name in forward_headers {
+        let Ok(name) = HeaderName::from_bytes(name.trim().as_bytes()) else {
+            continue;
+        };
+        // 扩展由代理与上游单独协商（见 open_upstream），客户端的提议不透传
+        if name == header::SEC_WEBSOCKET_PROTOCOL || name == header::SEC_WEBSOCKET_EXTENSIONS {
+            continue;
+        }
+        for v in inbound_headers.get_all(&name) {
+            out.append(name.clone(), v.clone());
+        }
+    }
+
+    let remote_ip = remote.ip().to_string();
+    if let Ok(v) = HeaderValue::from_str(&remote_ip) {
+        out.insert(HeaderName::from_static("x-real-ip"), v);
+    }
+
+    let prior = inbound_headers
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty());
+    let combined = match prior {
+        Some(p) => format!("{}, {}", p, remote_ip),
+        None => remote_ip,
+    };
+    if let Ok(v) = HeaderValue::from_str(&combined) {
+        out.insert(HeaderName::from_static("x-forwarded-for"), v);
+    }
+
+    out.insert(
+        HeaderName::from_static("x-forwarded-proto"),
+        HeaderValue::from_static(if is_tls { "https" } else { "http" }),
+    );
+
+    Ok(request)
+}
+
+/// 两个转发方向与保活定时器共享的活跃时间、未响应 Ping 计数
+struct RelayActivity {
+    started: Instant,
+    /// 最近一次转发数据的时间（相对 started 的毫秒数）
+    last_activity_ms: AtomicU64,
+    client_missed: AtomicU32,
+    upstream_missed: AtomicU32,
+}
+
+impl RelayActivity {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_activity_ms: AtomicU64::new(0),
+            client_missed: AtomicU32::new(0),
+            upstream_missed: AtomicU32::new(0),
+        }
+    }
+
+    fn touch(&self) {
+        self.last_activity_ms
+            .store(self.started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn idle_for(&self) -> Duration {
+        let last = Duration::from_millis(self.last_activity_ms.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(last)
+    }
+}
+
+/// 双向中继：两个方向各自独立读写，一端写阻塞不影响另一方向；保活与空闲检测在单独的定时分支
+async fn proxy_ws(
+    client: ClientWs,
+    upstream: UpstreamWs,
+    keepalive: WsKeepalive,
+    conn_stats: &WsConnStats,
+) -> Result<RelayEnd> {
+    let (u_tx, mut u_rx) = upstream.split();
+    let (c_tx, mut c_rx) = client.split();
+    let c_tx = tokio::sync::Mutex::new(c_tx);
+    let u_tx = tokio::sync::Mutex::new(u_tx);
+    let activity = RelayActivity::new();
+
+    let client_to_upstream = async {
+        while let Some(msg) = c_rx.next().await {
+            let msg = match msg {
+                Ok(m) => m,
+                Err(e) if is_size_error(&e) => {
+                    close_pair(
+                        &mut *c_tx.lock().await,
+                        &mut *u_tx.lock().await,
+                        CloseCode::Size,
+                        "message too big",
+                    )
+                    .await;
+                    return Ok(RelayEnd::MessageTooBig(format!("client: {e}")));
                 }
+                Err(e) => return Err(anyhow!(e)),
             };
-            u_tx.send(tmsg).await.map_err(|e| anyhow!(e))?;
+            if let Message::Pong(b) = &msg {
+                if b.as_ref() == KEEPALIVE_PAYLOAD {
+                    activity.client_missed.store(0, Ordering::Relaxed);
+                    continue;
+                }
+            }
+            // 保活 Pong 不计入活跃，空闲超时只看实际转发的帧
+            activity.touch();
+            if let Some(n) = data_len(&msg) {
+                conn_stats.on_message(true, n);
+            }
+            u_tx.lock().await.send(msg).await.map_err(|e| anyhow!(e))?;
         }
-        Result::<()>::Ok(())
+        Ok(RelayEnd::Closed)
     };
 
-    let u_to_c = async {
+    let upstream_to_client = async {
         while let Some(msg) = u_rx.next().await {
-            let msg = msg.map_err(|e| anyhow!(e))?;
-            let amsg = match msg {
-                tokio_tungstenite::tungstenite::Message::Text(s) => {
-                    ws::Message::Text(s.to_string().into())
-                }
-                tokio_tungstenite::tungstenite::Message::Binary(b) => {
-                    ws::Message::Binary(Bytes::from(b))
+            let msg = match msg {
+                Ok(m) => m,
+                Err(e) if is_size_error(&e) => {
+                    close_pair(
+                        &mut *c_tx.lock().await,
+                        &mut *u_tx.lock().await,
+                        CloseCode::Size,
+                        "message too big",
+                    )
+                    .await;
+                    return Ok(RelayEnd::MessageTooBig(format!("upstream: {e}")));
                 }
-                tokio_tungstenite::tungstenite::Message::Ping(b) => {
-                    ws::Message::Ping(Bytes::from(b))
-                }
-                tokio_tungstenite::tungstenite::Message::Pong(b) => {
-                    ws::Message::Pong(Bytes::from(b))
-                }
-                tokio_tungstenite::tungstenite::Message::Close(c) => {
-                    let close = c.map(|c| ws::CloseFrame {
-                        code: ws::CloseCode::from(u16::from(c.code)),
-                        reason: ws::Utf8Bytes::from(c.reason.to_string()),
-                    });
-                    ws::Message::Close(close)
-                }
-                tokio_tungstenite::tungstenite::Message::Frame(_) => {
+                Err(e) => return Err(anyhow!(e)),
+            };
+            if let Message::Pong(b) = &msg {
+                if b.as_ref() == KEEPALIVE_PAYLOAD {
+                    activity.upstream_missed.store(0, Ordering::Relaxed);
                     continue;
                 }
+            }
+            activity.touch();
+            if let Some(n) = data_len(&msg) {
+                conn_stats.on_message(false, n);
+            }
+            c_tx.lock().await.send(msg).await.map_err(|e| anyhow!(e))?;
+        }
+        Ok(RelayEnd::Closed)
+    };
+
+    let keepalive_timer = async {
+        if !keepalive.enabled() {
+            return std::future::pending().await;
+        }
+        let mut ticker = tokio::time::interval(keepalive.tick());
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut last_ping = Instant::now();
+        loop {
+            ticker.tick().await;
+
+            let timed_out = if keepalive
+                .idle_timeout
+                .is_some_and(|idle| activity.idle_for() >= idle)
+            {
+                Some("idle timeout")
+            } else if activity.client_missed.load(Ordering::Relaxed) >= keepalive.max_missed_pings
+                || activity.upstream_missed.load(Ordering::Relaxed) >= keepalive.max_missed_pings
+            {
+                Some("ping timeout")
+            } else {
+                None
             };
-            c_tx.send(amsg).await.map_err(|e| anyhow!(e))?;
+
+            if let Some(reason) = timed_out {
+                close_pair(
+                    &mut *c_tx.lock().await,
+                    &mut *u_tx.lock().await,
+                    CloseCode::Away,
+                    reason,
+                )
+                .await;
+                return Ok(RelayEnd::TimedOut(reason));
+            }
+
+            if let Some(interval) = keepalive.ping_interval {
+                if last_ping.elapsed() >= interval {
+                    last_ping = Instant::now();
+                    let payload = Bytes::from_static(KEEPALIVE_PAYLOAD);
+                    c_tx.lock()
+                        .await
+                        .send(Message::Ping(payload.clone()))
+                        .await
+                        .map_err(|e| anyhow!(e))?;
+                    u_tx.lock()
+                        .await
+                        .send(Message::Ping(payload))
+                        .await
+                        .map_err(|e| anyhow!(e))?;
+                    activity.client_missed.fetch_add(1, Ordering::Relaxed);
+                    activity.upstream_missed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
         }
-        Result::<()>::Ok(())
     };
 
     tokio::select! {
-        r = c_to_u => { r?; }
-        r = u_to_c => { r?; }
+        end = client_to_upstream => end,
+        end = upstream_to_client => end,
+        end = keepalive_timer => end,
     }
+}
 
-    Ok(())
+/// 数据帧（Text/Binary）的负载长度，控制帧返回 None
+fn data_len(msg: &Message) -> Option<usize> {
+    match msg {
+        Message::Text(s) => Some(s.len()),
+        Message::Binary(b) => Some(b.len()),
+        _ => None,
+    }
 }
 
-fn match_ws_route<'a>(routes: &'a [WsRoute], path: &str) -> Option<&'a WsRoute> {
-    routes
-        .iter()
-        .filter(|r| path.starts_with(r.path.as_str()))
-        .max_by_key(|r| r.path.len())
+fn match_ws_route<'a>(
+    routes: &'a [WsRoute],
+    request_host: &str,
+    path: &str,
+) -> Option<&'a WsRoute> {
+    let host = matching::normalize_host(request_host);
+    matching::best_ranked(routes.iter().filter_map(|r| {
+        Some((
+            r,
+            matching::route_rank(r.host.as_deref(), &r.path, host, path)?,
+        ))
+    }))
 }
 
 /// 解析监听地址，返回主地址和是否需要同时绑定 IPv4/IPv6
@@ -399,3 +1924,559 @@ fn parse_listen_addr(s: &str) -> Result<(SocketAddr, bool)> {
 
     Ok((normalized, need_dual_stack))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        build_upstream_request, classify_connect_error, client_offered_protocol, connect_attempts,
+        default_ws_forward_headers, match_ws_route, open_upstream, proxy_ws, tls_connector,
+        ws_handler, ws_metrics_snapshot, ws_target_url, ws_upstream_candidates, ClientUpgrade,
+        RelayEnd, UpstreamOptions, WsAppState, WsConnStats, WsKeepalive, WsLbStrategy,
+        WsListenRule, WsRoute, WsRuleStats, WsUpstreamTls,
+    };
+    use crate::proxy::lifecycle::InFlight;
+    use crate::proxy::ws_deflate::{DeflateParams, WsDeflate};
+    use axum::http::Uri;
+    use axum::http::{HeaderMap, HeaderValue};
+    use futures_util::{SinkExt, StreamExt};
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Role, WebSocketConfig};
+    use tokio_tungstenite::tungstenite::Message;
+
+    type RelayResult = tokio::sync::oneshot::Receiver<anyhow::Result<RelayEnd>>;
+
+    /// 测试上游：responsive 为 false 时连接后不再读取，因此不会回应保活 Ping
+    async fn spawn_upstream(responsive: bool) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            if responsive {
+                while let Some(Ok(_)) = ws.next().await {}
+            } else {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+            }
+        });
+        format!("ws://{addr}")
+    }
+
+    /// 用 proxy_ws 把接入的客户端中继到上游，返回监听地址与中继结果
+    async fn spawn_relay(
+        upstream_url: String,
+        keepalive: WsKeepalive,
+        max_message_size: Option<usize>,
+    ) -> (SocketAddr, RelayResult) {
+        let (end_tx, end_rx) = tokio::sync::oneshot::channel();
+        let end_tx = Arc::new(parking_lot::Mutex::new(Some(end_tx)));
+        let app = axum::Router::new().route(
+            "/",
+            axum::routing::any(move |upgrade: ClientUpgrade| {
+                let end_tx = end_tx.clone();
+                let upstream_url = upstream_url.clone();
+                async move {
+                    let mut config = WebSocketConfig::default();
+                    if let Some(n) = max_message_size {
+                        config = config.max_message_size(Some(n));
+                    }
+                    let stats = WsConnStats::new(Arc::new(WsRuleStats::default()), "/");
+                    let opts = UpstreamOptions {
+                        config,
+                        deflate: false,
+                        stats: &stats,
+                    };
+                    let request = upstream_url.into_client_request().unwrap();
+                    let (upstream, _) = open_upstream(request, None, &opts).await.unwrap();
+                    let response = upgrade.response(None, None);
+                    tokio::spawn(async move {
+                        let socket = upgrade
+                            .into_socket(None, config, stats.deflate_observer())
+                            .await
+                            .unwrap();
+                        let end = proxy_ws(socket, upstream, keepalive, &stats).await;
+                        if let Some(tx) = end_tx.lock().take() {
+                            let _ = tx.send(end);
+                        }
+                    });
+                    response
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+        (addr, end_rx)
+    }
+
+    /// 读到 Close 帧为止（期间的保活 Ping 由 tungstenite 自动回应）
+    async fn next_close<S>(client: &mut tokio_tungstenite::WebSocketStream<S>) -> CloseFrame
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match client.next().await {
+                    Some(Ok(Message::Close(Some(frame)))) => return frame,
+                    Some(Ok(_)) => continue,
+                    other => panic!("connection ended without close frame: {other:?}"),
+                }
+            }
+        })
+        .await
+        .expect("close frame not received")
+    }
+
+    async fn relay_end(end_rx: RelayResult) -> RelayEnd {
+        tokio::time::timeout(Duration::from_secs(5), end_rx)
+            .await
+            .expect("relay did not finish")
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn relay_closes_idle_connection() {
+        let upstream = spawn_upstream(true).await;
+        let keepalive = WsKeepalive {
+            ping_interval: None,
+            idle_timeout: Some(Duration::from_millis(300)),
+            max_missed_pings: 3,
+        };
+        let (addr, end_rx) = spawn_relay(upstream, keepalive, None).await;
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/"))
+            .await
+            .unwrap();
+
+        let frame = next_close(&mut client).await;
+        assert_eq!(u16::from(frame.code), 1001);
+        assert_eq!(frame.reason.as_str(), "idle timeout");
+        assert!(matches!(
+            relay_end(end_rx).await,
+            RelayEnd::TimedOut("idle timeout")
+        ));
+    }
+
+    #[tokio::test]
+    async fn relay_closes_after_missed_pongs() {
+        // 客户端正常回应 Pong，上游不读取也就不回应
+        let upstream = spawn_upstream(false).await;
+        let keepalive = WsKeepalive {
+            ping_interval: Some(Duration::from_millis(100)),
+            idle_timeout: None,
+            max_missed_pings: 2,
+        };
+        let (addr, end_rx) = spawn_relay(upstream, keepalive, None).await;
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/"))
+            .await
+            .unwrap();
+
+        let frame = next_close(&mut client).await;
+        assert_eq!(u16::from(frame.code), 1001);
+        assert_eq!(frame.reason.as_str(), "ping timeout");
+        assert!(matches!(
+            relay_end(end_rx).await,
+            RelayEnd::TimedOut("ping timeout")
+        ));
+    }
+
+    #[tokio::test]
+    async fn relay_closes_oversized_client_message_with_1009() {
+        let upstream = spawn_upstream(true).await;
+        let keepalive = WsKeepalive {
+            ping_interval: None,
+            idle_timeout: None,
+            max_missed_pings: 1,
+        };
+        let (addr, end_rx) = spawn_relay(upstream, keepalive, Some(64)).await;
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/"))
+            .await
+            .unwrap();
+
+        client
+            .send(Message::Binary(vec![0u8; 1024].into()))
+            .await
+            .unwrap();
+
+        let frame = next_close(&mut client).await;
+        assert_eq!(u16::from(frame.code), 1009);
+        assert!(matches!(
+            relay_end(end_rx).await,
+            RelayEnd::MessageTooBig(_)
+        ));
+    }
+
+    /// 支持 permessage-deflate 的回显上游：要求握手中带有压缩提议并接受
+    async fn spawn_deflate_upstream() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(deflate_echo(stream));
+            }
+        });
+        format!("ws://{addr}")
+    }
+
+    async fn deflate_echo(mut stream: tokio::net::TcpStream) {
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut b = [0u8; 1];
+            stream.read_exact(&mut b).await.unwrap();
+            head.push(b[0]);
+        }
+        let head = String::from_utf8(head).unwrap();
+        let header = |name: &str| {
+            head.lines()
+                .filter_map(|l| l.split_once(':'))
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.trim().to_string())
+                .unwrap_or_default()
+        };
+        assert_eq!(header("sec-websocket-extensions"), "permessage-deflate");
+        let accept = tokio_tungstenite::tungstenite::handshake::derive_accept_key(
+            header("sec-websocket-key").as_bytes(),
+        );
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Accept: {accept}\r\nSec-WebSocket-Extensions: permessage-deflate\r\n\r\n"
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+
+        let io = WsDeflate::new(stream, Some(DeflateParams::default()), usize::MAX);
+        let mut ws =
+            tokio_tungstenite::WebSocketStream::from_raw_socket(io, Role::Server, None).await;
+        while let Some(Ok(msg)) = ws.next().await {
+            if msg.is_text() || msg.is_binary() {
+                ws.send(msg).await.unwrap();
+            }
+        }
+    }
+
+    /// 经 ws_handler 监听规则（与 start_ws_rule_server 相同的路由）
+    async fn spawn_ws_rule(rule: WsListenRule) -> SocketAddr {
+        let state = WsAppState {
+            rule,
+            app: crate::app_events::AppEvents::headless(),
+            ws_access_control_enabled: false,
+            access_lists: crate::access_control::access_lists_slot(),
+            in_flight: InFlight::new(),
+        };
+        let app = axum::Router::new()
+            .fallback(axum::routing::any(ws_handler))
+            .with_state(state)
+            .into_make_service_with_connect_info::<SocketAddr>();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn relay_negotiates_permessage_deflate_per_leg() {
+        let upstream = spawn_deflate_upstream().await;
+        let rule: WsListenRule = toml::from_str(&format!(
+            r#"
+enabled = true
+listen_addr = "deflate-test"
+ssl_enable = false
+cert_file = ""
+key_file = ""
+permessage_deflate = true
+
+[[routes]]
+path = "/"
+upstream_url = "{upstream}"
+"#
+        ))
+        .unwrap();
+        let addr = spawn_ws_rule(rule).await;
+
+        let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut request = format!("ws://{addr}/").into_client_request().unwrap();
+        request.headers_mut().insert(
+            "sec-websocket-extensions",
+            HeaderValue::from_static("permessage-deflate; client_max_window_bits"),
+        );
+        let io = WsDeflate::client(tcp, true, usize::MAX);
+        let (mut client, resp) = tokio_tungstenite::client_async(request, io).await.unwrap();
+        assert_eq!(
+            resp.headers().get("sec-websocket-extensions").unwrap(),
+            "permessage-deflate"
+        );
+        assert!(client.get_ref().negotiated());
+
+        let payload = "{\"metric\":\"rps\",\"value\":1234}".repeat(500);
+        client
+            .send(Message::Text(payload.clone().into()))
+            .await
+            .unwrap();
+        let echoed = tokio::time::timeout(Duration::from_secs(5), client.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(echoed, Message::Text(payload.clone().into()));
+
+        let stats = ws_metrics_snapshot()
+            .into_iter()
+            .find(|m| m.listen_addr == "deflate-test")
+            .unwrap();
+        // 客户端与上游两段、往返两个方向都经过压缩
+        assert!(stats.stats.deflate_bytes_saved > 3 * payload.len() as u64);
+
+        // 客户端不提议压缩：客户端一段不压缩，上游一段仍然压缩
+        let (mut plain, resp) = tokio_tungstenite::connect_async(format!("ws://{addr}/"))
+            .await
+            .unwrap();
+        assert!(resp.headers().get("sec-websocket-extensions").is_none());
+        plain
+            .send(Message::Text(payload.clone().into()))
+            .await
+            .unwrap();
+        let echoed = tokio::time::timeout(Duration::from_secs(5), plain.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(echoed, Message::Text(payload.into()));
+    }
+
+    #[test]
+    fn build_upstream_request_forwards_whitelisted_headers_and_client_ip() {
+        let remote = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 5000);
+        let mut inbound = HeaderMap::new();
+        inbound.insert("cookie", HeaderValue::from_static("sid=1"));
+        inbound.insert("authorization", HeaderValue::from_static("Bearer t"));
+        inbound.insert("x-custom", HeaderValue::from_static("drop-me"));
+        inbound.insert("x-forwarded-for", HeaderValue::from_static("1.1.1.1"));
+
+        let req = build_upstream_request(
+            "ws://backend/ws",
+            &default_ws_forward_headers(),
+            &inbound,
+            &remote,
+            true,
+        )
+        .unwrap();
+        let h = req.headers();
+
+        assert_eq!(h.get("cookie").unwrap(), "sid=1");
+        assert_eq!(h.get("authorization").unwrap(), "Bearer t");
+        assert!(h.get("x-custom").is_none());
+        assert_eq!(h.get("x-forwarded-for").unwrap(), "1.1.1.1, 10.0.0.2");
+        assert_eq!(h.get("x-real-ip").unwrap(), "10.0.0.2");
+        assert_eq!(h.get("x-forwarded-proto").unwrap(), "https");
+        assert!(h.get("sec-websocket-key").is_some());
+    }
+
+    #[test]
+    fn build_upstream_request_rejects_invalid_url() {
+        let remote = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 80);
+        assert!(
+            build_upstream_request("not a url", &[], &HeaderMap::new(), &remote, false).is_err()
+        );
+    }
+
+    #[test]
+    fn classify_connect_error_separates_tls_from_refused() {
+        use tokio_tungstenite::tungstenite::Error;
+
+        let tls = Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            rustls::Error::InvalidCertificate(rustls::CertificateError::UnknownIssuer),
+        ));
+        assert_eq!(classify_connect_error(&tls), "TLS error");
+
+        let refused = Error::Io(std::io::ErrorKind::ConnectionRefused.into());
+        assert_eq!(classify_connect_error(&refused), "connection refused");
+    }
+
+    #[test]
+    fn tls_connector_defaults_and_missing_ca_file() {
+        assert!(tls_connector(None).unwrap().is_none());
+
+        let verify_only = WsUpstreamTls {
+            verify: true,
+            ca_file: None,
+        };
+        assert!(tls_connector(Some(&verify_only)).unwrap().is_none());
+
+        let missing_ca = WsUpstreamTls {
+            verify: true,
+            ca_file: Some("/nonexistent/ca.pem".into()),
+        };
+        assert!(tls_connector(Some(&missing_ca)).is_err());
+    }
+
+    #[test]
+    fn ws_route_accepts_legacy_upstream_url() {
+        let route: WsRoute = toml::from_str(
+            r#"
+path = "/ws"
+upstream_url = "ws://127.0.0.1:9000"
+"#,
+        )
+        .unwrap();
+        assert_eq!(route.upstreams.len(), 1);
+        assert_eq!(route.upstreams[0].url, "ws://127.0.0.1:9000");
+        assert_eq!(route.upstreams[0].weight, 1);
+        assert_eq!(route.lb_strategy, WsLbStrategy::RoundRobin);
+        assert_eq!(route.retry_count, 0);
+        assert_eq!(connect_attempts(&route, route.upstreams.len()), 1);
+        assert_eq!(route.max_fails, 1);
+        assert_eq!(route.fail_timeout, "30s");
+    }
+
+    #[test]
+    fn ws_upstream_candidates_lists_every_upstream_once_for_failover() {
+        let route: WsRoute = toml::from_str(
+            r#"
+path = "/chat"
+lb_strategy = "ip_hash"
+upstreams = [
+  { url = "ws://a", weight = 1 },
+  { url = "ws://b", weight = 2 },
+  { url = "ws://c" },
+]
+"#,
+        )
+        .unwrap();
+
+        let first = ws_upstream_candidates("127.0.0.1:9001", &route, "10.0.0.1");
+        let second = ws_upstream_candidates("127.0.0.1:9001", &route, "10.0.0.1");
+        assert_eq!(first, second);
+
+        let mut sorted = first.clone();
+        sorted.sort();
+        assert_eq!(sorted, vec!["ws://a", "ws://b", "ws://c"]);
+    }
+
+    #[test]
+    fn ws_rule_stats_enforces_per_ip_and_total_limits() {
+        let stats = Arc::new(WsRuleStats::default());
+
+        let a1 = stats.try_acquire("10.0.0.1", 2, 3).unwrap();
+        let _a2 = stats.try_acquire("10.0.0.1", 2, 3).unwrap();
+        assert!(stats.try_acquire("10.0.0.1", 2, 3).is_none());
+
+        let _b1 = stats.try_acquire("10.0.0.2", 2, 3).unwrap();
+        assert!(stats.try_acquire("10.0.0.3", 2, 3).is_none());
+        assert!(!stats.active_per_ip.contains_key("10.0.0.3"));
+
+        drop(a1);
+        assert_eq!(stats.active.load(Ordering::Relaxed), 2);
+        assert_eq!(*stats.active_per_ip.get("10.0.0.1").unwrap(), 1);
+        assert!(stats.try_acquire("10.0.0.3", 2, 3).is_some());
+    }
+
+    fn route_with_rewrite(path: &str, proxy_pass_path: Option<&str>) -> WsRoute {
+        let mut route: WsRoute = toml::from_str(&format!(
+            "path = \"{path}\"\nupstream_url = \"ws://backend\""
+        ))
+        .unwrap();
+        route.proxy_pass_path = proxy_pass_path.map(str::to_string);
+        route
+    }
+
+    #[test]
+    fn ws_target_url_appends_request_path_and_query() {
+        let route = route_with_rewrite("/socket", None);
+        let uri: Uri = "/socket/app1?token=abc&v=2".parse().unwrap();
+        assert_eq!(
+            ws_target_url("ws://backend/", &route, &uri).unwrap(),
+            "ws://backend/socket/app1?token=abc&v=2"
+        );
+    }
+
+    #[test]
+    fn ws_target_url_rewrites_prefix_with_trailing_slashes() {
+        let route = route_with_rewrite("/socket/", Some("/ws/"));
+        let uri: Uri = "/socket/app2?room=1".parse().unwrap();
+        assert_eq!(
+            ws_target_url("wss://backend:8443/", &route, &uri).unwrap(),
+            "wss://backend:8443/ws/app2?room=1"
+        );
+
+        let route = route_with_rewrite("/socket", Some("/"));
+        let uri: Uri = "/socket".parse().unwrap();
+        assert_eq!(
+            ws_target_url("ws://backend", &route, &uri).unwrap(),
+            "ws://backend/"
+        );
+    }
+
+    #[test]
+    fn client_offered_protocol_matches_exact_names_across_headers() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            "sec-websocket-protocol",
+            HeaderValue::from_static("graphql-ws, graphql-transport-ws"),
+        );
+        headers.append("sec-websocket-protocol", HeaderValue::from_static("mqtt"));
+
+        assert!(client_offered_protocol(&headers, "graphql-transport-ws"));
+        assert!(client_offered_protocol(&headers, "mqtt"));
+        assert!(!client_offered_protocol(&headers, "MQTT"));
+        assert!(!client_offered_protocol(&HeaderMap::new(), "mqtt"));
+    }
+
+    #[test]
+    fn build_upstream_request_always_forwards_subprotocols() {
+        let remote = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 80);
+        let mut inbound = HeaderMap::new();
+        inbound.insert("sec-websocket-protocol", HeaderValue::from_static("mqtt"));
+
+        let req = build_upstream_request("ws://backend/", &[], &inbound, &remote, false).unwrap();
+        assert_eq!(req.headers().get("sec-websocket-protocol").unwrap(), "mqtt");
+    }
+
+    #[test]
+    fn match_ws_route_prefers_host_then_longest_path() {
+        let mut chat = route_with_rewrite("/ws", None);
+        chat.host = Some("chat.example.com".into());
+        let mut game = route_with_rewrite("/ws", None);
+        game.host = Some("*.game.example.com".into());
+        let generic = route_with_rewrite("/ws/admin", None);
+        let routes = vec![generic, chat, game];
+
+        let matched = |host: &str, path: &str| {
+            match_ws_route(&routes, host, path).and_then(|r| r.host.clone())
+        };
+        assert_eq!(
+            matched("Chat.Example.com:8443", "/ws/admin"),
+            Some("chat.example.com".into())
+        );
+        assert_eq!(
+            matched("eu.game.example.com", "/ws"),
+            Some("*.game.example.com".into())
+        );
+        assert_eq!(
+            match_ws_route(&routes, "other.example.com", "/ws/admin").map(|r| r.path.as_str()),
+            Some("/ws/admin")
+        );
+        assert!(match_ws_route(&routes, "other.example.com", "/ws").is_none());
+    }
+
+    #[test]
+    fn build_upstream_request_never_forwards_extensions() {
+        let remote = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 80);
+        let mut inbound = HeaderMap::new();
+        inbound.insert(
+            "sec-websocket-extensions",
+            HeaderValue::from_static("permessage-deflate; client_max_window_bits"),
+        );
+
+        let forward = vec!["Sec-WebSocket-Extensions".to_string()];
+        let req =
+            build_upstream_request("ws://backend/", &forward, &inbound, &remote, false).unwrap();
+        assert!(req.headers().get("sec-websocket-extensions").is_none());
+    }
+}
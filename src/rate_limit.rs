@@ -118,6 +118,11 @@ impl RateLimiter {
         }
     }
 
+    /// 当前跟踪的 IP 数量
+    pub fn tracked_ips(&self) -> usize {
+        self.buckets.len()
+    }
+
     /// 检查是否允许请求，返回 (是否允许, 是否需要封禁)
     pub fn check(&self, ip: &str) -> (bool, bool) {
         if !self.config.enabled {
@@ -156,3 +161,234 @@ pub fn get_rate_limiter(listen_addr: &str, config: RateLimitConfig) -> Arc<RwLoc
         .or_insert_with(|| Arc::new(RwLock::new(RateLimiter::new(config))))
         .clone()
 }
+
+/// 字节令牌桶（带宽限制），同时记录吞吐统计
+pub(crate) struct ByteBucket {
+    /// 当前可用字节数（允许为负，表示需要等待的欠额）
+    tokens: f64,
+    /// 令牌桶容量（字节）
+    capacity: f64,
+    /// 字节补充速率（每秒）
+    refill_rate: f64,
+    /// 上次更新时间
+    last_update: Instant,
+    /// 当前统计窗口起始时间
+    window_start: Instant,
+    /// 当前统计窗口内已发送字节数
+    window_bytes: u64,
+    /// 上一个统计窗口计算出的吞吐（字节/秒）
+    bytes_per_sec: f64,
+    /// 累计发送字节数
+    total_bytes: u64,
+}
+
+impl ByteBucket {
+    pub(crate) fn new(capacity: f64, refill_rate: f64) -> Self {
+        let now = Instant::now();
+        Self {
+            tokens: capacity,
+            capacity,
+            refill_rate,
+            last_update: now,
+            window_start: now,
+            window_bytes: 0,
+            bytes_per_sec: 0.0,
+            total_bytes: 0,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_update);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.refill_rate).min(self.capacity);
+        self.last_update = now;
+    }
+
+    fn record(&mut self, now: Instant, bytes: usize) {
+        self.tokens -= bytes as f64;
+        self.total_bytes = self.total_bytes.saturating_add(bytes as u64);
+        self.window_bytes = self.window_bytes.saturating_add(bytes as u64);
+
+        let window = now.duration_since(self.window_start);
+        if window >= Duration::from_secs(1) {
+            self.bytes_per_sec = self.window_bytes as f64 / window.as_secs_f64();
+            self.window_start = now;
+            self.window_bytes = 0;
+        }
+    }
+
+    /// 记账并返回发送前需要等待的时长（不足时透支，由调用方延迟发送）
+    pub(crate) fn reserve(&mut self, bytes: usize) -> Duration {
+        let now = Instant::now();
+        self.refill(now);
+        self.record(now, bytes);
+
+        if self.tokens >= 0.0 || self.refill_rate <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.refill_rate)
+        }
+    }
+
+    /// 令牌足够时扣除并返回 true，不足时不透支（用于直接丢弃的场景，如 UDP 包限速）
+    pub(crate) fn try_take(&mut self, n: usize) -> bool {
+        let now = Instant::now();
+        self.refill(now);
+        if self.tokens < n as f64 {
+            return false;
+        }
+        self.record(now, n);
+        true
+    }
+
+    /// 当前吞吐（字节/秒），长时间无流量时归零
+    fn current_bytes_per_sec(&self, now: Instant) -> f64 {
+        if now.duration_since(self.window_start) >= Duration::from_secs(2) {
+            0.0
+        } else {
+            self.bytes_per_sec
+        }
+    }
+}
+
+/// 单个 IP 的带宽统计
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BandwidthIpStats {
+    pub ip: String,
+    pub bytes_per_sec: f64,
+    pub total_bytes: u64,
+}
+
+/// 带宽限制器（按客户端 IP 限制写回客户端的字节速率）
+pub struct BandwidthLimiter {
+    /// IP -> 字节令牌桶的映射
+    buckets: Arc<DashMap<String, Arc<RwLock<ByteBucket>>>>,
+    /// 每个IP的字节限制（每秒）
+    bytes_per_sec: u64,
+    /// 清理任务句柄
+    _cleanup_handle: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let buckets: Arc<DashMap<String, Arc<RwLock<ByteBucket>>>> = Arc::new(DashMap::new());
+        let buckets_clone = buckets.clone();
+
+        // 与请求限速相同的清理策略：每5分钟清理一次空闲超过10分钟的桶
+        let cleanup_handle = Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(300));
+            loop {
+                interval.tick().await;
+                let now = Instant::now();
+                buckets_clone.retain(|_, bucket| {
+                    now.duration_since(bucket.read().last_update) < Duration::from_secs(600)
+                });
+            }
+        }));
+
+        Self {
+            buckets,
+            bytes_per_sec,
+            _cleanup_handle: cleanup_handle,
+        }
+    }
+
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec
+    }
+
+    /// 记录即将发送给 ip 的字节数，返回需要延迟的时长
+    pub fn reserve(&self, ip: &str, bytes: usize) -> Duration {
+        if self.bytes_per_sec == 0 || bytes == 0 {
+            return Duration::ZERO;
+        }
+
+        let bucket = self
+            .buckets
+            .entry(ip.to_string())
+            .or_insert_with(|| {
+                Arc::new(RwLock::new(ByteBucket::new(
+                    self.bytes_per_sec as f64,
+                    self.bytes_per_sec as f64,
+                )))
+            })
+            .clone();
+
+        let mut bucket = bucket.write();
+        bucket.reserve(bytes)
+    }
+
+    /// 各 IP 当前吞吐统计（按吞吐降序）
+    pub fn stats(&self) -> Vec<BandwidthIpStats> {
+        let now = Instant::now();
+        let mut out: Vec<BandwidthIpStats> = self
+            .buckets
+            .iter()
+            .map(|entry| {
+                let bucket = entry.value().read();
+                BandwidthIpStats {
+                    ip: entry.key().clone(),
+                    bytes_per_sec: bucket.current_bytes_per_sec(now),
+                    total_bytes: bucket.total_bytes,
+                }
+            })
+            .collect();
+        out.sort_by(|a, b| b.bytes_per_sec.total_cmp(&a.bytes_per_sec));
+        out
+    }
+}
+
+impl Drop for BandwidthLimiter {
+    fn drop(&mut self) {
+        if let Some(handle) = self._cleanup_handle.take() {
+            handle.abort();
+        }
+    }
+}
+
+/// 全局带宽限制器（按监听地址分组）
+pub static BANDWIDTH_LIMITERS: once_cell::sync::Lazy<Arc<DashMap<String, Arc<BandwidthLimiter>>>> =
+    once_cell::sync::Lazy::new(|| Arc::new(DashMap::new()));
+
+/// 获取或创建带宽限制器（限速值变化时重建）
+pub fn get_bandwidth_limiter(listen_addr: &str, bytes_per_sec: u64) -> Arc<BandwidthLimiter> {
+    if let Some(existing) = BANDWIDTH_LIMITERS.get(listen_addr) {
+        if existing.bytes_per_sec() == bytes_per_sec {
+            return existing.clone();
+        }
+    }
+    let limiter = Arc::new(BandwidthLimiter::new(bytes_per_sec));
+    BANDWIDTH_LIMITERS.insert(listen_addr.to_string(), limiter.clone());
+    limiter
+}
+
+/// 移除监听地址对应的带宽限制器
+pub fn remove_bandwidth_limiter(listen_addr: &str) {
+    BANDWIDTH_LIMITERS.remove(listen_addr);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ByteBucket;
+    use std::time::Duration;
+
+    #[test]
+    fn byte_bucket_allows_burst_then_requests_delay() {
+        let mut bucket = ByteBucket::new(1000.0, 1000.0);
+        assert_eq!(bucket.reserve(600), Duration::ZERO);
+        assert_eq!(bucket.reserve(400), Duration::ZERO);
+
+        let wait = bucket.reserve(500);
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+        assert_eq!(bucket.total_bytes, 1500);
+    }
+
+    #[test]
+    fn byte_bucket_try_take_does_not_overdraw() {
+        let mut bucket = ByteBucket::new(3.0, 1.0);
+        assert!(bucket.try_take(1));
+        assert!(bucket.try_take(2));
+        assert!(!bucket.try_take(1));
+        assert!(!bucket.try_take(1));
+        assert_eq!(bucket.total_bytes, 3);
+    }
+}
@@ -0,0 +1,287 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::path::PathBuf;
+
+use crate::config::{self, Config, ListenRule};
+
+// 系统凭据存储中的服务名，条目名即 keyring:<name> 中的 name
+const KEYRING_SERVICE: &str = "SSLProxyManager";
+const REFERENCE_PREFIX: &str = "keyring:";
+// 凭据存储无法枚举条目，另存一份名称列表（不含值）
+const NAMES_FILE: &str = "secret_names.json";
+
+/// 配置值形如 "keyring:<name>" 时返回条目名
+pub fn reference_name(value: &str) -> Option<&str> {
+    let name = value.trim().strip_prefix(REFERENCE_PREFIX)?.trim();
+    (!name.is_empty()).then_some(name)
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    let valid = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if name.is_empty() || name.len() > 128 || !valid {
+        bail!("Invalid secret name '{name}': use letters, digits, '-', '_' or '.'");
+    }
+    Ok(())
+}
+
+fn entry(name: &str) -> Result<keyring::Entry> {
+    validate_name(name)?;
+    keyring::Entry::new(KEYRING_SERVICE, name)
+        .with_context(|| format!("Failed to open keychain entry '{name}'"))
+}
+
+fn lookup(name: &str) -> Result<String> {
+    match entry(name)?.get_password() {
+        Ok(value) => Ok(value),
+        Err(keyring::Error::NoEntry) => Err(anyhow!("Keychain entry '{name}' not found")),
+        Err(e) => Err(anyhow!("Failed to read keychain entry '{name}': {e}")),
+    }
+}
+
+/// 解析可能引用凭据存储的配置值，普通值原样返回
+pub fn resolve(value: &str) -> Result<String> {
+    match reference_name(value) {
+        Some(name) => lookup(name),
+        None => Ok(value.to_string()),
+    }
+}
+
+/// 返回解析了密码引用的规则副本，只用于运行时，不写回配置
+pub fn resolve_rule(rule: &ListenRule) -> Result<ListenRule> {
+    let mut rule = rule.clone();
+    rule.basic_auth_password = resolve(&rule.basic_auth_password)
+        .with_context(|| format!("Listen rule ({}) basic_auth_password", rule.listen_addr))?;
+    for route in &mut rule.routes {
+        if let Some(password) = route.basic_auth_password.as_mut() {
+            *password = resolve(password)?;
+        }
+    }
+    Ok(rule)
+}
+
+/// 解析可选配置值，附带字段路径便于定位
+pub fn resolve_optional(value: Option<&str>, path: &str) -> Result<Option<String>> {
+    value
+        .map(|v| resolve(v).with_context(|| path.to_string()))
+        .transpose()
+}
+
+/// 配置中的凭据引用：(字段路径, 所在规则是否启用, 条目名)
+pub fn secret_references(cfg: &Config) -> Vec<(String, bool, String)> {
+    let mut refs = Vec::new();
+    for (i, rule) in cfg.rules.iter().enumerate() {
+        if let Some(name) = reference_name(&rule.basic_auth_password) {
+            refs.push((
+                format!("rules[{i}].basic_auth_password"),
+                rule.enabled,
+                name.to_string(),
+            ));
+        }
+        for (r, route) in rule.routes.iter().enumerate() {
+            if let Some(name) = route
+                .basic_auth_password
+                .as_deref()
+                .and_then(reference_name)
+            {
+                refs.push((
+                    format!("rules[{i}].routes[{r}].basic_auth_password"),
+                    rule.enabled && route.enabled,
+                    name.to_string(),
+                ));
+            }
+        }
+    }
+    // 规则之外的单值凭据字段
+    let mut push = |path: &str, enabled: bool, value: Option<&str>| {
+        if let Some(name) = value.and_then(reference_name) {
+            refs.push((path.to_string(), enabled, name.to_string()));
+        }
+    };
+    if let Some(webhook) = cfg.alerting.as_ref().and_then(|a| a.webhook.as_ref()) {
+        push(
+            "alerting.webhook.secret",
+            webhook.enabled,
+            webhook.secret.as_deref(),
+        );
+    }
+    if let Some(prometheus) = cfg.prometheus.as_ref() {
+        push(
+            "prometheus.bearer_token",
+            prometheus.enabled,
+            prometheus.bearer_token.as_deref(),
+        );
+    }
+    if let Some(storage) = cfg.metrics_storage.as_ref() {
+        let postgres = storage
+            .backend
+            .as_deref()
+            .is_some_and(|b| b.trim().eq_ignore_ascii_case("postgres"));
+        push(
+            "metrics_storage.url",
+            storage.enabled && postgres,
+            storage.url.as_deref(),
+        );
+    }
+    refs
+}
+
+/// 找不到对应凭据条目的引用
+pub fn missing_secrets(cfg: &Config) -> Vec<(String, bool, String)> {
+    secret_references(cfg)
+        .into_iter()
+        .filter(|(_, _, name)| lookup(name).is_err())
+        .collect()
+}
+
+fn names_path() -> Result<PathBuf> {
+    let path = config::get_config_path()?;
+    let parent = path.parent().context("配置路径缺少父目录")?;
+    Ok(parent.join(NAMES_FILE))
+}
+
+pub fn list_secret_names() -> Result<Vec<String>> {
+    let path = names_path()?;
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("读取凭据名称列表失败: {}", path.display()))?;
+    serde_json::from_str(&content).context("解析凭据名称列表失败")
+}
+
+fn save_secret_names(names: &[String]) -> Result<()> {
+    let content = serde_json::to_string_pretty(names)?;
+    config::write_file_atomic(&names_path()?, &content).context("写入凭据名称列表失败")
+}
+
+pub fn set_secret(name: &str, value: &str) -> Result<()> {
+    entry(name)?
+        .set_password(value)
+        .with_context(|| format!("Failed to store keychain entry '{name}'"))?;
+
+    let mut names = list_secret_names()?;
+    if !names.iter().any(|n| n == name) {
+        names.push(name.to_string());
+        names.sort();
+        save_secret_names(&names)?;
+    }
+    Ok(())
+}
+
+pub fn delete_secret(name: &str) -> Result<()> {
+    match entry(name)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => {}
+        Err(e) => bail!("Failed to delete keychain entry '{name}': {e}"),
+    }
+    let mut names = list_secret_names()?;
+    names.retain(|n| n != name);
+    save_secret_names(&names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_keyring_references_in_config() {
+        assert_eq!(reference_name("keyring:site-a"), Some("site-a"));
+        assert_eq!(reference_name(" keyring: site-a "), Some("site-a"));
+        assert_eq!(reference_name("keyring:"), None);
+        assert_eq!(reference_name("plain-password"), None);
+        assert_eq!(resolve("plain-password").unwrap(), "plain-password");
+        assert!(validate_name("../x").is_err());
+
+        let cfg: Config = toml::from_str(
+            r#"
+allow_all_lan = true
+whitelist = []
+
+[[rules]]
+enabled = false
+listen_addr = ":8080"
+ssl_enable = false
+cert_file = ""
+key_file = ""
+basic_auth_enable = true
+basic_auth_username = "admin"
+basic_auth_password = "keyring:lab-admin"
+basic_auth_forward_header = false
+
+[[rules.routes]]
+path = "/"
+basic_auth_password = "keyring:route-pw"
+upstreams = []
+"#,
+        )
+        .unwrap();
+        let refs = secret_references(&cfg);
+        assert_eq!(
+            refs,
+            vec![
+                (
+                    "rules[0].basic_auth_password".to_string(),
+                    false,
+                    "lab-admin".to_string()
+                ),
+                (
+                    "rules[0].routes[0].basic_auth_password".to_string(),
+                    false,
+                    "route-pw".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_keyring_references_outside_rules() {
+        let cfg: Config = toml::from_str(
+            r#"
+allow_all_lan = true
+whitelist = []
+rules = []
+
+[alerting]
+enabled = true
+
+[alerting.webhook]
+enabled = true
+provider = "wecom"
+url = "https://example.com/hook"
+secret = "keyring:hook-secret"
+
+[prometheus]
+enabled = false
+bearer_token = "keyring:prom-token"
+
+[metrics_storage]
+enabled = true
+db_path = ""
+backend = "postgres"
+url = "keyring:pg-url"
+"#,
+        )
+        .unwrap();
+        let refs = secret_references(&cfg);
+        assert_eq!(
+            refs,
+            vec![
+                (
+                    "alerting.webhook.secret".to_string(),
+                    true,
+                    "hook-secret".to_string()
+                ),
+                (
+                    "prometheus.bearer_token".to_string(),
+                    false,
+                    "prom-token".to_string()
+                ),
+                (
+                    "metrics_storage.url".to_string(),
+                    true,
+                    "pg-url".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -4,7 +4,8 @@
 )]
 
 use anyhow::Result;
-use tauri::AppHandle;
+
+use crate::app_events::AppEvents;
 
 mod collect;
 mod prelude;
@@ -31,7 +32,7 @@ pub fn set_system_metrics_subscription(active: bool) {
     state::set_system_metrics_subscription_state(active);
 }
 
-pub fn start_system_sampler(app: AppHandle) {
+pub fn start_system_sampler(app: AppEvents) {
     start_system_sampler_inner(app);
 }
 
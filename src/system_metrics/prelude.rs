@@ -3,8 +3,6 @@ pub(super) use anyhow::Context;
 pub(super) use anyhow::{anyhow, Result};
 pub(super) use once_cell::sync::Lazy;
 pub(super) use parking_lot::RwLock;
-#[cfg(any(target_os = "linux", target_os = "windows"))]
-pub(super) use sqlx::QueryBuilder;
 #[cfg(target_os = "windows")]
 pub(super) use std::collections::HashMap;
 pub(super) use std::collections::VecDeque;
@@ -18,7 +16,6 @@ pub(super) use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 pub(super) use std::time::Duration;
 #[cfg(any(target_os = "linux", target_os = "windows"))]
 pub(super) use std::time::Instant;
-pub(super) use tauri::AppHandle;
 #[cfg(any(target_os = "linux", target_os = "windows"))]
 pub(super) use tauri::{Emitter, Manager};
 #[cfg(target_os = "windows")]
@@ -71,7 +71,13 @@ pub(super) async fn query_historical_system_metrics_inner(
             });
         }
 
-        let Some(pool) = crate::metrics::db_pool() else {
+        let span = req.end_time - req.start_time;
+        let granularity = choose_granularity(span, req.granularity_secs);
+
+        let Some(rows) =
+            crate::metrics::system_metrics_history(req.start_time, req.end_time, granularity)
+                .await?
+        else {
             return Ok(QuerySystemMetricsResponse {
                 points: vec![],
                 supported: true,
@@ -80,58 +86,6 @@ pub(super) async fn query_historical_system_metrics_inner(
             });
         };
 
-        let span = req.end_time - req.start_time;
-        let granularity = choose_granularity(span, req.granularity_secs);
-
-        let rows = sqlx::query_as::<_, HistoricalRow>(
-            r#"
-            SELECT
-              (timestamp / ?) * ? AS bucket,
-              AVG(cpu_usage_percent) AS cpu_usage_percent,
-              AVG(load1) AS load1,
-              AVG(load5) AS load5,
-              AVG(load15) AS load15,
-              MAX(mem_total_bytes) AS mem_total_bytes,
-              AVG(mem_available_bytes) AS mem_available_bytes,
-              AVG(mem_used_bytes) AS mem_used_bytes,
-              AVG(mem_used_percent) AS mem_used_percent,
-              MAX(swap_total_bytes) AS swap_total_bytes,
-              AVG(swap_free_bytes) AS swap_free_bytes,
-              AVG(swap_used_bytes) AS swap_used_bytes,
-              AVG(swap_used_percent) AS swap_used_percent,
-              MAX(net_rx_bytes) AS net_rx_bytes,
-              MAX(net_tx_bytes) AS net_tx_bytes,
-              AVG(net_rx_bps) AS net_rx_bps,
-              AVG(net_tx_bps) AS net_tx_bps,
-              MAX(disk_read_bytes) AS disk_read_bytes,
-              MAX(disk_write_bytes) AS disk_write_bytes,
-              AVG(disk_read_bps) AS disk_read_bps,
-              AVG(disk_write_bps) AS disk_write_bps,
-              AVG(tcp_established) AS tcp_established,
-              AVG(tcp_time_wait) AS tcp_time_wait,
-              AVG(tcp_close_wait) AS tcp_close_wait,
-              AVG(process_count) AS process_count,
-              AVG(fd_used) AS fd_used,
-              MAX(fd_max) AS fd_max,
-              AVG(fd_usage_percent) AS fd_usage_percent,
-              AVG(procs_running) AS procs_running,
-              AVG(procs_blocked) AS procs_blocked,
-              MAX(context_switches) AS context_switches,
-              MAX(processes_forked_total) AS processes_forked_total,
-              AVG(uptime_seconds) AS uptime_seconds
-            FROM system_metrics
-            WHERE timestamp >= ? AND timestamp <= ?
-            GROUP BY bucket
-            ORDER BY bucket ASC
-            "#,
-        )
-        .bind(granularity)
-        .bind(granularity)
-        .bind(req.start_time)
-        .bind(req.end_time)
-        .fetch_all(&*pool)
-        .await?;
-
         let points = map_historical_rows(rows);
         let summary = build_summary(&points);
         Ok(QuerySystemMetricsResponse {
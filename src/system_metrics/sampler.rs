@@ -4,7 +4,7 @@ use super::realtime::push_realtime_point;
 use super::*;
 
 #[cfg(any(target_os = "linux", target_os = "windows"))]
-async fn collect_and_publish_one(app: &AppHandle, persist_enabled: bool, emit_to_frontend: bool) {
+async fn collect_and_publish_one(app: &AppEvents, persist_enabled: bool, emit_to_frontend: bool) {
     let collected = tauri::async_runtime::spawn_blocking(collect_one_point).await;
     if let Ok(Ok((point, interfaces))) = collected {
         *LAST_INTERFACES.write() = interfaces.clone();
@@ -14,7 +14,7 @@ async fn collect_and_publish_one(app: &AppHandle, persist_enabled: bool, emit_to
         }
 
         if emit_to_frontend {
-            if let Some(window) = app.get_webview_window("main") {
+            if let Some(window) = app.handle().and_then(|a| a.get_webview_window("main")) {
                 let _ = window.emit(
                     "system-metrics",
                     SystemMetricsEventPayload { point, interfaces },
@@ -25,7 +25,7 @@ async fn collect_and_publish_one(app: &AppHandle, persist_enabled: bool, emit_to
 }
 
 #[cfg(any(target_os = "linux", target_os = "windows"))]
-async fn run_sampler_loop(app: AppHandle) {
+async fn run_sampler_loop(app: AppEvents) {
     loop {
         if !SAMPLER_RUNNING.load(Ordering::Relaxed) {
             break;
@@ -53,7 +53,7 @@ async fn run_sampler_loop(app: AppHandle) {
     SAMPLER_RUNNING.store(false, Ordering::SeqCst);
 }
 
-pub(super) fn start_system_sampler_inner(app: AppHandle) {
+pub(super) fn start_system_sampler_inner(app: AppEvents) {
     #[cfg(not(any(target_os = "linux", target_os = "windows")))]
     {
         let _ = app;
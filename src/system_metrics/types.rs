@@ -143,38 +143,38 @@ pub(super) struct RawSnapshot {
 }
 
 #[derive(Debug, Clone, sqlx::FromRow)]
-pub(super) struct HistoricalRow {
-    pub(super) bucket: i64,
-    pub(super) cpu_usage_percent: Option<f64>,
-    pub(super) load1: Option<f64>,
-    pub(super) load5: Option<f64>,
-    pub(super) load15: Option<f64>,
-    pub(super) mem_total_bytes: Option<i64>,
-    pub(super) mem_available_bytes: Option<f64>,
-    pub(super) mem_used_bytes: Option<f64>,
-    pub(super) mem_used_percent: Option<f64>,
-    pub(super) swap_total_bytes: Option<i64>,
-    pub(super) swap_free_bytes: Option<f64>,
-    pub(super) swap_used_bytes: Option<f64>,
-    pub(super) swap_used_percent: Option<f64>,
-    pub(super) net_rx_bytes: Option<i64>,
-    pub(super) net_tx_bytes: Option<i64>,
-    pub(super) net_rx_bps: Option<f64>,
-    pub(super) net_tx_bps: Option<f64>,
-    pub(super) disk_read_bytes: Option<i64>,
-    pub(super) disk_write_bytes: Option<i64>,
-    pub(super) disk_read_bps: Option<f64>,
-    pub(super) disk_write_bps: Option<f64>,
-    pub(super) tcp_established: Option<f64>,
-    pub(super) tcp_time_wait: Option<f64>,
-    pub(super) tcp_close_wait: Option<f64>,
-    pub(super) process_count: Option<f64>,
-    pub(super) fd_used: Option<f64>,
-    pub(super) fd_max: Option<i64>,
-    pub(super) fd_usage_percent: Option<f64>,
-    pub(super) procs_running: Option<f64>,
-    pub(super) procs_blocked: Option<f64>,
-    pub(super) context_switches: Option<i64>,
-    pub(super) processes_forked_total: Option<i64>,
-    pub(super) uptime_seconds: Option<f64>,
+pub(crate) struct HistoricalRow {
+    pub(crate) bucket: i64,
+    pub(crate) cpu_usage_percent: Option<f64>,
+    pub(crate) load1: Option<f64>,
+    pub(crate) load5: Option<f64>,
+    pub(crate) load15: Option<f64>,
+    pub(crate) mem_total_bytes: Option<i64>,
+    pub(crate) mem_available_bytes: Option<f64>,
+    pub(crate) mem_used_bytes: Option<f64>,
+    pub(crate) mem_used_percent: Option<f64>,
+    pub(crate) swap_total_bytes: Option<i64>,
+    pub(crate) swap_free_bytes: Option<f64>,
+    pub(crate) swap_used_bytes: Option<f64>,
+    pub(crate) swap_used_percent: Option<f64>,
+    pub(crate) net_rx_bytes: Option<i64>,
+    pub(crate) net_tx_bytes: Option<i64>,
+    pub(crate) net_rx_bps: Option<f64>,
+    pub(crate) net_tx_bps: Option<f64>,
+    pub(crate) disk_read_bytes: Option<i64>,
+    pub(crate) disk_write_bytes: Option<i64>,
+    pub(crate) disk_read_bps: Option<f64>,
+    pub(crate) disk_write_bps: Option<f64>,
+    pub(crate) tcp_established: Option<f64>,
+    pub(crate) tcp_time_wait: Option<f64>,
+    pub(crate) tcp_close_wait: Option<f64>,
+    pub(crate) process_count: Option<f64>,
+    pub(crate) fd_used: Option<f64>,
+    pub(crate) fd_max: Option<i64>,
+    pub(crate) fd_usage_percent: Option<f64>,
+    pub(crate) procs_running: Option<f64>,
+    pub(crate) procs_blocked: Option<f64>,
+    pub(crate) context_switches: Option<i64>,
+    pub(crate) processes_forked_total: Option<i64>,
+    pub(crate) uptime_seconds: Option<f64>,
 }
@@ -178,6 +178,47 @@ pub struct SslCertInfoResult {
     pub error: Option<String>,
 }
 
+/// 上游连通性测试选项
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpstreamTestOptions {
+    /// 按该监听规则的 client_overrides 取客户端设置，为空时使用全局设置
+    pub listen_rule_id: Option<String>,
+    /// 连通后发送的请求方法（HEAD / GET），为空时只测到 TLS 握手
+    pub method: Option<String>,
+}
+
+/// 上游证书信息；与代理转发一致不校验证书，仅供查看
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamCertInfo {
+    pub subject: String,
+    pub issuer: String,
+    pub not_after: String,
+    pub days_until_expiry: i64,
+}
+
+/// 上游连通性测试结果：各阶段耗时（毫秒），失败时 failed_stage 为出错的阶段
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpstreamTestResult {
+    pub url: String,
+    pub ok: bool,
+    /// url / dns / connect / tls / request
+    pub failed_stage: Option<String>,
+    pub error: Option<String>,
+    /// 本次使用的上游客户端设置
+    pub client_settings: String,
+    pub resolved_ips: Vec<String>,
+    pub dns_ms: Option<f64>,
+    pub connected_addr: Option<String>,
+    pub connect_ms: Option<f64>,
+    pub tls_handshake_ms: Option<f64>,
+    pub tls_version: Option<String>,
+    pub alpn: Option<String>,
+    pub certificate: Option<UpstreamCertInfo>,
+    pub status: Option<u16>,
+    /// 发出请求到收到响应头的耗时
+    pub first_byte_ms: Option<f64>,
+}
+
 /// 生成自签名证书请求
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SelfSignedCertRequest {
@@ -1270,6 +1311,218 @@ pub async fn get_ssl_cert_info(req: SslCertInfoRequest) -> Result<SslCertInfoRes
     }
 }
 
+/// 按代理转发上游时的方式测试连通性：使用相同的客户端设置，依次解析 DNS、建立 TCP 连接、
+/// TLS 握手，可选再发送一个 HEAD/GET；任一阶段失败即停止并在结果中标出该阶段
+pub async fn test_upstream(url: &str, options: UpstreamTestOptions) -> Result<UpstreamTestResult> {
+    let cfg = config::get_config();
+    let settings = match options.listen_rule_id.as_deref() {
+        Some(id) => {
+            let rule = cfg
+                .rules
+                .iter()
+                .find(|r| r.id.as_deref() == Some(id))
+                .with_context(|| format!("未找到监听规则: {id}"))?;
+            config::UpstreamClientSettings::for_rule(&cfg, rule)
+        }
+        None => config::UpstreamClientSettings::global(&cfg),
+    };
+    let method = options
+        .method
+        .as_deref()
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .map(str::to_ascii_uppercase);
+    if let Some(m) = method.as_deref() {
+        if m != "HEAD" && m != "GET" {
+            anyhow::bail!("只支持 HEAD 或 GET 请求: {m}");
+        }
+    }
+
+    let mut result = UpstreamTestResult {
+        url: url.to_string(),
+        client_settings: settings.to_string(),
+        ..Default::default()
+    };
+    match probe_upstream(url, &settings, method.as_deref(), &mut result).await {
+        Ok(()) => result.ok = true,
+        Err((stage, error)) => {
+            result.failed_stage = Some(stage.to_string());
+            result.error = Some(error);
+        }
+    }
+    Ok(result)
+}
+
+type ProbeError = (&'static str, String);
+
+fn elapsed_ms(started: Instant) -> f64 {
+    (started.elapsed().as_secs_f64() * 100_000.0).round() / 100.0
+}
+
+/// 错误及其 source 链，reqwest 的顶层错误信息通常不含具体原因
+fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut out = e.to_string();
+    let mut source = e.source();
+    while let Some(inner) = source {
+        out.push_str(": ");
+        out.push_str(&inner.to_string());
+        source = inner.source();
+    }
+    out
+}
+
+async fn probe_upstream(
+    url: &str,
+    settings: &config::UpstreamClientSettings,
+    method: Option<&str>,
+    result: &mut UpstreamTestResult,
+) -> std::result::Result<(), ProbeError> {
+    let mut parsed = reqwest::Url::parse(url).map_err(|e| ("url", e.to_string()))?;
+    let tls = match parsed.scheme() {
+        "http" | "ws" => false,
+        "https" | "wss" => true,
+        other => return Err(("url", format!("不支持的协议: {other}"))),
+    };
+    let host = parsed
+        .host_str()
+        .map(|h| h.trim_start_matches('[').trim_end_matches(']').to_string())
+        .filter(|h| !h.is_empty())
+        .ok_or(("url", "缺少主机名".to_string()))?;
+    let port = parsed
+        .port_or_known_default()
+        .ok_or(("url", "缺少端口".to_string()))?;
+    let connect_timeout = Duration::from_millis(settings.connect_timeout_ms);
+
+    let started = Instant::now();
+    let addrs: Vec<std::net::SocketAddr> = match timeout(
+        connect_timeout,
+        tokio::net::lookup_host((host.as_str(), port)),
+    )
+    .await
+    {
+        Ok(Ok(addrs)) => addrs.collect(),
+        Ok(Err(e)) => return Err(("dns", e.to_string())),
+        Err(_) => {
+            return Err((
+                "dns",
+                format!("解析超时（{}ms）", settings.connect_timeout_ms),
+            ))
+        }
+    };
+    result.dns_ms = Some(elapsed_ms(started));
+    result.resolved_ips = addrs.iter().map(|a| a.ip().to_string()).collect();
+    if addrs.is_empty() {
+        return Err(("dns", "没有解析到地址".to_string()));
+    }
+
+    // 按解析顺序逐个尝试，记录每个地址的失败原因
+    let mut errors = Vec::new();
+    let mut stream = None;
+    for addr in &addrs {
+        let started = Instant::now();
+        match timeout(connect_timeout, tokio::net::TcpStream::connect(addr)).await {
+            Ok(Ok(s)) => {
+                result.connect_ms = Some(elapsed_ms(started));
+                result.connected_addr = Some(addr.to_string());
+                stream = Some(s);
+                break;
+            }
+            Ok(Err(e)) => errors.push(format!("{addr}: {e}")),
+            Err(_) => errors.push(format!(
+                "{addr}: 连接超时（{}ms）",
+                settings.connect_timeout_ms
+            )),
+        }
+    }
+    let Some(stream) = stream else {
+        return Err(("connect", errors.join("; ")));
+    };
+
+    if tls {
+        probe_tls(stream, &host, settings, result)
+            .await
+            .map_err(|e| ("tls", e))?;
+    } else {
+        drop(stream);
+    }
+
+    let Some(method) = method else {
+        return Ok(());
+    };
+    if parsed.scheme().starts_with("ws") {
+        let scheme = if tls { "https" } else { "http" };
+        let _ = parsed.set_scheme(scheme);
+    }
+    let (_, client) = crate::proxy::server::build_upstream_clients(settings)
+        .map_err(|e| ("request", format!("{e:#}")))?;
+    let method =
+        reqwest::Method::from_bytes(method.as_bytes()).map_err(|e| ("request", e.to_string()))?;
+    let started = Instant::now();
+    let resp = client
+        .request(method, parsed)
+        .send()
+        .await
+        .map_err(|e| ("request", error_chain(&e)))?;
+    result.first_byte_ms = Some(elapsed_ms(started));
+    result.status = Some(resp.status().as_u16());
+    Ok(())
+}
+
+async fn probe_tls(
+    stream: tokio::net::TcpStream,
+    host: &str,
+    settings: &config::UpstreamClientSettings,
+    result: &mut UpstreamTestResult,
+) -> std::result::Result<(), String> {
+    use crate::proxy::ws_proxy::NoCertVerifier;
+    use rustls::pki_types::ServerName;
+    use std::sync::Arc;
+    use x509_parser::prelude::*;
+
+    let mut tls_config = rustls::ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(NoCertVerifier::new()))
+        .with_no_client_auth();
+    tls_config.alpn_protocols = if settings.enable_http2 {
+        vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+    } else {
+        vec![b"http/1.1".to_vec()]
+    };
+    let server_name = ServerName::try_from(host.to_string()).map_err(|e| e.to_string())?;
+    let connector = tokio_rustls::TlsConnector::from(Arc::new(tls_config));
+
+    let started = Instant::now();
+    let tls_stream = match timeout(
+        Duration::from_millis(settings.connect_timeout_ms),
+        connector.connect(server_name, stream),
+    )
+    .await
+    {
+        Ok(Ok(s)) => s,
+        Ok(Err(e)) => return Err(error_chain(&e)),
+        Err(_) => return Err(format!("握手超时（{}ms）", settings.connect_timeout_ms)),
+    };
+    result.tls_handshake_ms = Some(elapsed_ms(started));
+
+    let (_, session) = tls_stream.get_ref();
+    result.tls_version = session.protocol_version().map(|v| format!("{v:?}"));
+    result.alpn = session
+        .alpn_protocol()
+        .map(|p| String::from_utf8_lossy(p).into_owned());
+    if let Some(cert) = session.peer_certificates().and_then(|c| c.first()) {
+        if let Ok((_, parsed)) = X509Certificate::from_der(cert.as_ref()) {
+            let expiry = parsed.validity().not_after.timestamp();
+            result.certificate = Some(UpstreamCertInfo {
+                subject: parsed.subject().to_string(),
+                issuer: parsed.issuer().to_string(),
+                not_after: parsed.validity().not_after.to_string(),
+                days_until_expiry: (expiry - chrono::Utc::now().timestamp()) / 86400,
+            });
+        }
+    }
+    Ok(())
+}
+
 /// 端口扫描
 pub async fn scan_ports(req: PortScanRequest) -> Result<PortScanResult> {
     use futures_util::stream::{self, StreamExt};
@@ -1381,3 +1634,64 @@ pub fn encode_decode(req: EncodeDecodeRequest) -> Result<EncodeDecodeResult> {
         error: None,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_upstream_reports_stages_and_status() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // 先有一次 TCP 探测连接，随后才是真正的请求，逐个应答
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                if matches!(socket.read(&mut buf).await, Ok(n) if n > 0) {
+                    let _ = socket
+                        .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                        .await;
+                }
+            }
+        });
+
+        let options = UpstreamTestOptions {
+            method: Some("head".into()),
+            ..Default::default()
+        };
+        let result = test_upstream(&format!("http://{addr}/health"), options)
+            .await
+            .unwrap();
+        assert!(result.ok, "{:?}", result.error);
+        assert_eq!(result.resolved_ips, vec!["127.0.0.1".to_string()]);
+        assert_eq!(result.connected_addr, Some(addr.to_string()));
+        assert!(result.tls_handshake_ms.is_none());
+        assert_eq!(result.status, Some(204));
+    }
+
+    #[tokio::test]
+    async fn test_upstream_names_the_failing_stage() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let result = test_upstream(&format!("http://{addr}"), UpstreamTestOptions::default())
+            .await
+            .unwrap();
+        assert!(!result.ok);
+        assert_eq!(result.failed_stage.as_deref(), Some("connect"));
+        assert!(result.connect_ms.is_none());
+
+        let result = test_upstream("ftp://127.0.0.1", UpstreamTestOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(result.failed_stage.as_deref(), Some("url"));
+
+        let bad_method = UpstreamTestOptions {
+            method: Some("POST".into()),
+            ..Default::default()
+        };
+        assert!(test_upstream("http://127.0.0.1", bad_method).await.is_err());
+    }
+}
@@ -8,6 +8,7 @@ use tauri::{
 
 struct TrayMenuHandles<R: tauri::Runtime> {
     status: MenuItem<R>,
+    profile: MenuItem<R>,
     toggle: MenuItem<R>,
     restart: MenuItem<R>,
     show: MenuItem<R>,
@@ -19,6 +20,7 @@ static TRAY_HANDLES: RwLock<Option<TrayMenuHandles<tauri::Wry>>> = RwLock::new(N
 
 fn store_tray_handles(
     status: MenuItem<tauri::Wry>,
+    profile: MenuItem<tauri::Wry>,
     toggle: MenuItem<tauri::Wry>,
     restart: MenuItem<tauri::Wry>,
     show: MenuItem<tauri::Wry>,
@@ -27,6 +29,7 @@ fn store_tray_handles(
 ) {
     *TRAY_HANDLES.write() = Some(TrayMenuHandles {
         status,
+        profile,
         toggle,
         restart,
         show,
@@ -35,6 +38,24 @@ fn store_tray_handles(
     });
 }
 
+/// 运行中的状态文字：有监听未在运行时显示为部分运行，如 "部分运行 (3/4)"
+fn running_status_text() -> String {
+    let (running, total) = crate::proxy::listener_counts();
+    if running < total {
+        format!(
+            "{} ({running}/{total})",
+            i18n::t(i18n::TrayText::StatusPartial)
+        )
+    } else {
+        i18n::t(i18n::TrayText::StatusRunning).to_string()
+    }
+}
+
+/// 按当前运行状态刷新托盘，单个监听启动、停止或失败时调用
+pub fn refresh_tray_proxy_state() {
+    set_tray_proxy_state(crate::proxy::is_effectively_running());
+}
+
 pub fn set_tray_proxy_state(running: bool) {
     let handles = TRAY_HANDLES.read();
     let Some(h) = handles.as_ref() else {
@@ -42,7 +63,7 @@ pub fn set_tray_proxy_state(running: bool) {
     };
 
     if running {
-        let _ = h.status.set_text(i18n::t(i18n::TrayText::StatusRunning));
+        let _ = h.status.set_text(running_status_text());
         let _ = h.toggle.set_text(i18n::t(i18n::TrayText::ToggleStop));
         let _ = h.restart.set_enabled(true);
     } else {
@@ -52,6 +73,20 @@ pub fn set_tray_proxy_state(running: bool) {
     }
 }
 
+fn profile_text(name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!("{}{}", i18n::t(i18n::TrayText::ProfileLabel), name),
+        None => i18n::t(i18n::TrayText::ProfileDefault).to_string(),
+    }
+}
+
+pub fn set_tray_active_profile(name: Option<&str>) {
+    let handles = TRAY_HANDLES.read();
+    if let Some(h) = handles.as_ref() {
+        let _ = h.profile.set_text(profile_text(name));
+    }
+}
+
 pub fn update_tray_menu_texts() {
     let handles = TRAY_HANDLES.read();
     let Some(h) = handles.as_ref() else {
@@ -63,11 +98,14 @@ pub fn update_tray_menu_texts() {
     let _ = h.hide.set_text(i18n::t(i18n::TrayText::HideWindow));
     let _ = h.restart.set_text(i18n::t(i18n::TrayText::RestartProxy));
     let _ = h.quit.set_text(i18n::t(i18n::TrayText::Quit));
+    let _ = h.profile.set_text(profile_text(
+        crate::config_profiles::active_profile().as_deref(),
+    ));
 
     // 同时更新状态和切换按钮（根据当前运行状态）
     let running = crate::proxy::is_effectively_running();
     if running {
-        let _ = h.status.set_text(i18n::t(i18n::TrayText::StatusRunning));
+        let _ = h.status.set_text(running_status_text());
         let _ = h.toggle.set_text(i18n::t(i18n::TrayText::ToggleStop));
     } else {
         let _ = h.status.set_text(i18n::t(i18n::TrayText::StatusStopped));
@@ -76,6 +114,7 @@ pub fn update_tray_menu_texts() {
 }
 
 const MENU_ID_STATUS: &str = "status";
+const MENU_ID_PROFILE: &str = "profile";
 const MENU_ID_SHOW: &str = "show";
 const MENU_ID_HIDE: &str = "hide";
 const MENU_ID_TOGGLE: &str = "toggle";
@@ -94,6 +133,13 @@ pub fn init_tray(app: &AppHandle) -> tauri::Result<()> {
         false,
         None::<&str>,
     )?;
+    let profile = MenuItem::with_id(
+        app,
+        MENU_ID_PROFILE,
+        profile_text(crate::config_profiles::active_profile().as_deref()),
+        false,
+        None::<&str>,
+    )?;
     let show = MenuItem::with_id(
         app,
         MENU_ID_SHOW,
@@ -136,6 +182,7 @@ pub fn init_tray(app: &AppHandle) -> tauri::Result<()> {
         app,
         &[
             &status,
+            &profile,
             &PredefinedMenuItem::separator(app)?,
             &show,
             &hide,
@@ -167,6 +214,7 @@ pub fn init_tray(app: &AppHandle) -> tauri::Result<()> {
     // 保存句柄（给前端 invoke 的 command 用）
     store_tray_handles(
         status.clone(),
+        profile.clone(),
         toggle.clone(),
         restart.clone(),
         show.clone(),
@@ -176,7 +224,7 @@ pub fn init_tray(app: &AppHandle) -> tauri::Result<()> {
 
     let builder = builder
         .on_menu_event(move |app, event| match event.id().as_ref() {
-            MENU_ID_STATUS => {}
+            MENU_ID_STATUS | MENU_ID_PROFILE => {}
             MENU_ID_SHOW => {
                 if let Some(window) = app.get_webview_window("main") {
                     let _ = window.unminimize();
@@ -203,17 +251,15 @@ pub fn init_tray(app: &AppHandle) -> tauri::Result<()> {
             MENU_ID_TOGGLE => {
                 // 仍允许从托盘直接启动/停止，但不在这里更新托盘文案（由前端 status 事件驱动）
                 if crate::proxy::is_effectively_running() {
-                    crate::proxy::stop_server(app.clone()).ok();
+                    crate::proxy::stop_server(app.into()).ok();
                 } else {
-                    crate::proxy::start_server(app.clone()).ok();
+                    crate::proxy::start_server(app.into()).ok();
                 }
             }
             MENU_ID_RESTART => {
                 let app = app.clone();
                 tauri::async_runtime::spawn(async move {
-                    crate::proxy::stop_server(app.clone()).ok();
-                    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
-                    crate::proxy::start_server(app).ok();
+                    crate::proxy::restart_server(app.into()).await.ok();
                 });
             }
             MENU_ID_QUIT => {